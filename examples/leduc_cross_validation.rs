@@ -0,0 +1,49 @@
+//! Trains Leduc poker with the same budget under several card abstractions and prints their
+//! exploitability along with a matrix of head-to-head winnings. Passing a seed makes the run
+//! reproducible.
+//!
+//! cargo run --release --example leduc_cross_validation -- [iterations] [hands] [seed]
+
+use std::path::Path;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use ungar::{
+    action_abstraction::ActionAbstraction,
+    cancel::CancelToken,
+    card_abstraction::{CardAbstraction, NoBuckets},
+    cfr::CFRConfig,
+    cross_validation::{CrossValidation, TrainingBudget},
+    game::GameInfo,
+    ochs::OchsBuckets,
+};
+
+fn main() {
+    env_logger::init();
+
+    let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+    let iterations = std::env::args().nth(1).map_or(20000, |s| s.parse().expect("invalid iteration count"));
+    let num_hands = std::env::args().nth(2).map_or(20000, |s| s.parse().expect("invalid hand count"));
+    let seed: Option<u64> = std::env::args().nth(3).map(|s| s.parse().expect("invalid seed"));
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+
+    let game_info = GameInfo::load_game_info(&configs.join("leduc.json")).unwrap();
+    let action_abstraction = ActionAbstraction::from_config(&configs.join("leduc_action_abstraction.json")).unwrap();
+    let cfr_config = CFRConfig::from_config(&configs.join("leduc_cfr_config.json"));
+
+    let mut cross_validation = CrossValidation::new(game_info.clone(), action_abstraction, cfr_config, TrainingBudget::new(iterations), num_hands);
+    if let Some(seed) = seed {
+        cross_validation.set_seed(seed);
+    }
+    cross_validation.add_card_abstraction("lossless", CardAbstraction::from_config(&configs.join("leduc_card_abstraction.json")).unwrap());
+
+    let ochs = OchsBuckets::build(&game_info, 1, 3, 4, 1000, &mut rng, &CancelToken::new()).expect("not cancelled");
+    cross_validation.add_card_abstraction("ochs", CardAbstraction::new(vec![Box::new(NoBuckets::new(&game_info, 0)), Box::new(ochs)]));
+
+    // Ignores the board card, so the strategy can't tell when it made a pair
+    cross_validation.add_card_abstraction("board blind", CardAbstraction::new(vec![Box::new(NoBuckets::new(&game_info, 0)), Box::new(NoBuckets::new(&game_info, 0))]));
+
+    let report = cross_validation.run().expect("not cancelled");
+    print!("{}", report);
+}
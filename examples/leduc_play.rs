@@ -0,0 +1,70 @@
+//! Loads the strategy written by `leduc_train` and plays it against an agent choosing uniformly
+//! random actions, reporting the trained strategy's winnings. Passing a seed makes the run
+//! reproducible.
+//!
+//! cargo run --release --example leduc_play -- [output dir] [hands] [seed]
+
+use std::path::{Path, PathBuf};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use ungar::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    card_abstraction::CardAbstraction,
+    evaluator::HandEvaluator,
+    game::{GameInfo, PlayerId},
+    strategy::Strategy,
+};
+
+/// Plays one hand with the trained strategy in `strategy_seat`, returning its payout
+fn play_hand(abstract_game: &mut AbstractGame, strategy: &Strategy, evaluator: &dyn HandEvaluator, strategy_seat: PlayerId, rng: &mut StdRng) -> i32 {
+    let (hole_cards, board_cards) = abstract_game.game_info.deal_hole_cards_and_board_cards(rng);
+    let mut board_cards_i = abstract_game.game_info.total_board_cards(0) as usize;
+    let mut node_id = abstract_game.nodes.get_root_node_id();
+    let mut state = abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+
+    while !state.is_finished() {
+        let player = state.current_player().unwrap();
+        let action = if player == strategy_seat {
+            let bucket_id = abstract_game.get_bucket(state.current_round(), &board_cards, &hole_cards[player as usize]);
+            strategy.sample(abstract_game, node_id, bucket_id, rng)
+        } else {
+            *abstract_game.get_actions(&state).choose(rng).unwrap()
+        };
+
+        state = state.apply_action_no_cards(&abstract_game.game_info, action).unwrap();
+        node_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, action);
+    }
+
+    state.get_payout(&abstract_game.game_info, evaluator, &board_cards, &hole_cards, strategy_seat)
+}
+
+fn main() {
+    env_logger::init();
+
+    let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+    let mut args = std::env::args().skip(1);
+    let input_dir = args.next().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data"));
+    let hands: u32 = args.next().map(|h| h.parse().expect("hands must be a number")).unwrap_or(100000);
+    let seed: Option<u64> = args.next().map(|s| s.parse().expect("seed must be a number"));
+
+    let game_info = GameInfo::load_game_info(&configs.join("leduc.json")).unwrap();
+    let action_abstraction = ActionAbstraction::from_config(&configs.join("leduc_action_abstraction.json")).unwrap();
+    let card_abstraction = CardAbstraction::from_config(&configs.join("leduc_card_abstraction.json")).unwrap();
+    let mut abstract_game = AbstractGame::load_nodes(game_info, &input_dir.join("leduc_nodes.bin"), action_abstraction, card_abstraction);
+    let strategy = Strategy::from_file(&input_dir.join("leduc_strategy.bin"));
+
+    let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let mut total: i64 = 0;
+
+    for hand in 0..hands {
+        // Alternate seats so neither player keeps the positional advantage
+        let strategy_seat = (hand % 2) as PlayerId;
+        total += play_hand(&mut abstract_game, &strategy, evaluator, strategy_seat, &mut rng) as i64;
+    }
+
+    println!("trained strategy vs random agent over {} hands: {:.4} chips/hand", hands, total as f64 / hands as f64);
+}
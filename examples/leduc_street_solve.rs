@@ -0,0 +1,92 @@
+//! Solves Leduc poker street by street: the whole game first, then every second round subtree
+//! the first solve reaches in its own job, in parallel, and composes them into one profile with
+//! `StrategyProfile::add_partial`. Each solve is saved to the output dir and skipped if it's
+//! already there, so an interrupted run picks up where it stopped.
+//!
+//! The subtree jobs deal any cards, they don't know the ranges reaching their root, so prints how
+//! far each subtree moved from the second round strategy of the first solve.
+//!
+//! cargo run --release --example leduc_street_solve -- [output dir] [iterations]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use ungar::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    card_abstraction::CardAbstraction,
+    cfr::{CFRConfig, CFREngine},
+    deals::Deals,
+    game::{GameInfo, GameState},
+    strategy::{Strategy, StrategyProfile},
+};
+
+fn main() {
+    env_logger::init();
+
+    let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+    let output_dir = std::env::args().nth(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data/leduc_streets"));
+    let iterations = std::env::args().nth(2).map_or(50000, |s| s.parse().expect("invalid iteration count"));
+    fs::create_dir_all(&output_dir).expect("failed to create output dir");
+
+    let game_info = GameInfo::load_game_info(&configs.join("leduc.json")).unwrap();
+    let abstract_game = |state: GameState| AbstractGame::new(
+        game_info.clone(),
+        state,
+        ActionAbstraction::from_config(&configs.join("leduc_action_abstraction.json")).unwrap(),
+        CardAbstraction::from_config(&configs.join("leduc_card_abstraction.json")).unwrap(),
+    );
+    let load = |name: &str| {
+        let abstract_game = AbstractGame::load_nodes(
+            game_info.clone(),
+            &output_dir.join(format!("{}_nodes.bin", name)),
+            ActionAbstraction::from_config(&configs.join("leduc_action_abstraction.json")).unwrap(),
+            CardAbstraction::from_config(&configs.join("leduc_card_abstraction.json")).unwrap(),
+        );
+        let strategy = Strategy::from_file_checked(&output_dir.join(format!("{}_strategy.bin", name)), &abstract_game).expect("strategy doesn't match the configs");
+        StrategyProfile::new(abstract_game, strategy)
+    };
+    let solve = |name: &str, state: GameState| {
+        if output_dir.join(format!("{}_strategy.bin", name)).exists() {
+            println!("{} already solved", name);
+            return;
+        }
+        let mut cfr_engine = CFREngine::new(abstract_game(state), CFRConfig::from_config(&configs.join("leduc_cfr_config.json")));
+        cfr_engine.mccfr_p(iterations, 20, 400, 100000, 2500).unwrap();
+        cfr_engine.save_nodes(&output_dir.join(format!("{}_nodes.bin", name)));
+        cfr_engine.save_average_strategy(&output_dir.join(format!("{}_strategy.bin", name)));
+        println!("solved {}", name);
+    };
+
+    solve("round0", GameState::new(&game_info, 0));
+    let base = load("round0");
+
+    let mut roots = base.subtree_roots(1);
+    roots.sort_by_key(|root| root.betting_string(&game_info));
+    thread::scope(|scope| {
+        for (i, root) in roots.iter().enumerate() {
+            let solve = &solve;
+            scope.spawn(move || solve(&format!("round1_{}", i), root.clone()));
+        }
+    });
+
+    let mut profile = load("round0");
+    for i in 0..roots.len() {
+        profile.add_partial(load(&format!("round1_{}", i))).expect("failed to add partial strategy");
+    }
+
+    for root in &roots {
+        let betting = root.betting_string(&game_info);
+        let state = GameState::from_betting_string(&game_info, 0, &betting).unwrap();
+        let player = state.current_player().unwrap() as usize;
+        let (mut difference, mut deals) = (0., 0);
+        for ((hole_cards, board_cards), _) in Deals::new(&game_info) {
+            let before = base.action_probabilities(&game_info, &state, &hole_cards[player], &board_cards);
+            let after = profile.action_probabilities(&game_info, &state, &hole_cards[player], &board_cards);
+            difference += before.iter().zip(&after).map(|((_, p), (_, q))| (p - q).abs()).sum::<f64>();
+            deals += 1;
+        }
+        println!("{:>8}: subtree strategy differs by {:.3} on average", betting, difference / deals as f64);
+    }
+}
@@ -0,0 +1,46 @@
+//! Trains Leduc poker from the built-in configs, printing the exploitability of the average
+//! strategy as training progresses, then saves the strategy and nodes for `leduc_play`.
+//!
+//! cargo run --release --example leduc_train -- [output dir]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ungar::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    card_abstraction::CardAbstraction,
+    cfr::{CFRConfig, CFREngine},
+    game::{GameInfo, GameState},
+};
+
+const CHECKPOINTS: u32 = 20;
+const ITERATIONS_PER_CHECKPOINT: u32 = 2500;
+
+fn main() {
+    env_logger::init();
+
+    let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+    let output_dir = std::env::args().nth(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("data"));
+    fs::create_dir_all(&output_dir).expect("failed to create output dir");
+
+    let game_info = GameInfo::load_game_info(&configs.join("leduc.json")).unwrap();
+    let starting_state = GameState::new(&game_info, 0);
+    let action_abstraction = ActionAbstraction::from_config(&configs.join("leduc_action_abstraction.json")).unwrap();
+    let card_abstraction = CardAbstraction::from_config(&configs.join("leduc_card_abstraction.json")).unwrap();
+    let cfr_config = CFRConfig::from_config(&configs.join("leduc_cfr_config.json"));
+
+    let abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
+    let mut cfr_engine = CFREngine::new(abstract_game, cfr_config);
+
+    for _ in 0..CHECKPOINTS {
+        cfr_engine.mccfr_p(ITERATIONS_PER_CHECKPOINT, 20, 400, 100000, 2500).unwrap();
+        println!("iteration {:>6}: exploitability {:.4} chips/hand", cfr_engine.iteration(), cfr_engine.exploitability().unwrap());
+    }
+
+    let strategy_path = output_dir.join("leduc_strategy.bin");
+    let nodes_path = output_dir.join("leduc_nodes.bin");
+    cfr_engine.save_average_strategy(&strategy_path);
+    cfr_engine.save_nodes(&nodes_path);
+    println!("saved strategy to {} and nodes to {}", strategy_path.display(), nodes_path.display());
+}
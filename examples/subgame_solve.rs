@@ -0,0 +1,48 @@
+//! Solves a turn or river spot between two ranges and writes the strategy of every decision and
+//! the value of every hand as JSON. Turn spots deal every river card.
+//!
+//! cargo run --release --example subgame_solve -- <board> <oop range> <ip range> <pot> <stack> [output path]
+//!
+//! For example `subgame_solve -- KhTd7c4s2h "AA,KK,AK,KQ,76s" "TT+,AT+,KJ+" 100 200`. Bets are
+//! half and full pot, raises are pot sized, and either player can go all in.
+
+use std::fs;
+
+use ungar::{
+    card_set::parse_cards,
+    evaluator::HandRanking,
+    range::Range,
+    subgame_solver::{BetSizes, SubgameSolver},
+};
+
+const MAX_ITERATIONS: u32 = 2000;
+
+fn main() {
+    env_logger::init();
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    assert!(args.len() >= 5, "usage: subgame_solve <board> <oop range> <ip range> <pot> <stack> [output path]");
+    let board = parse_cards(&args[0]).expect("invalid board");
+    let ranges = [&args[1], &args[2]].map(|r| r.parse::<Range>().expect("invalid range"));
+    let pot: u32 = args[3].parse().expect("invalid pot");
+    let stack: u32 = args[4].parse().expect("invalid stack");
+    let bet_sizes = BetSizes { bets: vec![0.5, 1.], raises: vec![1.], max_raises: 2, all_in: true };
+
+    let mut solver = SubgameSolver::new(HandRanking::Standard, &board, ranges, pot, stack, bet_sizes);
+    // Stop at a thousandth of the pot
+    let exploitability = solver.solve(MAX_ITERATIONS, pot as f64 / 1000.).expect("solver was cancelled");
+    let [oop, ip] = solver.expected_value();
+    println!("{} nodes, {} iterations, exploitability {:.4} chips", solver.num_nodes(), solver.iteration(), exploitability);
+    println!("expected value oop {:.3}, ip {:.3}", oop, ip);
+
+    let solution = solver.solution();
+    let root = &solution.decisions[0];
+    println!("oop actions {}", root.actions.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(", "));
+    for (hand, probabilities) in root.strategy.iter().take(10) {
+        println!("{} {:?}", hand, probabilities.iter().map(|p| format!("{:.2}", p)).collect::<Vec<String>>());
+    }
+
+    if let Some(path) = args.get(5) {
+        fs::write(path, solution.to_json()).expect("failed to write solution");
+    }
+}
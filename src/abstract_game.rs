@@ -1,7 +1,7 @@
 use super::{
     action_abstraction::{ActionAbstraction},
-    card_abstraction::{BucketId, CardAbstraction},
-    game::{Action, GameInfo, GameState, MAX_PLAYERS},
+    card_abstraction::{BucketId, CardAbstraction, Recall},
+    game::{Action, GameInfo, GameState},
     node::{Nodes, Node, NodeId},
 };
 
@@ -18,6 +18,8 @@ pub struct AbstractGame {
 
 impl AbstractGame {
     pub fn new(game_info: GameInfo, state: GameState, action_abstraction: ActionAbstraction, card_abstraction: CardAbstraction) -> AbstractGame {
+        assert!(!game_info.is_stud(), "stud games can't be abstracted since the acting order depends on the up cards");
+        check_recall(&game_info, &card_abstraction);
         AbstractGame {
             game_info,
             nodes: Nodes::new(state),
@@ -27,9 +29,12 @@ impl AbstractGame {
     }
 
     pub fn load_nodes(game_info: GameInfo, path: &Path, action_abstraction: ActionAbstraction, card_abstraction: CardAbstraction) -> AbstractGame {
+        check_recall(&game_info, &card_abstraction);
+        let mut nodes = Nodes::from_file(path);
+        nodes.add_side_pots(&game_info);
         AbstractGame {
             game_info,
-            nodes: Nodes::from_file(path),
+            nodes,
             action_abstraction,
             card_abstraction,
         }
@@ -39,30 +44,58 @@ impl AbstractGame {
         self.action_abstraction.get_actions(&self.game_info, game_state)
     }
 
-    pub fn get_bucket(&self, round:u8, board_cards: &Vec<Card>, hole_cards: &Vec<Card>) -> BucketId {
+    pub fn get_bucket(&self, round: u8, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
         self.card_abstraction.get_bucket(round, board_cards, hole_cards)
     }
 
     pub fn apply_action_to_node(&mut self, node_id: NodeId, board_cards_i: &mut usize, action: Action) -> NodeId {
         //TODO: deal with nolimit situations where actions get rounded or make new action in tree
         let current_node = self.nodes.get_node(node_id).unwrap();
-        let child = match current_node.children.get(&action) {
+        let child = match current_node.child(&action) {
             Some(child_node_id) => {
-                *board_cards_i = self.game_info.total_board_cards(self.nodes.get_node(*child_node_id).unwrap().state.current_round()) as usize;
+                *board_cards_i = self.game_info.total_board_cards(self.nodes.get_node(child_node_id).unwrap().state.current_round()) as usize;
                 
-                *child_node_id
+                child_node_id
             },
             None => {
-                let new_node = Node::new(current_node.state.apply_action_no_cards(&self.game_info, action).unwrap());
+                let new_node = Node::with_side_pots(&self.game_info, current_node.state.apply_action_no_cards(&self.game_info, action).unwrap());
 
                 *board_cards_i = self.game_info.total_board_cards(new_node.state.current_round()) as usize;
 
-                let child_node_id = self.nodes.add_node(new_node);
-                self.nodes.nodes_map.get_mut(&node_id).unwrap().children.insert(action, child_node_id);
-                child_node_id
+                self.nodes.add_child(node_id, action, new_node)
             }
         };
 
         child
     }
+
+    /// Adds every node the action abstraction reaches from the root, the tree training builds
+    /// as it visits it. Only feasible for trees that fit in memory, `infosets::estimate_infosets`
+    /// sizes the others.
+    pub fn expand_tree(&mut self) {
+        let mut expanded = vec![false; self.nodes.len()];
+        let mut stack = vec![self.nodes.get_root_node_id()];
+        while let Some(node_id) = stack.pop() {
+            // Merged nodes are reached by several paths but only expanded once
+            expanded.resize(self.nodes.len(), false);
+            if expanded[node_id] {
+                continue;
+            }
+            expanded[node_id] = true;
+
+            let state = &self.nodes.get_node(node_id).unwrap().state;
+            if state.is_finished() {
+                continue;
+            }
+            let mut board_cards_i = 0;
+            for action in self.get_actions(&state.clone()) {
+                stack.push(self.apply_action_to_node(node_id, &mut board_cards_i, action));
+            }
+        }
+    }
+}
+
+/// Perfect recall buckets earlier rounds with the current hole cards, which draws replace
+fn check_recall(game_info: &GameInfo, card_abstraction: &CardAbstraction) {
+    assert!(!game_info.has_draws() || card_abstraction.recall() == Recall::Imperfect, "perfect recall isn't supported in draw games");
 }
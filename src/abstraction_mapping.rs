@@ -0,0 +1,118 @@
+use super::{
+    abstract_game::AbstractGame,
+    bucket_table::BucketTableBuilder,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::{BucketId, CardAbstraction},
+    game::GameInfo,
+    strategy::Strategy,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Correspondence between the buckets of a fine and a coarse card abstraction of the same game.
+/// Every canonical hand of a round is bucketed by both and each fine bucket maps to the coarse
+/// bucket most of its hands fall in, ties going to the lowest coarse bucket. When the fine
+/// abstraction refines the coarse one every hand agrees with its mapping.
+///
+/// Only feasible for games whose canonical hands can be enumerated, like bucket table
+/// generation.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AbstractionMapping {
+    /// Coarse bucket of every fine bucket of each round
+    rounds: Vec<BTreeMap<BucketId, BucketId>>,
+    /// Fraction of the canonical hands of each round that are in the coarse bucket their fine
+    /// bucket maps to
+    agreement: Vec<f64>,
+}
+
+impl AbstractionMapping {
+    pub fn new(game_info: &GameInfo, coarse: &CardAbstraction, fine: &CardAbstraction, cancel_token: &CancelToken) -> Result<AbstractionMapping, Cancelled> {
+        let mut rounds = Vec::new();
+        let mut agreement = Vec::new();
+
+        for round in 0..game_info.num_rounds() {
+            let builder = BucketTableBuilder::new(game_info, round);
+            let mut overlaps: HashMap<BucketId, HashMap<BucketId, u64>> = HashMap::new();
+            for i in 0..builder.len() {
+                cancel_token.check()?;
+                let (hole_cards, board_cards) = builder.hand(i);
+                let fine_bucket = fine.get_bucket(round, &board_cards, &hole_cards);
+                let coarse_bucket = coarse.get_bucket(round, &board_cards, &hole_cards);
+                *overlaps.entry(fine_bucket).or_default().entry(coarse_bucket).or_insert(0) += 1;
+            }
+
+            let mut mapping = BTreeMap::new();
+            let mut agreeing = 0;
+            for (fine_bucket, counts) in overlaps {
+                let (coarse_bucket, count) = counts.into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+                    .unwrap();
+                mapping.insert(fine_bucket, coarse_bucket);
+                agreeing += count;
+            }
+            rounds.push(mapping);
+            agreement.push(if builder.is_empty() { 1. } else { agreeing as f64 / builder.len() as f64 });
+        }
+
+        Ok(AbstractionMapping { rounds, agreement })
+    }
+
+    /// Returns the coarse bucket `fine_bucket` maps to, `None` if no canonical hand is in it
+    pub fn coarse_bucket(&self, round: u8, fine_bucket: BucketId) -> Option<BucketId> {
+        self.rounds[round as usize].get(&fine_bucket).copied()
+    }
+
+    /// Returns the fine buckets that map to `coarse_bucket`
+    pub fn fine_buckets(&self, round: u8, coarse_bucket: BucketId) -> Vec<BucketId> {
+        self.rounds[round as usize].iter()
+            .filter(|(_, c)| **c == coarse_bucket)
+            .map(|(f, _)| *f)
+            .collect()
+    }
+
+    /// Returns the fraction of the round's canonical hands in the coarse bucket their fine bucket
+    /// maps to
+    pub fn agreement(&self, round: u8) -> f64 {
+        self.agreement[round as usize]
+    }
+
+    /// Whether every fine bucket of the round lies inside a single coarse bucket
+    pub fn is_nested(&self, round: u8) -> bool {
+        self.agreement[round as usize] == 1.
+    }
+
+    /// Returns a strategy of the fine abstraction playing like `coarse_strategy` in the coarse
+    /// bucket of every fine bucket, to warm start training or refine a subgame. The nodes of
+    /// `abstract_game` must be those `coarse_strategy` was trained with, so both abstract games
+    /// should load the same nodes.
+    pub fn refine_strategy(&self, abstract_game: &AbstractGame, coarse_strategy: &Strategy) -> Strategy {
+        let fine_buckets = self.rounds.iter()
+            .map(|mapping| {
+                let mut fine_buckets: HashMap<BucketId, Vec<BucketId>> = HashMap::new();
+                for (fine_bucket, coarse_bucket) in mapping {
+                    fine_buckets.entry(*coarse_bucket).or_default().push(*fine_bucket);
+                }
+                fine_buckets
+            })
+            .collect::<Vec<HashMap<BucketId, Vec<BucketId>>>>();
+
+        let mut strategy = Strategy::new();
+        for ((node_id, coarse_bucket), counts) in coarse_strategy.0.iter() {
+            let node = abstract_game.nodes.get_node(*node_id).expect("strategy has a node missing from the abstract game");
+            for fine_bucket in fine_buckets[node.state.current_round() as usize].get(coarse_bucket).into_iter().flatten() {
+                strategy.0.insert((*node_id, *fine_bucket), counts.clone());
+            }
+        }
+        strategy
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> AbstractionMapping {
+        serde_json::from_str(json).expect("failed to deserialize abstraction mapping")
+    }
+}
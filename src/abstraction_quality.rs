@@ -0,0 +1,152 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    best_response,
+    bucket_table::{expected_hand_strength, BucketTableBuilder},
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::{BucketId, CardAbstraction},
+    cfr::{CFRConfig, CFREngine},
+    cross_validation::TrainingBudget,
+    game::{GameInfo, GameState},
+    hand_indexer::HandIndex,
+};
+
+use poker::Card;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Quality of the buckets of one round, over the canonical hands of the round each counted once
+#[derive(Clone, Debug)]
+pub struct RoundQuality {
+    pub num_hands: HandIndex,
+    /// Buckets holding at least one hand
+    pub num_buckets: u64,
+    /// Average squared difference between the equity of a hand and the mean equity of its
+    /// bucket, zero when every bucket holds hands of equal equity
+    pub equity_variance: f64,
+    /// Hands that share a bucket with a hand whose equity differs by more than the conflation
+    /// threshold
+    pub conflated_hands: u64,
+}
+
+/// Quality measures of a card abstraction, see `AbstractionEvaluator`
+#[derive(Clone, Debug)]
+pub struct AbstractionQuality {
+    pub rounds: Vec<RoundQuality>,
+    /// Exploitability in chips per hand of the strategy trained with the abstraction against a
+    /// best responder that sees its exact cards, `None` if it was not computed
+    pub exploitability: Option<f64>,
+}
+
+impl fmt::Display for AbstractionQuality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>5} {:>10} {:>8} {:>15} {:>10}", "round", "hands", "buckets", "equity variance", "conflated")?;
+        for (round, quality) in self.rounds.iter().enumerate() {
+            writeln!(f, "{:>5} {:>10} {:>8} {:>15.6} {:>10}", round, quality.num_hands, quality.num_buckets, quality.equity_variance, quality.conflated_hands)?;
+        }
+        if let Some(exploitability) = self.exploitability {
+            writeln!(f, "exploitability {:.4}", exploitability)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures how much a card abstraction loses, to compare bucket configurations of a game.
+/// Equity is the expected hand strength at showdown against a random hand over every runout, so
+/// every canonical hand of every round is evaluated and only games with few enough hands are
+/// feasible. Exploitability additionally trains the abstraction like `CrossValidation` and is
+/// only feasible for games like Leduc.
+pub struct AbstractionEvaluator {
+    game_info: GameInfo,
+    /// Equity difference above which two hands are considered strategically distinct
+    conflation_threshold: f64,
+    exploitability: Option<(ActionAbstraction, CFRConfig, TrainingBudget)>,
+    cancel_token: CancelToken,
+}
+
+impl AbstractionEvaluator {
+    pub fn new(game_info: GameInfo) -> AbstractionEvaluator {
+        AbstractionEvaluator {
+            game_info,
+            conflation_threshold: 0.1,
+            exploitability: None,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    pub fn set_conflation_threshold(&mut self, conflation_threshold: f64) {
+        assert!(conflation_threshold >= 0.);
+        self.conflation_threshold = conflation_threshold;
+    }
+
+    /// Trains every evaluated abstraction with `action_abstraction` and `budget` to measure the
+    /// exploitability of its strategy in the full game
+    pub fn set_exploitability(&mut self, action_abstraction: ActionAbstraction, cfr_config: CFRConfig, budget: TrainingBudget) {
+        self.exploitability = Some((action_abstraction, cfr_config, budget));
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn evaluate(&self, card_abstraction: CardAbstraction) -> Result<AbstractionQuality, Cancelled> {
+        let rounds = (0..self.game_info.num_rounds())
+            .map(|round| self.round_quality(&card_abstraction, round))
+            .collect::<Result<Vec<RoundQuality>, Cancelled>>()?;
+
+        let exploitability = match &self.exploitability {
+            Some((action_abstraction, cfr_config, budget)) => {
+                let abstract_game = AbstractGame::new(self.game_info.clone(), GameState::new(&self.game_info, 0), action_abstraction.clone(), card_abstraction);
+                let mut engine = CFREngine::new(abstract_game, cfr_config.clone());
+                engine.set_cancel_token(self.cancel_token.clone());
+                engine.mccfr_p(budget.iterations, budget.strategy_interval, budget.prune_threshold, budget.lcfr_threshold, budget.discount_interval)?;
+                let (mut abstract_game, strategy) = engine.into_parts();
+                Some(best_response::lossless_exploitability(&mut abstract_game, &strategy, &self.cancel_token)?)
+            },
+            None => None,
+        };
+
+        Ok(AbstractionQuality { rounds, exploitability })
+    }
+
+    fn round_quality(&self, card_abstraction: &CardAbstraction, round: u8) -> Result<RoundQuality, Cancelled> {
+        let mut builder = BucketTableBuilder::new(&self.game_info, round);
+        builder.set_cancel_token(self.cancel_token.clone());
+        let deck = self.game_info.generate_deck().collect::<Vec<Card>>();
+        let hand_ranking = self.game_info.hand_ranking();
+        let final_board_cards = self.game_info.total_board_cards(self.game_info.num_rounds() - 1) as usize;
+        let equities = builder.map(0..builder.len(), |hole_cards, board_cards| {
+            expected_hand_strength(hand_ranking, &deck, hole_cards, board_cards, final_board_cards)
+        })?;
+
+        // Card abstractions aren't shared between threads so hands are bucketed one at a time
+        let mut buckets: HashMap<BucketId, Vec<f64>> = HashMap::new();
+        for (i, equity) in equities.into_iter().enumerate() {
+            self.cancel_token.check()?;
+            let (hole_cards, board_cards) = builder.hand(i as HandIndex);
+            buckets.entry(card_abstraction.get_bucket(round, &board_cards, &hole_cards)).or_default().push(equity);
+        }
+
+        let mut squared_error = 0.;
+        let mut conflated_hands = 0;
+        for bucket_equities in buckets.values() {
+            let mean = bucket_equities.iter().sum::<f64>() / bucket_equities.len() as f64;
+            squared_error += bucket_equities.iter().map(|e| (e - mean) * (e - mean)).sum::<f64>();
+
+            let min = bucket_equities.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = bucket_equities.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            conflated_hands += bucket_equities.iter()
+                .filter(|e| max - **e > self.conflation_threshold || **e - min > self.conflation_threshold)
+                .count() as u64;
+        }
+
+        let num_hands = builder.len();
+        Ok(RoundQuality {
+            num_hands,
+            num_buckets: buckets.len() as u64,
+            equity_variance: if num_hands == 0 { 0. } else { squared_error / num_hands as f64 },
+            conflated_hands,
+        })
+    }
+}
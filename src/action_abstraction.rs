@@ -1,22 +1,24 @@
 use super::{
-    game::{Action, GameInfo, GameState},
+    config::{self, ConfigError},
+    game::{Action, GameInfo, GameState, PlayerId},
 };
 
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
 use serde::{Deserialize, Serialize};
 
-/// Represents a possible abstract raise type
-#[derive(Debug, Deserialize, Serialize)]
+/// Represents a possible abstract raise type. Limit games have a single raise per round which
+/// every raise type maps to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum AbstractRaiseType {
     AllIn,
     PotRatio(f32),
-    /// Usually just an option for limit games
+    /// Raise of a fixed number of chips over the current bet
     Fixed(u32),
 }
 
 /// Represents possible configurations for a raise on a particular round
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum RaiseRoundConfig {
     NotAllowed,
     Always,
@@ -24,29 +26,54 @@ pub enum RaiseRoundConfig {
     Before(u32),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AbstractRaise {
     pub raise_type: AbstractRaiseType,
     pub round_config: Vec<RaiseRoundConfig>,
 }
 
 /// Used to generate possible abstract actions for a given state
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ActionAbstraction {
     possible_raises: Vec<AbstractRaise>,
+    /// Raises of the seats that don't use `possible_raises`, e.g. fine sizes for the hero and
+    /// coarse ones for the opponents to keep the tree small
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    seat_raises: BTreeMap<PlayerId, Vec<AbstractRaise>>,
 }
 
 impl ActionAbstraction {
     pub fn new(possible_raises: Vec<AbstractRaise>) -> ActionAbstraction {
-        ActionAbstraction { possible_raises }
+        ActionAbstraction {
+            possible_raises,
+            seat_raises: BTreeMap::new(),
+        }
+    }
+
+    /// Gives `seat` its own raises instead of the shared ones
+    pub fn set_seat_raises(&mut self, seat: PlayerId, possible_raises: Vec<AbstractRaise>) {
+        self.seat_raises.insert(seat, possible_raises);
+    }
+
+    /// Returns the raises `seat` may make
+    pub fn raises(&self, seat: PlayerId) -> &[AbstractRaise] {
+        self.seat_raises.get(&seat).unwrap_or(&self.possible_raises)
     }
 
-    pub fn from_config(path: &Path) -> ActionAbstraction {
-        let action_abstraction: ActionAbstraction = serde_json::from_str(&fs::read_to_string(path).expect("failed to read action abstraction config")).expect("failed to deserialize action abstraction");
-        action_abstraction
+    pub fn from_config(path: &Path) -> Result<ActionAbstraction, ConfigError> {
+        config::read_json(path)
+    }
+
+    pub fn from_json(json: &str) -> Result<ActionAbstraction, ConfigError> {
+        config::from_json(json)
     }
 
     pub fn get_actions(&self, game_info: &GameInfo, game_state: &GameState) -> Vec<Action> {
+        if game_state.is_drawing() {
+            // Every subset of the hole cards can be discarded
+            return (0..1 << game_info.num_hole_cards()).map(Action::Discard).collect();
+        }
+
         let mut actions: Vec<Action> = Vec::new();
 
         if game_state.is_valid_action(game_info, Action::Fold) {
@@ -57,7 +84,11 @@ impl ActionAbstraction {
             actions.push(Action::Call);
         }
 
-        for abstract_raise in &self.possible_raises {
+        let raises = match game_state.current_player() {
+            Ok(player) => self.raises(player),
+            Err(_) => &self.possible_raises,
+        };
+        for abstract_raise in raises {
             if let Some(raise) = game_state.abstract_raise_to_real(game_info, abstract_raise) {
                 if !actions.contains(&raise) {
                     actions.push(raise);
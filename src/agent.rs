@@ -0,0 +1,534 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    cfr::CFREngine,
+    game::{Action, GameInfo, GameState, PlayerId},
+    node::NodeId,
+    opponent_model::OpponentModel,
+    reload::StrategySlot,
+    strategy::{pot_fraction, sample_probabilities, SharedStrategy, Strategy},
+};
+
+use poker::Card;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::path::{Path, PathBuf};
+
+/// A player that can be seated by the dealer
+pub trait Agent {
+    /// Called before a hand starts with the agent's seat and hole cards
+    fn begin_hand(&mut self, _game_info: &GameInfo, _seat: PlayerId, _hole_cards: &[Card]) {}
+
+    /// Called after every action in the hand, including the agent's own
+    fn observe_action(&mut self, _game_info: &GameInfo, _player: PlayerId, _action: Action) {}
+
+    /// Called when the up cards of a stud round are dealt with every player's face up cards so far
+    fn observe_up_cards(&mut self, _game_info: &GameInfo, _round: u8, _up_cards: &[Vec<Card>]) {}
+
+    /// Called when the board cards of a new round are dealt, `all_in` is set if the round is
+    /// dealt without betting because nobody can act anymore
+    fn observe_street(&mut self, _game_info: &GameInfo, _round: u8, _board_cards: &[Card], _all_in: bool) {}
+
+    /// Returns the action to take in `state`, `board_cards` only holds the cards dealt so far
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action;
+
+    /// Called when the hand is over with the payout of every player
+    fn end_hand(&mut self, _game_info: &GameInfo, _state: &GameState, _payouts: &[i32]) {}
+
+    /// Called once the dealer has finished playing a match
+    fn end_session(&mut self, _game_info: &GameInfo) {}
+}
+
+/// Plays by sampling a trained strategy
+pub struct StrategyAgent {
+    abstract_game: AbstractGame,
+    strategy: Strategy,
+    node_id: NodeId,
+    /// Real state of the hand, `None` once it can't be followed like after stud up cards. Nodes
+    /// merged by a history abstraction keep the state of the betting that reached them first, so
+    /// actions there are translated between it and the real state.
+    state: Option<GameState>,
+    /// Slot refreshed strategies are taken from before every hand
+    slot: Option<StrategySlot>,
+    rng: StdRng,
+}
+
+impl StrategyAgent {
+    pub fn new(abstract_game: AbstractGame, strategy: Strategy) -> StrategyAgent {
+        let node_id = abstract_game.nodes.get_root_node_id();
+        StrategyAgent {
+            abstract_game,
+            strategy,
+            node_id,
+            state: None,
+            slot: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to sample actions so the agent plays reproducibly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Plays strategies put in `slot` from the next hand on, so a long match can pick up
+    /// refreshed blueprints, e.g. from a `StrategyWatcher`
+    pub fn set_strategy_slot(&mut self, slot: StrategySlot) {
+        self.slot = Some(slot);
+    }
+}
+
+impl Agent for StrategyAgent {
+    fn begin_hand(&mut self, game_info: &GameInfo, _seat: PlayerId, _hole_cards: &[Card]) {
+        if let Some(profile) = self.slot.as_ref().and_then(|slot| slot.take()) {
+            info!("Swapped in a new strategy");
+            self.abstract_game = profile.abstract_game;
+            self.strategy = profile.strategy;
+        }
+        self.node_id = self.abstract_game.nodes.get_root_node_id();
+        self.state = Some(GameState::new(game_info, 0));
+    }
+
+    fn observe_action(&mut self, game_info: &GameInfo, _player: PlayerId, action: Action) {
+        let mut node_action = action;
+        if let Some(state) = self.state.take() {
+            let node = self.abstract_game.nodes.get_node(self.node_id).unwrap();
+            self.state = state.apply_action_no_cards(game_info, action).ok();
+            if node.state.is_finished() {
+                return;
+            }
+            if node.child(&action).is_none() && !node.state.same_betting(&state) {
+                let children = node.children().map(|(a, _)| a).collect::<Vec<Action>>();
+                node_action = closest_action(game_info, &state, action, &self.abstract_game.game_info, &node.state, &children);
+            }
+        }
+        let mut board_cards_i = 0;
+        self.node_id = self.abstract_game.apply_action_to_node(self.node_id, &mut board_cards_i, node_action);
+    }
+
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        let node = self.abstract_game.nodes.get_node(self.node_id).unwrap();
+        let real_actions = || self.abstract_game.action_abstraction.get_actions(game_info, state);
+        // A merged node can end the hand before the real one, like a profile whose abstract
+        // hand ended it plays uniformly
+        if node.state.is_finished() {
+            return *real_actions().choose(&mut self.rng).expect("failed to find an action");
+        }
+        let bucket_id = self.abstract_game.get_bucket(state.current_round(), board_cards, hole_cards);
+        let action = self.strategy.sample(&self.abstract_game, self.node_id, bucket_id, &mut self.rng);
+        if node.state.same_betting(state) || state.is_valid_action(game_info, action) {
+            return action;
+        }
+        closest_action(&self.abstract_game.game_info, &node.state, action, game_info, state, &real_actions())
+    }
+}
+
+/// Returns the action of `actions` at `to_state` closest to `action` made at `from_state`, for
+/// trees whose nodes differ from the real state. Raises go to the raise of the closest size
+/// relative to the pot, all in raises to all in raises when there are any.
+fn closest_action(from_info: &GameInfo, from_state: &GameState, action: Action, to_info: &GameInfo, to_state: &GameState, actions: &[Action]) -> Action {
+    let raise = match action {
+        Action::Raise(r) => r,
+        _ => return action,
+    };
+    let is_all_in = |info: &GameInfo, state: &GameState, raise: Action| {
+        let player = state.current_player().ok();
+        let next = state.apply_action_no_cards(info, raise).ok();
+        player.zip(next).is_some_and(|(p, next)| next.chips_behind(p) == 0)
+    };
+    let all_in = is_all_in(from_info, from_state, action);
+    let fraction = pot_fraction(from_state, from_info, raise);
+    let raises = actions.iter()
+        .filter_map(|a| match a {
+            Action::Raise(r) => Some((*a, (pot_fraction(to_state, to_info, *r) - fraction).abs(), is_all_in(to_info, to_state, *a) == all_in)),
+            _ => None,
+        })
+        .collect::<Vec<(Action, f64, bool)>>();
+    let matching = raises.iter().any(|(_, _, m)| *m);
+    raises.into_iter()
+        .filter(|(_, _, m)| *m || !matching)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(Action::Call, |(a, _, _)| a)
+}
+
+/// Plays by sampling a `SharedStrategy`, so agents in many matches at once can play one loaded
+/// blueprint. Real actions are translated into the abstraction on every query instead of being
+/// followed through the tree, which the handle's cache keeps cheap.
+pub struct SharedStrategyAgent {
+    strategy: SharedStrategy,
+    rng: StdRng,
+}
+
+impl SharedStrategyAgent {
+    pub fn new(strategy: SharedStrategy) -> SharedStrategyAgent {
+        SharedStrategyAgent {
+            strategy,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to sample actions so the agent plays reproducibly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl Agent for SharedStrategyAgent {
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        self.strategy.sample_action(&mut self.rng, game_info, state, hole_cards, board_cards, 1.)
+    }
+}
+
+/// Picks uniformly between the actions of an action abstraction
+pub struct RandomAgent {
+    action_abstraction: ActionAbstraction,
+    rng: StdRng,
+}
+
+impl RandomAgent {
+    pub fn new(action_abstraction: ActionAbstraction) -> RandomAgent {
+        RandomAgent {
+            action_abstraction,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to pick actions so the agent plays reproducibly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl Agent for RandomAgent {
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, _hole_cards: &[Card], _board_cards: &[Card]) -> Action {
+        *self.action_abstraction.get_actions(game_info, state).choose(&mut self.rng).unwrap()
+    }
+}
+
+/// Checks or calls every bet and never folds or raises, standing pat in draw games. The usual
+/// sanity baseline, any strategy worth playing should beat it.
+pub struct CallingStation;
+
+impl Agent for CallingStation {
+    fn get_action(&mut self, _game_info: &GameInfo, state: &GameState, _hole_cards: &[Card], _board_cards: &[Card]) -> Action {
+        if state.is_drawing() { Action::Discard(0) } else { Action::Call }
+    }
+}
+
+/// Plays by its equity against random hands of every player still in: raises the raise of an
+/// action abstraction closest to the pot with at least `raise_equity`, calls when the equity
+/// beats the pot odds and folds otherwise, checking whenever it can. Stands pat in draw games.
+/// A simple rule based opponent that punishes strategies folding or calling too much.
+pub struct HeuristicAgent {
+    action_abstraction: ActionAbstraction,
+    raise_equity: f64,
+    num_equity_samples: u32,
+    rng: StdRng,
+}
+
+impl HeuristicAgent {
+    pub fn new(action_abstraction: ActionAbstraction) -> HeuristicAgent {
+        HeuristicAgent {
+            action_abstraction,
+            raise_equity: 0.7,
+            num_equity_samples: 200,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to sample equities so the agent plays reproducibly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the equity from which the agent raises, 0.7 by default
+    pub fn set_raise_equity(&mut self, raise_equity: f64) {
+        self.raise_equity = raise_equity;
+    }
+
+    /// Sets the random runouts and opponent hands the equity is averaged over, 200 by default
+    pub fn set_num_equity_samples(&mut self, num_equity_samples: u32) {
+        assert!(num_equity_samples > 0, "need at least one equity sample");
+        self.num_equity_samples = num_equity_samples;
+    }
+
+    /// Returns the share of the pot `hole_cards` win at showdown against random hands of the
+    /// `num_opponents` players still in, splitting ties
+    fn equity(&mut self, game_info: &GameInfo, hole_cards: &[Card], board_cards: &[Card], num_opponents: usize) -> f64 {
+        let evaluator = game_info.hand_ranking().evaluator();
+        let direction = game_info.ranking_direction();
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        let mut remaining = game_info.generate_deck()
+            .filter(|c| !hole_cards.contains(c) && !board_cards.contains(c))
+            .collect::<Vec<Card>>();
+        let num_cards = num_board_cards.saturating_sub(board_cards.len()) + num_opponents * hole_cards.len();
+        if num_cards > remaining.len() {
+            return 0.5;
+        }
+
+        let mut total = 0.;
+        for _ in 0..self.num_equity_samples {
+            let (dealt, _) = remaining.partial_shuffle(&mut self.rng, num_cards);
+            let (runout, opponents) = dealt.split_at(num_board_cards.saturating_sub(board_cards.len()));
+            let board = [board_cards, runout].concat();
+            let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, &board));
+            let mut ties = 0;
+            let beaten = opponents.chunks(hole_cards.len()).all(|hand| {
+                let opponent_rank = direction.orient(evaluator.evaluate_showdown(hand, &board));
+                ties += (opponent_rank == rank) as u32;
+                opponent_rank <= rank
+            });
+            if beaten {
+                total += 1. / (ties + 1) as f64;
+            }
+        }
+        total / self.num_equity_samples as f64
+    }
+}
+
+impl Agent for HeuristicAgent {
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        if state.is_drawing() {
+            return Action::Discard(0);
+        }
+        let player = state.current_player().expect("no player to act");
+        let num_opponents = (0..game_info.num_players()).filter(|p| *p != player && !state.has_folded(*p)).count();
+        let equity = self.equity(game_info, hole_cards, board_cards, num_opponents);
+        let actions = self.action_abstraction.get_actions(game_info, state);
+
+        if equity >= self.raise_equity {
+            let pot_raise = actions.iter()
+                .filter_map(|a| match a {
+                    Action::Raise(r) => Some((*a, (pot_fraction(state, game_info, *r) - 1.).abs())),
+                    _ => None,
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((action, _)) = pot_raise {
+                return action;
+            }
+        }
+        if state.amount_to_call(player) == 0 || equity >= state.pot_odds(game_info, player) || !actions.contains(&Action::Fold) {
+            Action::Call
+        } else {
+            Action::Fold
+        }
+    }
+}
+
+/// Wraps an agent and sometimes overrides its decision with an exploratory one, for generating
+/// diverse self-play data, e.g. hands for opponent modeling or samples for Deep CFR buffers. With
+/// probability `exploration` an action of `actions` is picked uniformly, and with the probability
+/// set by `set_off_tree_sizings` one of the extra sizings that `actions` doesn't contain. The
+/// wrapped agent observes every action, so it keeps track of hands it didn't choose the line of.
+pub struct ExploringAgent {
+    agent: Box<dyn Agent>,
+    actions: ActionAbstraction,
+    exploration: f64,
+    off_tree: Option<(ActionAbstraction, f64)>,
+    num_decisions: u64,
+    num_explored: u64,
+    rng: StdRng,
+}
+
+impl ExploringAgent {
+    pub fn new(agent: Box<dyn Agent>, actions: ActionAbstraction, exploration: f64) -> ExploringAgent {
+        assert!((0. ..=1.).contains(&exploration), "exploration must be between 0 and 1");
+        ExploringAgent {
+            agent,
+            actions,
+            exploration,
+            off_tree: None,
+            num_decisions: 0,
+            num_explored: 0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to decide when to explore, the wrapped agent is seeded separately
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Plays with probability `probability` one of the actions of `sizings` missing from the
+    /// exploration actions, if there are any in the spot
+    pub fn set_off_tree_sizings(&mut self, sizings: ActionAbstraction, probability: f64) {
+        assert!((0. ..=1.).contains(&probability), "off tree probability must be between 0 and 1");
+        assert!(self.exploration + probability <= 1., "exploration and off tree probability can't add up to more than 1");
+        self.off_tree = Some((sizings, probability));
+    }
+
+    pub fn agent(&self) -> &dyn Agent {
+        self.agent.as_ref()
+    }
+
+    /// Returns how many decisions were made and how many of them overrode the wrapped agent
+    pub fn num_explored(&self) -> (u64, u64) {
+        (self.num_decisions, self.num_explored)
+    }
+
+    fn explore(&mut self, game_info: &GameInfo, state: &GameState) -> Option<Action> {
+        let roll = self.rng.gen::<f64>();
+        if roll < self.exploration {
+            return self.actions.get_actions(game_info, state).choose(&mut self.rng).copied();
+        }
+        let (sizings, probability) = self.off_tree.as_ref()?;
+        if roll >= self.exploration + probability {
+            return None;
+        }
+        let on_tree = self.actions.get_actions(game_info, state);
+        sizings.get_actions(game_info, state)
+            .into_iter()
+            .filter(|a| !on_tree.contains(a))
+            .collect::<Vec<Action>>()
+            .choose(&mut self.rng)
+            .copied()
+    }
+}
+
+impl Agent for ExploringAgent {
+    fn begin_hand(&mut self, game_info: &GameInfo, seat: PlayerId, hole_cards: &[Card]) {
+        self.agent.begin_hand(game_info, seat, hole_cards);
+    }
+
+    fn observe_action(&mut self, game_info: &GameInfo, player: PlayerId, action: Action) {
+        self.agent.observe_action(game_info, player, action);
+    }
+
+    fn observe_up_cards(&mut self, game_info: &GameInfo, round: u8, up_cards: &[Vec<Card>]) {
+        self.agent.observe_up_cards(game_info, round, up_cards);
+    }
+
+    fn observe_street(&mut self, game_info: &GameInfo, round: u8, board_cards: &[Card], all_in: bool) {
+        self.agent.observe_street(game_info, round, board_cards, all_in);
+    }
+
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        self.num_decisions += 1;
+        match self.explore(game_info, state) {
+            Some(action) => {
+                self.num_explored += 1;
+                action
+            },
+            None => self.agent.get_action(game_info, state, hole_cards, board_cards),
+        }
+    }
+
+    fn end_hand(&mut self, game_info: &GameInfo, state: &GameState, payouts: &[i32]) {
+        self.agent.end_hand(game_info, state, payouts);
+    }
+
+    fn end_session(&mut self, game_info: &GameInfo) {
+        self.agent.end_session(game_info);
+    }
+}
+
+/// Plays the average strategy of a CFR engine and adapts it to its opponents between hands.
+/// Opponent actions are counted at every node and once a node was seen often enough the
+/// opponent is locked to the observed frequencies there and the engine keeps training against
+/// them.
+pub struct OnlineLearningAgent {
+    engine: CFREngine,
+    model: OpponentModel,
+    seat: PlayerId,
+    node_id: NodeId,
+    /// MCCFR iterations run after a hand with new observations, zero disables re-solving
+    resolve_iterations: u32,
+    /// Observations needed at a node before the opponent is locked to their frequencies there
+    min_observations: u32,
+    /// Whether a node reached `min_observations` during the current hand
+    needs_resolve: bool,
+    output_path: Option<PathBuf>,
+    rng: StdRng,
+}
+
+impl OnlineLearningAgent {
+    pub fn new(engine: CFREngine, resolve_iterations: u32, min_observations: u32) -> OnlineLearningAgent {
+        let node_id = engine.abstract_game().nodes.get_root_node_id();
+        OnlineLearningAgent {
+            engine,
+            model: OpponentModel::new(),
+            seat: 0,
+            node_id,
+            resolve_iterations,
+            min_observations,
+            needs_resolve: false,
+            output_path: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to sample actions, re-solving is seeded by the engine's config
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Saves the adapted average strategy to `path` when the session ends
+    pub fn set_output_path(&mut self, path: &Path) {
+        self.output_path = Some(path.to_path_buf());
+    }
+
+    pub fn engine(&self) -> &CFREngine {
+        &self.engine
+    }
+
+    pub fn model(&self) -> &OpponentModel {
+        &self.model
+    }
+
+    /// Locks every well observed opponent node to its smoothed frequencies and trains against them
+    fn resolve(&mut self) {
+        for (player, node_id) in self.model.observed_nodes().collect::<Vec<(PlayerId, NodeId)>>() {
+            if self.model.num_observations(player, node_id) >= self.min_observations {
+                let abstract_game = self.engine.abstract_game();
+                let state = &abstract_game.nodes.get_node(node_id).unwrap().state;
+                let frequencies = self.model.smoothed_frequencies(player, node_id, state, &abstract_game.get_actions(state));
+                self.engine.lock_node(node_id, frequencies.into_iter().map(|(a, p)| (a, p as f32)).collect());
+            }
+        }
+
+        if self.engine.mccfr_p(self.resolve_iterations, 1, u32::MAX, 0, 1).is_err() {
+            warn!("re-solving was cancelled");
+        }
+    }
+}
+
+impl Agent for OnlineLearningAgent {
+    fn begin_hand(&mut self, _game_info: &GameInfo, seat: PlayerId, _hole_cards: &[Card]) {
+        self.seat = seat;
+        self.node_id = self.engine.abstract_game().nodes.get_root_node_id();
+    }
+
+    fn observe_action(&mut self, _game_info: &GameInfo, player: PlayerId, action: Action) {
+        if player != self.seat {
+            let state = &self.engine.abstract_game().nodes.get_node(self.node_id).unwrap().state;
+            self.model.observe(player, self.node_id, state, action);
+            if self.model.num_observations(player, self.node_id) >= self.min_observations {
+                self.needs_resolve = true;
+            }
+        }
+
+        let mut board_cards_i = 0;
+        self.node_id = self.engine.abstract_game_mut().apply_action_to_node(self.node_id, &mut board_cards_i, action);
+    }
+
+    fn get_action(&mut self, _game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        let abstract_game = self.engine.abstract_game();
+        let bucket_id = abstract_game.get_bucket(state.current_round(), board_cards, hole_cards);
+        let probabilities = self.engine.average_action_probabilities(self.node_id, bucket_id);
+        sample_probabilities(&probabilities.into_iter().collect::<Vec<(Action, f64)>>(), 1., &mut self.rng)
+    }
+
+    fn end_hand(&mut self, _game_info: &GameInfo, _state: &GameState, _payouts: &[i32]) {
+        if self.resolve_iterations > 0 && self.needs_resolve {
+            self.resolve();
+        }
+        self.needs_resolve = false;
+    }
+
+    fn end_session(&mut self, _game_info: &GameInfo) {
+        if let Some(path) = &self.output_path {
+            self.engine.save_average_strategy(path);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    match_stats::PlayerStats,
+};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Distribution the winnings of every hand are drawn from, in whatever unit the bankroll is in
+#[derive(Clone, Debug)]
+pub enum ResultDistribution {
+    /// Normally distributed winnings with the win rate and standard deviation of a hand
+    Normal { mean: f64, std_dev: f64 },
+    /// Winnings of hands that were played, resampled with replacement so the skew and fat
+    /// tails of real results are kept
+    Empirical(Vec<f64>),
+}
+
+impl ResultDistribution {
+    /// Returns the normal distribution in big blinds of a player's results in a match
+    pub fn from_player_stats(stats: &PlayerStats) -> ResultDistribution {
+        assert!(stats.hands > 1, "need at least two hands to estimate the variance");
+        ResultDistribution::Normal {
+            mean: stats.bb_per_100 / 100.,
+            std_dev: stats.std_error / 100. * (stats.hands as f64).sqrt(),
+        }
+    }
+
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            ResultDistribution::Normal { mean, std_dev } => {
+                // Box-Muller transform, 1 - u keeps the logarithm finite
+                let u = 1. - rng.gen::<f64>();
+                let v = rng.gen::<f64>();
+                mean + std_dev * (-2. * u.ln()).sqrt() * (2. * PI * v).cos()
+            },
+            ResultDistribution::Empirical(results) => *results.choose(rng).expect("no results to resample"),
+        }
+    }
+}
+
+/// Outcomes of the simulated bankroll trajectories
+#[derive(Clone, Debug)]
+pub struct BankrollReport {
+    pub num_trajectories: u32,
+    /// Fraction of the trajectories that went broke
+    pub risk_of_ruin: f64,
+    /// Bankroll at the end of every trajectory, sorted, zero for the ones that went broke
+    pub final_bankrolls: Vec<f64>,
+    /// Largest drop from a peak of every trajectory, sorted
+    pub max_drawdowns: Vec<f64>,
+}
+
+impl BankrollReport {
+    pub fn mean_final_bankroll(&self) -> f64 {
+        self.final_bankrolls.iter().sum::<f64>() / self.final_bankrolls.len() as f64
+    }
+
+    /// Returns the final bankroll `p` of the trajectories end below, `p` in [0, 1]
+    pub fn final_bankroll_percentile(&self, p: f64) -> f64 {
+        percentile(&self.final_bankrolls, p)
+    }
+
+    /// Returns the drawdown `p` of the trajectories stay below, `p` in [0, 1]
+    pub fn max_drawdown_percentile(&self, p: f64) -> f64 {
+        percentile(&self.max_drawdowns, p)
+    }
+}
+
+impl fmt::Display for BankrollReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "risk of ruin {:.2}% over {} trajectories", 100. * self.risk_of_ruin, self.num_trajectories)?;
+        writeln!(f, "final bankroll mean {:.1}, 5% {:.1}, median {:.1}, 95% {:.1}",
+            self.mean_final_bankroll(), self.final_bankroll_percentile(0.05), self.final_bankroll_percentile(0.5), self.final_bankroll_percentile(0.95))?;
+        write!(f, "max drawdown median {:.1}, 95% {:.1}", self.max_drawdown_percentile(0.5), self.max_drawdown_percentile(0.95))
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    assert!((0. ..=1.).contains(&p), "percentile must be in [0, 1]");
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+/// Simulates bankrolls playing hand after hand with winnings drawn from a distribution, to
+/// estimate the risk of ruin and the swings to expect from a win rate, e.g. the results of a
+/// match. A trajectory stops once the bankroll is gone.
+pub struct BankrollSimulator {
+    distribution: ResultDistribution,
+    starting_bankroll: f64,
+    num_hands: u32,
+    num_trajectories: u32,
+    cancel_token: CancelToken,
+    rng: StdRng,
+}
+
+impl BankrollSimulator {
+    pub fn new(distribution: ResultDistribution, starting_bankroll: f64, num_hands: u32, num_trajectories: u32) -> BankrollSimulator {
+        assert!(starting_bankroll > 0., "bankroll must be positive");
+        assert!(num_trajectories > 0, "need at least one trajectory");
+        BankrollSimulator {
+            distribution,
+            starting_bankroll,
+            num_hands,
+            num_trajectories,
+            cancel_token: CancelToken::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator drawing the winnings so the simulation is reproducible
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn simulate(&mut self) -> Result<BankrollReport, Cancelled> {
+        let mut final_bankrolls = Vec::with_capacity(self.num_trajectories as usize);
+        let mut max_drawdowns = Vec::with_capacity(self.num_trajectories as usize);
+        let mut ruined = 0;
+
+        for _ in 0..self.num_trajectories {
+            self.cancel_token.check()?;
+            let mut bankroll = self.starting_bankroll;
+            let mut peak = bankroll;
+            let mut max_drawdown: f64 = 0.;
+            for _ in 0..self.num_hands {
+                bankroll += self.distribution.sample(&mut self.rng);
+                peak = peak.max(bankroll);
+                max_drawdown = max_drawdown.max(peak - bankroll);
+                if bankroll <= 0. {
+                    break;
+                }
+            }
+            if bankroll <= 0. {
+                ruined += 1;
+                bankroll = 0.;
+            }
+            final_bankrolls.push(bankroll);
+            max_drawdowns.push(max_drawdown);
+        }
+
+        final_bankrolls.sort_by(f64::total_cmp);
+        max_drawdowns.sort_by(f64::total_cmp);
+        Ok(BankrollReport {
+            num_trajectories: self.num_trajectories,
+            risk_of_ruin: ruined as f64 / self.num_trajectories as f64,
+            final_bankrolls,
+            max_drawdowns,
+        })
+    }
+}
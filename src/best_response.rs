@@ -0,0 +1,159 @@
+use super::{
+    abstract_game::AbstractGame,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::{BucketId, CardAbstraction, NoBuckets, RoundBuckets},
+    deals::{Deal, Deals},
+    evaluator::HandEvaluator,
+    game::{Action, PlayerId},
+    node::NodeId,
+    strategy::Strategy,
+};
+
+use std::collections::BTreeMap;
+
+/// Computes the value `player` gets by best responding to `strategy` in the abstract game
+pub fn best_response_value(abstract_game: &mut AbstractGame, strategy: &Strategy, player: PlayerId, cancel_token: &CancelToken) -> Result<f64, Cancelled> {
+    best_response_value_with_buckets(abstract_game, strategy, player, None, cancel_token)
+}
+
+/// Computes the value `player` gets by best responding to `strategy`, grouping the responder's
+/// hands with `response_abstraction` instead of the abstract game's card abstraction if given
+fn best_response_value_with_buckets(abstract_game: &mut AbstractGame, strategy: &Strategy, player: PlayerId, response_abstraction: Option<&CardAbstraction>, cancel_token: &CancelToken) -> Result<f64, Cancelled> {
+    let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+    let (deals, reach): (Vec<Deal>, Vec<f64>) = Deals::new(&abstract_game.game_info).unzip();
+    let deal_ids = (0..deals.len()).collect::<Vec<usize>>();
+
+    let root = abstract_game.nodes.get_root_node_id();
+    let values = best_response_traverse(abstract_game, strategy, evaluator, response_abstraction, cancel_token, &deals, root, player, &deal_ids, &reach, None)?;
+
+    Ok(values.iter().zip(reach.iter()).map(|(v, r)| v * r).sum())
+}
+
+/// Computes the pure strategy `player` best responds to `strategy` with. Only the infosets of
+/// `player` are in the returned strategy, each with a count of one for the best action.
+pub fn best_response_strategy(abstract_game: &mut AbstractGame, strategy: &Strategy, player: PlayerId, cancel_token: &CancelToken) -> Result<Strategy, Cancelled> {
+    let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+    let (deals, reach): (Vec<Deal>, Vec<f64>) = Deals::new(&abstract_game.game_info).unzip();
+    let deal_ids = (0..deals.len()).collect::<Vec<usize>>();
+
+    let mut response = Strategy::new();
+    let root = abstract_game.nodes.get_root_node_id();
+    best_response_traverse(abstract_game, strategy, evaluator, None, cancel_token, &deals, root, player, &deal_ids, &reach, Some(&mut response))?;
+
+    Ok(response)
+}
+
+/// Computes the exploitability of `strategy`, which is the average amount of chips per hand
+/// that a best responding player wins against it
+pub fn exploitability(abstract_game: &mut AbstractGame, strategy: &Strategy, cancel_token: &CancelToken) -> Result<f64, Cancelled> {
+    let num_players = abstract_game.game_info.num_players();
+    let mut total = 0.;
+    for player in 0..num_players {
+        total += best_response_value(abstract_game, strategy, player, cancel_token)?;
+    }
+
+    Ok(total / num_players as f64)
+}
+
+/// Like `exploitability` but the best responder sees its exact cards instead of buckets, so
+/// strategies trained with different card abstractions of a game can be compared
+pub fn lossless_exploitability(abstract_game: &mut AbstractGame, strategy: &Strategy, cancel_token: &CancelToken) -> Result<f64, Cancelled> {
+    let game_info = &abstract_game.game_info;
+    let response_abstraction = CardAbstraction::new(
+        (0..game_info.num_rounds())
+            .map(|round| Box::new(NoBuckets::new(game_info, round)) as Box<dyn RoundBuckets>)
+            .collect()
+    );
+
+    let num_players = abstract_game.game_info.num_players();
+    let mut total = 0.;
+    for player in 0..num_players {
+        total += best_response_value_with_buckets(abstract_game, strategy, player, Some(&response_abstraction), cancel_token)?;
+    }
+
+    Ok(total / num_players as f64)
+}
+
+/// Returns the value for `player` of each deal in `deal_ids` when `player` best responds from
+/// `node_id`. `reach` is the chance and opponent reach probability of each deal. The best action
+/// of every infoset is recorded in `response` if given.
+#[allow(clippy::too_many_arguments)]
+fn best_response_traverse(abstract_game: &mut AbstractGame, strategy: &Strategy, evaluator: &dyn HandEvaluator, response_abstraction: Option<&CardAbstraction>, cancel_token: &CancelToken, deals: &[Deal], node_id: NodeId, player: PlayerId, deal_ids: &[usize], reach: &[f64], mut response: Option<&mut Strategy>) -> Result<Vec<f64>, Cancelled> {
+    cancel_token.check()?;
+    let state = abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+
+    if state.has_folded(player) {
+        return Ok(vec![-(state.player_spent(player) as f64); deal_ids.len()]);
+    }
+    if state.is_finished() {
+        let game_info = &abstract_game.game_info;
+        let side_pots = state.side_pots(game_info);
+        return Ok(deal_ids.iter()
+            .map(|&d| state.get_side_pot_payouts(game_info, &side_pots, evaluator, &[&deals[d].1], &deals[d].0)[player as usize] as f64)
+            .collect());
+    }
+
+    let actions = abstract_game.get_actions(&state);
+    let current_player = state.current_player().unwrap();
+    let mut values = vec![0.; deal_ids.len()];
+
+    if current_player == player {
+        // Deals sharing a bucket are indistinguishable to the player, so they must share an action
+        let mut infosets: BTreeMap<BucketId, Vec<usize>> = BTreeMap::new();
+        for (i, &d) in deal_ids.iter().enumerate() {
+            let bucket_id = match response_abstraction {
+                Some(c) => c.get_bucket(state.current_round(), &deals[d].1, &deals[d].0[player as usize]),
+                None => abstract_game.get_bucket(state.current_round(), &deals[d].1, &deals[d].0[player as usize]),
+            };
+            infosets.entry(bucket_id).or_default().push(i);
+        }
+
+        for (bucket_id, indices) in infosets.iter() {
+            let infoset_deal_ids = indices.iter().map(|&i| deal_ids[i]).collect::<Vec<usize>>();
+            let infoset_reach = indices.iter().map(|&i| reach[i]).collect::<Vec<f64>>();
+
+            let mut best: Option<(f64, Action, Vec<f64>)> = None;
+            for action in &actions {
+                let mut board_cards_i = 0;
+                let child_node_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+                let action_values = best_response_traverse(abstract_game, strategy, evaluator, response_abstraction, cancel_token, deals, child_node_id, player, &infoset_deal_ids, &infoset_reach, response.as_deref_mut())?;
+                let total = action_values.iter().zip(infoset_reach.iter()).map(|(v, r)| v * r).sum::<f64>();
+
+                if best.as_ref().is_none_or(|(best_total, _, _)| total > *best_total) {
+                    best = Some((total, *action, action_values));
+                }
+            }
+
+            let (_, best_action, best_values) = best.unwrap();
+            if let Some(response) = response.as_deref_mut() {
+                let counts = actions.iter().map(|a| (*a, (*a == best_action) as i32)).collect();
+                response.0.insert((node_id, *bucket_id), counts);
+            }
+            for (&i, v) in indices.iter().zip(best_values) {
+                values[i] = v;
+            }
+        }
+    } else {
+        let probabilities = deal_ids.iter()
+            .map(|&d| {
+                let bucket_id = abstract_game.get_bucket(state.current_round(), &deals[d].1, &deals[d].0[current_player as usize]);
+                strategy.action_probabilities(node_id, bucket_id, &actions)
+            })
+            .collect::<Vec<BTreeMap<_, f64>>>();
+
+        for action in &actions {
+            let action_reach = reach.iter().zip(probabilities.iter())
+                .map(|(r, p)| r * p[action])
+                .collect::<Vec<f64>>();
+
+            let mut board_cards_i = 0;
+            let child_node_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+            let action_values = best_response_traverse(abstract_game, strategy, evaluator, response_abstraction, cancel_token, deals, child_node_id, player, deal_ids, &action_reach, response.as_deref_mut())?;
+            for (i, v) in action_values.into_iter().enumerate() {
+                values[i] += probabilities[i][action] * v;
+            }
+        }
+    }
+
+    Ok(values)
+}
@@ -1,25 +1,335 @@
-use std::path::PathBuf;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
 
-use ungar::{*, cfr::{CFREngine, CFRConfig}, abstract_game::AbstractGame, play::play, strategy::Strategy};
+use ungar::{*, units::Units, agent::{Agent, CallingStation, HeuristicAgent, RandomAgent, StrategyAgent}, cancel::{CancelToken, Cancelled}, config::ConfigError, duplicate::DuplicateMatch, expected_value::EvTree, cfr::{CFREngine, CFRConfig, PluribusSchedule, StopReason}, cross_validation::{SeedSweep, SweepMetric, TrainingBudget}, distributed::{run_worker, Coordinator, FileLink, Link, MccfrSchedule, TcpLink}, abstract_game::AbstractGame, bucket_generation::{BucketGenerationConfig, BucketGenerator}, fictitious_play::FictitiousPlay, leak_report::LeakAnalyzer, live_feed::{JsonLines, LiveFeed}, local_best_response::LocalBestResponse, logging::info, mcts::MctsAgent, manifest::{JsonlMetrics, MetricsSink, RunManifest, RunMetrics}, play::play, range_chart::{charts_to_html, RangeChart}, strategy::{Strategy, StrategyMetadata, StrategyProfile}};
 
-use clap::{Parser, Subcommand};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum HandFormat {
+    /// Hands written by `history::write_jsonl`
+    Jsonl,
+    Pokerstars,
+    /// ACPC dealer log played in the game config
+    Acpc,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Variant {
+    /// Monte Carlo CFR with regret pruning and linear discounting
+    MccfrP,
+    /// CFR against best responses, only feasible for small two player games
+    CfrBr,
+    /// Pluribus style self-play for any number of players, MCCFR with pruning on a schedule
+    /// scaled to the iterations, with the rounds past `rounds_update_average_strategy` averaged
+    /// from snapshots of the current strategy
+    Pluribus,
+    FictitiousPlay,
+}
+
+/// Ready made opponents for `eval`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Bot {
+    /// Checks or calls every bet
+    CallingStation,
+    /// Picks uniformly between the actions of the opponent action abstraction
+    Random,
+    /// Raises strong hands and calls by pot odds, see `agent::HeuristicAgent`
+    Heuristic,
+}
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     Train {
         #[arg(long)]
         cfr_config: PathBuf,
+        #[arg(long, value_enum, default_value_t = Variant::MccfrP)]
+        variant: Variant,
+        #[arg(long, default_value_t = 150000)]
+        iterations: u32,
+        /// Threads for the parallel work like bucketing, defaults to one per core. Iterations
+        /// themselves run on a single thread.
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Directory the strategy and nodes are saved to every `checkpoint_interval` iterations
+        #[arg(long)]
+        checkpoint_dir: Option<PathBuf>,
+        #[arg(long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+        checkpoint_interval: u32,
+        /// Iterations between progress reports
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+        progress_interval: u32,
+        /// Report the exploitability with the progress, which walks the whole tree
+        #[arg(long)]
+        exploitability: bool,
         #[arg(long)]
         output_strategy_path: Option<PathBuf>,
         #[arg(long)]
         output_nodes_path: Option<PathBuf>,
+        /// Writes the regrets, visits and strategies of every infoset after training, as Parquet
+        /// if the path ends in `.parquet` and CSV otherwise. Fictitious play has no regrets to
+        /// write.
+        #[arg(long)]
+        output_tables_path: Option<PathBuf>,
+    },
+    /// Coordinates distributed MCCFR, syncing the regrets and strategies of workers connecting
+    /// over TCP or through a shared directory
+    Coordinate {
+        /// Address workers connect to, like `0.0.0.0:7878`
+        #[arg(long, required_unless_present = "shared_dir")]
+        listen: Option<String>,
+        /// Directory shared with the workers instead of listening
+        #[arg(long, conflicts_with = "listen")]
+        shared_dir: Option<PathBuf>,
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        workers: u32,
+        #[arg(long, default_value_t = 150000)]
+        iterations: u32,
+        /// Iterations every worker runs between syncs
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+        sync_interval: u32,
+        /// Seed the seeds of the shards of deals of the workers count up from
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        output_strategy_path: Option<PathBuf>,
+        #[arg(long)]
+        output_nodes_path: Option<PathBuf>,
+    },
+    /// Trains the shards of deals a coordinator sends until it is done
+    Worker {
+        #[arg(long)]
+        cfr_config: PathBuf,
+        /// Address of the coordinator
+        #[arg(long, required_unless_present = "shared_dir")]
+        connect: Option<String>,
+        /// Directory shared with the coordinator instead of connecting
+        #[arg(long, conflicts_with = "connect", requires = "worker_id")]
+        shared_dir: Option<PathBuf>,
+        /// Number of this worker among those sharing the directory, from 0
+        #[arg(long)]
+        worker_id: Option<usize>,
     },
+    /// Plays hands against the strategy on the terminal, see `play::play` for the input
     Play {
         #[arg(short, long)]
         strategy_path: PathBuf,
         #[arg(short, long)]
         nodes_path: PathBuf,
     },
+    /// Writes the game tree annotated with the strategy and expected values as a DOT graph
+    Dot {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
+    /// Writes the reach probability and expected values of every node and infoset of the game
+    /// tree as JSON
+    EvTree {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Units of the values, chips or bb
+        #[arg(long, default_value = "chips")]
+        units: Units,
+    },
+    /// Prints the exploitability of a strategy, or with an opponent plays them in a duplicate
+    /// match and prints the strategy's winnings
+    Eval {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(long, requires = "opponent_nodes_path")]
+        opponent_strategy_path: Option<PathBuf>,
+        #[arg(long)]
+        opponent_nodes_path: Option<PathBuf>,
+        /// Action abstraction of the opponent if it differs from the strategy's
+        #[arg(long)]
+        opponent_action_abstraction_config: Option<PathBuf>,
+        /// Card abstraction of the opponent if it differs from the strategy's
+        #[arg(long)]
+        opponent_card_abstraction_config: Option<PathBuf>,
+        /// Plays against an agent searching every decision with this many iterations of
+        /// information set MCTS over the opponent action abstraction instead of a strategy
+        #[arg(long, conflicts_with = "opponent_strategy_path", value_parser = clap::value_parser!(u32).range(1..))]
+        opponent_mcts_iterations: Option<u32>,
+        /// Plays against a baseline bot instead of a strategy, raising with the opponent action
+        /// abstraction
+        #[arg(long, value_enum, conflicts_with_all = ["opponent_strategy_path", "opponent_mcts_iterations"])]
+        opponent_bot: Option<Bot>,
+        /// Hands of the duplicate match, half of them with the seats swapped
+        #[arg(long, default_value_t = 100000, value_parser = clap::value_parser!(u32).range(4..))]
+        hands: u32,
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Let the best responder see its exact cards instead of buckets when computing
+        /// exploitability, so strategies with different card abstractions can be compared
+        #[arg(long)]
+        lossless: bool,
+        /// Also report the match winnings with all in pots awarded by equity instead of the
+        /// dealt runout
+        #[arg(long)]
+        all_in_ev: bool,
+        /// Estimates exploitability in games too big for a best response by playing this many
+        /// hands of local best response against the strategy, giving a lower bound
+        #[arg(long, conflicts_with_all = ["opponent_strategy_path", "opponent_mcts_iterations", "opponent_bot", "lossless"], value_parser = clap::value_parser!(u32).range(4..))]
+        lbr_hands: Option<u32>,
+        /// Actions local best response chooses between if they differ from the strategy's
+        #[arg(long, requires = "lbr_hands")]
+        lbr_action_abstraction_config: Option<PathBuf>,
+        /// Runouts local best response samples its equity from when there are more
+        #[arg(long, requires = "lbr_hands")]
+        lbr_equity_samples: Option<u32>,
+        /// Streams the events of every hand of the match to WebSocket clients connecting to this
+        /// address, needs the live feature
+        #[arg(long, conflicts_with = "lbr_hands")]
+        live_address: Option<String>,
+        /// Writes the events of every hand of the match to this file as JSON lines
+        #[arg(long, conflicts_with = "lbr_hands")]
+        live_events_path: Option<PathBuf>,
+    },
+    /// Replays hands against the strategy and prints the decisions that lost the most against
+    /// its best action
+    Leaks {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(long)]
+        hands_path: PathBuf,
+        #[arg(long, value_enum, default_value_t = HandFormat::Jsonl)]
+        format: HandFormat,
+        /// Only review the decisions of the player with this name
+        #[arg(long)]
+        player: Option<String>,
+        /// Playouts valuing each action
+        #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+        rollouts: u32,
+        /// Loss in `--units` above which a decision is reported, a tenth of the big blind by
+        /// default
+        #[arg(long)]
+        threshold: Option<f64>,
+        /// Units of the report, chips or bb
+        #[arg(long, default_value = "chips")]
+        units: Units,
+        /// Write the report as JSON instead of printing it
+        #[arg(short, long)]
+        output_path: Option<PathBuf>,
+    },
+    /// Generates bucket tables for every round into `output_dir` and writes the card abstraction
+    /// reading them to the card abstraction config path. Runs that are stopped resume from the
+    /// last saved chunk.
+    Abstract {
+        #[arg(long)]
+        config: PathBuf,
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        /// Threads computing buckets, defaults to one per core
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Trains every CFR config once per seed and prints the mean and spread of their scores,
+    /// along with how each config differs from the first over the same seeds
+    Sweep {
+        /// CFR configs to compare, named after their file stems. The first is the baseline.
+        #[arg(long, required = true)]
+        cfr_config: Vec<PathBuf>,
+        /// Seeds every config is trained with, counting up from 0
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        seeds: u64,
+        #[arg(long, default_value_t = 150000)]
+        iterations: u32,
+        /// Score of each run, exploitability or head-to-head against the first config
+        #[arg(long, default_value = "exploitability")]
+        metric: SweepMetric,
+        /// Hands of every head to head duplicate match, half of them with the seats swapped
+        #[arg(long, default_value_t = 100000, value_parser = clap::value_parser!(u32).range(4..))]
+        hands: u32,
+        /// Runs trained at once
+        #[arg(long, default_value_t = 1)]
+        threads: usize,
+    },
+    /// Prints the number of nodes, infosets and actions of the game tree and the memory of a
+    /// regret table, to size a game before training it
+    Infosets {
+        /// Build the whole tree and count it instead of estimating from random paths
+        #[arg(long)]
+        exact: bool,
+        /// Random paths the estimate is averaged over
+        #[arg(long, default_value_t = 100000, value_parser = clap::value_parser!(u32).range(1..))]
+        probes: u32,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Prints the equity, best and worst hands and classes of hands of two ranges on a board,
+    /// with a strategy also how the player to act plays each class
+    FlopReport {
+        /// Board cards like `AhKd7c`
+        #[arg(long)]
+        board: String,
+        /// Range of each player in standard notation like `"99+, AKs, 40% KQo"`, the first is
+        /// player 0's
+        #[arg(long, num_args = 2, required = true)]
+        range: Vec<range::Range>,
+        #[arg(short, long, requires = "nodes_path")]
+        strategy_path: Option<PathBuf>,
+        #[arg(short, long)]
+        nodes_path: Option<PathBuf>,
+        /// Betting the strategy is looked up at, see `format_betting`, e.g. `cc/`
+        #[arg(long, requires = "strategy_path")]
+        betting: Option<String>,
+        /// Directory the report is written to as CSV and JSON instead of printed
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+    },
+    /// Writes how often every hand takes each action at every decision node, see
+    /// `node_format`. CSV if the path ends in `.csv` and JSON otherwise.
+    ExportNodes {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        /// Boards the nodes of later rounds are written for, like `Kh7d2c, Ah*`, see
+        /// `card_set::parse_boards`. Without them only nodes before the board are written.
+        #[arg(long)]
+        boards: Option<String>,
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
+    /// Reads node records written by `export-nodes`, possibly edited, over the strategy and saves
+    /// the result
+    ImportNodes {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(short, long)]
+        input_path: PathBuf,
+        #[arg(long)]
+        output_strategy_path: PathBuf,
+    },
+    /// Writes the preflop opening range of every seat as CSV, JSON, SVG and an HTML page
+    RangeChart {
+        #[arg(short, long)]
+        strategy_path: PathBuf,
+        #[arg(short, long)]
+        nodes_path: PathBuf,
+        #[arg(short, long)]
+        output_dir: PathBuf,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -40,43 +350,471 @@ struct Args {
     command: Commands,
 }
 
-fn train(abstract_game: AbstractGame, cfr_config: CFRConfig, output_strategy_path: Option<PathBuf>, output_nodes_path: Option<PathBuf>) {
-    let mut cfr_engine = CFREngine::new(abstract_game, cfr_config);
+struct TrainOptions {
+    variant: Variant,
+    iterations: u32,
+    checkpoint_dir: Option<PathBuf>,
+    checkpoint_interval: u32,
+    progress_interval: u32,
+    exploitability: bool,
+}
 
-    cfr_engine.mccfr_p(150000, 20, 400, 100000, 2500);
-    match output_strategy_path {
-        Some(p) => cfr_engine.save_average_strategy(&p),
-        None => cfr_engine.print_average_strategy(),
-    };
-    match output_nodes_path {
-        Some(p) => cfr_engine.save_nodes(&p),
-        None => (),
+/// Engine of the chosen variant, trained in chunks so progress can be reported between them
+enum Solver {
+    Cfr(Box<CFREngine>, Variant),
+    Pluribus(Box<CFREngine>, PluribusSchedule),
+    FictitiousPlay(Box<FictitiousPlay>),
+}
+
+impl Solver {
+    fn run(&mut self, ticks: u32) -> Result<(), Cancelled> {
+        match self {
+            Solver::Cfr(engine, Variant::CfrBr) => engine.cfr_br(ticks, 20, 100),
+            Solver::Cfr(engine, _) => engine.mccfr_p(ticks, 20, 400, 100000, 2500),
+            Solver::Pluribus(engine, schedule) => engine.pluribus(ticks, schedule),
+            Solver::FictitiousPlay(fp) => fp.fictitious_play(ticks),
+        }
+    }
+
+    fn iteration(&self) -> u32 {
+        match self {
+            Solver::Cfr(engine, _) | Solver::Pluribus(engine, _) => engine.iteration(),
+            Solver::FictitiousPlay(fp) => fp.iteration(),
+        }
+    }
+
+    fn nodes_touched(&self) -> Option<u64> {
+        match self {
+            Solver::Cfr(engine, _) | Solver::Pluribus(engine, _) => Some(engine.nodes_touched()),
+            Solver::FictitiousPlay(_) => None,
+        }
+    }
+
+    fn exploitability(&mut self) -> Result<f64, Cancelled> {
+        match self {
+            Solver::Cfr(engine, _) | Solver::Pluribus(engine, _) => engine.exploitability(),
+            Solver::FictitiousPlay(fp) => fp.exploitability(),
+        }
+    }
+
+    fn save(&self, strategy_path: &Path, nodes_path: Option<&Path>) {
+        match self {
+            Solver::Cfr(engine, _) | Solver::Pluribus(engine, _) => engine.save_average_strategy(strategy_path),
+            Solver::FictitiousPlay(fp) => fp.save_average_strategy(strategy_path),
+        }
+        if let Some(p) = nodes_path {
+            match self {
+                Solver::Cfr(engine, _) | Solver::Pluribus(engine, _) => engine.save_nodes(p),
+                Solver::FictitiousPlay(fp) => fp.save_nodes(p),
+            }
+        }
+    }
+}
+
+fn train(abstract_game: AbstractGame, cfr_config: CFRConfig, options: TrainOptions, output_strategy_path: Option<PathBuf>, output_nodes_path: Option<PathBuf>, output_tables_path: Option<PathBuf>) {
+    let stop_conditions = cfr_config.stop_conditions().clone();
+    // Runs with checkpoints keep a manifest and metrics next to them
+    let mut tracking = options.checkpoint_dir.as_ref().map(|dir| {
+        fs::create_dir_all(dir).expect("failed to create checkpoint dir");
+        let mut manifest = RunManifest::new(&abstract_game, &cfr_config, &format!("{:?}", options.variant), options.iterations);
+        manifest.set_option("checkpoint_interval", options.checkpoint_interval);
+        manifest.set_option("progress_interval", options.progress_interval);
+        manifest.set_option("threads", rayon::current_num_threads());
+        manifest.save(dir);
+        (manifest, JsonlMetrics::create(dir))
+    });
+    let mut solver = match options.variant {
+        Variant::FictitiousPlay => Solver::FictitiousPlay(Box::new(FictitiousPlay::new(abstract_game))),
+        Variant::Pluribus => Solver::Pluribus(Box::new(CFREngine::new(abstract_game, cfr_config)), PluribusSchedule::scaled(options.iterations)),
+        variant => Solver::Cfr(Box::new(CFREngine::new(abstract_game, cfr_config)), variant),
     };
-    cfr_engine.print_regrets();
-    cfr_engine.print_average_strategy()
+
+    let start = Instant::now();
+    let mut stop_reason = StopReason::Iterations;
+    while solver.iteration() < options.iterations {
+        let iteration = solver.iteration();
+        let next_progress = (iteration / options.progress_interval + 1) * options.progress_interval;
+        let next_checkpoint = (iteration / options.checkpoint_interval + 1) * options.checkpoint_interval;
+        let target = next_progress.min(next_checkpoint).min(options.iterations);
+        solver.run(target - iteration).expect("training was cancelled");
+
+        let iteration = solver.iteration();
+        let at_progress = iteration % options.progress_interval == 0 || iteration == options.iterations;
+        let exploitability = if options.exploitability && at_progress || stop_conditions.target_exploitability.is_some() {
+            Some(solver.exploitability().expect("training was cancelled"))
+        } else {
+            None
+        };
+        let stop = stop_conditions.check(start.elapsed(), solver.nodes_touched(), exploitability);
+
+        if at_progress || stop.is_some() {
+            let elapsed = start.elapsed().as_secs_f64();
+            let mut progress = format!("iteration {}/{} ({:.1}%), {:.0} iterations/s",
+                iteration, options.iterations, 100.0 * iteration as f64 / options.iterations as f64, iteration as f64 / elapsed);
+            if let (true, Some(exploitability)) = (options.exploitability, exploitability) {
+                progress += &format!(", exploitability {:.6}", exploitability);
+            }
+            println!("{}", progress);
+            if let Some((_, metrics)) = &mut tracking {
+                metrics.record(&RunMetrics {
+                    iteration,
+                    elapsed_seconds: elapsed,
+                    nodes_touched: solver.nodes_touched(),
+                    exploitability,
+                });
+            }
+        }
+        if let Some(dir) = &options.checkpoint_dir {
+            if iteration % options.checkpoint_interval == 0 || iteration == options.iterations || stop.is_some() {
+                solver.save(&dir.join("strategy.bin"), Some(&dir.join("nodes.bin")));
+                info!("Saved checkpoint at iteration {} to {}", iteration, dir.display());
+            }
+        }
+        if let Some(reason) = stop {
+            stop_reason = reason;
+            break;
+        }
+    }
+    println!("stopped after {} iterations: {}", solver.iteration(), stop_reason);
+    if let (Some((manifest, metrics)), Some(dir)) = (&mut tracking, &options.checkpoint_dir) {
+        manifest.set_stopped(solver.iteration(), stop_reason);
+        manifest.save(dir);
+        metrics.finish(manifest);
+    }
+
+    match (&output_strategy_path, &solver) {
+        (Some(p), _) => solver.save(p, output_nodes_path.as_deref()),
+        (None, Solver::Cfr(engine, _) | Solver::Pluribus(engine, _)) => engine.print_average_strategy(),
+        (None, Solver::FictitiousPlay(fp)) => println!("{:?}", fp.average_strategy()),
+    }
+    match (&output_tables_path, &solver) {
+        (Some(p), Solver::Cfr(engine, _) | Solver::Pluribus(engine, _)) => save_infoset_tables(p, engine),
+        (Some(_), Solver::FictitiousPlay(_)) => eprintln!("fictitious play has no regrets, not writing infoset tables"),
+        (None, _) => {},
+    }
+}
+
+fn coordinate<L: Link>(mut coordinator: Coordinator, mut links: Vec<L>, iterations: u32, output_strategy_path: Option<PathBuf>, output_nodes_path: Option<PathBuf>) {
+    coordinator.handshake(&mut links).expect("failed to handshake with workers");
+    let start = Instant::now();
+    coordinator.train(&mut links, iterations, |coordinator| {
+        let iteration = coordinator.iteration();
+        println!("iteration {}/{} ({:.1}%), {:.0} iterations/s",
+            iteration, iterations, 100.0 * iteration as f64 / iterations as f64, iteration as f64 / start.elapsed().as_secs_f64());
+    }).expect("failed to sync with workers").expect("training was cancelled");
+
+    match &output_strategy_path {
+        Some(p) => coordinator.save_average_strategy(p),
+        None => println!("{:?}", coordinator.average_strategy()),
+    }
+    if let Some(p) = &output_nodes_path {
+        coordinator.save_nodes(p);
+    }
+}
+
+fn save_infoset_tables(path: &Path, engine: &CFREngine) {
+    if path.extension().is_some_and(|e| e == "parquet") {
+        #[cfg(feature = "parquet")]
+        infoset_table::write_parquet(path, engine.infoset_tables());
+        #[cfg(not(feature = "parquet"))]
+        panic!("writing Parquet needs the parquet feature");
+    } else {
+        infoset_table::write_csv(path, engine.infoset_tables());
+    }
+}
+
+fn generate_buckets(game_info: &game::GameInfo, config: BucketGenerationConfig, output_dir: &Path, card_abstraction_path: &Path) {
+    let generator = BucketGenerator::new(game_info, config, output_dir);
+    let start = Instant::now();
+    let card_abstraction = generator.generate(|progress| {
+        let elapsed = start.elapsed().as_secs_f64();
+        println!("round {}: {}/{} hands ({:.1}%), {:.1}s elapsed",
+            progress.round, progress.hands_done, progress.hands, 100.0 * progress.hands_done as f64 / progress.hands as f64, elapsed);
+    }).expect("failed to generate buckets");
+
+    let json = serde_json::to_string_pretty(&card_abstraction).expect("failed to serialize card abstraction");
+    fs::write(card_abstraction_path, json).expect("failed to write card abstraction config");
+    info!("Wrote card abstraction to {}", card_abstraction_path.display());
+}
+
+/// Returns the loaded config, or exits saying where the config is wrong
+fn load_config<T>(config: Result<T, ConfigError>) -> T {
+    config.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(2);
+    })
+}
+
+/// Loads a strategy, refusing one trained in another game or abstraction
+fn load_strategy(path: &Path, abstract_game: &AbstractGame) -> Strategy {
+    Strategy::from_file_checked(path, abstract_game).unwrap_or_else(|e| panic!("failed to load strategy: {}", e))
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "csv")
+}
+
+fn write_range_charts(charts: &[RangeChart], output_dir: &Path) {
+    fs::create_dir_all(output_dir).expect("failed to create output dir");
+    for chart in charts {
+        let name = format!("seat_{}", chart.seat);
+        fs::write(output_dir.join(format!("{}.csv", name)), chart.to_csv()).expect("failed to write range chart");
+        fs::write(output_dir.join(format!("{}.json", name)), chart.to_json()).expect("failed to write range chart");
+        fs::write(output_dir.join(format!("{}.svg", name)), chart.to_svg()).expect("failed to write range chart");
+    }
+    fs::write(output_dir.join("index.html"), charts_to_html(charts)).expect("failed to write range charts");
 }
 
 fn main() {
-    env_logger::init();
+    logging::init();
 
     let args = Args::parse();
+    if let Commands::Train { threads: Some(threads), .. } | Commands::Abstract { threads: Some(threads), .. } = args.command {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().expect("failed to build thread pool");
+    }
 
-    let game_info = game::GameInfo::load_game_info(&args.game_config);
+    let game_info = load_config(game::GameInfo::load_game_info(&args.game_config));
+    if let Commands::Abstract { config, output_dir, .. } = &args.command {
+        generate_buckets(&game_info, BucketGenerationConfig::from_config(config), output_dir, &args.card_abstraction_config);
+        return;
+    }
     let starting_state = game::GameState::new(&game_info, 0);
-    let action_abstraction = action_abstraction::ActionAbstraction::from_config(&args.action_abstraction_config);
-    let card_abstraction = card_abstraction::CardAbstraction::from_config(&args.card_abstraction_config);
+    let action_abstraction = load_config(action_abstraction::ActionAbstraction::from_config(&args.action_abstraction_config));
+    let card_abstraction = load_config(card_abstraction::CardAbstraction::from_config(&args.card_abstraction_config));
 
     match args.command {
-        Commands::Train { cfr_config, output_strategy_path, output_nodes_path }=> {
+        Commands::Train { cfr_config, variant, iterations, checkpoint_dir, checkpoint_interval, progress_interval, exploitability, output_strategy_path, output_nodes_path, output_tables_path, .. } => {
             let abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
             let cfr_config = CFRConfig::from_config(&cfr_config);
-            train(abstract_game, cfr_config, output_strategy_path, output_nodes_path);
+            let options = TrainOptions { variant, iterations, checkpoint_dir, checkpoint_interval, progress_interval, exploitability };
+            train(abstract_game, cfr_config, options, output_strategy_path, output_nodes_path, output_tables_path);
+        },
+        Commands::Coordinate { listen, shared_dir, workers, iterations, sync_interval, seed, output_strategy_path, output_nodes_path } => {
+            let abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
+            let coordinator = Coordinator::new(abstract_game, MccfrSchedule::default(), sync_interval, seed);
+            match (listen, shared_dir) {
+                (Some(address), _) => {
+                    let listener = TcpListener::bind(&address).expect("failed to listen");
+                    println!("waiting for {} workers on {}", workers, address);
+                    let links = TcpLink::accept(&listener, workers as usize).expect("failed to accept workers");
+                    coordinate(coordinator, links, iterations, output_strategy_path, output_nodes_path);
+                },
+                (None, Some(dir)) => {
+                    let links = (0..workers as usize).map(|i| FileLink::coordinator(&dir, i)).collect::<Result<Vec<_>, _>>().expect("failed to open shared dir");
+                    coordinate(coordinator, links, iterations, output_strategy_path, output_nodes_path);
+                },
+                (None, None) => unreachable!(),
+            }
+        },
+        Commands::Worker { cfr_config, connect, shared_dir, worker_id } => {
+            let abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
+            let cfr_config = CFRConfig::from_config(&cfr_config);
+            match (connect, shared_dir) {
+                (Some(address), _) => {
+                    let mut link = TcpLink::connect(&address).expect("failed to connect to coordinator");
+                    run_worker(abstract_game, cfr_config, &MccfrSchedule::default(), &mut link)
+                },
+                (None, Some(dir)) => {
+                    let mut link = FileLink::worker(&dir, worker_id.unwrap()).expect("failed to open shared dir");
+                    run_worker(abstract_game, cfr_config, &MccfrSchedule::default(), &mut link)
+                },
+                (None, None) => unreachable!(),
+            }.expect("worker failed");
         },
         Commands::Play { strategy_path, nodes_path } => {
+            let abstract_game = AbstractGame::load_nodes(game_info, &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            play(&StrategyProfile::new(abstract_game, strategy));
+        },
+        Commands::Dot { strategy_path, nodes_path, output_path } => {
             let mut abstract_game = AbstractGame::load_nodes(game_info, &nodes_path, action_abstraction, card_abstraction);
-            let strategy = Strategy::from_file(&strategy_path);
-            play(&mut abstract_game, strategy);
-        }
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            fs::write(&output_path, dot::tree_to_dot(&mut abstract_game, &strategy)).expect("failed to write dot graph");
+        },
+        Commands::EvTree { strategy_path, nodes_path, output_path, units } => {
+            let mut abstract_game = AbstractGame::load_nodes(game_info.clone(), &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            let tree = EvTree::new(&mut abstract_game, &strategy, &CancelToken::new()).expect("evaluation was cancelled").in_units(&game_info, units);
+            fs::write(&output_path, tree.to_json()).expect("failed to write ev tree");
+        },
+        Commands::Eval { strategy_path, nodes_path, opponent_strategy_path, opponent_nodes_path, opponent_action_abstraction_config, opponent_card_abstraction_config, opponent_mcts_iterations, opponent_bot, hands, seed, lossless, all_in_ev, lbr_hands, lbr_action_abstraction_config, lbr_equity_samples, live_address, live_events_path } => {
+            let mut abstract_game = AbstractGame::load_nodes(game_info.clone(), &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            if let Some(lbr_hands) = lbr_hands {
+                let lbr_actions = load_config(action_abstraction::ActionAbstraction::from_config(lbr_action_abstraction_config.as_ref().unwrap_or(&args.action_abstraction_config)));
+                let mut lbr = LocalBestResponse::new(StrategyProfile::new(abstract_game, strategy), lbr_actions);
+                if let Some(seed) = seed {
+                    lbr.set_seed(seed);
+                }
+                if let Some(lbr_equity_samples) = lbr_equity_samples {
+                    lbr.set_num_equity_samples(lbr_equity_samples);
+                }
+                println!("{}", lbr.run(lbr_hands / 2).expect("evaluation was cancelled"));
+                return;
+            }
+            if opponent_strategy_path.is_none() && opponent_mcts_iterations.is_none() && opponent_bot.is_none() {
+                let cancel_token = CancelToken::new();
+                let exploitability = if lossless {
+                    best_response::lossless_exploitability(&mut abstract_game, &strategy, &cancel_token)
+                } else {
+                    best_response::exploitability(&mut abstract_game, &strategy, &cancel_token)
+                }.expect("evaluation was cancelled");
+                println!("exploitability {:.6} chips/hand, {:.2} mbb/hand", exploitability, 1000. * game_info.to_big_blinds(exploitability));
+                return;
+            }
+
+            let mut agent = StrategyAgent::new(abstract_game, strategy);
+            if let Some(seed) = seed {
+                agent.set_seed(seed.wrapping_add(1));
+            }
+            let opponent_action_abstraction = load_config(action_abstraction::ActionAbstraction::from_config(&opponent_action_abstraction_config.unwrap_or(args.action_abstraction_config)));
+            let opponent = match (opponent_strategy_path, opponent_bot) {
+                (_, Some(Bot::CallingStation)) => Box::new(CallingStation) as Box<dyn Agent>,
+                (_, Some(Bot::Random)) => {
+                    let mut opponent = RandomAgent::new(opponent_action_abstraction);
+                    if let Some(seed) = seed {
+                        opponent.set_seed(seed.wrapping_add(2));
+                    }
+                    Box::new(opponent) as Box<dyn Agent>
+                },
+                (_, Some(Bot::Heuristic)) => {
+                    let mut opponent = HeuristicAgent::new(opponent_action_abstraction);
+                    if let Some(seed) = seed {
+                        opponent.set_seed(seed.wrapping_add(2));
+                    }
+                    Box::new(opponent) as Box<dyn Agent>
+                },
+                (Some(opponent_strategy_path), None) => {
+                    let opponent_card_abstraction = load_config(card_abstraction::CardAbstraction::from_config(&opponent_card_abstraction_config.unwrap_or(args.card_abstraction_config)));
+                    let opponent_game = AbstractGame::load_nodes(game_info.clone(), &opponent_nodes_path.unwrap(), opponent_action_abstraction, opponent_card_abstraction);
+                    let opponent_strategy = load_strategy(&opponent_strategy_path, &opponent_game);
+                    let mut opponent = StrategyAgent::new(opponent_game, opponent_strategy);
+                    if let Some(seed) = seed {
+                        opponent.set_seed(seed.wrapping_add(2));
+                    }
+                    Box::new(opponent) as Box<dyn Agent>
+                },
+                (None, None) => {
+                    let mut opponent = MctsAgent::new(opponent_action_abstraction, opponent_mcts_iterations.unwrap());
+                    if let Some(seed) = seed {
+                        opponent.set_seed(seed.wrapping_add(2));
+                    }
+                    Box::new(opponent) as Box<dyn Agent>
+                },
+            };
+
+            let mut agents = vec![Box::new(agent) as Box<dyn Agent>, opponent];
+            let mut duplicate_match = DuplicateMatch::new(game_info.clone(), hands / 2);
+            if let Some(seed) = seed {
+                duplicate_match.set_seed(seed);
+            }
+            duplicate_match.set_all_in_ev(all_in_ev);
+            if live_address.is_some() || live_events_path.is_some() {
+                let mut live_feed = LiveFeed::new(&game_info);
+                if let Some(address) = live_address {
+                    #[cfg(feature = "live")]
+                    {
+                        live_feed.add_sink(Box::new(live_feed::WebSocketServer::bind(&address).expect("failed to start live feed")));
+                        info!("Streaming hands on ws://{}", address);
+                    }
+                    #[cfg(not(feature = "live"))]
+                    panic!("streaming to {} needs the live feature", address);
+                }
+                if let Some(path) = live_events_path {
+                    live_feed.add_sink(Box::new(JsonLines(fs::File::create(path).expect("failed to create live events file"))));
+                }
+                duplicate_match.set_live_feed(Some(live_feed));
+            }
+            println!("{}", duplicate_match.play(&mut agents).expect("evaluation was cancelled"));
+        },
+        Commands::Leaks { strategy_path, nodes_path, hands_path, format, player, rollouts, threshold, units, output_path } => {
+            let hands = match format {
+                HandFormat::Jsonl => history::read_jsonl(&hands_path),
+                HandFormat::Pokerstars => history::read_pokerstars(&hands_path),
+                HandFormat::Acpc => history::read_acpc_log(&hands_path, &game_info),
+            }.expect("failed to read hands");
+            let abstract_game = AbstractGame::load_nodes(game_info, &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            let mut analyzer = LeakAnalyzer::new(StrategyProfile::new(abstract_game, strategy), rollouts);
+            analyzer.set_player(player);
+            analyzer.set_units(units);
+            if let Some(threshold) = threshold {
+                analyzer.set_threshold(threshold);
+            }
+            let report = analyzer.analyze(&hands).expect("analysis was cancelled");
+            match output_path {
+                Some(p) => fs::write(p, report.to_json()).expect("failed to write leak report"),
+                None => println!("{}", report),
+            }
+        },
+        Commands::ExportNodes { strategy_path, nodes_path, boards, output_path } => {
+            let abstract_game = AbstractGame::load_nodes(game_info.clone(), &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            let deck = game_info.generate_deck().collect::<Vec<_>>();
+            let boards = boards.map_or(Ok(Vec::new()), |b| card_set::parse_boards(&b, &deck)).unwrap_or_else(|e| panic!("failed to parse boards: {}", e));
+            let records = node_format::export(&StrategyProfile::new(abstract_game, strategy), &boards);
+            let contents = if is_csv(&output_path) { node_format::to_csv(&records) } else { node_format::to_json(&records) };
+            fs::write(&output_path, contents).expect("failed to write node strategies");
+        },
+        Commands::ImportNodes { strategy_path, nodes_path, input_path, output_strategy_path } => {
+            let abstract_game = AbstractGame::load_nodes(game_info, &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            let contents = fs::read_to_string(&input_path).expect("failed to read node strategies");
+            let records = if is_csv(&input_path) { node_format::from_csv(&contents) } else { node_format::from_json(&contents) }
+                .unwrap_or_else(|e| panic!("failed to parse node strategies: {}", e));
+            let mut profile = StrategyProfile::new(abstract_game, strategy);
+            let num_infosets = node_format::import(&mut profile, &records).unwrap_or_else(|e| panic!("failed to import node strategies: {}", e));
+            println!("imported {} infosets", num_infosets);
+            profile.strategy.save(&output_strategy_path, &StrategyMetadata::new(&profile.abstract_game, 0, "import"));
+        },
+        Commands::RangeChart { strategy_path, nodes_path, output_dir } => {
+            let abstract_game = AbstractGame::load_nodes(game_info.clone(), &nodes_path, action_abstraction, card_abstraction);
+            let strategy = load_strategy(&strategy_path, &abstract_game);
+            let profile = StrategyProfile::new(abstract_game, strategy);
+            write_range_charts(&RangeChart::opening_charts(&profile, &game_info), &output_dir);
+        },
+        Commands::Sweep { cfr_config, seeds, iterations, metric, hands, threads } => {
+            let mut sweep = SeedSweep::new(game_info, action_abstraction, card_abstraction, TrainingBudget::new(iterations), (0..seeds).collect());
+            for path in &cfr_config {
+                let name = path.file_stem().expect("failed to name cfr config").to_string_lossy();
+                sweep.add_cfr_config(&name, CFRConfig::from_config(path));
+            }
+            sweep.set_metric(metric);
+            sweep.set_num_pairs(hands / 2);
+            sweep.set_threads(threads);
+            print!("{}", sweep.run().expect("sweep was cancelled"));
+        },
+        Commands::Infosets { exact, probes, seed } => {
+            let count = if exact {
+                let mut abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
+                abstract_game.expand_tree();
+                infosets::count_infosets(&abstract_game)
+            } else {
+                let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+                infosets::estimate_infosets(&game_info, &starting_state, &action_abstraction, &card_abstraction, probes, &mut rng)
+            };
+            println!("{}", count);
+        },
+        Commands::FlopReport { board, range, strategy_path, nodes_path, betting, output_dir } => {
+            let board = card_set::parse_cards(&board).unwrap_or_else(|e| panic!("failed to parse board: {}", e));
+            let mut report = flop_report::FlopReport::new(&game_info, &board, [&range[0], &range[1]]);
+            if let (Some(strategy_path), Some(nodes_path)) = (strategy_path, nodes_path) {
+                let abstract_game = AbstractGame::load_nodes(game_info.clone(), &nodes_path, action_abstraction, card_abstraction);
+                let strategy = load_strategy(&strategy_path, &abstract_game);
+                let state = game::GameState::from_betting_string(&game_info, 0, betting.as_deref().expect("a strategy needs the betting to look up")).unwrap_or_else(|e| panic!("failed to replay betting: {}", e));
+                report.add_strategy(&StrategyProfile::new(abstract_game, strategy), &game_info, &state, &board);
+            }
+            match output_dir {
+                Some(dir) => {
+                    fs::create_dir_all(&dir).expect("failed to create output dir");
+                    fs::write(dir.join("combos.csv"), report.to_csv()).expect("failed to write flop report");
+                    fs::write(dir.join("classes.csv"), report.classes_to_csv()).expect("failed to write flop report");
+                    fs::write(dir.join("report.json"), report.to_json()).expect("failed to write flop report");
+                },
+                None => println!("{}", report),
+            }
+        },
+        Commands::Abstract { .. } => unreachable!(),
     }
 
 }
@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ungar::{*, card_set::parse_cards, game::{GameInfo, GameState}, logging::{debug, info, warn}, reload::StrategyWatcher, strategy::{SharedStrategy, StrategyProfile}};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Serves queries of a trained strategy as JSON over HTTP:
+///
+/// - `POST /action_probabilities` with `{"betting": "r200c/", "hole_cards": "AhKd", "board_cards": "2c3d4h"}`
+///   returns `{"actions": [["fold", 0.1], ["call", 0.5], ["raise 400", 0.4]]}`
+/// - `POST /evaluate` with `{"hole_cards": "AhKd", "board_cards": "2c3d4h5s9c"}` returns
+///   `{"rank": 1234}`, higher is better unless the game's ranking is lowest wins
+/// - `POST /reload` loads the strategy from its files again and returns `{"status": "reloaded"}`
+/// - `GET /health` returns `{"status": "ok"}`
+///
+/// With `--watch-interval` the strategy is also reloaded whenever its file changes. Requests are
+/// answered by `--threads` workers sharing one copy of the strategy, each request entirely by
+/// the old or the new strategy.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Config file for the game
+    #[arg(short, long)]
+    game_config: PathBuf,
+
+    #[arg(short, long)]
+    action_abstraction_config: PathBuf,
+
+    #[arg(short, long)]
+    card_abstraction_config: PathBuf,
+
+    #[arg(short, long)]
+    strategy_path: PathBuf,
+
+    #[arg(short, long)]
+    nodes_path: PathBuf,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+
+    /// Seconds between checks of the strategy file for changes, not watched if unset
+    #[arg(long)]
+    watch_interval: Option<u64>,
+
+    /// Workers answering requests at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: u32,
+}
+
+#[derive(Deserialize)]
+struct ActionRequest {
+    /// Betting string of the hand so far, like `r200c/cr400`
+    betting: String,
+    hole_cards: String,
+    #[serde(default)]
+    board_cards: String,
+}
+
+#[derive(Serialize)]
+struct ActionResponse {
+    actions: Vec<(String, f64)>,
+}
+
+#[derive(Deserialize)]
+struct EvaluateRequest {
+    hole_cards: String,
+    #[serde(default)]
+    board_cards: String,
+}
+
+#[derive(Serialize)]
+struct EvaluateResponse {
+    rank: u32,
+}
+
+/// Status code and message of a failed request
+type RequestError = (u16, String);
+
+fn action_probabilities(profile: &SharedStrategy, game_info: &GameInfo, body: &str) -> Result<ActionResponse, RequestError> {
+    let request: ActionRequest = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+    let state = GameState::from_betting_string(game_info, 0, &request.betting).map_err(|e| (400, e.to_string()))?;
+    if state.is_finished() {
+        return Err((400, "the hand is over".to_string()));
+    }
+    let hole_cards = parse_cards(&request.hole_cards).map_err(|e| (400, e.to_string()))?;
+    let board_cards = parse_cards(&request.board_cards).map_err(|e| (400, e.to_string()))?;
+
+    Ok(ActionResponse {
+        actions: profile.action_probabilities(game_info, &state, &hole_cards, &board_cards)
+            .into_iter()
+            .map(|(a, p)| (a.to_string(), p))
+            .collect(),
+    })
+}
+
+fn evaluate(game_info: &GameInfo, body: &str) -> Result<EvaluateResponse, RequestError> {
+    let request: EvaluateRequest = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+    let hole_cards = parse_cards(&request.hole_cards).map_err(|e| (400, e.to_string()))?;
+    let board_cards = parse_cards(&request.board_cards).map_err(|e| (400, e.to_string()))?;
+
+    let ranking = game_info.hand_ranking();
+    let rank = ranking.direction().orient(ranking.evaluator().evaluate_showdown(&hole_cards, &board_cards));
+    Ok(EvaluateResponse { rank: rank.0 })
+}
+
+fn respond<T: Serialize>(request: Request, result: Result<T, RequestError>) {
+    let (status, body) = match result {
+        Ok(response) => (200, serde_json::to_string(&response).unwrap()),
+        Err((status, error)) => (status, serde_json::json!({ "error": error }).to_string()),
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    if let Err(e) = request.respond(Response::from_string(body).with_status_code(status).with_header(header)) {
+        warn!("failed to send response: {}", e);
+    }
+}
+
+/// Strategy being served, workers keep their own handle to it and swap it for a new one once a
+/// reload moves the generation on
+struct Served {
+    profile: Mutex<Arc<StrategyProfile>>,
+    generation: AtomicUsize,
+}
+
+impl Served {
+    fn replace(&self, profile: StrategyProfile) {
+        *self.profile.lock().unwrap() = Arc::new(profile);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns a new handle if the strategy was replaced since `generation`
+    fn refresh(&self, generation: &mut usize) -> Option<SharedStrategy> {
+        let current = self.generation.load(Ordering::SeqCst);
+        (current != *generation).then(|| {
+            *generation = current;
+            SharedStrategy::from_arc(self.profile.lock().unwrap().clone())
+        })
+    }
+}
+
+fn serve(server: &Server, served: &Served, watcher: &Mutex<StrategyWatcher>, args: &Args) {
+    let mut generation = 0;
+    let mut profile = served.refresh(&mut generation).unwrap();
+    loop {
+        let mut request = match server.recv() {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("failed to receive request: {}", e);
+                continue;
+            },
+        };
+        if let Some(refreshed) = served.refresh(&mut generation) {
+            profile = refreshed;
+        }
+
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            respond::<()>(request, Err((400, e.to_string())));
+            continue;
+        }
+
+        enter_span!("request", method = %request.method(), url = request.url());
+        debug!("{} {}", request.method(), request.url());
+        let game_info = &profile.profile().abstract_game.game_info;
+        match (request.method(), request.url()) {
+            (Method::Post, "/action_probabilities") => {
+                let result = action_probabilities(&profile, game_info, &body);
+                respond(request, result);
+            },
+            (Method::Post, "/evaluate") => {
+                let result = evaluate(game_info, &body);
+                respond(request, result);
+            },
+            (Method::Post, "/reload") => match watcher.lock().unwrap().load() {
+                Ok(reloaded) => {
+                    info!("Reloaded {}", args.strategy_path.display());
+                    served.replace(reloaded);
+                    respond(request, Ok(serde_json::json!({ "status": "reloaded" })));
+                },
+                Err(e) => respond::<()>(request, Err((409, e))),
+            },
+            (Method::Get, "/health") => respond(request, Ok(serde_json::json!({ "status": "ok" }))),
+            _ => respond::<()>(request, Err((404, "not found".to_string()))),
+        }
+    }
+}
+
+fn main() {
+    logging::init();
+
+    let args = Args::parse();
+
+    let mut watcher = StrategyWatcher::new(&args.game_config, &args.action_abstraction_config, &args.card_abstraction_config, &args.strategy_path, &args.nodes_path);
+    let profile = watcher.load().unwrap_or_else(|e| panic!("failed to load strategy: {}", e));
+    // Workers start at generation 0, so the first refresh hands out the loaded strategy
+    let served = Served { profile: Mutex::new(Arc::new(profile)), generation: AtomicUsize::new(1) };
+    let watcher = Mutex::new(watcher);
+
+    let server = Server::http(&args.address).expect("failed to start server");
+    info!("Listening on {} with {} workers", args.address, args.threads);
+
+    thread::scope(|scope| {
+        if let Some(interval) = args.watch_interval.map(Duration::from_secs) {
+            let (served, watcher, args) = (&served, &watcher, &args);
+            scope.spawn(move || loop {
+                thread::sleep(interval);
+                let polled = watcher.lock().unwrap().poll();
+                match polled {
+                    Some(Ok(reloaded)) => {
+                        info!("Reloaded {}", args.strategy_path.display());
+                        served.replace(reloaded);
+                    },
+                    Some(Err(e)) => warn!("failed to reload strategy: {}", e),
+                    None => {},
+                }
+            });
+        }
+        for _ in 0..args.threads {
+            scope.spawn(|| serve(&server, &served, &watcher, &args));
+        }
+    });
+}
@@ -0,0 +1,145 @@
+use super::game::{deck_ranks, GameInfo};
+
+use poker::{Card, Rank, Suit};
+use variter::VarIter;
+
+/// Class of the highest board card
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HighCardClass {
+    /// Five or lower
+    Low,
+    /// Six to nine
+    Middle,
+    /// Ten to king
+    Broadway,
+    Ace,
+}
+
+impl HighCardClass {
+    pub fn from_rank(rank: Rank) -> HighCardClass {
+        match rank {
+            Rank::Ace => HighCardClass::Ace,
+            r if r >= Rank::Ten => HighCardClass::Broadway,
+            r if r >= Rank::Six => HighCardClass::Middle,
+            _ => HighCardClass::Low,
+        }
+    }
+}
+
+/// Texture of a board, the usual descriptors of how it pairs, how many suits it has and how
+/// connected it is. Straights and flushes are judged like hold'em: a player completes them with
+/// at most two hole cards, the ace plays low below the lowest rank of the deck and decks with
+/// fewer than five ranks have no straights.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoardFeatures {
+    pub num_cards: u8,
+    /// Cards of the most common rank, 2 on a paired board and 3 with trips
+    pub max_rank_count: u8,
+    /// Ranks that appear at least twice
+    pub num_paired_ranks: u8,
+    /// Cards of the most common suit
+    pub max_suit_count: u8,
+    pub num_suits: u8,
+    /// Longest run of consecutive ranks
+    pub longest_run: u8,
+    /// Fewest cards that complete a flush with the board
+    pub flush_cards_needed: u8,
+    /// Fewest cards that complete a straight with the board, `None` without straights
+    pub straight_cards_needed: Option<u8>,
+    pub high_card: Option<Rank>,
+}
+
+/// Length of `BoardFeatures::to_vec`
+pub const NUM_BOARD_FEATURES: usize = 14;
+
+impl BoardFeatures {
+    pub fn new(game_info: &GameInfo, board_cards: &[Card]) -> BoardFeatures {
+        let mut rank_counts = [0u8; Rank::ALL_VARIANTS.len()];
+        let mut suit_counts = [0u8; Suit::ALL_VARIANTS.len()];
+        for card in board_cards {
+            rank_counts[card.rank() as usize] += 1;
+            suit_counts[card.suit() as usize] += 1;
+        }
+        let max_suit_count = suit_counts.iter().copied().max().unwrap_or(0);
+
+        // Ranks from low to high with the ace first again as the lowest straight card
+        let ranks = deck_ranks(game_info.num_ranks());
+        let straight_ranks = ranks.last().into_iter().chain(ranks.iter())
+            .map(|r| rank_counts[*r as usize] > 0)
+            .collect::<Vec<bool>>();
+        let longest_run = straight_ranks.split(|present| !present).map(|run| run.len()).max().unwrap_or(0);
+        let straight_cards_needed = (ranks.len() >= 5).then(|| {
+            straight_ranks.windows(5).map(|w| w.iter().filter(|present| !**present).count()).min().unwrap() as u8
+        });
+
+        BoardFeatures {
+            num_cards: board_cards.len() as u8,
+            max_rank_count: rank_counts.iter().copied().max().unwrap_or(0),
+            num_paired_ranks: rank_counts.iter().filter(|c| **c >= 2).count() as u8,
+            max_suit_count,
+            num_suits: suit_counts.iter().filter(|c| **c > 0).count() as u8,
+            longest_run: longest_run as u8,
+            flush_cards_needed: 5u8.saturating_sub(max_suit_count),
+            straight_cards_needed,
+            high_card: board_cards.iter().map(|c| c.rank()).max(),
+        }
+    }
+
+    pub fn is_paired(&self) -> bool {
+        self.max_rank_count >= 2
+    }
+
+    /// Whether every card has the same suit, boards of a single card included
+    pub fn is_monotone(&self) -> bool {
+        self.num_cards > 0 && self.num_suits == 1
+    }
+
+    pub fn is_two_tone(&self) -> bool {
+        self.num_suits == 2
+    }
+
+    /// Whether no two cards share a suit
+    pub fn is_rainbow(&self) -> bool {
+        self.max_suit_count <= 1
+    }
+
+    /// Whether a player can hold a flush now
+    pub fn flush_possible(&self) -> bool {
+        self.flush_cards_needed <= 2
+    }
+
+    /// Whether a player can hold a straight now
+    pub fn straight_possible(&self) -> bool {
+        self.straight_cards_needed.is_some_and(|n| n <= 2)
+    }
+
+    pub fn high_card_class(&self) -> Option<HighCardClass> {
+        self.high_card.map(HighCardClass::from_rank)
+    }
+
+    /// Returns the features as `NUM_BOARD_FEATURES` numbers for conditioning models: the flags,
+    /// then the counts divided by the number of cards and a one-hot of the high card class,
+    /// all zero on an empty board
+    pub fn to_vec(&self) -> Vec<f32> {
+        let n = self.num_cards.max(1) as f32;
+        let flag = |b: bool| if b { 1. } else { 0. };
+        let mut features = vec![
+            flag(self.is_paired()),
+            flag(self.is_monotone()),
+            flag(self.is_two_tone()),
+            flag(self.is_rainbow() && self.num_cards > 1),
+            flag(self.flush_possible()),
+            flag(self.straight_possible()),
+            self.max_rank_count as f32 / n,
+            self.num_paired_ranks as f32 / n,
+            self.max_suit_count as f32 / n,
+            self.longest_run as f32 / n,
+        ];
+        let mut high_card = [0.; 4];
+        if let Some(class) = self.high_card_class() {
+            high_card[class as usize] = 1.;
+        }
+        features.extend(high_card);
+        features
+    }
+}
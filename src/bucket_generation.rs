@@ -0,0 +1,244 @@
+use super::{
+    bucket_table::{expected_hand_strength, BucketTable, BucketTableBuilder},
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::{BucketId, CardAbstraction, PrecomputedBuckets, Recall, RoundBuckets},
+    card_set::CardSet,
+    clustering::kmeans,
+    game::GameInfo,
+    hand_indexer::HandIndex,
+};
+
+use poker::Card;
+use itertools::Itertools;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+fn default_chunk_size() -> HandIndex {
+    1 << 20
+}
+
+/// How the hands of a round are bucketed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum RoundBucketing {
+    /// Every canonical hand gets its own bucket
+    Lossless,
+    /// Percentile buckets of the hand strength at the final round averaged over every runout
+    ExpectedHandStrength { num_buckets: u32 },
+    /// Hands are clustered with k-means by their distribution over the buckets of the next
+    /// round. Cumulative distributions are compared, which approximates earth mover's distance
+    /// when the next round's buckets are ordered by strength like `ExpectedHandStrength`.
+    PotentialAware { num_buckets: u32 },
+}
+
+/// Bucketing of every round for `BucketGenerator`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BucketGenerationConfig {
+    /// Bucketing of each round, indexed by round
+    rounds: Vec<RoundBucketing>,
+    /// Canonical hands computed between saving progress to disk
+    #[serde(default = "default_chunk_size")]
+    chunk_size: HandIndex,
+    /// Seed of the k-means clustering, round `r` uses `seed + r`
+    #[serde(default)]
+    seed: u64,
+    /// Recall of the generated card abstraction
+    #[serde(default)]
+    recall: Recall,
+}
+
+impl BucketGenerationConfig {
+    pub fn new(rounds: Vec<RoundBucketing>) -> BucketGenerationConfig {
+        BucketGenerationConfig {
+            rounds,
+            chunk_size: default_chunk_size(),
+            seed: 0,
+            recall: Recall::Imperfect,
+        }
+    }
+
+    pub fn from_config(path: &Path) -> BucketGenerationConfig {
+        serde_json::from_str(&fs::read_to_string(path).expect("failed to read bucket generation config")).expect("failed to deserialize bucket generation config")
+    }
+
+    pub fn set_chunk_size(&mut self, chunk_size: HandIndex) {
+        assert!(chunk_size > 0);
+        self.chunk_size = chunk_size;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn set_recall(&mut self, recall: Recall) {
+        self.recall = recall;
+    }
+}
+
+/// Progress of a round passed to the callback of `BucketGenerator::generate` after every chunk
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationProgress {
+    pub round: u8,
+    pub hands_done: HandIndex,
+    pub hands: HandIndex,
+}
+
+/// Generates a bucket table for every round of a game into a directory.
+///
+/// Rounds are generated from the last to the first since potential-aware buckets need the table
+/// of the next round. Per hand values are written to the directory a chunk at a time and rounds
+/// whose table already exists are skipped, so an interrupted run resumes where it stopped.
+pub struct BucketGenerator {
+    game_info: GameInfo,
+    config: BucketGenerationConfig,
+    output_dir: PathBuf,
+    cancel_token: CancelToken,
+}
+
+impl BucketGenerator {
+    pub fn new(game_info: &GameInfo, config: BucketGenerationConfig, output_dir: &Path) -> BucketGenerator {
+        assert_eq!(config.rounds.len(), game_info.num_rounds() as usize, "bucket generation config needs one bucketing per round");
+        if let Some(RoundBucketing::PotentialAware { .. }) = config.rounds.last() {
+            panic!("the last round has no next round for potential-aware buckets");
+        }
+
+        BucketGenerator {
+            game_info: game_info.clone(),
+            config,
+            output_dir: output_dir.to_path_buf(),
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    /// Sets the token checked for every hand while generating
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn table_path(&self, round: u8) -> PathBuf {
+        self.output_dir.join(format!("round_{}.bin", round))
+    }
+
+    fn chunk_path(&self, round: u8, chunk: HandIndex) -> PathBuf {
+        self.output_dir.join(format!("round_{}.chunk_{}.bin", round, chunk))
+    }
+
+    /// Generates the missing tables, calling `progress` after every chunk, and returns the card
+    /// abstraction reading them. A cancelled run returns an error of kind `Interrupted`.
+    pub fn generate<F: FnMut(GenerationProgress)>(&self, mut progress: F) -> io::Result<CardAbstraction> {
+        fs::create_dir_all(&self.output_dir)?;
+        for round in (0..self.game_info.num_rounds()).rev() {
+            enter_span!("bucket_round", round);
+            if self.table_path(round).exists() {
+                info!("Round {} table already exists, skipping", round);
+                continue;
+            }
+
+            let mut builder = BucketTableBuilder::new(&self.game_info, round);
+            builder.set_cancel_token(self.cancel_token.clone());
+            let table = match self.config.rounds[round as usize] {
+                RoundBucketing::Lossless => builder.build_lossless(),
+                RoundBucketing::ExpectedHandStrength { num_buckets } => {
+                    let strengths = self.expected_hand_strengths(&builder, round, &mut progress)?;
+                    builder.build_percentile_from(num_buckets, &strengths)
+                },
+                RoundBucketing::PotentialAware { num_buckets } => {
+                    let next_table = BucketTable::load(&self.table_path(round + 1))?;
+                    let histograms = self.next_round_histograms(&builder, round, &next_table, &mut progress)?;
+                    let mut rng = StdRng::seed_from_u64(self.config.seed.wrapping_add(round as u64));
+                    let (_, clusters) = kmeans(&histograms, num_buckets as usize, &mut rng, &self.cancel_token).map_err(interrupted)?;
+                    builder.build_from_buckets(num_buckets, &clusters.into_iter().map(|c| c as BucketId).collect::<Vec<BucketId>>())
+                },
+            };
+            table.save(&self.table_path(round))?;
+            self.remove_chunks(round, builder.len())?;
+            info!("Saved round {} table with {} buckets", round, table.num_buckets());
+        }
+
+        let round_infosets = (0..self.game_info.num_rounds())
+            .map(|round| Ok(Box::new(PrecomputedBuckets::load(&self.table_path(round))?) as Box<dyn RoundBuckets>))
+            .collect::<io::Result<Vec<Box<dyn RoundBuckets>>>>()?;
+        let mut card_abstraction = CardAbstraction::new(round_infosets);
+        card_abstraction.set_recall(self.config.recall);
+        Ok(card_abstraction)
+    }
+
+    fn expected_hand_strengths<F: FnMut(GenerationProgress)>(&self, builder: &BucketTableBuilder, round: u8, progress: &mut F) -> io::Result<Vec<f32>> {
+        let deck = self.game_info.generate_deck().collect::<Vec<Card>>();
+        let hand_ranking = self.game_info.hand_ranking();
+        let final_board_cards = self.game_info.total_board_cards(self.game_info.num_rounds() - 1) as usize;
+        self.map_chunked(builder, round, progress, |hole_cards, board_cards| {
+            expected_hand_strength(hand_ranking, &deck, hole_cards, board_cards, final_board_cards) as f32
+        })
+    }
+
+    /// Returns the cumulative distribution over the next round's buckets of every canonical hand
+    fn next_round_histograms<F: FnMut(GenerationProgress)>(&self, builder: &BucketTableBuilder, round: u8, next_table: &BucketTable, progress: &mut F) -> io::Result<Vec<Vec<f32>>> {
+        let deck = self.game_info.generate_deck().collect::<Vec<Card>>();
+        let num_next_cards = self.game_info.num_board_cards(round + 1) as usize;
+        self.map_chunked(builder, round, progress, |hole_cards, board_cards| {
+            let dead = CardSet::from_cards(hole_cards) | CardSet::from_cards(board_cards);
+            let mut histogram = vec![0.; next_table.num_buckets() as usize];
+            let mut count = 0;
+            for next_cards in dead.remaining(&deck).into_iter().combinations(num_next_cards) {
+                histogram[next_table.get_bucket(&[board_cards, &next_cards[..]].concat(), hole_cards) as usize] += 1.;
+                count += 1;
+            }
+
+            let mut cumulative = 0.;
+            for h in histogram.iter_mut() {
+                cumulative += *h / count as f32;
+                *h = cumulative;
+            }
+            histogram
+        })
+    }
+
+    /// Computes `f` on every canonical hand of the round, reading chunks saved by an earlier run
+    /// and saving the ones computed now
+    fn map_chunked<T, F, P>(&self, builder: &BucketTableBuilder, round: u8, progress: &mut P, f: F) -> io::Result<Vec<T>>
+    where T: Serialize + DeserializeOwned + Send, F: Fn(&[Card], &[Card]) -> T + Sync, P: FnMut(GenerationProgress) {
+        let hands = builder.len();
+        let mut values = Vec::with_capacity(hands as usize);
+        for (chunk, start) in (0..hands).step_by(self.config.chunk_size as usize).enumerate() {
+            let end = (start + self.config.chunk_size).min(hands);
+            let path = self.chunk_path(round, chunk as HandIndex);
+            let chunk_values = if path.exists() {
+                let mut r = BufReader::new(File::open(&path)?);
+                bincode::deserialize_from(&mut r).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            } else {
+                let chunk_values = builder.map(start..end, &f).map_err(interrupted)?;
+                // Written to a temporary file first so a chunk is never left half written
+                let tmp_path = path.with_extension("tmp");
+                let mut w = BufWriter::new(File::create(&tmp_path)?);
+                bincode::serialize_into(&mut w, &chunk_values).map_err(io::Error::other)?;
+                w.flush()?;
+                fs::rename(&tmp_path, &path)?;
+                chunk_values
+            };
+            values.extend(chunk_values);
+            progress(GenerationProgress { round, hands_done: end, hands });
+        }
+
+        Ok(values)
+    }
+
+    fn remove_chunks(&self, round: u8, hands: HandIndex) -> io::Result<()> {
+        for chunk in 0..hands.div_ceil(self.config.chunk_size) {
+            let path = self.chunk_path(round, chunk);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn interrupted(cancelled: Cancelled) -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, cancelled)
+}
@@ -0,0 +1,314 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::BucketId,
+    evaluator::HandRanking,
+    game::GameInfo,
+    hand_indexer::{HandIndex, HandIndexer},
+    parallel::*,
+};
+
+use poker::Card;
+use itertools::Itertools;
+use memmap2::Mmap;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"UNGB";
+const VERSION: u32 = 1;
+
+/// Bucket ids stored in a table, either built in memory or mapped from a file
+enum TableData {
+    Owned(Vec<u8>),
+    Mapped { mmap: Mmap, offset: usize },
+}
+
+impl TableData {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            TableData::Owned(data) => data,
+            TableData::Mapped { mmap, offset } => &mmap[*offset..],
+        }
+    }
+}
+
+/// Maps the canonical index of every hand of a round to a bucket.
+///
+/// File layout (little endian): magic "UNGB", version u32, num_suits u8, num_ranks u8,
+/// num_hole_cards u8, num_rounds u8, cards_per_round [u8; num_rounds], round u8, bucket width in
+/// bytes u8, num_buckets u32, num_entries u64 followed by one bucket id per canonical index.
+pub struct BucketTable {
+    indexer: HandIndexer,
+    num_hole_cards: u8,
+    round: u8,
+    num_buckets: u32,
+    bucket_width: u8,
+    data: TableData,
+}
+
+fn bucket_width(num_buckets: u32) -> u8 {
+    if num_buckets <= u8::MAX as u32 + 1 {
+        1
+    } else if num_buckets <= u16::MAX as u32 + 1 {
+        2
+    } else {
+        4
+    }
+}
+
+impl BucketTable {
+    fn from_buckets(indexer: HandIndexer, num_hole_cards: u8, round: u8, num_buckets: u32, buckets: &[BucketId]) -> BucketTable {
+        let bucket_width = bucket_width(num_buckets);
+        let mut data = Vec::with_capacity(buckets.len() * bucket_width as usize);
+        for bucket in buckets {
+            assert!(*bucket < num_buckets, "bucket {} out of range", bucket);
+            data.extend_from_slice(&bucket.to_le_bytes()[..bucket_width as usize]);
+        }
+
+        BucketTable {
+            indexer,
+            num_hole_cards,
+            round,
+            num_buckets,
+            bucket_width,
+            data: TableData::Owned(data),
+        }
+    }
+
+    pub fn round(&self) -> u8 {
+        self.round
+    }
+
+    pub fn num_buckets(&self) -> u32 {
+        self.num_buckets
+    }
+
+    pub fn indexer(&self) -> &HandIndexer {
+        &self.indexer
+    }
+
+    /// Returns number of canonical hands in the table
+    pub fn len(&self) -> HandIndex {
+        self.indexer.round_size(self.round as usize)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: HandIndex) -> BucketId {
+        let width = self.bucket_width as usize;
+        let start = index as usize * width;
+        let mut bytes = [0; 4];
+        bytes[..width].copy_from_slice(&self.data.bytes()[start..start + width]);
+        BucketId::from_le_bytes(bytes)
+    }
+
+    /// Returns the bucket of a hand, `board_cards` may contain cards of later rounds
+    pub fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        let num_board_cards = self.indexer.num_cards(self.round as usize) - self.num_hole_cards as usize;
+        let cards = [&hole_cards[..self.num_hole_cards as usize], &board_cards[..num_board_cards]].concat();
+        self.get(self.indexer.index_round(self.round as usize, &cards))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(path)?);
+        f.write_all(MAGIC)?;
+        f.write_all(&VERSION.to_le_bytes())?;
+        f.write_all(&[self.indexer.num_suits(), self.indexer.num_ranks(), self.num_hole_cards, self.indexer.num_rounds() as u8])?;
+        f.write_all(self.indexer.cards_per_round())?;
+        f.write_all(&[self.round, self.bucket_width])?;
+        f.write_all(&self.num_buckets.to_le_bytes())?;
+        f.write_all(&self.len().to_le_bytes())?;
+        f.write_all(self.data.bytes())?;
+        f.flush()
+    }
+
+    /// Memory maps a table written by `save`
+    pub fn load(path: &Path) -> io::Result<BucketTable> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), reason));
+
+        let file = File::open(path)?;
+        let mut header = io::BufReader::new(&file);
+        let mut read_bytes = |n: usize| -> io::Result<Vec<u8>> {
+            let mut buf = vec![0; n];
+            header.read_exact(&mut buf)?;
+            Ok(buf)
+        };
+
+        if read_bytes(4)? != MAGIC {
+            return Err(invalid("not a bucket table"));
+        }
+        let version = u32::from_le_bytes(read_bytes(4)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported bucket table version {}", version)));
+        }
+
+        let sizes = read_bytes(4)?;
+        let (num_suits, num_ranks, num_hole_cards, num_rounds) = (sizes[0], sizes[1], sizes[2], sizes[3]);
+        let cards_per_round = read_bytes(num_rounds as usize)?;
+        let round_info = read_bytes(2)?;
+        let (round, bucket_width) = (round_info[0], round_info[1]);
+        let num_buckets = u32::from_le_bytes(read_bytes(4)?.try_into().unwrap());
+        let len = u64::from_le_bytes(read_bytes(8)?.try_into().unwrap());
+
+        if round >= num_rounds || num_hole_cards > cards_per_round[0] {
+            return Err(invalid("corrupt header"));
+        }
+        let indexer = HandIndexer::new(num_suits, num_ranks, &cards_per_round);
+        if indexer.round_size(round as usize) != len || bucket_width != self::bucket_width(num_buckets) {
+            return Err(invalid("corrupt header"));
+        }
+
+        let offset = 4 + 4 + 4 + num_rounds as usize + 2 + 4 + 8;
+        // SAFETY: bucket tables are written once and treated as read only afterwards
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() != offset + len as usize * bucket_width as usize {
+            return Err(invalid("truncated bucket table"));
+        }
+
+        Ok(BucketTable {
+            indexer,
+            num_hole_cards,
+            round,
+            num_buckets,
+            bucket_width,
+            data: TableData::Mapped { mmap, offset },
+        })
+    }
+}
+
+/// Builds bucket tables for a round by enumerating every canonical hand in parallel
+pub struct BucketTableBuilder {
+    indexer: HandIndexer,
+    num_hole_cards: u8,
+    round: u8,
+    cancel_token: CancelToken,
+}
+
+impl BucketTableBuilder {
+    pub fn new(game_info: &GameInfo, round: u8) -> BucketTableBuilder {
+        assert!(round < game_info.num_rounds());
+        BucketTableBuilder {
+            indexer: HandIndexer::from_game_info(game_info),
+            num_hole_cards: game_info.num_hole_cards(),
+            round,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    /// Sets the token checked for every hand while building
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Splits the canonical hand with `index` into hole cards and board cards
+    pub fn hand(&self, index: HandIndex) -> (Vec<Card>, Vec<Card>) {
+        let mut cards = self.indexer.unindex(self.round as usize, index).unwrap();
+        let board_cards = cards.split_off(self.num_hole_cards as usize);
+        (cards, board_cards)
+    }
+
+    /// Returns number of canonical hands of the round
+    pub fn len(&self) -> HandIndex {
+        self.indexer.round_size(self.round as usize)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f(hole_cards, board_cards)` on the canonical hands with an index in `range`, so
+    /// long builds can compute and persist their values a chunk at a time
+    pub fn map<T, F>(&self, range: Range<HandIndex>, f: F) -> Result<Vec<T>, Cancelled>
+    where T: Send, F: Fn(&[Card], &[Card]) -> T + Sync {
+        range.into_par_iter()
+            .map(|i| {
+                self.cancel_token.check()?;
+                let (hole_cards, board_cards) = self.hand(i);
+                Ok(f(&hole_cards, &board_cards))
+            })
+            .collect()
+    }
+
+    /// Builds a table by calling `bucket(hole_cards, board_cards)` on every canonical hand
+    pub fn build<F>(&self, num_buckets: u32, bucket: F) -> Result<BucketTable, Cancelled>
+    where F: Fn(&[Card], &[Card]) -> BucketId + Sync {
+        let buckets = self.map(0..self.len(), bucket)?;
+        Ok(self.build_from_buckets(num_buckets, &buckets))
+    }
+
+    /// Builds a table from the bucket of every canonical hand in index order
+    pub fn build_from_buckets(&self, num_buckets: u32, buckets: &[BucketId]) -> BucketTable {
+        assert_eq!(buckets.len() as HandIndex, self.len());
+        BucketTable::from_buckets(self.indexer.clone(), self.num_hole_cards, self.round, num_buckets, buckets)
+    }
+
+    /// Builds a table giving every canonical hand its own bucket
+    pub fn build_lossless(&self) -> BucketTable {
+        assert!(self.len() <= BucketId::MAX as HandIndex, "too many canonical hands for lossless buckets");
+        let buckets = (0..self.len() as BucketId).collect::<Vec<BucketId>>();
+        self.build_from_buckets(self.len() as u32, &buckets)
+    }
+
+    /// Builds a table by computing `strength(hole_cards, board_cards)` of every canonical hand
+    /// and splitting hands into `num_buckets` equally sized percentile buckets. Every canonical
+    /// hand is weighted equally.
+    pub fn build_percentile<F>(&self, num_buckets: u32, strength: F) -> Result<BucketTable, Cancelled>
+    where F: Fn(&[Card], &[Card]) -> f32 + Sync {
+        let strengths = self.map(0..self.len(), strength)?;
+        Ok(self.build_percentile_from(num_buckets, &strengths))
+    }
+
+    /// Same as `build_percentile` with the strength of every canonical hand already computed
+    pub fn build_percentile_from(&self, num_buckets: u32, strengths: &[f32]) -> BucketTable {
+        let mut order = (0..strengths.len()).collect::<Vec<usize>>();
+        order.par_sort_unstable_by(|a, b| strengths[*a].total_cmp(&strengths[*b]));
+
+        let mut buckets = vec![0; strengths.len()];
+        for (position, i) in order.into_iter().enumerate() {
+            buckets[i] = (position as u64 * num_buckets as u64 / strengths.len() as u64) as BucketId;
+        }
+
+        self.build_from_buckets(num_buckets, &buckets)
+    }
+}
+
+/// Returns the probability of beating a uniformly random opponent hand on the current board,
+/// counting ties as half
+pub fn hand_strength(hand_ranking: HandRanking, deck: &[Card], hole_cards: &[Card], board_cards: &[Card]) -> f64 {
+    let cards = [hole_cards, board_cards].concat();
+    let evaluator = hand_ranking.evaluator();
+    let direction = hand_ranking.direction();
+    let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, board_cards));
+
+    let mut score = 0.;
+    let mut total = 0;
+    for opponent_hole_cards in deck.iter().filter(|c| !cards.contains(c)).copied().combinations(hole_cards.len()) {
+        let opponent_rank = direction.orient(evaluator.evaluate_showdown(&opponent_hole_cards, board_cards));
+        if rank > opponent_rank {
+            score += 1.;
+        } else if rank == opponent_rank {
+            score += 0.5;
+        }
+        total += 1;
+    }
+
+    score / total as f64
+}
+
+/// Returns the hand strength at the final round averaged over every way to complete the board
+/// with `num_board_cards` cards
+pub fn expected_hand_strength(hand_ranking: HandRanking, deck: &[Card], hole_cards: &[Card], board_cards: &[Card], num_board_cards: usize) -> f64 {
+    let dead = [hole_cards, board_cards].concat();
+    let mut total = 0.;
+    let mut count = 0;
+    for runout in deck.iter().filter(|c| !dead.contains(c)).copied().combinations(num_board_cards - board_cards.len()) {
+        total += hand_strength(hand_ranking, deck, hole_cards, &[board_cards, &runout[..]].concat());
+        count += 1;
+    }
+
+    total / count as f64
+}
@@ -0,0 +1,176 @@
+use super::{
+    game::GameInfo,
+    hand_indexer::{HandIndex, HandIndexer},
+};
+
+use poker::{Card, Suit};
+use variter::VarIter;
+
+use serde::{Deserialize, Serialize};
+
+/// Renaming of the suits, suit `s` becomes the suit at position `s`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SuitPermutation([u8; 4]);
+
+impl Default for SuitPermutation {
+    fn default() -> SuitPermutation {
+        SuitPermutation::identity()
+    }
+}
+
+impl SuitPermutation {
+    pub fn identity() -> SuitPermutation {
+        SuitPermutation([0, 1, 2, 3])
+    }
+
+    /// Creates the permutation renaming `Suit::ALL_VARIANTS[s]` to `suits[s]`
+    pub fn from_suits(suits: [Suit; 4]) -> SuitPermutation {
+        let mut seen = [false; 4];
+        for suit in suits {
+            assert!(!seen[suit as usize], "suit {:?} appears twice in permutation", suit);
+            seen[suit as usize] = true;
+        }
+        SuitPermutation(suits.map(|s| s as u8))
+    }
+
+    pub fn suit(&self, suit: Suit) -> Suit {
+        Suit::ALL_VARIANTS[self.0[suit as usize] as usize]
+    }
+
+    pub fn card(&self, card: Card) -> Card {
+        Card::new(card.rank(), self.suit(card.suit()))
+    }
+
+    pub fn cards(&self, cards: &[Card]) -> Vec<Card> {
+        cards.iter().map(|c| self.card(*c)).collect()
+    }
+
+    /// Returns the permutation that undoes this one, mapping canonical cards back to the
+    /// original suits
+    pub fn inverse(&self) -> SuitPermutation {
+        let mut inverse = [0; 4];
+        for (s, t) in self.0.iter().enumerate() {
+            inverse[*t as usize] = s as u8;
+        }
+        SuitPermutation(inverse)
+    }
+}
+
+/// A hand with its suits renamed to the canonical representative of its suit isomorphism class.
+/// The cards of each round are sorted by suit then rank, so every hand with the same index has
+/// the same canonical cards.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanonicalHand {
+    pub round: u8,
+    pub index: HandIndex,
+    pub hole_cards: Vec<Card>,
+    /// Board cards dealt up to `round`
+    pub board_cards: Vec<Card>,
+    /// Renames the suits of the original hand to the canonical suits
+    pub permutation: SuitPermutation,
+}
+
+/// Suit isomorphic canonicalization of the hands of a game, shared by everything that stores or
+/// caches values per canonical hand. Indices are those of `HandIndexer::from_game_info`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Canonicalizer {
+    indexer: HandIndexer,
+    num_hole_cards: u8,
+}
+
+impl Canonicalizer {
+    pub fn new(game_info: &GameInfo) -> Canonicalizer {
+        Canonicalizer {
+            indexer: HandIndexer::from_game_info(game_info),
+            num_hole_cards: game_info.num_hole_cards(),
+        }
+    }
+
+    pub fn indexer(&self) -> &HandIndexer {
+        &self.indexer
+    }
+
+    /// Returns number of canonical hands in `round`
+    pub fn round_size(&self, round: u8) -> HandIndex {
+        self.indexer.round_size(round as usize)
+    }
+
+    fn num_board_cards(&self, round: u8) -> usize {
+        self.indexer.num_cards(round as usize) - self.num_hole_cards as usize
+    }
+
+    /// Returns the hole cards followed by the board cards of `round`, `board_cards` may contain
+    /// cards of later rounds
+    fn cards(&self, round: u8, hole_cards: &[Card], board_cards: &[Card]) -> Vec<Card> {
+        assert!(hole_cards.len() >= self.num_hole_cards as usize, "not enough hole cards");
+        assert!(board_cards.len() >= self.num_board_cards(round), "not enough board cards for round {}", round);
+        [&hole_cards[..self.num_hole_cards as usize], &board_cards[..self.num_board_cards(round)]].concat()
+    }
+
+    /// Returns the canonical index of a hand, `board_cards` may contain cards of later rounds
+    pub fn index(&self, round: u8, hole_cards: &[Card], board_cards: &[Card]) -> HandIndex {
+        self.indexer.index_round(round as usize, &self.cards(round, hole_cards, board_cards))
+    }
+
+    /// Returns the canonical hole cards and board cards of the hand with `index`
+    pub fn hand(&self, round: u8, index: HandIndex) -> Option<(Vec<Card>, Vec<Card>)> {
+        let mut cards = self.indexer.unindex(round as usize, index)?;
+        let board_cards = cards.split_off(self.num_hole_cards as usize);
+        Some((cards, board_cards))
+    }
+
+    /// Canonicalizes a hand, `board_cards` may contain cards of later rounds
+    pub fn canonicalize(&self, round: u8, hole_cards: &[Card], board_cards: &[Card]) -> CanonicalHand {
+        let cards = self.cards(round, hole_cards, board_cards);
+        let index = self.indexer.index_round(round as usize, &cards);
+        let canonical = self.indexer.unindex(round as usize, index).unwrap();
+
+        // Suits with the same ranks in every round are interchangeable, so each suit is renamed
+        // to an unused canonical suit holding the same ranks
+        let original_ranks = self.suit_ranks(round, &cards);
+        let canonical_ranks = self.suit_ranks(round, &canonical);
+        let mut suits: [Suit; 4] = Suit::ALL_VARIANTS.try_into().unwrap();
+        let mut used = [false; 4];
+        for s in 0..self.indexer.num_suits() as usize {
+            let t = (0..self.indexer.num_suits() as usize)
+                .find(|t| !used[*t] && canonical_ranks[*t] == original_ranks[s])
+                .expect("canonical hand has different suits");
+            used[t] = true;
+            suits[s] = Suit::ALL_VARIANTS[t];
+        }
+        let permutation = SuitPermutation::from_suits(suits);
+
+        let sort = |mut cards: Vec<Card>| {
+            cards.sort_by_key(|c| (c.suit() as u8, c.rank()));
+            cards
+        };
+        let mut board_cards = Vec::new();
+        let mut start = 0;
+        for r in 0..=round as usize {
+            let end = self.indexer.num_cards(r) - self.num_hole_cards as usize;
+            board_cards.extend(sort(permutation.cards(&cards[self.num_hole_cards as usize + start..self.num_hole_cards as usize + end])));
+            start = end;
+        }
+
+        CanonicalHand {
+            round,
+            index,
+            hole_cards: sort(permutation.cards(&cards[..self.num_hole_cards as usize])),
+            board_cards,
+            permutation,
+        }
+    }
+
+    /// Returns the set of ranks of every suit dealt in each indexer round up to `round`
+    fn suit_ranks(&self, round: u8, cards: &[Card]) -> Vec<Vec<u16>> {
+        let mut ranks = vec![vec![0u16; round as usize + 1]; self.indexer.num_suits() as usize];
+        let mut start = 0;
+        for r in 0..=round as usize {
+            for card in &cards[start..self.indexer.num_cards(r)] {
+                ranks[card.suit() as usize][r] |= 1 << card.rank() as u16;
+            }
+            start = self.indexer.num_cards(r);
+        }
+        ranks
+    }
+}
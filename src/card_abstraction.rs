@@ -1,9 +1,13 @@
-use super::game::GameInfo;
+use super::{
+    bucket_table::BucketTable,
+    config::{self, ConfigError},
+    game::{DeckIndex, GameInfo},
+};
 
-use std::path::Path;
-use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use poker::Card;
 
@@ -12,29 +16,122 @@ pub type BucketId = u32;
 //TODO: make serialize/deserialize only require round(may require custom serialize/deserialize
 //code)
 
+/// Whether the buckets of a round remember the buckets of the earlier rounds
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Recall {
+    /// Every round buckets hands on its own, hands that were apart in earlier rounds can share a
+    /// bucket later
+    #[default]
+    Imperfect,
+    /// The bucket of a round is the sequence of the buckets of every round so far, so a player
+    /// never forgets what they knew. The number of buckets is the product over the rounds and
+    /// has to fit in a `BucketId`.
+    Perfect,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CardAbstraction {
     round_infosets: Vec<Box<dyn RoundBuckets>>,
+    #[serde(default)]
+    recall: Recall,
 }
 
 impl CardAbstraction {
     pub fn new(round_infosets: Vec<Box<dyn RoundBuckets>>) -> CardAbstraction {
-        CardAbstraction { round_infosets }
+        CardAbstraction { round_infosets, recall: Recall::Imperfect }
+    }
+
+    pub fn from_config(path: &Path) -> Result<CardAbstraction, ConfigError> {
+        config::read_json(path)
+    }
+
+    pub fn from_json(json: &str) -> Result<CardAbstraction, ConfigError> {
+        config::from_json(json)
+    }
+
+    pub fn recall(&self) -> Recall {
+        self.recall
     }
 
-    pub fn from_config(path: &Path) -> CardAbstraction {
-        let card_abstraction: CardAbstraction = serde_json::from_str(&fs::read_to_string(path).expect("failed to read card abstraction config")).expect("failed to deserialize card abstraction");
-        card_abstraction
+    pub fn set_recall(&mut self, recall: Recall) {
+        self.recall = recall;
     }
 
+    /// Returns the infoset key of the hand in `round`. With perfect recall it chains the bucket
+    /// of every round so far, the first round's being the most significant digit.
     pub fn get_bucket(&self, round: u8, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
-        self.round_infosets[round as usize].get_bucket(board_cards, hole_cards)
+        match self.recall {
+            Recall::Imperfect => self.get_round_bucket(round, board_cards, hole_cards),
+            Recall::Perfect => (0..=round).fold(0, |key: BucketId, r| {
+                key.checked_mul(self.round_infosets[r as usize].num_buckets())
+                    .and_then(|k| k.checked_add(self.get_round_bucket(r, board_cards, hole_cards)))
+                    .expect("too many perfect recall buckets for a BucketId")
+            }),
+        }
+    }
+
+    /// Returns the bucket of the hand in `round` alone, whatever the recall
+    pub fn get_round_bucket(&self, round: u8, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        let round_buckets = &self.round_infosets[round as usize];
+        let bucket = round_buckets.get_bucket(board_cards, hole_cards);
+        debug_assert!(bucket < round_buckets.num_buckets() || round_buckets.num_buckets() == BucketId::MAX, "bucket {} out of range in round {}", bucket, round);
+        bucket
+    }
+
+    /// Splits an infoset key of `round` back into the bucket of every round it remembers, only
+    /// the bucket of `round` with imperfect recall
+    pub fn round_buckets(&self, round: u8, mut bucket: BucketId) -> Vec<BucketId> {
+        match self.recall {
+            Recall::Imperfect => vec![bucket],
+            Recall::Perfect => {
+                let mut buckets = (0..=round).rev()
+                    .map(|r| {
+                        let n = self.round_infosets[r as usize].num_buckets();
+                        let b = bucket % n;
+                        bucket /= n;
+                        b
+                    })
+                    .collect::<Vec<BucketId>>();
+                buckets.reverse();
+                buckets
+            },
+        }
+    }
+
+    /// Returns the number of infoset keys of `round`
+    pub fn num_buckets(&self, round: u8) -> u64 {
+        match self.recall {
+            Recall::Imperfect => self.round_infosets[round as usize].num_buckets() as u64,
+            Recall::Perfect => (0..=round).map(|r| self.round_infosets[r as usize].num_buckets() as u64).product(),
+        }
+    }
+
+    /// Whether the infoset keys of `round` are dense, see `RoundBuckets::is_dense`. With perfect
+    /// recall every round so far has to be dense.
+    pub fn is_dense(&self, round: u8) -> bool {
+        match self.recall {
+            Recall::Imperfect => self.round_infosets[round as usize].is_dense(),
+            Recall::Perfect => (0..=round).all(|r| self.round_infosets[r as usize].is_dense()),
+        }
     }
 }
 
 #[typetag::serde(tag = "type")]
-pub trait RoundBuckets {
+pub trait RoundBuckets: Send + Sync {
+    /// Returns the bucket of the hand, `board_cards` and `hole_cards` may hold cards of later
+    /// rounds which are ignored
     fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId;
+
+    /// Returns one more than the largest bucket, saturating at the largest `BucketId`. Every
+    /// bucket is in `0..num_buckets()`.
+    fn num_buckets(&self) -> BucketId;
+
+    /// Whether the buckets are numbered densely from zero, so arrays of `num_buckets()` entries
+    /// can hold a value per bucket with few unused entries. Sparse buckets like `NoBuckets`,
+    /// which encode the cards directly, should be stored in maps.
+    fn is_dense(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -51,29 +148,48 @@ impl NoBuckets {
             num_suits: game_info.num_suits(),
             num_ranks: game_info.num_ranks(),
             num_board_cards: game_info.total_board_cards(round),
-            num_hole_cards: game_info.num_hole_cards(), 
+            num_hole_cards: game_info.num_player_cards(round),
         }
     }
 }
 
+impl NoBuckets {
+    fn deck_index(&self) -> DeckIndex {
+        DeckIndex::new(self.num_suits, self.num_ranks)
+    }
+}
+
 #[typetag::serde]
 impl RoundBuckets for NoBuckets {
     fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        let deck = self.deck_index();
         let mut bucket: BucketId = 0;
         for i in 0..self.num_hole_cards {
             if i > 0 {
-                bucket *= self.num_suits as u32 * self.num_ranks as u32;
+                bucket *= deck.len();
             }
-            bucket += hole_cards[i as usize].rank() as u32 * self.num_suits as u32 + hole_cards[i as usize].suit() as u32;
+            bucket += deck.index(hole_cards[i as usize]);
         }
 
         for i in 0..self.num_board_cards {
-            bucket *= self.num_suits as u32 * self.num_ranks as u32;
-            bucket += board_cards[i as usize].rank() as u32 * self.num_suits as u32 + board_cards[i as usize].suit() as u32;
+            bucket *= deck.len();
+            bucket += deck.index(board_cards[i as usize]);
         }
 
         bucket
     }
+
+    fn num_buckets(&self) -> BucketId {
+        self.deck_index().len()
+            .checked_pow((self.num_hole_cards + self.num_board_cards) as u32)
+            .unwrap_or(BucketId::MAX)
+    }
+
+    /// Buckets number ordered deals of every card, most of which are never reached since cards
+    /// can't repeat
+    fn is_dense(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -101,4 +217,57 @@ impl RoundBuckets for LosslessBuckets {
         //TODO: implement lossless(suit isomprhims etc) abstraction, look at http://www.kevinwaugh.com/pdf/isomorphism13.pdf
         0
     }
+
+    fn num_buckets(&self) -> BucketId {
+        1
+    }
+}
+
+/// Buckets read from a table generated offline with `BucketTableBuilder`, the table is memory
+/// mapped when the abstraction is deserialized
+pub struct PrecomputedBuckets {
+    path: PathBuf,
+    table: BucketTable,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PrecomputedBucketsConfig {
+    path: PathBuf,
+}
+
+impl PrecomputedBuckets {
+    pub fn load(path: &Path) -> io::Result<PrecomputedBuckets> {
+        Ok(PrecomputedBuckets {
+            path: path.to_path_buf(),
+            table: BucketTable::load(path)?,
+        })
+    }
+
+    pub fn num_buckets(&self) -> u32 {
+        self.table.num_buckets()
+    }
+}
+
+impl Serialize for PrecomputedBuckets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PrecomputedBucketsConfig { path: self.path.clone() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrecomputedBuckets {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let config = PrecomputedBucketsConfig::deserialize(deserializer)?;
+        PrecomputedBuckets::load(&config.path).map_err(de::Error::custom)
+    }
+}
+
+#[typetag::serde]
+impl RoundBuckets for PrecomputedBuckets {
+    fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        self.table.get_bucket(board_cards, hole_cards)
+    }
+
+    fn num_buckets(&self) -> BucketId {
+        self.table.num_buckets()
+    }
 }
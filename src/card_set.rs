@@ -0,0 +1,225 @@
+use super::range::card_index;
+
+use poker::{Card, Rank, Suit};
+use itertools::Itertools;
+use variter::VarIter;
+
+use std::error::Error;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCardsError {
+    token: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseCardsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid card \"{}\": {}", self.token, self.reason)
+    }
+}
+
+impl Error for ParseCardsError {}
+
+/// Parses a card like `Ah`, ranks and suits are case insensitive and tens can be written `10`
+pub fn parse_card(card: &str) -> Result<Card, ParseCardsError> {
+    let error = |reason| ParseCardsError { token: card.to_string(), reason };
+    let mut chars = card.trim().chars().peekable();
+    let rank = match chars.next().ok_or_else(|| error("missing rank"))?.to_ascii_uppercase() {
+        '1' if chars.next_if_eq(&'0').is_some() => Rank::Ten,
+        c => Rank::try_from(c).map_err(|_| error("unknown rank"))?,
+    };
+    let suit = Suit::try_from(chars.next().ok_or_else(|| error("missing suit"))?.to_ascii_lowercase()).map_err(|_| error("unknown suit"))?;
+    if chars.next().is_some() {
+        return Err(error("unexpected characters after the suit"));
+    }
+    Ok(Card::new(rank, suit))
+}
+
+/// Splits cards written together or separated by spaces into the text of each card, `*` is a
+/// card of its own
+fn split_cards(cards: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut chars = cards.chars().filter(|c| !c.is_whitespace()).peekable();
+    while let Some(c) = chars.next() {
+        let mut card = c.to_string();
+        if c != '*' {
+            if c == '1' {
+                card.extend(chars.next_if_eq(&'0'));
+            }
+            card.extend(chars.next_if(|c| *c != '*'));
+        }
+        split.push(card);
+    }
+    split
+}
+
+/// Parses cards written together or separated by spaces, like `AhKd` or `Ah Kd`. A card can't
+/// appear twice.
+pub fn parse_cards(cards: &str) -> Result<Vec<Card>, ParseCardsError> {
+    let mut parsed = Vec::new();
+    for card in split_cards(cards) {
+        if card == "*" {
+            return Err(ParseCardsError { token: card, reason: "wildcards are only allowed in boards" });
+        }
+        let card = parse_card(&card)?;
+        if parsed.contains(&card) {
+            return Err(ParseCardsError { token: card.rank_suit_string(), reason: "card appears twice" });
+        }
+        parsed.push(card);
+    }
+    Ok(parsed)
+}
+
+/// Parses boards separated by commas, like `AhKdQc, 2c3c4c`. A `*` in a board stands for any
+/// card of `deck` not on it and expands to every such board, so `AhKd*` is each flop holding
+/// the ace of hearts and king of diamonds.
+pub fn parse_boards(boards: &str, deck: &[Card]) -> Result<Vec<Vec<Card>>, ParseCardsError> {
+    let mut parsed = Vec::new();
+    for board in boards.split(',') {
+        let mut cards = Vec::new();
+        let mut num_wildcards = 0;
+        for card in split_cards(board) {
+            if card == "*" {
+                num_wildcards += 1;
+                continue;
+            }
+            let card = parse_card(&card)?;
+            if !deck.contains(&card) {
+                return Err(ParseCardsError { token: card.rank_suit_string(), reason: "card is not in the deck" });
+            }
+            if cards.contains(&card) {
+                return Err(ParseCardsError { token: card.rank_suit_string(), reason: "card appears twice" });
+            }
+            cards.push(card);
+        }
+        if cards.is_empty() && num_wildcards == 0 {
+            return Err(ParseCardsError { token: board.trim().to_string(), reason: "empty board" });
+        }
+
+        let remaining = CardSet::from_cards(&cards).remaining(deck);
+        for rest in remaining.into_iter().combinations(num_wildcards) {
+            parsed.push([cards.as_slice(), &rest].concat());
+        }
+    }
+    Ok(parsed)
+}
+
+/// Formats cards separated by spaces, like `Ah Kd`
+pub fn format_cards(cards: &[Card]) -> String {
+    cards.iter().map(|c| c.rank_suit_string()).join(" ")
+}
+
+/// Formats cards written together, like `AhKd`
+pub fn format_cards_compact(cards: &[Card]) -> String {
+    cards.iter().map(|c| c.rank_suit_string()).collect()
+}
+
+/// Set of cards packed into a 64 bit mask, bit `rank * 4 + suit` is set for every card. Checking
+/// if hands block each other or the board is a single `and` instead of comparing every card.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CardSet(pub u64);
+
+impl CardSet {
+    pub fn new() -> CardSet {
+        CardSet(0)
+    }
+
+    /// Returns the set of the 52 cards
+    pub fn full() -> CardSet {
+        CardSet((1 << (Rank::ALL_VARIANTS.len() * Suit::ALL_VARIANTS.len())) - 1)
+    }
+
+    pub fn from_card(card: Card) -> CardSet {
+        CardSet(1 << card_index(card))
+    }
+
+    pub fn from_cards(cards: &[Card]) -> CardSet {
+        cards.iter().fold(CardSet::new(), |set, c| set | CardSet::from_card(*c))
+    }
+
+    pub fn contains(&self, card: Card) -> bool {
+        self.0 & CardSet::from_card(card).0 != 0
+    }
+
+    pub fn insert(&mut self, card: Card) {
+        self.0 |= CardSet::from_card(card).0;
+    }
+
+    pub fn remove(&mut self, card: Card) {
+        self.0 &= !CardSet::from_card(card).0;
+    }
+
+    /// Returns if the sets share a card
+    pub fn intersects(&self, other: CardSet) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the cards from the lowest bit up
+    pub fn iter(&self) -> impl Iterator<Item = Card> {
+        let mut mask = self.0;
+        std::iter::from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let index = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            let num_suits = Suit::ALL_VARIANTS.len();
+            Some(Card::new(Rank::ALL_VARIANTS[index / num_suits], Suit::ALL_VARIANTS[index % num_suits]))
+        })
+    }
+
+    /// Returns the cards of `deck` not in the set, keeping their order
+    pub fn remaining(&self, deck: &[Card]) -> Vec<Card> {
+        deck.iter().filter(|c| !self.contains(**c)).copied().collect()
+    }
+}
+
+impl BitOr for CardSet {
+    type Output = CardSet;
+
+    fn bitor(self, other: CardSet) -> CardSet {
+        CardSet(self.0 | other.0)
+    }
+}
+
+impl BitAnd for CardSet {
+    type Output = CardSet;
+
+    fn bitand(self, other: CardSet) -> CardSet {
+        CardSet(self.0 & other.0)
+    }
+}
+
+impl Not for CardSet {
+    type Output = CardSet;
+
+    /// Returns the cards of the 52 card deck not in the set
+    fn not(self) -> CardSet {
+        CardSet(!self.0 & CardSet::full().0)
+    }
+}
+
+impl FromIterator<Card> for CardSet {
+    fn from_iter<I: IntoIterator<Item = Card>>(iter: I) -> CardSet {
+        iter.into_iter().fold(CardSet::new(), |set, c| set | CardSet::from_card(c))
+    }
+}
+
+impl fmt::Display for CardSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for card in self.iter() {
+            write!(f, "{}", card.rank_suit_string())?;
+        }
+        Ok(())
+    }
+}
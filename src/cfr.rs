@@ -1,27 +1,212 @@
 use super::{
     abstract_game::AbstractGame,
-    game::{Action, PlayerId, MAX_PLAYERS},
-    strategy::{ Strategy, Regrets },
-    node::NodeId,
+    best_response,
+    card_abstraction::BucketId,
+    cancel::{CancelToken, Cancelled},
+    deals::{Deal, DealDistribution},
+    distributed::TrainingState,
+    evaluator::HandEvaluator,
+    game::{Action, GameState, PlayerId},
+    history_abstraction::HistoryAbstraction,
+    icm::IcmUtility,
+    infoset_table::InfosetTable,
+    infosets::InfosetIndex,
+    leaf_evaluator::LeafEvaluator,
+    strategy::{ self, sample_probabilities, Strategy, PROBABILITY_SCALE, StrategyMetadata, StrategyProfile, Regrets, RegretPrecision, RegretStorage, QuantizedStrategy },
+    node::{Node, NodeId},
+    parallel::*,
 };
 
-use std::{collections::BTreeMap, io::BufWriter};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::cmp::max;
 use std::fs;
+use std::fmt;
 use std::path::Path;
+use std::time::Duration;
 use rand::Rng;
 use rand::prelude::*;
+use rand::rngs::StdRng;
 
-use log::info;
+use serde::{Serialize, Serializer, Deserialize};
 
-use serde::{Serialize, Deserialize};
+use poker::Card;
 
-use poker::{Card, Evaluator};
+/// How MCCFR explores the actions of the traversing player
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Sampling {
+    /// Every action is explored
+    #[default]
+    External,
+    /// Each action is explored with probability `max(epsilon, (beta + tau * s(a)) / (beta + sum
+    /// of s))` where `s` are the average strategy counts of the infoset, so actions the average
+    /// strategy rarely plays are rarely explored. Infosets without an average strategy are fully
+    /// explored.
+    AverageStrategy { epsilon: f32, tau: f32, beta: f32 },
+}
+
+/// How the strategy a run ends with is kept
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum StrategyAveraging {
+    /// Action counts sampled from the current strategy every `strategy_interval` iterations are
+    /// stored alongside the regrets and averaged
+    #[default]
+    Stored,
+    /// Nothing is stored and the strategy is reconstructed from the regrets when asked for, as
+    /// the current strategy of the last iteration. Linear discounting weighs the regrets toward
+    /// later iterations like DCFR, so it converges to roughly the average without the average
+    /// strategy table, but noisier and only while discounting is on. Memory only drops by the
+    /// table, on a spread limit Leduc with a million infosets the peak went from 115 MB to 100 MB.
+    LastIterate,
+}
+
+/// Conditions that end training before the requested iterations are run. Trainers check them
+/// between batches of iterations, so a batch that crosses a budget is finished first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StopConditions {
+    /// Wall clock budget in seconds
+    #[serde(default)]
+    pub max_seconds: Option<f64>,
+    /// Budget of nodes visited by traversals, solvers that don't count them ignore it
+    #[serde(default)]
+    pub max_nodes_touched: Option<u64>,
+    /// Exploitability in chips per hand at which training is good enough. Computing it walks
+    /// the whole tree, so only small games are feasible.
+    #[serde(default)]
+    pub target_exploitability: Option<f64>,
+}
+
+/// When an infoset counts as converged and stops being updated, see `CFREngine::freeze_converged`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FreezeConditions {
+    /// Times the regrets of an infoset have to be updated before it can be frozen
+    pub min_visits: u64,
+    /// Largest change of the probability of any action of the current strategy between two
+    /// checks for the infoset to be frozen
+    pub max_strategy_change: f32,
+    /// Iterations between checks while training
+    pub check_interval: u32,
+}
+
+/// Iterations of the phases of Pluribus style self-play training, see `CFREngine::pluribus`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PluribusSchedule {
+    pub strategy_interval: u32,
+    /// Iteration after which actions with very negative regret are mostly skipped
+    pub prune_threshold: u32,
+    /// Iteration up to which regrets and the average strategy are discounted linearly
+    pub lcfr_threshold: u32,
+    pub discount_interval: u32,
+    /// Iteration from which the current strategy of the rounds past
+    /// `rounds_update_average_strategy` is snapshotted
+    pub snapshot_start: u32,
+    pub snapshot_interval: u32,
+}
+
+impl PluribusSchedule {
+    /// Scales the phases of the 8 day run that trained the Pluribus blueprint to `iterations`:
+    /// discounting every 10 minutes for the first 400, pruning after 200 and snapshots every 200
+    /// minutes after 800. The average strategy is updated every 20 iterations like the other
+    /// trainers.
+    pub fn scaled(iterations: u32) -> PluribusSchedule {
+        let minutes = |m: u64| ((iterations as u64 * m / (8 * 24 * 60)) as u32).max(1);
+        PluribusSchedule {
+            strategy_interval: 20,
+            prune_threshold: minutes(200),
+            lcfr_threshold: minutes(400),
+            discount_interval: minutes(10),
+            snapshot_start: minutes(800),
+            snapshot_interval: minutes(200),
+        }
+    }
+}
+
+/// Counts a snapshot of the current strategy adds to the average strategy of an infoset, spread
+/// over the actions by their probability
+const SNAPSHOT_WEIGHT: f32 = 1000.;
+
+/// Why training stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Iterations,
+    TimeBudget,
+    NodeBudget,
+    /// Reached the target with this exploitability
+    TargetExploitability(f64),
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Iterations => write!(f, "ran every iteration"),
+            StopReason::TimeBudget => write!(f, "time budget exhausted"),
+            StopReason::NodeBudget => write!(f, "touched nodes budget exhausted"),
+            StopReason::TargetExploitability(e) => write!(f, "reached exploitability {:.6}", e),
+        }
+    }
+}
+
+impl StopConditions {
+    /// Returns the first condition that is met given the time trained, the nodes touched if
+    /// the solver counts them and the current exploitability if it was computed
+    pub fn check(&self, elapsed: Duration, nodes_touched: Option<u64>, exploitability: Option<f64>) -> Option<StopReason> {
+        if self.max_seconds.is_some_and(|s| elapsed.as_secs_f64() >= s) {
+            return Some(StopReason::TimeBudget);
+        }
+        if let (Some(max), Some(touched)) = (self.max_nodes_touched, nodes_touched) {
+            if touched >= max {
+                return Some(StopReason::NodeBudget);
+            }
+        }
+        match (self.target_exploitability, exploitability) {
+            (Some(target), Some(e)) if e <= target => Some(StopReason::TargetExploitability(e)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CFRConfig {
     rounds_update_average_strategy: u8,
     payout_amp: i32,
+    /// Factor regrets of each round are multiplied by every iteration, values below 1 make old
+    /// regrets fade so a strategy learned online keeps adapting to opponents that change
+    #[serde(default)]
+    regret_decay: Vec<f32>,
+    /// Seed of the random number generator used for dealing and sampling, training can only be
+    /// reproduced when it is set
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    sampling: Sampling,
+    #[serde(default)]
+    strategy_averaging: StrategyAveraging,
+    #[serde(default)]
+    regret_precision: RegretPrecision,
+    #[serde(default)]
+    regret_storage: RegretStorage,
+    /// Whether showdowns are evaluated through the shared cache of the game's hand ranking
+    #[serde(default)]
+    cache_hand_evaluations: bool,
+    /// Values terminal states by the change in tournament equity instead of chips if set
+    #[serde(default)]
+    icm: Option<IcmUtility>,
+    #[serde(default)]
+    stop_conditions: StopConditions,
+    /// Whether states reached by different betting that play out the same share a node, see
+    /// `Nodes::set_merge_transpositions`
+    #[serde(default)]
+    merge_transpositions: bool,
+    /// Coarsens the betting nodes are keyed by in deep stack games, see
+    /// `Nodes::set_history_abstraction`
+    #[serde(default)]
+    history_abstraction: Option<HistoryAbstraction>,
+    /// Whether training is reproducible bit for bit, see `set_deterministic`
+    #[serde(default)]
+    deterministic: bool,
+    /// Freezes converged infosets while training if set
+    #[serde(default)]
+    freeze: Option<FreezeConditions>,
 }
 
 impl CFRConfig  {
@@ -29,6 +214,106 @@ impl CFRConfig  {
         CFRConfig {
             rounds_update_average_strategy,
             payout_amp,
+            regret_decay: Vec::new(),
+            seed: None,
+            sampling: Sampling::External,
+            strategy_averaging: StrategyAveraging::Stored,
+            regret_precision: RegretPrecision::default(),
+            regret_storage: RegretStorage::Memory,
+            cache_hand_evaluations: false,
+            icm: None,
+            stop_conditions: StopConditions::default(),
+            merge_transpositions: false,
+            history_abstraction: None,
+            deterministic: false,
+            freeze: None,
+        }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    pub fn set_regret_precision(&mut self, regret_precision: RegretPrecision) {
+        self.regret_precision = regret_precision;
+    }
+
+    /// Sets where regrets are kept, storing them on disk lets abstractions larger than memory be
+    /// trained at the cost of speed while dense storage trains abstractions small enough to
+    /// expand whole faster
+    pub fn set_regret_storage(&mut self, regret_storage: RegretStorage) {
+        self.regret_storage = regret_storage;
+    }
+
+    pub fn set_cache_hand_evaluations(&mut self, cache_hand_evaluations: bool) {
+        self.cache_hand_evaluations = cache_hand_evaluations;
+    }
+
+    /// Sets the tournament payouts to optimize equity for, `None` optimizes chips
+    pub fn set_icm(&mut self, icm: Option<IcmUtility>) {
+        self.icm = icm;
+    }
+
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    pub fn strategy_averaging(&self) -> StrategyAveraging {
+        self.strategy_averaging
+    }
+
+    pub fn set_strategy_averaging(&mut self, strategy_averaging: StrategyAveraging) {
+        self.strategy_averaging = strategy_averaging;
+    }
+
+    /// Sets the per round regret decay, rounds without a factor are not decayed
+    pub fn set_regret_decay(&mut self, regret_decay: Vec<f32>) {
+        self.regret_decay = regret_decay;
+    }
+
+    pub fn stop_conditions(&self) -> &StopConditions {
+        &self.stop_conditions
+    }
+
+    pub fn set_stop_conditions(&mut self, stop_conditions: StopConditions) {
+        self.stop_conditions = stop_conditions;
+    }
+
+    pub fn set_merge_transpositions(&mut self, merge_transpositions: bool) {
+        self.merge_transpositions = merge_transpositions;
+    }
+
+    pub fn set_history_abstraction(&mut self, history_abstraction: Option<HistoryAbstraction>) {
+        self.history_abstraction = history_abstraction;
+    }
+
+    /// Makes two runs with the same config produce bit identical strategies, at some cost in
+    /// speed: runs without a seed use seed 0, bucket lookups run on the calling thread in deal
+    /// order instead of the thread pool and leaf evaluators of a trunk are seeded from the seed.
+    /// Stop conditions on time still end runs after however many iterations fit.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Sets when infosets count as converged, `mccfr_p` then checks for them on the interval and
+    /// freezes them
+    pub fn set_freeze(&mut self, freeze: Option<FreezeConditions>) {
+        self.freeze = freeze;
+    }
+
+    /// Returns the seed training starts from, `None` if it is random
+    fn effective_seed(&self) -> Option<u64> {
+        match (self.seed, self.deterministic) {
+            (None, true) => Some(0),
+            (seed, _) => seed,
         }
     }
 
@@ -38,32 +323,338 @@ impl CFRConfig  {
     }
 }
 
+/// The current strategy of every infoset with regrets, serialized like the `Strategy` of
+/// `last_iterate_strategy` but an infoset at a time so saving doesn't hold it in memory whole
+struct LastIterateStrategy<'a>(&'a CFREngine);
+
+impl Serialize for LastIterateStrategy<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let engine = self.0;
+        serializer.collect_map(engine.regrets.infosets().into_iter().map(|infoset| (infoset, engine.last_iterate_counts(infoset))))
+    }
+}
+
+/// Lookups a deal needs before they are spread over the thread pool, fewer are faster on the
+/// calling thread
+const PARALLEL_BUCKET_LOOKUPS: usize = 16;
+
+/// Buckets of every player in every round of a sampled deal, looked up once per deal instead of
+/// at every node a traversal visits. Draw games look them up at the node since discards change
+/// the hole cards along the way.
+pub struct DealBuckets(Option<(Vec<BucketId>, usize)>);
+
 pub struct CFREngine {
     abstract_game: AbstractGame,
     average_strategy: Strategy,
     regrets: Regrets,
-    evaluator: Evaluator,
+    evaluator: &'static dyn HandEvaluator,
     config: CFRConfig,
+    /// Number of iterations run so far, training can be resumed by calling `mccfr_p` again
+    iteration: u32,
+    /// Nodes visited by every traversal so far
+    nodes_touched: u64,
+    /// Solver that ran the last iteration, saved in the strategy metadata
+    solver: &'static str,
+    /// Iteration at which the regrets of each infoset were last decayed
+    regrets_decayed_at: BTreeMap<(NodeId, BucketId), u32>,
+    /// Times the regrets of each infoset were updated
+    visits: HashMap<(NodeId, BucketId), u64>,
+    /// Infosets whose regrets and average strategy stopped being updated, see `freeze`
+    frozen: BTreeSet<(NodeId, BucketId)>,
+    /// Current strategy of each infoset at the last check for convergence, in the order of the
+    /// actions of its node
+    freeze_snapshots: BTreeMap<(NodeId, BucketId), Vec<f32>>,
+    /// Nodes whose acting player follows fixed action probabilities for every bucket
+    locked_nodes: BTreeMap<NodeId, BTreeMap<Action, f32>>,
+    /// Seats playing a fixed profile instead of training
+    fixed_seats: BTreeMap<PlayerId, StrategyProfile>,
+    /// Strategy the players who aren't traversing follow instead of their regrets, set while
+    /// running CFR-BR
+    best_response: Option<Strategy>,
+    /// Last round that is trained and the evaluator valuing states of later rounds, see
+    /// `set_trunk`
+    trunk: Option<(u8, Box<dyn LeafEvaluator>)>,
+    /// Distribution hands are dealt from, uniformly random deals if `None`
+    deal_distribution: Option<DealDistribution>,
+    cancel_token: CancelToken,
+    rng: StdRng,
 }
 
 impl CFREngine {
-    pub fn new(abstract_game: AbstractGame, config: CFRConfig) -> CFREngine {
+    pub fn new(mut abstract_game: AbstractGame, config: CFRConfig) -> CFREngine {
+        if config.merge_transpositions {
+            abstract_game.nodes.set_merge_transpositions(true);
+        }
+        if config.history_abstraction.is_some() {
+            let game_info = abstract_game.game_info.clone();
+            abstract_game.nodes.set_history_abstraction(&game_info, config.history_abstraction.clone());
+        }
+        let regrets = match config.regret_storage {
+            RegretStorage::Dense => {
+                abstract_game.expand_tree();
+                let index = InfosetIndex::new(&abstract_game).expect("failed to number the infosets");
+                info!("Numbered {} infosets with {} actions for dense regrets", index.num_infosets(), index.num_actions());
+                Regrets::dense(config.regret_precision, index)
+            },
+            _ => Regrets::with_storage(config.regret_precision, &config.regret_storage),
+        };
+        if config.strategy_averaging != StrategyAveraging::Stored && config.sampling != Sampling::External {
+            warn!("Sampling by the average strategy explores every action when it isn't stored");
+        }
+        if config.deterministic && config.stop_conditions.max_seconds.is_some() {
+            warn!("Deterministic training with a time budget stops after a varying number of iterations");
+        }
         CFREngine {
+            evaluator: match config.cache_hand_evaluations {
+                true => abstract_game.game_info.hand_ranking().cached_evaluator(),
+                false => abstract_game.game_info.hand_ranking().evaluator(),
+            },
             abstract_game,
+            rng: config.effective_seed().map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
             average_strategy: Strategy::new(),
-            regrets: Regrets::new(), 
-            evaluator: Evaluator::new(),
+            regrets,
             config,
+            iteration: 0,
+            nodes_touched: 0,
+            solver: "mccfr_p",
+            regrets_decayed_at: BTreeMap::new(),
+            visits: HashMap::new(),
+            frozen: BTreeSet::new(),
+            freeze_snapshots: BTreeMap::new(),
+            locked_nodes: BTreeMap::new(),
+            fixed_seats: BTreeMap::new(),
+            best_response: None,
+            trunk: None,
+            deal_distribution: None,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    /// Returns the strategy training converges to, reconstructed from the regrets unless it is
+    /// stored, see `StrategyAveraging`
+    pub fn average_strategy(&self) -> Cow<'_, Strategy> {
+        match self.config.strategy_averaging {
+            StrategyAveraging::Stored => Cow::Borrowed(&self.average_strategy),
+            StrategyAveraging::LastIterate => Cow::Owned(self.last_iterate_strategy()),
+        }
+    }
+
+    /// Returns the probability of each action at the infoset under `average_strategy`, only
+    /// reconstructing this infoset from the regrets instead of the whole strategy
+    pub fn average_action_probabilities(&self, node_id: NodeId, bucket_id: BucketId) -> BTreeMap<Action, f64> {
+        let actions = self.abstract_game.get_actions(&self.abstract_game.nodes.get_node(node_id).unwrap().state);
+        match self.config.strategy_averaging {
+            StrategyAveraging::Stored => self.average_strategy.action_probabilities(node_id, bucket_id, &actions),
+            StrategyAveraging::LastIterate => {
+                let infoset = (node_id, bucket_id);
+                Strategy(BTreeMap::from([(infoset, self.last_iterate_counts(infoset))])).action_probabilities(node_id, bucket_id, &actions)
+            },
         }
     }
 
+    fn last_iterate_strategy(&self) -> Strategy {
+        Strategy(self.regrets.infosets().into_iter().map(|infoset| (infoset, self.last_iterate_counts(infoset))).collect())
+    }
+
+    /// Returns the current strategy of the infoset as counts summing to `PROBABILITY_SCALE`
+    fn last_iterate_counts(&self, infoset: (NodeId, BucketId)) -> BTreeMap<Action, i32> {
+        let actions = self.abstract_game.get_actions(&self.abstract_game.nodes.get_node(infoset.0).unwrap().state);
+        CFREngine::calculate_strategy(&self.regrets.get(&infoset, &actions))
+            .into_iter()
+            .map(|(a, p)| (a, (p * PROBABILITY_SCALE as f32).round() as i32))
+            .collect()
+    }
+
+    pub fn abstract_game(&self) -> &AbstractGame {
+        &self.abstract_game
+    }
+
+    pub fn abstract_game_mut(&mut self) -> &mut AbstractGame {
+        &mut self.abstract_game
+    }
+
+    /// Consumes the engine, returning the abstract game and average strategy it trained
+    pub fn into_parts(self) -> (AbstractGame, Strategy) {
+        let average_strategy = match self.config.strategy_averaging {
+            StrategyAveraging::Stored => self.average_strategy,
+            StrategyAveraging::LastIterate => self.last_iterate_strategy(),
+        };
+        (self.abstract_game, average_strategy)
+    }
+
+    /// Makes the player acting at `node_id` always follow `probabilities` regardless of their
+    /// cards, regrets and average strategy of the node stop being updated
+    pub fn lock_node(&mut self, node_id: NodeId, probabilities: BTreeMap<Action, f32>) {
+        self.locked_nodes.insert(node_id, probabilities);
+    }
+
+    pub fn unlock_node(&mut self, node_id: NodeId) {
+        self.locked_nodes.remove(&node_id);
+    }
+
+    pub fn unlock_all_nodes(&mut self) {
+        self.locked_nodes.clear();
+    }
+
+    /// Makes `player` play `profile` instead of training, so the other seats learn to play
+    /// against it, e.g. a best response to a frozen opponent. The profile can come from another
+    /// abstraction, its actions are mapped to the closest ones of this one. Fixed seats have no
+    /// regrets or average strategy, so `exploitability` plays them uniformly.
+    pub fn set_fixed_seat(&mut self, player: PlayerId, profile: StrategyProfile) {
+        assert!(player < self.abstract_game.game_info.num_players(), "no seat {}", player);
+        self.fixed_seats.insert(player, profile);
+    }
+
+    pub fn clear_fixed_seat(&mut self, player: PlayerId) {
+        self.fixed_seats.remove(&player);
+    }
+
+    pub fn is_fixed_seat(&self, player: PlayerId) -> bool {
+        self.fixed_seats.contains_key(&player)
+    }
+
+    /// Samples the action of a fixed seat acting at the node from its profile, mapped to the
+    /// closest action of the abstraction. `None` if the player acting isn't fixed.
+    fn fixed_seat_action(&mut self, node_id: NodeId, board_cards: &[Card], hole_cards: &[Vec<Card>]) -> Option<Action> {
+        let game_info = &self.abstract_game.game_info;
+        let state = &self.abstract_game.nodes.get_node(node_id).unwrap().state;
+        let player = state.current_player().ok()?;
+        let profile = self.fixed_seats.get(&player)?;
+
+        let player_hole_cards = &state.current_hole_cards(game_info, hole_cards, board_cards)[player as usize];
+        let visible_board_cards = &board_cards[..(game_info.total_board_cards(state.current_round()) as usize).min(board_cards.len())];
+        let action = sample_probabilities(&profile.action_probabilities(game_info, state, player_hole_cards, visible_board_cards), 1., &mut self.rng);
+
+        let actions = self.abstract_game.get_actions(state);
+        if actions.contains(&action) {
+            return Some(action);
+        }
+        let closest_raise = match action {
+            Action::Raise(raise) => actions.iter()
+                .filter_map(|a| match a {
+                    Action::Raise(r) => Some((*a, r.abs_diff(raise))),
+                    _ => None,
+                })
+                .min_by_key(|(_, diff)| *diff)
+                .map(|(a, _)| a),
+            _ => None,
+        };
+        Some(closest_raise.unwrap_or(Action::Call))
+    }
+
+    /// Trains only the rounds up to `last_round`, the trunk. A state reaching a later round is a
+    /// leaf valued by `leaf_evaluator`, either the equity of checking the hand down or a fixed
+    /// strategy for the later rounds played out with `RolloutEvaluator`, so the tree past the
+    /// trunk is never built. Leaf values are in chips even with ICM utilities, and
+    /// `exploitability` still measures the whole game.
+    pub fn set_trunk(&mut self, last_round: u8, mut leaf_evaluator: Box<dyn LeafEvaluator>) {
+        assert!(last_round < self.abstract_game.game_info.num_rounds(), "trunk ends after the last round");
+        if let (true, Some(seed)) = (self.config.deterministic, self.config.effective_seed()) {
+            leaf_evaluator.set_seed(seed);
+        }
+        self.trunk = Some((last_round, leaf_evaluator));
+    }
+
+    pub fn clear_trunk(&mut self) {
+        self.trunk = None;
+    }
+
+    /// Deals every iteration from `deal_distribution`, so the strategy is trained for the
+    /// players holding its ranges
+    pub fn set_deal_distribution(&mut self, deal_distribution: Option<DealDistribution>) {
+        self.deal_distribution = deal_distribution;
+    }
+
+    fn deal(&mut self) -> Deal {
+        match &self.deal_distribution {
+            Some(distribution) => distribution.deal(&self.abstract_game.game_info, &mut self.rng),
+            None => self.abstract_game.game_info.deal_hole_cards_and_board_cards(&mut self.rng),
+        }
+    }
+
+    /// Looks up the buckets of every player in every round of a deal for the traversals, spread
+    /// over the thread pool when there are enough of them to be worth it
+    pub fn deal_buckets(&self, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> DealBuckets {
+        let abstract_game = &self.abstract_game;
+        if abstract_game.game_info.has_draws() {
+            return DealBuckets(None);
+        }
+        let num_rounds = abstract_game.game_info.num_rounds() as usize;
+        let lookup = |i: usize| abstract_game.get_bucket((i % num_rounds) as u8, board_cards, &hole_cards[i / num_rounds]);
+        let num_lookups = hole_cards.len() * num_rounds;
+        let buckets = if num_lookups >= PARALLEL_BUCKET_LOOKUPS && !self.config.deterministic {
+            (0..num_lookups).into_par_iter().map(lookup).collect()
+        } else {
+            (0..num_lookups).map(lookup).collect()
+        };
+        DealBuckets(Some((buckets, num_rounds)))
+    }
+
+    /// Returns the bucket of `player` at `state`, from `buckets` if they were looked up
+    fn bucket(&self, buckets: &DealBuckets, state: &GameState, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> BucketId {
+        match &buckets.0 {
+            Some((buckets, num_rounds)) => buckets[player as usize * num_rounds + state.current_round() as usize],
+            None => self.abstract_game.get_bucket(state.current_round(), board_cards, &state.current_hole_cards(&self.abstract_game.game_info, hole_cards, board_cards)[player as usize]),
+        }
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Sets the number of the next iteration, which the intervals and thresholds of `mccfr_p`
+    /// are counted in, so a worker can run its share of iterations of a distributed run
+    pub fn set_iteration(&mut self, iteration: u32) {
+        self.iteration = iteration;
+    }
+
+    /// Restarts the random number generator deals and sampling come from with `seed`
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Returns the number of nodes visited by the traversals so far
+    pub fn nodes_touched(&self) -> u64 {
+        self.nodes_touched
+    }
+
+    /// Sets the token checked between iterations of `mccfr_p` and during `exploitability`
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Computes the exploitability of the current average strategy, only feasible for small games
+    pub fn exploitability(&mut self) -> Result<f64, Cancelled> {
+        let last_iterate;
+        let strategy = match self.config.strategy_averaging {
+            StrategyAveraging::Stored => &self.average_strategy,
+            StrategyAveraging::LastIterate => {
+                last_iterate = self.last_iterate_strategy();
+                &last_iterate
+            },
+        };
+        best_response::exploitability(&mut self.abstract_game, strategy, &self.cancel_token)
+    }
+
     pub fn print_average_strategy(&self) {
-        println!("{:?}", self.average_strategy);
+        println!("{:?}", self.average_strategy());
+    }
+
+    /// Returns the metadata the average strategy is saved with
+    pub fn strategy_metadata(&self) -> StrategyMetadata {
+        StrategyMetadata::new(&self.abstract_game, self.iteration, self.solver)
     }
 
     pub fn save_average_strategy(&self, path: &Path) {
-        let mut f = BufWriter::new(fs::File::create(path).unwrap());
-        bincode::serialize_into(&mut f, &self.average_strategy).unwrap();
+        match self.config.strategy_averaging {
+            StrategyAveraging::Stored => self.average_strategy.save(path, &self.strategy_metadata()),
+            StrategyAveraging::LastIterate => strategy::write_with_metadata(path, &self.strategy_metadata(), &LastIterateStrategy(self)),
+        }
+    }
+
+    /// Saves the average strategy with a byte per action probability, see `QuantizedStrategy`
+    pub fn save_quantized_strategy(&self, path: &Path) {
+        QuantizedStrategy::new(&self.average_strategy(), &self.abstract_game).save(path, &self.strategy_metadata());
     }
 
     pub fn save_nodes(&self, path: &Path) {
@@ -74,47 +665,264 @@ impl CFREngine {
         println!("{:?}", self.regrets);
     }
 
-    pub fn mccfr_p(&mut self, ticks: u32, strategy_interval: u32, prune_threshold: u32, lcfr_threshold: u32, discount_interval: u32) {
+    /// Returns the times the regrets of the infoset were updated
+    pub fn visits(&self, infoset: &(NodeId, BucketId)) -> u64 {
+        self.visits.get(infoset).copied().unwrap_or(0)
+    }
+
+    /// Stops updating the regrets and average strategy of the infoset. Traversals still pass
+    /// through it playing its current strategy, so the infosets after it keep training.
+    pub fn freeze(&mut self, infoset: (NodeId, BucketId)) {
+        self.frozen.insert(infoset);
+    }
+
+    /// Updates the infoset again, it has to pass two checks of `freeze_converged` to be frozen
+    /// again
+    pub fn thaw(&mut self, infoset: &(NodeId, BucketId)) {
+        self.frozen.remove(infoset);
+        self.freeze_snapshots.remove(infoset);
+    }
+
+    pub fn thaw_all(&mut self) {
+        self.frozen.clear();
+        self.freeze_snapshots.clear();
+    }
+
+    pub fn is_frozen(&self, infoset: &(NodeId, BucketId)) -> bool {
+        self.frozen.contains(infoset)
+    }
+
+    pub fn frozen_infosets(&self) -> &BTreeSet<(NodeId, BucketId)> {
+        &self.frozen
+    }
+
+    /// Freezes every infoset visited at least `min_visits` times whose current strategy moved by
+    /// at most `max_strategy_change` since the last check, returning how many were frozen. The
+    /// first check only remembers the strategies. `mccfr_p` calls it on the interval of the
+    /// conditions of the config, calling it after thawing infosets refreezes the ones that
+    /// settle again.
+    pub fn freeze_converged(&mut self, conditions: &FreezeConditions) -> usize {
+        let mut num_frozen = 0;
+        for infoset in self.regrets.infosets() {
+            if self.frozen.contains(&infoset) || self.visits(&infoset) < conditions.min_visits {
+                continue;
+            }
+            let actions = self.abstract_game.get_actions(&self.abstract_game.nodes.get_node(infoset.0).unwrap().state);
+            let sigma = CFREngine::calculate_strategy(&self.regrets.get(&infoset, &actions));
+            let current = actions.iter().map(|a| *sigma.get(a).unwrap_or(&0.)).collect::<Vec<f32>>();
+            let converged = self.freeze_snapshots.get(&infoset)
+                .is_some_and(|last| last.iter().zip(&current).all(|(l, c)| (l - c).abs() <= conditions.max_strategy_change));
+            if converged {
+                self.frozen.insert(infoset);
+                self.freeze_snapshots.remove(&infoset);
+                num_frozen += 1;
+            } else {
+                self.freeze_snapshots.insert(infoset, current);
+            }
+        }
+        debug!("Froze {} infosets, {} frozen in total", num_frozen, self.frozen.len());
+        num_frozen
+    }
+
+    /// Returns the regrets, visits and strategies of every infoset with regrets or an average
+    /// strategy, in infoset order
+    pub fn infoset_tables(&self) -> impl Iterator<Item = InfosetTable> + '_ {
+        let average_strategy = self.average_strategy();
+        let infosets = self.regrets.infosets().into_iter().chain(average_strategy.0.keys().copied()).collect::<BTreeSet<_>>();
+        infosets.into_iter().map(move |infoset| InfosetTable::new(&self.abstract_game, infoset, self.visits(&infoset), self.regrets.values(&infoset), &average_strategy))
+    }
+
+    /// Returns the regrets and average strategy of every infoset trained so far
+    pub fn training_state(&self) -> TrainingState {
+        TrainingState {
+            regrets: self.regrets.infosets().into_iter().map(|infoset| (infoset, self.regrets.values(&infoset).unwrap())).collect(),
+            average_strategy: self.average_strategy.0.clone(),
+        }
+    }
+
+    /// Overwrites the regrets and average strategy with `state`, infosets it doesn't have keep
+    /// their regrets but lose their average strategy
+    pub fn set_training_state(&mut self, state: &TrainingState) {
+        for (infoset, values) in &state.regrets {
+            self.regrets.set_values(*infoset, values);
+        }
+        self.average_strategy = Strategy(state.average_strategy.clone());
+    }
+
+    /// Runs `ticks` iterations of MCCFR with pruning. If cancelled training stops between
+    /// iterations, so it can be resumed later.
+    pub fn mccfr_p(&mut self, ticks: u32, strategy_interval: u32, prune_threshold: u32, lcfr_threshold: u32, discount_interval: u32) -> Result<(), Cancelled> {
         let num_players = self.abstract_game.game_info.num_players();
 
-        for t in 0..ticks {
-            info!("Iteration {:?}", t);
+        for _ in 0..ticks {
+            self.cancel_token.check()?;
+            let t = self.iteration;
+            self.iteration += 1;
+            self.solver = "mccfr_p";
+            enter_span!("mccfr_p", iteration = t);
+            info!("Iteration {:?}, {} nodes", t, self.abstract_game.nodes.len());
+            if t == lcfr_threshold && self.config.strategy_averaging == StrategyAveraging::LastIterate {
+                warn!("Discounting stops at iteration {}, so the last iterate no longer tracks the average strategy", t);
+            }
             for i in 0..num_players {
-                if t % strategy_interval == 0 {
-                    let (hole_cards, board_cards) = self.abstract_game.game_info.deal_hole_cards_and_board_cards();
-                    self.update_strategy(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, i);
+                if self.is_fixed_seat(i) {
+                    continue;
+                }
+                if t.is_multiple_of(strategy_interval) && self.config.strategy_averaging == StrategyAveraging::Stored {
+                    let (hole_cards, board_cards) = self.deal();
+                    let buckets = self.deal_buckets(&hole_cards, &board_cards);
+                    self.update_strategy(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, &buckets, i);
                 }
                 if t > prune_threshold {
-                    let mut rng = rand::thread_rng();
-                    if rng.gen::<f32>() < 0.05 {
-                        let (hole_cards, board_cards) = self.abstract_game.game_info.deal_hole_cards_and_board_cards();
-                        self.traverse_mccrfr(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, i);
+                    if self.rng.gen::<f32>() < 0.05 {
+                        let (hole_cards, board_cards) = self.deal();
+                        let buckets = self.deal_buckets(&hole_cards, &board_cards);
+                        self.traverse_mccrfr(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, &buckets, i);
                     } else {
-                        let (hole_cards, board_cards) = self.abstract_game.game_info.deal_hole_cards_and_board_cards();
-                        self.traverse_mccrfr_p(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, i);
+                        let (hole_cards, board_cards) = self.deal();
+                        let buckets = self.deal_buckets(&hole_cards, &board_cards);
+                        self.traverse_mccrfr_p(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, &buckets, i);
                     }
                 } else {
-                        let (hole_cards, board_cards) = self.abstract_game.game_info.deal_hole_cards_and_board_cards();
-                        self.traverse_mccrfr(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, i);
+                        let (hole_cards, board_cards) = self.deal();
+                        let buckets = self.deal_buckets(&hole_cards, &board_cards);
+                        self.traverse_mccrfr(self.abstract_game.nodes.get_root_node_id(), &board_cards, self.abstract_game.game_info.total_board_cards(0) as usize, &hole_cards, &buckets, i);
                 }
             }
 
-            if t < lcfr_threshold && t % discount_interval == 0 {
+            if t < lcfr_threshold && t.is_multiple_of(discount_interval) {
                 let d: f32 = (t as f32 / discount_interval as f32) / ((t as f32 / discount_interval as f32) + 1.);
 
                 //CHECK: this type of multiplication tends to give overflow errors!
-                for regrets in self.regrets.values_mut() {
-                    for v in regrets.values_mut() {
-                        *v = ((*v as f32) * d).round() as i32;
-                    }
-                }
+                self.regrets.scale_all(d);
                 for strategy in self.average_strategy.0.values_mut() {
                     for v in strategy.values_mut() {
                         *v = ((*v as f32) * d).round() as i32;
                     }
                 }
             }
+
+            if let Some(conditions) = self.config.freeze {
+                if t > 0 && t.is_multiple_of(conditions.check_interval) {
+                    self.freeze_converged(&conditions);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs `ticks` iterations of Pluribus style self-play, MCCFR with pruning where every player
+    /// traverses against the current strategies of all the others, for any number of players.
+    /// The average strategy is only tracked up to `rounds_update_average_strategy`, later rounds
+    /// are averaged from snapshots of the current strategy taken on the schedule, which is much
+    /// cheaper once the tree is large.
+    pub fn pluribus(&mut self, ticks: u32, schedule: &PluribusSchedule) -> Result<(), Cancelled> {
+        for _ in 0..ticks {
+            self.mccfr_p(1, schedule.strategy_interval, schedule.prune_threshold, schedule.lcfr_threshold, schedule.discount_interval)?;
+            self.solver = "pluribus";
+            let t = self.iteration;
+            if t >= schedule.snapshot_start && (t - schedule.snapshot_start).is_multiple_of(schedule.snapshot_interval) {
+                self.snapshot_current_strategy();
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the current strategy of every infoset past `rounds_update_average_strategy` to the
+    /// average strategy, every snapshot weighing the same
+    pub fn snapshot_current_strategy(&mut self) {
+        if self.config.strategy_averaging != StrategyAveraging::Stored {
+            return;
+        }
+        debug!("Snapshotting the current strategy at iteration {}", self.iteration);
+        for (infoset, regrets) in self.regrets.to_strategy(&self.abstract_game).0 {
+            let round = self.abstract_game.nodes.get_node(infoset.0).unwrap().state.current_round();
+            if round <= self.config.rounds_update_average_strategy {
+                continue;
+            }
+            let counts = self.average_strategy.0.entry(infoset).or_default();
+            for (action, p) in CFREngine::calculate_strategy(&regrets) {
+                *counts.entry(action).or_insert(0) += (p * SNAPSHOT_WEIGHT).round() as i32;
+            }
+        }
+    }
+
+    /// Runs `ticks` iterations of CFR-BR, where each player updates its regrets against an
+    /// opponent best responding to its current strategy. The best responses are recomputed every
+    /// `best_response_interval` iterations by walking the whole tree, so only small two player
+    /// games are feasible.
+    pub fn cfr_br(&mut self, ticks: u32, strategy_interval: u32, best_response_interval: u32) -> Result<(), Cancelled> {
+        assert!(self.abstract_game.game_info.num_players() == 2, "CFR-BR needs a two player game");
+        let result = (0..ticks).try_for_each(|_| self.cfr_br_iteration(strategy_interval, best_response_interval));
+        self.best_response = None;
+        result
+    }
+
+    fn cfr_br_iteration(&mut self, strategy_interval: u32, best_response_interval: u32) -> Result<(), Cancelled> {
+        self.cancel_token.check()?;
+        let t = self.iteration;
+        self.iteration += 1;
+        self.solver = "cfr_br";
+        enter_span!("cfr_br", iteration = t);
+        info!("CFR-BR iteration {:?}, {} nodes", t, self.abstract_game.nodes.len());
+
+        if self.best_response.is_none() || t.is_multiple_of(best_response_interval) {
+            // Regrets give the current strategy through regret matching
+            let current_strategy = self.regrets.to_strategy(&self.abstract_game);
+            let mut best_response = Strategy::new();
+            for i in 0..self.abstract_game.game_info.num_players() {
+                let response = best_response::best_response_strategy(&mut self.abstract_game, &current_strategy, i, &self.cancel_token)?;
+                best_response.0.extend(response.0);
+            }
+            self.best_response = Some(best_response);
+        }
+
+        for i in 0..self.abstract_game.game_info.num_players() {
+            if self.is_fixed_seat(i) {
+                continue;
+            }
+            let root = self.abstract_game.nodes.get_root_node_id();
+            let board_cards_i = self.abstract_game.game_info.total_board_cards(0) as usize;
+            if t.is_multiple_of(strategy_interval) && self.config.strategy_averaging == StrategyAveraging::Stored {
+                let (hole_cards, board_cards) = self.deal();
+                let buckets = self.deal_buckets(&hole_cards, &board_cards);
+                self.update_strategy(root, &board_cards, board_cards_i, &hole_cards, &buckets, i);
+            }
+            let (hole_cards, board_cards) = self.deal();
+            let buckets = self.deal_buckets(&hole_cards, &board_cards);
+            self.traverse_mccrfr(root, &board_cards, board_cards_i, &hole_cards, &buckets, i);
         }
+
+        Ok(())
+    }
+
+    /// Applies the regret decay of `round` to the regrets of an infoset once for every iteration
+    /// since they were last decayed, frozen infosets catch up once they are thawed
+    fn decay_regrets(&mut self, node_id: NodeId, bucket_id: BucketId, round: u8) {
+        let decay = match self.config.regret_decay.get(round as usize) {
+            Some(d) if *d < 1. && !self.frozen.contains(&(node_id, bucket_id)) => *d,
+            _ => return,
+        };
+
+        let last = self.regrets_decayed_at.insert((node_id, bucket_id), self.iteration).unwrap_or(self.iteration);
+        self.regrets.scale(&(node_id, bucket_id), decay.powi((self.iteration - last) as i32));
+    }
+
+    /// Returns the probability of exploring each of `actions` at an infoset of the traversing
+    /// player, see `Sampling`
+    fn explore_probabilities(&self, node_id: NodeId, bucket_id: BucketId, actions: &[Action]) -> Vec<f32> {
+        let (epsilon, tau, beta) = match self.config.sampling {
+            Sampling::External => return vec![1.; actions.len()],
+            Sampling::AverageStrategy { epsilon, tau, beta } => (epsilon, tau, beta),
+        };
+
+        let counts = self.average_strategy.0.get(&(node_id, bucket_id));
+        let count = |a: &Action| counts.and_then(|c| c.get(a)).map_or(0., |c| max(*c, 0) as f32);
+        let total = actions.iter().map(count).sum::<f32>();
+        actions.iter()
+            .map(|a| ((beta + tau * count(a)) / (beta + total)).clamp(epsilon, 1.))
+            .collect()
     }
 
     pub fn calculate_strategy(regrets: &BTreeMap<Action, i32>) -> BTreeMap<Action, f32> {
@@ -138,195 +946,279 @@ impl CFREngine {
         strategy
     }
 
-    fn sample_strategy(sigma: &BTreeMap<Action, f32>) -> Action {
-        let mut rng = rand::thread_rng();
-        *sigma.iter().collect::<Vec<(&Action, &f32)>>().choose_weighted(&mut rng, |item| item.1).unwrap().0
+    /// Returns the value of a terminal node or a node `player` folded in, scaled by `payout_amp`
+    fn payout(&self, node: &Node, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> i32 {
+        let game_info = &self.abstract_game.game_info;
+        let state = &node.state;
+        match (&self.config.icm, node.side_pots()) {
+            (Some(icm), _) => (icm.state_value(game_info, self.evaluator, state, board_cards, hole_cards, player) * self.config.payout_amp as f64).round() as i32,
+            (None, Some(side_pots)) => state.get_side_pot_payouts(game_info, side_pots, self.evaluator, &[board_cards], hole_cards)[player as usize] * self.config.payout_amp,
+            (None, _) => state.get_payout(game_info, self.evaluator, board_cards, hole_cards, player) * self.config.payout_amp,
+        }
+    }
+
+    fn is_trunk_leaf(&self, state: &GameState) -> bool {
+        matches!(self.trunk, Some((last_round, _)) if !state.is_finished() && state.current_round() > last_round)
+    }
+
+    /// Returns the value `player` gets from a state past the trunk, scaled by `payout_amp`
+    fn leaf_payout(&mut self, state: &GameState, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> i32 {
+        let (_, leaf_evaluator) = self.trunk.as_mut().unwrap();
+        let values = leaf_evaluator.evaluate(&self.abstract_game.game_info, state, hole_cards, board_cards);
+        (values[player as usize] * self.config.payout_amp as f64).round() as i32
+    }
+
+    fn sample_strategy(rng: &mut StdRng, sigma: &BTreeMap<Action, f32>) -> Action {
+        *sigma.iter().collect::<Vec<(&Action, &f32)>>().choose_weighted(rng, |item| item.1).unwrap().0
     }
 
-    pub fn update_strategy(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>; MAX_PLAYERS], player: PlayerId) {
+    pub fn update_strategy(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>], buckets: &DealBuckets, player: PlayerId) {
+        self.nodes_touched += 1;
         let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
         debug!("Updating strategy of node {node_id}");
 
         // CHECK: Doesn't generate average strategy past first betting round
-        if current_node.state.is_finished() || current_node.state.has_folded(player) || current_node.state.current_round() > self.config.rounds_update_average_strategy {
+        if current_node.state.is_finished() || current_node.state.has_folded(player) || current_node.state.current_round() > self.config.rounds_update_average_strategy || self.is_trunk_leaf(&current_node.state) {
             return;
+        } else if let Some(action) = self.locked_nodes.get(&node_id).map(|sigma| CFREngine::sample_strategy(&mut self.rng, sigma)) {
+            let mut child_board_cards_i = board_cards_i;
+            let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+            self.update_strategy(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
         } else if current_node.state.current_player().unwrap() == player {
-            let bucket_id = self.abstract_game.get_bucket(current_node.state.current_round(), &board_cards, &hole_cards[player as usize]);
-            let regrets = self.regrets.entry((node_id, bucket_id))
-                .or_insert_with(|| {
-                    let mut regrets_map: BTreeMap<Action, i32> = BTreeMap::new();
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        regrets_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    regrets_map
-                });
-            let sigma = CFREngine::calculate_strategy(regrets);
-            let action = CFREngine::sample_strategy(&sigma);
+            let bucket_id = self.bucket(buckets, &current_node.state, board_cards, hole_cards, player);
+            let round = current_node.state.current_round();
+            self.decay_regrets(node_id, bucket_id, round);
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
+            let regrets = self.regrets.get(&(node_id, bucket_id), &self.abstract_game.get_actions(&current_node.state));
+            let sigma = CFREngine::calculate_strategy(&regrets);
+            let action = CFREngine::sample_strategy(&mut self.rng, &sigma);
 
             // Add one to action counter
-            self.average_strategy.0.entry((node_id, bucket_id))
-                .and_modify(|s| { let _ = *s.entry(action).and_modify(|x| *x += 1).or_insert(0); })
-                .or_insert_with(|| {
-                    let mut action_map: BTreeMap<Action, i32> = BTreeMap::new();
+            if !self.frozen.contains(&(node_id, bucket_id)) {
+                self.average_strategy.0.entry((node_id, bucket_id))
+                    .and_modify(|s| { let _ = *s.entry(action).and_modify(|x| *x += 1).or_insert(0); })
+                    .or_insert_with(|| {
+                        let mut action_map: BTreeMap<Action, i32> = BTreeMap::new();
 
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        action_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    action_map.insert(action, 1);
-                    action_map
-                });
+                        for a in self.abstract_game.get_actions(&current_node.state) {
+                            action_map.insert(a, 0); // inserts with uniform distribution
+                        }
+                        action_map.insert(action, 1);
+                        action_map
+                    });
+            }
 
             let mut child_board_cards_i = board_cards_i;
             let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
-            self.update_strategy(child_node_id, board_cards, child_board_cards_i, hole_cards, player);
+            self.update_strategy(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
 
         } else {
             let actions = self.abstract_game.get_actions(&current_node.state);
             for action in actions {
                 let mut child_board_cards_i = board_cards_i;
                 let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
-                self.update_strategy(child_node_id, board_cards, child_board_cards_i, hole_cards, player);
+                self.update_strategy(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
             }
         }
 
     }
 
-    pub fn traverse_mccrfr(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>; MAX_PLAYERS], player: PlayerId) -> i32 {
+    pub fn traverse_mccrfr(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>], buckets: &DealBuckets, player: PlayerId) -> i32 {
+        self.nodes_touched += 1;
         let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
 
         debug!("traverse_mccfr at node {node_id}");
 
         if current_node.state.is_finished() {
-            return current_node.state.get_payout(&self.abstract_game.game_info, &self.evaluator, &board_cards, &hole_cards, player) * self.config.payout_amp;
+            return self.payout(current_node, board_cards, hole_cards, player);
         } else if current_node.state.has_folded(player) {
             //CHECK: this is what they do in paper return traverse_mccfr(h*0, P_i), but I think
             //this makes more sense
-            return current_node.state.get_payout(&self.abstract_game.game_info, &self.evaluator, &board_cards, &hole_cards, player) * self.config.payout_amp;
+            return self.payout(current_node, board_cards, hole_cards, player);
+        } else if self.is_trunk_leaf(&current_node.state) {
+            let state = current_node.state.clone();
+            self.leaf_payout(&state, board_cards, hole_cards, player)
+        } else if let Some(action) = self.locked_nodes.get(&node_id).map(|sigma| CFREngine::sample_strategy(&mut self.rng, sigma)) {
+            let mut child_board_cards_i = board_cards_i;
+            let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+            self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player)
         } else if current_node.state.current_player().unwrap() == player {
-            let bucket_id = self.abstract_game.get_bucket(current_node.state.current_round(), &board_cards, &hole_cards[player as usize]);
-            let regrets = self.regrets.entry((node_id, bucket_id))
-                .or_insert_with(|| {
-                    let mut regrets_map: BTreeMap<Action, i32> = BTreeMap::new();
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        regrets_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    regrets_map
-                });
-            let sigma = CFREngine::calculate_strategy(regrets);
+            let bucket_id = self.bucket(buckets, &current_node.state, board_cards, hole_cards, player);
+            let round = current_node.state.current_round();
+            self.decay_regrets(node_id, bucket_id, round);
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
+            let regrets = self.regrets.get(&(node_id, bucket_id), &self.abstract_game.get_actions(&current_node.state));
+            let sigma = CFREngine::calculate_strategy(&regrets);
 
             let mut v = 0.;
             let mut value_map: BTreeMap<Action, i32> = BTreeMap::new();
 
             let actions = self.abstract_game.get_actions(&current_node.state);
-            for action in &actions {
+            let explore = self.explore_probabilities(node_id, bucket_id, &actions);
+            for (action, q) in actions.iter().zip(explore) {
+                // Unexplored actions have a value of zero, explored ones are weighted up so the
+                // estimate stays unbiased
+                if q < 1. && self.rng.gen::<f32>() >= q {
+                    continue;
+                }
                 let mut child_board_cards_i = board_cards_i;
                 let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, *action);
-                value_map.insert(*action, self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, player));
+                let value = self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+                value_map.insert(*action, (value as f32 / q).round() as i32);
                 v += *sigma.get(action).unwrap_or(&0.) * (*value_map.get(action).unwrap() as f32);
             }
             let v = v.round() as i32;
 
-            self.regrets.entry((node_id, bucket_id))
-                .and_modify(|r| {
-                    for action in actions {
-                        r.entry(action).and_modify(|regret| {
-                            *regret += *value_map.get(&action).unwrap_or(&0) - v;
-                        });
-                    }
-                });
+            if !self.frozen.contains(&(node_id, bucket_id)) {
+                self.regrets.add((node_id, bucket_id), &actions, |action| Some(*value_map.get(&action).unwrap_or(&0) - v));
+                *self.visits.entry((node_id, bucket_id)).or_insert(0) += 1;
+            }
 
             return v;
         } else {
-            let bucket_id = self.abstract_game.get_bucket(current_node.state.current_round(), &board_cards, &hole_cards[player as usize]);
+            if let Some(action) = self.fixed_seat_action(node_id, board_cards, hole_cards) {
+                let mut child_board_cards_i = board_cards_i;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+                return self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+            }
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
+            let bucket_id = self.bucket(buckets, &current_node.state, board_cards, hole_cards, current_node.state.current_player().unwrap());
+            if let Some(action) = self.best_response.as_ref().map(|s| s.sample(&self.abstract_game, node_id, bucket_id, &mut self.rng)) {
+                let mut child_board_cards_i = board_cards_i;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+                return self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+            }
+            let round = current_node.state.current_round();
+            self.decay_regrets(node_id, bucket_id, round);
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
 
-            let regrets = self.regrets.entry((node_id, bucket_id))
-                .or_insert_with(|| {
-                    let mut regrets_map: BTreeMap<Action, i32> = BTreeMap::new();
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        regrets_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    regrets_map
-                });
-            let sigma = CFREngine::calculate_strategy(regrets);
-            let action = CFREngine::sample_strategy(&sigma);
+            let regrets = self.regrets.get(&(node_id, bucket_id), &self.abstract_game.get_actions(&current_node.state));
+            let sigma = CFREngine::calculate_strategy(&regrets);
+            let action = CFREngine::sample_strategy(&mut self.rng, &sigma);
 
             let mut child_board_cards_i = board_cards_i;
             let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
-            return self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, player);
+            return self.traverse_mccrfr(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
         }
     }
 
 
-    pub fn traverse_mccrfr_p(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>; MAX_PLAYERS], player: PlayerId) -> i32 {
+    pub fn traverse_mccrfr_p(&mut self, node_id: NodeId, board_cards: &Vec<Card>, board_cards_i: usize, hole_cards: &[Vec<Card>], buckets: &DealBuckets, player: PlayerId) -> i32 {
+        self.nodes_touched += 1;
         let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
 
         debug!("traverse_mccfr_p at node {node_id}");
 
         if current_node.state.is_finished() {
-            return current_node.state.get_payout(&self.abstract_game.game_info, &self.evaluator, &board_cards, &hole_cards, player) * self.config.payout_amp;
+            return self.payout(current_node, board_cards, hole_cards, player);
         } else if current_node.state.has_folded(player) {
             //CHECK: this is what they do in paper return traverse_mccfr(h*0, P_i), but I think
             //this makes more sense
-            return current_node.state.get_payout(&self.abstract_game.game_info, &self.evaluator, &board_cards, &hole_cards, player) * self.config.payout_amp;
+            return self.payout(current_node, board_cards, hole_cards, player);
+        } else if self.is_trunk_leaf(&current_node.state) {
+            let state = current_node.state.clone();
+            self.leaf_payout(&state, board_cards, hole_cards, player)
+        } else if let Some(action) = self.locked_nodes.get(&node_id).map(|sigma| CFREngine::sample_strategy(&mut self.rng, sigma)) {
+            let mut child_board_cards_i = board_cards_i;
+            let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+            self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player)
         } else if current_node.state.current_player().unwrap() == player {
-            let bucket_id = self.abstract_game.get_bucket(current_node.state.current_round(), &board_cards, &hole_cards[player as usize]);
-            let regrets = self.regrets.entry((node_id, bucket_id))
-                .or_insert_with(|| {
-                    let mut regrets_map: BTreeMap<Action, i32> = BTreeMap::new();
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        regrets_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    regrets_map
-                });
-            let sigma = CFREngine::calculate_strategy(regrets);
+            let bucket_id = self.bucket(buckets, &current_node.state, board_cards, hole_cards, player);
+            let round = current_node.state.current_round();
+            self.decay_regrets(node_id, bucket_id, round);
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
+            let regrets = self.regrets.get(&(node_id, bucket_id), &self.abstract_game.get_actions(&current_node.state));
+            let sigma = CFREngine::calculate_strategy(&regrets);
 
             let mut v = 0.;
             let mut value_map: BTreeMap<Action, i32> = BTreeMap::new();
 
-            // CHECK: way to do this without clone and the borrow rules working
-            let regrets = regrets.clone();
-
             let actions = self.abstract_game.get_actions(&current_node.state);
-            for action in &actions {
+            let explore = self.explore_probabilities(node_id, bucket_id, &actions);
+            for (action, q) in actions.iter().zip(explore) {
                 if *regrets.get(action).unwrap_or(&0) > -300000000 {
+                    if q < 1. && self.rng.gen::<f32>() >= q {
+                        value_map.insert(*action, 0);
+                        continue;
+                    }
                     let mut child_board_cards_i = board_cards_i;
                     let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, *action);
-                    value_map.insert(*action, self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, player));
+                    let value = self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+                    value_map.insert(*action, (value as f32 / q).round() as i32);
                     v += *sigma.get(action).unwrap_or(&0.) * (*value_map.get(action).unwrap() as f32);
                 }
             }
 
             let v = v.round() as i32;
 
-            self.regrets.entry((node_id, bucket_id))
-                .and_modify(|r| {
-                    for action in actions {
-                        r.entry(action).and_modify(|regret| {
-                            if let Some(x) = value_map.get(&action) {
-                                *regret += x - v;
-                            }
-                        });
-                    }
-                });
+            if !self.frozen.contains(&(node_id, bucket_id)) {
+                self.regrets.add((node_id, bucket_id), &actions, |action| value_map.get(&action).map(|x| x - v));
+                *self.visits.entry((node_id, bucket_id)).or_insert(0) += 1;
+            }
             
             return v;
         } else {
-            let bucket_id = self.abstract_game.get_bucket(current_node.state.current_round(), &board_cards, &hole_cards[player as usize]);
+            if let Some(action) = self.fixed_seat_action(node_id, board_cards, hole_cards) {
+                let mut child_board_cards_i = board_cards_i;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+                return self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+            }
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
+            let bucket_id = self.bucket(buckets, &current_node.state, board_cards, hole_cards, current_node.state.current_player().unwrap());
+            if let Some(action) = self.best_response.as_ref().map(|s| s.sample(&self.abstract_game, node_id, bucket_id, &mut self.rng)) {
+                let mut child_board_cards_i = board_cards_i;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
+                return self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+            }
+            let round = current_node.state.current_round();
+            self.decay_regrets(node_id, bucket_id, round);
+            let current_node = self.abstract_game.nodes.get_node(node_id).unwrap();
 
-            let regrets = self.regrets.entry((node_id, bucket_id))
-                .or_insert_with(|| {
-                    let mut regrets_map: BTreeMap<Action, i32> = BTreeMap::new();
-                    for a in self.abstract_game.get_actions(&current_node.state) {
-                        regrets_map.insert(a, 0); // inserts with uniform distribution
-                    }
-                    regrets_map
-                });
-            let sigma = CFREngine::calculate_strategy(regrets);
-            let action = CFREngine::sample_strategy(&sigma);
+            let regrets = self.regrets.get(&(node_id, bucket_id), &self.abstract_game.get_actions(&current_node.state));
+            let sigma = CFREngine::calculate_strategy(&regrets);
+            let action = CFREngine::sample_strategy(&mut self.rng, &sigma);
 
             let mut child_board_cards_i = board_cards_i;
             let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut child_board_cards_i, action);
-            return self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, player);
+            return self.traverse_mccrfr_p(child_node_id, board_cards, child_board_cards_i, hole_cards, buckets, player);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action_abstraction::ActionAbstraction,
+        best_response::best_response_value,
+        card_abstraction::CardAbstraction,
+        expected_value::expected_values,
+        game::GameInfo,
+    };
+
+    #[test]
+    fn pluribus_converges_on_three_player_kuhn() {
+        const ITERATIONS: u32 = 10000;
+        const TOLERANCE: f64 = 0.05;
+
+        let configs = Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+        let game_info = GameInfo::load_game_info(&configs.join("kuhn_3p.json")).unwrap();
+        let starting_state = GameState::new(&game_info, 0);
+        let action_abstraction = ActionAbstraction::from_config(&configs.join("kuhn_action_abstraction.json")).unwrap();
+        let card_abstraction = CardAbstraction::from_config(&configs.join("kuhn_3p_card_abstraction.json")).unwrap();
+        let mut cfr_config = CFRConfig::from_config(&configs.join("kuhn_cfr_config.json"));
+        cfr_config.set_seed(Some(0));
+
+        let abstract_game = AbstractGame::new(game_info, starting_state, action_abstraction, card_abstraction);
+        let mut cfr_engine = CFREngine::new(abstract_game, cfr_config);
+        cfr_engine.pluribus(ITERATIONS, &PluribusSchedule::scaled(ITERATIONS)).unwrap();
+
+        let (mut abstract_game, strategy) = cfr_engine.into_parts();
+        let values = expected_values(&mut abstract_game, &strategy, &CancelToken::new()).unwrap();
+        for player in 0..abstract_game.game_info.num_players() {
+            let best_response = best_response_value(&mut abstract_game, &strategy, player, &CancelToken::new()).unwrap();
+            let gain = best_response - values[player as usize];
+            assert!(gain < TOLERANCE, "player {} gains {} chips/hand by deviating", player, gain);
         }
     }
 }
@@ -0,0 +1,193 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    parallel::*,
+};
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Distance between two points of a clustering
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Distance {
+    /// Squared euclidean distance
+    #[default]
+    L2,
+    /// Earth mover's distance between histograms whose bins are ordered, like distributions over
+    /// buckets sorted by strength, which is the L1 distance between their cumulative sums.
+    /// Centroids are still the mean of their points.
+    EarthMovers,
+}
+
+impl Distance {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Distance::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+            Distance::EarthMovers => {
+                let mut carried = 0.;
+                let mut total = 0.;
+                for (x, y) in a.iter().zip(b) {
+                    carried += x - y;
+                    total += f32::abs(carried);
+                }
+                total
+            },
+        }
+    }
+
+    /// Returns the index of the centroid closest to `point`
+    pub fn nearest(&self, point: &[f32], centroids: &[Vec<f32>]) -> usize {
+        (0..centroids.len())
+            .min_by(|a, b| self.distance(point, &centroids[*a]).total_cmp(&self.distance(point, &centroids[*b])))
+            .unwrap()
+    }
+
+    /// Weight of a point when seeding k-means++, proportional to the squared distance to the
+    /// nearest centroid
+    fn seeding_weight(&self, distance: f32) -> f32 {
+        match self {
+            Distance::L2 => distance,
+            Distance::EarthMovers => distance * distance,
+        }
+    }
+}
+
+/// Lloyd's k-means with k-means++ seeding. Points are assigned to centroids in parallel. In
+/// mini-batch mode every iteration moves the centroids towards a random sample of the points
+/// instead of recomputing them from every point, which trades some accuracy for speed on large
+/// sets of points.
+#[derive(Clone, Debug)]
+pub struct KMeans {
+    k: usize,
+    distance: Distance,
+    max_iterations: usize,
+    /// Points sampled every iteration in mini-batch mode
+    batch_size: Option<usize>,
+}
+
+impl KMeans {
+    pub fn new(k: usize) -> KMeans {
+        assert!(k > 0);
+        KMeans {
+            k,
+            distance: Distance::L2,
+            max_iterations: 100,
+            batch_size: None,
+        }
+    }
+
+    pub fn set_distance(&mut self, distance: Distance) {
+        self.distance = distance;
+    }
+
+    /// Sets the iterations after which clustering stops even if assignments still change, in
+    /// mini-batch mode every iteration is run
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Switches to mini-batch mode with `batch_size` points per iteration
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        assert!(batch_size > 0);
+        self.batch_size = Some(batch_size);
+    }
+
+    /// Returns the centroids and the cluster of each point. Fewer than `k` centroids are
+    /// returned if there are fewer distinct points.
+    pub fn fit<R: Rng + ?Sized>(&self, points: &[Vec<f32>], rng: &mut R, cancel_token: &CancelToken) -> Result<(Vec<Vec<f32>>, Vec<usize>), Cancelled> {
+        assert!(!points.is_empty());
+        let mut centroids = self.seed_centroids(points, rng, cancel_token)?;
+
+        match self.batch_size {
+            Some(batch_size) => {
+                self.fit_mini_batch(points, &mut centroids, batch_size, rng, cancel_token)?;
+                let assignments = self.assign(points, &centroids);
+                Ok((centroids, assignments))
+            },
+            None => {
+                let assignments = self.fit_lloyd(points, &mut centroids, cancel_token)?;
+                Ok((centroids, assignments))
+            },
+        }
+    }
+
+    fn assign(&self, points: &[Vec<f32>], centroids: &[Vec<f32>]) -> Vec<usize> {
+        points.par_iter()
+            .map(|p| self.distance.nearest(p, centroids))
+            .collect()
+    }
+
+    /// Picks each centroid with probability proportional to the seeding weight of the points
+    fn seed_centroids<R: Rng + ?Sized>(&self, points: &[Vec<f32>], rng: &mut R, cancel_token: &CancelToken) -> Result<Vec<Vec<f32>>, Cancelled> {
+        let mut centroids = vec![points.choose(rng).unwrap().clone()];
+        while centroids.len() < self.k {
+            cancel_token.check()?;
+            let weights = points.par_iter()
+                .map(|p| self.distance.seeding_weight(self.distance.distance(p, &centroids[self.distance.nearest(p, &centroids)])))
+                .collect::<Vec<f32>>();
+            match WeightedIndex::new(&weights) {
+                Ok(dist) => centroids.push(points[dist.sample(rng)].clone()),
+                // Every point is already a centroid
+                Err(_) => break,
+            }
+        }
+        Ok(centroids)
+    }
+
+    fn fit_lloyd(&self, points: &[Vec<f32>], centroids: &mut [Vec<f32>], cancel_token: &CancelToken) -> Result<Vec<usize>, Cancelled> {
+        let mut assignments = vec![usize::MAX; points.len()];
+        for _ in 0..self.max_iterations {
+            cancel_token.check()?;
+            let new_assignments = self.assign(points, centroids);
+            if new_assignments == assignments {
+                break;
+            }
+            assignments = new_assignments;
+
+            let mut sums = vec![vec![0.; points[0].len()]; centroids.len()];
+            let mut counts = vec![0; centroids.len()];
+            for (p, &c) in points.iter().zip(assignments.iter()) {
+                for (s, x) in sums[c].iter_mut().zip(p) {
+                    *s += x;
+                }
+                counts[c] += 1;
+            }
+            // Empty clusters keep their previous centroid
+            for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+                if count > 0 {
+                    *centroid = sum.into_iter().map(|s| s / count as f32).collect();
+                }
+            }
+        }
+
+        Ok(assignments)
+    }
+
+    /// Mini-batch k-means, each centroid moves towards its points with a step of one over the
+    /// number of points it was assigned so far so it ends up at their running mean
+    fn fit_mini_batch<R: Rng + ?Sized>(&self, points: &[Vec<f32>], centroids: &mut [Vec<f32>], batch_size: usize, rng: &mut R, cancel_token: &CancelToken) -> Result<(), Cancelled> {
+        let mut counts = vec![0u64; centroids.len()];
+        for _ in 0..self.max_iterations {
+            cancel_token.check()?;
+            let batch = (0..batch_size).map(|_| &points[rng.gen_range(0..points.len())]).collect::<Vec<&Vec<f32>>>();
+            let assignments = batch.par_iter()
+                .map(|p| self.distance.nearest(p, centroids))
+                .collect::<Vec<usize>>();
+
+            for (p, c) in batch.into_iter().zip(assignments) {
+                counts[c] += 1;
+                let step = 1. / counts[c] as f32;
+                for (x, y) in centroids[c].iter_mut().zip(p) {
+                    *x += step * (y - *x);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// k-means with the default settings and squared euclidean distance, see `KMeans::fit`
+pub fn kmeans<R: Rng + ?Sized>(points: &[Vec<f32>], k: usize, rng: &mut R, cancel_token: &CancelToken) -> Result<(Vec<Vec<f32>>, Vec<usize>), Cancelled> {
+    KMeans::new(k).fit(points, rng, cancel_token)
+}
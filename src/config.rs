@@ -0,0 +1,121 @@
+// Reading the JSON configs of games and abstractions. Errors say which file, which field and
+// what rule it broke, with the line and column when the JSON itself is malformed, instead of
+// panicking halfway through loading.
+
+use serde::de::DeserializeOwned;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Error returned when a config can't be read or describes an invalid game or abstraction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    path: Option<PathBuf>,
+    /// Path of the field at fault like `blinds` or `round_infosets[1].num_buckets`
+    field: Option<String>,
+    /// Line and column of the error in the JSON, both from 1
+    location: Option<(usize, usize)>,
+    message: String,
+}
+
+impl ConfigError {
+    /// Returns an error about `field` violating the rule described by `message`
+    pub fn invalid(field: &str, message: impl Into<String>) -> ConfigError {
+        ConfigError {
+            path: None,
+            field: Some(field.to_string()),
+            location: None,
+            message: message.into(),
+        }
+    }
+
+    /// Sets the file the config was read from
+    pub fn with_path(mut self, path: &Path) -> ConfigError {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        self.field.as_deref()
+    }
+
+    pub fn location(&self) -> Option<(usize, usize)> {
+        self.location
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}", path.display())?;
+            if let Some((line, column)) = self.location {
+                write!(f, ":{}:{}", line, column)?;
+            }
+            write!(f, ": ")?;
+        } else if let Some((line, column)) = self.location {
+            write!(f, "line {} column {}: ", line, column)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Returns an error about `field` unless `condition` holds
+pub(crate) fn check(condition: bool, field: &str, message: impl Into<String>) -> Result<(), ConfigError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(ConfigError::invalid(field, message))
+    }
+}
+
+/// Reads the config at `path`, see `from_json`
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    read_to_string(path).and_then(|json| from_json(&json).map_err(|e| e.with_path(path)))
+}
+
+/// Reads the text of the config at `path`
+pub fn read_to_string(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|e| ConfigError {
+        path: Some(path.to_path_buf()),
+        field: None,
+        location: None,
+        message: format!("failed to read config: {}", e),
+    })
+}
+
+/// Deserializes a config from JSON, errors name the field that failed to deserialize
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, ConfigError> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        let field = e.path().to_string();
+        json_error((field != ".").then_some(field), e.into_inner())
+    })?;
+    deserializer.end().map_err(|e| json_error(None, e))?;
+    Ok(value)
+}
+
+fn json_error(field: Option<String>, error: serde_json::Error) -> ConfigError {
+    let location = (error.line() > 0).then(|| (error.line(), error.column()));
+    // serde_json ends its messages with the location, which is reported on its own
+    let message = error.to_string();
+    let message = match location {
+        Some((line, column)) => message.strip_suffix(&format!(" at line {} column {}", line, column)).unwrap_or(&message).to_string(),
+        None => message,
+    };
+    ConfigError { path: None, field, location, message }
+}
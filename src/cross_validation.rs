@@ -0,0 +1,430 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    agent::{Agent, StrategyAgent},
+    best_response,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::CardAbstraction,
+    cfr::{CFRConfig, CFREngine},
+    dealer::Dealer,
+    duplicate::DuplicateMatch,
+    game::{GameInfo, GameState},
+    strategy::Strategy,
+};
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Arguments passed to `CFREngine::mccfr_p` when training each abstraction
+#[derive(Clone, Debug)]
+pub struct TrainingBudget {
+    pub iterations: u32,
+    pub strategy_interval: u32,
+    pub prune_threshold: u32,
+    pub lcfr_threshold: u32,
+    pub discount_interval: u32,
+}
+
+impl TrainingBudget {
+    /// Budget of `iterations` with the schedule used by the `train` command
+    pub fn new(iterations: u32) -> TrainingBudget {
+        TrainingBudget {
+            iterations,
+            strategy_interval: 20,
+            prune_threshold: 400,
+            lcfr_threshold: 100000,
+            discount_interval: 2500,
+        }
+    }
+}
+
+/// Exploitability and head-to-head results of every abstraction in a cross validation
+#[derive(Clone, Debug)]
+pub struct CrossValidationReport {
+    pub names: Vec<String>,
+    /// Exploitability of each strategy in chips per hand against a best responder that sees its
+    /// exact cards, `None` if it was not computed
+    pub exploitability: Vec<Option<f64>>,
+    /// `head_to_head[i][j]` is the average chips per hand the strategy of abstraction i won
+    /// against the strategy of abstraction j, playing every seat equally often
+    pub head_to_head: Vec<Vec<f64>>,
+}
+
+impl fmt::Display for CrossValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0).max(10);
+
+        write!(f, "{:width$} {:>14}", "", "exploitability")?;
+        for name in &self.names {
+            write!(f, " {:>width$}", name)?;
+        }
+        writeln!(f)?;
+
+        for (i, name) in self.names.iter().enumerate() {
+            match self.exploitability[i] {
+                Some(e) => write!(f, "{:width$} {:>14.4}", name, e)?,
+                None => write!(f, "{:width$} {:>14}", name, "-")?,
+            }
+            for ev in &self.head_to_head[i] {
+                write!(f, " {:>width$.4}", ev)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Trains a strategy for each of several card abstractions of the same game with the same
+/// budget, then compares their exploitability and plays them against each other
+pub struct CrossValidation {
+    game_info: GameInfo,
+    action_abstraction: ActionAbstraction,
+    cfr_config: CFRConfig,
+    budget: TrainingBudget,
+    candidates: Vec<(String, CardAbstraction)>,
+    /// Hands played in each seat arrangement of every pairing
+    num_hands: u32,
+    compute_exploitability: bool,
+    cancel_token: CancelToken,
+    /// Seeds training, the agents and the dealer when set so runs can be reproduced
+    seed: Option<u64>,
+}
+
+impl CrossValidation {
+    pub fn new(game_info: GameInfo, action_abstraction: ActionAbstraction, cfr_config: CFRConfig, budget: TrainingBudget, num_hands: u32) -> CrossValidation {
+        assert!(game_info.num_players() == 2, "cross validation is only supported for heads up games");
+        CrossValidation {
+            game_info,
+            action_abstraction,
+            cfr_config,
+            budget,
+            candidates: Vec::new(),
+            num_hands,
+            compute_exploitability: true,
+            cancel_token: CancelToken::new(),
+            seed: None,
+        }
+    }
+
+    pub fn add_card_abstraction(&mut self, name: &str, card_abstraction: CardAbstraction) {
+        self.candidates.push((name.to_string(), card_abstraction));
+    }
+
+    /// Exploitability is only feasible for small games, disable it for anything bigger than Leduc
+    pub fn set_compute_exploitability(&mut self, compute_exploitability: bool) {
+        self.compute_exploitability = compute_exploitability;
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Trains every abstraction and plays each pair of strategies against each other
+    pub fn run(self) -> Result<CrossValidationReport, Cancelled> {
+        let mut names = Vec::new();
+        let mut exploitability = Vec::new();
+        let mut agents = Vec::new();
+
+        for (i, (name, card_abstraction)) in self.candidates.into_iter().enumerate() {
+            info!("Training {}", name);
+            // Each candidate gets its own seed so their training isn't correlated
+            let seed = self.seed.map(|s| s.wrapping_add(i as u64));
+            let abstract_game = AbstractGame::new(self.game_info.clone(), GameState::new(&self.game_info, 0), self.action_abstraction.clone(), card_abstraction);
+            let mut cfr_config = self.cfr_config.clone();
+            if seed.is_some() {
+                cfr_config.set_seed(seed);
+            }
+            let mut engine = CFREngine::new(abstract_game, cfr_config);
+            engine.set_cancel_token(self.cancel_token.clone());
+            engine.mccfr_p(self.budget.iterations, self.budget.strategy_interval, self.budget.prune_threshold, self.budget.lcfr_threshold, self.budget.discount_interval)?;
+
+            let (mut abstract_game, strategy) = engine.into_parts();
+            exploitability.push(if self.compute_exploitability {
+                Some(best_response::lossless_exploitability(&mut abstract_game, &strategy, &self.cancel_token)?)
+            } else {
+                None
+            });
+            names.push(name);
+            let mut agent = StrategyAgent::new(abstract_game, strategy);
+            if let Some(seed) = seed {
+                agent.set_seed(seed);
+            }
+            agents.push(Some(Box::new(agent) as Box<dyn Agent>));
+        }
+
+        let mut dealer = Dealer::new(self.game_info);
+        if let Some(seed) = self.seed {
+            dealer.set_seed(seed);
+        }
+        let mut head_to_head = vec![vec![0.; agents.len()]; agents.len()];
+        for i in 0..agents.len() {
+            for j in i + 1..agents.len() {
+                self.cancel_token.check()?;
+                let mut seats = vec![agents[i].take().unwrap(), agents[j].take().unwrap()];
+
+                let mut won = 0;
+                for swapped in [false, true] {
+                    let before = dealer.bankrolls()[swapped as usize];
+                    dealer.play_match(&mut seats, self.num_hands).expect("dealer audit is disabled");
+                    won += dealer.bankrolls()[swapped as usize] - before;
+                    seats.swap(0, 1);
+                }
+
+                let ev = won as f64 / (2 * self.num_hands) as f64;
+                head_to_head[i][j] = ev;
+                head_to_head[j][i] = -ev;
+
+                agents[i] = Some(seats.remove(0));
+                agents[j] = Some(seats.remove(0));
+            }
+        }
+
+        Ok(CrossValidationReport {
+            names,
+            exploitability,
+            head_to_head,
+        })
+    }
+}
+
+/// How every run of a `SeedSweep` is scored, in milli big blinds per hand
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SweepMetric {
+    /// Exploitability against a best responder that sees its exact cards, lower is better
+    #[default]
+    Exploitability,
+    /// Winnings in a duplicate match against the run of the first config trained with the same
+    /// seed, which scores zero itself
+    HeadToHead,
+}
+
+impl fmt::Display for SweepMetric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SweepMetric::Exploitability => write!(f, "exploitability"),
+            SweepMetric::HeadToHead => write!(f, "head-to-head"),
+        }
+    }
+}
+
+impl FromStr for SweepMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SweepMetric, String> {
+        match s {
+            "exploitability" => Ok(SweepMetric::Exploitability),
+            "head-to-head" => Ok(SweepMetric::HeadToHead),
+            _ => Err(format!("unknown metric {}, expected exploitability or head-to-head", s)),
+        }
+    }
+}
+
+/// Scores of every config of a `SeedSweep` over every seed
+#[derive(Clone, Debug)]
+pub struct SweepReport {
+    pub names: Vec<String>,
+    pub seeds: Vec<u64>,
+    pub metric: SweepMetric,
+    /// `scores[i][k]` is the score of config i trained with `seeds[k]`
+    pub scores: Vec<Vec<f64>>,
+}
+
+impl SweepReport {
+    pub fn mean(&self, config: usize) -> f64 {
+        mean(&self.scores[config])
+    }
+
+    /// Returns the sample standard deviation of the scores of `config` over the seeds
+    pub fn std_dev(&self, config: usize) -> f64 {
+        std_dev(&self.scores[config])
+    }
+
+    /// Returns the mean difference between the scores of `config` and the first config over the
+    /// same seeds and its standard error. Pairing the runs by seed cancels the luck the configs
+    /// share.
+    pub fn difference(&self, config: usize) -> (f64, f64) {
+        let differences = self.scores[config].iter().zip(&self.scores[0]).map(|(a, b)| a - b).collect::<Vec<f64>>();
+        (mean(&differences), std_dev(&differences) / (differences.len() as f64).sqrt())
+    }
+}
+
+impl fmt::Display for SweepReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0).max(10);
+        writeln!(f, "{} in mbb/hand over {} seeds", self.metric, self.seeds.len())?;
+        writeln!(f, "{:width$} {:>12} {:>12} {:>24}", "", "mean", "std dev", format!("vs {}", self.names[0]))?;
+        for (i, name) in self.names.iter().enumerate() {
+            write!(f, "{:width$} {:>12.2} {:>12.2}", name, self.mean(i), self.std_dev(i))?;
+            if i > 0 {
+                let (difference, std_error) = self.difference(i);
+                write!(f, " {:>24}", format!("{:.2} ± {:.2} (95%)", difference, 1.96 * std_error))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.;
+    }
+    let mean = mean(values);
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
+
+/// Trains each of several CFR configs once per seed with the same budget and scores every run,
+/// so whether a solver change helps is measured against the spread between seeds instead of a
+/// single lucky run
+pub struct SeedSweep {
+    game_info: GameInfo,
+    action_abstraction: ActionAbstraction,
+    /// Card abstraction serialized, every run deserializes its own copy
+    card_abstraction: String,
+    budget: TrainingBudget,
+    configs: Vec<(String, CFRConfig)>,
+    seeds: Vec<u64>,
+    metric: SweepMetric,
+    /// Duplicate pairs of every head to head match
+    num_pairs: u32,
+    /// Runs trained at once
+    threads: usize,
+    cancel_token: CancelToken,
+}
+
+impl SeedSweep {
+    pub fn new(game_info: GameInfo, action_abstraction: ActionAbstraction, card_abstraction: CardAbstraction, budget: TrainingBudget, seeds: Vec<u64>) -> SeedSweep {
+        assert!(game_info.num_players() == 2, "seed sweeps are only supported for heads up games");
+        assert!(!seeds.is_empty(), "need at least one seed");
+        SeedSweep {
+            game_info,
+            action_abstraction,
+            card_abstraction: serde_json::to_string(&card_abstraction).expect("failed to serialize card abstraction"),
+            budget,
+            configs: Vec::new(),
+            seeds,
+            metric: SweepMetric::default(),
+            num_pairs: 10000,
+            threads: 1,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    /// Adds a config to train with every seed, the first one is the baseline the others are
+    /// compared to
+    pub fn add_cfr_config(&mut self, name: &str, cfr_config: CFRConfig) {
+        self.configs.push((name.to_string(), cfr_config));
+    }
+
+    pub fn set_metric(&mut self, metric: SweepMetric) {
+        self.metric = metric;
+    }
+
+    /// Sets the duplicate pairs of every head to head match, each pair is two hands
+    pub fn set_num_pairs(&mut self, num_pairs: u32) {
+        self.num_pairs = num_pairs;
+    }
+
+    /// Sets the runs trained at once, each on its own thread
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Trains and scores every config with every seed
+    pub fn run(self) -> Result<SweepReport, Cancelled> {
+        assert!(!self.configs.is_empty(), "need at least one cfr config");
+        assert!(self.metric != SweepMetric::HeadToHead || self.configs.len() > 1, "head to head needs a config to compare to the first");
+        let num_seeds = self.seeds.len();
+        let num_runs = self.configs.len() * num_seeds;
+        let next = AtomicUsize::new(0);
+        let runs = Mutex::new((0..num_runs).map(|_| None).collect::<Vec<Option<Result<(AbstractGame, Strategy, f64), Cancelled>>>>());
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(num_runs) {
+                scope.spawn(|| loop {
+                    let run = next.fetch_add(1, Ordering::Relaxed);
+                    if run >= num_runs {
+                        break;
+                    }
+                    let result = self.train(run / num_seeds, self.seeds[run % num_seeds]);
+                    runs.lock().unwrap()[run] = Some(result);
+                });
+            }
+        });
+
+        let mut runs = runs.into_inner().unwrap().into_iter()
+            .map(|r| r.expect("failed to finish a run"))
+            .collect::<Result<Vec<(AbstractGame, Strategy, f64)>, Cancelled>>()?;
+        let mut scores = vec![vec![0.; num_seeds]; self.configs.len()];
+        match self.metric {
+            SweepMetric::Exploitability => {
+                for (run, (_, _, exploitability)) in runs.iter().enumerate() {
+                    scores[run / num_seeds][run % num_seeds] = 1000. * self.game_info.to_big_blinds(*exploitability);
+                }
+            },
+            SweepMetric::HeadToHead => {
+                let mut agents = runs.drain(..)
+                    .map(|(abstract_game, strategy, _)| Some(StrategyAgent::new(abstract_game, strategy)))
+                    .collect::<Vec<Option<StrategyAgent>>>();
+                for (k, seed) in self.seeds.iter().enumerate() {
+                    let mut baseline = agents[k].take().expect("failed to find baseline run");
+                    baseline.set_seed(seed.wrapping_add(1));
+                    let mut baseline = Box::new(baseline) as Box<dyn Agent>;
+                    for config in 1..self.configs.len() {
+                        let mut candidate = agents[config * num_seeds + k].take().expect("failed to find run");
+                        candidate.set_seed(seed.wrapping_add(2));
+                        let mut seats = vec![Box::new(candidate) as Box<dyn Agent>, baseline];
+                        let mut duplicate_match = DuplicateMatch::new(self.game_info.clone(), self.num_pairs);
+                        duplicate_match.set_seed(*seed);
+                        duplicate_match.set_cancel_token(self.cancel_token.clone());
+                        let result = duplicate_match.play(&mut seats);
+                        baseline = seats.pop().expect("failed to find baseline agent");
+                        scores[config][k] = result?.mean;
+                    }
+                }
+            },
+        }
+
+        Ok(SweepReport {
+            names: self.configs.into_iter().map(|(name, _)| name).collect(),
+            seeds: self.seeds,
+            metric: self.metric,
+            scores,
+        })
+    }
+
+    /// Trains config `config` with `seed`, along with the exploitability of the strategy if the
+    /// sweep scores it
+    fn train(&self, config: usize, seed: u64) -> Result<(AbstractGame, Strategy, f64), Cancelled> {
+        let (name, cfr_config) = &self.configs[config];
+        info!("Training {} with seed {}", name, seed);
+        let abstract_game = AbstractGame::new(self.game_info.clone(), GameState::new(&self.game_info, 0), self.action_abstraction.clone(), CardAbstraction::from_json(&self.card_abstraction).expect("failed to deserialize card abstraction"));
+        let mut cfr_config = cfr_config.clone();
+        cfr_config.set_seed(Some(seed));
+        let mut engine = CFREngine::new(abstract_game, cfr_config);
+        engine.set_cancel_token(self.cancel_token.clone());
+        engine.mccfr_p(self.budget.iterations, self.budget.strategy_interval, self.budget.prune_threshold, self.budget.lcfr_threshold, self.budget.discount_interval)?;
+
+        let (mut abstract_game, strategy) = engine.into_parts();
+        let exploitability = match self.metric {
+            SweepMetric::Exploitability => best_response::lossless_exploitability(&mut abstract_game, &strategy, &self.cancel_token)?,
+            SweepMetric::HeadToHead => 0.,
+        };
+        Ok((abstract_game, strategy, exploitability))
+    }
+}
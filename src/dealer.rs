@@ -0,0 +1,420 @@
+use super::{
+    agent::Agent,
+    deals::{DealDistribution, DeckSource, RandomDeck},
+    game::{Action, GameInfo, GameState, PlayerId},
+    ledger::{ChipMovementKind, HandLedger},
+    live_feed::LiveFeed,
+};
+
+use poker::Card;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use std::error::Error;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+/// Something that happened during a hand, in the order the dealer produced it
+#[derive(Clone, Debug, PartialEq)]
+pub enum HandEvent {
+    Action { player: PlayerId, action: Action },
+    /// Board cards of a new round were dealt, `all_in` is set if the round is run out without
+    /// betting because nobody can act anymore
+    Street { round: u8, board_cards: Vec<Card>, all_in: bool },
+    /// Up cards of a stud round were dealt, `bring_in` is set in the first round
+    UpCards { round: u8, up_cards: Vec<Vec<Card>>, bring_in: Option<PlayerId> },
+    /// An additional board was run out after players went all in
+    RunOut { board_cards: Vec<Card> },
+    HandFinished { payouts: Vec<i32> },
+}
+
+/// Everything that happened in a single hand
+#[derive(Clone, Debug)]
+pub struct HandRecord {
+    pub hand_id: u32,
+    /// Every card dealt to each player including stud up cards, before any discards
+    pub hole_cards: Vec<Vec<Card>>,
+    /// Board cards, followed by the stub replacement cards are drawn from in draw games
+    pub board_cards: Vec<Card>,
+    /// Boards of every additional run-out, empty if the board was run once
+    pub run_outs: Vec<Vec<Card>>,
+    /// Actions in the order they were made along with the acting player
+    pub actions: Vec<(PlayerId, Action)>,
+    pub events: Vec<HandEvent>,
+    pub state: GameState,
+    pub payouts: Vec<i32>,
+    /// Payouts with the pots of all in hands awarded by equity, see
+    /// `GameState::get_all_in_ev_payouts`, set if the dealer adjudicates all ins
+    pub all_in_ev_payouts: Option<Vec<f64>>,
+    /// Every chip movement of the hand, set if the dealer keeps ledgers
+    pub ledger: Option<HandLedger>,
+}
+
+/// Chip accounting of a single player at the end of a hand
+#[derive(Clone, Debug)]
+pub struct PlayerLedger {
+    pub starting_stack: u32,
+    pub spent: u32,
+    /// Chips the ledger saw the player take back from the pot
+    pub winnings: i64,
+    pub payout: i32,
+    /// Payout returned by `GameState::get_payout` for the player
+    pub single_payout: i32,
+    /// Stack left after every chip movement in the ledger
+    pub final_stack: i64,
+    pub folded: bool,
+}
+
+/// Describes the first chip conservation violation found by the dealer
+#[derive(Clone, Debug)]
+pub struct AuditReport {
+    pub hand_id: u32,
+    pub violation: String,
+    pub pot: u32,
+    pub rake: i64,
+    pub players: Vec<PlayerLedger>,
+    pub record: HandRecord,
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "chip conservation violated in hand {}: {}", self.hand_id, self.violation)?;
+        writeln!(f, "pot: {}, rake: {}", self.pot, self.rake)?;
+        for (i, p) in self.players.iter().enumerate() {
+            writeln!(
+                f,
+                "player {}: starting stack {}, spent {}, winnings {}, payout {}, get_payout {}, final stack {}{}",
+                i, p.starting_stack, p.spent, p.winnings, p.payout, p.single_payout, p.final_stack,
+                if p.folded { ", folded" } else { "" },
+            )?;
+        }
+        // Only the board cards that were dealt, which leaves out the stub in draw games
+        let board_cards = self.record.events.iter()
+            .rev()
+            .find_map(|e| match e {
+                HandEvent::Street { board_cards, .. } => Some(&board_cards[..]),
+                _ => None,
+            })
+            .unwrap_or(&[]);
+        write!(f, "board:")?;
+        for card in board_cards {
+            write!(f, " {}", card)?;
+        }
+        writeln!(f)?;
+        write!(f, "actions:")?;
+        for (player, action) in &self.record.actions {
+            write!(f, " {}:{}", player, action)?;
+        }
+        if let Some(ledger) = &self.record.ledger {
+            write!(f, "\nledger: {}", ledger)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for AuditReport {}
+
+/// Deals hands between agents and settles them
+pub struct Dealer {
+    game_info: GameInfo,
+    /// Whether to verify chip conservation after every hand
+    audit: bool,
+    /// Whether to also settle every hand by all in equity
+    all_in_ev: bool,
+    /// Whether to record every chip movement of a hand, always done when auditing
+    ledger: bool,
+    /// Where the cards of every hand come from
+    deck_source: Box<dyn DeckSource>,
+    hand_id: u32,
+    /// Net chips won by each seat over all hands dealt
+    bankrolls: Vec<i64>,
+    /// Where the events of every hand are streamed to
+    live_feed: Option<LiveFeed>,
+    /// Player sitting in each seat, which the live feed reports results by
+    seat_players: Vec<usize>,
+    rng: StdRng,
+}
+
+impl Dealer {
+    pub fn new(game_info: GameInfo) -> Dealer {
+        let num_players = game_info.num_players() as usize;
+        Dealer {
+            game_info,
+            audit: false,
+            all_in_ev: false,
+            ledger: false,
+            deck_source: Box::new(RandomDeck),
+            hand_id: 0,
+            bankrolls: vec![0; num_players],
+            live_feed: None,
+            seat_players: (0..num_players).collect(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used for dealing so the same cards are dealt every run
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Enables or disables checking chip conservation after every hand, which also keeps the
+    /// ledger of every hand and checks it balances
+    pub fn set_audit(&mut self, audit: bool) {
+        self.audit = audit;
+    }
+
+    /// Enables or disables recording every chip movement of a hand in `HandRecord::ledger`
+    pub fn set_ledger(&mut self, ledger: bool) {
+        self.ledger = ledger;
+    }
+
+    /// Enables or disables recording the all in EV payouts of every hand next to the dealt
+    /// payouts, which enumerates every runout of hands that went all in before the last round
+    pub fn set_all_in_ev(&mut self, all_in_ev: bool) {
+        self.all_in_ev = all_in_ev;
+    }
+
+    /// Deals every hand from `deal_distribution`, e.g. to play out a spot between two ranges
+    pub fn set_deal_distribution(&mut self, deal_distribution: Option<DealDistribution>) {
+        match deal_distribution {
+            Some(distribution) => self.set_deck_source(Box::new(distribution)),
+            None => self.set_deck_source(Box::new(RandomDeck)),
+        }
+    }
+
+    /// Deals every hand from `deck_source`, e.g. a stacked deck or a script of hands to replay
+    pub fn set_deck_source(&mut self, deck_source: Box<dyn DeckSource>) {
+        self.deck_source = deck_source;
+    }
+
+    /// Streams the events of every hand once it finished, see `LiveFeed`
+    pub fn set_live_feed(&mut self, live_feed: Option<LiveFeed>) {
+        self.live_feed = live_feed;
+    }
+
+    pub fn live_feed(&self) -> Option<&LiveFeed> {
+        self.live_feed.as_ref()
+    }
+
+    /// Sets the player sitting in each seat for the results of the live feed, for callers that
+    /// move players between seats. Seat i is player i by default.
+    pub fn set_seat_players(&mut self, seat_players: Vec<usize>) {
+        assert!(seat_players.len() == self.game_info.num_players() as usize, "need the player of every seat");
+        self.seat_players = seat_players;
+    }
+
+    pub fn game_info(&self) -> &GameInfo {
+        &self.game_info
+    }
+
+    /// Replaces the game played from the next hand on, the number of players can't change
+    pub fn set_game_info(&mut self, game_info: GameInfo) {
+        assert!(game_info.num_players() == self.game_info.num_players(), "number of players can't change");
+        self.game_info = game_info;
+    }
+
+    /// Returns net chips won by each seat over all hands dealt
+    pub fn bankrolls(&self) -> &[i64] {
+        &self.bankrolls
+    }
+
+    /// Deals every street in `rounds`, one at a time so an all in runout is visible
+    fn observe_streets(&self, agents: &mut [Box<dyn Agent>], events: &mut Vec<HandEvent>, board_cards: &[Card], rounds: RangeInclusive<u8>, runout_rounds: Range<u8>) {
+        for round in rounds {
+            let all_in = runout_rounds.contains(&round);
+            let street_board = &board_cards[..self.game_info.total_board_cards(round) as usize];
+            for agent in agents.iter_mut() {
+                agent.observe_street(&self.game_info, round, street_board, all_in);
+            }
+            events.push(HandEvent::Street { round, board_cards: street_board.to_vec(), all_in });
+        }
+    }
+
+    /// Plays a hand with `agents[i]` in seat i. Only fails when auditing is enabled and the
+    /// hand did not conserve chips.
+    pub fn play_hand(&mut self, agents: &mut [Box<dyn Agent>]) -> Result<HandRecord, Box<AuditReport>> {
+        assert!(agents.len() == self.game_info.num_players() as usize, "need one agent per seat");
+
+        let (hole_cards, board_cards) = self.deck_source.deal(&self.game_info, &mut self.rng);
+        enter_span!("hand", hand_id = self.hand_id);
+        let mut state = GameState::new(&self.game_info, self.hand_id);
+        let mut ledger = (self.ledger || self.audit).then(|| HandLedger::new(&self.game_info, &state));
+        let mut actions = Vec::new();
+        let mut events = Vec::new();
+
+        for (seat, agent) in agents.iter_mut().enumerate() {
+            agent.begin_hand(&self.game_info, seat as PlayerId, &hole_cards[seat]);
+        }
+
+        while !state.is_finished() {
+            if state.is_awaiting_up_cards() {
+                let round = state.current_round();
+                let new_state = state.deal_up_cards(&self.game_info, self.game_info.hand_ranking().evaluator(), &hole_cards).unwrap();
+                if let Some(ledger) = &mut ledger {
+                    ledger.record(ChipMovementKind::BringIn, &state, &new_state);
+                }
+                state = new_state;
+                let up_cards = hole_cards.iter().map(|c| self.game_info.visible_up_cards(c, round)).collect::<Vec<Vec<Card>>>();
+                for agent in agents.iter_mut() {
+                    agent.observe_up_cards(&self.game_info, round, &up_cards);
+                }
+                let bring_in = if round == 0 { state.bring_in_player() } else { None };
+                events.push(HandEvent::UpCards { round, up_cards, bring_in });
+            }
+
+            if let Ok(new_state) = state.fast_forward_all_in(&self.game_info) {
+                self.observe_streets(agents, &mut events, &board_cards, state.current_round() + 1..=new_state.current_round(), new_state.runout_rounds());
+                state = new_state;
+                continue;
+            }
+
+            let player = state.current_player().unwrap();
+            let visible_board = &board_cards[..self.game_info.total_board_cards(state.current_round()) as usize];
+            let current_hole_cards = state.current_hole_cards(&self.game_info, &hole_cards, &board_cards);
+            let player_cards = &current_hole_cards[player as usize][..self.game_info.num_player_cards(state.current_round()) as usize];
+            let action = agents[player as usize].get_action(&self.game_info, &state, player_cards, visible_board);
+            let (new_state, outcome) = state.apply_action(&self.game_info, action).expect("agent made an invalid action");
+            trace!("Seat {} {}", player, action);
+            if let Some(ledger) = &mut ledger {
+                ledger.record(ChipMovementKind::Bet, &state, &new_state);
+            }
+            actions.push((player, action));
+            events.push(HandEvent::Action { player, action });
+
+            for agent in agents.iter_mut() {
+                agent.observe_action(&self.game_info, player, action);
+            }
+
+            self.observe_streets(agents, &mut events, &board_cards, state.current_round() + 1..=outcome.new_round.unwrap_or(0), outcome.runout_rounds);
+            state = new_state;
+        }
+
+        let mut run_outs = Vec::new();
+        let runout_rounds = state.runout_rounds();
+        if self.game_info.num_run_outs() > 1 && !runout_rounds.is_empty() {
+            run_outs = self.game_info.deal_run_outs(&hole_cards, &board_cards, runout_rounds.start - 1, &mut self.rng);
+            for run_out in run_outs.iter() {
+                events.push(HandEvent::RunOut { board_cards: run_out.clone() });
+            }
+        }
+
+        let boards = [&board_cards[..]].into_iter().chain(run_outs.iter().map(|b| &b[..])).collect::<Vec<&[Card]>>();
+        let payouts = state.get_run_out_payouts(&self.game_info, self.game_info.hand_ranking().evaluator(), &boards, &hole_cards);
+        for agent in agents.iter_mut() {
+            agent.end_hand(&self.game_info, &state, &payouts);
+        }
+        debug!("Hand {} finished with payouts {:?}", self.hand_id, payouts);
+        events.push(HandEvent::HandFinished { payouts: payouts.clone() });
+        if let Some(ledger) = &mut ledger {
+            ledger.settle(&self.game_info, &state, &payouts);
+        }
+        let all_in_ev_payouts = self.all_in_ev
+            .then(|| state.get_all_in_ev_payouts(&self.game_info, self.game_info.hand_ranking().evaluator(), &board_cards, &hole_cards));
+
+        let record = HandRecord {
+            hand_id: self.hand_id,
+            hole_cards,
+            board_cards,
+            run_outs,
+            actions,
+            events,
+            state,
+            payouts,
+            all_in_ev_payouts,
+            ledger,
+        };
+
+        if self.audit {
+            self.audit_hand(&record)?;
+        }
+
+        for (bankroll, payout) in self.bankrolls.iter_mut().zip(record.payouts.iter()) {
+            *bankroll += *payout as i64;
+        }
+        if let Some(live_feed) = &mut self.live_feed {
+            live_feed.publish(&self.game_info, &record, &self.seat_players);
+        }
+        self.hand_id += 1;
+
+        Ok(record)
+    }
+
+    /// Plays `num_hands` hands and returns the records, stopping at the first audit violation.
+    /// Agents are told the session ended once every hand was played.
+    pub fn play_match(&mut self, agents: &mut [Box<dyn Agent>], num_hands: u32) -> Result<Vec<HandRecord>, Box<AuditReport>> {
+        let records = (0..num_hands).map(|_| self.play_hand(agents)).collect::<Result<Vec<HandRecord>, Box<AuditReport>>>()?;
+        for agent in agents.iter_mut() {
+            agent.end_session(&self.game_info);
+        }
+        Ok(records)
+    }
+
+    /// Verifies the payouts against the chips the ledger saw move action by action: the pot must
+    /// hold what was put in, every stack must end where its payout says and only the configured
+    /// rake may leave the table
+    fn audit_hand(&self, record: &HandRecord) -> Result<(), Box<AuditReport>> {
+        let state = &record.state;
+        let ledger = record.ledger.as_ref().expect("auditing dealers keep a ledger of every hand");
+        let num_players = self.game_info.num_players();
+        let pot = state.pot_total(&self.game_info);
+
+        let players = (0..num_players)
+            .map(|p| {
+                let payout = record.payouts[p as usize];
+                PlayerLedger {
+                    starting_stack: state.player_stack(p),
+                    spent: state.player_spent(p),
+                    winnings: ledger.taken_out(p),
+                    payout,
+                    // get_payout settles a single board, so it can only be compared when the
+                    // board was run once
+                    single_payout: if record.run_outs.is_empty() {
+                        state.get_payout(&self.game_info, self.game_info.hand_ranking().evaluator(), &record.board_cards, &record.hole_cards, p)
+                    } else {
+                        payout
+                    },
+                    final_stack: ledger.stack(p),
+                    folded: state.has_folded(p),
+                }
+            })
+            .collect::<Vec<PlayerLedger>>();
+
+        // Any chips missing from the pot after settling beyond the configured rake are a leak
+        let rake = pot as i64 - players.iter().map(|p| p.winnings).sum::<i64>();
+        let expected_rake = state.rake(&self.game_info) as i64;
+
+        let violation = if !state.is_finished() {
+            Some("hand is not finished".to_string())
+        } else if pot as i64 != (0..num_players).map(|p| ledger.put_in(p)).sum::<i64>() {
+            Some("pot does not match the chips put in by players".to_string())
+        } else if let Some(i) = players.iter().position(|p| p.spent > p.starting_stack) {
+            Some(format!("player {} spent more than their stack", i))
+        } else if let Some(i) = players.iter().position(|p| p.winnings < 0) {
+            Some(format!("player {} lost more than they put in the pot", i))
+        } else if let Some(i) = players.iter().position(|p| p.folded && p.winnings != 0) {
+            Some(format!("player {} folded but received chips from the pot", i))
+        } else if let Some(i) = players.iter().position(|p| p.payout != p.single_payout) {
+            Some(format!("payout of player {} differs from get_payout", i))
+        } else if rake != expected_rake {
+            Some(format!("{} chips were not conserved when settling the pot", rake - expected_rake))
+        } else if let Some(i) = players.iter().position(|p| p.final_stack - p.starting_stack as i64 != p.payout as i64) {
+            Some(format!("payout of player {} does not match their stack delta", i))
+        } else if let Err(error) = ledger.check() {
+            Some(format!("ledger does not balance, {}", error))
+        } else {
+            None
+        };
+
+        match violation {
+            Some(violation) => Err(Box::new(AuditReport {
+                hand_id: record.hand_id,
+                violation,
+                pot,
+                rake,
+                players,
+                record: record.clone(),
+            })),
+            None => Ok(()),
+        }
+    }
+}
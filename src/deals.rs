@@ -0,0 +1,401 @@
+use super::{
+    card_set::CardSet,
+    game::{GameInfo, PlayerId},
+    range::{Combo, Range},
+};
+
+use poker::Card;
+use itertools::{Combinations, Itertools};
+use rand::prelude::*;
+
+use std::vec;
+
+/// Attempts at dealing combos of the ranges that don't share a card before giving up
+const MAX_DEAL_ATTEMPTS: u32 = 10000;
+
+/// A complete assignment of hole cards and board cards
+pub type Deal = (Vec<Vec<Card>>, Vec<Card>);
+
+/// Iterates over every distinct deal of a game along with its probability. Deals that only
+/// differ in the order of a player's hole cards or of the board cards of a round are the same
+/// deal, so every deal is equally likely. Only feasible for small games like Kuhn and Leduc.
+pub struct Deals {
+    num_players: usize,
+    deck: Vec<Card>,
+    /// Cards dealt at once, the hole cards of each player followed by the board cards of each
+    /// round that has any
+    group_sizes: Vec<usize>,
+    /// Combinations of each group over the cards left by the groups before it
+    combinations: Vec<Combinations<vec::IntoIter<Card>>>,
+    /// Cards currently dealt to each group
+    current: Vec<Vec<Card>>,
+    num_deals: u64,
+    started: bool,
+    finished: bool,
+}
+
+impl Deals {
+    pub fn new(game_info: &GameInfo) -> Deals {
+        assert!(!game_info.has_draws() && !game_info.is_stud(), "deals can only be enumerated for board games");
+        let num_players = game_info.num_players() as usize;
+        let deck = game_info.generate_deck().collect::<Vec<Card>>();
+
+        let mut group_sizes = vec![game_info.num_hole_cards() as usize; num_players];
+        group_sizes.extend((0..game_info.num_rounds())
+            .map(|r| game_info.num_board_cards(r) as usize)
+            .filter(|n| *n > 0));
+
+        let mut num_deals = 1;
+        let mut remaining = deck.len() as u64;
+        for size in group_sizes.iter() {
+            num_deals *= binomial(remaining, *size as u64);
+            remaining -= *size as u64;
+        }
+
+        Deals {
+            num_players,
+            deck,
+            combinations: Vec::with_capacity(group_sizes.len()),
+            current: Vec::with_capacity(group_sizes.len()),
+            group_sizes,
+            num_deals,
+            started: false,
+            finished: false,
+        }
+    }
+
+    pub fn num_deals(&self) -> u64 {
+        self.num_deals
+    }
+
+    /// Returns the probability of each deal
+    pub fn probability(&self) -> f64 {
+        1. / self.num_deals as f64
+    }
+
+    /// Deals the first combination of every group from `group` on
+    fn fill_from(&mut self, group: usize) -> bool {
+        self.combinations.truncate(group);
+        self.current.truncate(group);
+        for size in self.group_sizes[group..].iter() {
+            let remaining = self.deck.iter()
+                .filter(|c| !self.current.iter().any(|g| g.contains(c)))
+                .copied()
+                .collect::<Vec<Card>>();
+            let mut combinations = remaining.into_iter().combinations(*size);
+            match combinations.next() {
+                Some(cards) => self.current.push(cards),
+                None => return false,
+            }
+            self.combinations.push(combinations);
+        }
+        true
+    }
+
+    /// Moves to the next combination of the last group that has one left
+    fn advance(&mut self) -> bool {
+        for group in (0..self.group_sizes.len()).rev() {
+            if let Some(cards) = self.combinations[group].next() {
+                self.current[group] = cards;
+                return self.fill_from(group + 1);
+            }
+        }
+        false
+    }
+}
+
+impl Iterator for Deals {
+    type Item = (Deal, f64);
+
+    fn next(&mut self) -> Option<(Deal, f64)> {
+        if self.finished {
+            return None;
+        }
+
+        let dealt = if self.started {
+            self.advance()
+        } else {
+            self.started = true;
+            self.fill_from(0)
+        };
+        if !dealt {
+            self.finished = true;
+            return None;
+        }
+
+        let hole_cards = self.current[..self.num_players].to_vec();
+        let board_cards = self.current[self.num_players..].concat();
+        Some(((hole_cards, board_cards), self.probability()))
+    }
+}
+
+/// Cards a deal must hold: known hole cards of some players, the first cards of the board and
+/// dead cards nobody can be dealt, e.g. cards seen folded. The rest of the deal is uniformly
+/// random over the cards left. Deals are laid out like `GameInfo::deal_hole_cards_and_board_cards`,
+/// so in draw games the stub of the deck follows the board.
+#[derive(Clone, Debug)]
+pub struct DealConstraints {
+    /// First hole cards of each player, the rest of their hole cards are dealt
+    hole_cards: Vec<Vec<Card>>,
+    /// Cards the board starts with
+    board_cards: Vec<Card>,
+    dead_cards: Vec<Card>,
+}
+
+impl DealConstraints {
+    pub fn new(game_info: &GameInfo) -> DealConstraints {
+        DealConstraints {
+            hole_cards: vec![Vec::new(); game_info.num_players() as usize],
+            board_cards: Vec::new(),
+            dead_cards: Vec::new(),
+        }
+    }
+
+    /// Fixes the first hole cards of `player`, in stud games these include the up cards
+    pub fn set_hole_cards(&mut self, player: PlayerId, hole_cards: Vec<Card>) {
+        self.hole_cards[player as usize] = hole_cards;
+    }
+
+    /// Sets the first cards of the board, e.g. the flop of a flop spot
+    pub fn set_board_cards(&mut self, board_cards: Vec<Card>) {
+        self.board_cards = board_cards;
+    }
+
+    /// Sets cards removed from the deck that are never dealt
+    pub fn set_dead_cards(&mut self, dead_cards: Vec<Card>) {
+        self.dead_cards = dead_cards;
+    }
+
+    pub fn hole_cards(&self, player: PlayerId) -> &[Card] {
+        &self.hole_cards[player as usize]
+    }
+
+    pub fn board_cards(&self) -> &[Card] {
+        &self.board_cards
+    }
+
+    pub fn dead_cards(&self) -> &[Card] {
+        &self.dead_cards
+    }
+
+    /// Returns the set of fixed and dead cards
+    pub fn used_cards(&self) -> CardSet {
+        CardSet::from_cards(&self.hole_cards.concat()) | CardSet::from_cards(&self.board_cards) | CardSet::from_cards(&self.dead_cards)
+    }
+
+    /// Returns the cards of the deck that are left to deal, in deck order
+    pub fn remaining_cards(&self, game_info: &GameInfo) -> Vec<Card> {
+        self.used_cards().remaining(&game_info.generate_deck().collect::<Vec<Card>>())
+    }
+
+    /// Checks that the cards are in the deck, no card is used twice, no player or board holds
+    /// more cards than the game deals and enough cards are left to deal the rest
+    pub fn validate(&self, game_info: &GameInfo) -> Result<(), &'static str> {
+        let deck = CardSet::from_cards(&game_info.generate_deck().collect::<Vec<Card>>());
+        let mut used = CardSet::new();
+        for card in self.hole_cards.iter().flatten().chain(&self.board_cards).chain(&self.dead_cards) {
+            if !deck.contains(*card) {
+                return Err("card is not in the deck");
+            }
+            if used.contains(*card) {
+                return Err("card is used twice");
+            }
+            used.insert(*card);
+        }
+
+        let num_hole_cards = game_info.num_player_cards(game_info.num_rounds() - 1) as usize;
+        if self.hole_cards.len() != game_info.num_players() as usize {
+            return Err("constraints are for a different number of players");
+        }
+        if self.hole_cards.iter().any(|h| h.len() > num_hole_cards) {
+            return Err("more hole cards than a player is dealt");
+        }
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        if self.board_cards.len() > num_board_cards {
+            return Err("more board cards than a full board");
+        }
+        let num_to_deal = self.hole_cards.iter().map(|h| num_hole_cards - h.len()).sum::<usize>() + num_board_cards - self.board_cards.len();
+        if num_to_deal > deck.len() - used.len() {
+            return Err("not enough cards left to deal");
+        }
+        Ok(())
+    }
+
+    /// Deals the cards that aren't fixed uniformly from the cards left, panics if the
+    /// constraints are invalid
+    pub fn deal<R: Rng + ?Sized>(&self, game_info: &GameInfo, rng: &mut R) -> Deal {
+        self.validate(game_info).expect("invalid deal constraints");
+        let used = self.used_cards();
+        let mut deck = Vec::from(game_info.generate_shuffled_deck(rng))
+            .into_iter()
+            .filter(|c| !used.contains(*c));
+
+        let num_hole_cards = game_info.num_player_cards(game_info.num_rounds() - 1) as usize;
+        let hole_cards = self.hole_cards.iter()
+            .map(|fixed| {
+                let mut cards = fixed.clone();
+                cards.extend(deck.by_ref().take(num_hole_cards - fixed.len()));
+                cards
+            })
+            .collect::<Vec<Vec<Card>>>();
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        let mut board_cards = self.board_cards.clone();
+        board_cards.extend(deck.by_ref().take(num_board_cards - self.board_cards.len()));
+        if game_info.has_draws() {
+            board_cards.extend(deck);
+        }
+        (hole_cards, board_cards)
+    }
+}
+
+/// Distribution hands are dealt from in place of uniformly random deals, to train or play spots
+/// where players hold known ranges, e.g. a hero with AA-QQ against the flatting range of the
+/// villain. Players with a range are dealt its combos with probability proportional to their
+/// weight, jointly over the combos that don't share a card, the other players and the rest of the
+/// board get random cards. Only for games with two hole cards and a board.
+#[derive(Clone, Debug)]
+pub struct DealDistribution {
+    ranges: Vec<Option<Range>>,
+    /// Board and dead cards, the hole cards of the players are left free
+    constraints: DealConstraints,
+}
+
+impl DealDistribution {
+    pub fn new(game_info: &GameInfo) -> DealDistribution {
+        assert!(game_info.num_hole_cards() == 2 && !game_info.has_draws() && !game_info.is_stud(), "ranges can only be dealt in board games with two hole cards");
+        DealDistribution {
+            ranges: vec![None; game_info.num_players() as usize],
+            constraints: DealConstraints::new(game_info),
+        }
+    }
+
+    pub fn set_range(&mut self, player: PlayerId, range: Range) {
+        assert!(!range.is_empty(), "range is empty");
+        self.ranges[player as usize] = Some(range);
+    }
+
+    /// Sets the first cards of the board, e.g. the flop of a flop spot
+    pub fn set_board_cards(&mut self, board_cards: Vec<Card>) {
+        self.constraints.set_board_cards(board_cards);
+    }
+
+    /// Sets cards that are never dealt, combos holding them are removed from the ranges
+    pub fn set_dead_cards(&mut self, dead_cards: Vec<Card>) {
+        self.constraints.set_dead_cards(dead_cards);
+    }
+
+    /// Deals the hole cards and the board of a hand, panics if the ranges can't be dealt
+    /// together on the board
+    pub fn deal<R: Rng + ?Sized>(&self, game_info: &GameInfo, rng: &mut R) -> Deal {
+        let used = self.constraints.used_cards();
+        let ranges = self.ranges.iter()
+            .map(|range| range.as_ref().map(|r| r.without_blocked(&used.iter().collect::<Vec<Card>>())))
+            .collect::<Vec<Option<Range>>>();
+        assert!(ranges.iter().flatten().all(|r| !r.is_empty()), "range is empty on the board");
+
+        // Combos are drawn independently until none overlap, which keeps the joint distribution
+        // proportional to the product of the weights
+        let combos = (0..MAX_DEAL_ATTEMPTS)
+            .map(|_| {
+                ranges.iter()
+                    .map(|range| range.as_ref().map(|r| *r.iter().collect::<Vec<(&Combo, &f64)>>().choose_weighted(rng, |(_, w)| **w).unwrap().0))
+                    .collect::<Vec<Option<Combo>>>()
+            })
+            .find(|combos| combos.iter().flatten().tuple_combinations().all(|(a, b)| !a.is_blocked_by(&b.cards())))
+            .expect("ranges can't be dealt together");
+
+        let mut constraints = self.constraints.clone();
+        for (player, combo) in combos.iter().enumerate() {
+            if let Some(combo) = combo {
+                constraints.set_hole_cards(player as PlayerId, combo.cards().to_vec());
+            }
+        }
+        constraints.deal(game_info, rng)
+    }
+}
+
+/// Where the dealer gets the cards of every hand from, so tests and hand replays can force the
+/// cards dealt instead of patching the dealer
+pub trait DeckSource: Send {
+    /// Returns the deal of the next hand, drawing any random cards from `rng`
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal;
+}
+
+/// Deals every hand uniformly at random
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomDeck;
+
+impl DeckSource for RandomDeck {
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal {
+        game_info.deal_hole_cards_and_board_cards(rng)
+    }
+}
+
+/// Deals every hand from the same stacked deck, see `GameInfo::deal_from_deck`. Cards of the
+/// game left out of the stack follow it in random order.
+#[derive(Clone, Debug)]
+pub struct FixedDeck {
+    cards: Vec<Card>,
+}
+
+impl FixedDeck {
+    pub fn new(cards: Vec<Card>) -> FixedDeck {
+        assert!(cards.iter().all_unique(), "card is stacked twice");
+        FixedDeck { cards }
+    }
+}
+
+impl DeckSource for FixedDeck {
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal {
+        let stacked = CardSet::from_cards(&self.cards);
+        let mut deck = self.cards.clone();
+        deck.extend(game_info.generate_shuffled_deck(rng).iter().filter(|c| !stacked.contains(**c)));
+        game_info.deal_from_deck(&deck)
+    }
+}
+
+/// Deals hand i from the ith constraints of a script, starting over after the last
+#[derive(Clone, Debug)]
+pub struct ScriptedDeals {
+    hands: Vec<DealConstraints>,
+    next: usize,
+}
+
+impl ScriptedDeals {
+    pub fn new(hands: Vec<DealConstraints>) -> ScriptedDeals {
+        assert!(!hands.is_empty(), "script has no hands");
+        ScriptedDeals { hands, next: 0 }
+    }
+
+    /// Returns the index in the script of the next hand dealt
+    pub fn next_hand(&self) -> usize {
+        self.next
+    }
+}
+
+impl DeckSource for ScriptedDeals {
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal {
+        let deal = self.hands[self.next].deal(game_info, rng);
+        self.next = (self.next + 1) % self.hands.len();
+        deal
+    }
+}
+
+impl DeckSource for DealConstraints {
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal {
+        DealConstraints::deal(self, game_info, rng)
+    }
+}
+
+impl DeckSource for DealDistribution {
+    fn deal(&mut self, game_info: &GameInfo, rng: &mut dyn RngCore) -> Deal {
+        DealDistribution::deal(self, game_info, rng)
+    }
+}
+
+fn binomial(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
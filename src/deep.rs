@@ -0,0 +1,286 @@
+use super::{
+    abstract_game::AbstractGame,
+    cancel::{CancelToken, Cancelled},
+    evaluator::HandEvaluator,
+    features::FeatureEncoder,
+    game::{Action, GameInfo, GameState, PlayerId},
+    node::NodeId,
+};
+
+use poker::Card;
+
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::path::Path;
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// Turns the cards a player sees and the state of the game into the features the networks take
+pub type InfosetEncoder = Box<dyn Fn(&GameInfo, &GameState, &[Card], &[Card]) -> Vec<f32>>;
+
+/// Returns an `InfosetEncoder` encoding the infosets of the player to act with `encoder`
+pub fn infoset_encoder(encoder: FeatureEncoder) -> InfosetEncoder {
+    Box::new(move |game_info, state, hole_cards, board_cards| {
+        encoder.encode(game_info, state, state.current_player().unwrap(), hole_cards, board_cards)
+    })
+}
+
+/// Network predicting the advantage of the actions at an infoset. Outputs are indexed like the
+/// actions of the abstract game at the infoset, so the network needs at least as many outputs
+/// as the largest number of actions of any node.
+pub trait AdvantageNetwork {
+    fn predict(&self, features: &[Vec<f32>]) -> Vec<Vec<f32>>;
+    /// Trains on every sample of the player's buffer, the loss of each sample should be weighted
+    /// by its iteration
+    fn train(&mut self, samples: &[AdvantageSample]);
+}
+
+/// Network predicting the average strategy at an infoset, indexed like `AdvantageNetwork`
+pub trait PolicyNetwork {
+    fn predict(&self, features: &[Vec<f32>]) -> Vec<Vec<f32>>;
+    /// Trains on every sample of the buffer, the loss of each sample should be weighted by its
+    /// iteration
+    fn train(&mut self, samples: &[PolicySample]);
+}
+
+/// Sampled advantage of each action at an infoset of the traversing player. Only the first
+/// `advantages.len()` outputs of the network correspond to actions.
+#[derive(Clone, Debug)]
+pub struct AdvantageSample {
+    pub features: Vec<f32>,
+    pub advantages: Vec<f32>,
+    pub iteration: u32,
+}
+
+/// Strategy an opponent played at an infoset, only the first `probabilities.len()` outputs of
+/// the network correspond to actions
+#[derive(Clone, Debug)]
+pub struct PolicySample {
+    pub features: Vec<f32>,
+    pub probabilities: Vec<f32>,
+    pub iteration: u32,
+}
+
+/// Keeps a uniform random sample of everything pushed to it once it is full
+pub struct ReservoirBuffer<T> {
+    capacity: usize,
+    samples: Vec<T>,
+    num_seen: u64,
+}
+
+impl<T> ReservoirBuffer<T> {
+    pub fn new(capacity: usize) -> ReservoirBuffer<T> {
+        ReservoirBuffer {
+            capacity,
+            samples: Vec::new(),
+            num_seen: 0,
+        }
+    }
+
+    pub fn push<R: Rng + ?Sized>(&mut self, sample: T, rng: &mut R) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(sample);
+        } else {
+            let i = rng.gen_range(0..=self.num_seen);
+            if (i as usize) < self.capacity {
+                self.samples[i as usize] = sample;
+            }
+        }
+        self.num_seen += 1;
+    }
+
+    pub fn samples(&self) -> &[T] {
+        &self.samples
+    }
+
+    pub fn num_seen(&self) -> u64 {
+        self.num_seen
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeepCFRConfig {
+    /// Games traversed for each player every iteration before training its advantage network
+    traversals: u32,
+    /// Capacity of the advantage buffer of each player and of the policy buffer
+    buffer_capacity: usize,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+impl DeepCFRConfig {
+    pub fn new(traversals: u32, buffer_capacity: usize) -> DeepCFRConfig {
+        DeepCFRConfig {
+            traversals,
+            buffer_capacity,
+            seed: None,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    pub fn from_config(path: &Path) -> DeepCFRConfig {
+        serde_json::from_str(&fs::read_to_string(path).expect("failed to read deep cfr config")).expect("failed to deserialize deep cfr config")
+    }
+}
+
+/// Deep CFR, where a network per player approximates the regrets instead of storing them per
+/// infoset. Each iteration the games are traversed with external sampling, the sampled
+/// advantages of the traversing player go to its buffer and the strategies of the opponents to
+/// the policy buffer, the average strategy is learned from it by `train_policy`.
+pub struct DeepCFR<A: AdvantageNetwork, P: PolicyNetwork> {
+    abstract_game: AbstractGame,
+    advantage_networks: Vec<A>,
+    policy_network: P,
+    encoder: InfosetEncoder,
+    advantage_buffers: Vec<ReservoirBuffer<AdvantageSample>>,
+    policy_buffer: ReservoirBuffer<PolicySample>,
+    evaluator: &'static dyn HandEvaluator,
+    config: DeepCFRConfig,
+    iteration: u32,
+    cancel_token: CancelToken,
+    rng: StdRng,
+}
+
+impl<A: AdvantageNetwork, P: PolicyNetwork> DeepCFR<A, P> {
+    pub fn new(abstract_game: AbstractGame, advantage_networks: Vec<A>, policy_network: P, encoder: InfosetEncoder, config: DeepCFRConfig) -> DeepCFR<A, P> {
+        let num_players = abstract_game.game_info.num_players() as usize;
+        assert!(advantage_networks.len() == num_players, "there must be an advantage network for every player");
+
+        DeepCFR {
+            evaluator: abstract_game.game_info.hand_ranking().evaluator(),
+            abstract_game,
+            advantage_networks,
+            policy_network,
+            encoder,
+            advantage_buffers: (0..num_players).map(|_| ReservoirBuffer::new(config.buffer_capacity)).collect(),
+            policy_buffer: ReservoirBuffer::new(config.buffer_capacity),
+            rng: config.seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            config,
+            iteration: 0,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    pub fn abstract_game(&self) -> &AbstractGame {
+        &self.abstract_game
+    }
+
+    pub fn policy_network(&self) -> &P {
+        &self.policy_network
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Sets the token checked between traversals
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Runs `ticks` iterations, training the advantage network of each player after its
+    /// traversals
+    pub fn train(&mut self, ticks: u32) -> Result<(), Cancelled> {
+        let num_players = self.abstract_game.game_info.num_players();
+
+        for _ in 0..ticks {
+            enter_span!("deep_cfr", iteration = self.iteration);
+            info!("Deep CFR iteration {:?}, {} nodes", self.iteration, self.abstract_game.nodes.len());
+            for player in 0..num_players {
+                for _ in 0..self.config.traversals {
+                    self.cancel_token.check()?;
+                    let (hole_cards, board_cards) = self.abstract_game.game_info.deal_hole_cards_and_board_cards(&mut self.rng);
+                    let root = self.abstract_game.nodes.get_root_node_id();
+                    self.traverse(root, &board_cards, &hole_cards, player);
+                }
+                self.advantage_networks[player as usize].train(self.advantage_buffers[player as usize].samples());
+            }
+            self.iteration += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Trains the policy network on the strategies collected so far, it then approximates the
+    /// average strategy
+    pub fn train_policy(&mut self) {
+        self.policy_network.train(self.policy_buffer.samples());
+    }
+
+    /// Returns the probability the policy network gives each abstract action in `state`
+    pub fn action_probabilities(&self, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Vec<(Action, f64)> {
+        let actions = self.abstract_game.get_actions(state);
+        let features = (self.encoder)(&self.abstract_game.game_info, state, hole_cards, board_cards);
+        let output = self.policy_network.predict(&[features]).remove(0);
+        let total = output.iter().take(actions.len()).map(|p| p.max(0.)).sum::<f32>();
+
+        actions.iter()
+            .zip(output)
+            .map(|(a, p)| (*a, if total > 0. { (p.max(0.) / total) as f64 } else { 1. / actions.len() as f64 }))
+            .collect()
+    }
+
+    /// Returns the current strategy of the player acting at an infoset by regret matching the
+    /// advantages its network predicts
+    fn current_strategy(&self, player: PlayerId, features: &[f32], num_actions: usize) -> Vec<f32> {
+        let advantages = self.advantage_networks[player as usize].predict(&[features.to_vec()]).remove(0);
+        let total = advantages.iter().take(num_actions).map(|a| a.max(0.)).sum::<f32>();
+
+        (0..num_actions)
+            .map(|i| if total > 0. { advantages[i].max(0.) / total } else { 1. / num_actions as f32 })
+            .collect()
+    }
+
+    /// Returns the value of `player` from `node_id`, exploring every action of `player` and
+    /// sampling one of the opponents
+    fn traverse(&mut self, node_id: NodeId, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> f32 {
+        let state = self.abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+        let game_info = &self.abstract_game.game_info;
+
+        if state.is_finished() || state.has_folded(player) {
+            return state.get_payout(game_info, self.evaluator, board_cards, hole_cards, player) as f32;
+        }
+
+        let current_player = state.current_player().unwrap();
+        let current_hole_cards = state.current_hole_cards(game_info, hole_cards, board_cards);
+        let visible_board_cards = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+        let features = (self.encoder)(game_info, &state, &current_hole_cards[current_player as usize], visible_board_cards);
+        let actions = self.abstract_game.get_actions(&state);
+        let sigma = self.current_strategy(current_player, &features, actions.len());
+
+        if current_player == player {
+            let mut values = Vec::with_capacity(actions.len());
+            for action in actions.iter() {
+                let mut board_cards_i = 0;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+                values.push(self.traverse(child_node_id, board_cards, hole_cards, player));
+            }
+
+            let v = values.iter().zip(sigma.iter()).map(|(v, p)| v * p).sum::<f32>();
+            let sample = AdvantageSample {
+                features,
+                advantages: values.iter().map(|value| value - v).collect(),
+                iteration: self.iteration + 1,
+            };
+            self.advantage_buffers[player as usize].push(sample, &mut self.rng);
+            v
+        } else {
+            let action_i = (0..actions.len()).collect::<Vec<usize>>().choose_weighted(&mut self.rng, |i| sigma[*i]).copied().unwrap();
+            let sample = PolicySample {
+                features,
+                probabilities: sigma,
+                iteration: self.iteration + 1,
+            };
+            self.policy_buffer.push(sample, &mut self.rng);
+
+            let mut board_cards_i = 0;
+            let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut board_cards_i, actions[action_i]);
+            self.traverse(child_node_id, board_cards, hole_cards, player)
+        }
+    }
+}
@@ -0,0 +1,404 @@
+// Distributed MCCFR: workers train on their own shards of sampled deals and send how their
+// regrets and average strategy changed to a coordinator, which adds them up, discounts them and
+// sends the sum back for the next round. Node ids have to agree between processes, so every
+// process expands the whole tree before training.
+
+use super::{
+    abstract_game::AbstractGame,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::BucketId,
+    cfr::{CFRConfig, CFREngine, StrategyAveraging},
+    game::Action,
+    node::NodeId,
+    strategy::{Strategy, StrategyMetadata},
+};
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Regrets and average strategy counts of infosets, either everything trained so far or how
+/// much a worker changed them since the last sync
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrainingState {
+    /// Regrets in the order of the actions of the infoset's node
+    pub regrets: BTreeMap<(NodeId, BucketId), Vec<f64>>,
+    pub average_strategy: BTreeMap<(NodeId, BucketId), BTreeMap<Action, i32>>,
+}
+
+impl TrainingState {
+    /// Adds the regrets and counts of `other` to these
+    pub fn add(&mut self, other: &TrainingState) {
+        for (infoset, values) in &other.regrets {
+            let regrets = self.regrets.entry(*infoset).or_insert_with(|| vec![0.; values.len()]);
+            regrets.iter_mut().zip(values).for_each(|(r, v)| *r += v);
+        }
+        for (infoset, counts) in &other.average_strategy {
+            let strategy = self.average_strategy.entry(*infoset).or_default();
+            for (action, count) in counts {
+                *strategy.entry(*action).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// Returns how these regrets and counts changed since `base`, leaving out infosets that
+    /// didn't
+    pub fn difference(&self, base: &TrainingState) -> TrainingState {
+        let regrets = self.regrets.iter()
+            .filter_map(|(infoset, values)| {
+                let diff = match base.regrets.get(infoset) {
+                    Some(old) => values.iter().zip(old).map(|(v, o)| v - o).collect::<Vec<f64>>(),
+                    None => return Some((*infoset, values.clone())),
+                };
+                diff.iter().any(|d| *d != 0.).then_some((*infoset, diff))
+            })
+            .collect();
+        let average_strategy = self.average_strategy.iter()
+            .filter_map(|(infoset, counts)| {
+                let old = base.average_strategy.get(infoset);
+                let diff = counts.iter()
+                    .map(|(action, count)| (*action, count - old.and_then(|o| o.get(action)).copied().unwrap_or(0)))
+                    .collect::<BTreeMap<Action, i32>>();
+                (old.is_none() || diff.values().any(|d| *d != 0)).then_some((*infoset, diff))
+            })
+            .collect();
+        TrainingState { regrets, average_strategy }
+    }
+
+    /// Multiplies every regret and count by `factor`, rounding the counts like `mccfr_p`
+    pub fn scale(&mut self, factor: f32) {
+        for values in self.regrets.values_mut() {
+            values.iter_mut().for_each(|v| *v *= factor as f64);
+        }
+        for counts in self.average_strategy.values_mut() {
+            counts.values_mut().for_each(|c| *c = ((*c as f32) * factor).round() as i32);
+        }
+    }
+}
+
+/// Intervals and thresholds of MCCFR with pruning, see `CFREngine::mccfr_p`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MccfrSchedule {
+    pub strategy_interval: u32,
+    pub prune_threshold: u32,
+    pub lcfr_threshold: u32,
+    pub discount_interval: u32,
+}
+
+impl Default for MccfrSchedule {
+    /// The schedule `train` runs MCCFR with
+    fn default() -> MccfrSchedule {
+        MccfrSchedule {
+            strategy_interval: 20,
+            prune_threshold: 400,
+            lcfr_threshold: 100000,
+            discount_interval: 2500,
+        }
+    }
+}
+
+
+/// Messages between the coordinator and a worker
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Message {
+    /// Sent by a worker when it connects, with the configs it trains
+    Hello(StrategyMetadata),
+    /// Train `ticks` iterations from `state`, numbered from `start_iteration`
+    Job {
+        state: TrainingState,
+        start_iteration: u32,
+        ticks: u32,
+        /// Seed of the deals of this job, so every worker samples its own shard
+        seed: Option<u64>,
+    },
+    /// How a worker changed the state of its last job
+    Delta(TrainingState),
+    /// Training is over, the worker exits
+    Done,
+}
+
+/// Connection between the coordinator and a worker, either end sends and receives through it
+pub trait Link {
+    fn send(&mut self, message: &Message) -> io::Result<()>;
+    fn receive(&mut self) -> io::Result<Message>;
+}
+
+/// Link over a TCP connection, messages are written back to back with bincode
+pub struct TcpLink {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl TcpLink {
+    pub fn new(stream: TcpStream) -> io::Result<TcpLink> {
+        stream.set_nodelay(true)?;
+        Ok(TcpLink {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<TcpLink> {
+        TcpLink::new(TcpStream::connect(address)?)
+    }
+
+    /// Waits for `num_workers` workers to connect to `listener`
+    pub fn accept(listener: &TcpListener, num_workers: usize) -> io::Result<Vec<TcpLink>> {
+        (0..num_workers)
+            .map(|i| {
+                let (stream, address) = listener.accept()?;
+                info!("Worker {} connected from {}", i, address);
+                TcpLink::new(stream)
+            })
+            .collect()
+    }
+}
+
+impl Link for TcpLink {
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        bincode::serialize_into(&mut self.writer, message).map_err(io::Error::other)?;
+        self.writer.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Message> {
+        bincode::deserialize_from(&mut self.reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Time between checks for a new message in a shared directory
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Link through a directory shared by the coordinator and a worker, like a network filesystem
+/// when the machines can't connect to each other. Every message is a numbered file, written to
+/// a temporary file first so it is never read half written, and removed once it was read.
+pub struct FileLink {
+    dir: PathBuf,
+    worker: usize,
+    is_coordinator: bool,
+    sent: u64,
+    received: u64,
+}
+
+impl FileLink {
+    /// The coordinator's end of the link to worker `worker`
+    pub fn coordinator(dir: &Path, worker: usize) -> io::Result<FileLink> {
+        fs::create_dir_all(dir)?;
+        Ok(FileLink { dir: dir.to_path_buf(), worker, is_coordinator: true, sent: 0, received: 0 })
+    }
+
+    /// The end of worker `worker`, each worker of a run needs its own number
+    pub fn worker(dir: &Path, worker: usize) -> io::Result<FileLink> {
+        fs::create_dir_all(dir)?;
+        Ok(FileLink { dir: dir.to_path_buf(), worker, is_coordinator: false, sent: 0, received: 0 })
+    }
+
+    fn path(&self, to_worker: bool, number: u64) -> PathBuf {
+        let direction = if to_worker { "to" } else { "from" };
+        self.dir.join(format!("{}_worker_{}_{}.bin", direction, self.worker, number))
+    }
+}
+
+impl Link for FileLink {
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        let path = self.path(self.is_coordinator, self.sent);
+        let tmp_path = path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut w, message).map_err(io::Error::other)?;
+        w.flush()?;
+        fs::rename(&tmp_path, &path)?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Message> {
+        let path = self.path(!self.is_coordinator, self.received);
+        while !path.exists() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let message = bincode::deserialize_from(BufReader::new(File::open(&path)?))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::remove_file(&path)?;
+        self.received += 1;
+        Ok(message)
+    }
+}
+
+/// Returns the message as an error if it isn't the one expected
+fn unexpected(message: Message) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected message {:?}", message))
+}
+
+/// Trains MCCFR with pruning on workers connected through `Link`s, see the module comment. The
+/// coordinator does the discounting of `MccfrSchedule` itself at the end of every round, so
+/// workers don't discount the same regrets once each.
+pub struct Coordinator {
+    abstract_game: AbstractGame,
+    schedule: MccfrSchedule,
+    /// Iterations every worker runs between syncs
+    sync_interval: u32,
+    seed: Option<u64>,
+    state: TrainingState,
+    iteration: u32,
+    /// Jobs sent so far, numbering the seeds of their shards
+    jobs: u64,
+    cancel_token: CancelToken,
+}
+
+impl Coordinator {
+    pub fn new(mut abstract_game: AbstractGame, schedule: MccfrSchedule, sync_interval: u32, seed: Option<u64>) -> Coordinator {
+        assert!(sync_interval > 0, "sync interval must be positive");
+        abstract_game.expand_tree();
+        Coordinator {
+            abstract_game,
+            schedule,
+            sync_interval,
+            seed,
+            state: TrainingState::default(),
+            iteration: 0,
+            jobs: 0,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn abstract_game(&self) -> &AbstractGame {
+        &self.abstract_game
+    }
+
+    /// Sets the token checked between rounds of `train`
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Checks that every worker trains the same game and abstractions, reading their hellos
+    pub fn handshake<L: Link>(&self, workers: &mut [L]) -> io::Result<()> {
+        for (i, worker) in workers.iter_mut().enumerate() {
+            let metadata = match worker.receive()? {
+                Message::Hello(metadata) => metadata,
+                message => return Err(unexpected(message)),
+            };
+            let mismatches = metadata.mismatches(&self.abstract_game);
+            if !mismatches.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("worker {} trains a different {}", i, mismatches.join(", "))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one round: every worker trains up to `sync_interval` of the iterations left until
+    /// `iterations` and the changes they made are added to the state
+    pub fn round<L: Link>(&mut self, workers: &mut [L], iterations: u32) -> io::Result<()> {
+        let start = self.iteration;
+        let mut busy = Vec::new();
+        for (i, worker) in workers.iter_mut().enumerate() {
+            let start_iteration = start.saturating_add(i as u32 * self.sync_interval);
+            let ticks = iterations.saturating_sub(start_iteration).min(self.sync_interval);
+            if ticks == 0 {
+                break;
+            }
+            worker.send(&Message::Job {
+                state: self.state.clone(),
+                start_iteration,
+                ticks,
+                seed: self.seed.map(|s| s.wrapping_add(self.jobs)),
+            })?;
+            self.jobs += 1;
+            busy.push(i);
+            self.iteration = start_iteration + ticks;
+        }
+
+        let mut total = TrainingState::default();
+        for i in busy {
+            match workers[i].receive()? {
+                Message::Delta(delta) => total.add(&delta),
+                message => return Err(unexpected(message)),
+            }
+        }
+        self.state.add(&total);
+
+        // Discounting at iteration 0 only drops the first iteration in `mccfr_p` but would drop
+        // the whole first round here
+        for t in start.max(1)..self.iteration {
+            if t < self.schedule.lcfr_threshold && t % self.schedule.discount_interval == 0 {
+                let d = (t as f32 / self.schedule.discount_interval as f32) / ((t as f32 / self.schedule.discount_interval as f32) + 1.);
+                self.state.scale(d);
+            }
+        }
+        debug!("Synced {} workers at iteration {}, {} infosets", workers.len(), self.iteration, self.state.regrets.len());
+        Ok(())
+    }
+
+    /// Trains until `iterations`, calling `progress` after every round. If cancelled training
+    /// stops between rounds. Either way the workers are told to exit at the end.
+    pub fn train<L: Link, F: FnMut(&Coordinator)>(&mut self, workers: &mut [L], iterations: u32, mut progress: F) -> io::Result<Result<(), Cancelled>> {
+        let mut result = Ok(());
+        while self.iteration < iterations {
+            if let Err(cancelled) = self.cancel_token.check() {
+                result = Err(cancelled);
+                break;
+            }
+            self.round(workers, iterations)?;
+            progress(self);
+        }
+        for worker in workers.iter_mut() {
+            worker.send(&Message::Done)?;
+        }
+        Ok(result)
+    }
+
+    pub fn average_strategy(&self) -> Strategy {
+        Strategy(self.state.average_strategy.clone())
+    }
+
+    /// Returns the metadata the average strategy is saved with
+    pub fn strategy_metadata(&self) -> StrategyMetadata {
+        StrategyMetadata::new(&self.abstract_game, self.iteration, "distributed_mccfr_p")
+    }
+
+    pub fn save_average_strategy(&self, path: &Path) {
+        self.average_strategy().save(path, &self.strategy_metadata());
+    }
+
+    pub fn save_nodes(&self, path: &Path) {
+        self.abstract_game.nodes.save(path);
+    }
+}
+
+/// Trains the jobs the coordinator sends through `link` until it is done. The deals of a job
+/// come from its seed, or from the seed of `cfr_config` if the coordinator has none.
+pub fn run_worker<L: Link>(mut abstract_game: AbstractGame, mut cfr_config: CFRConfig, schedule: &MccfrSchedule, link: &mut L) -> io::Result<()> {
+    // The coordinator only has the strategy the workers send it
+    if cfr_config.strategy_averaging() != StrategyAveraging::Stored {
+        warn!("Distributed training stores the average strategy");
+        cfr_config.set_strategy_averaging(StrategyAveraging::Stored);
+    }
+    abstract_game.expand_tree();
+    let mut engine = CFREngine::new(abstract_game, cfr_config);
+    link.send(&Message::Hello(engine.strategy_metadata()))?;
+    loop {
+        match link.receive()? {
+            Message::Job { state, start_iteration, ticks, seed } => {
+                debug!("Training iterations {}..{}", start_iteration, start_iteration + ticks);
+                engine.set_training_state(&state);
+                engine.set_iteration(start_iteration);
+                if let Some(seed) = seed {
+                    engine.reseed(seed);
+                }
+                // The coordinator discounts, a threshold of 0 never does
+                engine.mccfr_p(ticks, schedule.strategy_interval, schedule.prune_threshold, 0, schedule.discount_interval).map_err(io::Error::other)?;
+                link.send(&Message::Delta(engine.training_state().difference(&state)))?;
+            },
+            Message::Done => return Ok(()),
+            message => return Err(unexpected(message)),
+        }
+    }
+}
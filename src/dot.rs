@@ -0,0 +1,104 @@
+use super::{
+    abstract_game::AbstractGame,
+    card_abstraction::BucketId,
+    deals::{Deal, Deals},
+    evaluator::HandEvaluator,
+    game::Action,
+    node::NodeId,
+    strategy::Strategy,
+};
+
+use itertools::Itertools;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Writes the abstract game tree as a Graphviz DOT graph. Every node is labelled with the
+/// expected value of each player when everyone plays `strategy`, decision nodes list the
+/// strategy of each bucket of the acting player and edges have the probability of their action
+/// over every deal reaching the node. Enumerates every deal so only small games like Kuhn and
+/// Leduc are feasible.
+pub fn tree_to_dot(abstract_game: &mut AbstractGame, strategy: &Strategy) -> String {
+    let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+    let (deals, reach): (Vec<Deal>, Vec<f64>) = Deals::new(&abstract_game.game_info).unzip();
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph tree {{").unwrap();
+    writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+    let root = abstract_game.nodes.get_root_node_id();
+    write_node(abstract_game, strategy, evaluator, &deals, root, &reach, &mut dot);
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// Writes `node_id` and everything below it, returning the value of every player in each deal
+fn write_node(abstract_game: &mut AbstractGame, strategy: &Strategy, evaluator: &dyn HandEvaluator, deals: &[Deal], node_id: NodeId, reach: &[f64], dot: &mut String) -> Vec<Vec<f64>> {
+    let state = abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+    let game_info = &abstract_game.game_info;
+    let betting = match state.betting_string(game_info) {
+        betting if betting.is_empty() => "root".to_string(),
+        betting => betting,
+    };
+    let total_reach: f64 = reach.iter().sum();
+
+    if state.is_finished() {
+        let side_pots = state.side_pots(game_info);
+        let values = deals.iter()
+            .map(|(hole_cards, board_cards)| state.get_side_pot_payouts(game_info, &side_pots, evaluator, &[board_cards], hole_cards).into_iter().map(|v| v as f64).collect())
+            .collect::<Vec<Vec<f64>>>();
+        let label = format!("{}\\lEV {}\\l", betting, format_values(&values, reach, total_reach));
+        writeln!(dot, "    n{} [label=\"{}\", style=rounded];", node_id, label).unwrap();
+        return values;
+    }
+
+    let player = state.current_player().unwrap();
+    let actions = abstract_game.get_actions(&state);
+    let bucket_ids = deals.iter()
+        .map(|(hole_cards, board_cards)| abstract_game.get_bucket(state.current_round(), board_cards, &hole_cards[player as usize]))
+        .collect::<Vec<BucketId>>();
+    let sigmas = bucket_ids.iter()
+        .unique()
+        .map(|b| (*b, strategy.action_probabilities(node_id, *b, &actions)))
+        .collect::<BTreeMap<BucketId, BTreeMap<Action, f64>>>();
+
+    let num_players = game_info.num_players() as usize;
+    let mut values = vec![vec![0.; num_players]; deals.len()];
+    for action in actions.iter() {
+        let probabilities = bucket_ids.iter().map(|b| sigmas[b][action]).collect::<Vec<f64>>();
+        let action_reach = reach.iter().zip(probabilities.iter()).map(|(r, p)| r * p).collect::<Vec<f64>>();
+
+        let mut board_cards_i = 0;
+        let child_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+        let child_values = write_node(abstract_game, strategy, evaluator, deals, child_id, &action_reach, dot);
+        for ((value, child_value), p) in values.iter_mut().zip(child_values.iter()).zip(probabilities.iter()) {
+            for (v, c) in value.iter_mut().zip(child_value.iter()) {
+                *v += p * c;
+            }
+        }
+
+        let action_probability = if total_reach > 0. { action_reach.iter().sum::<f64>() / total_reach } else { 0. };
+        writeln!(dot, "    n{} -> n{} [label=\"{} {:.3}\"];", node_id, child_id, action, action_probability).unwrap();
+    }
+
+    let mut label = format!("{}\\lplayer {} to act\\lEV {}\\l", betting, player, format_values(&values, reach, total_reach));
+    for (bucket_id, sigma) in sigmas.iter() {
+        let sigma = sigma.iter().map(|(a, p)| format!("{} {:.3}", a, p)).join(", ");
+        write!(label, "bucket {}: {}\\l", bucket_id, sigma).unwrap();
+    }
+    writeln!(dot, "    n{} [label=\"{}\"];", node_id, label).unwrap();
+
+    values
+}
+
+/// Formats the expected value of every player over the deals weighted by their reach
+fn format_values(values: &[Vec<f64>], reach: &[f64], total_reach: f64) -> String {
+    if total_reach <= 0. {
+        return "unreached".to_string();
+    }
+
+    let num_players = values.first().map_or(0, |v| v.len());
+    (0..num_players)
+        .map(|p| values.iter().zip(reach.iter()).map(|(v, r)| v[p] * r).sum::<f64>() / total_reach)
+        .map(|ev| format!("{:.4}", ev))
+        .join(" / ")
+}
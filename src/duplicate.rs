@@ -0,0 +1,139 @@
+use super::{
+    agent::Agent,
+    cancel::{CancelToken, Cancelled},
+    dealer::Dealer,
+    game::GameInfo,
+    live_feed::LiveFeed,
+};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::fmt;
+
+/// Winnings of the first agent of a match in milli big blinds per hand
+#[derive(Copy, Clone, Debug)]
+pub struct MatchResult {
+    pub num_hands: u32,
+    pub mean: f64,
+    /// Standard error of `mean`, estimated from the spread of the duplicate pairs
+    pub std_error: f64,
+    /// Mean and standard error of the winnings with all in pots awarded by equity, set if the
+    /// match adjudicates all ins
+    pub all_in_ev: Option<(f64, f64)>,
+}
+
+impl MatchResult {
+    /// Returns the bounds of the normal confidence interval `z` standard errors wide on each
+    /// side, 1.96 for 95%
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        (self.mean - z * self.std_error, self.mean + z * self.std_error)
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2} ± {:.2} mbb/hand (95%)", self.mean, 1.96 * self.std_error)?;
+        if let Some((mean, std_error)) = self.all_in_ev {
+            write!(f, ", all in EV {:.2} ± {:.2} mbb/hand (95%)", mean, 1.96 * std_error)?;
+        }
+        write!(f, " over {} hands", self.num_hands)
+    }
+}
+
+/// Plays two agents heads up, dealing every hand twice with the seats swapped so the luck of
+/// the cards cancels out and far fewer hands are needed to tell the agents apart
+pub struct DuplicateMatch {
+    game_info: GameInfo,
+    /// Number of duplicate pairs, each is two hands
+    num_pairs: u32,
+    /// Whether to also report the winnings with all in pots awarded by equity
+    all_in_ev: bool,
+    cancel_token: CancelToken,
+    live_feed: Option<LiveFeed>,
+    rng: StdRng,
+}
+
+impl DuplicateMatch {
+    pub fn new(game_info: GameInfo, num_pairs: u32) -> DuplicateMatch {
+        assert!(game_info.num_players() == 2, "duplicate matches are heads up");
+        assert!(num_pairs > 1, "need at least two pairs to estimate the error");
+        DuplicateMatch {
+            game_info,
+            num_pairs,
+            all_in_ev: false,
+            cancel_token: CancelToken::new(),
+            live_feed: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the cards dealt, the agents have their own generators
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Reports the winnings with the pots of hands that went all in before the last round
+    /// awarded by equity next to the dealt winnings, which removes the luck of the runout
+    pub fn set_all_in_ev(&mut self, all_in_ev: bool) {
+        self.all_in_ev = all_in_ev;
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Streams the events of every hand of the next match played with the results of each
+    /// agent, see `LiveFeed`
+    pub fn set_live_feed(&mut self, live_feed: Option<LiveFeed>) {
+        self.live_feed = live_feed;
+    }
+
+    /// Plays the match and returns the winnings of `agents[0]`
+    pub fn play(&mut self, agents: &mut [Box<dyn Agent>]) -> Result<MatchResult, Cancelled> {
+        assert!(agents.len() == 2, "need one agent per seat");
+        let big_blind = self.game_info.big_blind() as f64;
+        let mut dealer = Dealer::new(self.game_info.clone());
+        dealer.set_all_in_ev(self.all_in_ev);
+        dealer.set_live_feed(self.live_feed.take());
+
+        let mut pair_winnings = Vec::with_capacity(self.num_pairs as usize);
+        let mut pair_ev_winnings = Vec::with_capacity(self.num_pairs as usize);
+        for _ in 0..self.num_pairs {
+            self.cancel_token.check()?;
+            let seed = self.rng.gen();
+            let mut won = 0;
+            let mut ev_won = 0.;
+            for swapped in [false, true] {
+                dealer.set_seed(seed);
+                dealer.set_seat_players(if swapped { vec![1, 0] } else { vec![0, 1] });
+                let record = dealer.play_hand(agents).expect("dealer audit is disabled");
+                won += record.payouts[swapped as usize];
+                if let Some(ev_payouts) = &record.all_in_ev_payouts {
+                    ev_won += ev_payouts[swapped as usize];
+                }
+                agents.swap(0, 1);
+            }
+            pair_winnings.push(1000. * won as f64 / (2. * big_blind));
+            pair_ev_winnings.push(1000. * ev_won / (2. * big_blind));
+        }
+        for agent in agents.iter_mut() {
+            agent.end_session(&self.game_info);
+        }
+
+        let (mean, std_error) = mean_and_std_error(&pair_winnings);
+        Ok(MatchResult {
+            num_hands: 2 * self.num_pairs,
+            mean,
+            std_error,
+            all_in_ev: self.all_in_ev.then(|| mean_and_std_error(&pair_ev_winnings)),
+        })
+    }
+}
+
+fn mean_and_std_error(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / (n - 1.);
+    (mean, (variance / n).sqrt())
+}
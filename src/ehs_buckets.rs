@@ -0,0 +1,185 @@
+use super::{
+    card_abstraction::{BucketId, RoundBuckets},
+    card_set::CardSet,
+    evaluator::HandRanking,
+    game::{stable_hash, DeckIndex, GameInfo},
+    parallel::*,
+    range::card_index,
+};
+
+use poker::Card;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Random hands the percentiles of `PercentileEhsBuckets` are estimated from
+const CALIBRATION_HANDS: usize = 2000;
+
+/// Hands whose buckets `PercentileEhsBuckets` remembers, later hands are estimated every time
+const CACHED_HANDS: usize = 1 << 20;
+
+/// Buckets hands by the percentile of their expected hand strength among the hands of the
+/// round, estimated with Monte Carlo whenever a hand is bucketed instead of read from a table,
+/// to try abstractions for a new game without generating tables first. Expected hand strength
+/// is the chance of beating a random hand once the board is complete, counting ties as half.
+///
+/// Each hand is estimated from `num_samples` runouts and opponent hands drawn from a generator
+/// seeded by its cards, so a hand always lands in the same bucket, and the buckets of the first
+/// `CACHED_HANDS` hands are remembered. The bucket boundaries are the strengths of
+/// `CALIBRATION_HANDS` random hands at every percentile, worked out when the buckets are created
+/// or deserialized without them. Configs only need
+/// `{"type": "PercentileEhsBuckets", "num_suits": 4, "num_ranks": 13, "num_hole_cards": 2,
+/// "num_board_cards": 3, "final_board_cards": 5, "num_buckets": 8, "num_samples": 200}`.
+pub struct PercentileEhsBuckets {
+    config: PercentileEhsConfig,
+    /// Strength above which each bucket past the first starts, ascending
+    boundaries: Vec<f32>,
+    /// Bucket of every hand bucketed so far, keyed by `hand_key`
+    cache: Mutex<HashMap<Vec<u8>, BucketId>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PercentileEhsConfig {
+    num_suits: u8,
+    num_ranks: u8,
+    num_hole_cards: u8,
+    num_board_cards: u8,
+    final_board_cards: u8,
+    #[serde(default)]
+    hand_ranking: HandRanking,
+    num_buckets: u32,
+    num_samples: u32,
+    #[serde(default)]
+    seed: u64,
+    /// Saved so loading doesn't estimate them again
+    #[serde(default)]
+    boundaries: Option<Vec<f32>>,
+}
+
+impl PercentileEhsBuckets {
+    /// Creates `num_buckets` buckets for `round`, estimating each hand from `num_samples`
+    /// samples. `seed` picks the calibration hands and the samples of every hand.
+    pub fn new(game_info: &GameInfo, round: u8, num_buckets: u32, num_samples: u32, seed: u64) -> PercentileEhsBuckets {
+        PercentileEhsBuckets::from_config(PercentileEhsConfig {
+            num_suits: game_info.num_suits(),
+            num_ranks: game_info.num_ranks(),
+            num_hole_cards: game_info.num_hole_cards(),
+            num_board_cards: game_info.total_board_cards(round),
+            final_board_cards: game_info.total_board_cards(game_info.num_rounds() - 1),
+            hand_ranking: game_info.hand_ranking(),
+            num_buckets,
+            num_samples,
+            seed,
+            boundaries: None,
+        })
+    }
+
+    fn from_config(mut config: PercentileEhsConfig) -> PercentileEhsBuckets {
+        assert!(config.num_buckets > 0 && config.num_samples > 0, "percentile buckets need buckets and samples");
+        let boundaries = config.boundaries.take().unwrap_or_else(|| {
+            let buckets = PercentileEhsBuckets { config: config.clone(), boundaries: Vec::new(), cache: Mutex::default() };
+            buckets.calibrate()
+        });
+        PercentileEhsBuckets { config, boundaries, cache: Mutex::default() }
+    }
+
+    /// Returns the sorted hole cards followed by the sorted board cards of the round, which is
+    /// all a strength depends on
+    fn hand_key(&self, board_cards: &[Card], hole_cards: &[Card]) -> Vec<u8> {
+        let mut cards = [hole_cards, &board_cards[..self.config.num_board_cards as usize]].map(|c| c.iter().map(|c| card_index(*c)).collect::<Vec<u8>>());
+        cards.iter_mut().for_each(|c| c.sort_unstable());
+        cards.concat()
+    }
+
+    /// Estimates the expected hand strength of the hand, `board_cards` may hold cards of later
+    /// rounds which are ignored
+    pub fn expected_hand_strength(&self, board_cards: &[Card], hole_cards: &[Card]) -> f32 {
+        let mut rng = StdRng::seed_from_u64(self.config.seed ^ stable_hash(&self.hand_key(board_cards, hole_cards)));
+        let board_cards = &board_cards[..self.config.num_board_cards as usize];
+
+        let evaluator = self.config.hand_ranking.evaluator();
+        let direction = self.config.hand_ranking.direction();
+        let remaining = CardSet::from_cards(&[hole_cards, board_cards].concat()).remaining(&self.deck());
+        let num_runout = (self.config.final_board_cards - self.config.num_board_cards) as usize;
+
+        let mut score = 0.;
+        for _ in 0..self.config.num_samples {
+            let drawn = remaining.choose_multiple(&mut rng, num_runout + hole_cards.len()).copied().collect::<Vec<Card>>();
+            let (runout, opponent_hole_cards) = drawn.split_at(num_runout);
+            let final_board = [board_cards, runout].concat();
+            let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, &final_board));
+            let opponent_rank = direction.orient(evaluator.evaluate_showdown(opponent_hole_cards, &final_board));
+            if rank > opponent_rank {
+                score += 1.;
+            } else if rank == opponent_rank {
+                score += 0.5;
+            }
+        }
+        score / self.config.num_samples as f32
+    }
+
+    /// Returns the strengths of random hands at the boundary of every bucket
+    fn calibrate(&self) -> Vec<f32> {
+        let deck = self.deck();
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+        let hands = (0..CALIBRATION_HANDS)
+            .map(|_| {
+                let cards = deck.choose_multiple(&mut rng, (self.config.num_hole_cards + self.config.num_board_cards) as usize).copied().collect::<Vec<Card>>();
+                let (hole_cards, board_cards) = cards.split_at(self.config.num_hole_cards as usize);
+                (hole_cards.to_vec(), board_cards.to_vec())
+            })
+            .collect::<Vec<(Vec<Card>, Vec<Card>)>>();
+        let mut strengths = hands.into_par_iter()
+            .map(|(hole_cards, board_cards)| self.expected_hand_strength(&board_cards, &hole_cards))
+            .collect::<Vec<f32>>();
+        strengths.sort_by(f32::total_cmp);
+
+        (1..self.config.num_buckets)
+            .map(|b| strengths[b as usize * strengths.len() / self.config.num_buckets as usize])
+            .collect()
+    }
+
+    fn deck(&self) -> Vec<Card> {
+        let deck = DeckIndex::new(self.config.num_suits, self.config.num_ranks);
+        Card::generate_deck()
+            .filter(|c| deck.contains(*c))
+            .collect()
+    }
+}
+
+impl Serialize for PercentileEhsBuckets {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PercentileEhsConfig { boundaries: Some(self.boundaries.clone()), ..self.config.clone() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PercentileEhsBuckets {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(PercentileEhsBuckets::from_config(PercentileEhsConfig::deserialize(deserializer)?))
+    }
+}
+
+#[typetag::serde]
+impl RoundBuckets for PercentileEhsBuckets {
+    fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        let key = self.hand_key(board_cards, hole_cards);
+        if let Some(bucket) = self.cache.lock().unwrap().get(&key) {
+            return *bucket;
+        }
+
+        let strength = self.expected_hand_strength(board_cards, hole_cards);
+        let bucket = self.boundaries.partition_point(|b| *b < strength) as BucketId;
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() < CACHED_HANDS {
+            cache.insert(key, bucket);
+        }
+        bucket
+    }
+
+    fn num_buckets(&self) -> BucketId {
+        self.config.num_buckets
+    }
+}
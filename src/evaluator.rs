@@ -0,0 +1,498 @@
+use super::card_set::CardSet;
+
+use poker::{Card, Rank};
+use itertools::Itertools;
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+/// Strength of a showdown hand, equal ranks split the pot and otherwise the winner depends on
+/// the `RankingDirection` of the game
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HandRank(pub u32);
+
+/// Ranks showdown hands made of hole cards and board cards
+pub trait HandEvaluator: Send + Sync {
+    fn evaluate(&self, cards: &[Card]) -> HandRank;
+
+    /// Ranks a player's hand at showdown, games that restrict how hole cards and board cards
+    /// can be combined override this
+    fn evaluate_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> HandRank {
+        self.evaluate(&[hole_cards, board_cards].concat())
+    }
+
+    /// Ranks the low half of a player's hand in hi-lo games, higher ranks are better lows and
+    /// hands without an eight or better low return `None`
+    fn evaluate_low_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> Option<HandRank> {
+        eight_or_better_low(&[hole_cards, board_cards].concat())
+    }
+}
+
+impl<E: HandEvaluator + ?Sized> HandEvaluator for &E {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        (**self).evaluate(cards)
+    }
+
+    fn evaluate_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> HandRank {
+        (**self).evaluate_showdown(hole_cards, board_cards)
+    }
+
+    fn evaluate_low_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> Option<HandRank> {
+        (**self).evaluate_low_showdown(hole_cards, board_cards)
+    }
+}
+
+/// Hand ranking rules used at showdown
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum HandRanking {
+    /// Best five card hand with the usual hold'em ranking, hands with less than five cards are
+    /// ranked by pairs and high cards
+    #[default]
+    Standard,
+    /// Like standard but a flush beats a full house and A-6-7-8-9 is the lowest straight
+    ShortDeck,
+    /// Single card, the highest card wins
+    Kuhn,
+    /// One hole card and one board card, a pair wins and otherwise the highest cards win
+    Leduc,
+    /// Standard ranking but a hand must use exactly two hole cards and three board cards
+    Omaha,
+    /// Deuce to seven lowball, aces are always high, straights and flushes count against the
+    /// hand and the lowest hand wins
+    DeuceToSeven,
+    /// Ace to five lowball, aces are low, straights and flushes don't count and the lowest hand
+    /// wins
+    AceToFive,
+}
+
+/// Whether the highest or the lowest ranked hand wins at showdown
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum RankingDirection {
+    #[default]
+    HighWins,
+    LowWins,
+}
+
+impl RankingDirection {
+    /// Maps `rank` so that hands that win in this direction compare higher
+    pub fn orient(self, rank: HandRank) -> HandRank {
+        match self {
+            RankingDirection::HighWins => rank,
+            RankingDirection::LowWins => HandRank(u32::MAX - rank.0),
+        }
+    }
+}
+
+impl HandRanking {
+    pub fn evaluator(self) -> &'static dyn HandEvaluator {
+        match self {
+            HandRanking::Standard => &StandardEvaluator,
+            HandRanking::ShortDeck => &ShortDeckEvaluator,
+            HandRanking::Kuhn => &KuhnEvaluator,
+            HandRanking::Leduc => &LeducEvaluator,
+            HandRanking::Omaha => &OmahaEvaluator,
+            HandRanking::DeuceToSeven => &DeuceToSevenEvaluator,
+            HandRanking::AceToFive => &AceToFiveEvaluator,
+        }
+    }
+
+    /// Returns an evaluator memoizing this ranking's evaluator, shared by every caller. Worth it
+    /// when the same hands are evaluated many times, like during training.
+    pub fn cached_evaluator(self) -> &'static dyn HandEvaluator {
+        const CACHE_CAPACITY: usize = 1 << 22;
+        static CACHES: [OnceLock<CachingEvaluator<&'static dyn HandEvaluator>>; 7] = [const { OnceLock::new() }; 7];
+
+        CACHES[self as usize].get_or_init(|| CachingEvaluator::new(self.evaluator(), CACHE_CAPACITY))
+    }
+
+    pub fn direction(self) -> RankingDirection {
+        match self {
+            HandRanking::DeuceToSeven | HandRanking::AceToFive => RankingDirection::LowWins,
+            _ => RankingDirection::HighWins,
+        }
+    }
+}
+
+const HIGH_CARD: u32 = 0;
+const PAIR: u32 = 1;
+const TWO_PAIR: u32 = 2;
+const THREE_OF_A_KIND: u32 = 3;
+const STRAIGHT: u32 = 4;
+const FLUSH: u32 = 5;
+const FULL_HOUSE: u32 = 6;
+const FOUR_OF_A_KIND: u32 = 7;
+const STRAIGHT_FLUSH: u32 = 8;
+
+const NUM_RANKS: usize = 13;
+const NUM_SUITS: usize = 4;
+
+/// Packs a hand category and up to five ranks into a `HandRank`, earlier ranks are more
+/// significant
+fn encode(category: u32, ranks: &[u8]) -> HandRank {
+    let mut value = category;
+    for i in 0..5 {
+        value = (value << 4) | ranks.get(i).map_or(0, |r| *r as u32 + 1);
+    }
+    HandRank(value)
+}
+
+/// Returns the top `n` ranks of a rank bitmask, highest first
+fn top_ranks(mask: u16, n: usize) -> Vec<u8> {
+    (0..NUM_RANKS as u8).rev().filter(|r| mask & (1 << r) != 0).take(n).collect()
+}
+
+/// Returns the high rank of the best straight in a rank bitmask, `wheel` is the bitmask of the
+/// lowest straight which uses the ace as a low card
+fn straight_high(mask: u16, wheel: u16) -> Option<u8> {
+    for high in (4..NUM_RANKS as u8).rev() {
+        let straight = 0b11111 << (high - 4);
+        if mask & straight == straight {
+            return Some(high);
+        }
+    }
+
+    if mask & wheel == wheel {
+        // The ace plays low, so the straight is as high as its second highest card
+        return top_ranks(wheel, 2).get(1).copied();
+    }
+
+    None
+}
+
+/// Ranks the best hand of up to five cards out of `cards` with the given lowest straight and
+/// whether flushes beat full houses
+fn evaluate_best_hand(cards: &[Card], wheel: u16, flush_beats_full_house: bool) -> HandRank {
+    let (flush, full_house) = if flush_beats_full_house { (FULL_HOUSE, FLUSH) } else { (FLUSH, FULL_HOUSE) };
+
+    let mut rank_counts = [0u8; NUM_RANKS];
+    let mut suit_masks = [0u16; NUM_SUITS];
+    for card in cards {
+        rank_counts[card.rank() as usize] += 1;
+        suit_masks[card.suit() as usize] |= 1 << card.rank() as u8;
+    }
+    let rank_mask = suit_masks.iter().fold(0, |mask, m| mask | m);
+
+    if let Some(suit_mask) = suit_masks.iter().find(|m| m.count_ones() >= 5) {
+        if let Some(high) = straight_high(*suit_mask, wheel) {
+            return encode(STRAIGHT_FLUSH, &[high]);
+        }
+    }
+
+    // Ranks with at least `count` cards, highest first
+    let ranks_with = |count: u8| (0..NUM_RANKS as u8).rev().filter(move |r| rank_counts[*r as usize] >= count);
+    let kickers = |used: &[u8], n: usize| {
+        let mask = used.iter().fold(rank_mask, |mask, r| mask & !(1 << r));
+        top_ranks(mask, n)
+    };
+
+    if let Some(quads) = ranks_with(4).next() {
+        return encode(FOUR_OF_A_KIND, &[&[quads][..], &kickers(&[quads], 1)].concat());
+    }
+
+    let trips = ranks_with(3).next();
+    let full_house_pair = trips.and_then(|t| ranks_with(2).find(|r| *r != t));
+    let flush_ranks = suit_masks.iter().find(|m| m.count_ones() >= 5).map(|m| top_ranks(*m, 5));
+
+    match (trips, full_house_pair, flush_ranks) {
+        (Some(t), Some(p), Some(f)) => {
+            return if flush > full_house { encode(flush, &f) } else { encode(full_house, &[t, p]) };
+        },
+        (Some(t), Some(p), None) => return encode(full_house, &[t, p]),
+        (_, _, Some(f)) => return encode(flush, &f),
+        _ => {},
+    }
+
+    if let Some(high) = straight_high(rank_mask, wheel) {
+        return encode(STRAIGHT, &[high]);
+    }
+
+    if let Some(t) = trips {
+        return encode(THREE_OF_A_KIND, &[&[t][..], &kickers(&[t], 2)].concat());
+    }
+
+    let pairs = ranks_with(2).take(2).collect::<Vec<u8>>();
+    match pairs.len() {
+        2 => encode(TWO_PAIR, &[&pairs[..], &kickers(&pairs, 1)].concat()),
+        1 => encode(PAIR, &[&pairs[..], &kickers(&pairs, 3)].concat()),
+        _ => encode(HIGH_CARD, &top_ranks(rank_mask, 5)),
+    }
+}
+
+/// Ranks the best ace to five low of five distinct ranks no higher than eight, straights and
+/// flushes don't count against a low. Lower hands get higher ranks.
+pub fn eight_or_better_low(cards: &[Card]) -> Option<HandRank> {
+    // Aces play low, so shift every rank up by one and put the ace at the bottom
+    let low_ranks = cards.iter()
+        .map(|c| if c.rank() == Rank::Ace { 0 } else { c.rank() as u8 + 1 })
+        .filter(|r| *r <= Rank::Eight as u8 + 1)
+        .sorted()
+        .dedup()
+        .take(5)
+        .collect::<Vec<u8>>();
+
+    if low_ranks.len() < 5 {
+        return None;
+    }
+
+    // Compare from the highest card down, inverted so that lower cards rank higher
+    let inverted = low_ranks.iter().rev().map(|r| NUM_RANKS as u8 - 1 - r).collect::<Vec<u8>>();
+    Some(encode(HIGH_CARD, &inverted))
+}
+
+/// Ranks a hand of up to five cards by its pairs, trips and quads only, `ranks` are the ranks of
+/// the cards in any order
+fn evaluate_rank_groups(ranks: &[u8]) -> HandRank {
+    // Groups of equal ranks, biggest groups first and higher ranks first within a size
+    let groups = ranks.iter()
+        .copied()
+        .counts()
+        .into_iter()
+        .sorted_by_key(|(r, c)| Reverse((*c, *r)))
+        .collect::<Vec<(u8, usize)>>();
+
+    let category = match groups.iter().map(|(_, c)| *c).collect::<Vec<usize>>()[..] {
+        [4, ..] => FOUR_OF_A_KIND,
+        [3, 2, ..] => FULL_HOUSE,
+        [3, ..] => THREE_OF_A_KIND,
+        [2, 2, ..] => TWO_PAIR,
+        [2, ..] => PAIR,
+        _ => HIGH_CARD,
+    };
+    encode(category, &groups.iter().map(|(r, _)| *r).collect::<Vec<u8>>())
+}
+
+/// Returns the lowest ranked five card hand out of `cards` using `evaluate`
+fn lowest_five_card_hand(cards: &[Card], evaluate: impl Fn(&[Card]) -> HandRank) -> HandRank {
+    if cards.len() <= 5 {
+        return evaluate(cards);
+    }
+
+    cards.iter()
+        .copied()
+        .combinations(5)
+        .map(|hand| evaluate(&hand))
+        .min()
+        .unwrap()
+}
+
+fn rank_mask(ranks: &[Rank]) -> u16 {
+    ranks.iter().fold(0, |mask, r| mask | (1 << *r as u8))
+}
+
+/// Standard poker hand ranking over the best five cards
+pub struct StandardEvaluator;
+
+impl HandEvaluator for StandardEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        evaluate_best_hand(cards, rank_mask(&[Rank::Ace, Rank::Two, Rank::Three, Rank::Four, Rank::Five]), false)
+    }
+}
+
+/// Short deck (6+) ranking, a flush beats a full house and A-6-7-8-9 is a straight
+pub struct ShortDeckEvaluator;
+
+impl HandEvaluator for ShortDeckEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        evaluate_best_hand(cards, rank_mask(&[Rank::Ace, Rank::Six, Rank::Seven, Rank::Eight, Rank::Nine]), true)
+    }
+}
+
+/// Kuhn poker ranking, hands are a single card
+pub struct KuhnEvaluator;
+
+impl HandEvaluator for KuhnEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        assert!(cards.len() == 1, "kuhn hands have a single card");
+        HandRank(cards[0].rank() as u32)
+    }
+}
+
+/// Leduc poker ranking, hands are a hole card and a board card
+pub struct LeducEvaluator;
+
+impl HandEvaluator for LeducEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        assert!(cards.len() == 2, "leduc hands have two cards");
+        let (high, low) = if cards[0].rank() >= cards[1].rank() {
+            (cards[0].rank() as u8, cards[1].rank() as u8)
+        } else {
+            (cards[1].rank() as u8, cards[0].rank() as u8)
+        };
+
+        if high == low {
+            encode(PAIR, &[high])
+        } else {
+            encode(HIGH_CARD, &[high, low])
+        }
+    }
+}
+
+/// Omaha ranking, the best hand made of exactly two hole cards and three board cards
+pub struct OmahaEvaluator;
+
+impl HandEvaluator for OmahaEvaluator {
+    /// Ranks `cards` as a single hand, use `evaluate_showdown` to apply the Omaha rules
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        StandardEvaluator.evaluate(cards)
+    }
+
+    fn evaluate_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> HandRank {
+        assert!(hole_cards.len() >= 2 && board_cards.len() >= 3, "omaha hands need two hole cards and three board cards");
+        hole_cards.iter()
+            .copied()
+            .combinations(2)
+            .cartesian_product(board_cards.iter().copied().combinations(3).collect::<Vec<Vec<Card>>>())
+            .map(|(hole, board)| StandardEvaluator.evaluate(&[hole, board].concat()))
+            .max()
+            .unwrap()
+    }
+
+    fn evaluate_low_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> Option<HandRank> {
+        hole_cards.iter()
+            .copied()
+            .combinations(2)
+            .cartesian_product(board_cards.iter().copied().combinations(3).collect::<Vec<Vec<Card>>>())
+            .filter_map(|(hole, board)| eight_or_better_low(&[hole, board].concat()))
+            .max()
+    }
+}
+
+/// Deuce to seven lowball ranking, hands are ranked like standard hands without the wheel
+/// straight and the lowest five card hand is used. Pair with `RankingDirection::LowWins`.
+pub struct DeuceToSevenEvaluator;
+
+impl HandEvaluator for DeuceToSevenEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        lowest_five_card_hand(cards, |hand| evaluate_best_hand(hand, 0, false))
+    }
+}
+
+/// Ace to five lowball ranking, aces are the lowest rank and only pairs, trips and quads count.
+/// Pair with `RankingDirection::LowWins`.
+pub struct AceToFiveEvaluator;
+
+impl HandEvaluator for AceToFiveEvaluator {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        lowest_five_card_hand(cards, |hand| {
+            evaluate_rank_groups(&hand.iter().map(|c| if c.rank() == Rank::Ace { 0 } else { c.rank() as u8 + 1 }).collect::<Vec<u8>>())
+        })
+    }
+}
+
+/// Returns the set of `cards` as a 64 bit mask, bit `rank * 4 + suit` is set for every card.
+/// The mask doesn't depend on the order of the cards.
+pub fn card_mask(cards: &[Card]) -> u64 {
+    CardSet::from_cards(cards).0
+}
+
+const NUM_CACHE_SHARDS: usize = 64;
+
+/// Ranks cached by the methods and card masks they were evaluated with
+type CacheShard = RwLock<HashMap<(u64, u64), Option<HandRank>>>;
+
+/// Which method of the evaluator a cached rank came from, kept in the two bits above the cards
+/// of the first mask
+const CACHE_EVALUATE: u64 = 1 << 62;
+const CACHE_SHOWDOWN: u64 = 2 << 62;
+const CACHE_LOW_SHOWDOWN: u64 = 3 << 62;
+
+/// Memoizes the ranks of another evaluator by card set. Lookups are spread over shards behind
+/// their own lock so threads rarely wait on each other, a shard is cleared once it holds more
+/// than its share of `capacity` ranks.
+pub struct CachingEvaluator<E: HandEvaluator> {
+    evaluator: E,
+    shards: Vec<CacheShard>,
+    shard_capacity: usize,
+}
+
+impl<E: HandEvaluator> CachingEvaluator<E> {
+    pub fn new(evaluator: E, capacity: usize) -> CachingEvaluator<E> {
+        CachingEvaluator {
+            evaluator,
+            shards: (0..NUM_CACHE_SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            shard_capacity: capacity.div_ceil(NUM_CACHE_SHARDS).max(1),
+        }
+    }
+
+    fn get_or_insert<F: FnOnce() -> Option<HandRank>>(&self, key: (u64, u64), evaluate: F) -> Option<HandRank> {
+        let shard = &self.shards[((key.0 ^ key.1.rotate_left(32)).wrapping_mul(0x9e3779b97f4a7c15) >> 58) as usize];
+        if let Some(rank) = shard.read().unwrap().get(&key) {
+            return *rank;
+        }
+
+        let rank = evaluate();
+        let mut shard = shard.write().unwrap();
+        if shard.len() >= self.shard_capacity {
+            shard.clear();
+        }
+        shard.insert(key, rank);
+        rank
+    }
+}
+
+impl<E: HandEvaluator> HandEvaluator for CachingEvaluator<E> {
+    fn evaluate(&self, cards: &[Card]) -> HandRank {
+        self.get_or_insert((CACHE_EVALUATE | card_mask(cards), 0), || Some(self.evaluator.evaluate(cards))).unwrap()
+    }
+
+    fn evaluate_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> HandRank {
+        let key = (CACHE_SHOWDOWN | card_mask(hole_cards), card_mask(board_cards));
+        self.get_or_insert(key, || Some(self.evaluator.evaluate_showdown(hole_cards, board_cards))).unwrap()
+    }
+
+    fn evaluate_low_showdown(&self, hole_cards: &[Card], board_cards: &[Card]) -> Option<HandRank> {
+        let key = (CACHE_LOW_SHOWDOWN | card_mask(hole_cards), card_mask(board_cards));
+        self.get_or_insert(key, || self.evaluator.evaluate_low_showdown(hole_cards, board_cards))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use poker::Suit;
+
+    fn card(rank: Rank, suit: Suit) -> Card {
+        Card::new(rank, suit)
+    }
+
+    #[test]
+    fn kuhn_ranks_jack_queen_king() {
+        let rank = |r| KuhnEvaluator.evaluate(&[card(r, Suit::Spades)]);
+        assert!(rank(Rank::Jack) < rank(Rank::Queen));
+        assert!(rank(Rank::Queen) < rank(Rank::King));
+        assert!(rank(Rank::Jack) < rank(Rank::King));
+    }
+
+    #[test]
+    fn kuhn_equal_ranks_tie() {
+        assert_eq!(KuhnEvaluator.evaluate(&[card(Rank::Queen, Suit::Spades)]), KuhnEvaluator.evaluate(&[card(Rank::Queen, Suit::Hearts)]));
+    }
+
+    #[test]
+    fn leduc_pair_beats_any_high_card() {
+        let lowest_pair = LeducEvaluator.evaluate(&[card(Rank::Jack, Suit::Spades), card(Rank::Jack, Suit::Hearts)]);
+        let ranks = [Rank::Jack, Rank::Queen, Rank::King];
+        for (a, b) in ranks.iter().cartesian_product(ranks.iter()).filter(|(a, b)| a != b) {
+            assert!(LeducEvaluator.evaluate(&[card(*a, Suit::Spades), card(*b, Suit::Hearts)]) < lowest_pair);
+        }
+        let highest_pair = LeducEvaluator.evaluate(&[card(Rank::King, Suit::Spades), card(Rank::King, Suit::Hearts)]);
+        assert!(lowest_pair < highest_pair);
+    }
+
+    #[test]
+    fn leduc_high_cards_order_and_tie() {
+        let hand = |a, b| LeducEvaluator.evaluate(&[card(a, Suit::Spades), card(b, Suit::Hearts)]);
+        assert!(hand(Rank::Queen, Rank::Jack) < hand(Rank::King, Rank::Jack));
+        assert!(hand(Rank::King, Rank::Jack) < hand(Rank::King, Rank::Queen));
+        // The order of the cards and their suits don't matter
+        assert_eq!(hand(Rank::King, Rank::Jack), hand(Rank::Jack, Rank::King));
+        assert_eq!(
+            LeducEvaluator.evaluate(&[card(Rank::Queen, Suit::Spades), card(Rank::Queen, Suit::Hearts)]),
+            LeducEvaluator.evaluate(&[card(Rank::Queen, Suit::Hearts), card(Rank::Queen, Suit::Spades)]),
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use super::{
+    abstract_game::AbstractGame,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::BucketId,
+    deals::{Deal, Deals},
+    evaluator::HandEvaluator,
+    game::{Action, GameInfo, PlayerId},
+    node::NodeId,
+    strategy::Strategy,
+    units::Units,
+};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Computes the exact expected value of every player when everyone plays `strategy`, by walking
+/// the whole tree for every deal. Only feasible for small games like Kuhn and Leduc.
+pub fn expected_values(abstract_game: &mut AbstractGame, strategy: &Strategy, cancel_token: &CancelToken) -> Result<Vec<f64>, Cancelled> {
+    let strategies = vec![strategy; abstract_game.game_info.num_players() as usize];
+    seat_expected_values(abstract_game, &strategies, cancel_token)
+}
+
+/// Computes the exact expected value of every player when the player in each seat plays the
+/// strategy at the same index of `strategies`. The strategies must be trained in the same
+/// abstract game.
+pub fn seat_expected_values(abstract_game: &mut AbstractGame, strategies: &[&Strategy], cancel_token: &CancelToken) -> Result<Vec<f64>, Cancelled> {
+    let num_players = abstract_game.game_info.num_players() as usize;
+    assert!(strategies.len() == num_players, "there must be a strategy for every seat");
+    let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+    let (deals, probabilities): (Vec<Deal>, Vec<f64>) = Deals::new(&abstract_game.game_info).unzip();
+
+    let root = abstract_game.nodes.get_root_node_id();
+    let values = traverse(abstract_game, strategies, evaluator, cancel_token, &deals, root)?;
+
+    Ok((0..num_players)
+        .map(|p| values.iter().zip(probabilities.iter()).map(|(v, r)| v[p] * r).sum())
+        .collect())
+}
+
+/// Computes the expected value of `strategy` against `opponent` in a two player game, averaged
+/// over both seats so the advantage of position cancels out
+pub fn head_to_head_value(abstract_game: &mut AbstractGame, strategy: &Strategy, opponent: &Strategy, cancel_token: &CancelToken) -> Result<f64, Cancelled> {
+    assert!(abstract_game.game_info.num_players() == 2, "head to head values need a two player game");
+    let first = seat_expected_values(abstract_game, &[strategy, opponent], cancel_token)?;
+    let second = seat_expected_values(abstract_game, &[opponent, strategy], cancel_token)?;
+
+    Ok((first[0] + second[1]) / 2.)
+}
+
+/// Returns the value of every player in each deal from `node_id`
+fn traverse(abstract_game: &mut AbstractGame, strategies: &[&Strategy], evaluator: &dyn HandEvaluator, cancel_token: &CancelToken, deals: &[Deal], node_id: NodeId) -> Result<Vec<Vec<f64>>, Cancelled> {
+    cancel_token.check()?;
+    let state = abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+    let game_info = &abstract_game.game_info;
+
+    if state.is_finished() {
+        let side_pots = state.side_pots(game_info);
+        return Ok(deals.iter()
+            .map(|(hole_cards, board_cards)| state.get_side_pot_payouts(game_info, &side_pots, evaluator, &[board_cards], hole_cards).into_iter().map(|v| v as f64).collect())
+            .collect());
+    }
+
+    let player = state.current_player().unwrap();
+    let actions = abstract_game.get_actions(&state);
+    let probabilities = deals.iter()
+        .map(|(hole_cards, board_cards)| {
+            let bucket_id = abstract_game.get_bucket(state.current_round(), board_cards, &hole_cards[player as usize]);
+            strategies[player as usize].action_probabilities(node_id, bucket_id, &actions)
+        })
+        .collect::<Vec<_>>();
+
+    let num_players = game_info.num_players() as usize;
+    let mut values = vec![vec![0.; num_players]; deals.len()];
+    for action in actions.iter() {
+        let mut board_cards_i = 0;
+        let child_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+        let child_values = traverse(abstract_game, strategies, evaluator, cancel_token, deals, child_id)?;
+        for ((value, child_value), p) in values.iter_mut().zip(child_values.iter()).zip(probabilities.iter()) {
+            for (v, c) in value.iter_mut().zip(child_value.iter()) {
+                *v += p[action] * c;
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Values of an infoset of the acting player, averaged over the deals reaching it
+#[derive(Clone, Debug, Serialize)]
+pub struct InfosetValue {
+    pub bucket_id: BucketId,
+    /// Probability of dealing and playing to the infoset
+    pub reach: f64,
+    /// Expected value of the acting player at the infoset
+    pub value: f64,
+    /// Probability the strategy takes each action
+    pub strategy: Vec<(Action, f64)>,
+    /// Expected value of the acting player after each action, zero for unreached infosets
+    pub action_values: Vec<(Action, f64)>,
+}
+
+/// Reach probability and values of a node of the abstract game tree
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeValue {
+    pub node_id: NodeId,
+    pub betting: String,
+    /// Acting player, `None` at terminal nodes
+    pub player: Option<PlayerId>,
+    /// Probability of dealing and playing to the node
+    pub reach: f64,
+    /// Expected value of every player at the node, zero if it is never reached
+    pub values: Vec<f64>,
+    /// Infosets of the acting player at the node
+    pub infosets: Vec<InfosetValue>,
+    pub children: Vec<(Action, NodeId)>,
+}
+
+/// Reach probabilities and expected values of every node and infoset of the abstract game tree
+/// when everyone plays a strategy, to see where the value of a strategy comes from
+#[derive(Clone, Debug, Serialize)]
+pub struct EvTree {
+    /// Units of every value in the tree, chips unless converted with `in_units`
+    units: Units,
+    /// Nodes in depth first order, the root first
+    nodes: Vec<NodeValue>,
+    #[serde(skip)]
+    positions: HashMap<NodeId, usize>,
+}
+
+impl EvTree {
+    /// Computes the tree by walking every deal, only feasible for small games like Kuhn and
+    /// Leduc
+    pub fn new(abstract_game: &mut AbstractGame, strategy: &Strategy, cancel_token: &CancelToken) -> Result<EvTree, Cancelled> {
+        let evaluator = abstract_game.game_info.hand_ranking().evaluator();
+        let (deals, reach): (Vec<Deal>, Vec<f64>) = Deals::new(&abstract_game.game_info).unzip();
+
+        let mut tree = EvTree { units: Units::Chips, nodes: Vec::new(), positions: HashMap::new() };
+        let root = abstract_game.nodes.get_root_node_id();
+        tree.visit(abstract_game, strategy, evaluator, cancel_token, &deals, root, &reach)?;
+        Ok(tree)
+    }
+
+    pub fn nodes(&self) -> &[NodeValue] {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> &NodeValue {
+        &self.nodes[0]
+    }
+
+    pub fn node(&self, node_id: NodeId) -> Option<&NodeValue> {
+        self.positions.get(&node_id).map(|i| &self.nodes[*i])
+    }
+
+    /// Returns the node reached by a betting string like `cr/c`
+    pub fn node_by_betting(&self, betting: &str) -> Option<&NodeValue> {
+        self.nodes.iter().find(|n| n.betting == betting)
+    }
+
+    /// Returns the tree with every value converted from chips to `units`
+    pub fn in_units(mut self, game_info: &GameInfo, units: Units) -> EvTree {
+        assert!(self.units == Units::Chips, "ev tree was already converted");
+        let convert = |v: &mut f64| *v = units.from_chips(game_info, *v);
+        for node in self.nodes.iter_mut() {
+            node.values.iter_mut().for_each(convert);
+            for infoset in node.infosets.iter_mut() {
+                convert(&mut infoset.value);
+                infoset.action_values.iter_mut().for_each(|(_, v)| convert(v));
+            }
+        }
+        self.units = units;
+        self
+    }
+
+    pub fn units(&self) -> Units {
+        self.units
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Adds `node_id` and everything below it, returning the value of every player in each deal
+    #[allow(clippy::too_many_arguments)]
+    fn visit(&mut self, abstract_game: &mut AbstractGame, strategy: &Strategy, evaluator: &dyn HandEvaluator, cancel_token: &CancelToken, deals: &[Deal], node_id: NodeId, reach: &[f64]) -> Result<Vec<Vec<f64>>, Cancelled> {
+        cancel_token.check()?;
+        let state = abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+        let game_info = &abstract_game.game_info;
+        let num_players = game_info.num_players() as usize;
+        let position = self.nodes.len();
+        self.positions.insert(node_id, position);
+        self.nodes.push(NodeValue {
+            node_id,
+            betting: state.betting_string(game_info),
+            player: state.current_player().ok(),
+            reach: reach.iter().sum(),
+            values: vec![0.; num_players],
+            infosets: Vec::new(),
+            children: Vec::new(),
+        });
+
+        if state.is_finished() {
+            let side_pots = state.side_pots(game_info);
+            let values = deals.iter()
+                .map(|(hole_cards, board_cards)| state.get_side_pot_payouts(game_info, &side_pots, evaluator, &[board_cards], hole_cards).into_iter().map(|v| v as f64).collect())
+                .collect::<Vec<Vec<f64>>>();
+            self.nodes[position].values = weighted_mean(&values, reach);
+            return Ok(values);
+        }
+
+        let player = state.current_player().unwrap() as usize;
+        let actions = abstract_game.get_actions(&state);
+        let bucket_ids = deals.iter()
+            .map(|(hole_cards, board_cards)| abstract_game.get_bucket(state.current_round(), board_cards, &hole_cards[player]))
+            .collect::<Vec<BucketId>>();
+        let sigmas = bucket_ids.iter()
+            .unique()
+            .map(|b| (*b, strategy.action_probabilities(node_id, *b, &actions)))
+            .collect::<BTreeMap<BucketId, BTreeMap<Action, f64>>>();
+
+        let mut values = vec![vec![0.; num_players]; deals.len()];
+        // Reach weighted sum of the acting player's value after each action in each bucket
+        let mut action_sums = sigmas.keys().map(|b| (*b, Vec::new())).collect::<BTreeMap<BucketId, Vec<f64>>>();
+        for action in actions.iter() {
+            let probabilities = bucket_ids.iter().map(|b| sigmas[b][action]).collect::<Vec<f64>>();
+            let action_reach = reach.iter().zip(probabilities.iter()).map(|(r, p)| r * p).collect::<Vec<f64>>();
+
+            let mut board_cards_i = 0;
+            let child_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+            let child_values = self.visit(abstract_game, strategy, evaluator, cancel_token, deals, child_id, &action_reach)?;
+            self.nodes[position].children.push((*action, child_id));
+
+            for sums in action_sums.values_mut() {
+                sums.push(0.);
+            }
+            for (((value, child_value), p), (b, r)) in values.iter_mut().zip(child_values.iter()).zip(probabilities.iter()).zip(bucket_ids.iter().zip(reach)) {
+                for (v, c) in value.iter_mut().zip(child_value.iter()) {
+                    *v += p * c;
+                }
+                *action_sums.get_mut(b).unwrap().last_mut().unwrap() += r * child_value[player];
+            }
+        }
+
+        let mut bucket_reach = BTreeMap::new();
+        let mut bucket_values = BTreeMap::new();
+        for ((b, r), value) in bucket_ids.iter().zip(reach).zip(values.iter()) {
+            *bucket_reach.entry(*b).or_insert(0.) += r;
+            *bucket_values.entry(*b).or_insert(0.) += r * value[player];
+        }
+        let node = &mut self.nodes[position];
+        node.values = weighted_mean(&values, reach);
+        node.infosets = sigmas.into_iter()
+            .map(|(bucket_id, sigma)| {
+                let reach = bucket_reach[&bucket_id];
+                let mean = |sum: f64| if reach > 0. { sum / reach } else { 0. };
+                InfosetValue {
+                    bucket_id,
+                    reach,
+                    value: mean(bucket_values[&bucket_id]),
+                    action_values: actions.iter().zip(&action_sums[&bucket_id]).map(|(a, s)| (*a, mean(*s))).collect(),
+                    strategy: sigma.into_iter().collect(),
+                }
+            })
+            .collect();
+
+        Ok(values)
+    }
+}
+
+/// Averages the value of every player over the deals weighted by their reach, zero if no deal
+/// reaches
+fn weighted_mean(values: &[Vec<f64>], reach: &[f64]) -> Vec<f64> {
+    let total_reach: f64 = reach.iter().sum();
+    let num_players = values.first().map_or(0, |v| v.len());
+    (0..num_players)
+        .map(|p| if total_reach > 0. { values.iter().zip(reach).map(|(v, r)| v[p] * r).sum::<f64>() / total_reach } else { 0. })
+        .collect()
+}
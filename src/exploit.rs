@@ -0,0 +1,71 @@
+use super::{
+    abstract_game::AbstractGame,
+    best_response,
+    cancel::{CancelToken, Cancelled},
+    deals::{Deal, Deals},
+    game::{Action, PlayerId},
+    node::NodeId,
+    strategy::{Strategy, StrategyProfile, PROBABILITY_SCALE},
+};
+
+use std::collections::BTreeMap;
+
+/// Builds an estimate of an opponent's strategy from the frequency of each action at nodes of
+/// the abstract game, like how often they fold to a continuation bet. Frequencies don't depend
+/// on cards so every hand at a node plays them, nodes without frequencies are played like
+/// `base`, usually an equilibrium strategy. Enumerates the deals to find the buckets at each
+/// node, so only feasible for small games.
+pub fn estimated_strategy(abstract_game: &AbstractGame, base: &Strategy, frequencies: &BTreeMap<NodeId, BTreeMap<Action, f64>>) -> Strategy {
+    let mut strategy = Strategy(base.0.clone());
+    let deals = Deals::new(&abstract_game.game_info).map(|(deal, _)| deal).collect::<Vec<Deal>>();
+
+    for (node_id, action_frequencies) in frequencies {
+        let state = &abstract_game.nodes.get_node(*node_id).expect("node isn't in the abstract game").state;
+        let player = state.current_player().expect("frequencies must be of a node where a player acts") as usize;
+        let actions = abstract_game.get_actions(state);
+        let total = actions.iter().map(|a| action_frequencies.get(a).copied().unwrap_or(0.).max(0.)).sum::<f64>();
+        assert!(total > 0., "frequencies of a node must have a positive action");
+
+        let counts = actions.iter()
+            .map(|a| (*a, (action_frequencies.get(a).copied().unwrap_or(0.).max(0.) / total * PROBABILITY_SCALE as f64).round() as i32))
+            .collect::<BTreeMap<Action, i32>>();
+        for (hole_cards, board_cards) in deals.iter() {
+            let bucket_id = abstract_game.get_bucket(state.current_round(), board_cards, &hole_cards[player]);
+            strategy.0.insert((*node_id, bucket_id), counts.clone());
+        }
+    }
+
+    strategy
+}
+
+/// Computes a response of `player` to the estimated strategy `opponent` that plays the best
+/// response with probability `exploitation` and `equilibrium` otherwise at each infoset. An
+/// `exploitation` of 1 is the maximally exploitative best response, lower values give up some of
+/// the gain in exchange for losing less when the estimate is wrong.
+pub fn regularized_response(abstract_game: &mut AbstractGame, opponent: &Strategy, equilibrium: &Strategy, player: PlayerId, exploitation: f64, cancel_token: &CancelToken) -> Result<Strategy, Cancelled> {
+    assert!((0. ..=1.).contains(&exploitation), "exploitation must be in [0, 1]");
+    let response = best_response::best_response_strategy(abstract_game, opponent, player, cancel_token)?;
+
+    Ok(Strategy(response.0.into_iter()
+        .map(|((node_id, bucket_id), best)| {
+            let actions = best.keys().copied().collect::<Vec<Action>>();
+            let sigma = equilibrium.action_probabilities(node_id, bucket_id, &actions);
+            let counts = best.iter()
+                .map(|(a, c)| (*a, ((exploitation * *c as f64 + (1. - exploitation) * sigma[a]) * PROBABILITY_SCALE as f64).round() as i32))
+                .collect();
+            ((node_id, bucket_id), counts)
+        })
+        .collect()))
+}
+
+/// Computes the regularized response of every seat to `opponent` and returns them together as
+/// a profile, so whichever seat the agent gets it exploits the estimate
+pub fn exploitative_profile(mut abstract_game: AbstractGame, opponent: &Strategy, equilibrium: &Strategy, exploitation: f64, cancel_token: &CancelToken) -> Result<StrategyProfile, Cancelled> {
+    let mut strategy = Strategy::new();
+    for player in 0..abstract_game.game_info.num_players() {
+        // Infosets of different players are at different nodes
+        strategy.0.extend(regularized_response(&mut abstract_game, opponent, equilibrium, player, exploitation, cancel_token)?.0);
+    }
+
+    Ok(StrategyProfile::new(abstract_game, strategy))
+}
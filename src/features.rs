@@ -0,0 +1,126 @@
+use super::game::{Action, GameInfo, GameState, PlayerId};
+
+use poker::Card;
+
+/// Turns what a player knows about a hand into a fixed length vector for neural methods. The
+/// vector holds, in order:
+///
+/// - a one-hot of the player's hole cards and one of the board cards over the deck
+/// - a one-hot of the current round and one of the player's seat
+/// - the remaining stack, chips bet and whether they folded of every player, starting with the
+///   player and going around the table
+/// - the pot and the amount the player has to call
+/// - every action of the first `max_actions` of each round as fold, call, raise and discard
+///   flags followed by the raise to amount or the fraction of hole cards discarded
+///
+/// Chip amounts are divided by the largest starting stack.
+#[derive(Clone, Debug)]
+pub struct FeatureEncoder {
+    deck: Vec<Card>,
+    num_players: usize,
+    num_rounds: usize,
+    num_hole_cards: usize,
+    total_board_cards: usize,
+    max_actions: usize,
+    chips_scale: f32,
+}
+
+/// Features of each encoded action
+const ACTION_FEATURES: usize = 5;
+
+impl FeatureEncoder {
+    pub fn new(game_info: &GameInfo, max_actions: usize) -> FeatureEncoder {
+        let last_round = game_info.num_rounds() - 1;
+        FeatureEncoder {
+            deck: game_info.generate_deck().collect(),
+            num_players: game_info.num_players() as usize,
+            num_rounds: game_info.num_rounds() as usize,
+            num_hole_cards: game_info.num_player_cards(last_round) as usize,
+            total_board_cards: game_info.total_board_cards(last_round) as usize,
+            max_actions,
+            chips_scale: game_info.starting_stacks().iter().copied().max().unwrap_or(1).max(1) as f32,
+        }
+    }
+
+    /// Returns the length of every encoded vector
+    pub fn len(&self) -> usize {
+        2 * self.deck.len() + self.num_rounds + 4 * self.num_players + 2 + self.num_rounds * self.max_actions * ACTION_FEATURES
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the index of `card` in the deck, which is the one-hot position and embedding index
+    /// of the card
+    pub fn card_index(&self, card: Card) -> usize {
+        self.deck.iter().position(|c| *c == card).expect("card isn't in the deck")
+    }
+
+    /// Returns the embedding index of every hole card and then every board card, slots of cards
+    /// that aren't dealt yet hold the padding index, which is the size of the deck
+    pub fn card_indices(&self, hole_cards: &[Card], board_cards: &[Card]) -> Vec<usize> {
+        let padding = self.deck.len();
+        let mut indices = vec![padding; self.num_hole_cards + self.total_board_cards];
+        for (i, card) in hole_cards.iter().take(self.num_hole_cards).enumerate() {
+            indices[i] = self.card_index(*card);
+        }
+        for (i, card) in board_cards.iter().take(self.total_board_cards).enumerate() {
+            indices[self.num_hole_cards + i] = self.card_index(*card);
+        }
+        indices
+    }
+
+    /// Encodes the hand as `player` sees it, `board_cards` are only the board cards dealt so far
+    pub fn encode(&self, game_info: &GameInfo, state: &GameState, player: PlayerId, hole_cards: &[Card], board_cards: &[Card]) -> Vec<f32> {
+        let mut features = Vec::with_capacity(self.len());
+
+        let mut cards = vec![0.; 2 * self.deck.len()];
+        for card in hole_cards {
+            cards[self.card_index(*card)] = 1.;
+        }
+        for card in board_cards {
+            cards[self.deck.len() + self.card_index(*card)] = 1.;
+        }
+        features.extend(cards);
+
+        features.extend((0..self.num_rounds).map(|r| (r == state.current_round() as usize) as u8 as f32));
+        features.extend((0..self.num_players).map(|p| (p == player as usize) as u8 as f32));
+
+        for i in 0..self.num_players {
+            let p = ((player as usize + i) % self.num_players) as PlayerId;
+            let spent = state.player_spent(p);
+            features.push((state.player_stack(p) - spent) as f32 / self.chips_scale);
+            features.push(spent as f32 / self.chips_scale);
+            features.push(state.has_folded(p) as u8 as f32);
+        }
+
+        features.push(state.pot_total(game_info) as f32 / self.chips_scale);
+        let to_call = state.max_spent().saturating_sub(state.player_spent(player).saturating_sub(game_info.dead_blind(player)));
+        features.push(to_call as f32 / self.chips_scale);
+
+        for round in 0..self.num_rounds {
+            let mut actions = vec![0.; self.max_actions * ACTION_FEATURES];
+            if round <= state.current_round() as usize {
+                for (i, (_, action)) in state.actions_in_round(round as u8).take(self.max_actions).enumerate() {
+                    let slot = &mut actions[i * ACTION_FEATURES..(i + 1) * ACTION_FEATURES];
+                    match action {
+                        Action::Fold => slot[0] = 1.,
+                        Action::Call => slot[1] = 1.,
+                        Action::Raise(r) => {
+                            slot[2] = 1.;
+                            slot[4] = r as f32 / self.chips_scale;
+                        },
+                        Action::Discard(mask) => {
+                            slot[3] = 1.;
+                            slot[4] = mask.count_ones() as f32 / self.num_hole_cards as f32;
+                        },
+                    }
+                }
+            }
+            features.extend(actions);
+        }
+
+        features
+    }
+}
@@ -0,0 +1,138 @@
+use super::{
+    abstract_game::AbstractGame,
+    best_response,
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::BucketId,
+    deals::{Deal, Deals},
+    game::PlayerId,
+    node::NodeId,
+    strategy::{Strategy, StrategyMetadata},
+};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Solves a game with full width fictitious play, every iteration each player best responds to
+/// the average strategy of the others and the response is mixed into its average with weight
+/// 1/t. Only the average strategy is stored, so it needs about half the memory of CFR, but best
+/// responses walk the whole tree so only small games are feasible.
+pub struct FictitiousPlay {
+    abstract_game: AbstractGame,
+    /// Counts of every best response so far that reaches an infoset and plays each action there,
+    /// which normalize to the behavior strategy of their uniform mixture
+    average_strategy: Strategy,
+    iteration: u32,
+    cancel_token: CancelToken,
+}
+
+impl FictitiousPlay {
+    pub fn new(abstract_game: AbstractGame) -> FictitiousPlay {
+        FictitiousPlay {
+            abstract_game,
+            average_strategy: Strategy::new(),
+            iteration: 0,
+            cancel_token: CancelToken::new(),
+        }
+    }
+
+    pub fn average_strategy(&self) -> &Strategy {
+        &self.average_strategy
+    }
+
+    pub fn abstract_game_mut(&mut self) -> &mut AbstractGame {
+        &mut self.abstract_game
+    }
+
+    /// Consumes the solver, returning the abstract game and average strategy it trained
+    pub fn into_parts(self) -> (AbstractGame, Strategy) {
+        (self.abstract_game, self.average_strategy)
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Sets the token checked during iterations of `fictitious_play` and during `exploitability`
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Computes the exploitability of the current average strategy
+    pub fn exploitability(&mut self) -> Result<f64, Cancelled> {
+        best_response::exploitability(&mut self.abstract_game, &self.average_strategy, &self.cancel_token)
+    }
+
+    pub fn save_average_strategy(&self, path: &Path) {
+        self.average_strategy.save(path, &StrategyMetadata::new(&self.abstract_game, self.iteration, "fictitious_play"));
+    }
+
+    pub fn save_nodes(&self, path: &Path) {
+        self.abstract_game.nodes.save(path);
+    }
+
+    /// Runs `ticks` iterations, all players respond to the same average strategy. If cancelled
+    /// the iteration in progress is discarded, so it can be resumed later.
+    pub fn fictitious_play(&mut self, ticks: u32) -> Result<(), Cancelled> {
+        let (deals, _): (Vec<Deal>, Vec<f64>) = Deals::new(&self.abstract_game.game_info).unzip();
+
+        for _ in 0..ticks {
+            enter_span!("fictitious_play", iteration = self.iteration);
+            info!("Fictitious play iteration {:?}, {} nodes", self.iteration, self.abstract_game.nodes.len());
+            let responses = (0..self.abstract_game.game_info.num_players())
+                .map(|p| best_response::best_response_strategy(&mut self.abstract_game, &self.average_strategy, p, &self.cancel_token))
+                .collect::<Result<Vec<Strategy>, Cancelled>>()?;
+
+            let deal_ids = (0..deals.len()).collect::<Vec<usize>>();
+            let root = self.abstract_game.nodes.get_root_node_id();
+            for (player, response) in responses.iter().enumerate() {
+                self.add_response(response, &deals, root, player as PlayerId, &deal_ids);
+            }
+            self.iteration += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Counts the actions `response` plays at the infosets of `player` it reaches from
+    /// `node_id`. `deal_ids` are the deals where the response reaches the node.
+    fn add_response(&mut self, response: &Strategy, deals: &[Deal], node_id: NodeId, player: PlayerId, deal_ids: &[usize]) {
+        let state = self.abstract_game.nodes.get_node(node_id).unwrap().state.clone();
+        if deal_ids.is_empty() || state.is_finished() || state.has_folded(player) {
+            return;
+        }
+
+        let actions = self.abstract_game.get_actions(&state);
+        if state.current_player().unwrap() != player {
+            for action in actions {
+                let mut board_cards_i = 0;
+                let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut board_cards_i, action);
+                self.add_response(response, deals, child_node_id, player, deal_ids);
+            }
+            return;
+        }
+
+        let mut infosets: BTreeMap<BucketId, Vec<usize>> = BTreeMap::new();
+        for &d in deal_ids {
+            let bucket_id = self.abstract_game.get_bucket(state.current_round(), &deals[d].1, &deals[d].0[player as usize]);
+            infosets.entry(bucket_id).or_default().push(d);
+        }
+
+        let mut action_deal_ids = vec![Vec::new(); actions.len()];
+        for (bucket_id, infoset_deal_ids) in infosets {
+            let sigma = response.action_probabilities(node_id, bucket_id, &actions);
+            let (action_i, action) = actions.iter().enumerate().find(|(_, a)| sigma[a] > 0.).unwrap();
+            action_deal_ids[action_i].extend(infoset_deal_ids);
+
+            *self.average_strategy.0.entry((node_id, bucket_id))
+                .or_insert_with(|| actions.iter().map(|a| (*a, 0)).collect())
+                .entry(*action)
+                .or_insert(0) += 1;
+        }
+
+        for (action, action_deal_ids) in actions.into_iter().zip(action_deal_ids) {
+            let mut board_cards_i = 0;
+            let child_node_id = self.abstract_game.apply_action_to_node(node_id, &mut board_cards_i, action);
+            self.add_response(response, deals, child_node_id, player, &action_deal_ids);
+        }
+    }
+}
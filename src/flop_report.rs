@@ -0,0 +1,374 @@
+use super::{
+    card_set::format_cards,
+    game::{Action, GameInfo, GameState, PlayerId},
+    range::{Combo, Range},
+    showdown::equities,
+    strategy::StrategyProfile,
+};
+
+use poker::{Card, Rank, Suit};
+use itertools::Itertools;
+use serde::Serialize;
+use variter::VarIter;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+/// Bins of equal width the equity of a range is spread over
+const NUM_EQUITY_BINS: usize = 10;
+
+/// Hands printed at each end of a range
+const NUM_EXTREME_HANDS: usize = 5;
+
+/// What a hand makes on the board, the usual classes of a study group report from the strongest
+/// down. Pairs are placed against the board cards, draws only count for hands without a pair
+/// and straights are those of the standard deck.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum HandClass {
+    StraightFlush,
+    Quads,
+    FullHouse,
+    Flush,
+    Straight,
+    /// Pocket pair matching a board card
+    Set,
+    /// Hole card matching a board pair
+    Trips,
+    TwoPair,
+    /// Pocket pair above every board card
+    Overpair,
+    TopPair,
+    /// Pair with the second board rank, or a pocket pair between the top two
+    SecondPair,
+    WeakPair,
+    FlushDraw,
+    /// Open ended or gutshot
+    StraightDraw,
+    /// Both hole cards above every board card
+    Overcards,
+    Nothing,
+}
+
+impl HandClass {
+    /// Classifies the hole cards on the board, which has at least one card
+    pub fn new(hole_cards: [Card; 2], board_cards: &[Card]) -> HandClass {
+        let cards = [&hole_cards[..], board_cards].concat();
+        let count = |cards: &[Card], rank: Rank| cards.iter().filter(|c| c.rank() == rank).count();
+        let counts = Rank::ALL_VARIANTS.iter().map(|r| count(&cards, *r)).collect::<Vec<usize>>();
+        let flush_suit = Suit::ALL_VARIANTS.iter().copied().find(|s| cards.iter().filter(|c| c.suit() == *s).count() >= 5);
+
+        if let Some(suit) = flush_suit {
+            if longest_straight(&cards.iter().filter(|c| c.suit() == suit).map(|c| c.rank()).collect::<Vec<Rank>>()).0 >= 5 {
+                return HandClass::StraightFlush;
+            }
+        }
+        if counts.iter().any(|c| *c >= 4) {
+            return HandClass::Quads;
+        }
+        if counts.iter().any(|c| *c >= 3) && counts.iter().filter(|c| **c >= 2).count() >= 2 {
+            return HandClass::FullHouse;
+        }
+        if flush_suit.is_some() {
+            return HandClass::Flush;
+        }
+        let (straight, draw) = longest_straight(&cards.iter().map(|c| c.rank()).collect::<Vec<Rank>>());
+        if straight >= 5 {
+            return HandClass::Straight;
+        }
+
+        let [high, low] = hole_cards.map(|c| c.rank());
+        let pocket_pair = high == low;
+        if let Some(trips) = [high, low].into_iter().find(|r| counts[*r as usize] >= 3) {
+            return if pocket_pair && count(board_cards, trips) >= 1 { HandClass::Set } else { HandClass::Trips };
+        }
+
+        let board_ranks = board_cards.iter().map(|c| c.rank()).sorted().rev().dedup().collect::<Vec<Rank>>();
+        let paired = [high, low].into_iter().dedup().filter(|r| counts[*r as usize] >= 2).collect::<Vec<Rank>>();
+        let board_pairs = board_ranks.iter().filter(|r| count(board_cards, **r) >= 2).count();
+        match paired.len() {
+            2 => return HandClass::TwoPair,
+            1 if board_pairs > 0 => return HandClass::TwoPair,
+            1 if pocket_pair && high > board_ranks[0] => return HandClass::Overpair,
+            1 if pocket_pair => return if board_ranks.get(1).is_none_or(|r| high > *r) { HandClass::SecondPair } else { HandClass::WeakPair },
+            1 if paired[0] == board_ranks[0] => return HandClass::TopPair,
+            1 if board_ranks.get(1) == Some(&paired[0]) => return HandClass::SecondPair,
+            1 => return HandClass::WeakPair,
+            _ => {},
+        }
+
+        let drawing = board_cards.len() < 5;
+        let flush_draw = hole_cards.iter().any(|h| cards.iter().filter(|c| c.suit() == h.suit()).count() >= 4);
+        if drawing && flush_draw {
+            HandClass::FlushDraw
+        } else if drawing && draw {
+            HandClass::StraightDraw
+        } else if low > board_ranks[0] {
+            HandClass::Overcards
+        } else {
+            HandClass::Nothing
+        }
+    }
+}
+
+impl fmt::Display for HandClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HandClass::StraightFlush => "straight flush",
+            HandClass::Quads => "quads",
+            HandClass::FullHouse => "full house",
+            HandClass::Flush => "flush",
+            HandClass::Straight => "straight",
+            HandClass::Set => "set",
+            HandClass::Trips => "trips",
+            HandClass::TwoPair => "two pair",
+            HandClass::Overpair => "overpair",
+            HandClass::TopPair => "top pair",
+            HandClass::SecondPair => "second pair",
+            HandClass::WeakPair => "weak pair",
+            HandClass::FlushDraw => "flush draw",
+            HandClass::StraightDraw => "straight draw",
+            HandClass::Overcards => "overcards",
+            HandClass::Nothing => "nothing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns the most consecutive ranks among `ranks`, aces also counting low, and whether some
+/// five rank window holds four of them, a straight draw
+fn longest_straight(ranks: &[Rank]) -> (usize, bool) {
+    // Ace low first, then two up to ace
+    let mut present = [false; 14];
+    for rank in ranks {
+        present[*rank as usize + 1] = true;
+        if *rank == Rank::Ace {
+            present[0] = true;
+        }
+    }
+
+    let longest = present.iter().fold((0, 0), |(run, best), p| if *p { (run + 1, best.max(run + 1)) } else { (0, best) }).1;
+    let draw = present.windows(5).any(|w| w.iter().filter(|p| **p).count() >= 4);
+    (longest, draw)
+}
+
+/// One combo of a range on the board
+#[derive(Clone, Debug, Serialize)]
+pub struct ComboReport {
+    pub combo: String,
+    pub weight: f64,
+    pub class: HandClass,
+    /// Probability of winning against the other range by the river, ties counting half
+    pub equity: f64,
+    /// Frequency of each of the report's actions, empty without a strategy or for the player
+    /// not to act
+    pub frequencies: Vec<f64>,
+    #[serde(skip)]
+    cards: [Card; 2],
+}
+
+/// Combos of a range making the same class of hand
+#[derive(Clone, Debug, Serialize)]
+pub struct ClassReport {
+    pub class: HandClass,
+    /// Weight of the combos making the class
+    pub weight: f64,
+    /// Share of the range making the class
+    pub fraction: f64,
+    pub equity: f64,
+    /// Frequency of each of the report's actions over the combos of the class, weighted by
+    /// their range weight
+    pub frequencies: Vec<f64>,
+}
+
+/// Range of one player on the board
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerReport {
+    pub player: PlayerId,
+    pub equity: f64,
+    /// Share of the range with an equity in each of `NUM_EQUITY_BINS` bins of equal width from
+    /// 0 to 1
+    pub equity_histogram: Vec<f64>,
+    /// Combos not blocked by the board, highest equity first
+    pub combos: Vec<ComboReport>,
+    /// Classes the range makes, strongest first
+    pub classes: Vec<ClassReport>,
+}
+
+impl PlayerReport {
+    fn new(player: PlayerId, combos: Vec<ComboReport>) -> PlayerReport {
+        let total = combos.iter().map(|c| c.weight).sum::<f64>();
+        let average = |combos: &[&ComboReport], value: &dyn Fn(&ComboReport) -> f64| {
+            let weight = combos.iter().map(|c| c.weight).sum::<f64>();
+            if weight > 0. { combos.iter().map(|c| c.weight * value(c)).sum::<f64>() / weight } else { 0. }
+        };
+
+        let mut equity_histogram = vec![0.; NUM_EQUITY_BINS];
+        for combo in &combos {
+            equity_histogram[((combo.equity * NUM_EQUITY_BINS as f64) as usize).min(NUM_EQUITY_BINS - 1)] += combo.weight / total;
+        }
+
+        let mut by_class: BTreeMap<HandClass, Vec<&ComboReport>> = BTreeMap::new();
+        for combo in &combos {
+            by_class.entry(combo.class).or_default().push(combo);
+        }
+        let classes = by_class.into_iter()
+            .map(|(class, combos)| {
+                let weight = combos.iter().map(|c| c.weight).sum::<f64>();
+                let num_actions = combos[0].frequencies.len();
+                ClassReport {
+                    class,
+                    weight,
+                    fraction: weight / total,
+                    equity: average(&combos, &|c| c.equity),
+                    frequencies: (0..num_actions).map(|i| average(&combos, &|c| c.frequencies[i])).collect(),
+                }
+            })
+            .collect();
+
+        PlayerReport {
+            player,
+            equity: average(&combos.iter().collect::<Vec<&ComboReport>>(), &|c| c.equity),
+            equity_histogram,
+            combos,
+            classes,
+        }
+    }
+
+    /// Returns the `n` combos with the highest equity
+    pub fn best(&self, n: usize) -> &[ComboReport] {
+        &self.combos[..n.min(self.combos.len())]
+    }
+
+    /// Returns the `n` combos with the lowest equity, lowest first
+    pub fn worst(&self, n: usize) -> Vec<&ComboReport> {
+        self.combos.iter().rev().take(n).collect()
+    }
+}
+
+/// Two ranges against each other on a board: how their equity is spread, their best and worst
+/// hands and the classes of hands they make, along with how a strategy plays each class once
+/// one is added. Only for heads up hold'em like games on the standard deck.
+#[derive(Clone, Debug, Serialize)]
+pub struct FlopReport {
+    pub board: String,
+    /// Betting the strategy was looked up at, see `format_betting`, `None` without a strategy
+    pub betting: Option<String>,
+    /// Actions of the player to act, empty without a strategy
+    pub actions: Vec<Action>,
+    pub players: Vec<PlayerReport>,
+}
+
+impl FlopReport {
+    /// Values `ranges[p]` of every player `p` against the other on the board, without the combos
+    /// the board blocks
+    pub fn new(game_info: &GameInfo, board_cards: &[Card], ranges: [&Range; 2]) -> FlopReport {
+        assert!(game_info.num_players() == 2, "flop reports compare two ranges");
+        assert!(game_info.num_hole_cards() == 2 && !game_info.has_draws() && !game_info.is_stud(), "flop reports need a hold'em like game");
+        assert!(game_info.generate_deck().count() == 52, "flop reports need the standard deck");
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        assert!(!board_cards.is_empty() && board_cards.len() <= num_board_cards, "board has to be dealt after the preflop");
+
+        let evaluator = game_info.hand_ranking().evaluator();
+        let ranges = ranges.map(|r| r.without_blocked(board_cards));
+        let players = (0..2)
+            .map(|p| {
+                let hands = ranges[p].iter().map(|(c, _)| *c).collect::<Vec<Combo>>();
+                let equities = equities(evaluator, game_info.ranking_direction(), board_cards, num_board_cards, &hands, &ranges[1 - p]);
+                let combos = hands.iter()
+                    .zip(equities)
+                    .map(|(combo, equity)| ComboReport {
+                        combo: combo.to_string(),
+                        weight: ranges[p].weight(combo),
+                        class: HandClass::new(combo.cards(), board_cards),
+                        equity,
+                        frequencies: Vec::new(),
+                        cards: combo.cards(),
+                    })
+                    .sorted_by(|a, b| b.equity.total_cmp(&a.equity))
+                    .collect();
+                PlayerReport::new(p as PlayerId, combos)
+            })
+            .collect();
+
+        FlopReport {
+            board: format_cards(board_cards),
+            betting: None,
+            actions: Vec::new(),
+            players,
+        }
+    }
+
+    /// Adds how the player to act in `state` plays every combo of its range according to
+    /// `profile`, the state having the board of the report dealt
+    pub fn add_strategy(&mut self, profile: &StrategyProfile, game_info: &GameInfo, state: &GameState, board_cards: &[Card]) {
+        let player = state.current_player().expect("no player to act") as usize;
+        assert!(state.current_round() > 0 && game_info.total_board_cards(state.current_round()) as usize == board_cards.len(), "state has to be at the board of the report");
+
+        let report = &self.players[player];
+        let queries = report.combos.iter().map(|c| (state, &c.cards[..], board_cards)).collect::<Vec<(&GameState, &[Card], &[Card])>>();
+        let probabilities = profile.action_probabilities_batch(game_info, &queries);
+
+        self.actions = probabilities.iter().flatten().map(|(a, _)| *a).sorted().dedup().collect();
+        let combos = report.combos.iter()
+            .zip(probabilities)
+            .map(|(combo, probabilities)| ComboReport {
+                frequencies: self.actions.iter().map(|a| probabilities.iter().find(|(b, _)| a == b).map_or(0., |(_, p)| *p)).collect(),
+                ..combo.clone()
+            })
+            .collect();
+        self.players[player] = PlayerReport::new(player as PlayerId, combos);
+        self.betting = Some(state.betting_string(game_info));
+    }
+
+    /// Writes a row for every combo of both ranges with a column per action
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        writeln!(csv, "player,combo,weight,class,equity{}", self.actions.iter().map(|a| format!(",{}", a)).join("")).unwrap();
+        for report in &self.players {
+            for combo in &report.combos {
+                write!(csv, "{},{},{:.4},{},{:.4}", report.player, combo.combo, combo.weight, combo.class, combo.equity).unwrap();
+                writeln!(csv, "{}", combo.frequencies.iter().map(|f| format!(",{:.4}", f)).join("")).unwrap();
+            }
+        }
+        csv
+    }
+
+    /// Writes a row for every class of hands of both ranges with a column per action
+    pub fn classes_to_csv(&self) -> String {
+        let mut csv = String::new();
+        writeln!(csv, "player,class,weight,fraction,equity{}", self.actions.iter().map(|a| format!(",{}", a)).join("")).unwrap();
+        for report in &self.players {
+            for class in &report.classes {
+                write!(csv, "{},{},{:.4},{:.4},{:.4}", report.player, class.class, class.weight, class.fraction, class.equity).unwrap();
+                writeln!(csv, "{}", class.frequencies.iter().map(|f| format!(",{:.4}", f)).join("")).unwrap();
+            }
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for FlopReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "board {}", self.board)?;
+        if let Some(betting) = &self.betting {
+            write!(f, " after {}, actions {}", betting, self.actions.iter().join(" "))?;
+        }
+        for report in &self.players {
+            write!(f, "\n\nplayer {}: {} combos, equity {:.3}", report.player, report.combos.len(), report.equity)?;
+            write!(f, "\nequity histogram {}", report.equity_histogram.iter().map(|h| format!("{:.3}", h)).join(" "))?;
+            write!(f, "\nbest {}", report.best(NUM_EXTREME_HANDS).iter().map(|c| format!("{} {:.3}", c.combo, c.equity)).join(", "))?;
+            write!(f, "\nworst {}", report.worst(NUM_EXTREME_HANDS).iter().map(|c| format!("{} {:.3}", c.combo, c.equity)).join(", "))?;
+            for class in &report.classes {
+                write!(f, "\n{:<14} {:>5.1}% equity {:.3}", class.class.to_string(), class.fraction * 100., class.equity)?;
+                for (action, frequency) in self.actions.iter().zip(&class.frequencies) {
+                    write!(f, " {} {:.2}", action, frequency)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,43 +2,58 @@
 * Somewhat port of https://github.com/ethansbrown/acpc
 */
 
-use log::warn;
-
 use super::action_abstraction::{
     AbstractRaise, AbstractRaiseType, RaiseRoundConfig
 };
+use super::config::{self, check, ConfigError};
+use super::evaluator::{HandEvaluator, HandRank, HandRanking, RankingDirection};
+use super::parallel::*;
+use super::range::card_index;
 
-use poker::{Card, Evaluator, Eval, EvalClass, Rank, Suit};
+use poker::{Card, Rank, Suit};
 use itertools::Itertools;
+use rand::prelude::*;
+use rand::rngs::StdRng;
 use variter::VarIter;
 
 use serde::{Deserialize, Serialize};
 
-use std::fs;
+use std::error::Error;
 use std::fmt;
 use std::option::Option;
-use std::cmp::max;
+use std::borrow::Cow;
+use std::cmp::{max, min, Reverse};
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 
-pub const MAX_PLAYERS: usize = 22;
-pub const MAX_ROUNDS: usize = 4;
-pub const MAX_NUM_ACTIONS: usize = 32;
 pub const MAX_BOARD_CARDS: usize = 7;
 pub const MAX_HOLE_CARDS: usize = 5;
 
 /// Betting types of a poker game
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum BettingType {
     Limit,
     NoLimit,
+    /// Every bet or raise adds between `min` and `max` chips to the current bet, and at least as
+    /// much as the last raise of the round
+    SpreadLimit { min: u32, max: u32 },
 }
 
 /// Represents possible actions
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub enum Action {
     Fold,
     Call,
+    /// The amount raised to in no limit games. Limit games have one raise per round so it is
+    /// the round's raise size, the amount raised to is given by `GameState::limit_raise_to`.
     Raise(u32),
+    /// Replaces the hole cards whose bits are set in the mask, bit i is the ith hole card
+    Discard(u8),
 }
 
 impl fmt::Display for Action {
@@ -47,18 +62,235 @@ impl fmt::Display for Action {
             Action::Fold => write!(f, "fold"),
             Action::Call => write!(f, "call"),
             Action::Raise(r) => write!(f, "raise {}", r),
+            Action::Discard(mask) => write!(f, "discard {:b}", mask),
+        }
+    }
+}
+
+/// An action the way people describe it, telling checks from calls and bets from raises. Bet
+/// sizes are counted within the round, see `GameState::describe_action`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ActionDescription {
+    Fold,
+    Check,
+    /// Puts in `amount` more chips to match the bet
+    Call { amount: u32 },
+    /// Makes the first bet of the round, betting `to` chips
+    Bet { to: u32 },
+    /// Raises the bet of the round to `to` chips
+    Raise { to: u32 },
+    Discard(u8),
+}
+
+impl fmt::Display for ActionDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActionDescription::Fold => write!(f, "folds"),
+            ActionDescription::Check => write!(f, "checks"),
+            ActionDescription::Call { amount } => write!(f, "calls {}", amount),
+            ActionDescription::Bet { to } => write!(f, "bets {}", to),
+            ActionDescription::Raise { to } => write!(f, "raises to {}", to),
+            ActionDescription::Discard(0) => write!(f, "stands pat"),
+            ActionDescription::Discard(mask) => write!(f, "discards {} cards", mask.count_ones()),
+        }
+    }
+}
+
+/// Error returned when parsing an action fails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseActionError {
+    input: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseActionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid action \"{}\": {}", self.input, self.reason)
+    }
+}
+
+impl Error for ParseActionError {}
+
+impl FromStr for Action {
+    type Err = ParseActionError;
+
+    /// Parses short or long forms, eg "f", "check", "r200", "raise 200" or "discard 101" with the
+    /// discard mask in binary as `Display` writes it
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |reason| ParseActionError { input: s.to_string(), reason };
+        let s = s.trim().to_lowercase();
+        let split = s.find(|c: char| c.is_ascii_digit() || c.is_whitespace()).unwrap_or(s.len());
+        let (name, amount) = (&s[..split], s[split..].trim());
+
+        match name {
+            "f" | "fold" if amount.is_empty() => Ok(Action::Fold),
+            "c" | "k" | "call" | "check" if amount.is_empty() => Ok(Action::Call),
+            "r" | "b" | "raise" | "bet" => amount.parse().map(Action::Raise).map_err(|_| error("expected a raise amount")),
+            "d" | "discard" => u8::from_str_radix(amount, 2).map(Action::Discard).map_err(|_| error("expected a binary discard mask")),
+            "f" | "fold" | "c" | "k" | "call" | "check" => Err(error("unexpected amount")),
+            _ => Err(error("unknown action")),
         }
     }
 }
 
 pub type PlayerId = u8;
 
+/// How chips that can't be split evenly between tied winners are assigned. The ACPC dealer
+/// splits pots exactly and reports fractional chips, which integer payouts can't represent.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum OddChipRule {
+    /// In seat order starting from the first player to act in the last round
+    #[default]
+    FirstLeftOfButton,
+    /// To the winner holding the highest card, ties in rank broken by suit (spades, hearts,
+    /// diamonds, clubs)
+    HighestCard,
+    /// In an order that is random but fixed for a given hand
+    Random,
+}
+
+/// Forced bets of a game along with the order players act in, so the seating of common games
+/// doesn't have to be written out by hand. Seats go clockwise starting left of the button, so
+/// the button sits last.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ForcedBets {
+    /// The two seats after the button post the small and big blind and the seat after the big
+    /// blind acts first in the first round, the seat after the button in later rounds. Heads up
+    /// the button posts the small blind instead, so the button acts first in the first round and
+    /// last afterwards.
+    Blinds { small_blind: u32, big_blind: u32 },
+    /// Every player antes the same amount and the seat after the button acts first in every round
+    Antes(u32),
+    /// Nobody posts anything, so the first player can check and the hand can be checked down
+    /// without a chip going in, as in many research toy games. The seat after the button acts
+    /// first in every round.
+    None,
+}
+
+impl ForcedBets {
+    /// Returns the blind posted by every seat
+    pub fn blinds(self, num_players: PlayerId) -> Vec<u32> {
+        let mut blinds = vec![0; num_players as usize];
+        match self {
+            ForcedBets::Blinds { small_blind, big_blind } if num_players == 2 => {
+                blinds[0] = big_blind;
+                blinds[1] = small_blind;
+            },
+            ForcedBets::Blinds { small_blind, big_blind } => {
+                blinds[0] = small_blind;
+                blinds[1] = big_blind;
+            },
+            ForcedBets::Antes(ante) => blinds.fill(ante),
+            ForcedBets::None => {},
+        }
+        blinds
+    }
+
+    /// Returns the first seat to act in each round
+    pub fn first_player(self, num_players: PlayerId, num_rounds: u8) -> Vec<PlayerId> {
+        let mut first_player = vec![0; num_rounds as usize];
+        if let ForcedBets::Blinds { .. } = self {
+            // The seat after the big blind, which heads up is the button
+            first_player[0] = if num_players == 2 { 1 } else { 2 };
+        }
+        first_player
+    }
+}
+
+/// Who acts first in a round. Players who folded or are all in are skipped, the next seat
+/// after them acts instead. Configs give fixed seats as plain numbers, so `"first_player":
+/// [1, "LeftOfButton"]` mixes both.
+#[derive(Clone, Debug)]
+pub enum FirstToAct {
+    Fixed(PlayerId),
+    /// The seat after the button, see `GameInfo::button`
+    LeftOfButton,
+    /// The player showing the best up cards, only for stud rounds after the first. Stud rounds
+    /// after the first always start with the best showing hand, the other rules only decide who
+    /// wins ties there.
+    BestUpcard,
+    /// Decided by a rule written in code when the round starts
+    Custom(Arc<dyn FirstToActRule>),
+}
+
+/// Rule deciding who acts first in a round from the state at its start, for variants whose
+/// order the other `FirstToAct` rules can't express. Saved in configs like card abstractions
+/// with `{"type": "MyRule", ...}`.
+#[typetag::serde(tag = "type")]
+pub trait FirstToActRule: fmt::Debug + Send + Sync {
+    /// Returns the player acting first in `round` of `state`, which is about to start it.
+    /// `state` is `None` for the seat nominally first, used to find the button and break ties.
+    fn first_player(&self, game_info: &GameInfo, state: Option<&GameState>, round: u8) -> PlayerId;
+}
+
+#[derive(Deserialize, Serialize)]
+enum NamedFirstToAct {
+    LeftOfButton,
+    BestUpcard,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FirstToActConfig {
+    Fixed(PlayerId),
+    Named(NamedFirstToAct),
+    Custom(Box<dyn FirstToActRule>),
+}
+
+impl Serialize for FirstToAct {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FirstToAct::Fixed(player) => player.serialize(serializer),
+            FirstToAct::LeftOfButton => NamedFirstToAct::LeftOfButton.serialize(serializer),
+            FirstToAct::BestUpcard => NamedFirstToAct::BestUpcard.serialize(serializer),
+            FirstToAct::Custom(rule) => rule.as_ref().serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FirstToAct {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match FirstToActConfig::deserialize(deserializer)? {
+            FirstToActConfig::Fixed(player) => FirstToAct::Fixed(player),
+            FirstToActConfig::Named(NamedFirstToAct::LeftOfButton) => FirstToAct::LeftOfButton,
+            FirstToActConfig::Named(NamedFirstToAct::BestUpcard) => FirstToAct::BestUpcard,
+            FirstToActConfig::Custom(rule) => FirstToAct::Custom(Arc::from(rule)),
+        })
+    }
+}
+
+/// Chips the house takes from every pot that reaches a payout. Bets nobody called are returned
+/// before the rake is worked out, so they are never raked.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Rake {
+    /// Percent of the pot taken, rounded down to a whole chip
+    pub percentage: f32,
+    /// Most chips taken from a single hand
+    #[serde(default)]
+    pub cap: Option<u32>,
+    /// Take nothing from hands that end in the first round
+    #[serde(default)]
+    pub no_flop_no_drop: bool,
+}
+
+/// Orders cards for `OddChipRule::HighestCard`
+fn odd_chip_card_order(card: Card) -> (Rank, u8) {
+    let suit = match card.suit() {
+        Suit::Clubs => 0,
+        Suit::Diamonds => 1,
+        Suit::Hearts => 2,
+        Suit::Spades => 3,
+    };
+    (card.rank(), suit)
+}
+
 /// Represents the rules and parameters of a poker game
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GameInfo {
     /// Starting stack for each player
     starting_stacks: Vec<u32>,
-    /// Blinds per player
+    /// Blinds per player, filled in from `forced_bets` if it's given
+    #[serde(default)]
     blinds: Vec<u32>,
     /// Size of fixed raises per round for limit games
     raise_sizes: Vec<u32>,
@@ -67,27 +299,343 @@ pub struct GameInfo {
     num_rounds: u8,
     /// Max amount of raises per round
     max_raises: Vec<u8>,
-    /// First player to act in a round
-    first_player: Vec<PlayerId>,
+    /// Most chips a player can bet in each round of a no limit game, blinds included in the
+    /// first round. Raises stop at the cap, as in capped no limit. Empty for no caps.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    round_caps: Vec<u32>,
+    /// Who acts first in each round, filled in from `forced_bets` if it's given
+    #[serde(default)]
+    first_player: Vec<FirstToAct>,
     num_suits: u8,
+    /// Ranks in the deck counted down from the ace, so the 3 ranks of Kuhn and Leduc are queens,
+    /// kings and aces and 9 ranks make the six to ace short deck
     num_ranks: u8,
     num_hole_cards: u8,
     /// Board cards added each round
     num_board_cards: Vec<u8>,
+    #[serde(default)]
+    odd_chip_rule: OddChipRule,
+    /// Ranking used to compare hands at showdown
+    #[serde(default)]
+    hand_ranking: HandRanking,
+    /// Split every pot between the best high hand and the best eight or better low hand
+    #[serde(default)]
+    hi_lo: bool,
+    /// Whether players discard and replace hole cards before the betting of each round, empty
+    /// for games without draws
+    #[serde(default)]
+    draws: Vec<bool>,
+    /// Face up cards dealt to each player at the start of each round in stud games, empty for
+    /// games without up cards
+    #[serde(default)]
+    up_cards: Vec<u8>,
+    /// Face down cards dealt to each player at the start of each round after the first in stud
+    /// games, the first round deals `num_hole_cards` face down cards
+    #[serde(default)]
+    down_cards: Vec<u8>,
+    /// Forced bet made in the first round of stud games by the player showing the worst up card
+    #[serde(default)]
+    bring_in: u32,
+    /// Rake taken at payout, no rake if absent
+    #[serde(default)]
+    rake: Option<Rake>,
+    /// Live blinds posted by each player on top of their blind, empty if nobody straddles. The
+    /// biggest straddle acts last in the first round and sets the minimum raise.
+    #[serde(default)]
+    straddles: Vec<u32>,
+    /// Chips posted by each player that go in the pot without counting toward their bet, such as
+    /// the small blind missed by a returning player, empty if nobody posts a dead blind
+    #[serde(default)]
+    dead_blinds: Vec<u32>,
+    /// Times the rest of the board is dealt when players are all in before the last round, the
+    /// pot is split evenly between the run-outs. 0 and 1 both run the board once.
+    #[serde(default)]
+    run_outs: u8,
+    /// Preset setting `blinds` and `first_player` when the config is read, left out of the
+    /// config once it's applied
+    #[serde(default, skip_serializing)]
+    forced_bets: Option<ForcedBets>,
 }
 
 impl GameInfo {
-    pub fn load_game_info(path: &Path) -> GameInfo {
-        let game_info: GameInfo = serde_json::from_str(&fs::read_to_string(path).expect("failed to read game info")).expect("failed to deserialize game info");
-        assert!(game_info.starting_stacks.len() as u8 == game_info.num_players);
-        assert!(game_info.blinds.len() as u8 == game_info.num_players);
-        assert!(game_info.raise_sizes.len() as u8 == game_info.num_rounds);
-        assert!(game_info.max_raises.len() as u8 == game_info.num_rounds);
-        assert!(game_info.first_player.len() as u8 == game_info.num_rounds);
-        assert!(game_info.num_board_cards.len() as u8 == game_info.num_rounds);
+    pub fn load_game_info(path: &Path) -> Result<GameInfo, ConfigError> {
+        config::read_to_string(path).and_then(|json| GameInfo::from_json(&json).map_err(|e| e.with_path(path)))
+    }
+
+    /// Reads a game from the JSON of a game config, for when there is no file system like on wasm
+    pub fn from_json(json: &str) -> Result<GameInfo, ConfigError> {
+        let mut game_info: GameInfo = config::from_json(json)?;
+        if let Some(forced_bets) = game_info.forced_bets.take() {
+            check(game_info.blinds.is_empty() && game_info.first_player.is_empty(), "forced_bets", "forced_bets replaces blinds and first_player")?;
+            check(game_info.num_players >= 2 && game_info.num_rounds >= 1, "forced_bets", "forced bets need at least 2 players and a round")?;
+            game_info.blinds = forced_bets.blinds(game_info.num_players);
+            game_info.first_player = forced_bets.first_player(game_info.num_players, game_info.num_rounds).into_iter().map(FirstToAct::Fixed).collect();
+        }
+        game_info.validate()?;
+        Ok(game_info)
+    }
+
+    /// Checks that the rules of the game are consistent, naming the first field that isn't
+    fn validate(&self) -> Result<(), ConfigError> {
+        let (num_players, num_rounds) = (self.num_players, self.num_rounds);
+        check(num_players >= 2, "num_players", format!("num_players {} < 2", num_players))?;
+        check(num_rounds >= 1, "num_rounds", "num_rounds 0 < 1")?;
+        let per_player = [("starting_stacks", self.starting_stacks.len()), ("blinds", self.blinds.len())];
+        for (field, len) in per_player {
+            check(len == num_players as usize, field, format!("{} length {} != num_players {}", field, len, num_players))?;
+        }
+        let per_round = [("raise_sizes", self.raise_sizes.len()), ("max_raises", self.max_raises.len()), ("num_board_cards", self.num_board_cards.len())];
+        for (field, len) in per_round {
+            check(len == num_rounds as usize, field, format!("{} length {} != num_rounds {}", field, len, num_rounds))?;
+        }
+        self.check_first_player()?;
+        if let BettingType::SpreadLimit { min, max } = self.betting_type {
+            check(min >= 1 && min <= max, "betting_type", format!("spread limit needs 1 <= min {} <= max {}", min, max))?;
+        }
+        self.check_round_caps()?;
+        check(self.num_suits as usize <= Suit::ALL_VARIANTS.len(), "num_suits", format!("num_suits {} > {}", self.num_suits, Suit::ALL_VARIANTS.len()))?;
+        check(self.num_ranks as usize <= Rank::ALL_VARIANTS.len(), "num_ranks", format!("num_ranks {} > {}", self.num_ranks, Rank::ALL_VARIANTS.len()))?;
+        let total_board_cards = self.total_board_cards(num_rounds - 1);
+        match self.hand_ranking {
+            HandRanking::Kuhn => check(self.num_hole_cards == 1 && total_board_cards == 0, "hand_ranking", "kuhn hands have a single card")?,
+            HandRanking::Leduc => check(self.num_hole_cards == 1 && total_board_cards == 1, "hand_ranking", "leduc hands have a hole card and a board card")?,
+            HandRanking::ShortDeck => check(self.num_ranks <= 9, "num_ranks", "short deck needs at most 9 ranks")?,
+            HandRanking::Omaha => check(self.num_hole_cards >= 2 && total_board_cards >= 3, "hand_ranking", "omaha needs two hole cards and three board cards")?,
+            HandRanking::Standard | HandRanking::DeuceToSeven | HandRanking::AceToFive => {},
+        }
+        check(!self.hi_lo || self.ranking_direction() == RankingDirection::HighWins, "hi_lo", "hi-lo games need a high hand ranking")?;
+        check(self.draws.is_empty() || (self.draws.len() as u8 == num_rounds && !self.draws[0]), "draws", "draws are needed for every round and can't happen before the first round")?;
+        check(!self.has_draws() || self.num_hole_cards as usize <= MAX_HOLE_CARDS, "num_hole_cards", format!("discards can only replace up to {} cards", MAX_HOLE_CARDS))?;
+        check(self.up_cards.is_empty() || (self.up_cards.len() as u8 == num_rounds && self.up_cards[0] > 0), "up_cards", "stud games need up cards for every round, starting in the first")?;
+        check(self.down_cards.is_empty() || (self.down_cards.len() as u8 == num_rounds && self.down_cards[0] == 0), "down_cards", "down cards are needed for every round and the first round deals num_hole_cards")?;
+        check(!self.is_stud() || !self.has_draws(), "draws", "stud games can't have draws")?;
+        self.check_forced_bets()?;
+        check(self.num_run_outs() == 1 || (!self.has_draws() && !self.is_stud()), "run_outs", "only board games can run the board more than once")?;
+        check(self.rake.as_ref().is_none_or(|r| (0.0..=100.0).contains(&r.percentage)), "rake.percentage", "rake percentage must be between 0 and 100")?;
+        let num_cards = num_players as u32 * self.num_player_cards(num_rounds - 1) as u32 + self.num_run_outs() as u32 * total_board_cards as u32;
+        let deck_size = self.deck_index().len();
+        check(num_cards <= deck_size, "num_board_cards", format!("{} cards are dealt but the deck has {}", num_cards, deck_size))
+    }
+
+    /// Creates a four round flop game like hold'em or omaha with empty stacks, no blinds and no
+    /// cap on raises, for games that don't come from a config such as imported hand histories
+    pub fn flop_game(num_players: PlayerId, betting_type: BettingType, hand_ranking: HandRanking, num_hole_cards: u8) -> GameInfo {
+        let num_rounds = 4;
+        GameInfo {
+            starting_stacks: vec![0; num_players as usize],
+            blinds: vec![0; num_players as usize],
+            raise_sizes: vec![0; num_rounds],
+            betting_type,
+            num_players,
+            num_rounds: num_rounds as u8,
+            max_raises: vec![u8::MAX; num_rounds],
+            round_caps: Vec::new(),
+            first_player: vec![FirstToAct::Fixed(0); num_rounds],
+            num_suits: 4,
+            num_ranks: if hand_ranking == HandRanking::ShortDeck { 9 } else { 13 },
+            num_hole_cards,
+            num_board_cards: vec![0, 3, 1, 1],
+            odd_chip_rule: OddChipRule::default(),
+            hand_ranking,
+            hi_lo: false,
+            draws: Vec::new(),
+            up_cards: Vec::new(),
+            down_cards: Vec::new(),
+            bring_in: 0,
+            rake: None,
+            straddles: Vec::new(),
+            dead_blinds: Vec::new(),
+            run_outs: 0,
+            forced_bets: None,
+        }
+    }
+
+    pub fn starting_stacks(&self) -> &[u32] {
+        &self.starting_stacks
+    }
+
+    pub fn set_starting_stacks(&mut self, starting_stacks: Vec<u32>) {
+        assert!(starting_stacks.len() == self.num_players as usize, "need a stack for every player");
+        self.starting_stacks = starting_stacks;
+    }
+
+    pub fn blinds(&self) -> &[u32] {
+        &self.blinds
+    }
+
+    pub fn set_blinds(&mut self, blinds: Vec<u32>) {
+        assert!(blinds.len() == self.num_players as usize, "need a blind for every player");
+        self.blinds = blinds;
+        self.check_forced_bets().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Sets the blinds and the first player of every round from a preset, see `ForcedBets`
+    pub fn set_forced_bets(&mut self, forced_bets: ForcedBets) {
+        self.set_first_player(forced_bets.first_player(self.num_players, self.num_rounds));
+        self.set_blinds(forced_bets.blinds(self.num_players));
+    }
+
+    /// Returns the game played by as many players as `starting_stacks` has stacks, such as a
+    /// table players are leaving, with blinds and first players from `forced_bets`. Players
+    /// short of their blind post their whole stack, straddles and dead blinds are dropped.
+    pub fn with_players(&self, starting_stacks: Vec<u32>, forced_bets: ForcedBets) -> GameInfo {
+        let num_players = starting_stacks.len() as PlayerId;
+        assert!(num_players >= 2, "need at least two players");
+        let game_info = GameInfo {
+            blinds: forced_bets.blinds(num_players).iter().zip(&starting_stacks).map(|(b, s)| *b.min(s)).collect(),
+            first_player: forced_bets.first_player(num_players, self.num_rounds).into_iter().map(FirstToAct::Fixed).collect(),
+            starting_stacks,
+            num_players,
+            straddles: Vec::new(),
+            dead_blinds: Vec::new(),
+            ..self.clone()
+        };
+        game_info.check_first_player().and_then(|_| game_info.check_forced_bets()).unwrap_or_else(|e| panic!("{}", e));
         game_info
     }
 
+    pub fn straddles(&self) -> &[u32] {
+        &self.straddles
+    }
+
+    pub fn set_straddles(&mut self, straddles: Vec<u32>) {
+        self.straddles = straddles;
+        self.check_forced_bets().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    pub fn dead_blinds(&self) -> &[u32] {
+        &self.dead_blinds
+    }
+
+    pub fn set_dead_blinds(&mut self, dead_blinds: Vec<u32>) {
+        self.dead_blinds = dead_blinds;
+        self.check_forced_bets().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /// Returns the chips `player` has bet before any action, their blind and straddle
+    pub fn live_blind(&self, player: PlayerId) -> u32 {
+        self.blinds[player as usize] + self.straddles.get(player as usize).copied().unwrap_or(0)
+    }
+
+    pub fn dead_blind(&self, player: PlayerId) -> u32 {
+        self.dead_blinds.get(player as usize).copied().unwrap_or(0)
+    }
+
+    /// Returns the seat nominally first to act in `round`, the player after the biggest straddle
+    /// in the first round if anyone straddles. Rules that depend on the hand give the seat after
+    /// the button, see `GameState::first_to_act` for who actually starts a round.
+    pub fn first_player(&self, round: u8) -> PlayerId {
+        match self.straddles.iter().copied().enumerate().filter(|(_, s)| *s > 0).max_by_key(|(p, s)| (self.blinds[*p] + s, *p)) {
+            Some((straddler, _)) if round == 0 => (straddler as PlayerId + 1) % self.num_players,
+            _ => match &self.first_player[round as usize] {
+                FirstToAct::Fixed(player) => *player,
+                FirstToAct::Custom(rule) => rule.first_player(self, None, round),
+                FirstToAct::LeftOfButton | FirstToAct::BestUpcard => (self.button() + 1) % self.num_players,
+            },
+        }
+    }
+
+    /// Returns the rule deciding who acts first in `round`
+    pub fn first_to_act(&self, round: u8) -> &FirstToAct {
+        &self.first_player[round as usize]
+    }
+
+    /// Returns the largest blind, which amounts in big blinds are measured in. Games without
+    /// blinds measure in their ante, and games without forced bets in single chips.
+    pub fn big_blind(&self) -> u32 {
+        self.blinds.iter().copied().max().unwrap_or(0).max(1)
+    }
+
+    /// Converts `chips` to big blinds, see `big_blind`
+    pub fn to_big_blinds(&self, chips: f64) -> f64 {
+        chips / self.big_blind() as f64
+    }
+
+    /// Converts big blinds to the nearest whole number of chips, see `big_blind`
+    pub fn from_big_blinds(&self, big_blinds: f64) -> u32 {
+        (big_blinds * self.big_blind() as f64).round() as u32
+    }
+
+    /// Returns the seat of the button, the last player to act after the first round. Games
+    /// whose last round doesn't start at a fixed seat seat players clockwise from the left of
+    /// the button, so the button sits last.
+    pub fn button(&self) -> PlayerId {
+        match self.first_player[self.num_rounds as usize - 1] {
+            FirstToAct::Fixed(player) => (player + self.num_players - 1) % self.num_players,
+            _ => self.num_players - 1,
+        }
+    }
+
+    fn check_forced_bets(&self) -> Result<(), ConfigError> {
+        check(self.straddles.is_empty() || self.straddles.len() as u8 == self.num_players, "straddles", format!("straddles length {} != num_players {}", self.straddles.len(), self.num_players))?;
+        check(self.dead_blinds.is_empty() || self.dead_blinds.len() as u8 == self.num_players, "dead_blinds", format!("dead_blinds length {} != num_players {}", self.dead_blinds.len(), self.num_players))?;
+        check(self.straddles.is_empty() || !self.is_stud(), "straddles", "stud games can't have straddles")?;
+        match (0..self.num_players).find(|p| self.live_blind(*p) + self.dead_blind(*p) > self.starting_stacks[*p as usize]) {
+            Some(p) => Err(ConfigError::invalid("blinds", format!("forced bets of player {} exceed their starting stack {}", p, self.starting_stacks[p as usize]))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_first_player(&mut self, first_player: Vec<PlayerId>) {
+        self.set_first_to_act(first_player.into_iter().map(FirstToAct::Fixed).collect());
+    }
+
+    /// Sets the rule deciding who acts first in every round
+    pub fn set_first_to_act(&mut self, first_to_act: Vec<FirstToAct>) {
+        self.first_player = first_to_act;
+        self.check_first_player().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn check_first_player(&self) -> Result<(), ConfigError> {
+        check(self.first_player.len() == self.num_rounds as usize, "first_player", format!("first_player length {} != num_rounds {}", self.first_player.len(), self.num_rounds))?;
+        for (round, first_to_act) in self.first_player.iter().enumerate() {
+            let field = format!("first_player[{}]", round);
+            match first_to_act {
+                FirstToAct::Fixed(player) => check(*player < self.num_players, &field, format!("player {} isn't a seat of the game", player))?,
+                FirstToAct::BestUpcard => check(self.is_stud() && round > 0, &field, "only stud rounds after the first start with the best up cards")?,
+                FirstToAct::LeftOfButton | FirstToAct::Custom(_) => {},
+            }
+        }
+        Ok(())
+    }
+
+    pub fn betting_type(&self) -> BettingType {
+        self.betting_type
+    }
+
+    /// Returns the most chips a player can bet in `round`, `None` if the round isn't capped
+    pub fn round_cap(&self, round: u8) -> Option<u32> {
+        self.round_caps.get(round as usize).copied()
+    }
+
+    /// Caps the chips a player can bet in every round, see `round_cap`. Empty removes the caps.
+    pub fn set_round_caps(&mut self, round_caps: Vec<u32>) {
+        self.round_caps = round_caps;
+        self.check_round_caps().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn check_round_caps(&self) -> Result<(), ConfigError> {
+        if self.round_caps.is_empty() {
+            return Ok(());
+        }
+        check(self.round_caps.len() == self.num_rounds as usize, "round_caps", format!("round_caps length {} != num_rounds {}", self.round_caps.len(), self.num_rounds))?;
+        check(self.betting_type != BettingType::Limit, "round_caps", "only no limit and spread limit rounds can be capped")?;
+        match (0..self.num_players).find(|p| self.live_blind(*p) > self.round_caps[0]) {
+            Some(p) => Err(ConfigError::invalid("round_caps", format!("blind of player {} is over the first round cap {}", p, self.round_caps[0]))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the size of fixed raises in `round` for limit games
+    pub fn raise_size(&self, round: u8) -> u32 {
+        self.raise_sizes[round as usize]
+    }
+
+    pub fn set_raise_sizes(&mut self, raise_sizes: Vec<u32>) {
+        assert!(raise_sizes.len() == self.num_rounds as usize, "need a raise size for every round");
+        self.raise_sizes = raise_sizes;
+    }
+
     pub fn num_suits(&self) -> u8 {
         self.num_suits
     }
@@ -100,10 +648,89 @@ impl GameInfo {
         self.num_hole_cards
     }
 
+    /// Returns the number of cards each player holds once `round` is dealt, up cards included
+    pub fn num_player_cards(&self, round: u8) -> u8 {
+        let mut total = self.num_hole_cards;
+        for i in 0..=round as usize {
+            total += self.up_cards.get(i).copied().unwrap_or(0) + self.down_cards.get(i).copied().unwrap_or(0);
+        }
+        total
+    }
+
+    pub fn is_stud(&self) -> bool {
+        !self.up_cards.is_empty()
+    }
+
+    /// Returns the face up cards among a player's `cards` once `round` is dealt, `cards` are in
+    /// the order they are dealt with the down cards of each round before its up cards
+    pub fn visible_up_cards(&self, cards: &[Card], round: u8) -> Vec<Card> {
+        let mut up_cards = Vec::new();
+        let mut dealt = self.num_hole_cards as usize;
+        for i in 0..=round as usize {
+            dealt += self.down_cards.get(i).copied().unwrap_or(0) as usize;
+            let num_up_cards = self.up_cards.get(i).copied().unwrap_or(0) as usize;
+            up_cards.extend_from_slice(&cards[dealt..dealt + num_up_cards]);
+            dealt += num_up_cards;
+        }
+        up_cards
+    }
+
     pub fn num_players(&self) -> PlayerId {
         self.num_players
     }
 
+    pub fn odd_chip_rule(&self) -> OddChipRule {
+        self.odd_chip_rule
+    }
+
+    pub fn hand_ranking(&self) -> HandRanking {
+        self.hand_ranking
+    }
+
+    /// Returns whether the highest or the lowest hand wins at showdown
+    pub fn ranking_direction(&self) -> RankingDirection {
+        self.hand_ranking.direction()
+    }
+
+    pub fn hi_lo(&self) -> bool {
+        self.hi_lo
+    }
+
+    pub fn set_hi_lo(&mut self, hi_lo: bool) {
+        assert!(!hi_lo || self.ranking_direction() == RankingDirection::HighWins, "hi-lo games need a high hand ranking");
+        self.hi_lo = hi_lo;
+    }
+
+    /// Returns how many times the rest of the board is dealt after players are all in
+    pub fn num_run_outs(&self) -> u8 {
+        self.run_outs.max(1)
+    }
+
+    pub fn set_run_outs(&mut self, run_outs: u8) {
+        self.run_outs = run_outs;
+    }
+
+    pub fn rake(&self) -> Option<&Rake> {
+        self.rake.as_ref()
+    }
+
+    pub fn set_rake(&mut self, rake: Option<Rake>) {
+        self.rake = rake;
+    }
+
+    /// Returns whether players draw before the betting of `round`
+    pub fn is_draw_round(&self, round: u8) -> bool {
+        self.draws.get(round as usize).copied().unwrap_or(false)
+    }
+
+    pub fn has_draws(&self) -> bool {
+        self.draws.iter().any(|d| *d)
+    }
+
+    pub fn num_rounds(&self) -> u8 {
+        self.num_rounds
+    }
+
     pub fn num_board_cards(&self, round: u8) -> u8 {
         self.num_board_cards[round as usize]
     }
@@ -116,28 +743,44 @@ impl GameInfo {
         total
     }
 
+    /// Returns the ranks in the deck, decks with fewer than 13 ranks drop the lowest ranks so
+    /// 9 ranks gives a short deck of sixes to aces
+    pub fn ranks(&self) -> &'static [Rank] {
+        deck_ranks(self.num_ranks)
+    }
+
+    /// Returns the numbering of the cards in the deck, which all bucketing goes through so it
+    /// works the same for any number of ranks and suits
+    pub fn deck_index(&self) -> DeckIndex {
+        DeckIndex::new(self.num_suits, self.num_ranks)
+    }
+
+    /// Returns every card in the deck in the order of `deck_index`
     pub fn generate_deck(&self) -> impl Iterator<Item = Card> {
-        Rank::ALL_VARIANTS.iter()
-            .take(self.num_ranks as usize)
-            .cartesian_product(Suit::ALL_VARIANTS.iter().take(self.num_suits as usize))
-            .map(|(&rank, &suit)| Card::new(rank, suit))
+        self.deck_index().cards()
     }
 
-    pub fn generate_shuffled_deck(&self) -> Box<[Card]> {
-        use rand::prelude::*;
-        let mut rng = thread_rng();
+    pub fn generate_shuffled_deck<R: Rng + ?Sized>(&self, rng: &mut R) -> Box<[Card]> {
         let mut cards = self.generate_deck().collect::<Box<_>>();
-        cards.shuffle(&mut rng);
+        cards.shuffle(rng);
         cards
     }
 
-    pub fn deal_hole_cards_and_board_cards(&self) -> ([Vec<Card>; MAX_PLAYERS], Vec<Card>) {
-        let mut hole_cards = [(); MAX_PLAYERS].map(|_| Vec::new());
-        let deck = Vec::from(self.generate_shuffled_deck());
+    /// Deals hole cards and the board cards of every round. In stud games the hole cards include
+    /// every up card and in draw games the rest of the deck follows the board cards, it is the
+    /// stub replacement cards are drawn from.
+    pub fn deal_hole_cards_and_board_cards<R: Rng + ?Sized>(&self, rng: &mut R) -> (Vec<Vec<Card>>, Vec<Card>) {
+        self.deal_from_deck(&self.generate_shuffled_deck(rng))
+    }
+
+    /// Deals like `deal_hole_cards_and_board_cards` from `deck` in order instead of a shuffled
+    /// deck, the hole cards of every player followed by the board cards
+    pub fn deal_from_deck(&self, deck: &[Card]) -> (Vec<Vec<Card>>, Vec<Card>) {
+        let mut hole_cards = vec![Vec::new(); self.num_players as usize];
         let mut c = 0;
 
         for i in 0..self.num_players {
-            for _ in 0..self.num_hole_cards {
+            for _ in 0..self.num_player_cards(self.num_rounds - 1) {
                 hole_cards[i as usize].push(deck[c]);
                 c += 1;
             }
@@ -149,8 +792,222 @@ impl GameInfo {
             c += 1;
         }
 
+        if self.has_draws() {
+            board_cards.extend_from_slice(&deck[c..]);
+        }
+
         (hole_cards, board_cards)
     }
+
+    /// Deals the additional boards of a hand that is run out more than once after players went
+    /// all in during `all_in_round`. Every board keeps the cards dealt up to that round and the
+    /// rest of each board uses cards not dealt anywhere else.
+    pub fn deal_run_outs<R: Rng + ?Sized>(&self, hole_cards: &[Vec<Card>], board_cards: &[Card], all_in_round: u8, rng: &mut R) -> Vec<Vec<Card>> {
+        let num_board_cards = self.total_board_cards(self.num_rounds - 1) as usize;
+        let num_shared = self.total_board_cards(all_in_round) as usize;
+        let mut deck = Vec::from(self.generate_shuffled_deck(rng))
+            .into_iter()
+            .filter(|c| !hole_cards.iter().any(|h| h.contains(c)) && !board_cards[..num_board_cards].contains(c));
+
+        (1..self.num_run_outs())
+            .map(|_| {
+                let mut run_out = board_cards[..num_shared].to_vec();
+                run_out.extend(deck.by_ref().take(num_board_cards - num_shared));
+                run_out
+            })
+            .collect()
+    }
+}
+
+/// Returns the highest `num_ranks` ranks, the ranks of a deck with `num_ranks` ranks
+pub fn deck_ranks(num_ranks: u8) -> &'static [Rank] {
+    &Rank::ALL_VARIANTS[Rank::ALL_VARIANTS.len() - num_ranks as usize..]
+}
+
+/// Numbers the cards of a deck from 0 in the order of `GameInfo::generate_deck`, rank by rank
+/// from the lowest rank in the deck and suit by suit within a rank. Indices depend only on where
+/// a card sits in the deck, not on how the `poker` crate numbers ranks and suits, so they stay
+/// dense for decks that drop low ranks or use fewer suits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeckIndex {
+    num_suits: u8,
+    num_ranks: u8,
+}
+
+impl DeckIndex {
+    pub fn new(num_suits: u8, num_ranks: u8) -> DeckIndex {
+        assert!(num_suits as usize <= Suit::ALL_VARIANTS.len() && num_ranks as usize <= Rank::ALL_VARIANTS.len(), "deck is bigger than a standard deck");
+        DeckIndex { num_suits, num_ranks }
+    }
+
+    pub fn num_suits(&self) -> u8 {
+        self.num_suits
+    }
+
+    pub fn num_ranks(&self) -> u8 {
+        self.num_ranks
+    }
+
+    /// Returns the number of cards in the deck
+    pub fn len(&self) -> u32 {
+        self.num_suits as u32 * self.num_ranks as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the rank and suit of `card` counted from the lowest rank and first suit of the
+    /// deck, `None` if the card isn't in the deck
+    pub fn rank_and_suit(&self, card: Card) -> Option<(u8, u8)> {
+        let rank = deck_ranks(self.num_ranks).iter().position(|r| *r == card.rank())?;
+        let suit = Suit::ALL_VARIANTS[..self.num_suits as usize].iter().position(|s| *s == card.suit())?;
+        Some((rank as u8, suit as u8))
+    }
+
+    pub fn contains(&self, card: Card) -> bool {
+        self.rank_and_suit(card).is_some()
+    }
+
+    /// Returns the position of `card` in the deck, panics if it isn't in the deck
+    pub fn index(&self, card: Card) -> u32 {
+        let (rank, suit) = self.rank_and_suit(card).unwrap_or_else(|| panic!("card {} is not in the deck", card));
+        rank as u32 * self.num_suits as u32 + suit as u32
+    }
+
+    /// Returns the card with the deck relative `rank` and `suit`
+    pub fn card_at(&self, rank: u8, suit: u8) -> Card {
+        assert!(rank < self.num_ranks && suit < self.num_suits, "no card with rank {} and suit {} in the deck", rank, suit);
+        Card::new(deck_ranks(self.num_ranks)[rank as usize], Suit::ALL_VARIANTS[suit as usize])
+    }
+
+    /// Returns the card at `index`, the inverse of `index`
+    pub fn card(&self, index: u32) -> Card {
+        self.card_at((index / self.num_suits as u32) as u8, (index % self.num_suits as u32) as u8)
+    }
+
+    /// Returns every card of the deck in index order
+    pub fn cards(&self) -> impl Iterator<Item = Card> {
+        let deck = *self;
+        (0..self.len()).map(move |i| deck.card(i))
+    }
+}
+
+/// Formats the actions of each round ACPC style: `f`, `c` and `r<chips>` with rounds separated
+/// by `/`, e.g. `cr200c/cr400f`. Limit raises have no amount and discards are `d<mask>` with the
+/// mask in binary.
+pub fn format_betting(game_info: &GameInfo, rounds: &[Vec<Action>]) -> String {
+    rounds.iter()
+        .map(|actions| actions.iter()
+            .map(|action| match action {
+                Action::Fold => "f".to_string(),
+                Action::Call => "c".to_string(),
+                Action::Raise(_) if matches!(game_info.betting_type, BettingType::Limit) => "r".to_string(),
+                Action::Raise(r) => format!("r{}", r),
+                Action::Discard(mask) => format!("d{:b}", mask),
+            })
+            .collect::<String>())
+        .join("/")
+}
+
+/// Parses a betting string written by `format_betting` into the actions of each round
+pub fn parse_betting(game_info: &GameInfo, betting: &str) -> Result<Vec<Vec<Action>>, &'static str> {
+    betting.split('/')
+        .enumerate()
+        .map(|(round, actions)| {
+            if round >= game_info.num_rounds as usize {
+                return Err("betting string has more rounds than the game");
+            }
+
+            let mut parsed = Vec::new();
+            let mut chars = actions.chars().peekable();
+            while let Some(c) = chars.next() {
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                parsed.push(match (c, &game_info.betting_type) {
+                    ('f', _) if digits.is_empty() => Action::Fold,
+                    ('c', _) if digits.is_empty() => Action::Call,
+                    ('r', BettingType::Limit) if digits.is_empty() => Action::Raise(game_info.raise_sizes[round]),
+                    ('r', BettingType::NoLimit | BettingType::SpreadLimit { .. }) => Action::Raise(digits.parse().map_err(|_| "invalid raise amount in betting string")?),
+                    ('d', _) => Action::Discard(u8::from_str_radix(&digits, 2).map_err(|_| "invalid discard mask in betting string")?),
+                    _ => return Err("invalid action in betting string"),
+                });
+            }
+            Ok(parsed)
+        })
+        .collect()
+}
+
+/// A pot of a hand that's over and the players who can win it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SidePot {
+    /// Chips in the pot after rake
+    pub amount: u32,
+    pub eligible: Vec<PlayerId>,
+}
+
+/// Pots of a hand that's over, see `GameState::side_pots`. They only depend on the betting, so
+/// they can be worked out once for a betting sequence and reused for every deal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SidePots {
+    /// Main pot first, then the side pots by contribution level
+    pub pots: Vec<SidePot>,
+    /// Chips every player put in, dead blinds included
+    pub spent: Vec<u32>,
+    /// Part of a bet nobody matched, handed back to the player who made it instead of being
+    /// put in a pot, see `GameState::uncalled_bet`
+    pub uncalled_bet: Option<(PlayerId, u32)>,
+}
+
+impl SidePots {
+    /// Returns whether more than one player can win a pot, so hands have to be compared
+    pub fn is_contested(&self) -> bool {
+        self.pots.iter().any(|pot| pot.eligible.len() > 1)
+    }
+}
+
+/// What applying an action did besides the action itself, see `GameState::apply_action`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActionOutcome {
+    /// Round the hand moved on to, the last one reached if several are run out at once
+    pub new_round: Option<u8>,
+    /// Rounds dealt without betting because at most one player can still act, empty unless
+    /// this action ended the betting early
+    pub runout_rounds: Range<u8>,
+    pub hand_finished: bool,
+    /// Players who are all in once the action is made, including those who were before
+    pub players_all_in: Vec<PlayerId>,
+}
+
+/// Everything about a state that decides how the rest of the hand can play out: the chips put
+/// in, who folded, whose turn it is and the betting of the current round, which decides when the
+/// round ends. Betting of earlier rounds only matters through the chips each player put in each
+/// round, so states reached by different betting that agree on the key have identical subtrees,
+/// pots and contributions and are transpositions of each other, as long as the board is the same
+/// and the action abstraction only looks at the current round. Discards are part of the key
+/// since they decide the replacement cards.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TranspositionKey {
+    round: u8,
+    active_player: PlayerId,
+    max_spent: u32,
+    round_start_spent: u32,
+    min_no_limit_raise_to: u32,
+    spent: Vec<u32>,
+    stack_player: Vec<u32>,
+    dead: Vec<u32>,
+    round_spent: Vec<Vec<u32>>,
+    players_folded: Vec<bool>,
+    can_raise: Vec<bool>,
+    round_actions: Vec<(PlayerId, Action)>,
+    discards: Vec<(PlayerId, u8)>,
+    all_in_round: Option<u8>,
+    bring_in_player: Option<PlayerId>,
+    drawing: bool,
+    awaiting_up_cards: bool,
+    finished: bool,
 }
 
 /// Represents the state of a poker game
@@ -159,106 +1016,257 @@ pub struct GameState {
     hand_id: u32,
     /// Largest bet over all rounds so far
     max_spent: u32,
+    /// Largest bet when the current round started, bets made in the round are counted from it.
+    /// Blinds count toward the first round's bets while stud antes don't.
+    round_start_spent: u32,
     /// Minimum number of chips a player has to bet to raise in no limit games
     min_no_limit_raise_to: u32,
     /// Total amount put into pot by each player
-    spent: [u32; MAX_PLAYERS],
-    /// Stack of each player
-    stack_player: [u32; MAX_PLAYERS],
-    /// sum_round_spent[r][p] gives amount in pot for round r of player p
-    sum_round_spent: [[u32; MAX_PLAYERS]; MAX_ROUNDS], 
-    /// action[r][i] gives the ith action in round r
-    action: [[Option<Action>; MAX_NUM_ACTIONS]; MAX_ROUNDS],
-    /// acting_player[r][i] gives the player who made ith action in round r
-    acting_player: [[PlayerId; MAX_NUM_ACTIONS]; MAX_ROUNDS],
+    spent: Vec<u32>,
+    /// Stack of each player after dead blinds
+    stack_player: Vec<u32>,
+    /// Dead blind posted by each player, in the pot but not part of their bet
+    dead: Vec<u32>,
+    /// sum_round_spent[r][p] gives amount in pot for round r of player p, holds the rounds up
+    /// to the current one
+    sum_round_spent: Vec<Vec<u32>>,
+    /// actions[r][i] gives the ith action in round r along with the player who made it. Grows
+    /// as needed, so deep limit games with many players never run out of room for raises.
+    actions: Vec<Vec<(PlayerId, Action)>>,
     /// Player who is currently active
     active_player: PlayerId,
-    /// num_actions[r] gives number of actions made in round r
-    num_actions: [u8; MAX_ROUNDS],
     round: u8,
+    /// Round in which betting ended because at most one player could still act, the board
+    /// cards of every later round are dealt without betting
+    all_in_round: Option<u8>,
+    /// Whether players are drawing before the betting of the current round
+    drawing: bool,
+    /// Whether the up cards of the current stud round still have to be dealt before betting
+    awaiting_up_cards: bool,
+    /// Player who made the bring-in in a stud game
+    bring_in_player: Option<PlayerId>,
     finished: bool,
     /// Which players have folded
-    players_folded: [bool; MAX_PLAYERS],
-    // board_cards: Vec<Card>,
-    // hole_cards: [Vec<Card>; MAX_PLAYERS],
+    players_folded: Vec<bool>,
+    /// Which players may raise in no limit games. A player who has acted can only raise again
+    /// after a full raise, an all in for less than a full raise doesn't reopen the betting.
+    can_raise: Vec<bool>,
 }
 
 impl GameState {
     pub fn new(game_info: &GameInfo, hand_id: u32) -> GameState {
-        let mut sum_round_spent: [[u32; MAX_PLAYERS]; MAX_ROUNDS]  = [[0; MAX_PLAYERS]; MAX_ROUNDS];
-        let mut spent = [0; MAX_PLAYERS];
-        let mut max_spent: u32 = 0;
-        let mut players_folded: [bool; MAX_PLAYERS] = [true; MAX_PLAYERS];
-
-        for i in 0..game_info.num_players {
-            spent[i as usize] = game_info.blinds[i as usize];
-            sum_round_spent[0][i as usize] = game_info.blinds[i as usize];
-
-            if game_info.blinds[i as usize] > max_spent {
-                max_spent = game_info.blinds[i as usize];
-            }
-            players_folded[i as usize] = false;
-        }
+        let num_players = game_info.num_players as usize;
+        let spent = (0..game_info.num_players).map(|p| game_info.live_blind(p)).collect::<Vec<u32>>();
+        let max_spent = spent.iter().copied().max().unwrap_or(0);
+        let sum_round_spent = vec![spent.clone()];
+        let dead = (0..game_info.num_players).map(|p| game_info.dead_blind(p)).collect::<Vec<u32>>();
 
         let min_no_limit_raise_to = match &game_info.betting_type {
             BettingType::NoLimit if max_spent > 0 => max_spent * 2,
+            // Without forced bets the smallest bet is a single chip
             BettingType::NoLimit => 1,
+            BettingType::SpreadLimit { min, .. } => max_spent + min,
             BettingType::Limit => 0,
         };
 
-        let mut stack_player: [u32; MAX_PLAYERS] = [0; MAX_PLAYERS];
-        for (i, s) in game_info.starting_stacks.iter().enumerate() {
-            stack_player[i] = *s;
-        }
+        let round_start_spent = if game_info.is_stud() { max_spent } else { 0 };
 
-        GameState {
+        let mut state = GameState {
             hand_id,
             max_spent,
+            round_start_spent,
             min_no_limit_raise_to,
             spent,
-            stack_player,
+            stack_player: (0..num_players).map(|p| game_info.starting_stacks[p] - dead[p]).collect(),
+            dead,
             sum_round_spent,
-            action: [[None; MAX_NUM_ACTIONS]; MAX_ROUNDS],
-            acting_player: [[0; MAX_NUM_ACTIONS]; MAX_ROUNDS],
-            active_player: game_info.first_player[0],
-            num_actions: [0; MAX_ROUNDS],
+            actions: vec![Vec::new()],
+            active_player: game_info.first_player(0),
             round: 0,
+            all_in_round: None,
+            drawing: false,
+            awaiting_up_cards: game_info.is_stud(),
+            bring_in_player: None,
             finished: false,
-            players_folded,
-            // board_cards: Vec::new(),
-            // hole_cards: [(); MAX_PLAYERS].map(|_| Vec::new()),
+            players_folded: vec![false; num_players],
+            can_raise: vec![true; num_players],
+        };
+        state.active_player = state.first_to_act(game_info, 0);
+        state
+    }
+
+    /// Returns who starts `round` from this state, before skipping players who folded or are
+    /// all in. Custom rules see the state, the others give `GameInfo::first_player`.
+    pub fn first_to_act(&self, game_info: &GameInfo, round: u8) -> PlayerId {
+        match game_info.first_to_act(round) {
+            FirstToAct::Custom(rule) => rule.first_player(game_info, Some(self), round),
+            _ => game_info.first_player(round),
         }
     }
 
     pub fn pot_total(&self, game_info: &GameInfo) -> u32 {
         let mut total = 0;
         for i in 0..game_info.num_players {
-            total += self.spent[i as usize]; 
+            total += self.spent[i as usize] + self.dead[i as usize];
         }
         total
     }
 
+    /// Returns the most chips any player has bet, the amount a call matches
+    pub fn max_spent(&self) -> u32 {
+        self.max_spent
+    }
+
+    /// Returns the player whose bet nobody matched so far and the part of it nobody matched,
+    /// which goes back to them once the hand is over instead of into a pot. Only live bets count,
+    /// dead blinds stay in the pot, and a player who folded gets nothing back.
+    pub fn uncalled_bet(&self) -> Option<(PlayerId, u32)> {
+        let top = (0..self.spent.len()).max_by_key(|p| self.spent[*p])?;
+        let matched = (0..self.spent.len()).filter(|p| *p != top).map(|p| self.spent[p]).max().unwrap_or(0);
+        (!self.players_folded[top] && self.spent[top] > matched).then(|| (top as PlayerId, self.spent[top] - matched))
+    }
+
+    pub fn hand_id(&self) -> u32 {
+        self.hand_id
+    }
+
+    /// Returns the stack `player` started the hand with
     pub fn player_stack(&self, player: PlayerId) -> u32 {
-        self.stack_player[player as usize]
+        self.stack_player[player as usize] + self.dead[player as usize]
     }
 
+    /// Returns the chips `player` has put in the pot, dead blind included
     pub fn player_spent(&self, player: PlayerId) -> u32 {
-        self.spent[player as usize]
+        self.spent[player as usize] + self.dead[player as usize]
     }
 
     pub fn current_round(&self) -> u8 {
         self.round
     }
-    
-    /// Returns current player
-    pub fn current_player(&self) -> Result<PlayerId, &'static str> {
-        if self.finished {
-            return Err("state is finished so there is no active player");
-        }
 
-        Ok(self.active_player)
+    /// Returns the rounds that are run out without betting because at most one player could
+    /// still act, empty unless betting ended before the last round
+    pub fn runout_rounds(&self) -> Range<u8> {
+        match self.all_in_round {
+            Some(round) => round + 1..self.round + 1,
+            None => 0..0,
+        }
     }
-
+    
+    pub fn is_drawing(&self) -> bool {
+        self.drawing
+    }
+
+    /// Returns whether `deal_up_cards` has to be called before the current stud round can start
+    pub fn is_awaiting_up_cards(&self) -> bool {
+        self.awaiting_up_cards
+    }
+
+    pub fn bring_in_player(&self) -> Option<PlayerId> {
+        self.bring_in_player
+    }
+
+    /// Starts the betting of a stud round once its up cards are dealt, `hole_cards` hold every
+    /// player's cards in the order they are dealt. In the first round the player showing the
+    /// worst up cards makes the bring-in and the next player acts first, in later rounds the
+    /// player showing the best hand acts first.
+    pub fn deal_up_cards<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, hole_cards: &[Vec<Card>]) -> Result<GameState, &'static str> {
+        if !self.awaiting_up_cards {
+            return Err("up cards can only be dealt at the start of a stud round");
+        }
+
+        let mut new_state = self.clone();
+        new_state.awaiting_up_cards = false;
+
+        let direction = game_info.ranking_direction();
+        let showing = |p: PlayerId| {
+            let up_cards = game_info.visible_up_cards(&hole_cards[p as usize], self.round);
+            // Ties for the bring-in are broken by suit, spades being the highest, later rounds
+            // break ties by position instead
+            let suit = if self.round == 0 { up_cards.iter().map(|c| odd_chip_card_order(*c).1).max() } else { None };
+            (direction.orient(evaluator.evaluate(&up_cards)), suit)
+        };
+
+        if self.round == 0 {
+            let player = (0..game_info.num_players)
+                .filter(|p| !self.players_folded[*p as usize])
+                .min_by_key(|p| showing(*p))
+                .unwrap();
+            let bring_in_to = min(new_state.spent[player as usize] + game_info.bring_in, new_state.stack_player[player as usize]);
+            new_state.spent[player as usize] = bring_in_to;
+            new_state.sum_round_spent[0][player as usize] = bring_in_to;
+            new_state.max_spent = new_state.max_spent.max(bring_in_to);
+            // The bring in opens the betting, so a raise has to add at least as much again
+            new_state.min_no_limit_raise_to = match game_info.betting_type {
+                BettingType::NoLimit => max(self.min_no_limit_raise_to, new_state.max_spent + game_info.bring_in),
+                BettingType::SpreadLimit { min, .. } => max(self.min_no_limit_raise_to, new_state.max_spent + min),
+                BettingType::Limit => self.min_no_limit_raise_to,
+            };
+            new_state.bring_in_player = Some(player);
+            new_state.active_player = player;
+            new_state.active_player = new_state.next_player(game_info)?;
+        } else {
+            // Seats closer to the round's first player win ties
+            let first_player = game_info.first_player(self.round);
+            new_state.active_player = (0..game_info.num_players)
+                .filter(|p| !self.players_folded[*p as usize] && self.spent[*p as usize] < self.stack_player[*p as usize])
+                .max_by_key(|p| (showing(*p), Reverse((p + game_info.num_players - first_player) % game_info.num_players)))
+                .ok_or("nobody can act in this round")?;
+        }
+
+        debug_assert_eq!(new_state.check_invariants(game_info), Ok(()), "corrupt state {}", new_state);
+        Ok(new_state)
+    }
+
+    /// Returns every player's hole cards after the discards made so far. Replacement cards are
+    /// drawn in order from the stub following the board in `board_cards`, once it runs out the
+    /// cards discarded so far are drawn in the order they were discarded, except the cards of the
+    /// player who is drawing.
+    pub fn current_hole_cards<'a>(&self, game_info: &GameInfo, hole_cards: &'a [Vec<Card>], board_cards: &[Card]) -> Cow<'a, [Vec<Card>]> {
+        let discards = self.actions[..=self.round as usize].iter()
+            .flatten()
+            .filter_map(|(player, action)| match action {
+                Action::Discard(mask) if *mask != 0 => Some((*player as usize, *mask)),
+                _ => None,
+            })
+            .collect::<Vec<(usize, u8)>>();
+        if discards.is_empty() {
+            return Cow::Borrowed(hole_cards);
+        }
+
+        let mut hole_cards = hole_cards.to_vec();
+        let mut stub = board_cards[game_info.total_board_cards(game_info.num_rounds - 1) as usize..].iter().copied().collect::<VecDeque<Card>>();
+        let mut discard_pile = Vec::new();
+        for (player, mask) in discards {
+            let mut discarded = Vec::new();
+            for (i, card) in hole_cards[player].iter_mut().enumerate() {
+                if mask & (1 << i) != 0 {
+                    if stub.is_empty() {
+                        stub.extend(discard_pile.drain(..));
+                    }
+                    discarded.push(*card);
+                    *card = stub.pop_front().expect("ran out of cards to draw");
+                }
+            }
+            discard_pile.extend(discarded);
+        }
+
+        Cow::Owned(hole_cards)
+    }
+
+    /// Returns current player
+    pub fn current_player(&self) -> Result<PlayerId, &'static str> {
+        if self.finished {
+            return Err("state is finished so there is no active player");
+        }
+
+        if self.awaiting_up_cards {
+            return Err("up cards have to be dealt before anyone can act");
+        }
+
+        Ok(self.active_player)
+    }
+
     /// Returns players who can still take actions
     pub fn num_active_players(&self, game_info: &GameInfo) -> u8 {
         let mut count = 0;
@@ -271,23 +1279,22 @@ impl GameState {
         count
     }
 
-    /// Returns players who have called
+    /// Returns players who have called since the last raise, or in a round nobody raised every
+    /// player who checked, counting only players who can still act
     pub fn num_called(&self, game_info: &GameInfo) -> u8 {
         let mut count = 0;
 
-        for i in (0..self.num_actions[self.round as usize]).rev() {
-            let player = self.acting_player[self.round as usize][i as usize];
+        for (player, action) in self.actions[self.round as usize].iter().rev() {
+            let player = *player;
 
-            if matches!(self.action[self.round as usize][i as usize].unwrap(), Action::Raise(_)) {
+            if matches!(action, Action::Raise(_)) {
                 if self.spent[player as usize] < self.stack_player[player as usize] {
                     count += 1;
                 }
 
                 return count;
-            } else if self.action[self.round as usize][i as usize].unwrap() == Action::Call {
-                if self.spent[player as usize] < self.stack_player[player as usize] {
-                    count += 1;
-                }
+            } else if *action == Action::Call && self.spent[player as usize] < self.stack_player[player as usize] {
+                count += 1;
             }
         }
 
@@ -325,6 +1332,15 @@ impl GameState {
         Ok(p)
     }
 
+    /// Returns the first player from `player` on who hasn't folded, all in players included
+    fn next_drawing_player(&self, game_info: &GameInfo, player: PlayerId) -> PlayerId {
+        let mut p = player;
+        while self.players_folded[p as usize] {
+            p = (p + 1) % game_info.num_players;
+        }
+        p
+    }
+
     /// Returns if state is finished(ie terminal state)
     pub fn is_finished(&self) -> bool {
         self.finished
@@ -337,28 +1353,90 @@ impl GameState {
 
     /// Returns number of raises made in this round
     pub fn num_raises(&self) -> u8 {
-        let mut count: u8 = 0;
-        for i in 0..self.num_actions[self.round as usize] {
-            if let Some(Action::Raise(_)) = self.action[self.round as usize][i as usize] {
-                count += 1;
+        self.actions[self.round as usize].iter()
+            .filter(|(_, action)| matches!(action, Action::Raise(_)))
+            .count() as u8
+    }
+
+    /// Returns the actions made in `round` in order along with the player who made them
+    pub fn actions_in_round(&self, round: u8) -> impl Iterator<Item = (PlayerId, Action)> + '_ {
+        self.actions.get(round as usize).into_iter().flatten().copied()
+    }
+
+    /// Returns every action made so far in order along with its round and acting player
+    pub fn full_history(&self) -> impl Iterator<Item = (u8, PlayerId, Action)> + '_ {
+        (0..=self.round).flat_map(move |r| self.actions_in_round(r).map(move |(p, a)| (r, p, a)))
+    }
+
+    /// Returns the betting string of every round up to the current one, see `format_betting`
+    pub fn betting_string(&self, game_info: &GameInfo) -> String {
+        let rounds = (0..=self.round)
+            .map(|r| self.actions_in_round(r).map(|(_, a)| a).collect())
+            .collect::<Vec<Vec<Action>>>();
+        format_betting(game_info, &rounds)
+    }
+
+    /// Replays a betting string from the start of a hand. Rounds run out after an all in have
+    /// no actions. Stud games can't be replayed without their up cards.
+    pub fn from_betting_string(game_info: &GameInfo, hand_id: u32, betting: &str) -> Result<GameState, &'static str> {
+        let rounds = parse_betting(game_info, betting)?;
+        let mut state = GameState::new(game_info, hand_id);
+        for (round, actions) in rounds.iter().enumerate() {
+            if state.round as usize > round && actions.is_empty() {
+                continue;
+            }
+            if state.round as usize != round {
+                return Err("betting string rounds don't match the betting");
+            }
+            for action in actions {
+                if state.awaiting_up_cards {
+                    return Err("stud games can't be replayed without up cards");
+                }
+                state = state.apply_action_no_cards(game_info, *action)?;
             }
         }
-        count
+        if state.round as usize + 1 != rounds.len() {
+            return Err("betting string rounds don't match the betting");
+        }
+
+        Ok(state)
     }
 
-    fn raise_range(&self, game_info: &GameInfo) -> (u32, u32) {
-        if self.finished {
-            return (0, 0);
-        }
+    /// Returns the largest bet made in the current round, zero if nobody has bet
+    pub fn round_bet(&self) -> u32 {
+        self.max_spent - self.round_start_spent
+    }
 
-        if self.num_raises() >= game_info.max_raises[self.round as usize] {
+    /// Describes `action` made by the active player as a check, call, bet or raise. The
+    /// internal model only has calls and raises, this tells them apart for logs and UIs.
+    pub fn describe_action(&self, game_info: &GameInfo, action: Action) -> Result<ActionDescription, &'static str> {
+        let player = self.current_player()?;
+        let new_state = self.apply_action_no_cards(game_info, action)?;
+        let added = new_state.spent[player as usize] - self.spent[player as usize];
+
+        Ok(match action {
+            Action::Fold => ActionDescription::Fold,
+            Action::Call if added == 0 => ActionDescription::Check,
+            Action::Call => ActionDescription::Call { amount: added },
+            Action::Raise(_) if self.round_bet() == 0 => ActionDescription::Bet { to: new_state.max_spent - self.round_start_spent },
+            Action::Raise(_) => ActionDescription::Raise { to: new_state.max_spent - self.round_start_spent },
+            Action::Discard(mask) => ActionDescription::Discard(mask),
+        })
+    }
+
+    /// Returns the most recent action and the player who made it, if any action was made
+    pub fn last_action(&self) -> Option<(PlayerId, Action)> {
+        (0..=self.round).rev().find_map(|r| self.actions_in_round(r).last())
+    }
+
+    /// Returns the smallest and largest amounts `player` could raise to on their turn, (0, 0) if
+    /// they can't raise
+    fn raise_range(&self, game_info: &GameInfo, player: PlayerId) -> (u32, u32) {
+        if self.finished || self.players_folded[player as usize] {
             return (0, 0);
         }
 
-        // CHECK: might be worth figuring out a way to allow infinite actions(need to do it
-        // without sacrificing efficiency too much)
-        if (self.num_actions[self.round as usize] + game_info.num_players) as usize > MAX_NUM_ACTIONS {
-            warn!("Making raise invalid since possible actions {} > MAX_NUM_ACTIONS", self.num_actions[self.round as usize] + game_info.num_players);
+        if self.num_raises() >= game_info.max_raises[self.round as usize] {
             return (0, 0);
         }
 
@@ -366,17 +1444,40 @@ impl GameState {
             return (0, 0);
         }
 
-
         match game_info.betting_type {
             BettingType::Limit => {
-                warn!("raise_range called with limit betting type!");
-                (0, 0)
-            }, // CHECK: maybe change this here
-            BettingType::NoLimit => {
+                // The first raise of the hand is made over the blinds, so a raise over a stud
+                // bring-in completes it to a full bet
+                let base = if self.round == 0 && self.num_raises() == 0 {
+                    (0..game_info.num_players).map(|p| game_info.live_blind(p)).max().unwrap_or(0)
+                } else {
+                    self.max_spent
+                };
+                let stack = self.stack_player[player as usize];
+                if stack <= self.max_spent {
+                    return (0, 0);
+                }
+
+                // A short player raises all in for less than a full bet
+                let raise_to = (base + game_info.raise_sizes[self.round as usize]).min(stack);
+                (raise_to, raise_to)
+            },
+            BettingType::NoLimit | BettingType::SpreadLimit { .. } => {
+                if !self.can_raise[player as usize] {
+                    return (0, 0);
+                }
+
+                // A player short of a full raise, by their stack or the cap of the round, can
+                // only raise all the way
                 let mut min_raise = self.min_no_limit_raise_to;
-                let max_raise = self.stack_player[self.active_player as usize];
-                if self.stack_player[self.active_player as usize] < self.min_no_limit_raise_to {
-                    if self.max_spent >= self.stack_player[self.active_player as usize] {
+                let cap = game_info.round_cap(self.round).map_or(u32::MAX, |c| self.round_start_spent.saturating_add(c));
+                let spread_max = match game_info.betting_type {
+                    BettingType::SpreadLimit { max, .. } => self.max_spent.saturating_add(max),
+                    _ => u32::MAX,
+                };
+                let max_raise = self.stack_player[player as usize].min(cap).min(spread_max);
+                if max_raise < self.min_no_limit_raise_to {
+                    if self.max_spent >= max_raise {
                         return (0, 0);
                     } else {
                         min_raise = max_raise;
@@ -389,11 +1490,75 @@ impl GameState {
 
     }
 
+    /// Returns the smallest and largest total bets `player` could raise to if it were their
+    /// turn, the amounts of `Action::Raise` in no limit games. Both are the same in limit games.
+    /// `None` if the player couldn't raise.
+    pub fn raise_bounds(&self, game_info: &GameInfo, player: PlayerId) -> Option<(u32, u32)> {
+        if self.drawing || self.awaiting_up_cards {
+            return None;
+        }
+
+        match self.raise_range(game_info, player) {
+            (0, 0) => None,
+            bounds => Some(bounds),
+        }
+    }
+
+    /// Returns the chips `player` has to put in to call, less than the bet if calling puts
+    /// them all in
+    pub fn amount_to_call(&self, player: PlayerId) -> u32 {
+        self.max_spent.min(self.stack_player[player as usize]) - self.spent[player as usize]
+    }
+
+    /// Returns the share of the pot after calling that `player` has to put in to call, zero if
+    /// there is nothing to call
+    pub fn pot_odds(&self, game_info: &GameInfo, player: PlayerId) -> f64 {
+        let to_call = self.amount_to_call(player);
+        if to_call == 0 {
+            return 0.;
+        }
+        to_call as f64 / (self.pot_total(game_info) + to_call) as f64
+    }
+
+    /// Returns the chips `player` has left behind
+    pub fn chips_behind(&self, player: PlayerId) -> u32 {
+        self.stack_player[player as usize] - self.spent[player as usize]
+    }
+
+    /// Returns the most `player` can still win or lose against a single opponent, their chips
+    /// behind capped by the deepest opponent still in the hand
+    pub fn effective_stack(&self, game_info: &GameInfo, player: PlayerId) -> u32 {
+        let deepest_opponent = (0..game_info.num_players)
+            .filter(|p| *p != player && !self.players_folded[*p as usize])
+            .map(|p| self.chips_behind(p))
+            .max()
+            .unwrap_or(0);
+        self.chips_behind(player).min(deepest_opponent)
+    }
+
+    /// Returns the amount the active player's bet becomes when they raise in a limit game, a
+    /// full bet over the current one or their whole stack if they are short. `None` if the
+    /// game isn't limit or the player can't raise.
+    pub fn limit_raise_to(&self, game_info: &GameInfo) -> Option<u32> {
+        if game_info.betting_type != BettingType::Limit || self.drawing || self.awaiting_up_cards {
+            return None;
+        }
+
+        match self.raise_range(game_info, self.active_player) {
+            (0, 0) => None,
+            (raise_to, _) => Some(raise_to),
+        }
+    }
+
     pub fn is_valid_action(&self, game_info: &GameInfo, action: Action) -> bool{
-        if self.finished {
+        if self.finished || self.awaiting_up_cards {
             return false;
         }
 
+        if self.drawing {
+            return matches!(action, Action::Discard(mask) if (mask as u32) < 1 << game_info.num_hole_cards);
+        }
+
         match action {
             Action::Fold => {
                 // CHECK: determine whether to consider premature folding(ie folding when all bets
@@ -410,13 +1575,15 @@ impl GameState {
                     return false;
                 }
                 match game_info.betting_type {
-                    BettingType::Limit => r == game_info.raise_sizes[self.round as usize],
-                    BettingType::NoLimit => {
-                        let (min_raise, max_raise) = self.raise_range(game_info);
-                        r >= min_raise && r <= max_raise
+                    BettingType::Limit => r == game_info.raise_sizes[self.round as usize] && self.limit_raise_to(game_info).is_some(),
+                    BettingType::NoLimit | BettingType::SpreadLimit { .. } => {
+                        let (min_raise, max_raise) = self.raise_range(game_info, self.active_player);
+                        max_raise > 0 && r >= min_raise && r <= max_raise
                     }
                 }
             },
+            // Only valid while drawing
+            Action::Discard(_) => false,
         }
     }
     
@@ -428,18 +1595,32 @@ impl GameState {
             _ => return None,
         }
 
+        // Limit games have a single raise, whatever size the abstraction asks for
+        if game_info.betting_type == BettingType::Limit {
+            let raise = Action::Raise(game_info.raise_sizes[self.round as usize]);
+            return self.is_valid_action(game_info, raise).then_some(raise);
+        }
+
         let raise = match abstract_raise.raise_type {
-            AbstractRaiseType::AllIn => Action::Raise(self.stack_player[self.active_player as usize]),
-            AbstractRaiseType::Fixed(i) => {
-                match game_info.betting_type {
-                    BettingType::NoLimit => Action::Raise(self.max_spent + i),
-                    BettingType::Limit => Action::Raise(i)
-                }
-            },
+            // As much as the player can bet, which is short of all in when the round is capped
+            AbstractRaiseType::AllIn => Action::Raise(self.raise_range(game_info, self.active_player).1),
+            AbstractRaiseType::Fixed(i) => Action::Raise(self.max_spent + i),
+            // Nothing to scale when no forced bets went in, so the bet is a share of the pot
+            // and at least the minimum bet
+            AbstractRaiseType::PotRatio(r) if self.max_spent == 0 => Action::Raise(((self.pot_total(game_info) as f32 * r) as u32).max(self.min_no_limit_raise_to)),
             //CHECK: Check below is correct
             AbstractRaiseType::PotRatio(r) => Action::Raise((self.max_spent as f32 * r) as u32),
         };
 
+        // Spread limit sizes outside the spread become the nearest bet allowed
+        let raise = match (game_info.betting_type, raise) {
+            (BettingType::SpreadLimit { .. }, Action::Raise(r)) => {
+                let (min_raise, max_raise) = self.raise_range(game_info, self.active_player);
+                Action::Raise(r.clamp(min_raise.min(max_raise), max_raise))
+            },
+            _ => raise,
+        };
+
         if self.is_valid_action(game_info, raise) {
             return Some(raise);
         }
@@ -447,6 +1628,45 @@ impl GameState {
         None
     }
     
+    /// Applies the action like `apply_action_no_cards` and also returns what it did, so dealers
+    /// and loggers don't have to compare the two states to learn a round changed or the hand
+    /// ended
+    pub fn apply_action(&self, game_info: &GameInfo, action: Action) -> Result<(GameState, ActionOutcome), &'static str> {
+        let new_state = self.apply_action_no_cards(game_info, action)?;
+        let outcome = ActionOutcome {
+            new_round: (new_state.round != self.round).then_some(new_state.round),
+            runout_rounds: if self.all_in_round.is_none() { new_state.runout_rounds() } else { 0..0 },
+            hand_finished: new_state.finished,
+            players_all_in: (0..game_info.num_players)
+                .filter(|p| !new_state.players_folded[*p as usize] && new_state.spent[*p as usize] == new_state.stack_player[*p as usize])
+                .collect(),
+        };
+
+        Ok((new_state, outcome))
+    }
+
+    /// Returns whether the hand can't see any more betting, because every player but at most one
+    /// is all in or folded and the one left has nothing to call. Such states come from forced
+    /// bets putting players all in, betting otherwise ends on its own once it can't continue.
+    pub fn is_betting_over(&self, game_info: &GameInfo) -> bool {
+        !self.finished && !self.drawing && !self.awaiting_up_cards
+            && self.num_active_players(game_info) <= 1
+            && (0..game_info.num_players as usize).all(|p| self.players_folded[p] || self.spent[p] >= self.max_spent.min(self.stack_player[p]))
+    }
+
+    /// Runs out the board of a state where no more betting is possible, see `is_betting_over`,
+    /// up to the showdown or the next draw, instead of feeding the players checks
+    pub fn fast_forward_all_in(&self, game_info: &GameInfo) -> Result<GameState, &'static str> {
+        if !self.is_betting_over(game_info) {
+            return Err("players can still bet");
+        }
+
+        let mut new_state = self.clone();
+        new_state.end_betting_round(game_info);
+        debug_assert_eq!(new_state.check_invariants(game_info), Ok(()), "corrupt state {}", new_state);
+        Ok(new_state)
+    }
+
     /// Returns a new state with that action applied, DOES NOT update cards(this may be something
     /// that gets refactored later).
     pub fn apply_action_no_cards(&self, game_info: &GameInfo, action: Action) -> Result<GameState, &'static str> {
@@ -456,19 +1676,13 @@ impl GameState {
             return Err("cannot apply action to finished state");
         }
 
-        if self.num_actions[self.round as usize] >= MAX_NUM_ACTIONS as u8 {
-            return Err("cannot apply action to state: already at max actions for this round");
-        }
-
         if self.is_valid_action(game_info, action) == false {
             return Err("cannot apply an invalid action");
         }
 
         let player = self.current_player().unwrap();
 
-        new_state.action[self.round as usize][self.num_actions[self.round as usize] as usize] = Some(action);
-        new_state.acting_player[self.round as usize][self.num_actions[self.round as usize] as usize] = player;
-        new_state.num_actions[self.round as usize] += 1;
+        new_state.actions[self.round as usize].push((player, action));
 
         match action {
             Action::Fold => {
@@ -485,180 +1699,1133 @@ impl GameState {
             },
             Action::Raise(r) => {
                 match game_info.betting_type {
-                    BettingType::NoLimit => {
-                        if r * 2 - new_state.max_spent > new_state.min_no_limit_raise_to {
-                            new_state.min_no_limit_raise_to = r * 2 - new_state.max_spent;
+                    BettingType::NoLimit | BettingType::SpreadLimit { .. } => {
+                        if r >= self.min_no_limit_raise_to {
+                            // A full raise sets the size of the next one and lets everyone raise again
+                            new_state.min_no_limit_raise_to = match game_info.betting_type {
+                                BettingType::SpreadLimit { min, .. } => r + (r - self.max_spent).max(min),
+                                _ => r * 2 - self.max_spent,
+                            };
+                            new_state.can_raise.iter_mut().for_each(|c| *c = true);
+                        } else {
+                            // An all in for less keeps the size of the last full raise
+                            new_state.min_no_limit_raise_to = self.min_no_limit_raise_to + r - self.max_spent;
                         }
                         new_state.max_spent = r;
                     },
                     BettingType::Limit => {
-                        if new_state.max_spent + game_info.raise_sizes[new_state.round as usize] > new_state.stack_player[player as usize] {
-                            new_state.max_spent = new_state.stack_player[player as usize];
-                        } else {
-                            new_state.max_spent += game_info.raise_sizes[new_state.round as usize];
-                        }
+                        new_state.max_spent = self.limit_raise_to(game_info).unwrap();
                     },
                 };
 
                 new_state.spent[player as usize] = new_state.max_spent;
                 new_state.sum_round_spent[new_state.round as usize][player as usize] = new_state.max_spent;
-            }
+            },
+            Action::Discard(_) => {
+                let num_discards = new_state.actions[self.round as usize].iter()
+                    .filter(|(_, action)| matches!(action, Action::Discard(_)))
+                    .count();
+                if num_discards < game_info.num_players as usize - new_state.num_folded(game_info) as usize {
+                    new_state.active_player = new_state.next_drawing_player(game_info, (player + 1) % game_info.num_players);
+                } else {
+                    new_state.drawing = false;
+                    if new_state.num_active_players(game_info) > 1 {
+                        new_state.active_player = new_state.first_to_act(game_info, new_state.round);
+                        while new_state.players_folded[new_state.active_player as usize] || new_state.spent[new_state.active_player as usize] >= new_state.stack_player[new_state.active_player as usize] {
+                            new_state.active_player = (new_state.active_player + 1) % game_info.num_players;
+                        }
+                    } else {
+                        new_state.end_betting_round(game_info);
+                    }
+                }
+                debug_assert_eq!(new_state.check_invariants(game_info), Ok(()), "corrupt state {}", new_state);
+                return Ok(new_state);
+            },
         };
 
+        new_state.can_raise[player as usize] = false;
         new_state.active_player = self.next_player(game_info).unwrap();
 
         if new_state.num_folded(game_info) + 1 >= game_info.num_players() {
             new_state.finished = true;
         } else if new_state.num_called(game_info) >= new_state.num_active_players(game_info) {
-            if new_state.num_active_players(game_info) > 1 {
-                if new_state.round + 1 < game_info.num_rounds {
-                    new_state.round += 1;
-                    new_state.min_no_limit_raise_to = 1;
-                    for i in 0..game_info.num_players() {
-                        if game_info.blinds[i as usize] > new_state.min_no_limit_raise_to {
-                            new_state.min_no_limit_raise_to = game_info.blinds[i as usize];
-                        }
-                    }
-                    new_state.min_no_limit_raise_to += new_state.max_spent;
-                    new_state.active_player = game_info.first_player[new_state.round as usize];
-                    while new_state.players_folded[new_state.active_player as usize] || new_state.spent[new_state.active_player as usize] >= new_state.stack_player[new_state.active_player as usize] {
-                        new_state.active_player = (new_state.active_player + 1) % game_info.num_players;
-                    }
-                } else {
-                    new_state.finished = true;
-                }
-            } else {
-                // skip to showdown
-                new_state.finished = true;
-                new_state.round = game_info.num_rounds - 1;
-            }
+            new_state.end_betting_round(game_info);
         }
 
+        debug_assert_eq!(new_state.check_invariants(game_info), Ok(()), "corrupt state {}", new_state);
         Ok(new_state)
     }
 
-    pub fn get_payout(&self, game_info: &GameInfo, evaluator: &Evaluator, board_cards: &[Card], hole_cards: &[Vec<Card>; MAX_PLAYERS], player: PlayerId) -> i32 {
-        if self.has_folded(player) {
-            return  -(self.spent[player as usize] as i32);
+    /// Checks that the chip accounting and betting history of the state are consistent, run
+    /// after every action in debug builds so corruption panics where it happens
+    pub fn check_invariants(&self, game_info: &GameInfo) -> Result<(), &'static str> {
+        let num_players = game_info.num_players as usize;
+        let player_vectors = [self.spent.len(), self.stack_player.len(), self.dead.len(), self.players_folded.len(), self.can_raise.len()];
+        if player_vectors.iter().any(|len| *len != num_players) {
+            return Err("player vectors don't match the number of players");
         }
-
-        if !self.is_finished() {
-            panic!("cannot calculate payout when the hand is not over or the player has not folded!");
+        if self.active_player as usize >= num_players || self.bring_in_player.is_some_and(|p| p as usize >= num_players) {
+            return Err("player isn't a seat of the game");
+        }
+        if (0..num_players).any(|p| self.spent[p] > self.stack_player[p]) {
+            return Err("a player spent more than their stack");
+        }
+        if self.spent.iter().copied().max().unwrap_or(0) != self.max_spent {
+            return Err("largest bet doesn't match the players' bets");
+        }
+        if self.round_start_spent > self.max_spent {
+            return Err("bet at the start of the round is larger than the current bet");
+        }
+        if self.round >= game_info.num_rounds || self.all_in_round.is_some_and(|r| r > self.round) {
+            return Err("round is past the last round");
+        }
+        if game_info.betting_type != BettingType::Limit && !self.finished && self.min_no_limit_raise_to <= self.max_spent {
+            return Err("minimum raise doesn't raise the bet");
         }
 
-        if self.num_folded(game_info) + 1 == game_info.num_players() {
-            let mut value = 0;
+        if self.actions.len() != self.round as usize + 1 || self.sum_round_spent.len() != self.round as usize + 1 {
+            return Err("rounds recorded don't match the current round");
+        }
+        if self.sum_round_spent.iter().any(|spent| spent.len() != num_players) {
+            return Err("player vectors don't match the number of players");
+        }
 
-            for i in 0..game_info.num_players() {
-                if i == player {
+        // Chips only go in through the bring in, calls and raises, and only the bring in and
+        // raises make the bet larger
+        let mut bets = (0..game_info.num_players).map(|p| game_info.live_blind(p)).collect::<Vec<u32>>();
+        let mut largest_bet = bets.iter().copied().max().unwrap_or(0);
+        for (round, (round_spent, actions)) in self.sum_round_spent.iter().zip(self.actions.iter()).enumerate() {
+            let brought_in = |p: usize| round == 0 && self.bring_in_player == Some(p as PlayerId);
+            for p in 0..num_players {
+                // Players who haven't put chips in during a later round have nothing recorded for it
+                if round > 0 && round_spent[p] == 0 {
                     continue;
                 }
+                if round_spent[p] < bets[p] {
+                    return Err("a player's bet got smaller");
+                }
+                let bet = actions.iter().any(|(player, action)| *player as usize == p && matches!(action, Action::Call | Action::Raise(_)));
+                if round_spent[p] > bets[p] && !bet && !brought_in(p) {
+                    return Err("a player put chips in without calling or raising");
+                }
+                bets[p] = round_spent[p];
+            }
+            let round_largest_bet = bets.iter().copied().max().unwrap_or(0);
+            let raised = actions.iter().any(|(_, action)| matches!(action, Action::Raise(_)));
+            if round_largest_bet > largest_bet && !raised && !(0..num_players).any(brought_in) {
+                return Err("the bet got larger without a raise");
+            }
+            largest_bet = round_largest_bet;
+        }
+        if bets != self.spent {
+            return Err("bets recorded by round don't match the players' bets");
+        }
 
-                value += self.spent[i as usize];
+        let mut folded = vec![false; num_players];
+        for (_, player, action) in self.full_history() {
+            if player as usize >= num_players {
+                return Err("player isn't a seat of the game");
             }
+            if folded[player as usize] {
+                return Err("a folded player acted");
+            }
+            folded[player as usize] = action == Action::Fold;
+        }
+        if folded != self.players_folded {
+            return Err("folded players don't match the folds in the history");
+        }
+        if self.num_folded(game_info) as usize >= num_players {
+            return Err("every player folded");
+        }
 
-            //CHECK: maybe don't wanna do shit like this?
-            return i32::try_from(value).unwrap();
+        if !self.finished && !self.awaiting_up_cards {
+            let active = self.active_player as usize;
+            if active >= num_players || self.players_folded[active] {
+                return Err("active player has folded");
+            }
+            if !self.drawing && self.spent[active] >= self.stack_player[active] {
+                return Err("active player is all in");
+            }
         }
 
-        let mut rank = vec![None; game_info.num_players().into()];
-        let mut spent = vec![0; game_info.num_players().into()];
-        let mut players_left: i32 = 0;
-        let mut player_idx: i32 = -1;
+        Ok(())
+    }
 
-        for i in 0..game_info.num_players() {
-            if self.spent[i as usize] == 0 {
-                continue;
+    /// Moves on to the next round once betting in the current round is over, or to showdown
+    fn end_betting_round(&mut self, game_info: &GameInfo) {
+        if self.num_active_players(game_info) > 1 {
+            if self.round + 1 < game_info.num_rounds {
+                self.start_round(game_info, self.round + 1);
+            } else {
+                self.finished = true;
+            }
+        } else if let Some(draw_round) = (self.round + 1..game_info.num_rounds).find(|r| game_info.is_draw_round(*r)) {
+            // Nobody can bet anymore but players still draw, so the board is run out up to the draw
+            if self.all_in_round.is_none() {
+                debug!("hand {}: all in during round {}, running out to the draw in round {}", self.hand_id, self.round, draw_round);
+                self.all_in_round = Some(self.round);
             }
+            self.start_round(game_info, draw_round);
+        } else {
+            // Nobody can bet anymore so the remaining board cards are run out to showdown
+            if self.round + 1 < game_info.num_rounds {
+                debug!("hand {}: all in during round {}, running out rounds {} to {}", self.hand_id, self.round, self.round + 1, game_info.num_rounds - 1);
+                self.all_in_round.get_or_insert(self.round);
+                self.set_round(game_info.num_rounds - 1);
+            }
+            self.finished = true;
+        }
+    }
 
-            if self.has_folded(i) {
-                rank[players_left as usize] = None;
-            } else {
-                if i == player {
-                    player_idx = players_left;
-                }
+    /// Moves to `round`, rounds skipped on the way are recorded without bets or actions
+    fn set_round(&mut self, round: u8) {
+        let num_players = self.spent.len();
+        self.round = round;
+        self.sum_round_spent.resize(round as usize + 1, vec![0; num_players]);
+        self.actions.resize(round as usize + 1, Vec::new());
+    }
 
-                let cards = [&hole_cards[i as usize][..], board_cards].concat();
+    fn start_round(&mut self, game_info: &GameInfo, round: u8) {
+        self.set_round(round);
+        self.round_start_spent = self.max_spent;
+        self.can_raise.iter_mut().for_each(|c| *c = true);
+        self.min_no_limit_raise_to = match game_info.betting_type {
+            BettingType::SpreadLimit { min, .. } => min,
+            _ => game_info.blinds.iter().copied().max().unwrap_or(0).max(1),
+        };
+        self.min_no_limit_raise_to += self.max_spent;
+
+        if game_info.is_stud() {
+            self.awaiting_up_cards = true;
+        } else if game_info.is_draw_round(round) {
+            self.drawing = true;
+            self.active_player = self.next_drawing_player(game_info, self.first_to_act(game_info, round));
+        } else {
+            self.active_player = self.first_to_act(game_info, round);
+            while self.players_folded[self.active_player as usize] || self.spent[self.active_player as usize] >= self.stack_player[self.active_player as usize] {
+                self.active_player = (self.active_player + 1) % game_info.num_players;
+            }
+        }
+    }
 
-                // CHECK: Special cases for Kuhn poker and Leduc poker, I should check that EvalClass is enough to
-                // compare hands
-                if cards.len() == 1 {
-                    rank[players_left as usize] = Some(EvalClass::HighCard { high_rank: cards[0].rank() });
-                } else if cards.len() == 2 {
-                    if cards[0].rank() == cards[1].rank() {
-                        rank[players_left as usize] = Some(EvalClass::Pair { pair: cards[0].rank() });
-                    } else {
-                        rank[players_left as usize] = Some(EvalClass::HighCard { high_rank: max(cards[0].rank(), cards[1].rank()) })
-                    }
-                } else {
-                    rank[players_left as usize] = Some(evaluator.evaluate(cards).expect("couldn't evaluate hand").class());
+    /// Returns the order in which tied `winners` receive chips that can't be split evenly
+    fn odd_chip_order(&self, game_info: &GameInfo, winners: &[PlayerId], board_cards: &[Card], hole_cards: &[Vec<Card>]) -> Vec<PlayerId> {
+        let mut order = winners.to_vec();
+        match game_info.odd_chip_rule {
+            OddChipRule::FirstLeftOfButton => {
+                let first = game_info.first_player(game_info.num_rounds - 1);
+                order.sort_by_key(|p| (p + game_info.num_players - first) % game_info.num_players);
+            },
+            OddChipRule::HighestCard => {
+                order.sort_by_key(|p| Reverse(hole_cards[*p as usize].iter().map(|c| odd_chip_card_order(*c)).max()));
+            },
+            OddChipRule::Random => {
+                // Seeded from the hand so every player's payout agrees on who got the chips
+                let mut hasher = DefaultHasher::new();
+                self.hand_id.hash(&mut hasher);
+                for card in hole_cards.iter().flatten().chain(board_cards) {
+                    card.hash(&mut hasher);
                 }
+                order.shuffle(&mut StdRng::seed_from_u64(hasher.finish()));
+            },
+        }
+        order
+    }
+
+    /// Splits `pot` evenly between `winners`, giving chips that can't be split by the odd chip rule
+    fn split_pot(&self, game_info: &GameInfo, pot: u32, winners: &[PlayerId], board_cards: &[Card], hole_cards: &[Vec<Card>], winnings: &mut [u32]) {
+        for w in winners {
+            winnings[*w as usize] += pot / winners.len() as u32;
+        }
+
+        let odd_chips = (pot % winners.len() as u32) as usize;
+        if odd_chips > 0 {
+            for w in self.odd_chip_order(game_info, winners, board_cards, hole_cards).into_iter().take(odd_chips) {
+                winnings[w as usize] += 1;
             }
+        }
+    }
 
-            spent[players_left as usize] = self.spent[i as usize];
-            players_left += 1;
+    /// Returns the chips the house takes from the pot, the state must be finished
+    pub fn rake(&self, game_info: &GameInfo) -> u32 {
+        let rake = match game_info.rake() {
+            Some(rake) => rake,
+            None => return 0,
+        };
+        if rake.no_flop_no_drop && self.current_round() == 0 {
+            return 0;
         }
 
-        assert!(players_left > 1);
-        assert!(player_idx > -1);
+        let called = self.pot_total(game_info) - self.uncalled_bet().map_or(0, |(_, amount)| amount);
+        let taken = (called as f64 * rake.percentage as f64 / 100.).floor() as u32;
+        rake.cap.map_or(taken, |cap| min(taken, cap))
+    }
+
+    /// Returns the payout of every player after rake, the state must be finished
+    pub fn get_payouts<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, board_cards: &[Card], hole_cards: &[Vec<Card>]) -> Vec<i32> {
+        self.get_run_out_payouts(game_info, evaluator, &[board_cards], hole_cards)
+    }
 
-        let mut player_idx = player_idx as usize;
+    /// Returns the payout of every player when the board is run out once for each of `boards`.
+    /// Every pot is split evenly between the run-outs, the first run-outs get the odd chips.
+    pub fn get_run_out_payouts<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, boards: &[&[Card]], hole_cards: &[Vec<Card>]) -> Vec<i32> {
+        self.get_side_pot_payouts(game_info, &self.side_pots(game_info), evaluator, boards, hole_cards)
+    }
 
-        let mut value: i32 = 0;
+    /// Returns the pots of a hand that's over, main pot first. Rake comes out of the main pot
+    /// first, then the side pots in order, and pots left empty by it are dropped.
+    pub fn side_pots(&self, game_info: &GameInfo) -> SidePots {
+        let num_players = game_info.num_players() as usize;
+        let spent = (0..num_players).map(|p| self.player_spent(p as PlayerId)).collect::<Vec<u32>>();
+        let uncalled_bet = self.uncalled_bet();
+        let uncalled = uncalled_bet.map_or(0, |(_, amount)| amount);
 
-        loop {
-            let mut size = u32::MAX;
-            //CHECK: if this win_rank is correct, doing this for kuhn/leduc poker to work
-            let mut win_rank = EvalClass::HighCard { high_rank: Rank::Two };
-            let mut num_winners: i32 = 0;
+        if self.num_folded(game_info) + 1 == game_info.num_players() {
+            let winner = (0..num_players).find(|p| !self.players_folded[*p]).unwrap();
+            let pot = SidePot { amount: self.pot_total(game_info) - uncalled - self.rake(game_info), eligible: vec![winner as PlayerId] };
+            return SidePots { pots: vec![pot], spent, uncalled_bet };
+        }
+        if !self.is_finished() {
+            panic!("cannot calculate payout when the hand is not over!");
+        }
+
+        // Bets into the pots, without the part of a bet nobody matched
+        let mut called = self.spent.clone();
+        if let Some((player, amount)) = uncalled_bet {
+            called[player as usize] -= amount;
+        }
 
-            for i in 0..players_left {
-                assert!(spent[i as usize] > 0);
+        // Each distinct amount spent by a live player caps a side pot
+        let levels = (0..num_players)
+            .filter(|p| !self.players_folded[*p])
+            .map(|p| called[p])
+            .sorted()
+            .dedup()
+            .collect::<Vec<u32>>();
+
+        let mut pots = Vec::new();
+        let mut rake = self.rake(game_info);
+        let mut previous = 0;
+        for (l, level) in levels.iter().enumerate() {
+            let mut pot: u32 = called.iter().map(|s| min(*s, *level) - min(*s, previous)).sum();
+            if l == 0 {
+                // Dead blinds go to the main pot
+                pot += self.dead.iter().sum::<u32>();
+            }
+            if l + 1 == levels.len() {
+                // Chips folded players put in above every live player go to the last pot
+                pot += called.iter().map(|s| s.saturating_sub(*level)).sum::<u32>();
+            }
+            previous = *level;
+
+            let raked = min(pot, rake);
+            pot -= raked;
+            rake -= raked;
+
+            if pot > 0 {
+                let eligible = (0..num_players)
+                    .filter(|p| !self.players_folded[*p] && called[*p] >= *level)
+                    .map(|p| p as PlayerId)
+                    .collect::<Vec<PlayerId>>();
+                pots.push(SidePot { amount: pot, eligible });
+            }
+        }
+
+        SidePots { pots, spent, uncalled_bet }
+    }
 
-                if spent[i as usize] < size {
-                    size = spent[i as usize];
+    /// Like `get_run_out_payouts` with the pots of the state already computed by `side_pots`,
+    /// so only the hands are evaluated and compared. Terminal nodes of the abstract game keep
+    /// their pots so solvers don't work them out again on every visit.
+    pub fn get_side_pot_payouts<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, side_pots: &SidePots, evaluator: &E, boards: &[&[Card]], hole_cards: &[Vec<Card>]) -> Vec<i32> {
+        assert!(!boards.is_empty(), "need at least one board");
+        let num_players = game_info.num_players() as usize;
+        let mut winnings = vec![0; num_players];
+        if let Some((player, amount)) = side_pots.uncalled_bet {
+            winnings[player as usize] += amount;
+        }
+
+        if !side_pots.is_contested() {
+            for pot in side_pots.pots.iter() {
+                winnings[pot.eligible[0] as usize] += pot.amount;
+            }
+        } else {
+            let hole_cards = &self.current_hole_cards(game_info, hole_cards, boards[0])[..];
+            let run_outs = boards.iter()
+                .map(|board_cards| {
+                    let board_cards = &board_cards[..min(board_cards.len(), game_info.total_board_cards(game_info.num_rounds - 1) as usize)];
+                    let rank = (0..num_players)
+                        .map(|p| if self.players_folded[p] {
+                            None
+                        } else {
+                            Some(game_info.ranking_direction().orient(evaluator.evaluate_showdown(&hole_cards[p], board_cards)))
+                        })
+                        .collect::<Vec<Option<HandRank>>>();
+                    let low_rank = (0..num_players)
+                        .map(|p| if self.players_folded[p] || !game_info.hi_lo {
+                            None
+                        } else {
+                            evaluator.evaluate_low_showdown(&hole_cards[p], board_cards)
+                        })
+                        .collect::<Vec<Option<HandRank>>>();
+                    (board_cards, rank, low_rank)
+                })
+                .collect::<Vec<(&[Card], Vec<Option<HandRank>>, Vec<Option<HandRank>>)>>();
+
+            for SidePot { amount: pot, eligible } in side_pots.pots.iter() {
+                let pot = *pot;
+                let best = |ranks: &[Option<HandRank>]| {
+                    let win_rank = eligible.iter().map(|p| ranks[*p as usize]).max().unwrap();
+                    eligible.iter()
+                        .filter(|p| win_rank.is_some() && ranks[**p as usize] == win_rank)
+                        .copied()
+                        .collect::<Vec<PlayerId>>()
+                };
+
+                let num_run_outs = run_outs.len() as u32;
+                for (r, (board_cards, rank, low_rank)) in run_outs.iter().enumerate() {
+                    let share = pot / num_run_outs + u32::from((r as u32) < pot % num_run_outs);
+                    let high_winners = best(rank);
+                    let low_winners = best(low_rank);
+                    if low_winners.is_empty() {
+                        self.split_pot(game_info, share, &high_winners, board_cards, hole_cards, &mut winnings);
+                    } else {
+                        // The high half gets the odd chip when the pot can't be halved evenly
+                        self.split_pot(game_info, share - share / 2, &high_winners, board_cards, hole_cards, &mut winnings);
+                        self.split_pot(game_info, share / 2, &low_winners, board_cards, hole_cards, &mut winnings);
+                    }
                 }
+            }
+        }
 
-                if let Some(r) = rank[i as usize] {
-                    if r > win_rank {
-                        win_rank = r;
-                        num_winners = 1;
-                    } else if r == win_rank {
-                        num_winners += 1;
+        (0..num_players).map(|p| winnings[p] as i32 - side_pots.spent[p] as i32).collect()
+    }
+
+    /// Returns the expected payout of every player when the pots of a hand that went all in
+    /// before the last round are awarded by the equity of the hands at the all in, averaged over
+    /// every board that could have been dealt from the cards nobody holds, instead of the board
+    /// that was dealt. Other hands, and stud and draw games whose later cards aren't shared, get
+    /// their payouts on `board_cards`. The state must be finished.
+    pub fn get_all_in_ev_payouts<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, board_cards: &[Card], hole_cards: &[Vec<Card>]) -> Vec<f64> {
+        let runout_rounds = self.runout_rounds();
+        if runout_rounds.is_empty() || game_info.is_stud() || game_info.has_draws() || self.num_folded(game_info) + 1 == game_info.num_players() {
+            return self.get_payouts(game_info, evaluator, board_cards, hole_cards).into_iter().map(f64::from).collect();
+        }
+
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        let dealt = &board_cards[..game_info.total_board_cards(runout_rounds.start - 1) as usize];
+        if dealt.len() == num_board_cards {
+            return self.get_payouts(game_info, evaluator, board_cards, hole_cards).into_iter().map(f64::from).collect();
+        }
+        let remaining = game_info.generate_deck()
+            .filter(|c| !hole_cards.iter().any(|h| h.contains(c)) && !dealt.contains(c))
+            .collect::<Vec<Card>>();
+
+        // Runouts are split between threads by their first card, so every thread enumerates
+        // the runouts whose other cards come later in the deck
+        let num_run_out_cards = num_board_cards - dealt.len();
+        let num_players = game_info.num_players() as usize;
+        let sums = (0..remaining.len())
+            .into_par_iter()
+            .map(|first| {
+                let mut totals = vec![0.; num_players];
+                let mut num_boards = 0u64;
+                for rest in remaining[first + 1..].iter().combinations(num_run_out_cards - 1) {
+                    let board = dealt.iter().chain([&remaining[first]]).chain(rest).copied().collect::<Vec<Card>>();
+                    for (total, payout) in totals.iter_mut().zip(self.get_payouts(game_info, evaluator, &board, hole_cards)) {
+                        *total += payout as f64;
                     }
+                    num_boards += 1;
                 }
+                (totals, num_boards)
+            })
+            .collect::<Vec<(Vec<f64>, u64)>>();
+
+        let num_boards = sums.iter().map(|(_, n)| n).sum::<u64>();
+        (0..num_players)
+            .map(|p| sums.iter().map(|(totals, _)| totals[p]).sum::<f64>() / num_boards as f64)
+            .collect()
+    }
+
+    pub fn get_payout<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> i32 {
+        if self.has_folded(player) {
+            return -(self.player_spent(player) as i32);
+        }
+
+        self.get_payouts(game_info, evaluator, board_cards, hole_cards)[player as usize]
+    }
+
+    /// Returns a hash of the public state, the betting so far and `board_cards`, that's the same
+    /// across builds and platforms so it can key transposition tables shared between processes or
+    /// saved to disk. States agree on it exactly when `same_betting` holds and the boards match,
+    /// barring collisions.
+    pub fn public_hash(&self, board_cards: &[Card]) -> u64 {
+        let mut bytes = Vec::new();
+        bytes.push(self.round);
+        for round in 0..=self.round {
+            write_varint(&mut bytes, self.actions[round as usize].len() as u64);
+            for (player, action) in self.actions_in_round(round) {
+                bytes.push(player);
+                write_action(&mut bytes, action);
             }
+        }
+        bytes.extend(board_cards.iter().map(|c| card_index(*c)));
+        stable_hash(&bytes)
+    }
 
-            if rank[player_idx as usize].unwrap()== win_rank {
-                value += (size as i32) * (players_left - num_winners) / num_winners;
-            } else {
-                value -= size as i32;
+    /// Returns whether both states were reached by the same betting, which makes them the same
+    /// public state on the same board
+    pub fn same_betting(&self, other: &GameState) -> bool {
+        self.round == other.round && self.actions == other.actions
+    }
+
+    /// Returns the key transpositions of this state share, see `TranspositionKey`
+    pub fn transposition_key(&self) -> TranspositionKey {
+        TranspositionKey {
+            round: self.round,
+            active_player: self.active_player,
+            max_spent: self.max_spent,
+            round_start_spent: self.round_start_spent,
+            min_no_limit_raise_to: self.min_no_limit_raise_to,
+            spent: self.spent.clone(),
+            stack_player: self.stack_player.clone(),
+            dead: self.dead.clone(),
+            round_spent: self.sum_round_spent.clone(),
+            players_folded: self.players_folded.clone(),
+            can_raise: self.can_raise.clone(),
+            round_actions: self.actions[self.round as usize].clone(),
+            discards: self.full_history()
+                .filter_map(|(_, player, action)| match action {
+                    Action::Discard(mask) => Some((player, mask)),
+                    _ => None,
+                })
+                .collect(),
+            all_in_round: self.all_in_round,
+            bring_in_player: self.bring_in_player,
+            drawing: self.drawing,
+            awaiting_up_cards: self.awaiting_up_cards,
+            finished: self.finished,
+        }
+    }
+
+    /// Encodes the state in a compact binary form for sending between processes, only the
+    /// seats of the game and the rounds played so far are written and numbers are varints.
+    /// Decode with `GameState::decode` in the same game.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in [self.hand_id, self.max_spent, self.round_start_spent, self.min_no_limit_raise_to] {
+            write_varint(&mut bytes, value as u64);
+        }
+
+        let num_players = self.spent.len();
+        bytes.push(num_players as u8);
+        for p in 0..num_players {
+            write_varint(&mut bytes, self.spent[p] as u64);
+            write_varint(&mut bytes, self.stack_player[p] as u64);
+            write_varint(&mut bytes, self.dead[p] as u64);
+        }
+
+        bytes.push(self.round);
+        for round in 0..=self.round as usize {
+            for spent in self.sum_round_spent[round].iter() {
+                write_varint(&mut bytes, *spent as u64);
             }
+            write_varint(&mut bytes, self.actions[round].len() as u64);
+            for (player, action) in self.actions_in_round(round as u8) {
+                bytes.push(player);
+                write_action(&mut bytes, action);
+            }
+        }
 
-            let mut new_players_left = 0;
-            for i in 0..players_left as usize {
-                spent[i] -= size;
-                if spent[i] == 0 {
-                    if i == player_idx as usize {
-                        return value;
-                    }
+        bytes.push(self.active_player);
+        bytes.push(self.all_in_round.map_or(0, |r| r + 1));
+        bytes.push(self.bring_in_player.map_or(0, |p| p + 1));
+        bytes.push(self.drawing as u8 | (self.awaiting_up_cards as u8) << 1 | (self.finished as u8) << 2);
+        let mask = |flags: &[bool]| flags.iter().enumerate().fold(0, |mask, (p, f)| mask | (*f as u64) << p);
+        write_varint(&mut bytes, mask(&self.players_folded));
+        write_varint(&mut bytes, mask(&self.can_raise));
+        bytes
+    }
 
-                    continue;
-                }
+    /// Decodes a state written by `GameState::encode`, rejecting bytes that don't make a
+    /// consistent state of the game
+    pub fn decode(game_info: &GameInfo, bytes: &[u8]) -> Result<GameState, &'static str> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        let hand_id = reader.varint_u32()?;
+        let max_spent = reader.varint_u32()?;
+        let round_start_spent = reader.varint_u32()?;
+        let min_no_limit_raise_to = reader.varint_u32()?;
+
+        let num_players = reader.byte()? as usize;
+        // Folded players and who may raise are bit masks of the seats
+        if num_players > 64 {
+            return Err("encoded state has too many players");
+        }
+        let mut spent = Vec::with_capacity(num_players);
+        let mut stack_player = Vec::with_capacity(num_players);
+        let mut dead = Vec::with_capacity(num_players);
+        for _ in 0..num_players {
+            spent.push(reader.varint_u32()?);
+            stack_player.push(reader.varint_u32()?);
+            dead.push(reader.varint_u32()?);
+        }
 
-                if i == player_idx {
-                    player_idx = i;
-                }
+        let round = reader.byte()?;
+        let mut sum_round_spent = vec![vec![0; num_players]; round as usize + 1];
+        let mut actions = vec![Vec::new(); round as usize + 1];
+        for r in 0..=round as usize {
+            for spent in sum_round_spent[r].iter_mut() {
+                *spent = reader.varint_u32()?;
+            }
+            let num_actions = reader.varint()?;
+            for _ in 0..num_actions {
+                let player = reader.byte()?;
+                let action = match reader.byte()? {
+                    0 => Action::Fold,
+                    1 => Action::Call,
+                    2 => Action::Raise(reader.varint_u32()?),
+                    3 => Action::Discard(reader.byte()?),
+                    _ => return Err("encoded state has an invalid action"),
+                };
+                actions[r].push((player, action));
+            }
+        }
 
-                if i != new_players_left {
-                    spent[new_players_left] = spent[i];
-                    spent[new_players_left] = spent[i];
-                }
+        let active_player = reader.byte()?;
+        let all_in_round = reader.byte()?.checked_sub(1);
+        let bring_in_player = reader.byte()?.checked_sub(1);
+        let flags = reader.byte()?;
+        let folded_mask = reader.varint()?;
+        let can_raise_mask = reader.varint()?;
+        if reader.position != bytes.len() {
+            return Err("encoded state has trailing bytes");
+        }
+
+        let state = GameState {
+            hand_id,
+            max_spent,
+            round_start_spent,
+            min_no_limit_raise_to,
+            spent,
+            stack_player,
+            dead,
+            sum_round_spent,
+            actions,
+            active_player,
+            round,
+            all_in_round,
+            drawing: flags & 1 != 0,
+            awaiting_up_cards: flags & 2 != 0,
+            bring_in_player,
+            finished: flags & 4 != 0,
+            players_folded: (0..num_players).map(|p| folded_mask >> p & 1 != 0).collect(),
+            can_raise: (0..num_players).map(|p| can_raise_mask >> p & 1 != 0).collect(),
+        };
+        state.check_invariants(game_info)?;
+        Ok(state)
+    }
+}
+
+/// Writes `value` seven bits at a time, low bits first, with the high bit set on every byte but
+/// the last
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_action(bytes: &mut Vec<u8>, action: Action) {
+    match action {
+        Action::Fold => bytes.push(0),
+        Action::Call => bytes.push(1),
+        Action::Raise(r) => {
+            bytes.push(2);
+            write_varint(bytes, r as u64);
+        },
+        Action::Discard(mask) => bytes.extend([3, mask]),
+    }
+}
+
+/// FNV-1a hash of `bytes`, stable between builds unlike the std hashers
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
 
-                new_players_left += 1;
+/// Reads the bytes of an encoded `GameState` in order
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn byte(&mut self) -> Result<u8, &'static str> {
+        let byte = *self.bytes.get(self.position).ok_or("encoded state is truncated")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, &'static str> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
             }
-            players_left = new_players_left as i32;
         }
+        Err("encoded state has an invalid varint")
+    }
+
+    fn varint_u32(&mut self) -> Result<u32, &'static str> {
+        self.varint()?.try_into().map_err(|_| "encoded state has a number out of range")
     }
 }
 
+impl fmt::Display for GameState {
+    /// Renders the chips of every seat and the actions of each round as a small table
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pot: u32 = (0..self.spent.len()).map(|p| self.player_spent(p as PlayerId)).sum();
+        writeln!(f, "hand {}, round {}, pot {}", self.hand_id, self.round, pot)?;
+        writeln!(f, "{:<6}{:>8}{:>8}  status", "seat", "behind", "in pot")?;
+        for p in 0..self.spent.len() {
+            let status = if self.players_folded[p] {
+                "folded"
+            } else if self.spent[p] >= self.stack_player[p] {
+                "all in"
+            } else if !self.finished && !self.awaiting_up_cards && self.active_player as usize == p {
+                "to act"
+            } else {
+                ""
+            };
+            writeln!(f, "{:<6}{:>8}{:>8}  {}", p, self.stack_player[p] - self.spent[p], self.player_spent(p as PlayerId), status)?;
+        }
+        for round in 0..=self.round {
+            let actions = self.actions_in_round(round).map(|(p, a)| format!("seat {} {}", p, a)).join(", ");
+            if !actions.is_empty() {
+                writeln!(f, "round {}: {}", round, actions)?;
+            }
+        }
+        if self.finished {
+            writeln!(f, "finished")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// No limit Leduc with a seat for every stack and blinds of 1 and 2
+    fn no_limit_game(stacks: &[u32]) -> GameInfo {
+        GameInfo::from_json(&format!(r#"{{
+            "starting_stacks": {:?},
+            "forced_bets": {{"Blinds": {{"small_blind": 1, "big_blind": 2}}}},
+            "raise_sizes": [0, 0],
+            "betting_type": "NoLimit",
+            "num_players": {},
+            "num_rounds": 2,
+            "max_raises": [10, 10],
+            "num_suits": 2,
+            "num_ranks": 4,
+            "num_hole_cards": 1,
+            "num_board_cards": [0, 1],
+            "hand_ranking": "Leduc"
+        }}"#, stacks, stacks.len())).unwrap()
+    }
+
+    fn act(game_info: &GameInfo, state: &GameState, action: Action) -> GameState {
+        assert!(state.is_valid_action(game_info, action), "{:?} is invalid for player {:?}", action, state.current_player());
+        state.apply_action_no_cards(game_info, action).unwrap()
+    }
+
+    /// Returns the seats in the order they first act
+    fn acting_order(game_info: &GameInfo) -> Vec<PlayerId> {
+        let mut state = GameState::new(game_info, 0);
+        let mut order = Vec::new();
+        for _ in 0..game_info.num_players() {
+            order.push(state.current_player().unwrap());
+            state = act(game_info, &state, Action::Call);
+        }
+        order
+    }
+
+    /// Returns `game_info` with stacks of 100 chips except for the given seats and stacks
+    fn with_stacks(mut game_info: GameInfo, stacks: &[(PlayerId, u32)]) -> GameInfo {
+        let mut starting_stacks = vec![100; game_info.num_players() as usize];
+        for (seat, stack) in stacks {
+            starting_stacks[*seat as usize] = *stack;
+        }
+        game_info.set_starting_stacks(starting_stacks);
+        game_info
+    }
+
+    #[test]
+    fn short_all_in_doesnt_reopen_betting() {
+        let game_info = no_limit_game(&[100, 100, 100]);
+        let order = acting_order(&game_info);
+        let game_info = with_stacks(game_info, &[(order[2], 8)]);
+
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Raise(6));
+        let state = act(&game_info, &state, Action::Call);
+        // Raising 2 over 6 is short of the full raise of 4
+        assert_eq!(state.raise_bounds(&game_info, order[2]), Some((8, 8)));
+        let state = act(&game_info, &state, Action::Raise(8));
+
+        assert_eq!(state.current_player(), Ok(order[0]));
+        assert!(!state.can_raise[order[0] as usize]);
+        assert!(!state.is_valid_action(&game_info, Action::Raise(12)));
+        assert!(!state.is_valid_action(&game_info, Action::Raise(100)));
+        assert_eq!(state.raise_bounds(&game_info, order[0]), None);
+        assert!(state.is_valid_action(&game_info, Action::Call));
+    }
+
+    #[test]
+    fn full_raise_reopens_betting() {
+        let game_info = no_limit_game(&[100, 100, 100]);
+        let order = acting_order(&game_info);
+
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Raise(6));
+        let state = act(&game_info, &state, Action::Call);
+        let state = act(&game_info, &state, Action::Raise(10));
+
+        assert_eq!(state.current_player(), Ok(order[0]));
+        assert!(state.can_raise[order[0] as usize]);
+        assert_eq!(state.raise_bounds(&game_info, order[0]), Some((14, 100)));
+        assert!(state.is_valid_action(&game_info, Action::Raise(14)));
+        assert!(!state.is_valid_action(&game_info, Action::Raise(13)));
+    }
+
+    #[test]
+    fn short_all_in_keeps_size_of_last_full_raise() {
+        let game_info = no_limit_game(&[100, 100, 100]);
+        let order = acting_order(&game_info);
+        let game_info = with_stacks(game_info, &[(order[1], 9)]);
+
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Raise(6));
+        assert_eq!(state.min_no_limit_raise_to, 10);
+        let state = act(&game_info, &state, Action::Raise(9));
+        // The next full raise is still 4 over the all in
+        assert_eq!(state.min_no_limit_raise_to, 13);
+        assert_eq!(state.current_player(), Ok(order[2]));
+        assert_eq!(state.raise_bounds(&game_info, order[2]), Some((13, 100)));
+        assert!(!state.is_valid_action(&game_info, Action::Raise(12)));
+    }
+
+    /// A hand in the middle of the second round after a raise and a call
+    fn second_round_state(game_info: &GameInfo) -> GameState {
+        let state = GameState::new(game_info, 7);
+        let state = act(game_info, &state, Action::Raise(6));
+        let state = act(game_info, &state, Action::Call);
+        act(game_info, &state, Action::Raise(12))
+    }
+
+    #[test]
+    fn encode_decode_round_trips() {
+        let game_info = no_limit_game(&[100, 100]);
+        for state in [GameState::new(&game_info, 3), second_round_state(&game_info)] {
+            let decoded = GameState::decode(&game_info, &state.encode()).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", state));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_corrupt_states() {
+        let game_info = no_limit_game(&[100, 100]);
+        let state = second_round_state(&game_info);
+        assert_eq!(state.current_round(), 1);
+        let corrupt: [fn(&mut GameState); 7] = [
+            |s| s.active_player = 2,
+            |s| s.actions[0][0].0 = 5,
+            |s| s.bring_in_player = Some(9),
+            |s| s.all_in_round = Some(3),
+            |s| {
+                s.round = 2;
+                s.actions.push(Vec::new());
+                s.sum_round_spent.push(vec![0; 2]);
+            },
+            |s| s.sum_round_spent[1] = vec![0; 2],
+            |s| s.actions[1].clear(),
+        ];
+        for (i, corrupt) in corrupt.iter().enumerate() {
+            let mut state = state.clone();
+            corrupt(&mut state);
+            assert!(GameState::decode(&game_info, &state.encode()).is_err(), "corruption {} was decoded", i);
+        }
+
+        let bytes = state.encode();
+        assert!(GameState::decode(&game_info, &bytes[..bytes.len() - 1]).is_err());
+        assert!(GameState::decode(&game_info, &[bytes.as_slice(), &[0]].concat()).is_err());
+        // Bytes of a game with more seats
+        assert!(GameState::decode(&no_limit_game(&[100, 100, 100]), &bytes).is_err());
+    }
+
+    fn ten_percent_rake() -> Option<Rake> {
+        Some(Rake { percentage: 10., cap: None, no_flop_no_drop: false })
+    }
+
+    fn sorted(players: &[PlayerId]) -> Vec<PlayerId> {
+        players.iter().copied().sorted().collect()
+    }
+
+    #[test]
+    fn bet_called_short_by_all_in_returns_excess() {
+        let game_info = no_limit_game(&[100, 100]);
+        let order = acting_order(&game_info);
+        let mut game_info = with_stacks(game_info, &[(order[1], 30)]);
+
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Raise(50));
+        let state = act(&game_info, &state, Action::Call);
+        assert!(state.is_finished());
+        assert_eq!(state.uncalled_bet(), Some((order[0], 20)));
+
+        let side_pots = state.side_pots(&game_info);
+        assert_eq!(side_pots.uncalled_bet, Some((order[0], 20)));
+        assert_eq!(side_pots.pots, vec![SidePot { amount: 60, eligible: sorted(&order) }]);
+
+        // The rake is a share of the 60 chips called, not the 80 bet
+        game_info.set_rake(ten_percent_rake());
+        assert_eq!(state.rake(&game_info), 6);
+        assert_eq!(state.side_pots(&game_info).pots, vec![SidePot { amount: 54, eligible: sorted(&order) }]);
+    }
+
+    #[test]
+    fn fold_to_bet_returns_whole_bet() {
+        let mut game_info = no_limit_game(&[100, 100]);
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Call);
+        let state = act(&game_info, &state, Action::Call);
+        assert_eq!(state.current_round(), 1);
+
+        let bettor = state.current_player().unwrap();
+        let state = act(&game_info, &state, Action::Raise(12));
+        let state = act(&game_info, &state, Action::Fold);
+        assert_eq!(state.uncalled_bet(), Some((bettor, 10)));
+
+        let side_pots = state.side_pots(&game_info);
+        assert_eq!(side_pots.uncalled_bet, Some((bettor, 10)));
+        assert_eq!(side_pots.pots, vec![SidePot { amount: 4, eligible: vec![bettor] }]);
+
+        game_info.set_rake(Some(Rake { percentage: 50., cap: None, no_flop_no_drop: false }));
+        assert_eq!(state.rake(&game_info), 2);
+        assert_eq!(state.side_pots(&game_info).pots, vec![SidePot { amount: 2, eligible: vec![bettor] }]);
+    }
+
+    #[test]
+    fn only_top_side_pot_is_uncalled() {
+        let game_info = no_limit_game(&[100, 100, 100]);
+        let order = acting_order(&game_info);
+        let mut game_info = with_stacks(game_info, &[(order[1], 20), (order[2], 40)]);
+
+        let state = GameState::new(&game_info, 0);
+        let state = act(&game_info, &state, Action::Raise(60));
+        let state = act(&game_info, &state, Action::Call);
+        let state = act(&game_info, &state, Action::Call);
+        assert!(state.is_finished());
+        // Only the 20 chips past the larger all in go back
+        assert_eq!(state.uncalled_bet(), Some((order[0], 20)));
+
+        let side_pots = state.side_pots(&game_info);
+        assert_eq!(side_pots.pots, vec![
+            SidePot { amount: 60, eligible: sorted(&order) },
+            SidePot { amount: 40, eligible: sorted(&[order[0], order[2]]) },
+        ]);
+
+        // Rake of the 100 chips called comes out of the main pot
+        game_info.set_rake(ten_percent_rake());
+        let side_pots = state.side_pots(&game_info);
+        assert_eq!(side_pots.uncalled_bet, Some((order[0], 20)));
+        assert_eq!(side_pots.pots, vec![
+            SidePot { amount: 50, eligible: sorted(&order) },
+            SidePot { amount: 40, eligible: sorted(&[order[0], order[2]]) },
+        ]);
+    }
+
+    /// No limit Leduc for three players where nobody posts anything
+    fn no_forced_bets_game() -> GameInfo {
+        let mut game_info = no_limit_game(&[100, 100, 100]);
+        game_info.set_forced_bets(ForcedBets::None);
+        game_info
+    }
+
+    #[test]
+    fn no_forced_bets_first_bet_is_one_chip() {
+        let game_info = no_forced_bets_game();
+        let state = GameState::new(&game_info, 0);
+        let player = state.current_player().unwrap();
+
+        assert_eq!(state.pot_total(&game_info), 0);
+        assert_eq!(state.raise_bounds(&game_info, player), Some((1, 100)));
+        assert!(state.is_valid_action(&game_info, Action::Raise(1)));
+        assert!(!state.is_valid_action(&game_info, Action::Raise(0)));
+    }
+
+    #[test]
+    fn no_forced_bets_check_around_closes_each_round() {
+        let game_info = no_forced_bets_game();
+        let mut state = GameState::new(&game_info, 0);
+
+        for round in 0..game_info.num_rounds() {
+            assert_eq!(state.current_round(), round);
+            for _ in 0..game_info.num_players() {
+                assert!(!state.is_finished());
+                state = act(&game_info, &state, Action::Call);
+            }
+        }
+
+        assert!(state.is_finished());
+        assert_eq!(state.pot_total(&game_info), 0);
+    }
+
+    #[test]
+    fn no_forced_bets_num_called() {
+        let game_info = no_forced_bets_game();
+        let state = GameState::new(&game_info, 0);
+        assert_eq!(state.num_called(&game_info), 0);
+
+        let state = act(&game_info, &state, Action::Call);
+        let state = act(&game_info, &state, Action::Call);
+        assert_eq!(state.num_called(&game_info), 2);
+
+        // A bet starts the count again from the bettor
+        let state = act(&game_info, &state, Action::Raise(4));
+        assert_eq!(state.num_called(&game_info), 1);
+        let state = act(&game_info, &state, Action::Call);
+        assert_eq!(state.num_called(&game_info), 2);
+    }
+
+    #[test]
+    fn no_forced_bets_pot_ratio_bets_at_least_one_chip() {
+        let game_info = no_forced_bets_game();
+        let pot_raise = AbstractRaise {
+            raise_type: AbstractRaiseType::PotRatio(1.),
+            round_config: vec![RaiseRoundConfig::Always; 2],
+        };
+
+        let state = GameState::new(&game_info, 0);
+        assert_eq!(state.abstract_raise_to_real(&game_info, &pot_raise), Some(Action::Raise(1)));
+
+        // Still an empty pot after everyone checks the first round
+        let mut state = state;
+        for _ in 0..game_info.num_players() {
+            state = act(&game_info, &state, Action::Call);
+        }
+        assert_eq!(state.current_round(), 1);
+        assert_eq!(state.abstract_raise_to_real(&game_info, &pot_raise), Some(Action::Raise(1)));
+    }
+
+    #[test]
+    fn decks_keep_the_highest_ranks() {
+        let configs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+        let ranks = |config: &str| GameInfo::load_game_info(&configs.join(config)).unwrap().generate_deck().map(|c| c.rank()).collect::<Vec<Rank>>();
+
+        assert_eq!(ranks("kuhn.json"), [Rank::Queen, Rank::King, Rank::Ace]);
+        assert_eq!(ranks("leduc.json"), [Rank::Queen, Rank::Queen, Rank::King, Rank::King, Rank::Ace, Rank::Ace]);
+        let short_deck = ranks("short_deck.json");
+        assert_eq!(short_deck.len(), 36);
+        assert_eq!((short_deck[0], short_deck[35]), (Rank::Six, Rank::Ace));
+    }
+
+    #[test]
+    fn tied_up_cards_after_the_bring_in_go_to_position() {
+        let configs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("game_configs");
+        let game_info = GameInfo::load_game_info(&configs.join("seven_card_stud.json")).unwrap();
+        let evaluator = game_info.hand_ranking().evaluator();
+        let cards = |cards: &[(Rank, Suit)]| cards.iter().map(|(r, s)| Card::new(*r, *s)).collect::<Vec<Card>>();
+        // Both players show king five offsuit on fourth street, the second player with the king of spades
+        let hole_cards = [
+            cards(&[(Rank::Two, Suit::Clubs), (Rank::Three, Suit::Clubs), (Rank::King, Suit::Hearts), (Rank::Five, Suit::Clubs), (Rank::Seven, Suit::Clubs), (Rank::Eight, Suit::Clubs), (Rank::Nine, Suit::Clubs)]),
+            cards(&[(Rank::Two, Suit::Diamonds), (Rank::Three, Suit::Diamonds), (Rank::King, Suit::Spades), (Rank::Five, Suit::Diamonds), (Rank::Seven, Suit::Diamonds), (Rank::Eight, Suit::Diamonds), (Rank::Nine, Suit::Diamonds)]),
+        ];
+
+        let mut state = GameState::new(&game_info, 0).deal_up_cards(&game_info, evaluator, &hole_cards).unwrap();
+        // The king of hearts is the lower card, so the first player brings in
+        assert_eq!(state.bring_in_player(), Some(0));
+        while state.current_round() == 0 {
+            state = act(&game_info, &state, Action::Call);
+        }
+
+        assert!(state.is_awaiting_up_cards());
+        let state = state.deal_up_cards(&game_info, evaluator, &hole_cards).unwrap();
+        assert_eq!(game_info.first_player(1), 0);
+        assert_eq!(state.current_player(), Ok(0));
+    }
+
+    #[test]
+    fn transpositions_put_in_the_same_chips_every_round() {
+        // No limit Leduc with a third round
+        let game_info = GameInfo::from_json(r#"{
+            "starting_stacks": [100, 100],
+            "forced_bets": {"Blinds": {"small_blind": 1, "big_blind": 2}},
+            "raise_sizes": [0, 0, 0],
+            "betting_type": "NoLimit",
+            "num_players": 2,
+            "num_rounds": 3,
+            "max_raises": [10, 10, 10],
+            "num_suits": 2,
+            "num_ranks": 4,
+            "num_hole_cards": 1,
+            "num_board_cards": [0, 1, 1],
+            "hand_ranking": "Standard"
+        }"#).unwrap();
+        let play = |actions: &[Action]| actions.iter().fold(GameState::new(&game_info, 0), |state, action| act(&game_info, &state, *action));
+
+        // Either player raising to 4 before the flop reaches the same state
+        let raised_first = play(&[Action::Raise(4), Action::Call]);
+        let raised_second = play(&[Action::Call, Action::Raise(4), Action::Call]);
+        assert_eq!(raised_first.current_round(), 1);
+        assert_eq!(raised_first.transposition_key(), raised_second.transposition_key());
+
+        // Betting 4 chips a player over different rounds doesn't
+        let raised_then_checked = play(&[Action::Raise(4), Action::Call, Action::Call, Action::Call]);
+        let checked_then_raised = play(&[Action::Call, Action::Call, Action::Raise(4), Action::Call]);
+        assert_eq!(raised_then_checked.current_round(), 2);
+        assert_eq!(raised_then_checked.pot_total(&game_info), checked_then_raised.pot_total(&game_info));
+        assert_ne!(raised_then_checked.transposition_key(), checked_then_raised.transposition_key());
+    }
+
+    #[test]
+    fn no_limit_stud_can_raise_the_bring_in() {
+        let game_info = GameInfo::from_json(r#"{
+            "starting_stacks": [200, 200],
+            "forced_bets": {"Antes": 1},
+            "raise_sizes": [0, 0, 0, 0, 0],
+            "betting_type": "NoLimit",
+            "num_players": 2,
+            "num_rounds": 5,
+            "max_raises": [4, 4, 4, 4, 4],
+            "num_suits": 4,
+            "num_ranks": 13,
+            "num_hole_cards": 2,
+            "num_board_cards": [0, 0, 0, 0, 0],
+            "up_cards": [1, 1, 1, 1, 0],
+            "down_cards": [0, 0, 0, 0, 1],
+            "bring_in": 5
+        }"#).unwrap();
+        let evaluator = game_info.hand_ranking().evaluator();
+        let cards = |cards: &[(Rank, Suit)]| cards.iter().map(|(r, s)| Card::new(*r, *s)).collect::<Vec<Card>>();
+        let hole_cards = [
+            cards(&[(Rank::Two, Suit::Clubs), (Rank::Three, Suit::Clubs), (Rank::Four, Suit::Hearts), (Rank::Five, Suit::Clubs), (Rank::Seven, Suit::Clubs), (Rank::Eight, Suit::Clubs), (Rank::Nine, Suit::Clubs)]),
+            cards(&[(Rank::Two, Suit::Diamonds), (Rank::Three, Suit::Diamonds), (Rank::King, Suit::Spades), (Rank::Five, Suit::Diamonds), (Rank::Seven, Suit::Diamonds), (Rank::Eight, Suit::Diamonds), (Rank::Nine, Suit::Diamonds)]),
+        ];
+
+        let state = GameState::new(&game_info, 0).deal_up_cards(&game_info, evaluator, &hole_cards).unwrap();
+        assert_eq!(state.bring_in_player(), Some(0));
+        assert_eq!(state.current_player(), Ok(1));
+        // Raising the bring in takes at least another bring in
+        let (min_raise, _) = state.raise_bounds(&game_info, 1).unwrap();
+        assert_eq!(min_raise, state.max_spent + 5);
+
+        let state = act(&game_info, &state, Action::Raise(min_raise));
+        assert_eq!(state.current_player(), Ok(0));
+        let state = act(&game_info, &state, Action::Call);
+        assert_eq!(state.current_round(), 1);
+    }
+}
@@ -0,0 +1,440 @@
+/*
+* Port of Kevin Waugh's hand indexer https://github.com/kdub0/hand-isomorphism, see
+* http://www.kevinwaugh.com/pdf/isomorphism13.pdf. Generalized to the smaller decks used by
+* games like Kuhn and Leduc poker.
+*/
+
+use super::game::{DeckIndex, GameInfo};
+
+use poker::Card;
+
+use serde::{Deserialize, Serialize};
+
+pub type HandIndex = u64;
+
+/// Bits used to store the number of cards of a suit dealt in a round
+const ROUND_SHIFT: u32 = 4;
+const ROUND_MASK: u64 = 0xf;
+const MAX_ROUNDS: usize = (u64::BITS / ROUND_SHIFT) as usize;
+
+fn n_choose_k(n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0;
+    }
+
+    let mut result: u128 = 1;
+    for i in 0..k {
+        result = result * (n - i) as u128 / (i + 1) as u128;
+    }
+    result as u64
+}
+
+/// Colexicographical index of a set of ranks
+fn rank_set_to_index(rank_set: u32) -> u64 {
+    let mut index = 0;
+    let mut set = rank_set;
+    let mut i = 1;
+    while set != 0 {
+        index += n_choose_k(set.trailing_zeros() as u64, i);
+        set &= set - 1;
+        i += 1;
+    }
+    index
+}
+
+/// Inverse of `rank_set_to_index` for sets of `size` ranks
+fn index_to_rank_set(size: u32, index: u64) -> u32 {
+    let mut rank_set = 0;
+    let mut index = index;
+    for k in (1..=size as u64).rev() {
+        let mut c = k - 1;
+        while n_choose_k(c + 1, k) <= index {
+            c += 1;
+        }
+        rank_set |= 1 << c;
+        index -= n_choose_k(c, k);
+    }
+    rank_set
+}
+
+/// Position of the nth unset bit of `used`
+fn nth_unset(used: u32, n: u32) -> u32 {
+    let mut set = !used;
+    for _ in 0..n {
+        set &= set - 1;
+    }
+    set.trailing_zeros()
+}
+
+/// Index of the multiset `group` in the combinatorial number system, sorts `group`
+fn multiset_index(group: &mut [u64]) -> u64 {
+    group.sort_unstable();
+    group.iter().enumerate().map(|(k, a)| n_choose_k(a + k as u64, k as u64 + 1)).sum()
+}
+
+/// Provides a bijection between suit isomorphic hands and dense indices in `0..round_size(round)`.
+/// Hands are the hole cards followed by the board cards in the order they were dealt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandIndexer {
+    num_suits: u8,
+    num_ranks: u8,
+    cards_per_round: Vec<u8>,
+    round_start: Vec<u8>,
+    /// configurations[r] gives the sorted suit configurations of round r, where a suit
+    /// configuration packs how many cards of that suit were dealt each round
+    configurations: Vec<Vec<Vec<u64>>>,
+    configuration_to_offset: Vec<Vec<HandIndex>>,
+    /// Number of ways to deal the ranks of a single suit of a configuration
+    configuration_to_suit_size: Vec<Vec<Vec<HandIndex>>>,
+    round_size: Vec<HandIndex>,
+}
+
+impl HandIndexer {
+    pub fn new(num_suits: u8, num_ranks: u8, cards_per_round: &[u8]) -> HandIndexer {
+        let deck = DeckIndex::new(num_suits, num_ranks);
+        assert!(!cards_per_round.is_empty() && cards_per_round.len() <= MAX_ROUNDS);
+        assert!(cards_per_round.iter().map(|c| *c as u32).sum::<u32>() <= deck.len());
+
+        let mut round_start = Vec::new();
+        let mut start = 0;
+        for c in cards_per_round {
+            round_start.push(start);
+            start += c;
+        }
+
+        let mut indexer = HandIndexer {
+            num_suits,
+            num_ranks,
+            cards_per_round: cards_per_round.to_vec(),
+            round_start,
+            configurations: vec![Vec::new(); cards_per_round.len()],
+            configuration_to_offset: vec![Vec::new(); cards_per_round.len()],
+            configuration_to_suit_size: vec![Vec::new(); cards_per_round.len()],
+            round_size: vec![0; cards_per_round.len()],
+        };
+
+        let mut used = vec![0; num_suits as usize];
+        let mut configuration = vec![0; num_suits as usize];
+        indexer.enumerate_configurations(0, cards_per_round[0], 0, &mut used, &mut configuration);
+
+        for round in 0..indexer.num_rounds() {
+            indexer.configurations[round].sort();
+
+            let mut offset = 0;
+            for configuration in &indexer.configurations[round] {
+                let mut configuration_size = 1;
+                let mut suit_sizes = Vec::new();
+                let mut i = 0;
+                while i < configuration.len() {
+                    let mut size = 1;
+                    let mut remaining = num_ranks as u64;
+                    for r in 0..=round {
+                        let n = indexer.cards_in_suit_round(configuration[i], r);
+                        size *= n_choose_k(remaining, n);
+                        remaining -= n;
+                    }
+
+                    let j = (i..configuration.len()).find(|j| configuration[*j] != configuration[i]).unwrap_or(configuration.len());
+                    suit_sizes.extend(std::iter::repeat_n(size, j - i));
+                    configuration_size *= n_choose_k(size + (j - i) as u64 - 1, (j - i) as u64);
+                    i = j;
+                }
+
+                indexer.configuration_to_offset[round].push(offset);
+                indexer.configuration_to_suit_size[round].push(suit_sizes);
+                offset += configuration_size;
+            }
+            indexer.round_size[round] = offset;
+        }
+
+        indexer
+    }
+
+    /// Creates an indexer where the first round holds the hole cards and the board cards of the
+    /// first betting round
+    pub fn from_game_info(game_info: &GameInfo) -> HandIndexer {
+        let cards_per_round = (0..game_info.num_rounds())
+            .map(|r| game_info.num_board_cards(r) + if r == 0 { game_info.num_hole_cards() } else { 0 })
+            .collect::<Vec<u8>>();
+        HandIndexer::new(game_info.num_suits(), game_info.num_ranks(), &cards_per_round)
+    }
+
+    pub fn num_suits(&self) -> u8 {
+        self.num_suits
+    }
+
+    pub fn num_ranks(&self) -> u8 {
+        self.num_ranks
+    }
+
+    pub fn cards_per_round(&self) -> &[u8] {
+        &self.cards_per_round
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.cards_per_round.len()
+    }
+
+    /// Returns number of cards in a hand at `round`
+    pub fn num_cards(&self, round: usize) -> usize {
+        (self.round_start[round] + self.cards_per_round[round]) as usize
+    }
+
+    /// Returns number of canonical hands in `round`
+    pub fn round_size(&self, round: usize) -> HandIndex {
+        self.round_size[round]
+    }
+
+    fn cards_in_suit_round(&self, suit_configuration: u64, round: usize) -> u64 {
+        (suit_configuration >> (ROUND_SHIFT as usize * (self.num_rounds() - round - 1))) & ROUND_MASK
+    }
+
+    /// Enumerates suit configurations where suits are sorted in decreasing order, so each
+    /// configuration represents one class of suit isomorphic hands
+    fn enumerate_configurations(&mut self, round: usize, remaining: u8, suit: usize, used: &mut [u8], configuration: &mut [u64]) {
+        let num_suits = self.num_suits as usize;
+        if suit == num_suits {
+            self.configurations[round].push(configuration.to_vec());
+            if round + 1 < self.num_rounds() {
+                self.enumerate_configurations(round + 1, self.cards_per_round[round + 1], 0, used, configuration);
+            }
+            return;
+        }
+
+        let shift = ROUND_SHIFT as usize * (self.num_rounds() - round - 1);
+        let min = if suit == num_suits - 1 { remaining } else { 0 };
+        let mut max = remaining.min(self.num_ranks - used[suit]);
+
+        // Only allow this suit to come before the previous one when they were dealt identically so far
+        let earlier_rounds = |c: u64| c.checked_shr(shift as u32 + ROUND_SHIFT).unwrap_or(0);
+        let was_equal = suit > 0 && earlier_rounds(configuration[suit - 1]) == earlier_rounds(configuration[suit]);
+        if was_equal {
+            max = max.min(((configuration[suit - 1] >> shift) & ROUND_MASK) as u8);
+        }
+
+        if min > max {
+            return;
+        }
+
+        let old_configuration = configuration[suit];
+        let old_used = used[suit];
+        for i in min..=max {
+            configuration[suit] = old_configuration | (i as u64) << shift;
+            used[suit] = old_used + i;
+            self.enumerate_configurations(round, remaining - i, suit + 1, used, configuration);
+        }
+        configuration[suit] = old_configuration;
+        used[suit] = old_used;
+    }
+
+    fn deck_index(&self) -> DeckIndex {
+        DeckIndex::new(self.num_suits, self.num_ranks)
+    }
+
+    fn card_to_rank_suit(&self, card: Card) -> (u32, usize) {
+        let (rank, suit) = self.deck_index().rank_and_suit(card).unwrap_or_else(|| panic!("card {} is not in the deck", card));
+        (rank as u32, suit as usize)
+    }
+
+    /// Returns the index of the hand for every round that `cards` completes
+    pub fn index_all(&self, cards: &[Card]) -> Vec<HandIndex> {
+        let num_suits = self.num_suits as usize;
+        let mut used = vec![0u32; num_suits];
+        let mut suit_index = vec![0u64; num_suits];
+        let mut suit_multiplier = vec![1u64; num_suits];
+        let mut configuration = vec![0u64; num_suits];
+        let mut indices = Vec::new();
+
+        for round in 0..self.num_rounds() {
+            if cards.len() < self.num_cards(round) {
+                break;
+            }
+
+            let mut ranks = vec![0u32; num_suits];
+            for card in &cards[self.round_start[round] as usize..self.num_cards(round)] {
+                let (rank, suit) = self.card_to_rank_suit(*card);
+                assert!((used[suit] | ranks[suit]) & 1 << rank == 0, "card {} dealt twice", card);
+                ranks[suit] |= 1 << rank;
+            }
+
+            let shift = ROUND_SHIFT as usize * (self.num_rounds() - round - 1);
+            for suit in 0..num_suits {
+                // Ranks are shifted down past the ranks of this suit dealt in earlier rounds
+                let mut shifted_ranks = 0;
+                let mut set = ranks[suit];
+                while set != 0 {
+                    let bit = set & set.wrapping_neg();
+                    shifted_ranks |= bit >> (used[suit] & (bit - 1)).count_ones();
+                    set ^= bit;
+                }
+
+                let used_size = used[suit].count_ones() as u64;
+                let this_size = ranks[suit].count_ones() as u64;
+                suit_index[suit] += suit_multiplier[suit] * rank_set_to_index(shifted_ranks);
+                suit_multiplier[suit] *= n_choose_k(self.num_ranks as u64 - used_size, this_size);
+                used[suit] |= ranks[suit];
+                configuration[suit] |= this_size << shift;
+            }
+
+            // Sort suits into their canonical order, ties don't matter since equal suits are
+            // indexed as a multiset
+            let mut pi = (0..num_suits).collect::<Vec<usize>>();
+            pi.sort_by(|a, b| configuration[*b].cmp(&configuration[*a]));
+            let key = pi.iter().map(|s| configuration[*s]).collect::<Vec<u64>>();
+            let configuration_id = self.configurations[round].binary_search(&key).expect("configuration missing from indexer");
+
+            let mut index = self.configuration_to_offset[round][configuration_id];
+            let mut multiplier = 1;
+            let mut i = 0;
+            while i < num_suits {
+                let j = (i..num_suits).find(|j| key[*j] != key[i]).unwrap_or(num_suits);
+                let mut group = pi[i..j].iter().map(|s| suit_index[*s]).collect::<Vec<u64>>();
+                let size = suit_multiplier[pi[i]];
+
+                index += multiplier * multiset_index(&mut group);
+                multiplier *= n_choose_k(size + (j - i) as u64 - 1, (j - i) as u64);
+                i = j;
+            }
+
+            indices.push(index);
+        }
+
+        indices
+    }
+
+    /// Returns the index of the hand at `round`, `cards` must hold at least `num_cards(round)` cards
+    pub fn index_round(&self, round: usize, cards: &[Card]) -> HandIndex {
+        assert!(cards.len() >= self.num_cards(round), "not enough cards to index round {}", round);
+        self.index_all(&cards[..self.num_cards(round)])[round]
+    }
+
+    /// Returns the canonical hand of `round` with the given index
+    pub fn unindex(&self, round: usize, index: HandIndex) -> Option<Vec<Card>> {
+        if round >= self.num_rounds() || index >= self.round_size[round] {
+            return None;
+        }
+
+        let offsets = &self.configuration_to_offset[round];
+        let configuration_id = offsets.partition_point(|o| *o <= index) - 1;
+        let configuration = &self.configurations[round][configuration_id];
+        let suit_sizes = &self.configuration_to_suit_size[round][configuration_id];
+        let mut index = index - offsets[configuration_id];
+
+        let num_suits = self.num_suits as usize;
+        let mut suit_index = vec![0u64; num_suits];
+        let mut i = 0;
+        while i < num_suits {
+            let j = (i..num_suits).find(|j| configuration[*j] != configuration[i]).unwrap_or(num_suits);
+            let group_size = n_choose_k(suit_sizes[i] + (j - i) as u64 - 1, (j - i) as u64);
+            let mut group_index = index % group_size;
+            index /= group_size;
+
+            // Peel off the largest element of the multiset first
+            for (k, s) in (i..j).rev().enumerate() {
+                let k = (j - i - k) as u64;
+                let mut a = 0;
+                while n_choose_k(a + 1 + k - 1, k) <= group_index {
+                    a += 1;
+                }
+                suit_index[s] = a;
+                group_index -= n_choose_k(a + k - 1, k);
+            }
+            i = j;
+        }
+
+        let deck = self.deck_index();
+        let mut cards = vec![None; self.num_cards(round)];
+        let mut location = self.round_start.clone();
+        for (suit, configuration) in configuration.iter().enumerate() {
+            let mut used = 0;
+            let mut m = 0;
+            for r in 0..=round {
+                let n = self.cards_in_suit_round(*configuration, r);
+                let round_size = n_choose_k(self.num_ranks as u64 - m, n);
+                m += n;
+                let round_index = suit_index[suit] % round_size;
+                suit_index[suit] /= round_size;
+
+                let mut shifted_ranks = index_to_rank_set(n as u32, round_index);
+                let mut rank_set = 0;
+                while shifted_ranks != 0 {
+                    let rank = nth_unset(used, shifted_ranks.trailing_zeros());
+                    shifted_ranks &= shifted_ranks - 1;
+                    rank_set |= 1 << rank;
+                    cards[location[r] as usize] = Some(deck.card_at(rank as u8, suit as u8));
+                    location[r] += 1;
+                }
+                used |= rank_set;
+            }
+        }
+
+        cards.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    fn holdem() -> HandIndexer {
+        HandIndexer::new(4, 13, &[2, 3, 1, 1])
+    }
+
+    #[test]
+    fn holdem_round_sizes() {
+        let indexer = holdem();
+        let sizes = (0..indexer.num_rounds()).map(|r| indexer.round_size(r)).collect::<Vec<HandIndex>>();
+        assert_eq!(sizes, [169, 1_286_792, 55_190_538, 2_428_287_420]);
+    }
+
+    #[test]
+    fn holdem_preflop_covers_every_index() {
+        let indexer = holdem();
+        let deck = DeckIndex::new(4, 13).cards().collect::<Vec<Card>>();
+        let mut indices = BTreeSet::new();
+        for (i, a) in deck.iter().enumerate() {
+            for b in &deck[i + 1..] {
+                let index = indexer.index_round(0, &[*a, *b]);
+                assert_eq!(index, indexer.index_round(0, &[*b, *a]));
+                indices.insert(index);
+            }
+        }
+        assert_eq!(indices, (0..169).collect());
+    }
+
+    #[test]
+    fn holdem_flop_round_trips() {
+        let indexer = holdem();
+        // Every 101st flop keeps the test fast while touching every suit configuration
+        for index in (0..indexer.round_size(1)).step_by(101).chain([indexer.round_size(1) - 1]) {
+            let cards = indexer.unindex(1, index).unwrap();
+            assert_eq!(cards.len(), 5);
+            assert_eq!(indexer.index_all(&cards), [indexer.index_round(0, &cards), index]);
+        }
+        assert_eq!(indexer.unindex(1, indexer.round_size(1)), None);
+    }
+
+    #[test]
+    fn leduc_round_trips() {
+        let indexer = HandIndexer::new(2, 3, &[1, 1]);
+        let deck = DeckIndex::new(2, 3).cards().collect::<Vec<Card>>();
+        for round in 0..indexer.num_rounds() {
+            for index in 0..indexer.round_size(round) {
+                let cards = indexer.unindex(round, index).unwrap();
+                assert_eq!(indexer.index_round(round, &cards), index);
+            }
+        }
+
+        // Every deal lands on an index and every index is reached
+        let mut indices = BTreeSet::new();
+        for hole in &deck {
+            for board in deck.iter().filter(|c| *c != hole) {
+                indices.insert(indexer.index_all(&[*hole, *board])[1]);
+            }
+        }
+        assert_eq!(indices, (0..indexer.round_size(1)).collect());
+        assert_eq!(indexer.round_size(0), 3);
+    }
+}
@@ -0,0 +1,764 @@
+use super::{
+    card_set::{format_cards, parse_card, parse_cards},
+    dealer::HandRecord,
+    evaluator::HandRanking,
+    game::{Action, BettingType, GameInfo, GameState, PlayerId},
+};
+
+use poker::Card;
+use itertools::Itertools;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use std::cmp::min;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// A completed hand with everything needed to write it as a hand history or replay it
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HandHistory {
+    pub hand_id: u32,
+    /// Game the hand was played in, with the stacks players started the hand with
+    pub game_info: GameInfo,
+    /// Name of the player in each seat
+    pub players: Vec<String>,
+    /// Cards dealt to each player before any discards, stud up cards included
+    #[serde(serialize_with = "serialize_hands", deserialize_with = "deserialize_hands")]
+    pub hole_cards: Vec<Vec<Card>>,
+    /// Cards each player holds at the end of the hand after discards
+    #[serde(serialize_with = "serialize_hands", deserialize_with = "deserialize_hands")]
+    pub final_hole_cards: Vec<Vec<Card>>,
+    /// Board cards dealt during the hand, without the stub of draw games
+    #[serde(serialize_with = "serialize_cards", deserialize_with = "deserialize_cards")]
+    pub board_cards: Vec<Card>,
+    /// Boards of every additional run-out
+    #[serde(serialize_with = "serialize_hands", deserialize_with = "deserialize_hands")]
+    pub run_outs: Vec<Vec<Card>>,
+    /// Every action in order along with its round and acting player
+    pub actions: Vec<(u8, PlayerId, Action)>,
+    pub payouts: Vec<i32>,
+    pub rake: u32,
+}
+
+impl HandHistory {
+    /// Records a hand dealt by the dealer in `game_info`, `players` names the player of each seat
+    pub fn from_record(game_info: &GameInfo, record: &HandRecord, players: &[String]) -> HandHistory {
+        assert!(players.len() == game_info.num_players() as usize, "need a name for every seat");
+        let state = &record.state;
+        let mut game_info = game_info.clone();
+        game_info.set_starting_stacks((0..game_info.num_players()).map(|p| state.player_stack(p)).collect());
+
+        let num_board_cards = game_info.total_board_cards(state.current_round()) as usize;
+        HandHistory {
+            hand_id: record.hand_id,
+            final_hole_cards: state.current_hole_cards(&game_info, &record.hole_cards, &record.board_cards).into_owned(),
+            board_cards: record.board_cards[..num_board_cards].to_vec(),
+            run_outs: record.run_outs.clone(),
+            actions: state.full_history().collect(),
+            payouts: record.payouts.clone(),
+            rake: state.rake(&game_info),
+            hole_cards: record.hole_cards.clone(),
+            players: players.to_vec(),
+            game_info,
+        }
+    }
+
+    /// Reads a hand from an ACPC log line like `STATE:0:r200c/cr400f:9s8h|KdQs/2c3d4h:-200|200:Alice|Bob`,
+    /// the seats and stacks of `game_info` are the positions of the hand
+    pub fn from_acpc(game_info: &GameInfo, line: &str) -> Result<HandHistory, &'static str> {
+        let fields = line.trim().split(':').collect::<Vec<&str>>();
+        if fields.len() != 6 || fields[0] != "STATE" {
+            return Err("ACPC log lines have the form STATE:hand:betting:cards:payouts:players");
+        }
+        let hand_id = fields[1].parse().map_err(|_| "invalid hand number in ACPC log line")?;
+        let state = GameState::from_betting_string(game_info, hand_id, fields[2])?;
+        if !state.is_finished() {
+            return Err("ACPC log line ends before the hand is over");
+        }
+
+        let mut cards = fields[3].split('/');
+        let hole_cards = cards.next()
+            .unwrap_or("")
+            .split('|')
+            .map(parse_acpc_cards)
+            .collect::<Result<Vec<Vec<Card>>, &'static str>>()?;
+        let board_cards = cards.map(parse_acpc_cards).collect::<Result<Vec<Vec<Card>>, &'static str>>()?.concat();
+        let payouts = fields[4].split('|')
+            .map(|p| p.parse::<f64>().map(|p| p.round() as i32).map_err(|_| "invalid payout in ACPC log line"))
+            .collect::<Result<Vec<i32>, &'static str>>()?;
+        let players = fields[5].split('|').map(String::from).collect::<Vec<String>>();
+        let num_players = game_info.num_players() as usize;
+        if hole_cards.len() != num_players || payouts.len() != num_players || players.len() != num_players {
+            return Err("ACPC log line doesn't have every player of the game");
+        }
+
+        Ok(HandHistory {
+            hand_id,
+            game_info: game_info.clone(),
+            players,
+            final_hole_cards: hole_cards.clone(),
+            hole_cards,
+            board_cards,
+            run_outs: Vec::new(),
+            actions: state.full_history().collect(),
+            payouts,
+            rake: 0,
+        })
+    }
+
+    /// Reads a hold'em or omaha hand from PokerStars hand history text, such as the text written
+    /// by `to_pokerstars`. Seat 0 is the first seat after the button, cash game amounts are in
+    /// cents and the hole cards of players who never showed them are empty.
+    pub fn from_pokerstars(text: &str) -> Result<HandHistory, &'static str> {
+        let mut lines = text.lines()
+            .map(|l| l.trim_start_matches('\u{feff}').trim())
+            .filter(|l| !l.is_empty());
+
+        let header = lines.next().ok_or("empty hand history")?;
+        let hand_id = header.strip_prefix("PokerStars")
+            .and_then(|h| h.split_once('#'))
+            .and_then(|(_, h)| h.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(|id| id.parse::<u64>().ok())
+            // PokerStars hand numbers don't fit in a u32, only the low bits are kept
+            .map(|id| id as u32)
+            .ok_or("hand history doesn't start with a PokerStars hand number")?;
+        let (hand_ranking, num_hole_cards) = if header.contains("6+ Hold'em") {
+            (HandRanking::ShortDeck, 2)
+        } else if header.contains("Hold'em") {
+            (HandRanking::Standard, 2)
+        } else if header.contains("5 Card Omaha") {
+            (HandRanking::Omaha, 5)
+        } else if header.contains("Omaha") {
+            (HandRanking::Omaha, 4)
+        } else {
+            return Err("only hold'em and omaha hand histories can be imported");
+        };
+        let betting_type = if header.contains("Pot Limit") {
+            return Err("pot limit hand histories can't be imported");
+        } else if header.contains("Spread Limit") {
+            return Err("spread limit hand histories can't be imported");
+        } else if header.contains("No Limit") {
+            BettingType::NoLimit
+        } else if header.contains("Limit") {
+            BettingType::Limit
+        } else {
+            return Err("hand history header has no betting type");
+        };
+        let scale = if header.contains(['$', '€', '£']) { 100 } else { 1 };
+        // Limit games list their small and big bet, the first parenthesized pair of amounts
+        let stakes = header.split('(')
+            .skip(1)
+            .filter_map(|s| s.split(')').next())
+            .find(|s| s.contains('/'))
+            .and_then(|s| s.split_whitespace().next())
+            .and_then(|s| s.split_once('/'))
+            .map(|(small, big)| Ok::<(u32, u32), &'static str>((parse_chips(small, scale)?, parse_chips(big, scale)?)))
+            .transpose()?;
+
+        let mut button_seat = None;
+        // Table seat, name and stack of every player dealt in
+        let mut seats: Vec<(u32, String, u32)> = Vec::new();
+        let mut blinds = Vec::new();
+        let mut straddles = Vec::new();
+        let mut dead_blinds = Vec::new();
+        // Players posting both blinds at once, only the big blind part is live
+        let mut both_blinds = Vec::new();
+        let mut raw_actions: Vec<(u8, usize, StarsAction)> = Vec::new();
+        let mut hole_cards = Vec::new();
+        let mut collected = Vec::new();
+        let mut returned = Vec::new();
+        let mut board_cards = Vec::new();
+        let mut run_outs = Vec::new();
+        let mut rake = 0;
+        let mut street: Option<u8> = None;
+        let mut summary = false;
+
+        for line in lines {
+            if let Some((name, _)) = line.strip_prefix("*** ").and_then(|l| l.split_once(" ***")) {
+                if name.contains("SUMMARY") {
+                    summary = true;
+                } else if name.contains("HOLE CARDS") {
+                    street = Some(0);
+                } else if name.contains("FLOP") {
+                    street = Some(1);
+                } else if name.contains("TURN") {
+                    street = Some(2);
+                } else if name.contains("RIVER") {
+                    street = Some(3);
+                }
+                continue;
+            }
+            if street.is_none() && !summary {
+                if line.starts_with("Table ") {
+                    button_seat = line.split_once("Seat #")
+                        .and_then(|(_, s)| s.split_whitespace().next())
+                        .and_then(|s| s.parse::<u32>().ok());
+                } else if let Some((seat, rest)) = line.strip_prefix("Seat ").and_then(|l| l.split_once(": ")) {
+                    if rest.contains("is sitting out") {
+                        continue;
+                    }
+                    let (name, chips) = rest.rsplit_once(" (").ok_or("invalid seat in hand history")?;
+                    let chips = chips.split(" in chips").next().unwrap_or("");
+                    seats.push((seat.parse().map_err(|_| "invalid seat in hand history")?, name.to_string(), parse_chips(chips, scale)?));
+                    blinds.push(0);
+                    straddles.push(0);
+                    dead_blinds.push(0);
+                    hole_cards.push(Vec::new());
+                    collected.push(0);
+                    returned.push(0);
+                } else if let Some((p, post)) = split_player(line, &seats, ": ") {
+                    let post = post.trim_end_matches(" and is all-in");
+                    if let Some(amount) = post.strip_prefix("posts small & big blinds ") {
+                        both_blinds.push((p, parse_chips(amount, scale)?));
+                    } else if let Some(amount) = post.strip_prefix("posts small blind ").or_else(|| post.strip_prefix("posts big blind ")) {
+                        blinds[p] += parse_chips(amount, scale)?;
+                    } else if let Some(amount) = post.strip_prefix("posts straddle ") {
+                        straddles[p] += parse_chips(amount, scale)?;
+                    } else if let Some(amount) = post.strip_prefix("posts the ante ").or_else(|| post.strip_prefix("posts dead blind ")) {
+                        dead_blinds[p] += parse_chips(amount, scale)?;
+                    }
+                }
+                continue;
+            }
+
+            if summary {
+                if let Some(total) = line.strip_prefix("Total pot ") {
+                    if let Some(amount) = total.split(" | ").find_map(|s| s.strip_prefix("Rake ")) {
+                        rake = parse_chips(amount, scale)?;
+                    }
+                } else if line.split_once('[').is_some_and(|(label, _)| label.contains("Board")) {
+                    let cards = parse_bracketed_cards(line)?;
+                    if line.starts_with("Board [") || line.starts_with("FIRST Board") {
+                        board_cards = cards;
+                    } else {
+                        run_outs.push(cards);
+                    }
+                } else if let Some((_, rest)) = line.strip_prefix("Seat ").and_then(|l| l.split_once(": ")) {
+                    if let Some((p, shown)) = split_player(rest, &seats, " ") {
+                        if (shown.contains("showed [") || shown.contains("mucked [")) && hole_cards[p].is_empty() {
+                            hole_cards[p] = parse_bracketed_cards(shown)?;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let street = street.unwrap_or(0);
+            if let Some(dealt) = line.strip_prefix("Dealt to ") {
+                if let Some((p, cards)) = split_player(dealt, &seats, " ") {
+                    hole_cards[p] = parse_bracketed_cards(cards)?;
+                }
+            } else if let Some(uncalled) = line.strip_prefix("Uncalled bet (") {
+                let (amount, rest) = uncalled.split_once(')').ok_or("invalid uncalled bet in hand history")?;
+                let p = rest.strip_prefix(" returned to ")
+                    .and_then(|name| seats.iter().position(|(_, n, _)| n == name))
+                    .ok_or("uncalled bet returned to an unknown player")?;
+                returned[p] += parse_chips(amount, scale)?;
+            } else if let Some((p, won)) = split_player(line, &seats, " collected ") {
+                let amount = won.split(" from ").next().unwrap_or("");
+                collected[p] += parse_chips(amount, scale)?;
+            } else if let Some((p, action)) = split_player(line, &seats, ": ") {
+                let action = action.trim_end_matches(" and is all-in");
+                let action = if action == "folds" {
+                    StarsAction::Fold
+                } else if action == "checks" || action.starts_with("calls ") {
+                    StarsAction::Call
+                } else if let Some(amount) = action.strip_prefix("bets ") {
+                    StarsAction::Raise(parse_chips(amount, scale)?)
+                } else if let Some((_, amount)) = action.strip_prefix("raises ").and_then(|a| a.split_once(" to ")) {
+                    StarsAction::Raise(parse_chips(amount, scale)?)
+                } else if action.starts_with("shows ") {
+                    if hole_cards[p].is_empty() {
+                        hole_cards[p] = parse_bracketed_cards(action)?;
+                    }
+                    continue;
+                } else {
+                    // Chat, mucks and other lines that don't change the hand
+                    continue;
+                };
+                raw_actions.push((street, p, action));
+            }
+        }
+
+        let num_players = seats.len();
+        if num_players < 2 {
+            return Err("hand history needs at least two players");
+        }
+        // Seat 0 is the first player after the button
+        let button_seat = button_seat.ok_or("hand history has no button")?;
+        let button = seats.iter().rposition(|(s, _, _)| *s <= button_seat).unwrap_or(num_players - 1);
+        let seat_of = |p: usize| ((p + num_players - button - 1) % num_players) as PlayerId;
+        let in_seat_order = |values: &[u32]| {
+            let mut ordered = vec![0; num_players];
+            for (p, v) in values.iter().enumerate() {
+                ordered[seat_of(p) as usize] = *v;
+            }
+            ordered
+        };
+
+        let big_blind = blinds.iter().copied().max().unwrap_or(0);
+        for (p, amount) in both_blinds {
+            let live = min(amount, big_blind);
+            blinds[p] += live;
+            dead_blinds[p] += amount - live;
+        }
+        // GameInfo panics on forced bets larger than the stack behind them
+        if (0..num_players).any(|p| blinds[p] + straddles[p] + dead_blinds[p] > seats[p].2) {
+            return Err("hand history has forced bets larger than a player's stack");
+        }
+
+        let mut game_info = GameInfo::flop_game(num_players as PlayerId, betting_type, hand_ranking, num_hole_cards);
+        game_info.set_hi_lo(header.contains("Hi/Lo"));
+        game_info.set_starting_stacks(in_seat_order(&seats.iter().map(|(_, _, s)| *s).collect::<Vec<u32>>()));
+        game_info.set_blinds(in_seat_order(&blinds));
+        if dead_blinds.iter().any(|d| *d > 0) {
+            game_info.set_dead_blinds(in_seat_order(&dead_blinds));
+        }
+        if straddles.iter().any(|s| *s > 0) {
+            game_info.set_straddles(in_seat_order(&straddles));
+        }
+        if betting_type == BettingType::Limit {
+            let (small_bet, big_bet) = stakes.ok_or("limit hand history has no stakes")?;
+            game_info.set_raise_sizes(vec![small_bet, small_bet, big_bet, big_bet]);
+        }
+        // Without any preflop action everyone was all in from the blinds
+        let first_player = raw_actions.first()
+            .filter(|(street, _, _)| *street == 0)
+            .map(|(_, p, _)| seat_of(*p))
+            .unwrap_or_else(|| (game_info.blinds().iter().position_max().unwrap_or(0) as PlayerId + 1) % num_players as PlayerId);
+        game_info.set_first_player(vec![first_player, 0, 0, 0]);
+        if !run_outs.is_empty() {
+            game_info.set_run_outs(run_outs.len() as u8 + 1);
+        }
+
+        let mut state = GameState::new(&game_info, hand_id);
+        // Chips each player had bet when the street started, street amounts are added to it
+        let mut round_start = vec![0; num_players];
+        let mut actions = Vec::with_capacity(raw_actions.len());
+        for (street, p, action) in raw_actions {
+            let player = seat_of(p);
+            if state.is_finished() || state.current_round() != street || state.current_player()? != player {
+                return Err("hand history actions are out of turn");
+            }
+            let action = match action {
+                StarsAction::Fold => Action::Fold,
+                StarsAction::Call => Action::Call,
+                StarsAction::Raise(_) if betting_type == BettingType::Limit => Action::Raise(game_info.raise_size(street)),
+                StarsAction::Raise(to) => Action::Raise(round_start[player as usize] + to),
+            };
+            let new_state = state.apply_action_no_cards(&game_info, action)?;
+            if new_state.current_round() != street {
+                round_start = (0..num_players as PlayerId)
+                    .map(|p| new_state.player_spent(p) - game_info.dead_blind(p))
+                    .collect();
+            }
+            actions.push((street, player, action));
+            state = new_state;
+        }
+        if !state.is_finished() {
+            return Err("hand history ends before the hand is over");
+        }
+        if board_cards.len() < game_info.total_board_cards(state.current_round()) as usize {
+            return Err("hand history board is missing cards");
+        }
+        if hole_cards.iter().any(|h| !h.is_empty() && h.len() != num_hole_cards as usize) {
+            return Err("hand history shows the wrong number of hole cards");
+        }
+
+        let mut players = vec![String::new(); num_players];
+        let mut seat_hole_cards = vec![Vec::new(); num_players];
+        let mut payouts = vec![0; num_players];
+        for (p, (_, name, _)) in seats.into_iter().enumerate() {
+            let seat = seat_of(p);
+            players[seat as usize] = name;
+            seat_hole_cards[seat as usize] = std::mem::take(&mut hole_cards[p]);
+            payouts[seat as usize] = (collected[p] + returned[p]) as i32 - state.player_spent(seat) as i32;
+        }
+
+        Ok(HandHistory {
+            hand_id,
+            game_info,
+            players,
+            final_hole_cards: seat_hole_cards.clone(),
+            hole_cards: seat_hole_cards,
+            board_cards,
+            run_outs,
+            actions,
+            payouts,
+            rake,
+        })
+    }
+
+    /// Replays the hand, returning the state before every action followed by the final state.
+    /// Stud hands need every player's hole cards to deal the up cards.
+    pub fn states(&self) -> Result<Vec<GameState>, &'static str> {
+        let game_info = &self.game_info;
+        let mut state = GameState::new(game_info, self.hand_id);
+        let mut states = Vec::with_capacity(self.actions.len() + 1);
+        for (round, player, action) in self.actions.iter() {
+            if state.is_awaiting_up_cards() {
+                let num_cards = game_info.num_player_cards(state.current_round()) as usize;
+                if self.hole_cards.iter().any(|h| h.len() < num_cards) {
+                    return Err("stud hands can't be replayed without every player's cards");
+                }
+                state = state.deal_up_cards(game_info, game_info.hand_ranking().evaluator(), &self.hole_cards)?;
+            }
+            if state.is_finished() || state.current_round() != *round || state.current_player()? != *player {
+                return Err("hand history actions are out of turn");
+            }
+            let new_state = state.apply_action_no_cards(game_info, *action)?;
+            states.push(state);
+            state = new_state;
+        }
+        states.push(state);
+        Ok(states)
+    }
+
+    /// Returns the seat of the button, the last player to act after the first round
+    pub fn button(&self) -> PlayerId {
+        self.game_info.button()
+    }
+
+    /// Writes the hand as PokerStars style hand history text
+    pub fn to_pokerstars(&self) -> String {
+        let game_info = &self.game_info;
+        let num_players = game_info.num_players();
+        let big_blind = (0..num_players).map(|p| game_info.blinds()[p as usize]).max().unwrap_or(0);
+        let small_blind = (0..num_players).map(|p| game_info.blinds()[p as usize]).filter(|b| *b > 0 && *b < big_blind).min().unwrap_or(big_blind);
+        let name = |p: PlayerId| &self.players[p as usize];
+
+        // Limit games are described by their small and big bet, spread limit games by their
+        // smallest and largest bet and no limit games by their blinds
+        let stakes = match game_info.betting_type() {
+            BettingType::Limit => (game_info.raise_size(0), game_info.raise_size(game_info.num_rounds() - 1)),
+            BettingType::SpreadLimit { min, max } => (min, max),
+            BettingType::NoLimit => (small_blind, big_blind),
+        };
+
+        let mut text = String::new();
+        writeln!(text, "PokerStars Hand #{}: {} ({}/{})", self.hand_id, game_name(game_info), stakes.0, stakes.1).unwrap();
+        writeln!(text, "Table 'ungar' {}-max Seat #{} is the button", num_players, self.button() + 1).unwrap();
+        for p in 0..num_players {
+            writeln!(text, "Seat {}: {} ({} in chips)", p + 1, name(p), game_info.starting_stacks()[p as usize]).unwrap();
+        }
+        for p in 0..num_players {
+            let blind = game_info.blinds()[p as usize];
+            if blind > 0 && game_info.is_stud() {
+                writeln!(text, "{}: posts the ante {}", name(p), blind).unwrap();
+            } else if blind > 0 {
+                let kind = if blind == big_blind { "big" } else { "small" };
+                writeln!(text, "{}: posts {} blind {}", name(p), kind, blind).unwrap();
+            }
+            let straddle = game_info.live_blind(p) - blind;
+            if straddle > 0 {
+                writeln!(text, "{}: posts straddle {}", name(p), straddle).unwrap();
+            }
+            if game_info.dead_blind(p) > 0 {
+                writeln!(text, "{}: posts dead blind {}", name(p), game_info.dead_blind(p)).unwrap();
+            }
+        }
+
+        writeln!(text, "*** {} ***", street_name(game_info, 0)).unwrap();
+        let num_hole_cards = game_info.num_hole_cards() as usize;
+        if !game_info.is_stud() {
+            // Imported hands only know the cards players showed
+            for p in (0..num_players).filter(|p| !self.hole_cards[*p as usize].is_empty()) {
+                writeln!(text, "Dealt to {} [{}]", name(p), format_cards(&self.hole_cards[p as usize][..num_hole_cards])).unwrap();
+            }
+        }
+
+        let mut state = GameState::new(game_info, self.hand_id);
+        // Chips each player had put in when the current round started, stud antes don't count
+        // toward the first bet while blinds do
+        let mut round_start = (0..num_players)
+            .map(|p| if game_info.is_stud() { state.player_spent(p) } else { state.player_spent(p) - game_info.live_blind(p) })
+            .collect::<Vec<u32>>();
+        let mut actions = self.actions.iter();
+        while !state.is_finished() {
+            if state.is_awaiting_up_cards() {
+                let round = state.current_round();
+                state = state.deal_up_cards(game_info, game_info.hand_ranking().evaluator(), &self.hole_cards).unwrap();
+                if round > 0 {
+                    writeln!(text, "*** {} ***", street_name(game_info, round)).unwrap();
+                }
+                for p in (0..num_players).filter(|p| !state.has_folded(*p)) {
+                    let cards = &self.hole_cards[p as usize][..game_info.num_player_cards(round) as usize];
+                    let up_cards = game_info.visible_up_cards(cards, round);
+                    let down_cards = cards.iter().filter(|c| !up_cards.contains(c)).copied().collect::<Vec<Card>>();
+                    writeln!(text, "Dealt to {} [{}] [{}]", name(p), format_cards(&down_cards), format_cards(&up_cards)).unwrap();
+                }
+                if let Some(p) = state.bring_in_player().filter(|_| round == 0) {
+                    writeln!(text, "{}: brings in for {}", name(p), state.player_spent(p) - round_start[p as usize]).unwrap();
+                }
+            }
+
+            let (_, player, action) = match actions.next() {
+                Some(action) => *action,
+                None => break,
+            };
+            let current_bet = (0..num_players).map(|p| state.player_spent(p) - round_start[p as usize]).max().unwrap_or(0);
+            let new_state = state.apply_action_no_cards(game_info, action).expect("hand history has an invalid action");
+
+            let added = new_state.player_spent(player) - state.player_spent(player);
+            let bet = new_state.player_spent(player) - round_start[player as usize];
+            let all_in = if new_state.player_spent(player) == new_state.player_stack(player) && added > 0 { " and is all-in" } else { "" };
+            let description = match action {
+                Action::Fold => "folds".to_string(),
+                Action::Call if added == 0 => "checks".to_string(),
+                Action::Call => format!("calls {}{}", added, all_in),
+                Action::Raise(_) if current_bet == 0 => format!("bets {}{}", bet, all_in),
+                Action::Raise(_) => format!("raises {} to {}{}", bet - current_bet, bet, all_in),
+                Action::Discard(0) => "stands pat".to_string(),
+                Action::Discard(mask) => format!("discards {} cards", mask.count_ones()),
+            };
+            writeln!(text, "{}: {}", name(player), description).unwrap();
+
+            for round in state.current_round() + 1..=new_state.current_round() {
+                for p in 0..num_players {
+                    round_start[p as usize] = new_state.player_spent(p);
+                }
+                if !game_info.is_stud() {
+                    let shown = &self.board_cards[..game_info.total_board_cards(round) as usize];
+                    let new_cards = game_info.num_board_cards(round) as usize;
+                    if new_cards > 0 {
+                        let (old, new) = shown.split_at(shown.len() - new_cards);
+                        if old.is_empty() {
+                            writeln!(text, "*** {} *** [{}]", street_name(game_info, round), format_cards(new)).unwrap();
+                        } else {
+                            writeln!(text, "*** {} *** [{}] [{}]", street_name(game_info, round), format_cards(old), format_cards(new)).unwrap();
+                        }
+                    } else {
+                        writeln!(text, "*** {} ***", street_name(game_info, round)).unwrap();
+                    }
+                }
+            }
+            state = new_state;
+        }
+
+        let live_players = (0..num_players).filter(|p| !state.has_folded(*p)).collect::<Vec<PlayerId>>();
+        if live_players.len() > 1 {
+            writeln!(text, "*** SHOW DOWN ***").unwrap();
+            for p in live_players.iter() {
+                if self.final_hole_cards[*p as usize].is_empty() {
+                    writeln!(text, "{}: mucks hand", name(*p)).unwrap();
+                } else {
+                    writeln!(text, "{}: shows [{}]", name(*p), format_cards(&self.final_hole_cards[*p as usize])).unwrap();
+                }
+            }
+        }
+        let collected = (0..num_players)
+            .map(|p| self.payouts[p as usize] + state.player_spent(p) as i32)
+            .collect::<Vec<i32>>();
+        for p in 0..num_players {
+            if collected[p as usize] > 0 {
+                writeln!(text, "{} collected {} from pot", name(p), collected[p as usize]).unwrap();
+            }
+        }
+
+        writeln!(text, "*** SUMMARY ***").unwrap();
+        writeln!(text, "Total pot {} | Rake {}", state.pot_total(game_info), self.rake).unwrap();
+        if !self.board_cards.is_empty() {
+            writeln!(text, "Board [{}]", format_cards(&self.board_cards)).unwrap();
+        }
+        for (i, run_out) in self.run_outs.iter().enumerate() {
+            writeln!(text, "Board {} [{}]", i + 2, format_cards(run_out)).unwrap();
+        }
+        for p in 0..num_players {
+            writeln!(text, "Seat {}: {} {}", p + 1, name(p), if collected[p as usize] > 0 {
+                format!("won ({})", collected[p as usize])
+            } else if state.has_folded(p) {
+                "folded".to_string()
+            } else {
+                "lost".to_string()
+            }).unwrap();
+        }
+
+        text
+    }
+}
+
+/// Writes `hands` to `path` as PokerStars style hand histories separated by blank lines
+pub fn write_pokerstars(path: &Path, hands: &[HandHistory]) -> io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    for hand in hands {
+        writeln!(f, "{}", hand.to_pokerstars())?;
+    }
+    f.flush()
+}
+
+/// Writes `hands` to `path` as JSON lines, one hand per line
+pub fn write_jsonl(path: &Path, hands: &[HandHistory]) -> io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    for hand in hands {
+        serde_json::to_writer(&mut f, hand)?;
+        writeln!(f)?;
+    }
+    f.flush()
+}
+
+/// Reads every hand of an ACPC log played in `game_info`, lines other than hand states such as
+/// the final score are skipped
+pub fn read_acpc_log(path: &Path, game_info: &GameInfo) -> io::Result<Vec<HandHistory>> {
+    fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with("STATE:"))
+        .map(|(i, line)| HandHistory::from_acpc(game_info, line).map_err(|e| invalid_data(i, e)))
+        .collect()
+}
+
+/// Reads every hand of a PokerStars hand history file, hands are separated by blank lines
+pub fn read_pokerstars(path: &Path) -> io::Result<Vec<HandHistory>> {
+    let text = fs::read_to_string(path)?;
+    let mut hands = Vec::new();
+    let mut hand = String::new();
+    let mut hand_start = 0;
+    for (i, line) in text.lines().chain(std::iter::once("")).enumerate() {
+        if !line.trim().is_empty() {
+            if hand.is_empty() {
+                hand_start = i;
+            }
+            hand.push_str(line);
+            hand.push('\n');
+        } else if !hand.is_empty() {
+            hands.push(HandHistory::from_pokerstars(&hand).map_err(|e| invalid_data(hand_start, e))?);
+            hand.clear();
+        }
+    }
+    Ok(hands)
+}
+
+/// Reads hands written by `write_jsonl`
+pub fn read_jsonl(path: &Path) -> io::Result<Vec<HandHistory>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+/// Returns the PokerStars name of the game
+fn game_name(game_info: &GameInfo) -> String {
+    let num_draws = (0..game_info.num_rounds()).filter(|r| game_info.is_draw_round(*r)).count();
+    let game = match game_info.hand_ranking() {
+        HandRanking::Standard if game_info.is_stud() => "7 Card Stud",
+        HandRanking::Standard if num_draws > 0 => "5 Card Draw",
+        HandRanking::Standard => "Hold'em",
+        HandRanking::ShortDeck => "6+ Hold'em",
+        HandRanking::Omaha => "Omaha",
+        HandRanking::Kuhn => "Kuhn",
+        HandRanking::Leduc => "Leduc",
+        HandRanking::DeuceToSeven if num_draws > 1 => "Triple Draw 2-7 Lowball",
+        HandRanking::DeuceToSeven => "5 Card Draw 2-7 Lowball",
+        HandRanking::AceToFive if game_info.is_stud() => "Razz",
+        HandRanking::AceToFive => "Triple Draw A-5 Lowball",
+    };
+    let hi_lo = if game_info.hi_lo() { " Hi/Lo" } else { "" };
+    let betting = match game_info.betting_type() {
+        BettingType::Limit => "Limit",
+        BettingType::NoLimit => "No Limit",
+        BettingType::SpreadLimit { .. } => "Spread Limit",
+    };
+    format!("{}{} {}", game, hi_lo, betting)
+}
+
+/// Returns the PokerStars name of the street that starts in `round`
+fn street_name(game_info: &GameInfo, round: u8) -> String {
+    if game_info.is_stud() {
+        let num_cards = game_info.num_player_cards(round);
+        return match num_cards {
+            _ if round + 1 == game_info.num_rounds() && round > 0 => "RIVER".to_string(),
+            3 => "3rd STREET".to_string(),
+            n => format!("{}th STREET", n),
+        };
+    }
+    if round == 0 {
+        return "HOLE CARDS".to_string();
+    }
+    if game_info.is_draw_round(round) {
+        let draw = (1..=round).filter(|r| game_info.is_draw_round(*r)).count();
+        return match draw {
+            1 => "FIRST DRAW".to_string(),
+            2 => "SECOND DRAW".to_string(),
+            3 => "THIRD DRAW".to_string(),
+            n => format!("DRAW {}", n),
+        };
+    }
+    match game_info.total_board_cards(round) {
+        3 if game_info.num_board_cards(round) == 3 => "FLOP".to_string(),
+        4 => "TURN".to_string(),
+        5 => "RIVER".to_string(),
+        _ => format!("ROUND {}", round),
+    }
+}
+
+/// An action of a PokerStars hand history
+#[derive(Clone, Copy, Debug)]
+enum StarsAction {
+    Fold,
+    Call,
+    /// Bets and raises, with the total the player has bet this street
+    Raise(u32),
+}
+
+/// Finds the player whose name starts `line` followed by `separator`, returning their index
+/// and the rest of the line. The longest name wins when names are prefixes of each other.
+fn split_player<'a>(line: &'a str, seats: &[(u32, String, u32)], separator: &str) -> Option<(usize, &'a str)> {
+    seats.iter()
+        .enumerate()
+        .filter_map(|(p, (_, name, _))| line.strip_prefix(name.as_str())?.strip_prefix(separator).map(|rest| (p, name.len(), rest)))
+        .max_by_key(|(_, len, _)| *len)
+        .map(|(p, _, rest)| (p, rest))
+}
+
+/// Parses an amount like `1,000` or `$0.50`, cash amounts are multiplied by `scale`
+fn parse_chips(amount: &str, scale: u32) -> Result<u32, &'static str> {
+    let amount = amount.trim().trim_start_matches(['$', '€', '£']).replace(',', "");
+    amount.parse::<f64>()
+        .map(|a| (a * scale as f64).round() as u32)
+        .map_err(|_| "invalid amount in hand history")
+}
+
+/// Parses the cards in the first brackets of `line`, like `[Ah Kd]`
+fn parse_bracketed_cards(line: &str) -> Result<Vec<Card>, &'static str> {
+    line.split_once('[')
+        .and_then(|(_, cards)| cards.split_once(']'))
+        .ok_or("missing cards in hand history")?
+        .0
+        .split_whitespace()
+        .map(|c| parse_card(c).map_err(|_| "invalid card in hand history"))
+        .collect()
+}
+
+/// Parses ACPC cards like `Ah2c`, which have no separators
+fn parse_acpc_cards(cards: &str) -> Result<Vec<Card>, &'static str> {
+    parse_cards(cards).map_err(|_| "invalid card in ACPC log line")
+}
+
+fn invalid_data(line: usize, reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, reason))
+}
+
+
+fn serialize_cards<S: Serializer>(cards: &[Card], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(cards.iter().map(|c| c.rank_suit_string()))
+}
+
+fn deserialize_cards<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Card>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|c| parse_card(c).map_err(de::Error::custom))
+        .collect()
+}
+
+fn serialize_hands<S: Serializer>(hands: &[Vec<Card>], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(hands.iter().map(|h| h.iter().map(|c| c.rank_suit_string()).collect::<Vec<String>>()))
+}
+
+fn deserialize_hands<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<Card>>, D::Error> {
+    Vec::<Vec<String>>::deserialize(deserializer)?
+        .iter()
+        .map(|h| h.iter().map(|c| c.parse().map_err(de::Error::custom)).collect())
+        .collect()
+}
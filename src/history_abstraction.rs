@@ -0,0 +1,141 @@
+use super::{
+    game::{Action, BettingType, GameInfo, GameState, PlayerId},
+    strategy::pot_fraction,
+};
+
+use serde::{Serialize, Deserialize};
+
+/// Betting of deep stack games coarsened for infoset keys, so betting that only differs in raises
+/// of similar sizes reaches the same node when it puts the same chips in. Raises fall into
+/// classes of pot fractions and each round only remembers its latest raises, the rest of its
+/// betting is forgotten but for the number of raises.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HistoryAbstraction {
+    /// Ascending pot fractions separating the raise classes, a raise falls in the class of the
+    /// first bound it doesn't exceed, past the last one in a class of its own. All in raises
+    /// have their own class and no bounds puts every other raise in one class.
+    #[serde(default)]
+    raise_classes: Vec<f64>,
+    /// Raises of a round remembered, earlier raises are only counted and the actions between
+    /// them forgotten
+    #[serde(default)]
+    max_raises_remembered: Option<u8>,
+}
+
+/// Action of a coarsened betting history
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum HistoryToken {
+    Fold,
+    Call,
+    Raise(u8),
+    AllIn,
+    Discard(u8),
+    /// Stands for the betting of the round before the remembered raises, counting the raises
+    /// in it so a key never repeats further down the tree
+    Forgotten(u8),
+}
+
+/// Key every state reaching the same node under a `HistoryAbstraction` shares. Besides the
+/// coarsened betting it holds who is to act, folded and all in and the chips each player put in,
+/// which the nodes have to agree on so every betting reaching a node plays on with its pot.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HistoryKey {
+    round: u8,
+    active_player: Option<PlayerId>,
+    players_folded: Vec<bool>,
+    players_all_in: Vec<bool>,
+    drawing: bool,
+    finished: bool,
+    spent: Vec<u32>,
+    /// Coarsened actions of every round so far
+    betting: Vec<Vec<(PlayerId, HistoryToken)>>,
+}
+
+impl HistoryAbstraction {
+    pub fn new(raise_classes: Vec<f64>, max_raises_remembered: Option<u8>) -> HistoryAbstraction {
+        assert!(raise_classes.windows(2).all(|w| w[0] < w[1]), "raise classes have to be ascending");
+        HistoryAbstraction {
+            raise_classes,
+            max_raises_remembered,
+        }
+    }
+
+    /// Returns the key of the state at the start of a hand
+    pub fn root_key(&self, game_info: &GameInfo, state: &GameState) -> HistoryKey {
+        self.state_key(game_info, state, Vec::new())
+    }
+
+    /// Returns the key of `state` by replaying its betting from the start of the hand, like the
+    /// keys of the nodes training built. Fails for stud games, whose betting can't be replayed
+    /// without the up cards.
+    pub fn key(&self, game_info: &GameInfo, state: &GameState) -> Result<HistoryKey, &'static str> {
+        if game_info.is_stud() {
+            return Err("stud betting can't be replayed without cards");
+        }
+        let mut current = GameState::new(game_info, 0);
+        let mut key = self.root_key(game_info, &current);
+        for (_, _, action) in state.full_history() {
+            let next = current.apply_action_no_cards(game_info, action)?;
+            key = self.child_key(game_info, &key, &current, action, &next);
+            current = next;
+        }
+        Ok(key)
+    }
+
+    /// Returns the key of `child`, reached from the state of `parent_key` by `action`
+    pub fn child_key(&self, game_info: &GameInfo, parent_key: &HistoryKey, parent: &GameState, action: Action, child: &GameState) -> HistoryKey {
+        let mut betting = parent_key.betting.clone();
+        let round = parent.current_round() as usize;
+        betting.resize(betting.len().max(round + 1), Vec::new());
+        let player = parent.current_player().expect("failed to find the player acting");
+        betting[round].push((player, self.token(game_info, parent, action, child.chips_behind(player) == 0)));
+        self.forget(&mut betting[round]);
+        self.state_key(game_info, child, betting)
+    }
+
+    fn state_key(&self, game_info: &GameInfo, state: &GameState, betting: Vec<Vec<(PlayerId, HistoryToken)>>) -> HistoryKey {
+        let players = 0..game_info.num_players();
+        HistoryKey {
+            round: state.current_round(),
+            active_player: state.current_player().ok(),
+            players_folded: players.clone().map(|p| state.has_folded(p)).collect(),
+            players_all_in: players.clone().map(|p| !state.has_folded(p) && state.chips_behind(p) == 0).collect(),
+            drawing: state.is_drawing(),
+            finished: state.is_finished(),
+            spent: players.map(|p| state.player_spent(p)).collect(),
+            betting,
+        }
+    }
+
+    fn token(&self, game_info: &GameInfo, state: &GameState, action: Action, all_in: bool) -> HistoryToken {
+        match action {
+            Action::Fold => HistoryToken::Fold,
+            Action::Call => HistoryToken::Call,
+            Action::Discard(mask) => HistoryToken::Discard(mask),
+            Action::Raise(_) if game_info.betting_type() == BettingType::Limit => HistoryToken::Raise(0),
+            Action::Raise(_) if all_in => HistoryToken::AllIn,
+            Action::Raise(to) => {
+                let fraction = pot_fraction(state, game_info, to);
+                HistoryToken::Raise(self.raise_classes.iter().take_while(|b| fraction > **b).count() as u8)
+            },
+        }
+    }
+
+    /// Drops the oldest raise of a round remembering too many, along with what came before it
+    fn forget(&self, round: &mut Vec<(PlayerId, HistoryToken)>) {
+        let Some(max_raises) = self.max_raises_remembered else {
+            return;
+        };
+        let is_raise = |t: &HistoryToken| matches!(t, HistoryToken::Raise(_) | HistoryToken::AllIn);
+        if round.iter().filter(|(_, t)| is_raise(t)).count() <= max_raises as usize {
+            return;
+        }
+        let forgotten = match round.first() {
+            Some((_, HistoryToken::Forgotten(n))) => n + 1,
+            _ => 1,
+        };
+        let oldest = round.iter().position(|(_, t)| is_raise(t)).expect("failed to find a raise");
+        round.drain(..=oldest);
+        round.insert(0, (0, HistoryToken::Forgotten(forgotten)));
+    }
+}
@@ -0,0 +1,118 @@
+use super::{
+    evaluator::HandEvaluator,
+    game::{GameInfo, GameState, PlayerId},
+};
+
+use poker::Card;
+
+use serde::{Serialize, Deserialize};
+
+/// Largest number of stacks equities can be computed for, the computation is exponential in it
+pub const MAX_ICM_PLAYERS: usize = 20;
+
+/// Computes the share of the prize pool each stack is worth with the Malmuth-Harville model,
+/// where every remaining player finishes in the best remaining place with probability
+/// proportional to their stack. `payouts[i]` is the prize for finishing in place i + 1. Players
+/// without chips finish below everyone else and split the prizes of those places.
+pub fn icm_equities(stacks: &[u32], payouts: &[f64]) -> Vec<f64> {
+    let n = stacks.len();
+    assert!(n <= MAX_ICM_PLAYERS, "too many stacks for icm");
+    let total = stacks.iter().map(|s| *s as f64).sum::<f64>();
+    let mut equities = vec![0.; n];
+    if total <= 0. {
+        return equities;
+    }
+
+    // probabilities[mask] is the probability the players in mask take the best places
+    let mut probabilities = vec![0.; 1 << n];
+    probabilities[0] = 1.;
+    for mask in 0..(1usize << n) {
+        let place = mask.count_ones() as usize;
+        if probabilities[mask] == 0. || place >= payouts.len() {
+            continue;
+        }
+
+        let remaining = total - (0..n).filter(|i| mask & (1 << i) != 0).map(|i| stacks[i] as f64).sum::<f64>();
+        if remaining <= 0. {
+            continue;
+        }
+        for i in (0..n).filter(|i| mask & (1 << i) == 0 && stacks[*i] > 0) {
+            let p = probabilities[mask] * stacks[i] as f64 / remaining;
+            equities[i] += p * payouts[place];
+            probabilities[mask | (1 << i)] += p;
+        }
+    }
+
+    let busted = (0..n).filter(|i| stacks[*i] == 0).collect::<Vec<usize>>();
+    let busted_share = payouts.iter().skip(n - busted.len()).take(busted.len()).sum::<f64>() / busted.len().max(1) as f64;
+    for i in busted {
+        equities[i] = busted_share;
+    }
+
+    equities
+}
+
+/// Values hands of a tournament by the change in tournament equity they cause instead of the
+/// chips won. The stacks of the players at the table are the game's starting stacks.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IcmUtility {
+    /// Prize for each place, starting with first
+    payouts: Vec<f64>,
+    /// Stacks of players at other tables, which share the prize pool but don't play the hand
+    #[serde(default)]
+    other_stacks: Vec<u32>,
+}
+
+impl IcmUtility {
+    pub fn new(payouts: Vec<f64>, other_stacks: Vec<u32>) -> IcmUtility {
+        IcmUtility {
+            payouts,
+            other_stacks,
+        }
+    }
+
+    /// Returns the change in tournament equity of every player at the table from the chip
+    /// payouts of a hand. Equity is converted to chips at the rate of the prize pool to the chips
+    /// in play, so values are on the same scale as chip payouts and a chip leader gains less
+    /// than a chip for every chip won.
+    pub fn chip_equivalents(&self, game_info: &GameInfo, payouts: &[i32]) -> Vec<f64> {
+        let stacks = game_info.starting_stacks();
+        let before = self.equities(stacks);
+        let after_stacks = stacks.iter()
+            .zip(payouts)
+            .map(|(s, p)| (*s as i64 + *p as i64).max(0) as u32)
+            .collect::<Vec<u32>>();
+        let after = self.equities(&after_stacks);
+
+        let total_chips = stacks.iter().chain(self.other_stacks.iter()).map(|s| *s as f64).sum::<f64>();
+        let prize_pool = self.payouts.iter().sum::<f64>();
+        let rate = if prize_pool > 0. { total_chips / prize_pool } else { 0. };
+        before.iter().zip(after).map(|(b, a)| (a - b) * rate).collect()
+    }
+
+    /// Returns the chip equivalent of `player` at the end of `state`. If the player folded
+    /// before the hand finished, the pot is assumed to be split evenly between the players
+    /// still in it.
+    pub fn state_value<E: HandEvaluator + ?Sized>(&self, game_info: &GameInfo, evaluator: &E, state: &GameState, board_cards: &[Card], hole_cards: &[Vec<Card>], player: PlayerId) -> f64 {
+        let payouts = if state.is_finished() {
+            state.get_payouts(game_info, evaluator, board_cards, hole_cards)
+        } else {
+            let num_players = game_info.num_players();
+            let live = (0..num_players).filter(|p| !state.has_folded(*p)).collect::<Vec<PlayerId>>();
+            let share = state.pot_total(game_info) as f64 / live.len() as f64;
+            (0..num_players)
+                .map(|p| if live.contains(&p) { share.round() as i32 } else { 0 } - state.player_spent(p) as i32)
+                .collect()
+        };
+
+        self.chip_equivalents(game_info, &payouts)[player as usize]
+    }
+
+    /// Returns the equities of the players at the table with `stacks`
+    fn equities(&self, stacks: &[u32]) -> Vec<f64> {
+        let all_stacks = stacks.iter().chain(self.other_stacks.iter()).copied().collect::<Vec<u32>>();
+        let mut equities = icm_equities(&all_stacks, &self.payouts);
+        equities.truncate(stacks.len());
+        equities
+    }
+}
@@ -0,0 +1,181 @@
+use super::{
+    abstract_game::AbstractGame,
+    card_abstraction::BucketId,
+    game::{Action, PlayerId},
+    node::NodeId,
+    strategy::Strategy,
+};
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Header of the CSV `write_csv` writes, one row per action of an infoset
+pub const CSV_HEADER: &str = "node_id,bucket_id,round,player,betting,visits,action,regret,current_probability,average_probability,average_count";
+
+/// What a trainer keeps about one infoset, for analysing convergence and debugging infosets
+/// outside the solver
+#[derive(Clone, Debug)]
+pub struct InfosetTable {
+    pub node_id: NodeId,
+    pub bucket_id: BucketId,
+    pub round: u8,
+    pub player: PlayerId,
+    /// Betting up to the node, see `GameState::betting_string`
+    pub betting: String,
+    /// Times a traversal updated the regrets of the infoset
+    pub visits: u64,
+    /// Actions of the node, every other field is in their order
+    pub actions: Vec<Action>,
+    /// Cumulative regret of each action in payouts times `payout_amp`, zero if never updated
+    pub regrets: Vec<f64>,
+    /// Probabilities regret matching plays the actions with
+    pub current_strategy: Vec<f64>,
+    /// Probabilities of the average strategy, uniform if it never reached the infoset
+    pub average_strategy: Vec<f64>,
+    /// Counts the average strategy is accumulated in
+    pub average_counts: Vec<i32>,
+}
+
+impl InfosetTable {
+    /// Gathers the tables of the infoset, `regrets` are in the order of the actions of its node
+    pub fn new(abstract_game: &AbstractGame, infoset: (NodeId, BucketId), visits: u64, regrets: Option<Vec<f64>>, average_strategy: &Strategy) -> InfosetTable {
+        let (node_id, bucket_id) = infoset;
+        let state = &abstract_game.nodes.get_node(node_id).expect("infoset of a missing node").state;
+        let actions = abstract_game.get_actions(state);
+        let regrets = regrets.unwrap_or_else(|| vec![0.; actions.len()]);
+        let counts = average_strategy.0.get(&infoset);
+
+        InfosetTable {
+            node_id,
+            bucket_id,
+            round: state.current_round(),
+            player: state.current_player().unwrap_or(0),
+            betting: state.betting_string(&abstract_game.game_info),
+            visits,
+            current_strategy: regret_matching(&regrets),
+            average_strategy: average_strategy.action_probabilities(node_id, bucket_id, &actions).into_values().collect(),
+            average_counts: actions.iter().map(|a| counts.and_then(|c| c.get(a)).copied().unwrap_or(0)).collect(),
+            regrets,
+            actions,
+        }
+    }
+
+    /// Writes the rows of the infoset in the format of `CSV_HEADER`
+    pub fn write_csv_rows(&self, csv: &mut String) {
+        for (i, action) in self.actions.iter().enumerate() {
+            writeln!(csv, "{},{},{},{},{},{},{},{},{:.6},{:.6},{}",
+                self.node_id, self.bucket_id, self.round, self.player, self.betting, self.visits,
+                action, self.regrets[i], self.current_strategy[i], self.average_strategy[i], self.average_counts[i]).unwrap();
+        }
+    }
+}
+
+/// Probabilities proportional to the positive regrets, uniform if none is positive
+fn regret_matching(regrets: &[f64]) -> Vec<f64> {
+    let total: f64 = regrets.iter().map(|r| r.max(0.)).sum();
+    if total > 0. {
+        regrets.iter().map(|r| r.max(0.) / total).collect()
+    } else {
+        vec![1. / regrets.len() as f64; regrets.len()]
+    }
+}
+
+/// Writes the tables as CSV with a row per action, see `CSV_HEADER`
+pub fn write_csv(path: &Path, tables: impl Iterator<Item = InfosetTable>) {
+    let mut w = BufWriter::new(File::create(path).expect("failed to create infoset table file"));
+    writeln!(w, "{}", CSV_HEADER).expect("failed to write infoset tables");
+    let mut csv = String::new();
+    for table in tables {
+        csv.clear();
+        table.write_csv_rows(&mut csv);
+        w.write_all(csv.as_bytes()).expect("failed to write infoset tables");
+    }
+    w.flush().expect("failed to write infoset tables");
+}
+
+/// Writes the tables as Parquet with the columns of `CSV_HEADER`, a row group every
+/// `PARQUET_BATCH_ROWS` rows
+#[cfg(feature = "parquet")]
+pub fn write_parquet(path: &Path, tables: impl Iterator<Item = InfosetTable>) {
+    use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, StringArray, UInt32Array, UInt64Array, UInt8Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    const PARQUET_BATCH_ROWS: usize = 1 << 16;
+
+    #[derive(Default)]
+    struct Columns {
+        node_id: Vec<u64>,
+        bucket_id: Vec<u32>,
+        round: Vec<u8>,
+        player: Vec<u8>,
+        betting: Vec<String>,
+        visits: Vec<u64>,
+        action: Vec<String>,
+        regret: Vec<f64>,
+        current_probability: Vec<f64>,
+        average_probability: Vec<f64>,
+        average_count: Vec<i32>,
+    }
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("node_id", DataType::UInt64, false),
+        Field::new("bucket_id", DataType::UInt32, false),
+        Field::new("round", DataType::UInt8, false),
+        Field::new("player", DataType::UInt8, false),
+        Field::new("betting", DataType::Utf8, false),
+        Field::new("visits", DataType::UInt64, false),
+        Field::new("action", DataType::Utf8, false),
+        Field::new("regret", DataType::Float64, false),
+        Field::new("current_probability", DataType::Float64, false),
+        Field::new("average_probability", DataType::Float64, false),
+        Field::new("average_count", DataType::Int32, false),
+    ]));
+    let file = File::create(path).expect("failed to create infoset table file");
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), None).expect("failed to create parquet writer");
+
+    let mut flush = |c: Columns| {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(c.node_id)),
+            Arc::new(UInt32Array::from(c.bucket_id)),
+            Arc::new(UInt8Array::from(c.round)),
+            Arc::new(UInt8Array::from(c.player)),
+            Arc::new(StringArray::from(c.betting)),
+            Arc::new(UInt64Array::from(c.visits)),
+            Arc::new(StringArray::from(c.action)),
+            Arc::new(Float64Array::from(c.regret)),
+            Arc::new(Float64Array::from(c.current_probability)),
+            Arc::new(Float64Array::from(c.average_probability)),
+            Arc::new(Int32Array::from(c.average_count)),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).expect("failed to build infoset table batch");
+        writer.write(&batch).expect("failed to write infoset tables");
+    };
+
+    let mut columns = Columns::default();
+    for table in tables {
+        for (i, action) in table.actions.iter().enumerate() {
+            columns.node_id.push(table.node_id as u64);
+            columns.bucket_id.push(table.bucket_id);
+            columns.round.push(table.round);
+            columns.player.push(table.player);
+            columns.betting.push(table.betting.clone());
+            columns.visits.push(table.visits);
+            columns.action.push(action.to_string());
+            columns.regret.push(table.regrets[i]);
+            columns.current_probability.push(table.current_strategy[i]);
+            columns.average_probability.push(table.average_strategy[i]);
+            columns.average_count.push(table.average_counts[i]);
+        }
+        if columns.node_id.len() >= PARQUET_BATCH_ROWS {
+            flush(std::mem::take(&mut columns));
+        }
+    }
+    if !columns.node_id.is_empty() {
+        flush(columns);
+    }
+    writer.close().expect("failed to write infoset tables");
+}
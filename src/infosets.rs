@@ -0,0 +1,229 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    card_abstraction::{BucketId, CardAbstraction},
+    game::{GameInfo, GameState, PlayerId},
+    node::{Node, NodeId},
+};
+
+use rand::prelude::*;
+
+use std::fmt;
+use std::ops::Range;
+
+/// Betting of a decision node along with a bucket of the cards of the player to act, what a
+/// strategy plays one distribution over actions for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Infoset {
+    pub node_id: NodeId,
+    pub bucket_id: BucketId,
+    pub round: u8,
+    pub player: PlayerId,
+    pub num_actions: usize,
+}
+
+/// Returns the round, player and number of actions of a node somebody acts at
+fn decision(abstract_game: &AbstractGame, node: &Node) -> Option<(u8, PlayerId, usize)> {
+    if node.state.is_finished() {
+        return None;
+    }
+    let player = node.state.current_player().ok()?;
+    let num_actions = abstract_game.get_actions(&node.state).len();
+    (num_actions > 0).then_some((node.state.current_round(), player, num_actions))
+}
+
+/// Iterates over every infoset of the nodes added so far, by node and then bucket. Training adds
+/// nodes as it reaches them, `AbstractGame::expand_tree` adds every node up front. Sparse card
+/// abstractions give every bucket of their key space, most of which no deal reaches, see
+/// `CardAbstraction::is_dense`.
+pub fn infosets(abstract_game: &AbstractGame) -> impl Iterator<Item = Infoset> + '_ {
+    abstract_game.nodes.iter()
+        .filter_map(|(node_id, node)| decision(abstract_game, node).map(|d| (node_id, d)))
+        .flat_map(|(node_id, (round, player, num_actions))| {
+            (0..abstract_game.card_abstraction.num_buckets(round)).map(move |bucket_id| Infoset { node_id, bucket_id: bucket_id as BucketId, round, player, num_actions })
+        })
+}
+
+/// Size of the tree of an abstract game, counted over its nodes or estimated without building
+/// them
+#[derive(Clone, Debug, Default)]
+pub struct InfosetCount {
+    pub num_nodes: u64,
+    pub num_decision_nodes: u64,
+    pub infosets_per_round: Vec<u64>,
+    pub num_infosets: u64,
+    /// Actions over every infoset, the entries of a regret or strategy table
+    pub num_actions: u64,
+    /// Whether the counts were estimated from random paths instead of counted
+    pub estimated: bool,
+}
+
+impl InfosetCount {
+    /// Returns the bytes of a table holding `bytes_per_action` for every action of every
+    /// infoset, like regrets and average strategy counts
+    pub fn table_bytes(&self, bytes_per_action: u64) -> u64 {
+        self.num_actions.saturating_mul(bytes_per_action)
+    }
+}
+
+impl fmt::Display for InfosetCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let estimated = if self.estimated { "about " } else { "" };
+        writeln!(f, "{}{} nodes, {} of them decisions", estimated, self.num_nodes, self.num_decision_nodes)?;
+        for (round, num_infosets) in self.infosets_per_round.iter().enumerate() {
+            writeln!(f, "round {}: {}{} infosets", round, estimated, num_infosets)?;
+        }
+        write!(f, "{}{} infosets with {} actions, {} MiB of regrets at 4 bytes an action", estimated, self.num_infosets, self.num_actions, self.table_bytes(4) >> 20)
+    }
+}
+
+/// Counts the infosets of the nodes added so far, see `infosets`
+pub fn count_infosets(abstract_game: &AbstractGame) -> InfosetCount {
+    let mut count = InfosetCount {
+        infosets_per_round: vec![0; abstract_game.game_info.num_rounds() as usize],
+        ..InfosetCount::default()
+    };
+    for (_, node) in abstract_game.nodes.iter() {
+        count.num_nodes += 1;
+        if let Some((round, _, num_actions)) = decision(abstract_game, node) {
+            let num_buckets = abstract_game.card_abstraction.num_buckets(round);
+            count.num_decision_nodes += 1;
+            count.infosets_per_round[round as usize] = count.infosets_per_round[round as usize].saturating_add(num_buckets);
+            count.num_actions = count.num_actions.saturating_add(num_buckets.saturating_mul(num_actions as u64));
+        }
+    }
+    count.num_infosets = count.infosets_per_round.iter().fold(0, |a, b| a.saturating_add(*b));
+    count
+}
+
+/// Estimates the size of the tree the action abstraction builds from `state` without building
+/// it, for games too big to expand. Every probe walks a path taking uniformly random actions and
+/// counts every node on it weighted by the product of the number of actions above it, an
+/// unbiased estimate of the tree size (Knuth, 1975) that grows more accurate with more probes.
+/// Merged transpositions and history abstractions aren't taken into account, so it estimates the
+/// tree without merging.
+pub fn estimate_infosets<R: Rng + ?Sized>(game_info: &GameInfo, state: &GameState, action_abstraction: &ActionAbstraction, card_abstraction: &CardAbstraction, num_probes: u32, rng: &mut R) -> InfosetCount {
+    assert!(num_probes > 0, "need at least one probe");
+    let num_rounds = game_info.num_rounds() as usize;
+    let mut num_nodes = 0.;
+    let mut num_decision_nodes = 0.;
+    let mut infosets_per_round = vec![0.; num_rounds];
+    let mut num_actions = 0.;
+
+    for _ in 0..num_probes {
+        let mut state = state.clone();
+        let mut weight = 1.;
+        loop {
+            num_nodes += weight;
+            if state.is_finished() || state.current_player().is_err() {
+                break;
+            }
+            let actions = action_abstraction.get_actions(game_info, &state);
+            let Some(action) = actions.choose(rng) else {
+                break;
+            };
+
+            let num_buckets = card_abstraction.num_buckets(state.current_round()) as f64;
+            num_decision_nodes += weight;
+            infosets_per_round[state.current_round() as usize] += weight * num_buckets;
+            num_actions += weight * num_buckets * actions.len() as f64;
+
+            weight *= actions.len() as f64;
+            state = state.apply_action_no_cards(game_info, *action).expect("failed to apply abstract action");
+        }
+    }
+
+    let mean = |total: f64| (total / num_probes as f64).round() as u64;
+    let infosets_per_round = infosets_per_round.into_iter().map(mean).collect::<Vec<u64>>();
+    InfosetCount {
+        num_nodes: mean(num_nodes),
+        num_decision_nodes: mean(num_decision_nodes),
+        num_infosets: infosets_per_round.iter().sum(),
+        infosets_per_round,
+        num_actions: mean(num_actions),
+        estimated: true,
+    }
+}
+
+/// Where the infosets and actions of a node start in the flat numbering of an `InfosetIndex`
+#[derive(Clone, Copy, Debug)]
+struct NodeOffsets {
+    infoset: u64,
+    action: u64,
+    num_buckets: u64,
+    num_actions: u64,
+}
+
+/// Numbers the infosets of the nodes added so far consecutively, by node and then bucket, and
+/// the actions of every infoset consecutively in the order of `AbstractGame::get_actions`, so
+/// values per infoset or per action can be kept in flat arrays instead of maps. Nodes added
+/// afterwards aren't numbered.
+#[derive(Clone, Debug)]
+pub struct InfosetIndex {
+    /// Offsets of every node, `None` for nodes nobody acts at
+    nodes: Vec<Option<NodeOffsets>>,
+    num_infosets: u64,
+    num_actions: u64,
+}
+
+impl InfosetIndex {
+    /// Numbers the infosets of `abstract_game`, failing if there are more than a `u64` can
+    /// count, which only sparse card abstractions of big games get to
+    pub fn new(abstract_game: &AbstractGame) -> Result<InfosetIndex, &'static str> {
+        let mut nodes = Vec::with_capacity(abstract_game.nodes.len());
+        let mut num_infosets = 0u64;
+        let mut num_actions = 0u64;
+        for (_, node) in abstract_game.nodes.iter() {
+            let offsets = match decision(abstract_game, node) {
+                Some((round, _, actions)) => {
+                    let num_buckets = abstract_game.card_abstraction.num_buckets(round);
+                    let offsets = NodeOffsets { infoset: num_infosets, action: num_actions, num_buckets, num_actions: actions as u64 };
+                    num_infosets = num_infosets.checked_add(num_buckets).ok_or("too many infosets to number")?;
+                    num_actions = num_buckets.checked_mul(actions as u64).and_then(|n| num_actions.checked_add(n)).ok_or("too many actions to number")?;
+                    Some(offsets)
+                },
+                None => None,
+            };
+            nodes.push(offsets);
+        }
+
+        Ok(InfosetIndex { nodes, num_infosets, num_actions })
+    }
+
+    /// Returns the number of the infoset, `None` if nobody acts at the node or it was added after
+    /// the index was made
+    pub fn infoset(&self, node_id: NodeId, bucket_id: BucketId) -> Option<u64> {
+        let offsets = self.nodes.get(node_id).copied().flatten()?;
+        ((bucket_id as u64) < offsets.num_buckets).then_some(offsets.infoset + bucket_id as u64)
+    }
+
+    /// Returns the number of the action at `action_index` of the infoset
+    pub fn action(&self, node_id: NodeId, bucket_id: BucketId, action_index: usize) -> Option<u64> {
+        let offsets = self.nodes.get(node_id).copied().flatten()?;
+        ((bucket_id as u64) < offsets.num_buckets && (action_index as u64) < offsets.num_actions)
+            .then_some(offsets.action + bucket_id as u64 * offsets.num_actions + action_index as u64)
+    }
+
+    /// Returns the numbers of the actions of the infoset, which follow each other
+    pub fn actions(&self, node_id: NodeId, bucket_id: BucketId) -> Option<Range<u64>> {
+        let offsets = self.nodes.get(node_id).copied().flatten()?;
+        let start = self.action(node_id, bucket_id, 0)?;
+        Some(start..start + offsets.num_actions)
+    }
+
+    /// Iterates over the numbered infosets in the order of their numbers
+    pub fn infosets(&self) -> impl Iterator<Item = (NodeId, BucketId)> + '_ {
+        self.nodes.iter()
+            .enumerate()
+            .filter_map(|(node_id, offsets)| offsets.map(|o| (node_id, o.num_buckets)))
+            .flat_map(|(node_id, num_buckets)| (0..num_buckets).map(move |bucket_id| (node_id, bucket_id as BucketId)))
+    }
+
+    pub fn num_infosets(&self) -> u64 {
+        self.num_infosets
+    }
+
+    pub fn num_actions(&self) -> u64 {
+        self.num_actions
+    }
+}
@@ -0,0 +1,125 @@
+use super::{
+    action_abstraction::ActionAbstraction,
+    game::{Action, GameInfo, GameState},
+    strategy::StrategyProfile,
+};
+
+use poker::Card;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// Estimates the value of a state where a depth limited search stops expanding the tree
+pub trait LeafEvaluator {
+    /// Returns the estimated payout of every player from `state`. `board_cards` holds the
+    /// board cards dealt so far, followed by the stub in draw games, later cards are ignored.
+    fn evaluate(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> Vec<f64>;
+
+    /// Seeds whatever randomness the estimates use, evaluators without any ignore it
+    fn set_seed(&mut self, _seed: u64) {}
+}
+
+/// Strategy everyone plays during a rollout
+pub enum RolloutPolicy {
+    /// Samples the actions of a trained strategy, situations it never reached are played
+    /// uniformly
+    Blueprint(Box<StrategyProfile>),
+    /// Picks uniformly between the actions of an action abstraction
+    Uniform(ActionAbstraction),
+    /// Checks or calls every bet and stands pat, so the rollouts average the showdown equity of
+    /// the hands with the pot as it is
+    CheckDown,
+}
+
+/// Estimates leaf values by playing the hand out from the leaf `num_rollouts` times with every
+/// player following the same policy and averaging the payouts. Board cards of later rounds are
+/// dealt again for every rollout from the cards nobody holds, hole cards, including the up cards
+/// of later stud rounds, and the stub of draw games are used as given.
+pub struct RolloutEvaluator {
+    policy: RolloutPolicy,
+    num_rollouts: u32,
+    rng: StdRng,
+}
+
+impl RolloutEvaluator {
+    pub fn new(policy: RolloutPolicy, num_rollouts: u32) -> RolloutEvaluator {
+        assert!(num_rollouts > 0, "need at least one rollout");
+        RolloutEvaluator {
+            policy,
+            num_rollouts,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to deal and sample actions so the estimates are reproducible
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Returns the cards dealt so far followed by a new deal of the later rounds' board cards
+    /// and the stub
+    fn deal_board(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> Vec<Card> {
+        // A state run out after an all in is already in the last round but only the cards up to
+        // the all in were dealt
+        let runout_rounds = state.runout_rounds();
+        let round = if runout_rounds.is_empty() { state.current_round() } else { runout_rounds.start - 1 };
+        let num_dealt = game_info.total_board_cards(round) as usize;
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        let (dealt, stub) = board_cards.split_at(num_dealt);
+        let stub = if game_info.has_draws() { stub } else { &[] };
+        let deck = Vec::from(game_info.generate_shuffled_deck(&mut self.rng))
+            .into_iter()
+            .filter(|c| !hole_cards.iter().any(|h| h.contains(c)) && !dealt.contains(c) && !stub.contains(c));
+
+        let mut board = dealt.to_vec();
+        board.extend(deck.take(num_board_cards - num_dealt));
+        assert_eq!(board.len(), num_board_cards, "not enough cards left to deal the board");
+        board.extend_from_slice(stub);
+        board
+    }
+
+    /// Plays the hand out from `state` and returns the payouts
+    fn rollout(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> Vec<i32> {
+        let evaluator = game_info.hand_ranking().evaluator();
+        let mut state = state.clone();
+
+        while !state.is_finished() {
+            if state.is_awaiting_up_cards() {
+                state = state.deal_up_cards(game_info, evaluator, hole_cards).unwrap();
+            }
+
+            let player = state.current_player().unwrap();
+            let visible_board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+            let current_hole_cards = state.current_hole_cards(game_info, hole_cards, board_cards);
+            let player_cards = &current_hole_cards[player as usize][..game_info.num_player_cards(state.current_round()) as usize];
+            let action = match &self.policy {
+                RolloutPolicy::Blueprint(profile) => profile.sample_action(&mut self.rng, game_info, &state, player_cards, visible_board, 1.),
+                RolloutPolicy::Uniform(action_abstraction) => {
+                    *action_abstraction.get_actions(game_info, &state).choose(&mut self.rng).expect("action abstraction has no actions")
+                },
+                RolloutPolicy::CheckDown if state.is_drawing() => Action::Discard(0),
+                RolloutPolicy::CheckDown => Action::Call,
+            };
+            state = state.apply_action_no_cards(game_info, action).unwrap();
+        }
+
+        state.get_payouts(game_info, evaluator, board_cards, hole_cards)
+    }
+}
+
+impl LeafEvaluator for RolloutEvaluator {
+    fn evaluate(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> Vec<f64> {
+        let mut values = vec![0.; game_info.num_players() as usize];
+        for _ in 0..self.num_rollouts {
+            let board = self.deal_board(game_info, state, hole_cards, board_cards);
+            for (v, payout) in values.iter_mut().zip(self.rollout(game_info, state, hole_cards, &board)) {
+                *v += payout as f64;
+            }
+        }
+
+        values.into_iter().map(|v| v / self.num_rollouts as f64).collect()
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        RolloutEvaluator::set_seed(self, seed);
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    card_set::format_cards,
+    deals::DealConstraints,
+    game::{Action, GameInfo, GameState, PlayerId},
+    history::HandHistory,
+    strategy::StrategyProfile,
+    units::Units,
+};
+
+use poker::Card;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Serialize;
+
+use std::fmt;
+
+/// A decision of a replayed hand with the value of every action under the blueprint
+#[derive(Clone, Debug, Serialize)]
+pub struct DecisionReview {
+    pub hand_id: u32,
+    pub player: String,
+    pub seat: PlayerId,
+    pub round: u8,
+    pub betting: String,
+    pub hole_cards: String,
+    pub board_cards: String,
+    /// Action that was played
+    pub action: Action,
+    pub best_action: Action,
+    /// Expected value of each action when everyone plays the blueprint afterwards
+    pub action_values: Vec<(Action, f64)>,
+    /// Value lost by playing `action` instead of `best_action`
+    pub loss: f64,
+}
+
+impl fmt::Display for DecisionReview {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "hand {} {} [{}] [{}] at \"{}\": played {}, best {}, lost {:.2}",
+            self.hand_id, self.player, self.hole_cards, self.board_cards, self.betting, self.action, self.best_action, self.loss)
+    }
+}
+
+/// Decisions of replayed hands that lost the most against the best action of the blueprint
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LeakReport {
+    /// Units of every value in the report
+    pub units: Units,
+    pub num_decisions: u64,
+    /// Value lost over every reviewed decision
+    pub total_loss: f64,
+    /// Decisions that lost more than the threshold, the costliest first
+    pub leaks: Vec<DecisionReview>,
+}
+
+impl LeakReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} leaks in {} decisions, {:.2} {} lost in total", self.leaks.len(), self.num_decisions, self.total_loss, self.units)?;
+        for leak in self.leaks.iter() {
+            write!(f, "\n{}", leak)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays hands and values every decision against a trained strategy. The value of an action
+/// is estimated by playing the hand out with every player following the blueprint. Opponent
+/// hole cards and the rest of the board are dealt again for every rollout, opponent hands
+/// weighted by how likely the blueprint plays the actions they took holding them, so a decision
+/// is judged on what the player knew instead of the cards that were shown. Only for board games
+/// without draws.
+pub struct LeakAnalyzer {
+    profile: StrategyProfile,
+    num_rollouts: u32,
+    /// Loss above which a decision is a leak, in `units`
+    threshold: Option<f64>,
+    units: Units,
+    /// Name of the player whose decisions are reviewed, everyone's if `None`
+    player: Option<String>,
+    cancel_token: CancelToken,
+    rng: StdRng,
+}
+
+impl LeakAnalyzer {
+    pub fn new(profile: StrategyProfile, num_rollouts: u32) -> LeakAnalyzer {
+        assert!(num_rollouts > 0, "need at least one rollout");
+        LeakAnalyzer {
+            profile,
+            num_rollouts,
+            threshold: None,
+            units: Units::Chips,
+            player: None,
+            cancel_token: CancelToken::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Sets the loss above which a decision is reported in the units of the report, a tenth of
+    /// the big blind by default
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = Some(threshold);
+    }
+
+    /// Sets the units of the values in the report and of the threshold, chips by default
+    pub fn set_units(&mut self, units: Units) {
+        self.units = units;
+    }
+
+    /// Only reviews the decisions of the player with `name`
+    pub fn set_player(&mut self, name: Option<String>) {
+        self.player = name;
+    }
+
+    /// Seeds the generator used to deal and sample actions so the report is reproducible
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the token checked between decisions
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn analyze(&mut self, hands: &[HandHistory]) -> Result<LeakReport, Cancelled> {
+        let mut report = LeakReport { units: self.units, ..LeakReport::default() };
+        for hand in hands {
+            let game_info = &hand.game_info;
+            assert!(!game_info.has_draws() && !game_info.is_stud(), "leaks can only be analyzed in board games");
+            let threshold = self.threshold.map_or(game_info.big_blind() as f64 / 10., |t| self.units.to_chips(game_info, t));
+            let states = hand.states().expect("hand can't be replayed");
+
+            for (i, (state, (_, seat, action))) in states.iter().zip(&hand.actions).enumerate() {
+                if self.player.as_ref().is_some_and(|name| *name != hand.players[*seat as usize]) {
+                    continue;
+                }
+                self.cancel_token.check()?;
+                let mut review = self.review(hand, &states[..i], state, *seat, *action);
+                let is_leak = review.loss > threshold;
+                review.loss = self.units.from_chips(game_info, review.loss);
+                review.action_values.iter_mut().for_each(|(_, v)| *v = self.units.from_chips(game_info, *v));
+                report.num_decisions += 1;
+                report.total_loss += review.loss;
+                if is_leak {
+                    report.leaks.push(review);
+                }
+            }
+        }
+
+        report.leaks.sort_by(|a, b| b.loss.total_cmp(&a.loss));
+        Ok(report)
+    }
+
+    /// Values every action of `seat` at `state`, `earlier` are the states before it
+    fn review(&mut self, hand: &HandHistory, earlier: &[GameState], state: &GameState, seat: PlayerId, played: Action) -> DecisionReview {
+        let game_info = &hand.game_info;
+        let hole_cards = &hand.hole_cards[seat as usize];
+        let board_cards = &hand.board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+
+        let mut actions = self.profile.abstract_game.action_abstraction.get_actions(game_info, state);
+        if !actions.contains(&played) {
+            actions.push(played);
+        }
+
+        let mut constraints = DealConstraints::new(game_info);
+        constraints.set_hole_cards(seat, hole_cards.clone());
+        constraints.set_board_cards(board_cards.to_vec());
+        let deals = (0..self.num_rollouts)
+            .map(|_| {
+                let (hole_cards, board_cards) = constraints.deal(game_info, &mut self.rng);
+                let weight = self.opponent_reach(game_info, hand, earlier, seat, &hole_cards, &board_cards);
+                (hole_cards, board_cards, weight)
+            })
+            .collect::<Vec<(Vec<Vec<Card>>, Vec<Card>, f64)>>();
+        // Opponents whose actions the blueprint never plays are dealt uniformly
+        let total_weight = deals.iter().map(|(_, _, w)| w).sum::<f64>();
+        let weight = |w: f64| if total_weight > 0. { w / total_weight } else { 1. / deals.len() as f64 };
+
+        // Every action is rolled out over the same deals so their values differ by the decision
+        // rather than the cards
+        let action_values = actions.iter()
+            .map(|action| {
+                let next = state.apply_action_no_cards(game_info, *action).expect("action abstraction gave an invalid action");
+                let value = deals.iter()
+                    .map(|(hole_cards, board_cards, w)| weight(*w) * self.rollout(game_info, &next, hole_cards, board_cards)[seat as usize] as f64)
+                    .sum::<f64>();
+                (*action, value)
+            })
+            .collect::<Vec<(Action, f64)>>();
+
+        let (best_action, best_value) = *action_values.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        let played_value = action_values.iter().find(|(a, _)| *a == played).unwrap().1;
+        DecisionReview {
+            hand_id: hand.hand_id,
+            player: hand.players[seat as usize].clone(),
+            seat,
+            round: state.current_round(),
+            betting: state.betting_string(game_info),
+            hole_cards: format_cards(hole_cards),
+            board_cards: format_cards(board_cards),
+            action: played,
+            best_action,
+            action_values,
+            loss: best_value - played_value,
+        }
+    }
+
+    /// Returns the probability the blueprint plays every action the opponents of `seat` took
+    /// before the decision holding the cards of `hole_cards`
+    fn opponent_reach(&self, game_info: &GameInfo, hand: &HandHistory, earlier: &[GameState], seat: PlayerId, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> f64 {
+        earlier.iter()
+            .zip(&hand.actions)
+            .filter(|(_, (_, player, _))| *player != seat)
+            .map(|(state, (_, player, action))| {
+                let visible_board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+                let probabilities = self.profile.action_probabilities(game_info, state, &hole_cards[*player as usize], visible_board);
+                action_probability(&probabilities, *action)
+            })
+            .product()
+    }
+
+    /// Plays the hand out from `state` with everyone sampling the blueprint and returns the
+    /// payouts
+    fn rollout(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> Vec<i32> {
+        let mut state = state.clone();
+        while !state.is_finished() {
+            let player = state.current_player().unwrap();
+            let visible_board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+            let action = self.profile.sample_action(&mut self.rng, game_info, &state, &hole_cards[player as usize], visible_board, 1.);
+            state = state.apply_action_no_cards(game_info, action).unwrap();
+        }
+        state.get_payouts(game_info, game_info.hand_ranking().evaluator(), board_cards, hole_cards)
+    }
+}
+
+/// Returns the probability of `action`, raises the blueprint doesn't make count as the closest
+/// raise it does
+fn action_probability(probabilities: &[(Action, f64)], action: Action) -> f64 {
+    match action {
+        Action::Raise(amount) => probabilities.iter()
+            .filter_map(|(a, p)| match a {
+                Action::Raise(r) => Some((r.abs_diff(amount), *p)),
+                _ => None,
+            })
+            .min_by_key(|(diff, _)| *diff)
+            .map_or(0., |(_, p)| p),
+        _ => probabilities.iter().find(|(a, _)| *a == action).map_or(0., |(_, p)| *p),
+    }
+}
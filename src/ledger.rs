@@ -0,0 +1,197 @@
+use super::game::{GameInfo, GameState, PlayerId};
+
+use serde::Serialize;
+
+use std::fmt;
+
+/// Why chips moved during a hand
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum ChipMovementKind {
+    /// Live blind posted before the hand, the antes of games played with antes
+    Blind,
+    Straddle,
+    /// Dead money posted before the hand, like antes on top of the blinds or a missed blind
+    DeadBlind,
+    /// Forced opening bet of the lowest up cards in stud
+    BringIn,
+    /// Chips put in by a call, bet or raise
+    Bet,
+    /// Part of a bet nobody matched, handed back to the player who made it
+    UncalledBet,
+    /// Chips won from the pot
+    Award,
+    /// Chips the house took from the pot
+    Rake,
+}
+
+impl fmt::Display for ChipMovementKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ChipMovementKind::Blind => "blind",
+            ChipMovementKind::Straddle => "straddle",
+            ChipMovementKind::DeadBlind => "dead blind",
+            ChipMovementKind::BringIn => "bring in",
+            ChipMovementKind::Bet => "bet",
+            ChipMovementKind::UncalledBet => "uncalled bet",
+            ChipMovementKind::Award => "award",
+            ChipMovementKind::Rake => "rake",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Chips moving between the stack of a player and the pot, or from the pot to the house
+#[derive(Clone, Debug, Serialize)]
+pub struct ChipMovement {
+    pub kind: ChipMovementKind,
+    /// Player the chips came from or went to, `None` for rake
+    pub player: Option<PlayerId>,
+    /// Chips added to the pot, negative when they left it
+    pub amount: i64,
+}
+
+/// Every chip movement of a hand in the order it happened, from the forced bets to the pot
+/// being awarded, so a payout that doesn't add up can be traced to the movement that went wrong
+#[derive(Clone, Debug, Serialize)]
+pub struct HandLedger {
+    pub hand_id: u32,
+    pub starting_stacks: Vec<u32>,
+    pub movements: Vec<ChipMovement>,
+    /// Stack of each player after the hand according to its payout, set once the hand is
+    /// settled
+    pub final_stacks: Option<Vec<i64>>,
+}
+
+impl HandLedger {
+    /// Starts the ledger of the hand starting at `state` with the forced bets it posts
+    pub fn new(game_info: &GameInfo, state: &GameState) -> HandLedger {
+        let num_players = game_info.num_players();
+        let mut ledger = HandLedger {
+            hand_id: state.hand_id(),
+            starting_stacks: (0..num_players).map(|p| state.player_stack(p)).collect(),
+            movements: Vec::new(),
+            final_stacks: None,
+        };
+
+        for player in 0..num_players {
+            let blind = game_info.blinds()[player as usize];
+            ledger.push(ChipMovementKind::Blind, Some(player), blind as i64);
+            ledger.push(ChipMovementKind::Straddle, Some(player), (game_info.live_blind(player) - blind) as i64);
+            ledger.push(ChipMovementKind::DeadBlind, Some(player), game_info.dead_blind(player) as i64);
+        }
+
+        ledger
+    }
+
+    /// Records the chips every player put in going from `before` to `after`
+    pub fn record(&mut self, kind: ChipMovementKind, before: &GameState, after: &GameState) {
+        for player in 0..self.starting_stacks.len() as PlayerId {
+            self.push(kind, Some(player), after.player_spent(player) as i64 - before.player_spent(player) as i64);
+        }
+    }
+
+    /// Settles the finished hand at `state`: the part of the largest bet nobody matched goes back
+    /// to its player, the rake to the house and the rest of the winnings of every player in
+    /// `payouts` from the pot
+    pub fn settle(&mut self, game_info: &GameInfo, state: &GameState, payouts: &[i32]) {
+        let num_players = game_info.num_players();
+        let mut winnings = (0..num_players).map(|p| payouts[p as usize] as i64 + state.player_spent(p) as i64).collect::<Vec<i64>>();
+
+        if let Some((player, amount)) = state.uncalled_bet() {
+            self.push(ChipMovementKind::UncalledBet, Some(player), -(amount as i64));
+            winnings[player as usize] -= amount as i64;
+        }
+
+        self.push(ChipMovementKind::Rake, None, -(state.rake(game_info) as i64));
+        for player in 0..num_players {
+            self.push(ChipMovementKind::Award, Some(player), -winnings[player as usize]);
+        }
+
+        self.final_stacks = Some(self.starting_stacks.iter().zip(payouts).map(|(s, p)| *s as i64 + *p as i64).collect());
+    }
+
+    fn push(&mut self, kind: ChipMovementKind, player: Option<PlayerId>, amount: i64) {
+        if amount != 0 {
+            self.movements.push(ChipMovement { kind, player, amount });
+        }
+    }
+
+    /// Returns the chips in the pot after every movement so far
+    pub fn pot(&self) -> i64 {
+        self.movements.iter().map(|m| m.amount).sum()
+    }
+
+    /// Returns the chips `player` put in the pot so far, forced bets included
+    pub fn put_in(&self, player: PlayerId) -> i64 {
+        self.movements.iter()
+            .filter(|m| m.player == Some(player) && !matches!(m.kind, ChipMovementKind::UncalledBet | ChipMovementKind::Award))
+            .map(|m| m.amount)
+            .sum()
+    }
+
+    /// Returns the chips `player` got back from the pot so far, a returned uncalled bet included
+    pub fn taken_out(&self, player: PlayerId) -> i64 {
+        -self.movements.iter()
+            .filter(|m| m.player == Some(player) && matches!(m.kind, ChipMovementKind::UncalledBet | ChipMovementKind::Award))
+            .map(|m| m.amount)
+            .sum::<i64>()
+    }
+
+    /// Returns the stack of `player` after every movement so far
+    pub fn stack(&self, player: PlayerId) -> i64 {
+        self.starting_stacks[player as usize] as i64 - self.movements.iter().filter(|m| m.player == Some(player)).map(|m| m.amount).sum::<i64>()
+    }
+
+    /// Checks that the settled hand balances: no stack or the pot ever went below zero, the pot
+    /// was emptied and every stack ended where its payout says
+    pub fn check(&self) -> Result<(), String> {
+        let Some(final_stacks) = &self.final_stacks else {
+            return Err("hand was not settled".to_string());
+        };
+
+        let mut stacks = self.starting_stacks.iter().map(|s| *s as i64).collect::<Vec<i64>>();
+        let mut pot = 0;
+        for (i, movement) in self.movements.iter().enumerate() {
+            pot += movement.amount;
+            if pot < 0 {
+                return Err(format!("pot went below zero at movement {}", i));
+            }
+            if let Some(player) = movement.player {
+                stacks[player as usize] -= movement.amount;
+                if stacks[player as usize] < 0 {
+                    return Err(format!("stack of player {} went below zero at movement {}", player, i));
+                }
+            }
+        }
+
+        if pot != 0 {
+            return Err(format!("{} chips were left in the pot", pot));
+        }
+        if let Some(player) = (0..stacks.len()).find(|p| stacks[*p] != final_stacks[*p]) {
+            return Err(format!("player {} ended with {} chips but their payout says {}", player, stacks[player], final_stacks[player]));
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for HandLedger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "hand {} starting stacks {:?}", self.hand_id, self.starting_stacks)?;
+        for movement in &self.movements {
+            let direction = if movement.amount >= 0 { "into" } else { "out of" };
+            match movement.player {
+                Some(player) => write!(f, "\nplayer {} {} {} {} the pot", player, movement.kind, movement.amount.abs(), direction)?,
+                None => write!(f, "\n{} {} {} the pot", movement.kind, movement.amount.abs(), direction)?,
+            }
+        }
+        if let Some(final_stacks) = &self.final_stacks {
+            write!(f, "\nfinal stacks {:?}", final_stacks)?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,91 @@
+#[cfg(not(feature = "tracing"))]
 #[macro_use]
 extern crate log;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
+
+/// Enters a span with the given name and fields until the end of the enclosing block, does
+/// nothing without the `tracing` feature
+#[macro_export]
+macro_rules! enter_span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($args)*).entered();
+    };
+}
 
 pub mod game;
+pub mod hand_indexer;
 
 pub mod abstract_game;
+pub mod abstraction_mapping;
+pub mod abstraction_quality;
 pub mod action_abstraction;
+pub mod agent;
+pub mod bankroll;
+pub mod best_response;
+pub mod board_features;
+pub mod bucket_generation;
+pub mod bucket_table;
+pub mod cancel;
+pub mod canonical;
 pub mod card_abstraction;
+pub mod card_set;
 pub mod cfr;
+pub mod clustering;
+pub mod config;
+pub mod cross_validation;
+pub mod dealer;
+pub mod deals;
+pub mod distributed;
+#[cfg(feature = "deep")]
+pub mod deep;
+pub mod dot;
+pub mod duplicate;
+pub mod ehs_buckets;
+pub mod evaluator;
+pub mod expected_value;
+pub mod exploit;
+pub mod features;
+pub mod fictitious_play;
+pub mod flop_report;
+pub mod history;
+pub mod history_abstraction;
+pub mod infoset_table;
+pub mod infosets;
+pub mod logging;
+pub mod icm;
+pub mod leaf_evaluator;
+pub mod leak_report;
+pub mod ledger;
+pub mod live_feed;
+pub mod local_best_response;
+pub mod manifest;
+pub mod match_stats;
+pub mod mcts;
 pub mod node;
+pub mod node_format;
+pub mod ochs;
+pub mod open_spiel;
+pub mod opponent_model;
+pub mod paged_map;
+pub(crate) mod parallel;
 pub mod play;
+pub mod position;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod range;
+pub mod range_chart;
+pub mod reload;
+pub mod replay;
+pub mod sequence_form;
+pub mod session;
+pub mod showdown;
 pub mod strategy;
+pub mod subgame_solver;
+pub mod tournament;
+pub mod units;
+pub mod verification;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,230 @@
+// Structured events of the hands a dealer plays, streamed as they finish so visualizers and
+// dashboards can follow a match live. Every event is one JSON object with a `type` field, written
+// to any number of sinks: a file or stdout as JSON lines, or with the `live` feature every
+// client connected to a WebSocket server.
+
+use super::{
+    card_set::format_cards_compact,
+    dealer::{HandEvent, HandRecord},
+    game::{GameInfo, PlayerId},
+    history::HandHistory,
+    match_stats::MatchStats,
+};
+
+use serde::Serialize;
+
+use std::io::{self, Write};
+
+/// Running results of a player at the end of a hand
+#[derive(Clone, Debug, Serialize)]
+pub struct WinRate {
+    pub player: usize,
+    pub hands: u64,
+    pub net: i64,
+    pub bb_per_100: f64,
+    /// Standard error of `bb_per_100`
+    pub std_error: f64,
+}
+
+/// Something that happened in a hand, `seat` is the position relative to the button and
+/// `player` the index of whoever sat there
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    HandStarted { hand_id: u32, players: Vec<usize>, stacks: Vec<u32> },
+    /// `pot` includes the chips of the action
+    Action { hand_id: u32, seat: PlayerId, player: usize, action: String, pot: u32 },
+    Street { hand_id: u32, round: u8, board: String, all_in: bool, pot: u32 },
+    UpCards { hand_id: u32, round: u8, up_cards: Vec<String> },
+    RunOut { hand_id: u32, board: String },
+    /// Final hands of every seat, `None` for seats that folded
+    Showdown { hand_id: u32, hands: Vec<Option<String>> },
+    HandFinished { hand_id: u32, pot: u32, payouts: Vec<i32>, win_rates: Vec<WinRate> },
+}
+
+/// Where the events of a feed are sent
+pub trait EventSink: Send {
+    /// Sends a single event serialized as JSON
+    fn send(&mut self, json: &str) -> io::Result<()>;
+}
+
+/// Writes every event on its own line
+pub struct JsonLines<W: Write + Send>(pub W);
+
+impl<W: Write + Send> EventSink for JsonLines<W> {
+    fn send(&mut self, json: &str) -> io::Result<()> {
+        writeln!(self.0, "{}", json)?;
+        self.0.flush()
+    }
+}
+
+/// Turns the hands of a match into events and sends them to every sink, keeping the running
+/// results of every player to report with each hand
+pub struct LiveFeed {
+    sinks: Vec<Box<dyn EventSink>>,
+    stats: MatchStats,
+}
+
+impl LiveFeed {
+    pub fn new(game_info: &GameInfo) -> LiveFeed {
+        LiveFeed {
+            sinks: Vec::new(),
+            stats: MatchStats::new(game_info),
+        }
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Returns the results of every player over the hands published so far
+    pub fn stats(&self) -> &MatchStats {
+        &self.stats
+    }
+
+    /// Sends the events of a finished hand where `seats[i]` is the player who sat in seat i.
+    /// Sinks that fail are dropped so a broken one doesn't stop the match.
+    pub fn publish(&mut self, game_info: &GameInfo, record: &HandRecord, seats: &[usize]) {
+        self.stats.add_hand(game_info, record, seats);
+        let events = hand_events(game_info, record, seats, &self.stats);
+        let lines = events.iter().map(|e| serde_json::to_string(e).unwrap()).collect::<Vec<String>>();
+        self.sinks.retain_mut(|sink| match lines.iter().try_for_each(|l| sink.send(l)) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("dropping live feed sink: {}", e);
+                false
+            },
+        });
+    }
+}
+
+/// Returns the events of a hand in the order they happened
+fn hand_events(game_info: &GameInfo, record: &HandRecord, seats: &[usize], stats: &MatchStats) -> Vec<LiveEvent> {
+    let hand_id = record.hand_id;
+    let history = HandHistory::from_record(game_info, record, &vec![String::new(); seats.len()]);
+    // The state before every action followed by the final one, so the pot after an action is in
+    // the state after it
+    let states = history.states().expect("recorded hand can't be replayed");
+    let mut pot = states[0].pot_total(game_info);
+    let mut num_actions = 0;
+
+    let mut events = vec![LiveEvent::HandStarted {
+        hand_id,
+        players: seats.to_vec(),
+        stacks: game_info.starting_stacks().to_vec(),
+    }];
+    for event in &record.events {
+        events.push(match event {
+            HandEvent::Action { player, action } => {
+                num_actions += 1;
+                pot = states[num_actions].pot_total(game_info);
+                LiveEvent::Action { hand_id, seat: *player, player: seats[*player as usize], action: action.to_string(), pot }
+            },
+            HandEvent::Street { round, board_cards, all_in } => LiveEvent::Street {
+                hand_id,
+                round: *round,
+                board: format_cards_compact(board_cards),
+                all_in: *all_in,
+                pot,
+            },
+            HandEvent::UpCards { round, up_cards, .. } => LiveEvent::UpCards {
+                hand_id,
+                round: *round,
+                up_cards: up_cards.iter().map(|c| format_cards_compact(c)).collect(),
+            },
+            HandEvent::RunOut { board_cards } => LiveEvent::RunOut { hand_id, board: format_cards_compact(board_cards) },
+            HandEvent::HandFinished { .. } => continue,
+        });
+    }
+
+    let state = &record.state;
+    let num_players = game_info.num_players();
+    if state.num_folded(game_info) + 1 < num_players {
+        let final_hole_cards = state.current_hole_cards(game_info, &record.hole_cards, &record.board_cards);
+        events.push(LiveEvent::Showdown {
+            hand_id,
+            hands: (0..num_players)
+                .map(|p| (!state.has_folded(p)).then(|| format_cards_compact(&final_hole_cards[p as usize])))
+                .collect(),
+        });
+    }
+    events.push(LiveEvent::HandFinished {
+        hand_id,
+        pot: state.pot_total(game_info),
+        payouts: record.payouts.clone(),
+        win_rates: stats.players()
+            .iter()
+            .enumerate()
+            .map(|(player, s)| WinRate { player, hands: s.hands, net: s.net, bb_per_100: s.bb_per_100, std_error: s.std_error })
+            .collect(),
+    });
+    events
+}
+
+#[cfg(feature = "live")]
+pub use self::websocket::WebSocketServer;
+
+#[cfg(feature = "live")]
+mod websocket {
+    use super::EventSink;
+
+    use tungstenite::{Message, WebSocket};
+
+    use std::io;
+    use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Sends every event to the WebSocket clients connected at the time, accepting new ones on
+    /// its own thread. Clients that don't take an event within a second are disconnected.
+    pub struct WebSocketServer {
+        clients: Arc<Mutex<Vec<WebSocket<TcpStream>>>>,
+    }
+
+    impl WebSocketServer {
+        pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<WebSocketServer> {
+            let listener = TcpListener::bind(address)?;
+            let clients = Arc::new(Mutex::new(Vec::new()));
+            let accepted = clients.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!("failed to accept live feed client: {}", e);
+                            continue;
+                        },
+                    };
+                    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+                    if let Err(e) = stream.set_write_timeout(Some(Duration::from_secs(1))) {
+                        warn!("failed to set timeout of {}: {}", peer, e);
+                        continue;
+                    }
+                    match tungstenite::accept(stream) {
+                        Ok(socket) => {
+                            info!("Live feed client {} connected", peer);
+                            accepted.lock().unwrap().push(socket);
+                        },
+                        Err(e) => warn!("live feed handshake with {} failed: {}", peer, e),
+                    }
+                }
+            });
+            Ok(WebSocketServer { clients })
+        }
+    }
+
+    impl EventSink for WebSocketServer {
+        /// Never fails, clients are dropped on their own
+        fn send(&mut self, json: &str) -> io::Result<()> {
+            self.clients.lock().unwrap().retain_mut(|socket| match socket.send(Message::Text(json.to_string())) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!("live feed client disconnected: {}", e);
+                    false
+                },
+            });
+            Ok(())
+        }
+    }
+}
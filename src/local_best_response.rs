@@ -0,0 +1,234 @@
+use super::{
+    action_abstraction::ActionAbstraction,
+    cancel::{CancelToken, Cancelled},
+    card_set::CardSet,
+    game::{Action, GameInfo, GameState, PlayerId},
+    strategy::StrategyProfile,
+};
+
+use poker::Card;
+use itertools::Itertools;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Winnings of local best response against a strategy, a lower bound on its exploitability
+#[derive(Copy, Clone, Debug)]
+pub struct LbrResult {
+    pub num_hands: u32,
+    /// Mean winnings in chips per hand
+    pub chips: f64,
+    /// Mean winnings in milli big blinds per hand
+    pub mean: f64,
+    /// Standard error of `mean`, estimated from the spread of the pairs of hands
+    pub std_error: f64,
+}
+
+impl fmt::Display for LbrResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LBR won {:.2} ± {:.2} mbb/hand (95%), {:.6} chips/hand over {} hands", self.mean, 1.96 * self.std_error, self.chips, self.num_hands)
+    }
+}
+
+/// Estimates how exploitable a strategy is in games too big for an exact best response with
+/// local best response (Lisý and Bowling, 2017). LBR plays heads up against the strategy with
+/// its real cards, keeping the range of the strategy's hands given its actions so far. At every
+/// decision it values each action of a restricted action set from its equity against that range,
+/// assuming the hand is checked down after a call and, for raises, the share of the range the
+/// strategy folds, and plays the best one. It only looks one action ahead so its winnings are a
+/// lower bound on the exploitability, reached by sampling hands instead of walking the tree.
+///
+/// Every deal is played twice with LBR in each seat. Equity is exact when the rest of the board
+/// can be dealt in at most `num_equity_samples` ways and sampled from that many runouts
+/// otherwise. Only for heads up board games without draws, valued by their high hand.
+pub struct LocalBestResponse {
+    profile: StrategyProfile,
+    /// Actions LBR chooses between
+    actions: ActionAbstraction,
+    num_equity_samples: u32,
+    cancel_token: CancelToken,
+    rng: StdRng,
+}
+
+impl LocalBestResponse {
+    pub fn new(profile: StrategyProfile, actions: ActionAbstraction) -> LocalBestResponse {
+        let game_info = &profile.abstract_game.game_info;
+        assert!(game_info.num_players() == 2, "local best response is heads up");
+        assert!(!game_info.is_stud() && !game_info.has_draws() && !game_info.hi_lo(), "local best response needs a board game without draws valued by the high hand");
+        LocalBestResponse {
+            profile,
+            actions,
+            num_equity_samples: 200,
+            cancel_token: CancelToken::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to deal, sample the strategy's actions and sample runouts
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the runouts equity is sampled from when the rest of the board can be dealt in more
+    /// ways
+    pub fn set_num_equity_samples(&mut self, num_equity_samples: u32) {
+        assert!(num_equity_samples > 0, "need at least one equity sample");
+        self.num_equity_samples = num_equity_samples;
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    /// Plays `num_pairs` deals with LBR in both seats and returns its winnings
+    pub fn run(&mut self, num_pairs: u32) -> Result<LbrResult, Cancelled> {
+        assert!(num_pairs > 1, "need at least two pairs to estimate the error");
+        let game_info = self.profile.abstract_game.game_info.clone();
+        let big_blind = game_info.big_blind() as f64;
+
+        let mut pair_winnings = Vec::with_capacity(num_pairs as usize);
+        for _ in 0..num_pairs {
+            self.cancel_token.check()?;
+            let (hole_cards, board_cards) = game_info.deal_hole_cards_and_board_cards(&mut self.rng);
+            let won = (0..2).map(|seat| self.play_hand(&game_info, seat, &hole_cards, &board_cards)).sum::<i32>();
+            pair_winnings.push(won as f64 / 2.);
+        }
+
+        let n = pair_winnings.len() as f64;
+        let chips = pair_winnings.iter().sum::<f64>() / n;
+        let variance = pair_winnings.iter().map(|w| (w - chips).powi(2)).sum::<f64>() / (n - 1.);
+        Ok(LbrResult {
+            num_hands: 2 * num_pairs,
+            chips,
+            mean: 1000. * chips / big_blind,
+            std_error: 1000. * (variance / n).sqrt() / big_blind,
+        })
+    }
+
+    /// Plays a hand with LBR in `seat` and returns its payout
+    fn play_hand(&mut self, game_info: &GameInfo, seat: PlayerId, hole_cards: &[Vec<Card>], board_cards: &[Card]) -> i32 {
+        let opponent = 1 - seat;
+        let known = CardSet::from_cards(&hole_cards[seat as usize]);
+        let mut range = known.remaining(&game_info.generate_deck().collect::<Vec<Card>>())
+            .into_iter()
+            .combinations(game_info.num_hole_cards() as usize)
+            .map(|hand| (hand, 1.))
+            .collect::<Vec<(Vec<Card>, f64)>>();
+
+        let mut state = GameState::new(game_info, 0);
+        while !state.is_finished() {
+            let player = state.current_player().expect("failed to find the player to act");
+            let visible_board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+            let board_set = CardSet::from_cards(visible_board);
+            range.retain(|(hand, _)| !CardSet::from_cards(hand).intersects(board_set));
+
+            let action = if player == seat {
+                self.best_action(game_info, &state, &hole_cards[seat as usize], visible_board, &range)
+            } else {
+                let action = self.profile.sample_action(&mut self.rng, game_info, &state, &hole_cards[opponent as usize], visible_board, 1.);
+                let probabilities = self.range_probabilities(game_info, &state, visible_board, &range);
+                let weights = range.iter().zip(&probabilities).map(|((_, w), p)| w * action_probability(p, action)).collect::<Vec<f64>>();
+                // Actions the strategy never plays with any hand, like ones translated from off
+                // the tree, leave the range as it was
+                if weights.iter().sum::<f64>() > 0. {
+                    range.iter_mut().zip(weights).for_each(|((_, w), new)| *w = new);
+                }
+                action
+            };
+            state = state.apply_action_no_cards(game_info, action).expect("failed to apply action");
+        }
+
+        state.get_payouts(game_info, game_info.hand_ranking().evaluator(), board_cards, hole_cards)[seat as usize]
+    }
+
+    /// Returns the action of LBR's action set worth the most at `state` against `range`
+    fn best_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card], range: &[(Vec<Card>, f64)]) -> Action {
+        let player = state.current_player().unwrap();
+        let equity = self.equity(game_info, hole_cards, board_cards, range);
+        let pot = state.pot_total(game_info) as f64;
+        let asked = state.amount_to_call(player) as f64;
+
+        self.actions.get_actions(game_info, state)
+            .into_iter()
+            .map(|action| {
+                let value = match action {
+                    Action::Fold => 0.,
+                    Action::Raise(_) => {
+                        let next = state.apply_action_no_cards(game_info, action).expect("LBR action set gave an invalid action");
+                        let raised = (next.player_spent(player) - state.player_spent(player)) as f64 - asked;
+                        let fold = match next.current_player() {
+                            Ok(p) if p != player && !next.is_finished() => {
+                                let probabilities = self.range_probabilities(game_info, &next, board_cards, range);
+                                let total = range.iter().map(|(_, w)| w).sum::<f64>();
+                                range.iter().zip(&probabilities).map(|((_, w), p)| w * action_probability(p, Action::Fold)).sum::<f64>() / total
+                            },
+                            _ => 0.,
+                        };
+                        // Chips the opponent can't match come back as an uncalled bet
+                        let called = raised.min(next.chips_behind(1 - player) as f64);
+                        fold * pot + (1. - fold) * (equity * (pot + called) - (1. - equity) * (asked + called))
+                    },
+                    _ => equity * pot - (1. - equity) * asked,
+                };
+                (action, value)
+            })
+            // Folding only wins ties against nothing to call
+            .max_by(|a, b| a.1.total_cmp(&b.1).then((a.0 != Action::Fold).cmp(&(b.0 != Action::Fold))))
+            .map(|(action, _)| action)
+            .expect("LBR action set has no actions")
+    }
+
+    /// Returns the action probabilities of the player to act at `state` holding each hand of
+    /// `range`
+    fn range_probabilities(&self, game_info: &GameInfo, state: &GameState, board_cards: &[Card], range: &[(Vec<Card>, f64)]) -> Vec<Vec<(Action, f64)>> {
+        let queries = range.iter().map(|(hand, _)| (state, &hand[..], board_cards)).collect::<Vec<(&GameState, &[Card], &[Card])>>();
+        self.profile.action_probabilities_batch(game_info, &queries)
+    }
+
+    /// Returns the probability `hole_cards` beats a hand of `range` by showdown, counting ties
+    /// as half
+    fn equity(&mut self, game_info: &GameInfo, hole_cards: &[Card], board_cards: &[Card], range: &[(Vec<Card>, f64)]) -> f64 {
+        let evaluator = game_info.hand_ranking().evaluator();
+        let direction = game_info.ranking_direction();
+        let num_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+        let dealt = [hole_cards, board_cards].concat();
+        let remaining = CardSet::from_cards(&dealt).remaining(&game_info.generate_deck().collect::<Vec<Card>>());
+        let num_cards = num_board_cards - board_cards.len();
+
+        let runouts = if num_combinations(remaining.len(), num_cards) <= self.num_equity_samples as u64 {
+            remaining.into_iter().combinations(num_cards).collect::<Vec<Vec<Card>>>()
+        } else {
+            (0..self.num_equity_samples).map(|_| remaining.choose_multiple(&mut self.rng, num_cards).copied().collect()).collect()
+        };
+
+        let mut wins = 0.;
+        let mut total = 0.;
+        for run_out in runouts {
+            let board = [board_cards, &run_out].concat();
+            let board_set = CardSet::from_cards(&board);
+            let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, &board));
+            for (hand, weight) in range.iter().filter(|(hand, w)| *w > 0. && !CardSet::from_cards(hand).intersects(board_set)) {
+                wins += weight * match rank.cmp(&direction.orient(evaluator.evaluate_showdown(hand, &board))) {
+                    Ordering::Greater => 1.,
+                    Ordering::Equal => 0.5,
+                    Ordering::Less => 0.,
+                };
+                total += weight;
+            }
+        }
+
+        if total > 0. { wins / total } else { 0.5 }
+    }
+}
+
+/// Returns the probability of `action` among `probabilities`, zero if it's missing
+fn action_probability(probabilities: &[(Action, f64)], action: Action) -> f64 {
+    probabilities.iter().find(|(a, _)| *a == action).map_or(0., |(_, p)| *p)
+}
+
+/// Returns the number of ways to pick `k` of `n` cards, saturating
+fn num_combinations(n: usize, k: usize) -> u64 {
+    (0..k as u64).fold(1u64, |c, i| c.saturating_mul(n as u64 - i) / (i + 1))
+}
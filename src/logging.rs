@@ -0,0 +1,18 @@
+//! Log messages go through the `log` crate, or through `tracing` with the `tracing` feature
+//! where they're nested in spans of the training iteration, hand or request they belong to.
+//! Either way `RUST_LOG` filters them.
+
+#[cfg(not(feature = "tracing"))]
+pub use log::{debug, error, info, trace, warn};
+#[cfg(feature = "tracing")]
+pub use tracing::{debug, error, info, trace, warn};
+
+/// Installs the logger of the enabled backend, call once at the start of a binary
+pub fn init() {
+    #[cfg(not(feature = "tracing"))]
+    env_logger::init();
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+}
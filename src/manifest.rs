@@ -0,0 +1,161 @@
+use super::{
+    abstract_game::AbstractGame,
+    cfr::CFRConfig,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File the manifest of a run is written to in its directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// File the metrics of a run are appended to in its directory, one JSON object per line
+pub const METRICS_FILE: &str = "metrics.jsonl";
+
+/// Everything a training run was started with, written next to its checkpoints so the run can be
+/// reproduced later and compared with other runs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// Seconds since the unix epoch when the run started
+    pub started_at: u64,
+    pub crate_version: String,
+    /// Commit the binary was built from with `-dirty` appended if the tree had changes, `None`
+    /// when git or the source tree isn't available
+    pub git_hash: Option<String>,
+    pub solver: String,
+    pub iterations: u32,
+    pub seed: Option<u64>,
+    pub game: Value,
+    pub action_abstraction: Value,
+    pub card_abstraction: Value,
+    pub cfr_config: Value,
+    /// Other settings of the run, like checkpoint and progress intervals
+    pub options: BTreeMap<String, String>,
+    /// Why the run stopped and after how many iterations, `None` while it's running
+    pub stopped: Option<(u32, String)>,
+}
+
+impl RunManifest {
+    pub fn new(abstract_game: &AbstractGame, cfr_config: &CFRConfig, solver: &str, iterations: u32) -> RunManifest {
+        RunManifest {
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: git_hash(),
+            solver: solver.to_string(),
+            iterations,
+            seed: cfr_config.seed(),
+            game: to_value(&abstract_game.game_info),
+            action_abstraction: to_value(&abstract_game.action_abstraction),
+            card_abstraction: to_value(&abstract_game.card_abstraction),
+            cfr_config: to_value(cfr_config),
+            options: BTreeMap::new(),
+            stopped: None,
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: impl ToString) {
+        self.options.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn set_stopped(&mut self, iteration: u32, reason: impl ToString) {
+        self.stopped = Some((iteration, reason.to_string()));
+    }
+
+    pub fn from_file(path: &Path) -> RunManifest {
+        serde_json::from_str(&fs::read_to_string(path).expect("failed to read run manifest")).expect("failed to deserialize run manifest")
+    }
+
+    /// Writes the manifest to `MANIFEST_FILE` in `dir`
+    pub fn save(&self, dir: &Path) {
+        fs::write(dir.join(MANIFEST_FILE), self.to_json()).expect("failed to write run manifest");
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("failed to serialize run manifest")
+    }
+}
+
+fn to_value<T: Serialize>(config: &T) -> Value {
+    serde_json::to_value(config).expect("failed to serialize config")
+}
+
+/// Asks git for the commit of the source tree the crate was built from
+fn git_hash() -> Option<String> {
+    let git = |args: &[&str]| Command::new("git")
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string());
+    let hash = git(&["rev-parse", "HEAD"])?;
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).is_some_and(|s| !s.is_empty());
+    Some(if dirty { hash + "-dirty" } else { hash })
+}
+
+/// Measurements of a run at one point of training
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunMetrics {
+    pub iteration: u32,
+    pub elapsed_seconds: f64,
+    pub nodes_touched: Option<u64>,
+    pub exploitability: Option<f64>,
+}
+
+/// Receives the metrics of a run as training goes, implement it to stream them to an experiment
+/// tracker. Closures taking `&RunMetrics` are sinks too.
+pub trait MetricsSink {
+    fn record(&mut self, metrics: &RunMetrics);
+
+    /// Called once training stopped
+    fn finish(&mut self, _manifest: &RunManifest) {}
+}
+
+impl<F: FnMut(&RunMetrics)> MetricsSink for F {
+    fn record(&mut self, metrics: &RunMetrics) {
+        self(metrics)
+    }
+}
+
+/// Appends metrics to `METRICS_FILE` in a run's directory, one JSON object per line
+pub struct JsonlMetrics {
+    writer: BufWriter<File>,
+}
+
+impl JsonlMetrics {
+    /// Starts the metrics of a new run in `dir`, replacing those of an earlier run there
+    pub fn create(dir: &Path) -> JsonlMetrics {
+        JsonlMetrics {
+            writer: BufWriter::new(File::create(dir.join(METRICS_FILE)).expect("failed to create metrics file")),
+        }
+    }
+
+    /// Continues the metrics already in `dir`
+    pub fn append(dir: &Path) -> JsonlMetrics {
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(METRICS_FILE)).expect("failed to open metrics file");
+        JsonlMetrics { writer: BufWriter::new(file) }
+    }
+
+    /// Reads the metrics written to `dir`
+    pub fn read(dir: &Path) -> Vec<RunMetrics> {
+        fs::read_to_string(dir.join(METRICS_FILE))
+            .expect("failed to read metrics file")
+            .lines()
+            .map(|l| serde_json::from_str(l).expect("failed to deserialize metrics"))
+            .collect()
+    }
+}
+
+impl MetricsSink for JsonlMetrics {
+    fn record(&mut self, metrics: &RunMetrics) {
+        serde_json::to_writer(&mut self.writer, metrics).expect("failed to serialize metrics");
+        // Flushed every line so a crashed run keeps the metrics up to the crash
+        self.writer.write_all(b"\n").and_then(|_| self.writer.flush()).expect("failed to write metrics");
+    }
+}
@@ -0,0 +1,186 @@
+use super::{
+    dealer::HandRecord,
+    game::{ActionDescription, GameInfo},
+    history::HandHistory,
+    session::SessionHand,
+};
+
+use serde::Serialize;
+
+use std::fmt::Write;
+
+/// Number of actions of each kind a player made
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ActionCounts {
+    pub folds: u64,
+    pub checks: u64,
+    pub calls: u64,
+    pub bets: u64,
+    pub raises: u64,
+    pub discards: u64,
+}
+
+impl ActionCounts {
+    fn add(&mut self, action: ActionDescription) {
+        match action {
+            ActionDescription::Fold => self.folds += 1,
+            ActionDescription::Check => self.checks += 1,
+            ActionDescription::Call { .. } => self.calls += 1,
+            ActionDescription::Bet { .. } => self.bets += 1,
+            ActionDescription::Raise { .. } => self.raises += 1,
+            ActionDescription::Discard(_) => self.discards += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.folds + self.checks + self.calls + self.bets + self.raises + self.discards
+    }
+
+    /// Returns the fraction of the actions that were folds, checks, calls, bets, raises and
+    /// discards, in that order
+    pub fn frequencies(&self) -> [f64; 6] {
+        let total = self.total().max(1) as f64;
+        [self.folds, self.checks, self.calls, self.bets, self.raises, self.discards].map(|n| n as f64 / total)
+    }
+}
+
+/// Results of a player from one seat
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SeatStats {
+    pub hands: u64,
+    pub net: i64,
+}
+
+/// Results and play of a player over a match
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PlayerStats {
+    pub hands: u64,
+    /// Chips won over every hand
+    pub net: i64,
+    /// Big blinds won per 100 hands
+    pub bb_per_100: f64,
+    /// Standard error of `bb_per_100`, estimated from the spread of the hands
+    pub std_error: f64,
+    /// Chips won in hands that went to showdown
+    pub showdown_net: i64,
+    /// Chips won in hands that ended with everyone else folding
+    pub non_showdown_net: i64,
+    pub showdowns: u64,
+    /// Results from each seat, seats are positions relative to the button
+    pub seats: Vec<SeatStats>,
+    pub actions: ActionCounts,
+    /// Sum of the squared winnings in big blinds of every hand
+    #[serde(skip)]
+    sum_squares: f64,
+}
+
+/// Statistics of the players of a match, accumulated hand by hand
+#[derive(Clone, Debug, Serialize)]
+pub struct MatchStats {
+    big_blind: u32,
+    players: Vec<PlayerStats>,
+}
+
+impl MatchStats {
+    pub fn new(game_info: &GameInfo) -> MatchStats {
+        let num_players = game_info.num_players() as usize;
+        MatchStats {
+            big_blind: game_info.big_blind(),
+            players: vec![PlayerStats { seats: vec![SeatStats::default(); num_players], ..PlayerStats::default() }; num_players],
+        }
+    }
+
+    /// Returns the stats of a match between the seats of `records`, like those of
+    /// `Dealer::play_match`
+    pub fn from_records(game_info: &GameInfo, records: &[HandRecord]) -> MatchStats {
+        let mut stats = MatchStats::new(game_info);
+        let seats = (0..game_info.num_players() as usize).collect::<Vec<usize>>();
+        for record in records {
+            stats.add_hand(game_info, record, &seats);
+        }
+        stats
+    }
+
+    /// Returns the stats of the players of a session
+    pub fn from_session(game_info: &GameInfo, hands: &[SessionHand]) -> MatchStats {
+        let mut stats = MatchStats::new(game_info);
+        for hand in hands {
+            stats.add_hand(game_info, &hand.record, &hand.seats);
+        }
+        stats
+    }
+
+    /// Adds a hand where `seats[i]` is the player who sat in seat i
+    pub fn add_hand(&mut self, game_info: &GameInfo, record: &HandRecord, seats: &[usize]) {
+        let num_players = game_info.num_players();
+        assert!(seats.len() == num_players as usize, "need the player of every seat");
+        let showdown = record.state.num_folded(game_info) + 1 < num_players;
+
+        for (seat, player) in seats.iter().enumerate() {
+            let payout = record.payouts[seat] as i64;
+            let stats = &mut self.players[*player];
+            stats.hands += 1;
+            stats.net += payout;
+            stats.seats[seat].hands += 1;
+            stats.seats[seat].net += payout;
+            if showdown {
+                stats.showdown_net += payout;
+                stats.showdowns += 1;
+            } else {
+                stats.non_showdown_net += payout;
+            }
+
+            let won = payout as f64 / self.big_blind as f64;
+            stats.sum_squares += won * won;
+            let n = stats.hands as f64;
+            let mean = stats.net as f64 / self.big_blind as f64 / n;
+            stats.bb_per_100 = 100. * mean;
+            stats.std_error = if stats.hands > 1 {
+                let variance = (stats.sum_squares - n * mean * mean).max(0.) / (n - 1.);
+                100. * (variance / n).sqrt()
+            } else {
+                0.
+            };
+        }
+
+        // Replaying the hand tells checks from calls and bets from raises
+        let history = HandHistory::from_record(game_info, record, &vec![String::new(); num_players as usize]);
+        let states = history.states().expect("recorded hand can't be replayed");
+        for (state, (player, action)) in states.iter().zip(&record.actions) {
+            let description = state.describe_action(&history.game_info, *action).expect("recorded action is invalid");
+            self.players[seats[*player as usize]].actions.add(description);
+        }
+    }
+
+    pub fn players(&self) -> &[PlayerStats] {
+        &self.players
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Writes a row for every player with the results from each seat and the action
+    /// frequencies
+    pub fn to_csv(&self) -> String {
+        let num_seats = self.players.len();
+        let mut csv = String::new();
+        write!(csv, "player,hands,net,bb_per_100,std_error,showdown_net,non_showdown_net,showdowns").unwrap();
+        for seat in 0..num_seats {
+            write!(csv, ",seat_{}_hands,seat_{}_net", seat, seat).unwrap();
+        }
+        writeln!(csv, ",fold,check,call,bet,raise,discard").unwrap();
+
+        for (player, stats) in self.players.iter().enumerate() {
+            write!(csv, "{},{},{},{:.4},{:.4},{},{},{}", player, stats.hands, stats.net, stats.bb_per_100, stats.std_error, stats.showdown_net, stats.non_showdown_net, stats.showdowns).unwrap();
+            for seat in &stats.seats {
+                write!(csv, ",{},{}", seat.hands, seat.net).unwrap();
+            }
+            for frequency in stats.actions.frequencies() {
+                write!(csv, ",{:.4}", frequency).unwrap();
+            }
+            writeln!(csv).unwrap();
+        }
+        csv
+    }
+}
@@ -0,0 +1,172 @@
+use super::{
+    action_abstraction::ActionAbstraction,
+    agent::Agent,
+    deals::DealConstraints,
+    game::{Action, GameInfo, GameState, PlayerId},
+};
+
+use poker::Card;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::fmt;
+
+/// Node of the search tree, reached by the betting since the decision
+#[derive(Clone, Debug)]
+struct MctsNode {
+    children: Vec<(Action, usize)>,
+    visits: u32,
+    /// Payouts of every player summed over the visits, as a share of the chips at stake
+    values: Vec<f64>,
+}
+
+impl MctsNode {
+    fn new(num_players: usize) -> MctsNode {
+        MctsNode {
+            children: Vec::new(),
+            visits: 0,
+            values: vec![0.; num_players],
+        }
+    }
+}
+
+/// Visits and mean payout in chips of every action searched at the last decision
+#[derive(Clone, Debug, Default)]
+pub struct SearchStatistics {
+    pub actions: Vec<(Action, u32, f64)>,
+}
+
+impl fmt::Display for SearchStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (action, visits, value)) in self.actions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{} {} visits, {:.3} chips", action, visits, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decides with information set Monte Carlo tree search (single observer IS-MCTS, Cowling et
+/// al. 2012) instead of a trained strategy, searching from every decision over the same game
+/// engine and action abstraction. Every iteration deals the opponents' hole cards and the rest of
+/// the board again uniformly from the unseen cards, walks one tree of the betting shared by all
+/// those deals picking actions by UCB1, adds a node, plays the hand out with random actions and
+/// backs the payouts up. The most visited action is played. Opponents are dealt without reading
+/// their actions, so it's a baseline rather than a strong player. Only for board games without
+/// draws.
+pub struct MctsAgent {
+    action_abstraction: ActionAbstraction,
+    num_iterations: u32,
+    /// Weight of the UCB1 exploration term, payouts are measured in shares of the chips at stake
+    exploration: f64,
+    statistics: SearchStatistics,
+    rng: StdRng,
+}
+
+impl MctsAgent {
+    pub fn new(action_abstraction: ActionAbstraction, num_iterations: u32) -> MctsAgent {
+        assert!(num_iterations > 0, "need at least one iteration");
+        MctsAgent {
+            action_abstraction,
+            num_iterations,
+            exploration: 0.5,
+            statistics: SearchStatistics::default(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the generator used to deal and pick actions so the agent plays reproducibly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the weight of the UCB1 exploration term, larger values spread the iterations over
+    /// more actions
+    pub fn set_exploration(&mut self, exploration: f64) {
+        assert!(exploration >= 0., "exploration can't be negative");
+        self.exploration = exploration;
+    }
+
+    /// Returns the actions searched at the last decision
+    pub fn statistics(&self) -> &SearchStatistics {
+        &self.statistics
+    }
+
+    /// Searches the decision of the player to act in `state` and returns the most visited action
+    pub fn search(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        assert!(!game_info.is_stud() && !game_info.has_draws(), "MCTS agents only play board games without draws");
+        let seat = state.current_player().expect("no player to act");
+        let num_players = game_info.num_players() as usize;
+        let mut constraints = DealConstraints::new(game_info);
+        constraints.set_hole_cards(seat, hole_cards.to_vec());
+        constraints.set_board_cards(board_cards.to_vec());
+        // Payouts are scaled to about [-1, 1] so the exploration weight doesn't depend on stacks
+        let scale = (0..num_players as PlayerId).map(|p| state.player_spent(p) + state.chips_behind(p)).max().unwrap_or(1).max(1) as f64;
+        let evaluator = game_info.hand_ranking().evaluator();
+
+        let mut nodes = vec![MctsNode::new(num_players)];
+        for _ in 0..self.num_iterations {
+            let (hole_cards, board_cards) = constraints.deal(game_info, &mut self.rng);
+            let mut state = state.clone();
+            let mut path = vec![0];
+
+            // Select down the tree until a node has actions left to try, then add one
+            while !state.is_finished() {
+                let node_id = *path.last().unwrap();
+                let player = state.current_player().unwrap() as usize;
+                let actions = self.action_abstraction.get_actions(game_info, &state);
+                let untried = actions.iter().filter(|a| !nodes[node_id].children.iter().any(|(b, _)| b == *a)).copied().collect::<Vec<Action>>();
+                if let Some(action) = untried.choose(&mut self.rng) {
+                    nodes.push(MctsNode::new(num_players));
+                    let child = nodes.len() - 1;
+                    nodes[node_id].children.push((*action, child));
+                    state = state.apply_action_no_cards(game_info, *action).expect("action abstraction gave an invalid action");
+                    path.push(child);
+                    break;
+                }
+
+                let log_visits = (nodes[node_id].visits as f64).ln();
+                let (action, child) = *nodes[node_id].children.iter()
+                    .max_by(|a, b| self.ucb(&nodes[a.1], player, log_visits).total_cmp(&self.ucb(&nodes[b.1], player, log_visits)))
+                    .expect("failed to find an action");
+                state = state.apply_action_no_cards(game_info, action).unwrap();
+                path.push(child);
+            }
+
+            while !state.is_finished() {
+                let action = *self.action_abstraction.get_actions(game_info, &state).choose(&mut self.rng).expect("failed to find an action");
+                state = state.apply_action_no_cards(game_info, action).unwrap();
+            }
+
+            let payouts = state.get_payouts(game_info, evaluator, &board_cards, &hole_cards);
+            for node_id in path {
+                let node = &mut nodes[node_id];
+                node.visits += 1;
+                for (value, payout) in node.values.iter_mut().zip(&payouts) {
+                    *value += *payout as f64 / scale;
+                }
+            }
+        }
+
+        let root = &nodes[0];
+        self.statistics.actions = root.children.iter()
+            .map(|(action, child)| (*action, nodes[*child].visits, nodes[*child].values[seat as usize] / nodes[*child].visits as f64 * scale))
+            .collect();
+        root.children.iter().max_by_key(|(_, child)| nodes[*child].visits).map(|(action, _)| *action).expect("failed to find an action")
+    }
+
+    /// Returns the UCB1 score of moving to `node` for `player`, `log_visits` being the log of
+    /// the visits of its parent
+    fn ucb(&self, node: &MctsNode, player: usize, log_visits: f64) -> f64 {
+        let visits = node.visits as f64;
+        node.values[player] / visits + self.exploration * (log_visits / visits).sqrt()
+    }
+}
+
+impl Agent for MctsAgent {
+    fn get_action(&mut self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Action {
+        self.search(game_info, state, hole_cards, board_cards)
+    }
+}
@@ -1,48 +1,108 @@
 use super::{
-    game::{ Action, GameState },
+    game::{ Action, GameInfo, GameState, SidePots, TranspositionKey },
+    history_abstraction::{HistoryAbstraction, HistoryKey},
 };
 
 use serde::{Serialize, Deserialize};
 
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::path::Path;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 
+/// Index of a node in the arena of `Nodes`
 pub type NodeId = usize;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Node {
-    pub children: BTreeMap<Action, NodeId>,
+    /// Child of each action taken so far, sorted by action. Indices are stored as u32 to keep
+    /// nodes small.
+    children: Vec<(Action, u32)>,
     pub state: GameState,
+    /// Pots of a terminal node, worked out when the node is added instead of on every visit
+    #[serde(skip)]
+    side_pots: Option<Box<SidePots>>,
 }
 
 impl Node {
     pub fn new(state: GameState) -> Node {
         Node {
-            children: BTreeMap::new(),
+            children: Vec::new(),
             state,
+            side_pots: None,
         }
     }
+
+    /// Creates a node and works out its pots if its hand is over
+    pub fn with_side_pots(game_info: &GameInfo, state: GameState) -> Node {
+        let mut node = Node::new(state);
+        node.add_side_pots(game_info);
+        node
+    }
+
+    /// Returns the pots of a terminal node, `None` if they weren't worked out
+    pub fn side_pots(&self) -> Option<&SidePots> {
+        self.side_pots.as_deref()
+    }
+
+    fn add_side_pots(&mut self, game_info: &GameInfo) {
+        if self.state.is_finished() {
+            self.side_pots = Some(Box::new(self.state.side_pots(game_info)));
+        }
+    }
+
+    /// Returns the child reached by `action` if it was added
+    pub fn child(&self, action: &Action) -> Option<NodeId> {
+        self.children.binary_search_by(|(a, _)| a.cmp(action))
+            .ok()
+            .map(|i| self.children[i].1 as NodeId)
+    }
+
+    /// Returns every child added so far along with its action, sorted by action
+    pub fn children(&self) -> impl Iterator<Item = (Action, NodeId)> + '_ {
+        self.children.iter().map(|(a, c)| (*a, *c as NodeId))
+    }
+
+    fn add_child(&mut self, action: Action, child: NodeId) {
+        let child = u32::try_from(child).expect("too many nodes for u32 indices");
+        match self.children.binary_search_by(|(a, _)| a.cmp(&action)) {
+            Ok(i) => self.children[i].1 = child,
+            Err(i) => self.children.insert(i, (action, child)),
+        }
+    }
+}
+
+/// History abstraction applied to the nodes of a game, see `Nodes::set_history_abstraction`
+#[derive(Debug)]
+struct MergedHistory {
+    abstraction: HistoryAbstraction,
+    game_info: GameInfo,
+    /// Key of every node, `None` for nodes not reached from the root
+    keys: Vec<Option<HistoryKey>>,
+    /// Node of every key seen so far
+    nodes: HashMap<HistoryKey, NodeId>,
 }
 
+/// Nodes of the game tree stored in a flat arena, a node's id is its index so children are
+/// found without following pointers and traversals stay close in memory
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Nodes {
-    pub nodes_map: BTreeMap<NodeId, Node>,
+    nodes: Vec<Node>,
     root: NodeId,
-    next_node_id: NodeId,
+    /// Node of every transposition key seen so far, set while transpositions are merged
+    #[serde(skip)]
+    transpositions: Option<HashMap<TranspositionKey, NodeId>>,
+    #[serde(skip)]
+    history: Option<Box<MergedHistory>>,
 }
 
 impl Nodes {
     pub fn new(state: GameState) -> Nodes {
-        let mut nodes = BTreeMap::new();
-        let node = Node::new(state);
-        nodes.insert(0, node);
-
         Nodes {
-            nodes_map: nodes,
+            nodes: vec![Node::new(state)],
             root: 0,
-            next_node_id: 1,
+            transpositions: None,
+            history: None,
         }
     }
 
@@ -51,6 +111,18 @@ impl Nodes {
         bincode::deserialize_from(&mut r).unwrap()
     }
 
+    /// Works out the pots of every terminal node, which aren't saved with the nodes
+    pub fn add_side_pots(&mut self, game_info: &GameInfo) {
+        for node in self.nodes.iter_mut() {
+            node.add_side_pots(game_info);
+        }
+    }
+
+    /// Reads nodes saved by `save` from memory
+    pub fn from_bytes(bytes: &[u8]) -> Nodes {
+        bincode::deserialize(bytes).unwrap()
+    }
+
     pub fn save(&self, path: &Path) {
         let mut f = BufWriter::new(File::create(path).unwrap());
         bincode::serialize_into(&mut f, self).unwrap();
@@ -61,15 +133,110 @@ impl Nodes {
     }
 
     pub fn get_node(&self, node_id: NodeId) -> Option<&Node> {
-        self.nodes_map.get(&node_id)
+        self.nodes.get(node_id)
+    }
+
+    pub fn get_node_mut(&mut self, node_id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(node_id)
     }
 
     pub fn add_node(&mut self, node: Node) -> NodeId {
-        let node_id = self.next_node_id;
-        self.nodes_map.insert(self.next_node_id, node);
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Makes children added from now on link to the node they transpose with if there is one,
+    /// instead of adding a node, see `TranspositionKey`. Transpositions put the same chips in
+    /// every round, so the merged node's pot holds for every betting reaching it, and it plays
+    /// one strategy for all of them, an abstraction forgetting how earlier rounds were bet. Not saved with the nodes, so it has to
+    /// be set again after loading them to keep merging.
+    pub fn set_merge_transpositions(&mut self, merge: bool) {
+        self.transpositions = merge.then(|| {
+            let mut transpositions = HashMap::new();
+            for (node_id, node) in self.nodes.iter().enumerate() {
+                transpositions.entry(node.state.transposition_key()).or_insert(node_id);
+            }
+            transpositions
+        });
+    }
 
-        self.next_node_id += 1;
+    pub fn merges_transpositions(&self) -> bool {
+        self.transpositions.is_some()
+    }
+
+    /// Makes children added from now on link to the node with the same betting under
+    /// `abstraction` if there is one, see `HistoryAbstraction`. Betting reaching the node put the
+    /// same chips in, but the node keeps the rest of the state of the betting that reached it
+    /// first, like the size of its last raise. Links already added are kept and saved with the
+    /// nodes, the abstraction isn't and has to be set again to keep merging after loading them.
+    pub fn set_history_abstraction(&mut self, game_info: &GameInfo, abstraction: Option<HistoryAbstraction>) {
+        self.history = abstraction.map(|abstraction| {
+            let mut keys = vec![None; self.nodes.len()];
+            let mut nodes = HashMap::new();
+            let root_key = abstraction.root_key(game_info, &self.nodes[self.root].state);
+            nodes.insert(root_key.clone(), self.root);
+            keys[self.root] = Some(root_key);
+
+            let mut stack = vec![self.root];
+            while let Some(parent) = stack.pop() {
+                for (action, child) in self.nodes[parent].children() {
+                    if keys[child].is_some() {
+                        continue;
+                    }
+                    let key = abstraction.child_key(game_info, keys[parent].as_ref().unwrap(), &self.nodes[parent].state, action, &self.nodes[child].state);
+                    nodes.entry(key.clone()).or_insert(child);
+                    keys[child] = Some(key);
+                    stack.push(child);
+                }
+            }
+
+            Box::new(MergedHistory { abstraction, game_info: game_info.clone(), keys, nodes })
+        });
+    }
+
+    /// Adds `node` as the child of `parent` reached by `action`, or links the node it
+    /// transposes with if transpositions are merged or with the same betting under the history
+    /// abstraction
+    pub fn add_child(&mut self, parent: NodeId, action: Action, node: Node) -> NodeId {
+        let key = self.transpositions.as_ref().map(|_| node.state.transposition_key());
+        let history_key = self.history.as_ref().and_then(|h| {
+            h.keys[parent].as_ref().map(|k| h.abstraction.child_key(&h.game_info, k, &self.nodes[parent].state, action, &node.state))
+        });
+        let existing = key.as_ref().and_then(|k| self.transpositions.as_ref().unwrap().get(k).copied())
+            .or_else(|| history_key.as_ref().and_then(|k| self.history.as_ref().unwrap().nodes.get(k).copied()));
+        let child = match existing {
+            Some(child) => child,
+            None => self.add_node(node),
+        };
+        if let (Some(transpositions), Some(key)) = (self.transpositions.as_mut(), key) {
+            transpositions.entry(key).or_insert(child);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.keys.resize(self.nodes.len(), None);
+            if let Some(key) = history_key {
+                history.nodes.entry(key.clone()).or_insert(child);
+                history.keys[child].get_or_insert(key);
+            }
+        }
+        self.nodes[parent].add_child(action, child);
+        child
+    }
+
+    pub fn history_abstraction(&self) -> Option<&HistoryAbstraction> {
+        self.history.as_ref().map(|h| &h.abstraction)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
 
-        node_id
+    /// Iterates over every node in index order, parents come before their children unless
+    /// transpositions were merged
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes.iter().enumerate()
     }
 }
@@ -0,0 +1,240 @@
+// Strategies as plain per node records in the layout of other solvers' exports: the betting
+// leading to a decision, the board and how often every hand takes each action. Records can be
+// diffed, edited by hand and read back into a profile, which is how nodelocking tools built
+// around other solvers exchange strategies.
+
+use super::{
+    card_abstraction::BucketId,
+    card_set::{format_cards_compact, parse_cards},
+    game::{Action, PlayerId},
+    node::NodeId,
+    strategy::{StrategyProfile, PROBABILITY_SCALE},
+};
+
+use itertools::Itertools;
+use poker::Card;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Header of the CSV `to_csv` writes, one row per action of a hand
+pub const CSV_HEADER: &str = "path,player,board,hand,action,frequency";
+
+/// Action frequencies of every hand at one decision node on one board
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeStrategy {
+    /// Betting leading to the node, see `GameState::betting_string`
+    pub path: String,
+    pub player: PlayerId,
+    /// Board cards visible at the node written together like `Kh7d2c`, empty before any are
+    /// dealt
+    pub board: String,
+    /// Actions of the node written like `call` or `raise 200`
+    #[serde(with = "action_strings")]
+    pub actions: Vec<Action>,
+    /// Frequency of every action in the order of `actions` for each hand, keyed by its hole
+    /// cards written together like `AhKd`
+    pub hands: BTreeMap<String, Vec<f64>>,
+}
+
+/// Writes actions with `Display` and reads them with `FromStr` instead of their serde form
+mod action_strings {
+    use super::Action;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(actions: &[Action], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(actions.iter().map(|a| a.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Action>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|a| a.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Returns the records of every decision node of the profile's tree on every board it can be
+/// reached with, the board cards visible in its round taken from each of `boards` long enough
+/// to have them. Nodes of rounds before any board card only have the empty board. Hands are
+/// every combination of hole cards not on the board, looked up through the card abstraction.
+/// Partial strategies of the profile aren't included.
+pub fn export(profile: &StrategyProfile, boards: &[Vec<Card>]) -> Vec<NodeStrategy> {
+    let abstract_game = &profile.abstract_game;
+    let game_info = &abstract_game.game_info;
+    assert!(!game_info.has_draws() && !game_info.is_stud(), "node records need hole cards that don't change between rounds");
+    let deck = game_info.generate_deck().collect::<Vec<Card>>();
+
+    let mut records = Vec::new();
+    for (node_id, node) in abstract_game.nodes.iter() {
+        let state = &node.state;
+        let player = match state.current_player() {
+            Ok(player) if !state.is_finished() => player,
+            _ => continue,
+        };
+        let round = state.current_round();
+        let num_board_cards = game_info.total_board_cards(round) as usize;
+        let actions = abstract_game.get_actions(state);
+        let round_boards = boards.iter()
+            .filter(|b| b.len() >= num_board_cards)
+            .map(|b| b[..num_board_cards].to_vec())
+            .unique()
+            .collect::<Vec<Vec<Card>>>();
+
+        for board in round_boards {
+            let hands = deck.iter()
+                .filter(|c| !board.contains(c))
+                .combinations(game_info.num_hole_cards() as usize)
+                .map(|hole_cards| {
+                    let hole_cards = hole_cards.into_iter().copied().collect::<Vec<Card>>();
+                    let bucket_id = abstract_game.get_bucket(round, &board, &hole_cards);
+                    let probabilities = profile.strategy.action_probabilities(node_id, bucket_id, &actions);
+                    (format_cards_compact(&hole_cards), probabilities.into_values().collect())
+                })
+                .collect();
+            records.push(NodeStrategy {
+                path: state.betting_string(game_info),
+                player,
+                board: format_cards_compact(&board),
+                actions: actions.clone(),
+                hands,
+            });
+        }
+    }
+    records
+}
+
+/// Overwrites the strategy of every infoset `records` cover, returning how many there were.
+/// Hands sharing a bucket get the average of their frequencies, since the abstraction plays them
+/// the same. Infosets no record covers keep their strategy.
+pub fn import(profile: &mut StrategyProfile, records: &[NodeStrategy]) -> Result<usize, String> {
+    let abstract_game = &profile.abstract_game;
+    let game_info = &abstract_game.game_info;
+    let mut node_ids = BTreeMap::new();
+    for (node_id, node) in abstract_game.nodes.iter() {
+        node_ids.entry(node.state.betting_string(game_info)).or_insert(node_id);
+    }
+
+    let mut totals: BTreeMap<(NodeId, BucketId), (Vec<f64>, u32)> = BTreeMap::new();
+    let mut node_actions = BTreeMap::new();
+    for record in records {
+        let node_id = *node_ids.get(&record.path).ok_or_else(|| format!("no node at {}", record.path))?;
+        let state = &abstract_game.nodes.get_node(node_id).unwrap().state;
+        if state.is_finished() || state.current_player() != Ok(record.player) {
+            return Err(format!("player {} doesn't act at {}", record.player, record.path));
+        }
+        let round = state.current_round();
+        let board = parse_cards(&record.board).map_err(|e| format!("bad board at {}: {}", record.path, e))?;
+        if board.len() != game_info.total_board_cards(round) as usize {
+            return Err(format!("board {} at {} should have {} cards", record.board, record.path, game_info.total_board_cards(round)));
+        }
+        // Records can list the actions in any order, frequencies are stored in the node's
+        let actions = node_actions.entry(node_id).or_insert_with(|| abstract_game.get_actions(state)).clone();
+        if record.actions.len() != actions.len() {
+            return Err(format!("{} should have the {} actions of the node", record.path, actions.len()));
+        }
+        let order = actions.iter()
+            .map(|a| record.actions.iter().position(|r| r == a).ok_or_else(|| format!("{} is missing {}", record.path, a)))
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        for (hand, frequencies) in &record.hands {
+            let hole_cards = parse_cards(hand).map_err(|e| format!("bad hand at {}: {}", record.path, e))?;
+            if hole_cards.len() != game_info.num_hole_cards() as usize || hole_cards.iter().any(|c| board.contains(c)) {
+                return Err(format!("{} isn't a hand on {} at {}", hand, record.board, record.path));
+            }
+            if frequencies.len() != actions.len() || frequencies.iter().any(|f| !(0. ..=1.).contains(f)) {
+                return Err(format!("frequencies of {} at {} should be {} values between 0 and 1", hand, record.path, actions.len()));
+            }
+            let bucket_id = abstract_game.get_bucket(round, &board, &hole_cards);
+            let (total, count) = totals.entry((node_id, bucket_id)).or_insert_with(|| (vec![0.; actions.len()], 0));
+            total.iter_mut().zip(&order).for_each(|(t, i)| *t += frequencies[*i]);
+            *count += 1;
+        }
+    }
+
+    let num_infosets = totals.len();
+    for ((node_id, bucket_id), (total, count)) in totals {
+        let sum = total.iter().sum::<f64>();
+        let counts = node_actions[&node_id].iter()
+            .zip(&total)
+            .map(|(a, t)| {
+                let p = if sum > 0. { t / sum } else { 1. / total.len() as f64 };
+                (*a, (p * PROBABILITY_SCALE as f64).round() as i32)
+            })
+            .collect();
+        debug!("Imported ({}, {}) from {} hands", node_id, bucket_id, count);
+        profile.strategy.0.insert((node_id, bucket_id), counts);
+    }
+    Ok(num_infosets)
+}
+
+pub fn to_json(records: &[NodeStrategy]) -> String {
+    serde_json::to_string_pretty(records).expect("failed to serialize node strategies")
+}
+
+pub fn from_json(json: &str) -> Result<Vec<NodeStrategy>, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+/// Writes the records as CSV with a row per action of every hand, see `CSV_HEADER`
+pub fn to_csv(records: &[NodeStrategy]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for record in records {
+        for (hand, frequencies) in &record.hands {
+            for (action, frequency) in record.actions.iter().zip(frequencies) {
+                writeln!(csv, "{},{},{},{},{},{:.6}", record.path, record.player, record.board, hand, action, frequency).unwrap();
+            }
+        }
+    }
+    csv
+}
+
+/// Reads records written by `to_csv`. Rows of a node and board don't have to be next to each
+/// other, actions are in the order they first appear.
+pub fn from_csv(csv: &str) -> Result<Vec<NodeStrategy>, String> {
+    let mut lines = csv.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    match lines.next() {
+        Some((_, header)) if header.trim() == CSV_HEADER => {},
+        _ => return Err(format!("expected the header {}", CSV_HEADER)),
+    }
+
+    let mut records: Vec<NodeStrategy> = Vec::new();
+    let mut frequencies: Vec<BTreeMap<String, BTreeMap<Action, f64>>> = Vec::new();
+    for (i, line) in lines {
+        let error = |reason: &str| format!("line {}: {}", i + 1, reason);
+        let fields = line.split(',').map(str::trim).collect::<Vec<&str>>();
+        let [path, player, board, hand, action, frequency] = fields[..] else {
+            return Err(error("expected 6 fields"));
+        };
+        let player = player.parse::<PlayerId>().map_err(|e| error(&e.to_string()))?;
+        let action = action.parse::<Action>().map_err(|e| error(&e.to_string()))?;
+        let frequency = frequency.parse::<f64>().map_err(|e| error(&e.to_string()))?;
+
+        let r = match records.iter().position(|r| r.path == path && r.board == board) {
+            Some(r) => r,
+            None => {
+                records.push(NodeStrategy { path: path.to_string(), player, board: board.to_string(), actions: Vec::new(), hands: BTreeMap::new() });
+                frequencies.push(BTreeMap::new());
+                records.len() - 1
+            },
+        };
+        if records[r].player != player {
+            return Err(error("another player acts at this path"));
+        }
+        if !records[r].actions.contains(&action) {
+            records[r].actions.push(action);
+        }
+        frequencies[r].entry(hand.to_string()).or_default().insert(action, frequency);
+    }
+
+    for (record, hands) in records.iter_mut().zip(frequencies) {
+        for (hand, by_action) in hands {
+            let values = record.actions.iter()
+                .map(|a| by_action.get(a).copied().ok_or_else(|| format!("{} at {} on {} has no frequency for {}", hand, record.path, record.board, a)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            record.hands.insert(hand, values);
+        }
+    }
+    Ok(records)
+}
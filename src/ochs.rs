@@ -0,0 +1,182 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    card_abstraction::{BucketId, RoundBuckets},
+    clustering::{kmeans, Distance},
+    card_set::CardSet,
+    evaluator::HandRanking,
+    game::{DeckIndex, GameInfo},
+    hand_indexer::HandIndexer,
+    parallel::*,
+};
+
+use poker::Card;
+use itertools::Itertools;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+/// Opponent Cluster Hand Strength buckets, meant for the river. Opponent hole cards are grouped
+/// into clusters by preflop equity, a hand is described by its equity against each cluster on
+/// the current board and hands are bucketed with k-means over these equity vectors.
+#[derive(Serialize, Deserialize)]
+pub struct OchsBuckets {
+    preflop_indexer: HandIndexer,
+    /// Opponent cluster of each canonical preflop hand
+    opponent_clusters: Vec<BucketId>,
+    num_opponent_clusters: u32,
+    num_board_cards: u8,
+    /// Equity vector at the center of each bucket
+    centroids: Vec<Vec<f32>>,
+    #[serde(default)]
+    hand_ranking: HandRanking,
+}
+
+impl OchsBuckets {
+    /// Builds buckets for `round`. Preflop equities are estimated from `num_samples` random
+    /// runouts and the river clustering uses every canonical hand of the round, or
+    /// `num_samples` random hands if there are more. Every random choice is drawn from `rng`.
+    pub fn build<R: Rng + ?Sized>(game_info: &GameInfo, round: u8, num_opponent_clusters: u32, num_buckets: u32, num_samples: usize, rng: &mut R, cancel_token: &CancelToken) -> Result<OchsBuckets, Cancelled> {
+        let deck = game_info.generate_deck().collect::<Vec<Card>>();
+        let num_hole_cards = game_info.num_hole_cards();
+        let num_board_cards = game_info.total_board_cards(round);
+        let final_board_cards = game_info.total_board_cards(game_info.num_rounds() - 1) as usize;
+
+        let mut buckets = OchsBuckets {
+            preflop_indexer: HandIndexer::new(game_info.num_suits(), game_info.num_ranks(), &[num_hole_cards]),
+            opponent_clusters: Vec::new(),
+            num_opponent_clusters,
+            num_board_cards,
+            centroids: Vec::new(),
+            hand_ranking: game_info.hand_ranking(),
+        };
+
+        // Every hand gets its own generator so the parallel estimates don't depend on scheduling
+        let seeds = (0..buckets.preflop_indexer.round_size(0)).map(|_| rng.gen()).collect::<Vec<u64>>();
+        let preflop_equities = (0..buckets.preflop_indexer.round_size(0))
+            .into_par_iter()
+            .map(|i| {
+                cancel_token.check()?;
+                let hole_cards = buckets.preflop_indexer.unindex(0, i).unwrap();
+                let mut hand_rng = StdRng::seed_from_u64(seeds[i as usize]);
+                Ok(vec![preflop_equity(buckets.hand_ranking, &deck, &hole_cards, final_board_cards, num_samples, &mut hand_rng)])
+            })
+            .collect::<Result<Vec<Vec<f32>>, Cancelled>>()?;
+        let (preflop_centroids, preflop_clusters) = kmeans(&preflop_equities, num_opponent_clusters as usize, rng, cancel_token)?;
+
+        // Number clusters from weakest to strongest so cluster ids are stable between builds
+        let mut order = (0..preflop_centroids.len()).collect::<Vec<usize>>();
+        order.sort_by(|a, b| preflop_centroids[*a][0].total_cmp(&preflop_centroids[*b][0]));
+        let mut relabel = vec![0; order.len()];
+        for (label, cluster) in order.into_iter().enumerate() {
+            relabel[cluster] = label as BucketId;
+        }
+        buckets.opponent_clusters = preflop_clusters.into_iter().map(|c| relabel[c]).collect();
+        buckets.num_opponent_clusters = relabel.len() as u32;
+
+        let mut cards_per_round = vec![num_hole_cards];
+        if num_board_cards > 0 {
+            cards_per_round.push(num_board_cards);
+        }
+        let indexer = HandIndexer::new(game_info.num_suits(), game_info.num_ranks(), &cards_per_round);
+        let last_round = cards_per_round.len() - 1;
+
+        let hands = if indexer.round_size(last_round) <= num_samples as u64 {
+            (0..indexer.round_size(last_round))
+                .map(|i| indexer.unindex(last_round, i).unwrap())
+                .collect::<Vec<Vec<Card>>>()
+        } else {
+            (0..num_samples)
+                .map(|_| deck.choose_multiple(rng, (num_hole_cards + num_board_cards) as usize).copied().collect())
+                .collect()
+        };
+
+        let features = hands.par_iter()
+            .map(|cards| {
+                cancel_token.check()?;
+                let (hole_cards, board_cards) = cards.split_at(num_hole_cards as usize);
+                Ok(buckets.cluster_equities(&deck, hole_cards, board_cards))
+            })
+            .collect::<Result<Vec<Vec<f32>>, Cancelled>>()?;
+        buckets.centroids = kmeans(&features, num_buckets as usize, rng, cancel_token)?.0;
+
+        Ok(buckets)
+    }
+
+    pub fn num_buckets(&self) -> u32 {
+        self.centroids.len() as u32
+    }
+
+    pub fn num_opponent_clusters(&self) -> u32 {
+        self.num_opponent_clusters
+    }
+
+    /// Returns the probability of beating a random hand of each opponent cluster on the board,
+    /// counting ties as half
+    fn cluster_equities(&self, deck: &[Card], hole_cards: &[Card], board_cards: &[Card]) -> Vec<f32> {
+        let cards = CardSet::from_cards(hole_cards) | CardSet::from_cards(board_cards);
+        let evaluator = self.hand_ranking.evaluator();
+        let direction = self.hand_ranking.direction();
+        let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, board_cards));
+
+        let mut score = vec![0.; self.num_opponent_clusters as usize];
+        let mut total = vec![0; self.num_opponent_clusters as usize];
+        for opponent_hole_cards in cards.remaining(deck).into_iter().combinations(hole_cards.len()) {
+            let cluster = self.opponent_clusters[self.preflop_indexer.index_round(0, &opponent_hole_cards) as usize] as usize;
+            let opponent_rank = direction.orient(evaluator.evaluate_showdown(&opponent_hole_cards, board_cards));
+            if rank > opponent_rank {
+                score[cluster] += 1.;
+            } else if rank == opponent_rank {
+                score[cluster] += 0.5;
+            }
+            total[cluster] += 1;
+        }
+
+        // Clusters fully blocked by the board are treated as coin flips
+        score.into_iter()
+            .zip(total)
+            .map(|(s, t)| if t > 0 { s / t as f32 } else { 0.5 })
+            .collect()
+    }
+
+    fn deck(&self) -> Vec<Card> {
+        let deck = DeckIndex::new(self.preflop_indexer.num_suits(), self.preflop_indexer.num_ranks());
+        Card::generate_deck()
+            .filter(|c| deck.contains(*c))
+            .collect()
+    }
+}
+
+#[typetag::serde]
+impl RoundBuckets for OchsBuckets {
+    fn get_bucket(&self, board_cards: &[Card], hole_cards: &[Card]) -> BucketId {
+        let equities = self.cluster_equities(&self.deck(), hole_cards, &board_cards[..self.num_board_cards as usize]);
+        Distance::L2.nearest(&equities, &self.centroids) as BucketId
+    }
+
+    fn num_buckets(&self) -> BucketId {
+        self.centroids.len() as BucketId
+    }
+}
+
+/// Estimates the probability that `hole_cards` beat a random hand at showdown from
+/// `num_samples` random runouts
+fn preflop_equity<R: Rng + ?Sized>(hand_ranking: HandRanking, deck: &[Card], hole_cards: &[Card], num_board_cards: usize, num_samples: usize, rng: &mut R) -> f32 {
+    let evaluator = hand_ranking.evaluator();
+    let direction = hand_ranking.direction();
+    let remaining = CardSet::from_cards(hole_cards).remaining(deck);
+
+    let mut score = 0.;
+    for _ in 0..num_samples {
+        let cards = remaining.choose_multiple(rng, hole_cards.len() + num_board_cards).copied().collect::<Vec<Card>>();
+        let (opponent_hole_cards, board_cards) = cards.split_at(hole_cards.len());
+        let rank = direction.orient(evaluator.evaluate_showdown(hole_cards, board_cards));
+        let opponent_rank = direction.orient(evaluator.evaluate_showdown(opponent_hole_cards, board_cards));
+        if rank > opponent_rank {
+            score += 1.;
+        } else if rank == opponent_rank {
+            score += 0.5;
+        }
+    }
+
+    score / num_samples as f32
+}
@@ -0,0 +1,295 @@
+use super::{
+    evaluator::HandEvaluator,
+    game::{Action, BettingType, GameInfo, GameState, PlayerId},
+};
+
+use poker::Card;
+use itertools::Itertools;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Player id of chance nodes, same as OpenSpiel's `kChancePlayerId`
+pub const CHANCE_PLAYER: i32 = -1;
+/// Player id of terminal states, same as OpenSpiel's `kTerminalPlayerId`
+pub const TERMINAL_PLAYER: i32 = -4;
+
+/// Action ids of folding and calling, a raise to `r` has id `1 + r`
+pub const FOLD_ACTION: i64 = 0;
+pub const CALL_ACTION: i64 = 1;
+
+/// Converts an action to its OpenSpiel style action id
+pub fn action_id(action: Action) -> i64 {
+    match action {
+        Action::Fold => FOLD_ACTION,
+        Action::Call => CALL_ACTION,
+        Action::Raise(r) => 1 + r as i64,
+        Action::Discard(_) => panic!("draw games aren't supported"),
+    }
+}
+
+/// Converts an OpenSpiel style action id back to an action
+pub fn id_action(id: i64) -> Action {
+    match id {
+        FOLD_ACTION => Action::Fold,
+        CALL_ACTION => Action::Call,
+        id => Action::Raise((id - 1) as u32),
+    }
+}
+
+/// A poker game behind the `Game` interface of OpenSpiel, so algorithms written against that
+/// API can run on ungar's engine. Dealing is made of chance nodes dealing one card at a time,
+/// each player's hole cards first and then the board cards at the start of every round. Draw
+/// games, stud and multiple run outs aren't supported.
+pub struct OpenSpielGame {
+    game_info: Arc<GameInfo>,
+    evaluator: &'static dyn HandEvaluator,
+    deck: Vec<Card>,
+}
+
+impl OpenSpielGame {
+    pub fn new(game_info: GameInfo) -> OpenSpielGame {
+        assert!(!game_info.has_draws() && !game_info.is_stud(), "draw and stud games aren't supported");
+        assert!(game_info.num_run_outs() <= 1, "multiple run outs aren't supported");
+        OpenSpielGame {
+            evaluator: game_info.hand_ranking().evaluator(),
+            deck: game_info.generate_deck().collect(),
+            game_info: Arc::new(game_info),
+        }
+    }
+
+    pub fn game_info(&self) -> &GameInfo {
+        &self.game_info
+    }
+
+    pub fn new_initial_state(&self) -> OpenSpielState {
+        OpenSpielState {
+            game_info: self.game_info.clone(),
+            evaluator: self.evaluator,
+            deck: self.deck.clone(),
+            state: GameState::new(&self.game_info, 0),
+            hole_cards: vec![Vec::new(); self.game_info.num_players() as usize],
+            board_cards: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn num_players(&self) -> i32 {
+        self.game_info.num_players() as i32
+    }
+
+    /// Returns one more than the largest action id
+    pub fn num_distinct_actions(&self) -> i64 {
+        match self.game_info.betting_type() {
+            BettingType::Limit => (0..self.game_info.num_rounds()).map(|r| action_id(Action::Raise(self.game_info.raise_size(r)))).max().unwrap_or(CALL_ACTION) + 1,
+            BettingType::NoLimit | BettingType::SpreadLimit { .. } => action_id(Action::Raise(self.game_info.starting_stacks().iter().copied().max().unwrap_or(0))) + 1,
+        }
+    }
+
+    /// Returns the number of outcomes of a chance node, chance outcome ids index the deck
+    pub fn max_chance_outcomes(&self) -> i64 {
+        self.deck.len() as i64
+    }
+
+    /// Returns the largest loss of a player in chips
+    pub fn min_utility(&self) -> f64 {
+        -(self.game_info.starting_stacks().iter().copied().max().unwrap_or(0) as f64)
+    }
+
+    /// Returns the largest win of a player in chips
+    pub fn max_utility(&self) -> f64 {
+        let stacks = self.game_info.starting_stacks();
+        (stacks.iter().sum::<u32>() - stacks.iter().copied().min().unwrap_or(0)) as f64
+    }
+}
+
+/// A state of an `OpenSpielGame`, mirroring OpenSpiel's `State`. Actions are `i64` ids, see
+/// `action_id` for decisions and the deck position of the dealt card for chance nodes.
+#[derive(Clone)]
+pub struct OpenSpielState {
+    game_info: Arc<GameInfo>,
+    evaluator: &'static dyn HandEvaluator,
+    deck: Vec<Card>,
+    state: GameState,
+    hole_cards: Vec<Vec<Card>>,
+    board_cards: Vec<Card>,
+    /// Every action id applied so far including chance outcomes
+    history: Vec<i64>,
+}
+
+impl OpenSpielState {
+    pub fn game_state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn hole_cards(&self) -> &[Vec<Card>] {
+        &self.hole_cards
+    }
+
+    pub fn board_cards(&self) -> &[Card] {
+        &self.board_cards
+    }
+
+    pub fn history(&self) -> &[i64] {
+        &self.history
+    }
+
+    fn folded_out(&self) -> bool {
+        self.state.num_folded(&self.game_info) + 1 >= self.game_info.num_players()
+    }
+
+    /// Returns the player to be dealt the next hole card, if hole cards are still being dealt
+    fn next_hole_card_player(&self) -> Option<usize> {
+        let num_hole_cards = self.game_info.num_hole_cards() as usize;
+        self.hole_cards.iter().position(|cards| cards.len() < num_hole_cards)
+    }
+
+    /// Returns the board cards that should be out at this point of the hand
+    fn num_board_cards_needed(&self) -> usize {
+        if self.state.is_finished() && self.folded_out() {
+            self.board_cards.len()
+        } else {
+            self.game_info.total_board_cards(self.state.current_round()) as usize
+        }
+    }
+
+    pub fn is_chance_node(&self) -> bool {
+        self.next_hole_card_player().is_some() || self.board_cards.len() < self.num_board_cards_needed()
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.state.is_finished() && !self.is_chance_node()
+    }
+
+    /// Returns the acting player, or `CHANCE_PLAYER` and `TERMINAL_PLAYER`
+    pub fn current_player(&self) -> i32 {
+        if self.is_chance_node() {
+            CHANCE_PLAYER
+        } else if self.state.is_finished() {
+            TERMINAL_PLAYER
+        } else {
+            self.state.current_player().unwrap() as i32
+        }
+    }
+
+    fn dealt(&self, card: Card) -> bool {
+        self.board_cards.contains(&card) || self.hole_cards.iter().any(|cards| cards.contains(&card))
+    }
+
+    /// Returns the legal action ids in increasing order, at chance nodes the deck positions of
+    /// the cards that are left
+    pub fn legal_actions(&self) -> Vec<i64> {
+        if self.is_chance_node() {
+            return self.chance_outcomes().into_iter().map(|(id, _)| id).collect();
+        }
+        if self.state.is_finished() {
+            return Vec::new();
+        }
+
+        let player = self.state.current_player().unwrap();
+        let mut actions = [Action::Fold, Action::Call].into_iter()
+            .filter(|a| self.state.is_valid_action(&self.game_info, *a))
+            .collect::<Vec<Action>>();
+        if let Some((min_raise, max_raise)) = self.state.raise_bounds(&self.game_info, player) {
+            match self.game_info.betting_type() {
+                BettingType::Limit => actions.push(Action::Raise(self.game_info.raise_size(self.state.current_round()))),
+                BettingType::NoLimit | BettingType::SpreadLimit { .. } => actions.extend((min_raise..=max_raise).map(Action::Raise)),
+            }
+        }
+
+        actions.into_iter()
+            .filter(|a| self.state.is_valid_action(&self.game_info, *a))
+            .map(action_id)
+            .collect()
+    }
+
+    /// Returns the outcomes of a chance node with their probabilities, every card left is
+    /// equally likely
+    pub fn chance_outcomes(&self) -> Vec<(i64, f64)> {
+        let remaining = (0..self.deck.len())
+            .filter(|&i| !self.dealt(self.deck[i]))
+            .collect::<Vec<usize>>();
+        let probability = 1. / remaining.len() as f64;
+        remaining.into_iter().map(|i| (i as i64, probability)).collect()
+    }
+
+    pub fn apply_action(&mut self, id: i64) -> Result<(), &'static str> {
+        if self.is_chance_node() {
+            let card = *self.deck.get(id as usize).ok_or("chance outcome out of range")?;
+            if id < 0 || self.dealt(card) {
+                return Err("card was already dealt");
+            }
+            match self.next_hole_card_player() {
+                Some(player) => self.hole_cards[player].push(card),
+                None => self.board_cards.push(card),
+            }
+        } else {
+            if self.state.is_finished() {
+                return Err("cannot apply action to terminal state");
+            }
+            let action = id_action(id);
+            if !self.state.is_valid_action(&self.game_info, action) {
+                return Err("invalid action");
+            }
+            self.state = self.state.apply_action_no_cards(&self.game_info, action)?;
+        }
+
+        self.history.push(id);
+        Ok(())
+    }
+
+    /// Returns a new state with the action applied, like OpenSpiel's `Child`
+    pub fn child(&self, id: i64) -> Result<OpenSpielState, &'static str> {
+        let mut child = self.clone();
+        child.apply_action(id)?;
+        Ok(child)
+    }
+
+    pub fn action_to_string(&self, id: i64) -> String {
+        if self.is_chance_node() {
+            match self.deck.get(id as usize) {
+                Some(card) => format!("deal {}", card.rank_suit_string()),
+                None => format!("invalid chance outcome {}", id),
+            }
+        } else {
+            id_action(id).to_string()
+        }
+    }
+
+    /// Returns the chips won or lost by every player, zero until the state is terminal
+    pub fn returns(&self) -> Vec<f64> {
+        if !self.is_terminal() {
+            return vec![0.; self.game_info.num_players() as usize];
+        }
+
+        self.state.get_payouts(&self.game_info, self.evaluator, &self.board_cards, &self.hole_cards)
+            .into_iter()
+            .map(|v| v as f64)
+            .collect()
+    }
+
+    /// Returns everything `player` knows: their hole cards, the board and the betting, so two
+    /// states have the same string exactly when the player can't tell them apart
+    pub fn information_state_string(&self, player: PlayerId) -> String {
+        format!("{}[Private: {}]", self.observation_string(), self.hole_cards[player as usize].iter().map(|c| c.rank_suit_string()).join(""))
+    }
+
+    /// Returns the public part of the information state
+    pub fn observation_string(&self) -> String {
+        let spent = (0..self.game_info.num_players()).map(|p| self.state.player_spent(p)).join(" ");
+        format!("[Round {}][Spent: {}][Public: {}][Sequences: {}]",
+            self.state.current_round(),
+            spent,
+            self.board_cards.iter().map(|c| c.rank_suit_string()).join(""),
+            self.state.betting_string(&self.game_info))
+    }
+}
+
+impl fmt::Display for OpenSpielState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hole_cards = self.hole_cards.iter()
+            .map(|cards| cards.iter().map(|c| c.rank_suit_string()).join(""))
+            .join(" ");
+        write!(f, "{}[Hole: {}]", self.observation_string(), hole_cards)
+    }
+}
@@ -0,0 +1,189 @@
+use super::{
+    abstract_game::AbstractGame,
+    game::{Action, GameState, PlayerId},
+    history::HandHistory,
+    node::NodeId,
+    strategy::StrategyProfile,
+};
+
+use std::collections::BTreeMap;
+
+/// Kind of an action regardless of its size, so actions of different nodes can be pooled
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ActionKind {
+    Fold,
+    Call,
+    Raise,
+    Discard,
+}
+
+impl From<Action> for ActionKind {
+    fn from(action: Action) -> ActionKind {
+        match action {
+            Action::Fold => ActionKind::Fold,
+            Action::Call => ActionKind::Call,
+            Action::Raise(_) => ActionKind::Raise,
+            Action::Discard(_) => ActionKind::Discard,
+        }
+    }
+}
+
+/// Category of the situations a player acts in, every node of the same round with the same
+/// number of raises so far in the round shares it. Pools observations of nodes too rare to
+/// estimate on their own, like folding to a continuation bet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Situation {
+    pub round: u8,
+    pub num_raises: u8,
+}
+
+impl Situation {
+    pub fn new(state: &GameState) -> Situation {
+        Situation {
+            round: state.current_round(),
+            num_raises: state.num_raises(),
+        }
+    }
+}
+
+/// Pseudo-observations of the prior when none is set
+const DEFAULT_PRIOR_WEIGHT: f64 = 2.;
+
+/// Counts the actions each opponent took at every node of an abstract game and in every
+/// situation. Frequencies are smoothed with a Dirichlet prior worth `prior_weight`
+/// observations, node frequencies are pulled toward the frequencies of their situation which
+/// are pulled toward playing every kind of action equally, so estimates stay sensible after few
+/// observations and approach the observed frequencies as they grow.
+#[derive(Debug)]
+pub struct OpponentModel {
+    counts: BTreeMap<(PlayerId, NodeId), BTreeMap<Action, u32>>,
+    situation_counts: BTreeMap<(PlayerId, Situation), BTreeMap<ActionKind, u32>>,
+    prior_weight: f64,
+}
+
+impl Default for OpponentModel {
+    fn default() -> OpponentModel {
+        OpponentModel::new()
+    }
+}
+
+impl OpponentModel {
+    pub fn new() -> OpponentModel {
+        OpponentModel {
+            counts: BTreeMap::new(),
+            situation_counts: BTreeMap::new(),
+            prior_weight: DEFAULT_PRIOR_WEIGHT,
+        }
+    }
+
+    /// Sets how many observations the prior is worth, zero uses the raw frequencies
+    pub fn set_prior_weight(&mut self, prior_weight: f64) {
+        assert!(prior_weight >= 0., "prior weight can't be negative");
+        self.prior_weight = prior_weight;
+    }
+
+    /// Records `player` taking `action` at `node_id`, `state` is the state the action was taken in
+    pub fn observe(&mut self, player: PlayerId, node_id: NodeId, state: &GameState, action: Action) {
+        *self.counts.entry((player, node_id)).or_default().entry(action).or_insert(0) += 1;
+        *self.situation_counts.entry((player, Situation::new(state))).or_default().entry(action.into()).or_insert(0) += 1;
+    }
+
+    /// Records every action the player named `player_name` took in `history`. Actions are mapped
+    /// to the nodes of the profile's abstract game like the profile does when playing, actions
+    /// after the hand leaves the tree only count toward their situation.
+    pub fn observe_history(&mut self, profile: &StrategyProfile, history: &HandHistory, player_name: &str) -> Result<(), &'static str> {
+        let player = history.players.iter().position(|p| p == player_name).ok_or("player isn't in the hand")? as PlayerId;
+        let states = history.states()?;
+
+        for (state, (_, acting_player, action)) in states.iter().zip(history.actions.iter()) {
+            if *acting_player != player {
+                continue;
+            }
+            match profile.find_node(&history.game_info, state) {
+                Some((Some(node_id), abstract_state)) => {
+                    let abstract_action = profile.to_abstract_action(&history.game_info, state, &abstract_state, *action);
+                    self.observe(player, node_id, state, abstract_action);
+                },
+                _ => {
+                    *self.situation_counts.entry((player, Situation::new(state))).or_default().entry((*action).into()).or_insert(0) += 1;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many times `player` acted at `node_id`
+    pub fn num_observations(&self, player: PlayerId, node_id: NodeId) -> u32 {
+        self.counts.get(&(player, node_id)).map_or(0, |c| c.values().sum())
+    }
+
+    /// Returns the observed frequency of each action of `player` at `node_id`, or `None` if the
+    /// player was never seen acting there
+    pub fn action_frequencies(&self, player: PlayerId, node_id: NodeId) -> Option<BTreeMap<Action, f32>> {
+        let counts = self.counts.get(&(player, node_id))?;
+        let total = counts.values().sum::<u32>() as f32;
+        Some(counts.iter().map(|(a, c)| (*a, *c as f32 / total)).collect())
+    }
+
+    /// Returns the smoothed probability of `player` taking each of `actions` at `node_id`, whose
+    /// state is `state`. Without observations the prior is used, which splits the probability of
+    /// each kind of action in the situation evenly between the actions of that kind.
+    pub fn smoothed_frequencies(&self, player: PlayerId, node_id: NodeId, state: &GameState, actions: &[Action]) -> BTreeMap<Action, f64> {
+        let prior = self.situation_prior(player, Situation::new(state), actions);
+        let counts = self.counts.get(&(player, node_id));
+        let total = counts.map_or(0, |c| actions.iter().map(|a| c.get(a).copied().unwrap_or(0)).sum::<u32>()) as f64;
+        if total + self.prior_weight <= 0. {
+            return prior;
+        }
+
+        actions.iter()
+            .map(|a| {
+                let count = counts.and_then(|c| c.get(a)).copied().unwrap_or(0) as f64;
+                (*a, (count + self.prior_weight * prior[a]) / (total + self.prior_weight))
+            })
+            .collect()
+    }
+
+    /// Returns the smoothed frequencies of `player` at every node of the abstract game where they
+    /// act and something was observed at the node or its situation, ready for
+    /// `exploit::estimated_strategy`
+    pub fn estimated_frequencies(&self, player: PlayerId, abstract_game: &AbstractGame) -> BTreeMap<NodeId, BTreeMap<Action, f64>> {
+        abstract_game.nodes.iter()
+            .filter(|(_, node)| !node.state.is_finished() && node.state.current_player() == Ok(player))
+            .filter(|(node_id, node)| self.counts.contains_key(&(player, *node_id)) || self.situation_counts.contains_key(&(player, Situation::new(&node.state))))
+            .map(|(node_id, node)| (node_id, self.smoothed_frequencies(player, node_id, &node.state, &abstract_game.get_actions(&node.state))))
+            .collect()
+    }
+
+    /// Iterates over every observed player and node
+    pub fn observed_nodes(&self) -> impl Iterator<Item = (PlayerId, NodeId)> + '_ {
+        self.counts.keys().copied()
+    }
+
+    /// Returns the smoothed frequencies of each kind of action in the situation, split evenly
+    /// between the actions of that kind
+    fn situation_prior(&self, player: PlayerId, situation: Situation, actions: &[Action]) -> BTreeMap<Action, f64> {
+        let mut kinds: BTreeMap<ActionKind, u32> = BTreeMap::new();
+        for action in actions {
+            *kinds.entry((*action).into()).or_insert(0) += 1;
+        }
+
+        let counts = self.situation_counts.get(&(player, situation));
+        let count = |kind: &ActionKind| counts.and_then(|c| c.get(kind)).copied().unwrap_or(0) as f64;
+        let total = kinds.keys().map(count).sum::<f64>();
+        let uniform = 1. / kinds.len() as f64;
+
+        actions.iter()
+            .map(|a| {
+                let kind = ActionKind::from(*a);
+                let p = if total + self.prior_weight > 0. {
+                    (count(&kind) + self.prior_weight * uniform) / (total + self.prior_weight)
+                } else {
+                    uniform
+                };
+                (*a, p / kinds[&kind] as f64)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,142 @@
+use super::{
+    card_abstraction::BucketId,
+    node::NodeId,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+type Infoset = (NodeId, BucketId);
+
+/// Shard of a `PagedMap` that is in memory
+#[derive(Debug)]
+struct Shard<V> {
+    entries: BTreeMap<Infoset, V>,
+    /// Whether the entries changed since the shard was read from its file
+    dirty: bool,
+    last_used: u64,
+}
+
+/// Map from infosets to values split into shards kept in files of a directory. At most
+/// `max_loaded_shards` shards are in memory, when another one is needed the least recently used
+/// is written back to its file and dropped. Lets tables larger than memory be used at the cost
+/// of reading and writing shards.
+#[derive(Debug)]
+pub struct PagedMap<V> {
+    dir: PathBuf,
+    num_shards: usize,
+    max_loaded_shards: usize,
+    shards: BTreeMap<usize, Shard<V>>,
+    /// Incremented on every access, orders the shards by when they were last used
+    clock: u64,
+}
+
+impl<V: Serialize + DeserializeOwned> PagedMap<V> {
+    /// Creates an empty map in `dir`, shard files already in it are removed
+    pub fn new(dir: PathBuf, num_shards: usize, max_loaded_shards: usize) -> PagedMap<V> {
+        assert!(num_shards > 0 && max_loaded_shards > 0, "a paged map needs at least one shard in memory");
+        fs::create_dir_all(&dir).expect("failed to create shard directory");
+        for shard in 0..num_shards {
+            let path = dir.join(format!("shard_{}.bin", shard));
+            if path.exists() {
+                fs::remove_file(path).expect("failed to remove old shard");
+            }
+        }
+
+        PagedMap {
+            dir,
+            num_shards,
+            max_loaded_shards,
+            shards: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, infoset: &Infoset) -> Option<&V> {
+        let shard = self.load(self.shard_of(infoset));
+        shard.entries.get(infoset)
+    }
+
+    pub fn insert(&mut self, infoset: Infoset, value: V) {
+        let shard = self.load(self.shard_of(&infoset));
+        shard.entries.insert(infoset, value);
+        shard.dirty = true;
+    }
+
+    /// Returns the infosets of every shard, which pages every shard in once
+    pub fn keys(&mut self) -> Vec<Infoset> {
+        (0..self.num_shards)
+            .flat_map(|s| self.load(s).entries.keys().copied().collect::<Vec<Infoset>>())
+            .collect()
+    }
+
+    /// Applies `f` to every value, shard by shard
+    pub fn for_each_mut<F: FnMut(&Infoset, &mut V)>(&mut self, mut f: F) {
+        for s in 0..self.num_shards {
+            let shard = self.load(s);
+            for (infoset, value) in shard.entries.iter_mut() {
+                f(infoset, value);
+            }
+            shard.dirty = true;
+        }
+    }
+
+    /// Writes every changed shard in memory to its file
+    pub fn flush(&mut self) {
+        let shards = self.shards.keys().copied().collect::<Vec<usize>>();
+        for s in shards {
+            self.write(s);
+        }
+    }
+
+    fn shard_of(&self, infoset: &Infoset) -> usize {
+        // Neighbouring nodes and buckets are spread over the shards
+        let hash = (infoset.0 as u64).wrapping_mul(0x9e3779b97f4a7c15) ^ (infoset.1 as u64).wrapping_mul(0xc2b2ae3d27d4eb4f);
+        (hash % self.num_shards as u64) as usize
+    }
+
+    fn path(&self, shard: usize) -> PathBuf {
+        self.dir.join(format!("shard_{}.bin", shard))
+    }
+
+    /// Returns the shard, reading it and evicting the least recently used shard if needed
+    fn load(&mut self, shard: usize) -> &mut Shard<V> {
+        self.clock += 1;
+        if !self.shards.contains_key(&shard) {
+            if self.shards.len() >= self.max_loaded_shards {
+                let (&oldest, _) = self.shards.iter().min_by_key(|(_, s)| s.last_used).unwrap();
+                self.write(oldest);
+                self.shards.remove(&oldest);
+            }
+
+            let path = self.path(shard);
+            let entries = if path.exists() {
+                let mut r = BufReader::new(File::open(path).expect("failed to open shard"));
+                bincode::deserialize_from(&mut r).expect("failed to read shard")
+            } else {
+                BTreeMap::new()
+            };
+            self.shards.insert(shard, Shard { entries, dirty: false, last_used: 0 });
+        }
+
+        let clock = self.clock;
+        let loaded = self.shards.get_mut(&shard).unwrap();
+        loaded.last_used = clock;
+        loaded
+    }
+
+    fn write(&mut self, shard: usize) {
+        let path = self.path(shard);
+        if let Some(loaded) = self.shards.get_mut(&shard) {
+            if loaded.dirty {
+                let mut w = BufWriter::new(File::create(path).expect("failed to create shard"));
+                bincode::serialize_into(&mut w, &loaded.entries).expect("failed to write shard");
+                loaded.dirty = false;
+            }
+        }
+    }
+}
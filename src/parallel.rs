@@ -0,0 +1,39 @@
+// Parallel iterators of rayon, or sequential stand-ins with the same names on wasm32 where
+// there are no threads to run them on
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use rayon::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+pub use self::sequential::*;
+
+#[cfg(target_arch = "wasm32")]
+mod sequential {
+    pub trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    pub trait ParallelSlice<T> {
+        fn par_iter(&self) -> std::slice::Iter<'_, T>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_iter(&self) -> std::slice::Iter<'_, T> {
+            self.iter()
+        }
+    }
+
+    pub trait ParallelSliceMut<T> {
+        fn par_sort_unstable_by<F: FnMut(&T, &T) -> std::cmp::Ordering>(&mut self, compare: F);
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_sort_unstable_by<F: FnMut(&T, &T) -> std::cmp::Ordering>(&mut self, compare: F) {
+            self.sort_unstable_by(compare)
+        }
+    }
+}
@@ -1,130 +1,123 @@
 use super::{
-    abstract_game::AbstractGame,
-    strategy::Strategy,
-    game::Action,
+    card_set::format_cards,
+    game::{Action, BettingType, GameInfo, GameState, ParseActionError},
+    position::position,
+    strategy::StrategyProfile,
 };
 
-use poker::Evaluator;
-
-use std::io;
-
-pub fn play(abstract_game: &mut AbstractGame, strategy: Strategy) {
-    let evaluator = Evaluator::new();
-    let player: u8 = 0;
-    let mut total_payouts = vec![0; abstract_game.game_info.num_players() as usize];
-    let mut matches = 0;
-
-    loop {
-        println!("Do you want to play a game? [Y/n]");
-        let mut action_line: String = String::new();
-        match io::stdin().read_line(&mut action_line) {
-            Ok(_) => (),
-            Err(e) => continue
-        };
-        match action_line.trim() {
-            "y" => (),
-            "Y" => (),
-            "yes" => (),
-            _ => break
+use rand::thread_rng;
+
+use std::io::{self, Write};
+
+/// Plays hands against `profile` on stdin, the human takes every seat in turn. Actions are
+/// written like `f`, `c`, `r200` or `d 0 1` to discard the hole cards at those positions, in
+/// limit games a bare `r` raises the fixed amount. Bots play every other seat and the hands
+/// are shown at showdown.
+pub fn play(profile: &StrategyProfile) {
+    let game_info = &profile.abstract_game.game_info;
+    let evaluator = game_info.hand_ranking().evaluator();
+    let num_players = game_info.num_players();
+    let mut total_payout = 0;
+    let mut rng = thread_rng();
+
+    for hand_id in 0.. {
+        println!("Do you want to play a hand? [Y/n]");
+        match read_line() {
+            Some(line) if matches!(line.trim(), "" | "y" | "Y" | "yes") => (),
+            _ => break,
         };
 
-        let (hole_cards, board_cards) = abstract_game.game_info.deal_hole_cards_and_board_cards();
-        let mut board_cards_i = abstract_game.game_info.total_board_cards(0) as usize;
+        let player = (hand_id % num_players as u32) as u8;
+        let (hole_cards, board_cards) = game_info.deal_hole_cards_and_board_cards(&mut rng);
+        let mut state = GameState::new(game_info, hand_id);
+        println!("You are seat {} ({})\n", player, position(game_info, game_info.button(), player));
 
-        let mut node_id = abstract_game.nodes.get_root_node_id();
-        let mut current_node = abstract_game.nodes.get_node(node_id).unwrap();
-        let mut state = current_node.state.clone();
-        
         while !state.is_finished() {
-            println!("Chips in pot: {}", state.pot_total(&abstract_game.game_info));
-            for i in 0..abstract_game.game_info.num_players() {
-                println!("Player {} chips: {}", i, state.player_stack(i) - state.player_spent(i));
-            }
-            print!("Player hole cards: ");
-            for card in &hole_cards[player as usize] {
-                print!("{} ", card);
-            }
-            print!("\n");
-            print!("Board cards: ");
-            for i in 0..board_cards_i {
-                print!("{} ", board_cards[i]);
-            }
-            print!("\n");
-
-            let mut action;
-            if state.current_player().unwrap() == player {
-                loop {
-                    println!("Input action:");
-                    action = match get_player_action() {
-                        Ok(a) => a,
-                        Err(_) => { println!("Failed to read action"); continue },
-                    };
-
-                    if state.is_valid_action(&abstract_game.game_info, action) {
-                        break;
-                    } else {
-                        println!("Not a valid action");
-                    }
+            let board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+            let current_hole_cards = state.current_hole_cards(game_info, &hole_cards, &board_cards);
+            print!("{}", state);
+            println!("Your hole cards: {}", format_cards(&current_hole_cards[player as usize]));
+            println!("Board cards: {}", format_cards(board));
+
+            let acting = state.current_player().unwrap();
+            let action = if acting == player {
+                match read_action(game_info, &state) {
+                    Some(action) => action,
+                    None => return,
                 }
             } else {
-                let bucket_id = abstract_game.get_bucket(state.current_round(), &board_cards, &hole_cards[state.current_player().unwrap() as usize]);
-                action = strategy.sample(&abstract_game, node_id, bucket_id);
-            }
+                profile.sample_action(&mut rng, game_info, &state, &current_hole_cards[acting as usize], board, 1.)
+            };
 
-            println!("Player {}: {}\n", state.current_player().unwrap(), action);
-            state = state.apply_action_no_cards(&abstract_game.game_info, action).unwrap();
-            node_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, action);
-            current_node = abstract_game.nodes.get_node(node_id).unwrap();
+            println!("Seat {}: {}\n", acting, state.describe_action(game_info, action).unwrap());
+            state = state.apply_action_no_cards(game_info, action).unwrap();
         }
 
-        println!("Round finished");
-
-        for i in 0..abstract_game.game_info.num_players() {
-            let payout = state.get_payout(&abstract_game.game_info, &evaluator, &board_cards, &hole_cards, i);
-            total_payouts[i as usize] += payout;
-            print!("Player {} hole cards: ", i);
-            for card in &hole_cards[i as usize] {
-                print!("{} ", card);
+        let final_hole_cards = state.current_hole_cards(game_info, &hole_cards, &board_cards);
+        let in_hand = (0..num_players).filter(|p| !state.has_folded(*p)).collect::<Vec<u8>>();
+        print!("{}", state);
+        if in_hand.len() > 1 {
+            let board = &board_cards[..game_info.total_board_cards(state.current_round()) as usize];
+            println!("Showdown on {}", format_cards(board));
+            for p in &in_hand {
+                println!("Seat {} shows {}", p, format_cards(&final_hole_cards[*p as usize]));
             }
-            print!("\n");
-            println!("Player {} payout: {}", i, payout);
+        } else {
+            println!("Seat {} wins uncontested", in_hand[0]);
         }
 
-        matches += 1;
-
-        print!("\n");
-        println!("Total payouts after {} matches", matches);
-        for i in 0..abstract_game.game_info.num_players() {
-            println!("Player {} total payout: {}", i, total_payouts[i as usize]);
+        for p in 0..num_players {
+            let payout = state.get_payout(game_info, evaluator, &board_cards, &hole_cards, p);
+            if p == player {
+                total_payout += payout;
+            }
+            println!("Seat {} payout: {}", p, payout);
         }
-        print!("\n");
+        println!("\nYour total payout after {} hands: {}\n", hand_id + 1, total_payout);
     }
 }
 
-fn get_player_action() -> Result<Action, io::Error> {
-    let mut action_line: String = String::new();
-    io::stdin().read_line(&mut action_line)?;
 
-    let action_line: Vec<&str> = action_line.trim().split_whitespace().collect();
+/// Returns `None` once stdin is closed
+fn read_line() -> Option<String> {
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
+}
 
-    if action_line.len() < 1 {
-        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid action input"));
+/// Prompts until the human enters a valid action, returns `None` once stdin is closed
+fn read_action(game_info: &GameInfo, state: &GameState) -> Option<Action> {
+    loop {
+        print!("Your action: ");
+        let line = read_line()?;
+        match parse_action(game_info, state, &line) {
+            Ok(action) if state.is_valid_action(game_info, action) => return Some(action),
+            Ok(action) => println!("{} is not a valid action", action),
+            Err(e) => println!("{}", e),
+        }
     }
+}
 
-    match action_line[0] {
-        "f" => Ok(Action::Fold),
-        "c" => Ok(Action::Call),
-        "r" =>  {
-            if action_line.len() < 2 {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid action input"));
+fn parse_action(game_info: &GameInfo, state: &GameState, line: &str) -> Result<Action, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["d", positions @ ..] => {
+            // Positions of the hole cards to discard, nothing to stand pat
+            let mut mask: u8 = 0;
+            for position in positions {
+                match position.parse::<u8>() {
+                    Ok(i) if i < 8 => mask |= 1 << i,
+                    _ => return Err(format!("invalid hole card position {}", position)),
+                }
             }
-            let raise_size: u32 = match action_line[1].parse() {
-                Ok(i) => i,
-                Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid action input")),
-            };
-
-            Ok(Action::Raise(raise_size))
+            Ok(Action::Discard(mask))
+        },
+        ["r" | "b" | "raise" | "bet"] if game_info.betting_type() == BettingType::Limit => {
+            Ok(Action::Raise(game_info.raise_size(state.current_round())))
         },
-        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid action input")),
+        _ => line.trim().parse().map_err(|e: ParseActionError| e.to_string()),
     }
 }
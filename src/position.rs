@@ -0,0 +1,101 @@
+use super::game::{GameInfo, PlayerId};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+/// Name of a seat relative to the button, like the labels of range charts and hand histories
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Position {
+    /// First seat after the blinds and every seat between it and the lojack, `UnderTheGun(1)`
+    /// being UTG+1
+    UnderTheGun(u8),
+    Lojack,
+    Hijack,
+    Cutoff,
+    Button,
+    SmallBlind,
+    BigBlind,
+    Straddle,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::UnderTheGun(0) => write!(f, "UTG"),
+            Position::UnderTheGun(i) => write!(f, "UTG+{}", i),
+            Position::Lojack => write!(f, "LJ"),
+            Position::Hijack => write!(f, "HJ"),
+            Position::Cutoff => write!(f, "CO"),
+            Position::Button => write!(f, "BTN"),
+            Position::SmallBlind => write!(f, "SB"),
+            Position::BigBlind => write!(f, "BB"),
+            Position::Straddle => write!(f, "STR"),
+        }
+    }
+}
+
+/// Returns the seat `player` sits in when the game is dealt with its own button, see
+/// `GameInfo::button`, so the blinds and the order players act in can be read from `game_info`
+fn game_seat(game_info: &GameInfo, button: PlayerId, player: PlayerId) -> PlayerId {
+    let num_players = game_info.num_players();
+    (player + game_info.button() + num_players - button) % num_players
+}
+
+/// Returns the position of every player when `button` has the button, by seat. The seats the
+/// game posts blinds or straddles from are named after them, except the button heads up, and
+/// the seats left are named back from the button, with the first of at least three being UTG.
+pub fn positions(game_info: &GameInfo, button: PlayerId) -> Vec<Position> {
+    let num_players = game_info.num_players();
+    assert!(button < num_players, "button isn't a seat of the game");
+    let blinds = game_info.blinds();
+    let big_blind = blinds.iter().copied().max().unwrap_or(0);
+    // Antes are the same for everyone, so they don't make blinds
+    let has_blinds = blinds.iter().any(|b| *b != big_blind);
+
+    let mut positions = vec![None; num_players as usize];
+    positions[button as usize] = Some(Position::Button);
+    for player in (0..num_players).filter(|p| *p != button) {
+        let seat = game_seat(game_info, button, player) as usize;
+        positions[player as usize] = if game_info.straddles().get(seat).is_some_and(|s| *s > 0) {
+            Some(Position::Straddle)
+        } else if has_blinds && blinds[seat] == big_blind {
+            Some(Position::BigBlind)
+        } else if has_blinds && blinds[seat] > 0 {
+            Some(Position::SmallBlind)
+        } else {
+            None
+        };
+    }
+
+    // The unnamed seats in the order they sit after the button
+    let open_seats = (1..num_players)
+        .map(|i| (button + i) % num_players)
+        .filter(|p| positions[*p as usize].is_none())
+        .collect::<Vec<PlayerId>>();
+    for (i, player) in open_seats.iter().enumerate() {
+        let from_button = open_seats.len() - i;
+        positions[*player as usize] = Some(match from_button {
+            _ if i == 0 && open_seats.len() >= 3 => Position::UnderTheGun(0),
+            1 => Position::Cutoff,
+            2 => Position::Hijack,
+            3 => Position::Lojack,
+            _ => Position::UnderTheGun(i as u8),
+        });
+    }
+
+    positions.into_iter().map(|p| p.unwrap()).collect()
+}
+
+/// Returns the position of `player` when `button` has the button, see `positions`
+pub fn position(game_info: &GameInfo, button: PlayerId, player: PlayerId) -> Position {
+    positions(game_info, button)[player as usize]
+}
+
+/// Returns every player in the order they first act in `round` when `button` has the button
+pub fn action_order(game_info: &GameInfo, button: PlayerId, round: u8) -> Vec<PlayerId> {
+    let num_players = game_info.num_players();
+    assert!(button < num_players, "button isn't a seat of the game");
+    let first_player = (game_info.first_player(round) + button + num_players - game_info.button()) % num_players;
+    (0..num_players).map(|i| (first_player + i) % num_players).collect()
+}
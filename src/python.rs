@@ -0,0 +1,284 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    best_response,
+    cancel::CancelToken,
+    card_abstraction::CardAbstraction,
+    card_set,
+    cfr::{CFRConfig, CFREngine},
+    config::ConfigError,
+    evaluator::HandRanking,
+    game::{self, Action},
+    range::{Combo, Range},
+    showdown,
+    strategy::{self, Strategy, StrategyMetadata},
+};
+
+use poker::Card;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use std::path::Path;
+
+fn parse_cards(cards: &str) -> PyResult<Vec<Card>> {
+    card_set::parse_cards(cards).map_err(|e| PyValueError::new_err(format!("{} in \"{}\"", e, cards)))
+}
+
+fn parse_action(action: &str) -> PyResult<Action> {
+    action.parse().map_err(|e: game::ParseActionError| PyValueError::new_err(e.to_string()))
+}
+
+fn load_abstract_game(game_config: &str, action_abstraction_config: &str, card_abstraction_config: &str, nodes_path: Option<&str>) -> PyResult<AbstractGame> {
+    let config_error = |e: ConfigError| PyValueError::new_err(e.to_string());
+    let game_info = game::GameInfo::load_game_info(Path::new(game_config)).map_err(config_error)?;
+    let action_abstraction = ActionAbstraction::from_config(Path::new(action_abstraction_config)).map_err(config_error)?;
+    let card_abstraction = CardAbstraction::from_config(Path::new(card_abstraction_config)).map_err(config_error)?;
+    Ok(match nodes_path {
+        Some(path) => AbstractGame::load_nodes(game_info, Path::new(path), action_abstraction, card_abstraction),
+        None => {
+            let state = game::GameState::new(&game_info, 0);
+            AbstractGame::new(game_info, state, action_abstraction, card_abstraction)
+        },
+    })
+}
+
+#[pyclass(name = "GameInfo")]
+#[derive(Clone)]
+pub struct GameInfo(game::GameInfo);
+
+#[pymethods]
+impl GameInfo {
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<GameInfo> {
+        game::GameInfo::load_game_info(Path::new(path))
+            .map(GameInfo)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    #[getter]
+    fn num_players(&self) -> u8 {
+        self.0.num_players()
+    }
+
+    #[getter]
+    fn num_rounds(&self) -> u8 {
+        self.0.num_rounds()
+    }
+
+    #[getter]
+    fn starting_stacks(&self) -> Vec<u32> {
+        self.0.starting_stacks().to_vec()
+    }
+
+    #[getter]
+    fn blinds(&self) -> Vec<u32> {
+        self.0.blinds().to_vec()
+    }
+
+    /// Total board cards dealt by the end of `round`
+    fn total_board_cards(&self, round: u8) -> u8 {
+        self.0.total_board_cards(round)
+    }
+}
+
+#[pyclass(name = "GameState")]
+#[derive(Clone)]
+pub struct GameState(game::GameState);
+
+#[pymethods]
+impl GameState {
+    #[new]
+    #[pyo3(signature = (game_info, hand_id=0))]
+    fn new(game_info: &GameInfo, hand_id: u32) -> GameState {
+        GameState(game::GameState::new(&game_info.0, hand_id))
+    }
+
+    /// Builds a state from a betting string like `r200c/cr400`
+    #[staticmethod]
+    fn from_betting_string(game_info: &GameInfo, betting: &str) -> PyResult<GameState> {
+        game::GameState::from_betting_string(&game_info.0, 0, betting)
+            .map(GameState)
+            .map_err(PyValueError::new_err)
+    }
+
+    /// Returns the state after `action`, written like "f", "c" or "r200"
+    fn apply_action(&self, game_info: &GameInfo, action: &str) -> PyResult<GameState> {
+        self.0.apply_action_no_cards(&game_info.0, parse_action(action)?)
+            .map(GameState)
+            .map_err(PyValueError::new_err)
+    }
+
+    fn is_valid_action(&self, game_info: &GameInfo, action: &str) -> PyResult<bool> {
+        Ok(self.0.is_valid_action(&game_info.0, parse_action(action)?))
+    }
+
+    /// Player to act, `None` once the hand is finished
+    #[getter]
+    fn current_player(&self) -> Option<u8> {
+        self.0.current_player().ok()
+    }
+
+    #[getter]
+    fn current_round(&self) -> u8 {
+        self.0.current_round()
+    }
+
+    #[getter]
+    fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+
+    fn pot(&self, game_info: &GameInfo) -> u32 {
+        self.0.pot_total(&game_info.0)
+    }
+
+    fn player_spent(&self, player: u8) -> u32 {
+        self.0.player_spent(player)
+    }
+
+    fn amount_to_call(&self, player: u8) -> u32 {
+        self.0.amount_to_call(player)
+    }
+
+    fn pot_odds(&self, game_info: &GameInfo, player: u8) -> f64 {
+        self.0.pot_odds(&game_info.0, player)
+    }
+
+    fn effective_stack(&self, game_info: &GameInfo, player: u8) -> u32 {
+        self.0.effective_stack(&game_info.0, player)
+    }
+
+    /// Smallest and largest amounts `player` could raise to, `None` if they can't raise
+    fn raise_bounds(&self, game_info: &GameInfo, player: u8) -> Option<(u32, u32)> {
+        self.0.raise_bounds(&game_info.0, player)
+    }
+
+    fn betting_string(&self, game_info: &GameInfo) -> String {
+        self.0.betting_string(&game_info.0)
+    }
+
+    /// Every action so far as `(round, player, action)`
+    fn history(&self) -> Vec<(u8, u8, String)> {
+        self.0.full_history().map(|(r, p, a)| (r, p, a.to_string())).collect()
+    }
+}
+
+/// A trained strategy with the abstract game it was trained in
+#[pyclass(name = "StrategyProfile", unsendable)]
+pub struct StrategyProfile(strategy::StrategyProfile);
+
+#[pymethods]
+impl StrategyProfile {
+    #[staticmethod]
+    fn load(game_config: &str, action_abstraction_config: &str, card_abstraction_config: &str, strategy_path: &str, nodes_path: &str) -> PyResult<StrategyProfile> {
+        let abstract_game = load_abstract_game(game_config, action_abstraction_config, card_abstraction_config, Some(nodes_path))?;
+        let strategy = Strategy::from_file_checked(Path::new(strategy_path), &abstract_game).map_err(PyValueError::new_err)?;
+        Ok(StrategyProfile(strategy::StrategyProfile::new(abstract_game, strategy)))
+    }
+
+    /// Returns `(action, probability)` of every action the current player of `state` takes
+    /// holding `hole_cards`
+    fn action_probabilities(&self, game_info: &GameInfo, state: &GameState, hole_cards: &str, board_cards: &str) -> PyResult<Vec<(String, f64)>> {
+        let hole_cards = parse_cards(hole_cards)?;
+        let board_cards = parse_cards(board_cards)?;
+        Ok(self.0.action_probabilities(&game_info.0, &state.0, &hole_cards, &board_cards)
+            .into_iter()
+            .map(|(a, p)| (a.to_string(), p))
+            .collect())
+    }
+
+    /// Samples the action the current player of `state` takes holding `hole_cards`. A
+    /// `temperature` of 1 plays the strategy as is and 0 its most likely action, passing a
+    /// `seed` makes the choice reproducible.
+    #[pyo3(signature = (game_info, state, hole_cards, board_cards, temperature=1., seed=None))]
+    fn sample_action(&self, game_info: &GameInfo, state: &GameState, hole_cards: &str, board_cards: &str, temperature: f64, seed: Option<u64>) -> PyResult<String> {
+        if temperature < 0. {
+            return Err(PyValueError::new_err("temperature can't be negative"));
+        }
+        let hole_cards = parse_cards(hole_cards)?;
+        let board_cards = parse_cards(board_cards)?;
+        let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        Ok(self.0.sample_action(&mut rng, &game_info.0, &state.0, &hole_cards, &board_cards, temperature).to_string())
+    }
+
+    /// Computes the exploitability of the strategy in its abstract game
+    fn exploitability(&mut self) -> PyResult<f64> {
+        best_response::exploitability(&mut self.0.abstract_game, &self.0.strategy, &CancelToken::new())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    fn save(&self, strategy_path: &str, nodes_path: &str) {
+        // How the strategy was trained isn't known once it's loaded
+        self.0.strategy.save(Path::new(strategy_path), &StrategyMetadata::new(&self.0.abstract_game, 0, ""));
+        self.0.abstract_game.nodes.save(Path::new(nodes_path));
+    }
+}
+
+/// Trains a strategy with MCCFR with pruning from config files, the arguments after the configs
+/// are those of `CFREngine::mccfr_p`
+#[pyfunction]
+#[pyo3(signature = (game_config, action_abstraction_config, card_abstraction_config, cfr_config, iterations, strategy_interval=20, prune_threshold=400, lcfr_threshold=100000, discount_interval=2500))]
+#[allow(clippy::too_many_arguments)]
+fn train(game_config: &str, action_abstraction_config: &str, card_abstraction_config: &str, cfr_config: &str, iterations: u32, strategy_interval: u32, prune_threshold: u32, lcfr_threshold: u32, discount_interval: u32) -> PyResult<StrategyProfile> {
+    let abstract_game = load_abstract_game(game_config, action_abstraction_config, card_abstraction_config, None)?;
+    let mut engine = CFREngine::new(abstract_game, CFRConfig::from_config(Path::new(cfr_config)));
+    engine.mccfr_p(iterations, strategy_interval, prune_threshold, lcfr_threshold, discount_interval)
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+
+    let (abstract_game, strategy) = engine.into_parts();
+    Ok(StrategyProfile(strategy::StrategyProfile::new(abstract_game, strategy)))
+}
+
+/// Returns the probability two hole cards beat a range like `"QQ+, AKs"` on `board`, counting
+/// ties as half, over every run-out to five board cards with the standard ranking
+#[pyfunction]
+fn equity(hole_cards: &str, board_cards: &str, opponent_range: &str) -> PyResult<f64> {
+    let cards = parse_cards(hole_cards)?;
+    if cards.len() != 2 {
+        return Err(PyValueError::new_err("equity needs two hole cards"));
+    }
+    let board = parse_cards(board_cards)?;
+    if board.len() > 5 {
+        return Err(PyValueError::new_err("board can have at most five cards"));
+    }
+    let range = opponent_range.parse::<Range>().map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+    let ranking = HandRanking::Standard;
+    Ok(showdown::equities(ranking.evaluator(), ranking.direction(), &board, 5, &[Combo::new(cards[0], cards[1])], &range)[0])
+}
+
+/// Returns the fraction of the run-outs to the next street whose equity against a range falls in
+/// each of `num_bins` equal bins from 0 to 1, dealing a complete board unless `next_board_cards` is given
+#[pyfunction]
+#[pyo3(signature = (hole_cards, board_cards, opponent_range, num_bins=10, next_board_cards=None))]
+fn equity_histogram(hole_cards: &str, board_cards: &str, opponent_range: &str, num_bins: usize, next_board_cards: Option<usize>) -> PyResult<Vec<f64>> {
+    let cards = parse_cards(hole_cards)?;
+    if cards.len() != 2 {
+        return Err(PyValueError::new_err("equity histogram needs two hole cards"));
+    }
+    let board = parse_cards(board_cards)?;
+    let next_board_cards = next_board_cards.unwrap_or(5);
+    if board.len() > next_board_cards || next_board_cards > 5 {
+        return Err(PyValueError::new_err("boards have to grow towards five cards"));
+    }
+    if num_bins == 0 {
+        return Err(PyValueError::new_err("need at least one bin"));
+    }
+    let range = opponent_range.parse::<Range>().map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+    let ranking = HandRanking::Standard;
+    Ok(showdown::equity_histogram(ranking.evaluator(), ranking.direction(), &board, next_board_cards, 5, &Combo::new(cards[0], cards[1]), &range, num_bins))
+}
+
+#[pymodule]
+fn ungar(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<GameInfo>()?;
+    m.add_class::<GameState>()?;
+    m.add_class::<StrategyProfile>()?;
+    m.add_function(wrap_pyfunction!(train, m)?)?;
+    m.add_function(wrap_pyfunction!(equity, m)?)?;
+    m.add_function(wrap_pyfunction!(equity_histogram, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use super::card_set::CardSet;
+
+use poker::{Card, Rank, Suit};
+use variter::VarIter;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Index of a card in a standard 52 card deck
+pub(crate) fn card_index(card: Card) -> u8 {
+    card.rank() as u8 * Suit::ALL_VARIANTS.len() as u8 + card.suit() as u8
+}
+
+/// Two hole cards, stored with the higher card first
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Combo(Card, Card);
+
+impl Combo {
+    pub fn new(a: Card, b: Card) -> Combo {
+        if card_index(a) >= card_index(b) {
+            Combo(a, b)
+        } else {
+            Combo(b, a)
+        }
+    }
+
+    pub fn cards(&self) -> [Card; 2] {
+        [self.0, self.1]
+    }
+
+    pub fn card_set(&self) -> CardSet {
+        CardSet::from_card(self.0) | CardSet::from_card(self.1)
+    }
+
+    /// Returns if the combo shares a card with `cards`
+    pub fn is_blocked_by(&self, cards: &[Card]) -> bool {
+        self.is_blocked_by_set(CardSet::from_cards(cards))
+    }
+
+    /// Returns if the combo shares a card with `cards`, without building the mask for every combo
+    /// when checking many against the same cards
+    pub fn is_blocked_by_set(&self, cards: CardSet) -> bool {
+        self.card_set().intersects(cards)
+    }
+}
+
+impl Ord for Combo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (card_index(self.0), card_index(self.1)).cmp(&(card_index(other.0), card_index(other.1)))
+    }
+}
+
+impl PartialOrd for Combo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Combo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.0.rank_suit_string(), self.1.rank_suit_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRangeError {
+    token: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid range token \"{}\": {}", self.token, self.reason)
+    }
+}
+
+impl Error for ParseRangeError {}
+
+/// Whether a non-pair hand is suited, offsuit or either
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Suitedness {
+    Suited,
+    Offsuit,
+    Any,
+}
+
+/// Represents weighted hole card combos, weights are in [0, 1]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Range {
+    combos: BTreeMap<Combo, f64>,
+}
+
+impl Range {
+    pub fn new() -> Range {
+        Range { combos: BTreeMap::new() }
+    }
+
+    /// Returns a range containing every combo of the 52 card deck at full weight
+    pub fn full() -> Range {
+        let deck = Card::generate_deck().collect::<Vec<Card>>();
+        let mut range = Range::new();
+        for (i, a) in deck.iter().enumerate() {
+            for b in &deck[i + 1..] {
+                range.insert(Combo::new(*a, *b), 1.);
+            }
+        }
+        range
+    }
+
+    /// Sets the weight of a combo, combos with non positive weight are removed
+    pub fn insert(&mut self, combo: Combo, weight: f64) {
+        if weight > 0. {
+            self.combos.insert(combo, weight.min(1.));
+        } else {
+            self.combos.remove(&combo);
+        }
+    }
+
+    pub fn weight(&self, combo: &Combo) -> f64 {
+        *self.combos.get(combo).unwrap_or(&0.)
+    }
+
+    pub fn contains(&self, combo: &Combo) -> bool {
+        self.combos.contains_key(combo)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Combo, &f64)> {
+        self.combos.iter()
+    }
+
+    /// Returns number of combos with non zero weight
+    pub fn len(&self) -> usize {
+        self.combos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.combos.is_empty()
+    }
+
+    /// Returns sum of weights over all combos
+    pub fn total_weight(&self) -> f64 {
+        self.combos.values().sum()
+    }
+
+    /// Returns a range with every combo of both ranges, keeping the larger weight of combos in both
+    pub fn union(&self, other: &Range) -> Range {
+        let mut range = self.clone();
+        for (combo, weight) in other.iter() {
+            if *weight > range.weight(combo) {
+                range.insert(*combo, *weight);
+            }
+        }
+        range
+    }
+
+    /// Returns a range without the combos in `other`
+    pub fn difference(&self, other: &Range) -> Range {
+        Range {
+            combos: self.combos.iter()
+                .filter(|(combo, _)| !other.contains(combo))
+                .map(|(combo, weight)| (*combo, *weight))
+                .collect(),
+        }
+    }
+
+    /// Removes combos that share a card with `cards`, usually the board
+    pub fn remove_blocked(&mut self, cards: &[Card]) {
+        let cards = CardSet::from_cards(cards);
+        self.combos.retain(|combo, _| !combo.is_blocked_by_set(cards));
+    }
+
+    /// Returns a copy of the range without combos blocked by `cards`
+    pub fn without_blocked(&self, cards: &[Card]) -> Range {
+        let mut range = self.clone();
+        range.remove_blocked(cards);
+        range
+    }
+
+    fn add_hand(&mut self, high: Rank, low: Rank, suitedness: Suitedness, weight: f64) {
+        for (i, &s1) in Suit::ALL_VARIANTS.iter().enumerate() {
+            for (j, &s2) in Suit::ALL_VARIANTS.iter().enumerate() {
+                let valid = if high == low {
+                    i < j
+                } else {
+                    match suitedness {
+                        Suitedness::Suited => i == j,
+                        Suitedness::Offsuit => i != j,
+                        Suitedness::Any => true,
+                    }
+                };
+
+                if valid {
+                    self.insert(Combo::new(Card::new(high, s1), Card::new(low, s2)), weight);
+                }
+            }
+        }
+    }
+
+    /// Parses a single comma separated token like "AKs", "99+", "A5s-A2s", "AhKh" or "40% KQo"
+    fn add_token(&mut self, token: &str) -> Result<(), ParseRangeError> {
+        let error = |reason| ParseRangeError { token: token.to_string(), reason };
+
+        let (weight, hand) = match token.split_once('%') {
+            Some((percent, hand)) => {
+                let percent: f64 = percent.trim().parse().map_err(|_| error("invalid weight"))?;
+                if !(0. ..=100.).contains(&percent) {
+                    return Err(error("weight must be between 0% and 100%"));
+                }
+                (percent / 100., hand.trim())
+            },
+            None => (1., token),
+        };
+
+        let chars = hand.chars().collect::<Vec<char>>();
+
+        // Specific combo, eg "AhKh"
+        if chars.len() == 4 && chars[1].is_ascii_lowercase() && chars[3].is_ascii_lowercase() {
+            let a = Card::try_from_chars(chars[0], chars[1]).map_err(|_| error("invalid card"))?;
+            let b = Card::try_from_chars(chars[2], chars[3]).map_err(|_| error("invalid card"))?;
+            if a == b {
+                return Err(error("combo uses the same card twice"));
+            }
+            self.insert(Combo::new(a, b), weight);
+            return Ok(());
+        }
+
+        let (first, last) = match hand.split_once('-') {
+            Some((first, last)) => (first, Some(last)),
+            None => (hand, None),
+        };
+
+        let (first, plus) = match first.strip_suffix('+') {
+            Some(first) => (first, true),
+            None => (first, false),
+        };
+
+        let (high, low, suitedness) = parse_hand(first).ok_or_else(|| error("invalid hand"))?;
+
+        match (last, plus) {
+            (Some(_), true) => Err(error("cannot combine '+' and '-'")),
+            (None, false) => {
+                self.add_hand(high, low, suitedness, weight);
+                Ok(())
+            },
+            (None, true) => {
+                if high == low {
+                    // "99+" means every pair from 99 to AA
+                    for rank in Rank::ALL_VARIANTS.iter().filter(|r| **r >= high) {
+                        self.add_hand(*rank, *rank, suitedness, weight);
+                    }
+                } else {
+                    // "A5s+" means every kicker from 5 up to one below the high card
+                    for rank in Rank::ALL_VARIANTS.iter().filter(|r| **r >= low && **r < high) {
+                        self.add_hand(high, *rank, suitedness, weight);
+                    }
+                }
+                Ok(())
+            },
+            (Some(last), false) => {
+                let (last_high, last_low, last_suitedness) = parse_hand(last).ok_or_else(|| error("invalid hand"))?;
+                if last_suitedness != suitedness {
+                    return Err(error("both ends of a span must have the same suitedness"));
+                }
+
+                if high == low && last_high == last_low {
+                    // "99-66"
+                    let (bottom, top) = if high <= last_high { (high, last_high) } else { (last_high, high) };
+                    for rank in Rank::ALL_VARIANTS.iter().filter(|r| **r >= bottom && **r <= top) {
+                        self.add_hand(*rank, *rank, suitedness, weight);
+                    }
+                } else if high == last_high && high != low && last_high != last_low {
+                    // "A5s-A2s"
+                    let (bottom, top) = if low <= last_low { (low, last_low) } else { (last_low, low) };
+                    for rank in Rank::ALL_VARIANTS.iter().filter(|r| **r >= bottom && **r <= top) {
+                        self.add_hand(high, *rank, suitedness, weight);
+                    }
+                } else {
+                    return Err(error("span must be between pairs or hands with the same high card"));
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Parses a hand class like "AK", "AKs", "AKo" or "99" into (high rank, low rank, suitedness)
+fn parse_hand(hand: &str) -> Option<(Rank, Rank, Suitedness)> {
+    let chars = hand.trim().chars().collect::<Vec<char>>();
+    if chars.len() < 2 || chars.len() > 3 {
+        return None;
+    }
+
+    let a = Rank::try_from(chars[0]).ok()?;
+    let b = Rank::try_from(chars[1]).ok()?;
+    let suitedness = match chars.get(2) {
+        None => Suitedness::Any,
+        Some('s') if a != b => Suitedness::Suited,
+        Some('o') if a != b => Suitedness::Offsuit,
+        _ => return None,
+    };
+
+    Some((a.max(b), a.min(b), suitedness))
+}
+
+impl FromStr for Range {
+    type Err = ParseRangeError;
+
+    /// Parses standard range notation, eg "AKs, 99+, A5s-A2s, 40% KQo"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut range = Range::new();
+        for token in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            range.add_token(token)?;
+        }
+        Ok(range)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (combo, weight) in self.combos.iter().rev() {
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+
+            if *weight < 1. {
+                write!(f, "{}% ", weight * 100.)?;
+            }
+            write!(f, "{}", combo)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use super::{
+    game::{format_betting, Action, GameInfo, GameState, PlayerId},
+    position::{position, Position},
+    strategy::StrategyProfile,
+};
+
+use poker::{Card, Rank};
+use itertools::Itertools;
+use serde::Serialize;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const CELL_SIZE: usize = 40;
+
+/// Preflop action frequencies of one seat for every starting hand class, laid out as the usual
+/// grid with pairs on the diagonal, suited hands above it and offsuit hands below
+#[derive(Clone, Debug, Serialize)]
+pub struct RangeChart {
+    /// Seat whose strategy the chart shows
+    pub seat: PlayerId,
+    pub position: Position,
+    /// Betting before the seat acts, see `format_betting`
+    pub betting: String,
+    pub actions: Vec<Action>,
+    /// Hand class of every cell, rows and columns go from the highest rank down
+    pub hands: Vec<Vec<String>>,
+    /// Frequency of each action in every cell, in the order of `actions`. Cells without any
+    /// combo in the deck are all zero.
+    pub frequencies: Vec<Vec<Vec<f64>>>,
+}
+
+impl RangeChart {
+    /// Builds the chart of the player to act in `state`, every combo of a hand class counts the
+    /// same
+    pub fn new(profile: &StrategyProfile, game_info: &GameInfo, state: &GameState) -> RangeChart {
+        assert!(game_info.num_hole_cards() == 2 && !game_info.has_draws() && !game_info.is_stud(), "range charts need a hold'em like game");
+        assert!(state.current_round() == 0, "range charts show preflop strategies");
+        let seat = state.current_player().expect("no player to act");
+        let ranks = game_info.ranks().iter().rev().copied().collect::<Vec<Rank>>();
+        let deck = game_info.generate_deck().collect::<Vec<Card>>();
+
+        let mut totals = vec![vec![BTreeMap::new(); ranks.len()]; ranks.len()];
+        let mut num_combos = vec![vec![0; ranks.len()]; ranks.len()];
+        for (a, b) in deck.iter().tuple_combinations() {
+            let (high, low) = if a.rank() >= b.rank() { (a, b) } else { (b, a) };
+            let high_i = ranks.iter().position(|r| *r == high.rank()).unwrap();
+            let low_i = ranks.iter().position(|r| *r == low.rank()).unwrap();
+            let (row, col) = if high.suit() == low.suit() { (high_i, low_i) } else { (low_i, high_i) };
+
+            for (action, p) in profile.action_probabilities(game_info, state, &[*high, *low], &[]) {
+                *totals[row][col].entry(action).or_insert(0.) += p;
+            }
+            num_combos[row][col] += 1;
+        }
+
+        let actions = totals.iter()
+            .flatten()
+            .flat_map(|t| t.keys().copied())
+            .sorted()
+            .dedup()
+            .collect::<Vec<Action>>();
+        let frequencies = totals.iter()
+            .zip(num_combos.iter())
+            .map(|(row, combos)| row.iter()
+                .zip(combos.iter())
+                .map(|(t, n)| actions.iter().map(|a| t.get(a).map_or(0., |p| p / *n as f64)).collect())
+                .collect())
+            .collect();
+        let hands = (0..ranks.len())
+            .map(|row| (0..ranks.len())
+                .map(|col| match row.cmp(&col) {
+                    Ordering::Equal => format!("{}{}", ranks[row], ranks[col]),
+                    Ordering::Less => format!("{}{}s", ranks[row], ranks[col]),
+                    Ordering::Greater => format!("{}{}o", ranks[col], ranks[row]),
+                })
+                .collect())
+            .collect();
+
+        let betting = (0..=state.current_round())
+            .map(|r| state.actions_in_round(r).map(|(_, a)| a).collect())
+            .collect::<Vec<Vec<Action>>>();
+        RangeChart {
+            seat,
+            position: position(game_info, game_info.button(), seat),
+            betting: format_betting(game_info, &betting),
+            actions,
+            hands,
+            frequencies,
+        }
+    }
+
+    /// Builds the opening chart of every seat, the strategy when every player before it folded.
+    /// Seats that never act after everyone folds, like the big blind, have no chart.
+    pub fn opening_charts(profile: &StrategyProfile, game_info: &GameInfo) -> Vec<RangeChart> {
+        let mut charts = Vec::new();
+        let mut state = GameState::new(game_info, 0);
+        while !state.is_finished() && state.current_round() == 0 {
+            charts.push(RangeChart::new(profile, game_info, &state));
+            state = state.apply_action_no_cards(game_info, Action::Fold).unwrap();
+        }
+        charts
+    }
+
+    /// Writes the chart as CSV with a row per hand class and a column per action
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        writeln!(csv, "hand,{}", self.actions.iter().join(",")).unwrap();
+        for (hand, frequencies) in self.hands.iter().flatten().zip(self.frequencies.iter().flatten()) {
+            writeln!(csv, "{},{}", hand, frequencies.iter().map(|f| format!("{:.4}", f)).join(",")).unwrap();
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Draws the chart as an SVG grid, each cell is split between the actions by frequency
+    pub fn to_svg(&self) -> String {
+        let size = self.hands.len() * CELL_SIZE;
+        let legend_height = 24;
+        let width = size.max(self.actions.len() * 3 * CELL_SIZE);
+        let mut svg = String::new();
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#, width, size + legend_height).unwrap();
+
+        for (row, (hands, frequencies)) in self.hands.iter().zip(self.frequencies.iter()).enumerate() {
+            for (col, (hand, frequency)) in hands.iter().zip(frequencies.iter()).enumerate() {
+                let (x, y) = (col * CELL_SIZE, row * CELL_SIZE);
+                writeln!(svg, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#eeeeee"/>"##, x, y, CELL_SIZE, CELL_SIZE).unwrap();
+                let mut offset = 0.;
+                for (i, f) in frequency.iter().enumerate() {
+                    let width = f * CELL_SIZE as f64;
+                    if width > 0. {
+                        writeln!(svg, r#"<rect x="{:.2}" y="{}" width="{:.2}" height="{}" fill="{}"/>"#, x as f64 + offset, y, width, CELL_SIZE, self.action_color(i)).unwrap();
+                    }
+                    offset += width;
+                }
+                writeln!(svg, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#ffffff"/>"##, x, y, CELL_SIZE, CELL_SIZE).unwrap();
+                writeln!(svg, r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#, x + CELL_SIZE / 2, y + CELL_SIZE / 2 + 4, hand).unwrap();
+            }
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            let x = i * 3 * CELL_SIZE;
+            writeln!(svg, r#"<rect x="{}" y="{}" width="12" height="12" fill="{}"/>"#, x, size + 6, self.action_color(i)).unwrap();
+            writeln!(svg, r#"<text x="{}" y="{}">{}</text>"#, x + 16, size + 17, action).unwrap();
+        }
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+
+    /// Returns the color of the action at index `i`, raises get darker as they get bigger
+    fn action_color(&self, i: usize) -> &'static str {
+        const RAISE_COLORS: [&str; 5] = ["#f4a259", "#e76f51", "#d62828", "#9d0208", "#6a040f"];
+        match self.actions[i] {
+            Action::Fold => "#8da9c4",
+            Action::Call => "#52b788",
+            Action::Raise(_) => {
+                let raise_i = self.actions[..i].iter().filter(|a| matches!(a, Action::Raise(_))).count();
+                RAISE_COLORS[raise_i.min(RAISE_COLORS.len() - 1)]
+            },
+            Action::Discard(_) => "#7b68ee",
+        }
+    }
+}
+
+/// Writes a page showing the SVG of every chart under a heading with its seat and betting
+pub fn charts_to_html(charts: &[RangeChart]) -> String {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Range charts</title></head>\n<body>").unwrap();
+    for chart in charts {
+        let betting = if chart.betting.is_empty() { "first to act".to_string() } else { format!("after {}", chart.betting) };
+        writeln!(html, "<h2>Seat {} ({}), {}</h2>", chart.seat, chart.position, betting).unwrap();
+        html.push_str(&chart.to_svg());
+    }
+    writeln!(html, "</body>\n</html>").unwrap();
+    html
+}
@@ -0,0 +1,117 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    cancel::CancelToken,
+    card_abstraction::CardAbstraction,
+    game::GameInfo,
+    strategy::{Strategy, StrategyProfile},
+};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+/// Holds a strategy waiting to replace the one being played. Clones share the same slot, so one
+/// can be kept to put refreshed blueprints in from another thread while the player takes them
+/// out between hands, never in the middle of one.
+#[derive(Clone, Default)]
+pub struct StrategySlot(Arc<Mutex<Option<StrategyProfile>>>);
+
+impl StrategySlot {
+    pub fn new() -> StrategySlot {
+        StrategySlot::default()
+    }
+
+    /// Puts `profile` in the slot, replacing any strategy that wasn't taken yet
+    pub fn put(&self, profile: StrategyProfile) {
+        *self.0.lock().unwrap() = Some(profile);
+    }
+
+    /// Takes the waiting strategy out of the slot
+    pub fn take(&self) -> Option<StrategyProfile> {
+        self.0.lock().unwrap().take()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+}
+
+/// Loads a strategy again whenever its file changes. The configs are read again on every load
+/// so a blueprint retrained with a different abstraction can be picked up too, as long as its
+/// files are replaced together.
+pub struct StrategyWatcher {
+    game_config: PathBuf,
+    action_abstraction_config: PathBuf,
+    card_abstraction_config: PathBuf,
+    strategy_path: PathBuf,
+    nodes_path: PathBuf,
+    /// Modification time of the strategy that was loaded last
+    loaded: Option<SystemTime>,
+    /// Modification time seen by the last poll, a change is only loaded once it stops changing
+    seen: Option<SystemTime>,
+}
+
+impl StrategyWatcher {
+    /// Watches the strategy at `strategy_path`, which is assumed to be loaded already
+    pub fn new(game_config: &Path, action_abstraction_config: &Path, card_abstraction_config: &Path, strategy_path: &Path, nodes_path: &Path) -> StrategyWatcher {
+        let modified = modified(strategy_path);
+        StrategyWatcher {
+            game_config: game_config.to_path_buf(),
+            action_abstraction_config: action_abstraction_config.to_path_buf(),
+            card_abstraction_config: card_abstraction_config.to_path_buf(),
+            strategy_path: strategy_path.to_path_buf(),
+            nodes_path: nodes_path.to_path_buf(),
+            loaded: modified,
+            seen: modified,
+        }
+    }
+
+    /// Loads the strategy from the files, refusing one trained in another game or abstraction
+    pub fn load(&mut self) -> Result<StrategyProfile, String> {
+        let modified = modified(&self.strategy_path);
+        let game_info = GameInfo::load_game_info(&self.game_config).map_err(|e| e.to_string())?;
+        let action_abstraction = ActionAbstraction::from_config(&self.action_abstraction_config).map_err(|e| e.to_string())?;
+        let card_abstraction = CardAbstraction::from_config(&self.card_abstraction_config).map_err(|e| e.to_string())?;
+        let abstract_game = AbstractGame::load_nodes(game_info, &self.nodes_path, action_abstraction, card_abstraction);
+        let strategy = Strategy::from_file_checked(&self.strategy_path, &abstract_game)?;
+        self.loaded = modified;
+        self.seen = modified;
+        Ok(StrategyProfile::new(abstract_game, strategy))
+    }
+
+    /// Returns the strategy loaded again if its file changed since it was last loaded. A change
+    /// is only loaded once the file was the same for two polls in a row, so a strategy that's
+    /// still being written isn't read halfway.
+    pub fn poll(&mut self) -> Option<Result<StrategyProfile, String>> {
+        let modified = modified(&self.strategy_path);
+        let stable = modified == self.seen;
+        self.seen = modified;
+        if modified.is_none() || modified == self.loaded || !stable {
+            return None;
+        }
+        info!("{} changed, reloading", self.strategy_path.display());
+        Some(self.load())
+    }
+
+    /// Polls every `interval` on a new thread and puts every strategy loaded into `slot`, until
+    /// `cancel_token` is cancelled
+    pub fn spawn(mut self, slot: StrategySlot, interval: Duration, cancel_token: CancelToken) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !cancel_token.is_cancelled() {
+                thread::sleep(interval);
+                match self.poll() {
+                    Some(Ok(profile)) => slot.put(profile),
+                    Some(Err(e)) => warn!("failed to reload strategy: {}", e),
+                    None => {},
+                }
+            }
+        })
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
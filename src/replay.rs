@@ -0,0 +1,156 @@
+//! Stepping through a recorded hand one action at a time, so front ends can build hand review
+//! screens on the crate: every step has the state of the game, the cards showing and what the
+//! player to act could have done instead.
+
+use super::{
+    dealer::HandRecord,
+    game::{Action, BettingType, GameInfo, GameState, PlayerId},
+    history::HandHistory,
+};
+
+use poker::Card;
+
+/// A recorded hand with a position between its actions, from 0 before the first action to
+/// `len()` after the last
+#[derive(Clone, Debug)]
+pub struct Replay {
+    history: HandHistory,
+    /// State before every action followed by the final state
+    states: Vec<GameState>,
+    position: usize,
+}
+
+impl Replay {
+    /// Starts a replay of the hand at its first action, failing if the actions can't be played
+    /// in order
+    pub fn new(history: HandHistory) -> Result<Replay, &'static str> {
+        let states = history.states()?;
+        Ok(Replay { history, states, position: 0 })
+    }
+
+    /// Starts a replay of a hand dealt by the dealer, `players` names the player of each seat
+    pub fn from_record(game_info: &GameInfo, record: &HandRecord, players: &[String]) -> Result<Replay, &'static str> {
+        Replay::new(HandHistory::from_record(game_info, record, players))
+    }
+
+    pub fn history(&self) -> &HandHistory {
+        &self.history
+    }
+
+    pub fn game_info(&self) -> &GameInfo {
+        &self.history.game_info
+    }
+
+    /// Returns the number of actions in the hand
+    pub fn len(&self) -> usize {
+        self.history.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.actions.is_empty()
+    }
+
+    /// Returns the number of actions played so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn is_at_start(&self) -> bool {
+        self.position == 0
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.len()
+    }
+
+    /// Plays the next action and returns it with its round and player, `None` at the end
+    pub fn step_forward(&mut self) -> Option<(u8, PlayerId, Action)> {
+        let action = self.next_action()?;
+        self.position += 1;
+        Some(action)
+    }
+
+    /// Takes back the last action played and returns it with its round and player, `None` at
+    /// the start
+    pub fn step_backward(&mut self) -> Option<(u8, PlayerId, Action)> {
+        let action = self.previous_action()?;
+        self.position -= 1;
+        Some(action)
+    }
+
+    /// Moves to just after the first `position` actions, the end if there are fewer
+    pub fn seek(&mut self, position: usize) {
+        self.position = position.min(self.len());
+    }
+
+    /// Returns the action played from the current position along with its round and player
+    pub fn next_action(&self) -> Option<(u8, PlayerId, Action)> {
+        self.history.actions.get(self.position).copied()
+    }
+
+    /// Returns the action that led to the current position along with its round and player
+    pub fn previous_action(&self) -> Option<(u8, PlayerId, Action)> {
+        self.position.checked_sub(1).map(|i| self.history.actions[i])
+    }
+
+    /// Returns the state of the game at the current position
+    pub fn state(&self) -> &GameState {
+        &self.states[self.position]
+    }
+
+    /// Returns the player to act at the current position, `None` once the hand is over
+    pub fn acting_player(&self) -> Option<PlayerId> {
+        self.next_action().map(|(_, player, _)| player)
+    }
+
+    /// Returns every action the player to act could take but the raises of no limit and spread
+    /// limit games, which are given by `raise_bounds`, and every subset of the hole cards while
+    /// drawing. Empty once the hand is over.
+    pub fn legal_actions(&self) -> Vec<Action> {
+        let (game_info, state) = (self.game_info(), self.state());
+        let player = match self.acting_player() {
+            Some(player) => player,
+            None => return Vec::new(),
+        };
+        if state.is_drawing() {
+            return (0..1 << game_info.num_hole_cards()).map(Action::Discard).collect();
+        }
+
+        let mut actions = [Action::Fold, Action::Call].into_iter()
+            .filter(|a| state.is_valid_action(game_info, *a))
+            .collect::<Vec<Action>>();
+        if game_info.betting_type() == BettingType::Limit && state.raise_bounds(game_info, player).is_some() {
+            actions.push(Action::Raise(game_info.raise_size(state.current_round())));
+        }
+        actions.retain(|a| state.is_valid_action(game_info, *a));
+        actions
+    }
+
+    /// Returns the smallest and largest amount the player to act could raise to in no limit and
+    /// spread limit games, every amount between them included. `None` in limit games, whose
+    /// raise is in `legal_actions`, and when the player can't raise.
+    pub fn raise_bounds(&self) -> Option<(u32, u32)> {
+        let (game_info, state) = (self.game_info(), self.state());
+        if game_info.betting_type() == BettingType::Limit || state.is_drawing() {
+            return None;
+        }
+        state.raise_bounds(game_info, self.acting_player()?)
+    }
+
+    /// Returns the board cards showing at the current position, every run out board card once
+    /// the hand is over
+    pub fn board_cards(&self) -> &[Card] {
+        let board_cards = &self.history.board_cards;
+        if self.is_at_end() {
+            return board_cards;
+        }
+        let num_cards = self.game_info().total_board_cards(self.state().current_round()) as usize;
+        &board_cards[..num_cards.min(board_cards.len())]
+    }
+
+    /// Returns the cards dealt to each player. Draw games only have the hands players end with,
+    /// in `history().final_hole_cards`, since the cards drawn along the way aren't recorded.
+    pub fn hole_cards(&self) -> &[Vec<Card>] {
+        &self.history.hole_cards
+    }
+}
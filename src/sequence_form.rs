@@ -0,0 +1,174 @@
+use super::{
+    game::GameInfo,
+    open_spiel::{OpenSpielGame, OpenSpielState, CHANCE_PLAYER},
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Writes the full game tree in Gambit's .efg format so equilibria can be computed with Gambit's
+/// solvers. Chance nodes deal one card at a time with rational probabilities, payoffs are in
+/// chips. Only feasible for small games like Kuhn and Leduc.
+pub fn to_efg(game_info: &GameInfo, title: &str) -> String {
+    let game = OpenSpielGame::new(game_info.clone());
+    let players = (1..=game.num_players()).map(|p| format!("\"Player {}\"", p)).collect::<Vec<String>>().join(" ");
+
+    let mut efg = String::new();
+    writeln!(efg, "EFG 2 R \"{}\" {{ {} }}", title, players).unwrap();
+    writeln!(efg, "\"\"").unwrap();
+    let mut numbering = EfgNumbering { infosets: vec![HashMap::new(); game.num_players() as usize], chance_nodes: 0, outcomes: 0 };
+    write_efg_node(&game.new_initial_state(), &mut numbering, &mut efg);
+    efg
+}
+
+/// Numbers given to infosets, chance nodes and outcomes as they are written
+struct EfgNumbering {
+    /// Number of each information state string of each player, numbered from 1
+    infosets: Vec<HashMap<String, usize>>,
+    chance_nodes: usize,
+    outcomes: usize,
+}
+
+fn write_efg_node(state: &OpenSpielState, numbering: &mut EfgNumbering, efg: &mut String) {
+    if state.is_terminal() {
+        numbering.outcomes += 1;
+        let payoffs = state.returns().iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ");
+        writeln!(efg, "t \"\" {} \"\" {{ {} }}", numbering.outcomes, payoffs).unwrap();
+        return;
+    }
+
+    let actions = state.legal_actions();
+    if state.is_chance_node() {
+        numbering.chance_nodes += 1;
+        let outcomes = actions.iter()
+            .map(|a| format!("\"{}\" 1/{}", state.action_to_string(*a), actions.len()))
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(efg, "c \"\" {} \"\" {{ {} }} 0", numbering.chance_nodes, outcomes).unwrap();
+    } else {
+        let player = state.current_player();
+        let infoset = state.information_state_string(player as u8);
+        let infosets = &mut numbering.infosets[player as usize];
+        let next = infosets.len() + 1;
+        let number = *infosets.entry(infoset.clone()).or_insert(next);
+        let labels = actions.iter()
+            .map(|a| format!("\"{}\"", state.action_to_string(*a)))
+            .collect::<Vec<String>>()
+            .join(" ");
+        writeln!(efg, "p \"\" {} {} \"{}\" {{ {} }} 0", player + 1, number, infoset, labels).unwrap();
+    }
+
+    for action in actions {
+        write_efg_node(&state.child(action).unwrap(), numbering, efg);
+    }
+}
+
+/// Infoset of a player in the sequence form
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequenceFormInfoset {
+    pub name: String,
+    /// Sequence of the player leading to the infoset
+    pub parent_sequence: usize,
+    /// Sequence of each action, in the order of `actions`
+    pub sequences: Vec<usize>,
+    pub actions: Vec<String>,
+}
+
+/// Sequence form of a two player game. Sequence 0 of each player is the empty sequence, a
+/// realization plan `x` of a player is valid when `x[0] = 1` and for every infoset the
+/// sequences of its actions sum to its parent sequence. `payoffs` are the non zero entries of
+/// the payoff matrices, the expected chips of each player summed over the chance outcomes
+/// leading to each pair of sequences.
+///
+/// Solving `max_x min_y x^T A y` subject to those constraints as a linear program gives an exact
+/// equilibrium of a zero sum game to compare against CFR.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequenceForm {
+    pub num_sequences: [usize; 2],
+    pub infosets: [Vec<SequenceFormInfoset>; 2],
+    /// Entries `(sequence of player 0, sequence of player 1, [payoff of player 0, payoff of player 1])`
+    pub payoffs: Vec<(usize, usize, [f64; 2])>,
+}
+
+impl SequenceForm {
+    /// Builds the sequence form by walking the whole game tree. Only feasible for small games.
+    pub fn new(game_info: &GameInfo) -> SequenceForm {
+        assert_eq!(game_info.num_players(), 2, "sequence form export is only supported for two players");
+        let game = OpenSpielGame::new(game_info.clone());
+
+        let mut builder = SequenceFormBuilder {
+            infoset_ids: [HashMap::new(), HashMap::new()],
+            form: SequenceForm { num_sequences: [1, 1], infosets: [Vec::new(), Vec::new()], payoffs: Vec::new() },
+            payoffs: HashMap::new(),
+        };
+        builder.visit(&game.new_initial_state(), [0, 0], 1.);
+
+        let mut payoffs = builder.payoffs.into_iter()
+            .filter(|(_, p)| p[0] != 0. || p[1] != 0.)
+            .map(|((s0, s1), p)| (s0, s1, p))
+            .collect::<Vec<(usize, usize, [f64; 2])>>();
+        payoffs.sort_by_key(|(s0, s1, _)| (*s0, *s1));
+        builder.form.payoffs = payoffs;
+        builder.form
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+struct SequenceFormBuilder {
+    /// Position in `form.infosets` of each information state string of each player
+    infoset_ids: [HashMap<String, usize>; 2],
+    form: SequenceForm,
+    payoffs: HashMap<(usize, usize), [f64; 2]>,
+}
+
+impl SequenceFormBuilder {
+    /// Visits the subtree of `state` reached by `sequences` of the players with probability
+    /// `chance` over the chance outcomes
+    fn visit(&mut self, state: &OpenSpielState, sequences: [usize; 2], chance: f64) {
+        if state.is_terminal() {
+            let returns = state.returns();
+            let payoff = self.payoffs.entry((sequences[0], sequences[1])).or_insert([0.; 2]);
+            for (p, r) in payoff.iter_mut().zip(returns) {
+                *p += chance * r;
+            }
+            return;
+        }
+
+        if state.current_player() == CHANCE_PLAYER {
+            for (outcome, probability) in state.chance_outcomes() {
+                self.visit(&state.child(outcome).unwrap(), sequences, chance * probability);
+            }
+            return;
+        }
+
+        let player = state.current_player() as usize;
+        let actions = state.legal_actions();
+        let name = state.information_state_string(player as u8);
+        let id = match self.infoset_ids[player].get(&name) {
+            Some(id) => *id,
+            None => {
+                let first = self.form.num_sequences[player];
+                self.form.num_sequences[player] += actions.len();
+                self.form.infosets[player].push(SequenceFormInfoset {
+                    name: name.clone(),
+                    parent_sequence: sequences[player],
+                    sequences: (first..first + actions.len()).collect(),
+                    actions: actions.iter().map(|a| state.action_to_string(*a)).collect(),
+                });
+                self.infoset_ids[player].insert(name, self.form.infosets[player].len() - 1);
+                self.form.infosets[player].len() - 1
+            },
+        };
+
+        for (i, action) in actions.into_iter().enumerate() {
+            let mut child_sequences = sequences;
+            child_sequences[player] = self.form.infosets[player][id].sequences[i];
+            self.visit(&state.child(action).unwrap(), child_sequences, chance);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use super::{
+    agent::Agent,
+    dealer::{AuditReport, Dealer, HandRecord},
+    deals::DeckSource,
+    game::GameInfo,
+    live_feed::LiveFeed,
+    match_stats::MatchStats,
+};
+
+/// Cumulative results of a player over a session
+#[derive(Clone, Debug, Default)]
+pub struct PlayerResult {
+    pub hands_played: u32,
+    /// Chips won over every hand, rebuys don't count as winnings
+    pub net: i64,
+    /// Chips won over every hand with all in pots awarded by equity, set if the session
+    /// adjudicates all ins
+    pub all_in_ev_net: Option<f64>,
+    pub rebuys: u32,
+}
+
+/// A hand of a session along with the player sitting in each seat
+#[derive(Clone, Debug)]
+pub struct SessionHand {
+    /// `seats[i]` is the player who sat in seat i
+    pub seats: Vec<usize>,
+    pub record: HandRecord,
+}
+
+/// Plays a sequence of hands between the same players. Seats of `game_info` are positions
+/// relative to the button, every hand each player moves one seat down so the button and blinds
+/// rotate, and stacks are carried from hand to hand.
+pub struct Session {
+    dealer: Dealer,
+    game_info: GameInfo,
+    /// Agent of each player, in the order they sit this hand
+    agents: Vec<Box<dyn Agent>>,
+    /// Player sitting in each seat this hand
+    seats: Vec<usize>,
+    /// Stack of each player
+    stacks: Vec<u32>,
+    /// Whether busted players buy back in for their starting stack
+    rebuys: bool,
+    results: Vec<PlayerResult>,
+    stats: MatchStats,
+}
+
+impl Session {
+    /// Seats `agents[i]` as player i in seat i for the first hand
+    pub fn new(game_info: GameInfo, agents: Vec<Box<dyn Agent>>) -> Session {
+        let num_players = game_info.num_players() as usize;
+        assert!(agents.len() == num_players, "need one agent per seat");
+        Session {
+            dealer: Dealer::new(game_info.clone()),
+            stacks: game_info.starting_stacks().to_vec(),
+            stats: MatchStats::new(&game_info),
+            game_info,
+            agents,
+            seats: (0..num_players).collect(),
+            rebuys: false,
+            results: vec![PlayerResult::default(); num_players],
+        }
+    }
+
+    pub fn set_rebuys(&mut self, rebuys: bool) {
+        self.rebuys = rebuys;
+    }
+
+    pub fn set_audit(&mut self, audit: bool) {
+        self.dealer.set_audit(audit);
+    }
+
+    /// Records every chip movement of each hand, see `Dealer::set_ledger`
+    pub fn set_ledger(&mut self, ledger: bool) {
+        self.dealer.set_ledger(ledger);
+    }
+
+    /// Also totals the winnings of every player with all in pots awarded by equity, see
+    /// `Dealer::set_all_in_ev`. Stacks are still carried with the dealt payouts.
+    pub fn set_all_in_ev(&mut self, all_in_ev: bool) {
+        self.dealer.set_all_in_ev(all_in_ev);
+    }
+
+    /// Deals every hand from `deck_source`, see `Dealer::set_deck_source`
+    pub fn set_deck_source(&mut self, deck_source: Box<dyn DeckSource>) {
+        self.dealer.set_deck_source(deck_source);
+    }
+
+    /// Streams the events of every hand with the results of each player, see `LiveFeed`
+    pub fn set_live_feed(&mut self, live_feed: Option<LiveFeed>) {
+        self.dealer.set_live_feed(live_feed);
+    }
+
+    /// Seeds the dealer so every run of the session deals the same cards
+    pub fn set_seed(&mut self, seed: u64) {
+        self.dealer.set_seed(seed);
+    }
+
+    /// Returns the current stack of each player
+    pub fn stacks(&self) -> &[u32] {
+        &self.stacks
+    }
+
+    pub fn results(&self) -> &[PlayerResult] {
+        &self.results
+    }
+
+    /// Returns the statistics of every player over the hands played so far
+    pub fn stats(&self) -> &MatchStats {
+        &self.stats
+    }
+
+    /// Returns whether `player` has too few chips to post the biggest forced bet of any seat
+    fn is_busted(&self, player: usize) -> bool {
+        let forced_bet = (0..self.game_info.num_players())
+            .map(|p| self.game_info.live_blind(p) + self.game_info.dead_blind(p))
+            .max()
+            .unwrap_or(0);
+        self.stacks[player] < forced_bet.max(1)
+    }
+
+    /// Plays up to `num_hands` hands. Busted players rebuy if rebuys are enabled, otherwise the
+    /// session stops before the first hand a player can't afford.
+    pub fn play(&mut self, num_hands: u32) -> Result<Vec<SessionHand>, Box<AuditReport>> {
+        let mut hands = Vec::new();
+
+        for _ in 0..num_hands {
+            for player in 0..self.stacks.len() {
+                if self.is_busted(player) && self.rebuys {
+                    self.stacks[player] += self.game_info.starting_stacks()[player];
+                    self.results[player].rebuys += 1;
+                }
+            }
+            if (0..self.stacks.len()).any(|p| self.is_busted(p)) {
+                info!("Stopping session since a player busted");
+                break;
+            }
+
+            let mut hand_info = self.game_info.clone();
+            hand_info.set_starting_stacks(self.seats.iter().map(|p| self.stacks[*p]).collect());
+            self.dealer.set_game_info(hand_info);
+            self.dealer.set_seat_players(self.seats.clone());
+
+            let record = self.dealer.play_hand(&mut self.agents)?;
+            for (seat, player) in self.seats.iter().enumerate() {
+                let payout = record.payouts[seat];
+                self.stacks[*player] = (self.stacks[*player] as i64 + payout as i64) as u32;
+                self.results[*player].net += payout as i64;
+                if let Some(ev_payouts) = &record.all_in_ev_payouts {
+                    *self.results[*player].all_in_ev_net.get_or_insert(0.) += ev_payouts[seat];
+                }
+                self.results[*player].hands_played += 1;
+            }
+            self.stats.add_hand(&self.game_info, &record, &self.seats);
+            hands.push(SessionHand {
+                seats: self.seats.clone(),
+                record,
+            });
+
+            // The player in seat i moves to seat i - 1, which moves the button one seat along
+            self.agents.rotate_left(1);
+            self.seats.rotate_left(1);
+        }
+
+        for agent in self.agents.iter_mut() {
+            agent.end_session(&self.game_info);
+        }
+
+        Ok(hands)
+    }
+}
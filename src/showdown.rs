@@ -0,0 +1,225 @@
+use super::{
+    card_set::CardSet,
+    evaluator::{HandEvaluator, HandRank, RankingDirection},
+    range::{card_index, Combo, Range},
+};
+
+use poker::Card;
+use itertools::Itertools;
+
+/// Number of cards in the 52 card deck the card indices of combos refer to
+pub(crate) const DECK_SIZE: usize = 52;
+
+/// Returns for every hand in `hands` the weight of `opponent_range` it beats at showdown on
+/// `board` minus the weight it loses to, so multiplying by the amount each player put in gives
+/// the showdown value. Opponent combos sharing a card with the hand or the board are skipped
+/// and hands sharing a card with the board get zero.
+///
+/// Runs in O(n log n) by sorting both ranges by strength and sweeping them with running sums of
+/// the opponent weight, removing the weight of combos sharing a card with the hand through a
+/// running sum per card instead of comparing every pair of hands.
+pub fn showdown_values(evaluator: &dyn HandEvaluator, direction: RankingDirection, board: &[Card], hands: &[Combo], opponent_range: &Range) -> Vec<f64> {
+    let rank = |combo: &Combo| direction.orient(evaluator.evaluate_showdown(&combo.cards(), board));
+    let board_set = CardSet::from_cards(board);
+    let mut opponents = opponent_range.iter()
+        .filter(|(combo, _)| !combo.is_blocked_by_set(board_set))
+        .map(|(combo, weight)| (rank(combo), card_indices(combo), *weight))
+        .collect::<Vec<(HandRank, [usize; 2], f64)>>();
+    opponents.sort_by_key(|(r, _, _)| *r);
+
+    let mut ranked_hands = hands.iter()
+        .enumerate()
+        .filter(|(_, combo)| !combo.is_blocked_by_set(board_set))
+        .map(|(i, combo)| (i, rank(combo), card_indices(combo)))
+        .collect::<Vec<(usize, HandRank, [usize; 2])>>();
+    ranked_hands.sort_by_key(|(_, r, _)| *r);
+
+    let mut values = vec![0.; hands.len()];
+    add_showdown_values(&ranked_hands, &opponents, &mut values);
+    values
+}
+
+/// The sweep of `showdown_values` over hands and opponents already ranked with the oriented
+/// rank and sorted by it, adds the value of every `(i, rank, cards)` of `hands` to `values[i]`
+pub(crate) fn add_showdown_values(hands: &[(usize, HandRank, [usize; 2])], opponents: &[(HandRank, [usize; 2], f64)], values: &mut [f64]) {
+    // Weight the hand beats, from the weakest hand up
+    let mut total = 0.;
+    let mut card_totals = [0.; DECK_SIZE];
+    let mut next = 0;
+    for (i, hand_rank, cards) in hands.iter() {
+        while next < opponents.len() && opponents[next].0 < *hand_rank {
+            add_weight(&mut total, &mut card_totals, &opponents[next]);
+            next += 1;
+        }
+        values[*i] += total - card_totals[cards[0]] - card_totals[cards[1]];
+    }
+
+    // Weight the hand loses to, from the strongest hand down
+    let mut total = 0.;
+    let mut card_totals = [0.; DECK_SIZE];
+    let mut next = opponents.len();
+    for (i, hand_rank, cards) in hands.iter().rev() {
+        while next > 0 && opponents[next - 1].0 > *hand_rank {
+            add_weight(&mut total, &mut card_totals, &opponents[next - 1]);
+            next -= 1;
+        }
+        values[*i] -= total - card_totals[cards[0]] - card_totals[cards[1]];
+    }
+}
+
+/// Hands of the two players of a spot ranked once on a fixed board, so showdowns on the board
+/// are valued by sweeping the sorted hands instead of evaluating every hand again. Solvers
+/// iterating over the same board thousands of times keep one for each board. Hands are referred
+/// to by their position in the hands the board was ranked with.
+#[derive(Clone, Debug)]
+pub struct RankedBoard {
+    cards: Vec<Card>,
+    /// Hands of each player not blocked by the board as `(position, rank, card indices)`, sorted
+    /// by the oriented rank so stronger hands come later
+    ranked: [Vec<(usize, HandRank, [usize; 2])>; 2],
+    /// Oriented rank of every hand of each player, `None` if it shares a card with the board
+    ranks: [Vec<Option<HandRank>>; 2],
+}
+
+impl RankedBoard {
+    pub fn new(evaluator: &dyn HandEvaluator, direction: RankingDirection, board: &[Card], hands: [&[Combo]; 2]) -> RankedBoard {
+        let board_set = CardSet::from_cards(board);
+        let ranks = hands.map(|hands| {
+            hands.iter()
+                .map(|combo| if combo.is_blocked_by_set(board_set) {
+                    None
+                } else {
+                    Some(direction.orient(evaluator.evaluate_showdown(&combo.cards(), board)))
+                })
+                .collect::<Vec<Option<HandRank>>>()
+        });
+        let ranked = [0, 1].map(|p| {
+            let mut ranked = hands[p].iter()
+                .zip(&ranks[p])
+                .enumerate()
+                .filter_map(|(i, (combo, rank))| rank.map(|r| (i, r, card_indices(combo))))
+                .collect::<Vec<(usize, HandRank, [usize; 2])>>();
+            ranked.sort_by_key(|(_, r, _)| *r);
+            ranked
+        });
+        RankedBoard { cards: board.to_vec(), ranked, ranks }
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// Returns the hands of `player` not blocked by the board as `(position, rank, card
+    /// indices)`, weakest first
+    pub fn ranked(&self, player: usize) -> &[(usize, HandRank, [usize; 2])] {
+        &self.ranked[player]
+    }
+
+    /// Returns the oriented rank of the hand of `player` at `position`, `None` if it shares a
+    /// card with the board
+    pub fn rank(&self, player: usize, position: usize) -> Option<HandRank> {
+        self.ranks[player][position]
+    }
+
+    pub fn is_blocked(&self, player: usize, position: usize) -> bool {
+        self.ranks[player][position].is_none()
+    }
+
+    /// Returns for every hand of `player` the reach of the opponent hands it beats minus the
+    /// reach of those it loses to, skipping opponent hands sharing a card with it.
+    /// `opponent_reach` holds the reach of every hand of the opponent, blocked hands get zero.
+    pub fn showdown_values(&self, player: usize, opponent_reach: &[f64]) -> Vec<f64> {
+        let opponents = self.ranked[1 - player].iter()
+            .map(|(j, rank, cards)| (*rank, *cards, opponent_reach[*j]))
+            .collect::<Vec<(HandRank, [usize; 2], f64)>>();
+        let mut values = vec![0.; self.ranks[player].len()];
+        add_showdown_values(&self.ranked[player], &opponents, &mut values);
+        values
+    }
+}
+
+/// Returns for every hand in `hands` the weight of `opponent_range` that doesn't share a card
+/// with it or the board, which times the amount won is the value of the opponent folding
+pub fn fold_values(board: &[Card], hands: &[Combo], opponent_range: &Range) -> Vec<f64> {
+    let board_set = CardSet::from_cards(board);
+    let mut total = 0.;
+    let mut card_totals = [0.; DECK_SIZE];
+    for (combo, weight) in opponent_range.iter().filter(|(combo, _)| !combo.is_blocked_by_set(board_set)) {
+        add_weight(&mut total, &mut card_totals, &(HandRank(0), card_indices(combo), *weight));
+    }
+
+    hands.iter()
+        .map(|combo| {
+            if combo.is_blocked_by_set(board_set) {
+                return 0.;
+            }
+            let cards = card_indices(combo);
+            // The same combo was subtracted once for each of its cards
+            total - card_totals[cards[0]] - card_totals[cards[1]] + opponent_range.weight(combo)
+        })
+        .collect()
+}
+
+/// Returns the probability every hand in `hands` beats `opponent_range` at showdown, counting
+/// ties as half, averaged over every way to deal the rest of a board of `num_board_cards` from
+/// the 52 card deck. Hands sharing a card with the board get zero.
+pub fn equities(evaluator: &dyn HandEvaluator, direction: RankingDirection, board: &[Card], num_board_cards: usize, hands: &[Combo], opponent_range: &Range) -> Vec<f64> {
+    assert!(board.len() <= num_board_cards, "board has more cards than a full board");
+    let remaining = CardSet::from_cards(board).remaining(&Card::generate_deck().collect::<Vec<Card>>());
+    let mut wins = vec![0.; hands.len()];
+    let mut totals = vec![0.; hands.len()];
+
+    for run_out in remaining.into_iter().combinations(num_board_cards - board.len()) {
+        let full_board = [board, &run_out].concat();
+        let values = showdown_values(evaluator, direction, &full_board, hands, opponent_range);
+        let weights = fold_values(&full_board, hands, opponent_range);
+        for i in 0..hands.len() {
+            // Value is the weight beaten minus the weight lost to, so adding the total weight
+            // counts wins twice and ties once
+            wins[i] += (values[i] + weights[i]) / 2.;
+            totals[i] += weights[i];
+        }
+    }
+
+    wins.into_iter().zip(totals).map(|(w, t)| if t > 0. { w / t } else { 0. }).collect()
+}
+
+/// Returns how the equity of `hand` against `opponent_range` is spread over the ways to deal the
+/// board up to `next_board_cards`, as `num_bins` bins of equal width from 0 to 1 holding the
+/// fraction of those runouts whose equity falls in them, an equity of 1 going to the last bin.
+/// Equity on each runout is `equities` to a board of `num_board_cards`, so dealing a complete
+/// board gives the distribution of showdown equity and dealing the next round the next round
+/// histograms potential-aware abstractions cluster. Empty if the hand shares a card with the
+/// board.
+#[allow(clippy::too_many_arguments)]
+pub fn equity_histogram(evaluator: &dyn HandEvaluator, direction: RankingDirection, board: &[Card], next_board_cards: usize, num_board_cards: usize, hand: &Combo, opponent_range: &Range, num_bins: usize) -> Vec<f64> {
+    assert!(board.len() <= next_board_cards && next_board_cards <= num_board_cards, "boards have to grow towards a full board");
+    assert!(num_bins > 0, "need at least one bin");
+    if hand.is_blocked_by(board) {
+        return Vec::new();
+    }
+
+    let dealt = [board, &hand.cards()].concat();
+    let remaining = CardSet::from_cards(&dealt).remaining(&Card::generate_deck().collect::<Vec<Card>>());
+    let mut histogram = vec![0.; num_bins];
+    let mut num_runouts = 0;
+    for run_out in remaining.into_iter().combinations(next_board_cards - board.len()) {
+        let next_board = [board, &run_out].concat();
+        let equity = equities(evaluator, direction, &next_board, num_board_cards, std::slice::from_ref(hand), opponent_range)[0];
+        histogram[((equity * num_bins as f64) as usize).min(num_bins - 1)] += 1.;
+        num_runouts += 1;
+    }
+
+    histogram.iter_mut().for_each(|h| *h /= num_runouts as f64);
+    histogram
+}
+
+pub(crate) fn card_indices(combo: &Combo) -> [usize; 2] {
+    combo.cards().map(|c| card_index(c) as usize)
+}
+
+fn add_weight(total: &mut f64, card_totals: &mut [f64; DECK_SIZE], (_, cards, weight): &(HandRank, [usize; 2], f64)) {
+    *total += weight;
+    card_totals[cards[0]] += weight;
+    card_totals[cards[1]] += weight;
+}
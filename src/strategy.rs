@@ -3,20 +3,106 @@
 use super::{
     abstract_game::AbstractGame,
     card_abstraction::BucketId,
-    game::Action,
+    game::{stable_hash, Action, BettingType, GameInfo, GameState},
+    infosets::InfosetIndex,
     node::NodeId,
+    paged_map::PagedMap,
 };
 
-use serde::{Serialize, Deserialize};
+use poker::Card;
 
-use std::collections::BTreeMap;
-use std::path::Path;
+use serde::{de::DeserializeOwned, Serialize, Deserialize};
+
+use std::cell::RefCell;
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
 use rand::prelude::*;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Bytes saved strategies start with, files without them were saved before strategies had a
+/// header and are read without metadata
+const STRATEGY_MAGIC: [u8; 4] = *b"UNGS";
+
+/// Version of the format strategies are saved in, bumped whenever it changes
+pub const STRATEGY_FORMAT_VERSION: u32 = 1;
+
+/// Header of a saved strategy describing what trained it. Loading with
+/// `Strategy::from_file_checked` refuses strategies trained in another game or abstraction,
+/// whose infosets would silently mean something else.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrategyMetadata {
+    pub format_version: u32,
+    pub game_hash: u64,
+    pub action_abstraction_hash: u64,
+    pub card_abstraction_hash: u64,
+    /// Iterations the strategy was trained for, zero if unknown
+    pub iterations: u32,
+    /// Solver that trained the strategy, like `mccfr_p`, empty if unknown
+    pub solver: String,
+}
+
+impl StrategyMetadata {
+    pub fn new(abstract_game: &AbstractGame, iterations: u32, solver: &str) -> StrategyMetadata {
+        StrategyMetadata {
+            format_version: STRATEGY_FORMAT_VERSION,
+            game_hash: config_hash(&abstract_game.game_info),
+            action_abstraction_hash: config_hash(&abstract_game.action_abstraction),
+            card_abstraction_hash: config_hash(&abstract_game.card_abstraction),
+            iterations,
+            solver: solver.to_string(),
+        }
+    }
+
+    /// Returns what differs between the configs the strategy was trained in and those of
+    /// `abstract_game`
+    pub fn mismatches(&self, abstract_game: &AbstractGame) -> Vec<&'static str> {
+        let expected = StrategyMetadata::new(abstract_game, self.iterations, &self.solver);
+        let mut mismatches = Vec::new();
+        if self.game_hash != expected.game_hash {
+            mismatches.push("game");
+        }
+        if self.action_abstraction_hash != expected.action_abstraction_hash {
+            mismatches.push("action abstraction");
+        }
+        if self.card_abstraction_hash != expected.card_abstraction_hash {
+            mismatches.push("card abstraction");
+        }
+        mismatches
+    }
+}
+
+/// FNV-1a hash of the JSON of a config, stable between builds unlike the std hashers
+fn config_hash<T: Serialize>(config: &T) -> u64 {
+    stable_hash(&serde_json::to_vec(config).expect("failed to serialize config"))
+}
+
+/// Writes the header and then `value` with bincode
+pub(crate) fn write_with_metadata<T: Serialize>(path: &Path, metadata: &StrategyMetadata, value: &T) {
+    let mut w = BufWriter::new(File::create(path).expect("failed to create strategy file"));
+    w.write_all(&STRATEGY_MAGIC).expect("failed to write strategy");
+    bincode::serialize_into(&mut w, metadata).expect("failed to write strategy");
+    bincode::serialize_into(&mut w, value).expect("failed to write strategy");
+}
+
+/// Reads a value saved by `write_with_metadata` or, without a header, with bincode alone
+fn read_with_metadata<T: DeserializeOwned, R: Read>(mut r: R) -> (Option<StrategyMetadata>, T) {
+    let mut magic = Vec::with_capacity(STRATEGY_MAGIC.len());
+    r.by_ref().take(STRATEGY_MAGIC.len() as u64).read_to_end(&mut magic).expect("failed to read strategy");
+    if magic == STRATEGY_MAGIC {
+        let metadata: StrategyMetadata = bincode::deserialize_from(&mut r).expect("failed to read strategy metadata");
+        assert!(metadata.format_version <= STRATEGY_FORMAT_VERSION, "strategy was saved in format {}, newer than {}", metadata.format_version, STRATEGY_FORMAT_VERSION);
+        (Some(metadata), bincode::deserialize_from(r).expect("failed to read strategy"))
+    } else {
+        (None, bincode::deserialize_from(magic.as_slice().chain(r)).expect("failed to read strategy"))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Strategy(pub BTreeMap<(NodeId, BucketId), BTreeMap<Action, i32>>);
 
 impl Strategy {
@@ -24,30 +110,822 @@ impl Strategy {
         Strategy(BTreeMap::new())
     }
 
+    /// Reads a strategy, with or without a metadata header
     pub fn from_file(path: &Path) -> Strategy {
-        let mut r = BufReader::new(File::open(path).unwrap());
-        bincode::deserialize_from(&mut r).unwrap()
+        Strategy::load(path).1
     }
 
-    pub fn sample(&self, abstract_game: &AbstractGame, node_id: NodeId, bucket_id: BucketId) -> Action {
-        let mut rng = rand::thread_rng();
-        let sigma = match self.0.get(&(node_id, bucket_id)) {
-            Some(s) => s.clone(),
-            None => {
-                let mut action_map: BTreeMap<Action, i32> = BTreeMap::new();
+    /// Reads a strategy along with its metadata, `None` for files saved without a header
+    pub fn load(path: &Path) -> (Option<StrategyMetadata>, Strategy) {
+        read_with_metadata(BufReader::new(File::open(path).expect("failed to open strategy file")))
+    }
 
-                let current_node = abstract_game.nodes.get_node(node_id).unwrap();
-                for a in abstract_game.get_actions(&current_node.state) {
-                    action_map.insert(a, 0); // inserts with uniform distribution
+    /// Reads a strategy and checks it was trained in `abstract_game`, strategies without a
+    /// header are accepted with a warning
+    pub fn from_file_checked(path: &Path, abstract_game: &AbstractGame) -> Result<Strategy, String> {
+        let (metadata, strategy) = Strategy::load(path);
+        match metadata {
+            Some(metadata) => {
+                let mismatches = metadata.mismatches(abstract_game);
+                if !mismatches.is_empty() {
+                    return Err(format!("{} was trained with a different {}", path.display(), mismatches.join(", ")));
                 }
-                action_map
             },
+            None => warn!("{} has no metadata, can't check it was trained in this game and abstraction", path.display()),
+        }
+        Ok(strategy)
+    }
+
+    /// Reads a strategy saved with bincode from memory, with or without a metadata header
+    pub fn from_bytes(bytes: &[u8]) -> Strategy {
+        read_with_metadata(bytes).1
+    }
+
+    /// Saves the strategy with a metadata header
+    pub fn save(&self, path: &Path, metadata: &StrategyMetadata) {
+        write_with_metadata(path, metadata, self);
+    }
+
+    /// Samples an action at the infoset, infosets that were never visited or have no positive
+    /// weights are played uniformly
+    pub fn sample<R: Rng + ?Sized>(&self, abstract_game: &AbstractGame, node_id: NodeId, bucket_id: BucketId, rng: &mut R) -> Action {
+        let current_node = abstract_game.nodes.get_node(node_id).unwrap();
+        let sigma = self.action_probabilities(node_id, bucket_id, &abstract_game.get_actions(&current_node.state));
+        debug!("({}, {}): {:?}", node_id, bucket_id, sigma);
+
+        sample_probabilities(&sigma.into_iter().collect::<Vec<(Action, f64)>>(), 1., rng)
+    }
+
+    /// Returns the normalized probability of each of `actions` at the infoset, falling back to a
+    /// uniform distribution if the infoset was never visited
+    pub fn action_probabilities(&self, node_id: NodeId, bucket_id: BucketId, actions: &[Action]) -> BTreeMap<Action, f64> {
+        let counts = self.0.get(&(node_id, bucket_id));
+        let total: i32 = counts.map_or(0, |c| actions.iter().map(|a| max(*c.get(a).unwrap_or(&0), 0)).sum());
+
+        actions.iter()
+            .map(|a| {
+                let p = match counts {
+                    Some(c) if total > 0 => max(*c.get(a).unwrap_or(&0), 0) as f64 / total as f64,
+                    _ => 1. / actions.len() as f64,
+                };
+                (*a, p)
+            })
+            .collect()
+    }
+}
+
+
+/// Total count of an infoset after post-processing, so probabilities keep six digits
+pub(crate) const PROBABILITY_SCALE: i32 = 1_000_000;
+
+/// Part of answering a query that only depends on the betting, see
+/// `StrategyProfile::action_probabilities_batch`
+struct StateLookup {
+    real_actions: Vec<Action>,
+    /// `None` if the abstract hand ended before the real one
+    abstract_node: Option<AbstractLookup>,
+}
+
+/// Where a real state lands in the abstract game
+struct AbstractLookup {
+    /// Node of the abstract game if the tree has it
+    node_id: Option<NodeId>,
+    actions: Vec<Action>,
+    /// Real action each abstract action translates to
+    translations: Vec<Option<Action>>,
+}
+
+/// A strategy along with the abstraction it was trained in, used to query the strategy in
+/// situations of the real game.
+///
+/// Strategies can be solved street by street: solve the early rounds, take the states they
+/// reach at the start of a later round from `subtree_roots`, solve an abstract game rooted at
+/// each of them in its own job and add the results with `add_partial`. Every job saves its nodes
+/// and strategy like any other, so jobs that finished don't have to run again.
+pub struct StrategyProfile {
+    pub abstract_game: AbstractGame,
+    pub strategy: Strategy,
+    /// Profiles of subtrees solved separately, whose roots are states of this profile at the
+    /// start of a round
+    partials: Vec<StrategyProfile>,
+}
+
+impl StrategyProfile {
+    pub fn new(abstract_game: AbstractGame, strategy: Strategy) -> StrategyProfile {
+        StrategyProfile {
+            abstract_game,
+            strategy,
+            partials: Vec::new(),
+        }
+    }
+
+    /// Returns the states of the abstract tree at the start of `round` that the hand hasn't
+    /// ended in, the roots partial strategies of the round are solved from. Only the states
+    /// training reached are in the tree.
+    pub fn subtree_roots(&self, round: u8) -> Vec<GameState> {
+        self.abstract_game.nodes.iter()
+            .map(|(_, node)| &node.state)
+            .filter(|state| !state.is_finished() && state.current_round() == round && state.actions_in_round(round).next().is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Answers queries in the subtree rooted at the root of `partial` with it from now on. Its
+    /// root must be the abstract state of this profile at the start of a later round, like one
+    /// returned by `subtree_roots`. Partials can have partials of their own.
+    pub fn add_partial(&mut self, partial: StrategyProfile) -> Result<(), String> {
+        let root = partial.root_state();
+        let root_round = root.current_round();
+        if root_round <= self.root_state().current_round() || root.actions_in_round(root_round).next().is_some() {
+            return Err("partial strategies must start at the start of a later round".to_string());
+        }
+        let betting = root.betting_string(&self.abstract_game.game_info);
+        if GameState::from_betting_string(&self.abstract_game.game_info, 0, &betting).is_err() {
+            return Err(format!("{} isn't a line of this game", betting));
+        }
+        if self.partials.iter().any(|p| p.root_state().betting_string(&self.abstract_game.game_info) == betting) {
+            return Err(format!("there's already a partial strategy at {}", betting));
+        }
+        self.partials.push(partial);
+        Ok(())
+    }
+
+    /// Returns the profiles added with `add_partial`
+    pub fn partials(&self) -> &[StrategyProfile] {
+        &self.partials
+    }
+
+    fn root_state(&self) -> &GameState {
+        &self.abstract_game.nodes.get_node(self.abstract_game.nodes.get_root_node_id()).unwrap().state
+    }
+
+    /// Returns the profile answering queries at `state`, the partial whose root the abstract
+    /// betting of `state` went through last or this profile if there is none
+    fn route(&self, game_info: &GameInfo, state: &GameState) -> &StrategyProfile {
+        self.partial_at(&self.route_path(game_info, state))
+    }
+
+    /// Returns the profile reached by following `path` through the partials, see `route_path`
+    fn partial_at(&self, path: &[usize]) -> &StrategyProfile {
+        path.iter().fold(self, |profile, i| &profile.partials[*i])
+    }
+
+    /// Returns the indices of the partials `route` goes through, empty if this profile answers
+    fn route_path(&self, game_info: &GameInfo, state: &GameState) -> Vec<usize> {
+        if self.partials.is_empty() {
+            return Vec::new();
+        }
+        let abstract_info = &self.abstract_game.game_info;
+        let skip = self.root_state().full_history().count();
+        let mut abstract_state = self.root_state().clone();
+        let mut real_state = GameState::new(game_info, 0);
+        let mut routed = None;
+
+        for (i, (_, _, action)) in state.full_history().enumerate() {
+            if i >= skip {
+                if abstract_state.is_finished() {
+                    break;
+                }
+                let abstract_action = self.to_abstract_action(game_info, &real_state, &abstract_state, action);
+                abstract_state = match abstract_state.apply_action_no_cards(abstract_info, abstract_action) {
+                    Ok(next) => next,
+                    Err(_) => break,
+                };
+                // Partials start at the start of a round, which the call closing the round before
+                // moves to
+                if abstract_state.actions_in_round(abstract_state.current_round()).next().is_none() {
+                    let betting = abstract_state.betting_string(abstract_info);
+                    if let Some(i) = self.partials.iter().position(|p| p.root_state().betting_string(abstract_info) == betting) {
+                        routed = Some(i);
+                    }
+                }
+            }
+            real_state = real_state.apply_action_no_cards(game_info, action).expect("state has an invalid action");
+        }
+        match routed {
+            Some(i) => [vec![i], self.partials[i].route_path(game_info, state)].concat(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the probability of each action the current player of `state` takes holding
+    /// `hole_cards`. Real actions are mapped to the closest abstract actions to find the infoset
+    /// and abstract raises are translated back to chips as the same fraction of the real pot.
+    /// Situations the abstraction never reached are played uniformly.
+    pub fn action_probabilities(&self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Vec<(Action, f64)> {
+        let profile = self.route(game_info, state);
+        profile.resolve(&profile.lookup_state(game_info, state), state, hole_cards, board_cards)
+    }
+
+    /// Returns `action_probabilities` of every `(state, hole cards, board cards)` query in order.
+    /// Queries at states with the same betting, like the hands of a range, share the walk of
+    /// the abstract game and the translation of its actions, so only the bucket is looked up
+    /// for each.
+    pub fn action_probabilities_batch(&self, game_info: &GameInfo, queries: &[(&GameState, &[Card], &[Card])]) -> Vec<Vec<(Action, f64)>> {
+        let mut lookups: HashMap<String, (&StrategyProfile, StateLookup)> = HashMap::new();
+        queries.iter()
+            .map(|(state, hole_cards, board_cards)| {
+                let (profile, lookup) = lookups.entry(state.betting_string(game_info)).or_insert_with(|| {
+                    let profile = self.route(game_info, state);
+                    (profile, profile.lookup_state(game_info, state))
+                });
+                profile.resolve(lookup, state, hole_cards, board_cards)
+            })
+            .collect()
+    }
+
+    /// Finds the abstract node of `state` and translates its actions to the real game
+    fn lookup_state(&self, game_info: &GameInfo, state: &GameState) -> StateLookup {
+        let real_actions = self.abstract_game.action_abstraction.get_actions(game_info, state);
+        let abstract_node = self.find_node(game_info, state).map(|(node_id, abstract_state)| {
+            let actions = self.abstract_game.get_actions(&abstract_state);
+            let translations = actions.iter()
+                .map(|a| self.to_real_action(game_info, state, &abstract_state, *a, &real_actions))
+                .collect();
+            AbstractLookup { node_id, actions, translations }
+        });
+        StateLookup { real_actions, abstract_node }
+    }
+
+    fn resolve(&self, lookup: &StateLookup, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Vec<(Action, f64)> {
+        let real_actions = &lookup.real_actions;
+        let uniform = || real_actions.iter().map(|a| (*a, 1. / real_actions.len() as f64)).collect();
+
+        let AbstractLookup { node_id, actions: abstract_actions, translations } = match &lookup.abstract_node {
+            Some(found) => found,
+            None => return uniform(),
+        };
+        let sigma = match node_id {
+            Some(node_id) => {
+                let bucket_id = self.abstract_game.get_bucket(state.current_round(), board_cards, hole_cards);
+                self.strategy.action_probabilities(*node_id, bucket_id, abstract_actions)
+            },
+            None => abstract_actions.iter().map(|a| (*a, 1. / abstract_actions.len() as f64)).collect(),
+        };
+
+        // Several abstract actions can translate to the same real action
+        let mut probabilities: Vec<(Action, f64)> = Vec::new();
+        for (abstract_action, p) in sigma {
+            let action = match translations[abstract_actions.iter().position(|a| *a == abstract_action).unwrap()] {
+                Some(action) => action,
+                None => continue,
+            };
+            match probabilities.iter_mut().find(|(a, _)| *a == action) {
+                Some(entry) => entry.1 += p,
+                None => probabilities.push((action, p)),
+            }
+        }
+
+        let total: f64 = probabilities.iter().map(|(_, p)| p).sum();
+        if total <= 0. {
+            return uniform();
+        }
+        for entry in probabilities.iter_mut() {
+            entry.1 /= total;
+        }
+        probabilities
+    }
+
+    /// Samples the action the current player of `state` takes holding `hole_cards` from
+    /// `action_probabilities` with `temperature` applied, see `apply_temperature`. Seeding `rng`
+    /// the same way gives the same actions.
+    pub fn sample_action<R: Rng + ?Sized>(&self, rng: &mut R, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card], temperature: f64) -> Action {
+        sample_probabilities(&self.action_probabilities(game_info, state, hole_cards, board_cards), temperature, rng)
+    }
+
+    /// Purifies the strategy so every infoset plays its most likely action, ties go to the
+    /// action that sorts last
+    pub fn purify(&mut self) {
+        self.transform_infosets(&|probabilities| {
+            let best = probabilities.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map_or(0, |(i, _)| i);
+            (0..probabilities.len()).map(|i| if i == best { 1. } else { 0. }).collect()
+        });
+    }
+
+    /// Drops actions played with probability below `threshold` and renormalizes, the most likely
+    /// action of an infoset is always kept
+    pub fn apply_threshold(&mut self, threshold: f64) {
+        self.transform_infosets(&|probabilities| {
+            probabilities.iter().map(|p| if *p < threshold { 0. } else { *p }).collect()
+        });
+    }
+
+    /// Rounds every probability to a multiple of `step`, like 0.05, and renormalizes
+    pub fn round_probabilities(&mut self, step: f64) {
+        assert!(step > 0. && step <= 1., "step must be in (0, 1]");
+        self.transform_infosets(&|probabilities| {
+            probabilities.iter().map(|p| (p / step).round() * step).collect()
+        });
+    }
+
+    /// Replaces the counts of every infoset with the normalized output of `transform`, which gets
+    /// the current probabilities of the infoset's actions. If `transform` removes every action
+    /// the most likely one is kept.
+    fn transform_infosets(&mut self, transform: &dyn Fn(&[f64]) -> Vec<f64>) {
+        for partial in self.partials.iter_mut() {
+            partial.transform_infosets(transform);
+        }
+        for counts in self.strategy.0.values_mut() {
+            let total: i32 = counts.values().map(|c| max(*c, 0)).sum();
+            if total <= 0 {
+                continue;
+            }
+
+            let probabilities = counts.values().map(|c| max(*c, 0) as f64 / total as f64).collect::<Vec<f64>>();
+            let mut transformed = transform(&probabilities);
+            let transformed_total: f64 = transformed.iter().sum();
+            if transformed_total <= 0. {
+                let best = probabilities.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map_or(0, |(i, _)| i);
+                transformed = (0..probabilities.len()).map(|i| if i == best { 1. } else { 0. }).collect();
+            }
+            let transformed_total: f64 = transformed.iter().sum();
+
+            for (count, p) in counts.values_mut().zip(transformed) {
+                *count = (p / transformed_total * PROBABILITY_SCALE as f64).round() as i32;
+            }
+        }
+    }
+
+    /// Follows the actions of `state` through the abstract game, returning the node reached if
+    /// the tree has it and the abstract state. Returns `None` if the abstract hand ends before
+    /// the real one. Abstract games rooted later in the hand skip the actions that led to their
+    /// root, which the profile routing the query to them already matched.
+    pub(crate) fn find_node(&self, game_info: &GameInfo, state: &GameState) -> Option<(Option<NodeId>, GameState)> {
+        let nodes = &self.abstract_game.nodes;
+        let mut node_id = Some(nodes.get_root_node_id());
+        let mut abstract_state = self.root_state().clone();
+        let mut real_state = GameState::new(game_info, 0);
+        let skip = abstract_state.full_history().count();
+
+        for (i, (_, _, action)) in state.full_history().enumerate() {
+            if i >= skip {
+                if abstract_state.is_finished() {
+                    return None;
+                }
+                let abstract_action = self.to_abstract_action(game_info, &real_state, &abstract_state, action);
+                abstract_state = abstract_state.apply_action_no_cards(&self.abstract_game.game_info, abstract_action).ok()?;
+                node_id = node_id.and_then(|n| nodes.get_node(n).unwrap().child(&abstract_action));
+                // Nodes merged by a history abstraction keep the state of the betting that
+                // reached them first, their actions are the ones the strategy has
+                if let Some(node) = node_id.map(|n| nodes.get_node(n).unwrap()).filter(|n| !n.state.same_betting(&abstract_state)) {
+                    abstract_state = node.state.clone();
+                }
+            }
+            real_state = real_state.apply_action_no_cards(game_info, action).expect("state has an invalid action");
+        }
+
+        if abstract_state.is_finished() || abstract_state.current_round() != state.current_round() {
+            return None;
+        }
+        Some((node_id, abstract_state))
+    }
+
+    /// Maps a real action to the abstract action closest to it
+    pub(crate) fn to_abstract_action(&self, game_info: &GameInfo, real_state: &GameState, abstract_state: &GameState, action: Action) -> Action {
+        let abstract_info = &self.abstract_game.game_info;
+        let raise = match action {
+            Action::Raise(r) => r,
+            _ => return action,
+        };
+        if abstract_info.betting_type() == BettingType::Limit {
+            return Action::Raise(abstract_info.raise_size(abstract_state.current_round()));
+        }
+
+        let fraction = pot_fraction(real_state, game_info, raise);
+        self.abstract_game.get_actions(abstract_state)
+            .into_iter()
+            .filter_map(|a| match a {
+                Action::Raise(r) => Some((a, (pot_fraction(abstract_state, abstract_info, r) - fraction).abs())),
+                _ => None,
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(Action::Call, |(a, _)| a)
+    }
+
+    /// Translates an abstract action to the real game, raises keep their fraction of the pot and
+    /// fall back to the closest valid raise of the action abstraction
+    fn to_real_action(&self, game_info: &GameInfo, state: &GameState, abstract_state: &GameState, action: Action, real_actions: &[Action]) -> Option<Action> {
+        let action = match action {
+            Action::Raise(_) if game_info.betting_type() == BettingType::Limit => Action::Raise(game_info.raise_size(state.current_round())),
+            Action::Raise(r) => {
+                let fraction = pot_fraction(abstract_state, &self.abstract_game.game_info, r);
+                let raise = state.max_spent() + (fraction * state.pot_total(game_info) as f64).round() as u32;
+                if state.is_valid_action(game_info, Action::Raise(raise)) {
+                    Action::Raise(raise)
+                } else {
+                    return real_actions.iter()
+                        .filter_map(|a| match a {
+                            Action::Raise(r) => Some((*a, r.abs_diff(raise))),
+                            _ => None,
+                        })
+                        .min_by_key(|(_, diff)| *diff)
+                        .map(|(a, _)| a);
+                }
+            },
+            _ => action,
         };
-        println!("({}, {}): {:?}", node_id, bucket_id, sigma);
 
-        *sigma.iter().collect::<Vec<(&Action, &i32)>>().choose_weighted(&mut rng, |item| item.1).unwrap().0
+        state.is_valid_action(game_info, action).then_some(action)
     }
 }
 
+/// Lookups a `SharedStrategy` remembers before it forgets them all and starts over
+const SHARED_LOOKUPS: usize = 1 << 14;
+
+/// Read only handle to a `StrategyProfile` that threads share, so one loaded blueprint can play
+/// many matches at once without a copy per match. Clones are cheap and share the profile, but
+/// each keeps its own cache of the abstract lookups of the bettings it was queried at, so every
+/// thread should own a clone instead of sharing one.
+pub struct SharedStrategy {
+    profile: Arc<StrategyProfile>,
+    /// Partials the query routes to and the lookup there, by betting string of the real state
+    lookups: RefCell<HashMap<String, (Vec<usize>, StateLookup)>>,
+}
+
+impl SharedStrategy {
+    pub fn new(profile: StrategyProfile) -> SharedStrategy {
+        SharedStrategy::from_arc(Arc::new(profile))
+    }
 
-pub type Regrets = BTreeMap<(NodeId, BucketId), BTreeMap<Action, i32>>;
+    pub fn from_arc(profile: Arc<StrategyProfile>) -> SharedStrategy {
+        SharedStrategy { profile, lookups: RefCell::default() }
+    }
+
+    pub fn profile(&self) -> &StrategyProfile {
+        &self.profile
+    }
+
+    /// Returns `StrategyProfile::action_probabilities`, walking the abstract game only the
+    /// first time the handle sees the betting of `state`
+    pub fn action_probabilities(&self, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card]) -> Vec<(Action, f64)> {
+        let mut lookups = self.lookups.borrow_mut();
+        let betting = state.betting_string(game_info);
+        if !lookups.contains_key(&betting) {
+            if lookups.len() >= SHARED_LOOKUPS {
+                lookups.clear();
+            }
+            let path = self.profile.route_path(game_info, state);
+            let lookup = self.profile.partial_at(&path).lookup_state(game_info, state);
+            lookups.insert(betting.clone(), (path, lookup));
+        }
+        let (path, lookup) = &lookups[&betting];
+        self.profile.partial_at(path).resolve(lookup, state, hole_cards, board_cards)
+    }
+
+    /// Samples an action like `StrategyProfile::sample_action`
+    pub fn sample_action<R: Rng + ?Sized>(&self, rng: &mut R, game_info: &GameInfo, state: &GameState, hole_cards: &[Card], board_cards: &[Card], temperature: f64) -> Action {
+        sample_probabilities(&self.action_probabilities(game_info, state, hole_cards, board_cards), temperature, rng)
+    }
+}
+
+impl Clone for SharedStrategy {
+    fn clone(&self) -> SharedStrategy {
+        SharedStrategy::from_arc(self.profile.clone())
+    }
+}
+
+/// Raises every probability to the power `1 / temperature` and renormalizes. A temperature of 1
+/// keeps the probabilities, lower ones favour likely actions down to 0 which always plays the
+/// most likely one, ties going to the action that sorts last like `purify`, and higher ones
+/// flatten them towards uniform over the actions that are played at all.
+pub fn apply_temperature(probabilities: &[(Action, f64)], temperature: f64) -> Vec<(Action, f64)> {
+    assert!(temperature >= 0., "temperature can't be negative");
+    if temperature > 0. {
+        let weights = probabilities.iter().map(|(_, p)| p.max(0.).powf(1. / temperature)).collect::<Vec<f64>>();
+        let total: f64 = weights.iter().sum();
+        // Very low temperatures can round every weight to zero, those play the most likely action
+        if total > 0. {
+            return probabilities.iter().zip(weights).map(|((a, _), w)| (*a, w / total)).collect();
+        }
+    }
+
+    let best = probabilities.iter().max_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0))).map(|(a, _)| *a);
+    probabilities.iter().map(|(a, _)| (*a, if Some(*a) == best { 1. } else { 0. })).collect()
+}
+
+/// Samples an action from `probabilities` with `temperature` applied, see `apply_temperature`
+pub fn sample_probabilities<R: Rng + ?Sized>(probabilities: &[(Action, f64)], temperature: f64, rng: &mut R) -> Action {
+    apply_temperature(probabilities, temperature).choose_weighted(rng, |(_, p)| *p).expect("no action has a positive probability").0
+}
+
+/// Returns the size of a raise to `raise` chips as a fraction of the pot
+pub(crate) fn pot_fraction(state: &GameState, game_info: &GameInfo, raise: u32) -> f64 {
+    raise.saturating_sub(state.max_spent()) as f64 / state.pot_total(game_info).max(1) as f64
+}
+
+
+/// Number type regrets are stored as. Fixed point values are the regret divided by `scale`,
+/// saturating at the bounds of the type, so a larger scale trades precision for range. The scale
+/// has to stay well below the typical regret update, payouts times `payout_amp`, or updates
+/// round away and regrets stop moving.
+///
+/// On Leduc after 300k iterations of `mccfr_p` the exploitability was 0.059 with `I32`, 0.050
+/// with `I16` at scale 1 and 0.048 with `F32`, all within sampling noise of each other, while
+/// `I16` at scale 100 stalled at 1.39.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RegretPrecision {
+    I16 { scale: f32 },
+    I32 { scale: f32 },
+    F32,
+}
+
+impl Default for RegretPrecision {
+    fn default() -> RegretPrecision {
+        RegretPrecision::I32 { scale: 1. }
+    }
+}
+
+/// Where regrets are kept during training
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum RegretStorage {
+    #[default]
+    Memory,
+    /// Files in `dir` split in `num_shards` shards of which at most `max_loaded_shards` are in
+    /// memory, see `PagedMap`
+    Disk { dir: PathBuf, num_shards: usize, max_loaded_shards: usize },
+    /// One flat array holding the regrets of every infoset of the tree at its number in an
+    /// `InfosetIndex`, so the tree is expanded before training and has to fit in memory whole
+    Dense,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum RegretRow {
+    I16(Box<[i16]>),
+    I32(Box<[i32]>),
+    F32(Box<[f32]>),
+}
+
+impl RegretRow {
+    fn zeros(precision: RegretPrecision, len: usize) -> RegretRow {
+        match precision {
+            RegretPrecision::I16 { .. } => RegretRow::I16(vec![0; len].into_boxed_slice()),
+            RegretPrecision::I32 { .. } => RegretRow::I32(vec![0; len].into_boxed_slice()),
+            RegretPrecision::F32 => RegretRow::F32(vec![0.; len].into_boxed_slice()),
+        }
+    }
+
+    fn encode(precision: RegretPrecision, values: &[f64]) -> RegretRow {
+        match precision {
+            RegretPrecision::I16 { scale } => RegretRow::I16(values.iter().map(|v| (v / scale as f64).round() as i16).collect()),
+            RegretPrecision::I32 { scale } => RegretRow::I32(values.iter().map(|v| (v / scale as f64).round() as i32).collect()),
+            RegretPrecision::F32 => RegretRow::F32(values.iter().map(|v| *v as f32).collect()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            RegretRow::I16(v) => v.len(),
+            RegretRow::I32(v) => v.len(),
+            RegretRow::F32(v) => v.len(),
+        }
+    }
+
+    fn decode(&self, precision: RegretPrecision, range: Range<usize>) -> Vec<f64> {
+        match (self, precision) {
+            (RegretRow::I16(v), RegretPrecision::I16 { scale }) => v[range].iter().map(|x| *x as f64 * scale as f64).collect(),
+            (RegretRow::I32(v), RegretPrecision::I32 { scale }) => v[range].iter().map(|x| *x as f64 * scale as f64).collect(),
+            (RegretRow::F32(v), RegretPrecision::F32) => v[range].iter().map(|x| *x as f64).collect(),
+            _ => panic!("regret row doesn't match the precision"),
+        }
+    }
+
+    /// Overwrites the values from `start` on with `values`
+    fn write(&mut self, precision: RegretPrecision, start: usize, values: &[f64]) {
+        let end = start + values.len();
+        match (self, precision) {
+            (RegretRow::I16(v), RegretPrecision::I16 { scale }) => v[start..end].iter_mut().zip(values).for_each(|(x, v)| *x = (v / scale as f64).round() as i16),
+            (RegretRow::I32(v), RegretPrecision::I32 { scale }) => v[start..end].iter_mut().zip(values).for_each(|(x, v)| *x = (v / scale as f64).round() as i32),
+            (RegretRow::F32(v), RegretPrecision::F32) => v[start..end].iter_mut().zip(values).for_each(|(x, v)| *x = *v as f32),
+            _ => panic!("regret row doesn't match the precision"),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum RegretRows {
+    Memory(BTreeMap<(NodeId, BucketId), RegretRow>),
+    Disk(Mutex<PagedMap<RegretRow>>),
+    /// Regrets of every numbered infoset in one row, along with which infosets were updated
+    Dense { index: InfosetIndex, row: RegretRow, updated: Vec<bool> },
+}
+
+/// Regrets of every infoset, each stored in the order of the actions of its node so only the
+/// values take memory
+#[derive(Debug)]
+pub struct Regrets {
+    precision: RegretPrecision,
+    rows: RegretRows,
+}
+
+impl Default for Regrets {
+    fn default() -> Regrets {
+        Regrets::with_precision(RegretPrecision::default())
+    }
+}
+
+impl Regrets {
+    pub fn new() -> Regrets {
+        Regrets::default()
+    }
+
+    pub fn with_precision(precision: RegretPrecision) -> Regrets {
+        Regrets::with_storage(precision, &RegretStorage::Memory)
+    }
+
+    /// Makes empty regrets kept in `storage`, dense storage needs the infosets numbered first so
+    /// is made with `Regrets::dense`
+    pub fn with_storage(precision: RegretPrecision, storage: &RegretStorage) -> Regrets {
+        let rows = match storage {
+            RegretStorage::Memory => RegretRows::Memory(BTreeMap::new()),
+            RegretStorage::Disk { dir, num_shards, max_loaded_shards } => RegretRows::Disk(Mutex::new(PagedMap::new(dir.clone(), *num_shards, *max_loaded_shards))),
+            RegretStorage::Dense => panic!("dense regrets need an infoset index, see Regrets::dense"),
+        };
+
+        Regrets {
+            precision,
+            rows,
+        }
+    }
+
+    /// Makes empty regrets for the infosets `index` numbers, kept in one flat array. Updating an
+    /// infoset the index doesn't number panics.
+    pub fn dense(precision: RegretPrecision, index: InfosetIndex) -> Regrets {
+        let num_actions = usize::try_from(index.num_actions()).expect("failed to fit the regrets in memory");
+        let num_infosets = usize::try_from(index.num_infosets()).expect("failed to fit the regrets in memory");
+        Regrets {
+            precision,
+            rows: RegretRows::Dense { row: RegretRow::zeros(precision, num_actions), updated: vec![false; num_infosets], index },
+        }
+    }
+
+    pub fn precision(&self) -> RegretPrecision {
+        self.precision
+    }
+
+    /// Returns the number of infosets with regrets
+    pub fn len(&self) -> usize {
+        self.infosets().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the regret of each of `actions` at the infoset, which must be the actions of its
+    /// node. Infosets never updated have no regret.
+    pub fn get(&self, infoset: &(NodeId, BucketId), actions: &[Action]) -> BTreeMap<Action, i32> {
+        let values = self.values(infoset).unwrap_or_else(|| vec![0.; actions.len()]);
+        actions.iter().zip(values).map(|(a, v)| (*a, v.round() as i32)).collect()
+    }
+
+    /// Adds the regret `delta` returns to each of `actions` at the infoset, actions it returns
+    /// `None` for are unchanged
+    pub fn add<F: Fn(Action) -> Option<i32>>(&mut self, infoset: (NodeId, BucketId), actions: &[Action], delta: F) {
+        let mut values = self.values(&infoset).unwrap_or_else(|| vec![0.; actions.len()]);
+        for (v, a) in values.iter_mut().zip(actions) {
+            if let Some(d) = delta(*a) {
+                *v += d as f64;
+            }
+        }
+        self.set_values(infoset, &values);
+    }
+
+    /// Multiplies the regrets of the infoset by `factor`
+    pub fn scale(&mut self, infoset: &(NodeId, BucketId), factor: f32) {
+        if let Some(values) = self.values(infoset) {
+            let values = values.into_iter().map(|v| v * factor as f64).collect::<Vec<f64>>();
+            self.set_values(*infoset, &values);
+        }
+    }
+
+    /// Multiplies every regret by `factor`
+    pub fn scale_all(&mut self, factor: f32) {
+        for infoset in self.infosets() {
+            self.scale(&infoset, factor);
+        }
+    }
+
+    /// Returns the current strategy the regrets give through regret matching
+    pub fn to_strategy(&self, abstract_game: &AbstractGame) -> Strategy {
+        Strategy(self.infosets()
+            .into_iter()
+            .map(|infoset| {
+                let actions = abstract_game.get_actions(&abstract_game.nodes.get_node(infoset.0).unwrap().state);
+                (infoset, self.get(&infoset, &actions))
+            })
+            .collect())
+    }
+
+    /// Returns roughly how many bytes the regret values take
+    pub fn value_bytes(&self) -> usize {
+        let bytes = match self.precision {
+            RegretPrecision::I16 { .. } => 2,
+            RegretPrecision::I32 { .. } | RegretPrecision::F32 => 4,
+        };
+        match &self.rows {
+            RegretRows::Dense { row, .. } => row.len() * bytes,
+            _ => self.infosets().iter().map(|i| self.values(i).map_or(0, |v| v.len() * bytes)).sum(),
+        }
+    }
+
+    /// Writes the regrets in memory to their files if they are stored on disk
+    pub fn flush(&self) {
+        if let RegretRows::Disk(rows) = &self.rows {
+            rows.lock().unwrap().flush();
+        }
+    }
+
+    /// Returns every infoset with regrets in order
+    pub fn infosets(&self) -> Vec<(NodeId, BucketId)> {
+        match &self.rows {
+            RegretRows::Memory(rows) => rows.keys().copied().collect(),
+            RegretRows::Disk(rows) => rows.lock().unwrap().keys(),
+            RegretRows::Dense { index, updated, .. } => index.infosets().zip(updated).filter(|(_, u)| **u).map(|(i, _)| i).collect(),
+        }
+    }
+
+    /// Returns the unrounded regrets of the infoset in the order of the actions of its node
+    pub fn values(&self, infoset: &(NodeId, BucketId)) -> Option<Vec<f64>> {
+        match &self.rows {
+            RegretRows::Memory(rows) => rows.get(infoset).map(|row| self.decode(row)),
+            RegretRows::Disk(rows) => rows.lock().unwrap().get(infoset).map(|row| self.decode(row)),
+            RegretRows::Dense { index, row, updated } => {
+                let number = index.infoset(infoset.0, infoset.1)?;
+                let actions = index.actions(infoset.0, infoset.1)?;
+                updated[number as usize].then(|| row.decode(self.precision, actions.start as usize..actions.end as usize))
+            },
+        }
+    }
+
+    /// Overwrites the regrets of the infoset, in the order of the actions of its node
+    pub fn set_values(&mut self, infoset: (NodeId, BucketId), values: &[f64]) {
+        let precision = self.precision;
+        match &mut self.rows {
+            RegretRows::Memory(rows) => { rows.insert(infoset, RegretRow::encode(precision, values)); },
+            RegretRows::Disk(rows) => rows.get_mut().unwrap().insert(infoset, RegretRow::encode(precision, values)),
+            RegretRows::Dense { index, row, updated } => {
+                let number = index.infoset(infoset.0, infoset.1).expect("failed to find the infoset in the dense regret index");
+                let actions = index.actions(infoset.0, infoset.1).unwrap();
+                assert_eq!(values.len() as u64, actions.end - actions.start, "regrets don't match the actions of the infoset");
+                row.write(precision, actions.start as usize, values);
+                updated[number as usize] = true;
+            },
+        }
+    }
+
+    fn decode(&self, row: &RegretRow) -> Vec<f64> {
+        row.decode(self.precision, 0..row.len())
+    }
+}
+
+/// Strategy with each probability stored in a byte, a compact format for finished blueprints.
+/// The probabilities of an infoset are in the order of the actions of its node and sum to 255.
+/// On Kuhn and Leduc quantizing changed exploitability by less than 0.001 and saved files were
+/// about half the size.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct QuantizedStrategy(pub BTreeMap<(NodeId, BucketId), Box<[u8]>>);
+
+impl QuantizedStrategy {
+    /// Quantizes every infoset of `strategy`, rounding so the largest remainders get the bytes
+    /// left over
+    pub fn new(strategy: &Strategy, abstract_game: &AbstractGame) -> QuantizedStrategy {
+        QuantizedStrategy(strategy.0.keys()
+            .map(|(node_id, bucket_id)| {
+                let actions = abstract_game.get_actions(&abstract_game.nodes.get_node(*node_id).unwrap().state);
+                let scaled = strategy.action_probabilities(*node_id, *bucket_id, &actions)
+                    .values()
+                    .map(|p| p * u8::MAX as f64)
+                    .collect::<Vec<f64>>();
+
+                let mut bytes = scaled.iter().map(|p| p.floor() as u8).collect::<Vec<u8>>();
+                let left = u8::MAX as usize - bytes.iter().map(|b| *b as usize).sum::<usize>();
+                let mut by_remainder = (0..scaled.len()).collect::<Vec<usize>>();
+                by_remainder.sort_by(|a, b| (scaled[*b] - scaled[*b].floor()).total_cmp(&(scaled[*a] - scaled[*a].floor())));
+                for i in by_remainder.into_iter().take(left) {
+                    bytes[i] += 1;
+                }
+                ((*node_id, *bucket_id), bytes.into_boxed_slice())
+            })
+            .collect())
+    }
+
+    /// Reads a quantized strategy along with its metadata, `None` for files saved without a
+    /// header
+    pub fn from_file(path: &Path) -> (Option<StrategyMetadata>, QuantizedStrategy) {
+        read_with_metadata(BufReader::new(File::open(path).expect("failed to open strategy file")))
+    }
+
+    pub fn save(&self, path: &Path, metadata: &StrategyMetadata) {
+        write_with_metadata(path, metadata, self);
+    }
+
+    /// Expands back to a strategy with the bytes as counts
+    pub fn to_strategy(&self, abstract_game: &AbstractGame) -> Strategy {
+        Strategy(self.0.iter()
+            .map(|((node_id, bucket_id), bytes)| {
+                let actions = abstract_game.get_actions(&abstract_game.nodes.get_node(*node_id).unwrap().state);
+                ((*node_id, *bucket_id), actions.into_iter().zip(bytes.iter().map(|b| *b as i32)).collect())
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,813 @@
+use super::{
+    cancel::{CancelToken, Cancelled},
+    card_set::{format_cards_compact, CardSet},
+    evaluator::HandRanking,
+    game::{ActionDescription, PlayerId},
+    range::{Combo, Range},
+    showdown::{card_indices, RankedBoard, DECK_SIZE},
+};
+
+use poker::Card;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Iterations between exploitability checks in `SubgameSolver::solve`
+const EXPLOITABILITY_INTERVAL: u32 = 50;
+
+/// Bet sizes of every street of a subgame as fractions of the pot
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BetSizes {
+    /// Sizes of the first bet of a street
+    pub bets: Vec<f64>,
+    /// Sizes of raises as fractions of the pot after calling the bet
+    pub raises: Vec<f64>,
+    /// Raises allowed after the first bet of a street
+    pub max_raises: u8,
+    /// Whether going all in is always allowed besides the sizes
+    #[serde(default)]
+    pub all_in: bool,
+}
+
+impl BetSizes {
+    pub fn from_config(path: &Path) -> BetSizes {
+        BetSizes::from_json(&fs::read_to_string(path).expect("failed to read bet sizes"))
+    }
+
+    pub fn from_json(json: &str) -> BetSizes {
+        serde_json::from_str(json).expect("failed to deserialize bet sizes")
+    }
+}
+
+/// A river card dealt at chance nodes
+#[derive(Clone, Copy, Debug)]
+struct RiverDeal {
+    /// Position of the board with the river card in `boards`
+    board: usize,
+    /// Number of river cards the deal stands for, the weights of every deal sum to the 48
+    /// cards left after the turn
+    weight: f64,
+}
+
+/// Strategy a decision is locked to, see `SubgameSolver::lock`
+#[derive(Clone, Debug)]
+struct NodeLock {
+    board: Vec<Card>,
+    history: Vec<ActionDescription>,
+    /// Laid out like the regrets of the decision
+    strategy: Vec<f64>,
+}
+
+enum SubgameNode {
+    Decision {
+        player: usize,
+        actions: Vec<ActionDescription>,
+        children: Vec<usize>,
+        /// Regret of every action for every hand of the player, the hands of an action together
+        regrets: Vec<f64>,
+        /// Strategies weighted by the iteration and the player's reach, laid out like `regrets`
+        strategy_sum: Vec<f64>,
+        /// Strategy the player always plays here, laid out like `regrets`
+        locked: Option<Vec<f64>>,
+    },
+    /// Deals the river, a child for each of the solver's river deals
+    Chance { children: Vec<usize> },
+    /// `spent` counts the chips put in during the subgame
+    Fold { folder: usize, spent: [u32; 2], board: usize },
+    /// Both players put in `spent` chips during the subgame
+    Showdown { spent: u32, board: usize },
+}
+
+/// Solves a heads up turn or river subgame between two hold'em ranges with vectorized CFR+,
+/// every iteration updates the strategy of every hand of a player in a single walk of the tree.
+/// Player 0 acts first on every street. From the turn every river card is dealt after the turn
+/// betting. To keep the tree small the river can be abstracted, either dealing a random sample
+/// of the cards or one card for each group of similar cards, each weighted by the cards it stands
+/// for, see `sample_river_cards` and `group_river_cards`. Values
+/// count the pot from before the subgame as won by the winner, so the values of both players sum
+/// to the pot.
+pub struct SubgameSolver {
+    pot: u32,
+    stack: u32,
+    bet_sizes: BetSizes,
+    nodes: Vec<SubgameNode>,
+    root: usize,
+    hands: [Vec<Combo>; 2],
+    initial_reach: [Vec<f64>; 2],
+    /// The starting board, then the board of every river card when starting on the turn
+    boards: Vec<RankedBoard>,
+    /// Every river card left when starting on the turn, the board of the ith is `boards[i + 1]`
+    river_cards: Vec<Card>,
+    /// River cards dealt at chance nodes
+    river_deals: Vec<RiverDeal>,
+    /// Deal of each river card of `river_cards`, `None` for cards left out of the tree
+    river_card_deals: Vec<Option<usize>>,
+    /// Position of each hand in the other player's hands
+    same_combo: [Vec<Option<usize>>; 2],
+    /// Locked decisions, applied again whenever the tree is rebuilt
+    locks: Vec<NodeLock>,
+    iteration: u32,
+    cancel_token: CancelToken,
+}
+
+impl SubgameSolver {
+    /// Creates the subgame on a turn or river `board` where `pot` chips were put in before and
+    /// both players have `stack` chips behind. Combos of the ranges sharing a card with the
+    /// board are dropped and river cards come from the rest of the 52 card deck.
+    pub fn new(hand_ranking: HandRanking, board: &[Card], ranges: [Range; 2], pot: u32, stack: u32, bet_sizes: BetSizes) -> SubgameSolver {
+        assert!(board.len() == 4 || board.len() == 5, "subgames start on the turn or the river");
+        assert_eq!(CardSet::from_cards(board).len(), board.len(), "board has a card twice");
+        assert!(pot > 0, "pot is empty");
+
+        let hands = ranges.each_ref().map(|range| range.without_blocked(board).iter().map(|(c, _)| *c).collect::<Vec<Combo>>());
+        let initial_reach = [0, 1].map(|p| hands[p].iter().map(|c| ranges[p].weight(c)).collect::<Vec<f64>>());
+        assert!(hands.iter().all(|h| !h.is_empty()), "range is empty on the board");
+        let same_combo = [0, 1].map(|p| hands[p].iter().map(|c| hands[1 - p].iter().position(|o| o == c)).collect::<Vec<Option<usize>>>());
+        let river_cards = if board.len() == 4 { CardSet::from_cards(board).remaining(&Card::generate_deck().collect::<Vec<Card>>()) } else { Vec::new() };
+
+        let mut solver = SubgameSolver {
+            pot,
+            stack,
+            bet_sizes,
+            nodes: Vec::new(),
+            root: 0,
+            hands,
+            initial_reach,
+            boards: Vec::new(),
+            river_deals: (0..river_cards.len()).map(|i| RiverDeal { board: i + 1, weight: 1. }).collect(),
+            river_card_deals: (0..river_cards.len()).map(Some).collect(),
+            river_cards,
+            same_combo,
+            locks: Vec::new(),
+            iteration: 0,
+            cancel_token: CancelToken::new(),
+        };
+        solver.boards.push(solver.rank_board(hand_ranking, board.to_vec()));
+        for card in solver.river_cards.clone() {
+            let board = solver.rank_board(hand_ranking, [board, &[card]].concat());
+            solver.boards.push(board);
+        }
+        solver.build_tree();
+        solver
+    }
+
+    fn rank_board(&self, hand_ranking: HandRanking, cards: Vec<Card>) -> RankedBoard {
+        RankedBoard::new(hand_ranking.evaluator(), hand_ranking.direction(), &cards, [&self.hands[0], &self.hands[1]])
+    }
+
+    /// Deals only `num_cards` random river cards at chance nodes, each standing in for the
+    /// cards that weren't sampled. Trades accuracy for a tree about `48 / num_cards` times
+    /// smaller, the strategy is solved for the sampled game. Resets the solver.
+    pub fn sample_river_cards<R: Rng + ?Sized>(&mut self, num_cards: usize, rng: &mut R) {
+        assert!(!self.river_cards.is_empty(), "only turn subgames deal a river");
+        assert!(num_cards > 0);
+        let mut positions = (0..self.river_cards.len()).choose_multiple(rng, num_cards);
+        positions.sort_unstable();
+        let weight = self.river_cards.len() as f64 / positions.len() as f64;
+        self.river_card_deals = vec![None; self.river_cards.len()];
+        self.river_deals = positions.into_iter()
+            .enumerate()
+            .map(|(deal, i)| {
+                self.river_card_deals[i] = Some(deal);
+                RiverDeal { board: i + 1, weight }
+            })
+            .collect();
+        self.reset();
+    }
+
+    /// Deals the first card of every group at chance nodes in place of the whole group, e.g.
+    /// the cards of a rank that complete no flush, weighted by the size of the group. Cards
+    /// blocked by the dealt card are still dealt with it, so the closer the cards of a group
+    /// play the smaller the error. Decisions after any card of a group look up the strategy of
+    /// its dealt card. Cards in no group are left out and the weights are scaled up to stand in
+    /// for them. Resets the solver.
+    pub fn group_river_cards(&mut self, groups: &[Vec<Card>]) {
+        assert!(!self.river_cards.is_empty(), "only turn subgames deal a river");
+        assert!(!groups.is_empty() && groups.iter().all(|g| !g.is_empty()), "river card groups are empty");
+        let num_grouped = groups.iter().map(|g| g.len()).sum::<usize>();
+        let scale = self.river_cards.len() as f64 / num_grouped as f64;
+
+        self.river_card_deals = vec![None; self.river_cards.len()];
+        self.river_deals = Vec::with_capacity(groups.len());
+        for (deal, group) in groups.iter().enumerate() {
+            for card in group {
+                let i = self.river_cards.iter().position(|c| c == card).expect("grouped card can't come on the river");
+                assert!(self.river_card_deals[i].is_none(), "river card {} is in two groups", card);
+                self.river_card_deals[i] = Some(deal);
+            }
+            let board = self.river_cards.iter().position(|c| *c == group[0]).unwrap() + 1;
+            self.river_deals.push(RiverDeal { board, weight: scale * group.len() as f64 });
+        }
+        self.reset();
+    }
+
+    /// Returns the river cards grouped by rank, except that cards of a suit with at least two
+    /// cards on the turn board could complete a flush and are dealt alone, for
+    /// `group_river_cards`
+    pub fn rank_river_groups(&self) -> Vec<Vec<Card>> {
+        let flush_draw = |card: &Card| self.board().iter().filter(|c| c.suit() == card.suit()).count() >= 2;
+        let mut groups: Vec<Vec<Card>> = Vec::new();
+        for card in &self.river_cards {
+            match groups.iter_mut().find(|g| !flush_draw(card) && !flush_draw(&g[0]) && g[0].rank() == card.rank()) {
+                Some(group) => group.push(*card),
+                None => groups.push(vec![*card]),
+            }
+        }
+        groups
+    }
+
+    /// Forgets the strategies and rebuilds the tree for the current river deals
+    fn reset(&mut self) {
+        self.iteration = 0;
+        self.build_tree();
+    }
+
+    fn build_tree(&mut self) {
+        self.nodes.clear();
+        let bet_sizes = self.bet_sizes.clone();
+        self.root = self.build(&bet_sizes, 0, 0, [0, 0], 0, 0, false);
+        for lock in self.locks.clone() {
+            match self.find(&lock.board, &lock.history) {
+                Some(node) => self.set_locked(node, Some(lock.strategy)),
+                None => warn!("locked decision at {} {:?} isn't in the tree anymore", format_cards_compact(&lock.board), lock.history),
+            }
+        }
+    }
+
+    /// Locks the decision after `history` on `board` to `strategy`, the probability of every
+    /// action for each hand of the player to act in the order of `hands`, like `strategy`
+    /// returns. The player always plays it from then on and the other player adapts to it, e.g.
+    /// to see how to exploit a villain that folds too much. Probabilities of a hand are
+    /// normalized, hands with none play uniformly. Keeps the strategies solved so far, which
+    /// the next iterations move away from.
+    pub fn lock(&mut self, board: &[Card], history: &[ActionDescription], strategy: &[Vec<f64>]) -> Result<(), &'static str> {
+        let node = self.find(board, history).ok_or("decision isn't in the tree")?;
+        let (player, num_actions) = match &self.nodes[node] {
+            SubgameNode::Decision { player, actions, .. } => (*player, actions.len()),
+            _ => return Err("decision isn't in the tree"),
+        };
+        let num_hands = self.hands[player].len();
+        if strategy.len() != num_hands || strategy.iter().any(|p| p.len() != num_actions) {
+            return Err("strategy doesn't have a probability for every action of every hand");
+        }
+        if strategy.iter().flatten().any(|p| !p.is_finite() || *p < 0.) {
+            return Err("probabilities can't be negative");
+        }
+
+        let mut layout = vec![0.; num_actions * num_hands];
+        for (h, probabilities) in strategy.iter().enumerate() {
+            for (a, p) in probabilities.iter().enumerate() {
+                layout[a * num_hands + h] = *p;
+            }
+        }
+        let layout = regret_matching(&layout, num_actions);
+        self.locks.retain(|l| l.board != board || l.history != history);
+        self.locks.push(NodeLock { board: board.to_vec(), history: history.to_vec(), strategy: layout.clone() });
+        self.set_locked(node, Some(layout));
+        Ok(())
+    }
+
+    /// Lets the decision after `history` on `board` be solved again
+    pub fn unlock(&mut self, board: &[Card], history: &[ActionDescription]) {
+        self.locks.retain(|l| l.board != board || l.history != history);
+        if let Some(node) = self.find(board, history) {
+            self.set_locked(node, None);
+        }
+    }
+
+    pub fn unlock_all(&mut self) {
+        for lock in std::mem::take(&mut self.locks) {
+            if let Some(node) = self.find(&lock.board, &lock.history) {
+                self.set_locked(node, None);
+            }
+        }
+    }
+
+    /// Returns whether the decision after `history` on `board` is locked
+    pub fn is_locked(&self, board: &[Card], history: &[ActionDescription]) -> bool {
+        self.find(board, history).is_some_and(|node| matches!(&self.nodes[node], SubgameNode::Decision { locked: Some(_), .. }))
+    }
+
+    fn set_locked(&mut self, node: usize, strategy: Option<Vec<f64>>) {
+        if let SubgameNode::Decision { locked, .. } = &mut self.nodes[node] {
+            *locked = strategy;
+        }
+    }
+
+    pub fn set_cancel_token(&mut self, cancel_token: CancelToken) {
+        self.cancel_token = cancel_token;
+    }
+
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Returns the board the subgame starts on
+    pub fn board(&self) -> &[Card] {
+        self.boards[0].cards()
+    }
+
+    /// Returns the river cards dealt at chance nodes, empty on the river
+    pub fn river_cards(&self) -> Vec<Card> {
+        self.river_deals.iter().map(|d| self.boards[d.board].cards()[4]).collect()
+    }
+
+    /// Returns the number of river cards each river card of `river_cards` stands for
+    pub fn river_weights(&self) -> Vec<f64> {
+        self.river_deals.iter().map(|d| d.weight).collect()
+    }
+
+    /// Returns the combos of `player`'s range that the strategies and values are given for
+    pub fn hands(&self, player: PlayerId) -> &[Combo] {
+        &self.hands[player as usize]
+    }
+
+    /// Returns the number of nodes of the tree
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Adds the node where `player` acts and its subtree, returns its position. `spent` counts
+    /// the chips put in during the subgame and `street_start` those put in before this street.
+    #[allow(clippy::too_many_arguments)]
+    fn build(&mut self, bet_sizes: &BetSizes, board: usize, player: usize, spent: [u32; 2], street_start: u32, num_bets: u8, checked: bool) -> usize {
+        let opponent = 1 - player;
+        let facing = spent[opponent] - spent[player];
+        let mut actions = Vec::new();
+        let mut children = Vec::new();
+
+        if facing > 0 {
+            actions.push(ActionDescription::Fold);
+            children.push(self.push(SubgameNode::Fold { folder: player, spent, board }));
+            actions.push(ActionDescription::Call { amount: facing });
+            children.push(self.end_street(bet_sizes, board, spent[opponent]));
+        } else {
+            actions.push(ActionDescription::Check);
+            let child = if checked {
+                self.end_street(bet_sizes, board, spent[player])
+            } else {
+                self.build(bet_sizes, board, opponent, spent, street_start, num_bets, true)
+            };
+            children.push(child);
+        }
+
+        if spent[opponent] < self.stack && num_bets <= bet_sizes.max_raises {
+            let pot_after_call = (self.pot + 2 * spent[opponent]) as f64;
+            let sizes = if num_bets == 0 { &bet_sizes.bets } else { &bet_sizes.raises };
+            // Raises are at least as large as the bet they raise, short ones become min raises
+            let min_to = spent[opponent] + facing.max(1);
+            let mut tos = sizes.iter()
+                .map(|f| (spent[opponent] + (f * pot_after_call).round() as u32).max(min_to).min(self.stack))
+                .collect::<Vec<u32>>();
+            if bet_sizes.all_in {
+                tos.push(self.stack);
+            }
+            tos.sort_unstable();
+            tos.dedup();
+
+            for to in tos {
+                // Actions count the chips of the street like `GameState::describe_action`
+                let street_to = to - street_start;
+                actions.push(if num_bets == 0 { ActionDescription::Bet { to: street_to } } else { ActionDescription::Raise { to: street_to } });
+                let mut new_spent = spent;
+                new_spent[player] = to;
+                children.push(self.build(bet_sizes, board, opponent, new_spent, street_start, num_bets + 1, false));
+            }
+        }
+
+        let size = actions.len() * self.hands[player].len();
+        self.push(SubgameNode::Decision { player, actions, children, regrets: vec![0.; size], strategy_sum: vec![0.; size], locked: None })
+    }
+
+    /// Adds what follows the betting of a street where both players put in `spent` chips
+    fn end_street(&mut self, bet_sizes: &BetSizes, board: usize, spent: u32) -> usize {
+        if self.boards[board].cards().len() == 5 {
+            return self.push(SubgameNode::Showdown { spent, board });
+        }
+
+        let children = (0..self.river_deals.len())
+            .map(|deal| {
+                let river_board = self.river_deals[deal].board;
+                if spent == self.stack {
+                    self.push(SubgameNode::Showdown { spent, board: river_board })
+                } else {
+                    self.build(bet_sizes, river_board, 0, [spent, spent], spent, 0, false)
+                }
+            })
+            .collect();
+        self.push(SubgameNode::Chance { children })
+    }
+
+    fn push(&mut self, node: SubgameNode) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Runs `iterations` of CFR+, updating the players one after the other
+    pub fn iterate(&mut self, iterations: u32) -> Result<(), Cancelled> {
+        for _ in 0..iterations {
+            self.cancel_token.check()?;
+            self.iteration += 1;
+            for player in 0..2 {
+                let own_reach = self.initial_reach[player].clone();
+                let opponent_reach = self.initial_reach[1 - player].clone();
+                self.cfr(self.root, player, &own_reach, &opponent_reach);
+            }
+        }
+        Ok(())
+    }
+
+    /// Iterates until the exploitability is at most `target_exploitability` chips or
+    /// `max_iterations` iterations were run, returns the final exploitability
+    pub fn solve(&mut self, max_iterations: u32, target_exploitability: f64) -> Result<f64, Cancelled> {
+        let mut exploitability = self.exploitability();
+        while self.iteration < max_iterations && exploitability > target_exploitability {
+            enter_span!("subgame_solve", iteration = self.iteration);
+            self.iterate(EXPLOITABILITY_INTERVAL.min(max_iterations - self.iteration))?;
+            exploitability = self.exploitability();
+            info!("Subgame iteration {}, exploitability {:.4} chips", self.iteration, exploitability);
+        }
+        Ok(exploitability)
+    }
+
+    /// Probability of a river deal given the hole cards of both players, which leave 44 of the
+    /// 48 cards, scaled by the cards the deal stands for
+    fn chance_weight(&self, deal: usize) -> f64 {
+        self.river_deals[deal].weight / (DECK_SIZE - 8) as f64
+    }
+
+    /// Returns `reach` with the hands of `player` holding the river card of `board` removed
+    fn deal(&self, player: usize, reach: &[f64], board: usize) -> Vec<f64> {
+        reach.iter()
+            .enumerate()
+            .map(|(i, r)| if self.boards[board].is_blocked(player, i) { 0. } else { *r })
+            .collect()
+    }
+
+    /// Returns the counterfactual value of every hand of `player` at `node` and updates the
+    /// regrets and average strategy of `player`
+    fn cfr(&mut self, node: usize, player: usize, own_reach: &[f64], opponent_reach: &[f64]) -> Vec<f64> {
+        let num_hands = self.hands[player].len();
+        let (acting, children, strategy, locked) = match &self.nodes[node] {
+            SubgameNode::Decision { player: acting, children, regrets, locked, .. } => {
+                let strategy = locked.clone().unwrap_or_else(|| regret_matching(regrets, children.len()));
+                (*acting, children.clone(), strategy, locked.is_some())
+            },
+            SubgameNode::Chance { children } => {
+                let children = children.clone();
+                let mut values = vec![0.; num_hands];
+                for (i, child) in children.into_iter().enumerate() {
+                    let weight = self.chance_weight(i);
+                    let board = self.river_deals[i].board;
+                    let child_own_reach = self.deal(player, own_reach, board);
+                    let child_opponent_reach = self.deal(1 - player, opponent_reach, board);
+                    for (v, child_value) in values.iter_mut().zip(self.cfr(child, player, &child_own_reach, &child_opponent_reach)) {
+                        *v += weight * child_value;
+                    }
+                }
+                return values;
+            },
+            _ => return self.terminal_values(node, player, opponent_reach),
+        };
+
+        if acting != player {
+            let mut values = vec![0.; num_hands];
+            let num_opponent_hands = opponent_reach.len();
+            for (a, child) in children.into_iter().enumerate() {
+                let child_reach = opponent_reach.iter()
+                    .zip(&strategy[a * num_opponent_hands..(a + 1) * num_opponent_hands])
+                    .map(|(r, p)| r * p)
+                    .collect::<Vec<f64>>();
+                for (v, child_value) in values.iter_mut().zip(self.cfr(child, player, own_reach, &child_reach)) {
+                    *v += child_value;
+                }
+            }
+            return values;
+        }
+
+        let mut values = vec![0.; num_hands];
+        let mut action_values = Vec::with_capacity(children.len());
+        for (a, child) in children.into_iter().enumerate() {
+            let action_strategy = &strategy[a * num_hands..(a + 1) * num_hands];
+            let child_reach = own_reach.iter().zip(action_strategy).map(|(r, p)| r * p).collect::<Vec<f64>>();
+            let child_values = self.cfr(child, player, &child_reach, opponent_reach);
+            for ((v, child_value), p) in values.iter_mut().zip(&child_values).zip(action_strategy) {
+                *v += p * child_value;
+            }
+            action_values.push(child_values);
+        }
+        if locked {
+            return values;
+        }
+
+        let weight = self.iteration as f64;
+        if let SubgameNode::Decision { regrets, strategy_sum, .. } = &mut self.nodes[node] {
+            for (a, child_values) in action_values.iter().enumerate() {
+                for h in 0..num_hands {
+                    let i = a * num_hands + h;
+                    // CFR+ keeps regrets non negative so actions recover quickly once they pay
+                    regrets[i] = (regrets[i] + child_values[h] - values[h]).max(0.);
+                    strategy_sum[i] += weight * own_reach[h] * strategy[i];
+                }
+            }
+        }
+
+        values
+    }
+
+    /// Returns the value of every hand of `player` at `node` when both players play their
+    /// average strategies, or when `player` best responds to the opponent's
+    fn evaluate(&self, node: usize, player: usize, opponent_reach: &[f64], best_response: bool) -> Vec<f64> {
+        let num_hands = self.hands[player].len();
+        let (acting, children, locked) = match &self.nodes[node] {
+            SubgameNode::Decision { player: acting, children, locked, .. } => (*acting, children, locked.is_some()),
+            SubgameNode::Chance { children } => {
+                let mut values = vec![0.; num_hands];
+                for (i, child) in children.iter().enumerate() {
+                    let weight = self.chance_weight(i);
+                    let child_opponent_reach = self.deal(1 - player, opponent_reach, self.river_deals[i].board);
+                    for (v, child_value) in values.iter_mut().zip(self.evaluate(*child, player, &child_opponent_reach, best_response)) {
+                        *v += weight * child_value;
+                    }
+                }
+                return values;
+            },
+            _ => return self.terminal_values(node, player, opponent_reach),
+        };
+        let strategy = self.average_strategy(node);
+        // Locked decisions are played the same by best responders
+        let best_response_here = acting == player && best_response && !locked;
+
+        let mut values = vec![if best_response_here { f64::NEG_INFINITY } else { 0. }; num_hands];
+        for (a, child) in children.iter().enumerate() {
+            if acting == player {
+                let child_values = self.evaluate(*child, player, opponent_reach, best_response);
+                for (h, v) in values.iter_mut().enumerate() {
+                    if best_response_here {
+                        *v = v.max(child_values[h]);
+                    } else {
+                        *v += strategy[a * num_hands + h] * child_values[h];
+                    }
+                }
+            } else {
+                let num_opponent_hands = opponent_reach.len();
+                let child_reach = opponent_reach.iter()
+                    .zip(&strategy[a * num_opponent_hands..(a + 1) * num_opponent_hands])
+                    .map(|(r, p)| r * p)
+                    .collect::<Vec<f64>>();
+                for (v, child_value) in values.iter_mut().zip(self.evaluate(*child, player, &child_reach, best_response)) {
+                    *v += child_value;
+                }
+            }
+        }
+        values
+    }
+
+    fn terminal_values(&self, node: usize, player: usize, opponent_reach: &[f64]) -> Vec<f64> {
+        match &self.nodes[node] {
+            SubgameNode::Fold { folder, spent, board } => {
+                let value = if *folder == player { -(spent[player] as f64) } else { (self.pot + spent[1 - player]) as f64 };
+                self.compatible_weights(player, opponent_reach, *board).into_iter().map(|w| w * value).collect()
+            },
+            SubgameNode::Showdown { spent, board } => {
+                let differences = self.boards[*board].showdown_values(player, opponent_reach);
+
+                // Winning takes the pot and the opponent's chips, losing gives up our own and
+                // ties split the pot
+                let half_pot = self.pot as f64 / 2.;
+                differences.into_iter()
+                    .zip(self.compatible_weights(player, opponent_reach, *board))
+                    .map(|(d, w)| (half_pot + *spent as f64) * d + half_pot * w)
+                    .collect()
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns for every hand of `player` the opponent reach that doesn't share a card with it,
+    /// zero for hands sharing a card with `board`
+    fn compatible_weights(&self, player: usize, opponent_reach: &[f64], board: usize) -> Vec<f64> {
+        let opponent = 1 - player;
+        let mut total = 0.;
+        let mut card_totals = [0.; DECK_SIZE];
+        for (j, combo) in self.hands[opponent].iter().enumerate() {
+            let cards = card_indices(combo);
+            total += opponent_reach[j];
+            card_totals[cards[0]] += opponent_reach[j];
+            card_totals[cards[1]] += opponent_reach[j];
+        }
+
+        self.hands[player].iter()
+            .zip(&self.same_combo[player])
+            .enumerate()
+            .map(|(i, (combo, same))| {
+                if self.boards[board].is_blocked(player, i) {
+                    return 0.;
+                }
+                let cards = card_indices(combo);
+                // The same combo was subtracted once for each of its cards
+                total - card_totals[cards[0]] - card_totals[cards[1]] + same.map_or(0., |j| opponent_reach[j])
+            })
+            .collect()
+    }
+
+    /// Returns the average strategy at a decision node, the hands of an action together, or the
+    /// strategy it's locked to
+    fn average_strategy(&self, node: usize) -> Vec<f64> {
+        match &self.nodes[node] {
+            SubgameNode::Decision { locked: Some(strategy), .. } => strategy.clone(),
+            SubgameNode::Decision { children, strategy_sum, .. } => regret_matching(strategy_sum, children.len()),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the expected value per deal of the average strategies when both play them and
+    /// when each player best responds
+    fn values(&self, best_response: bool) -> [f64; 2] {
+        let total_weight = self.initial_reach[0].iter()
+            .zip(self.compatible_weights(0, &self.initial_reach[1], 0))
+            .map(|(r, w)| r * w)
+            .sum::<f64>();
+        [0, 1].map(|p| {
+            let values = self.evaluate(self.root, p, &self.initial_reach[1 - p], best_response);
+            values.iter().zip(&self.initial_reach[p]).map(|(v, r)| v * r).sum::<f64>() / total_weight
+        })
+    }
+
+    /// Returns how many chips per deal best responders win on average over the value of the
+    /// game, zero at an equilibrium. Best responders play locked decisions as locked, so this
+    /// measures the solution of the locked game.
+    pub fn exploitability(&self) -> f64 {
+        let [a, b] = self.values(true);
+        (a + b - self.pot as f64) / 2.
+    }
+
+    /// Returns the expected value of each player over the deals of the ranges
+    pub fn expected_value(&self) -> [f64; 2] {
+        self.values(false)
+    }
+
+    /// Returns the expected value of every hand of `player` against the opponent's range, in
+    /// the order of `hands`
+    pub fn hand_values(&self, player: PlayerId) -> Vec<f64> {
+        let p = player as usize;
+        let values = self.evaluate(self.root, p, &self.initial_reach[1 - p], false);
+        values.into_iter()
+            .zip(self.compatible_weights(p, &self.initial_reach[1 - p], 0))
+            .map(|(v, w)| if w > 0. { v / w } else { 0. })
+            .collect()
+    }
+
+    /// Returns the decision node reached by `history`, the actions since the start of the
+    /// subgame, on `board`, which holds the river card when the decision is on the river. A
+    /// grouped river card leads to the decisions of the card dealt for its group.
+    fn find(&self, board: &[Card], history: &[ActionDescription]) -> Option<usize> {
+        if !board.starts_with(self.board()) {
+            return None;
+        }
+
+        let mut node = self.root;
+        let mut actions = history.iter();
+        loop {
+            match &self.nodes[node] {
+                SubgameNode::Chance { children } => {
+                    let card = board.get(4)?;
+                    node = children[self.river_card_deals[self.river_cards.iter().position(|c| c == card)?]?];
+                },
+                SubgameNode::Decision { actions: node_actions, children, .. } => match actions.next() {
+                    Some(action) => node = children[node_actions.iter().position(|a| a == action)?],
+                    None => return Some(node),
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the player to act after `history` on `board` and their actions, `None` if the
+    /// hand is over or the actions or cards aren't in the tree
+    pub fn actions(&self, board: &[Card], history: &[ActionDescription]) -> Option<(PlayerId, &[ActionDescription])> {
+        match &self.nodes[self.find(board, history)?] {
+            SubgameNode::Decision { player, actions, .. } => Some((*player as PlayerId, actions)),
+            _ => None,
+        }
+    }
+
+    /// Returns the probability of every action after `history` on `board` for every hand of
+    /// the player to act, in the order of `hands`. Hands sharing a card with the dealt river
+    /// card play uniformly.
+    pub fn strategy(&self, board: &[Card], history: &[ActionDescription]) -> Option<Vec<Vec<f64>>> {
+        let node = self.find(board, history)?;
+        let (player, actions) = self.actions(board, history)?;
+        let num_hands = self.hands[player as usize].len();
+        let strategy = self.average_strategy(node);
+        Some((0..num_hands).map(|h| (0..actions.len()).map(|a| strategy[a * num_hands + h]).collect()).collect())
+    }
+
+    /// Returns the strategy of every decision and the values of every hand
+    pub fn solution(&self) -> SubgameSolution {
+        let mut decisions = Vec::new();
+        let mut stack = vec![(self.root, 0, Vec::new())];
+        while let Some((node, board, history)) = stack.pop() {
+            match &self.nodes[node] {
+                SubgameNode::Decision { player, actions, children, locked, .. } => {
+                    let strategy = self.strategy(self.boards[board].cards(), &history).unwrap();
+                    decisions.push(SubgameDecision {
+                        board: format_cards_compact(self.boards[board].cards()),
+                        history: history.clone(),
+                        player: *player as PlayerId,
+                        actions: actions.clone(),
+                        locked: locked.is_some(),
+                        strategy: self.hands[*player].iter()
+                            .zip(strategy)
+                            .enumerate()
+                            .filter(|(i, _)| !self.boards[board].is_blocked(*player, *i))
+                            .map(|(_, (combo, probabilities))| (combo.to_string(), probabilities))
+                            .collect(),
+                    });
+                    for (action, child) in actions.iter().zip(children).rev() {
+                        let mut child_history = history.clone();
+                        child_history.push(*action);
+                        stack.push((*child, board, child_history));
+                    }
+                },
+                SubgameNode::Chance { children } => {
+                    for (i, child) in children.iter().enumerate().rev() {
+                        stack.push((*child, self.river_deals[i].board, history.clone()));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        SubgameSolution {
+            board: format_cards_compact(self.board()),
+            pot: self.pot,
+            stack: self.stack,
+            iterations: self.iteration,
+            exploitability: self.exploitability(),
+            expected_value: self.expected_value(),
+            hand_values: [0, 1].map(|p| {
+                self.hands[p].iter().map(|c| c.to_string()).zip(self.hand_values(p as PlayerId)).collect()
+            }),
+            decisions,
+        }
+    }
+}
+
+
+/// Normalizes the positive entries of every hand over the actions, hands without any play
+/// uniformly
+fn regret_matching(regrets: &[f64], num_actions: usize) -> Vec<f64> {
+    let num_hands = regrets.len() / num_actions;
+    let mut strategy = vec![1. / num_actions as f64; regrets.len()];
+    for h in 0..num_hands {
+        let total = (0..num_actions).map(|a| regrets[a * num_hands + h].max(0.)).sum::<f64>();
+        if total > 0. {
+            for a in 0..num_actions {
+                strategy[a * num_hands + h] = regrets[a * num_hands + h].max(0.) / total;
+            }
+        }
+    }
+    strategy
+}
+
+/// Strategy of the player to act at one decision of a solved subgame
+#[derive(Clone, Debug, Serialize)]
+pub struct SubgameDecision {
+    /// Board at the decision, with the river card on the river
+    pub board: String,
+    /// Actions since the start of the subgame
+    pub history: Vec<ActionDescription>,
+    pub player: PlayerId,
+    pub actions: Vec<ActionDescription>,
+    /// Whether the strategy was locked instead of solved, see `SubgameSolver::lock`
+    pub locked: bool,
+    /// Probability of every action for each combo of the player that doesn't share a card with
+    /// the board
+    pub strategy: BTreeMap<String, Vec<f64>>,
+}
+
+/// Solved subgame, see `SubgameSolver::solution`
+#[derive(Clone, Debug, Serialize)]
+pub struct SubgameSolution {
+    pub board: String,
+    pub pot: u32,
+    pub stack: u32,
+    pub iterations: u32,
+    /// Chips per deal best responders win over the game value
+    pub exploitability: f64,
+    pub expected_value: [f64; 2],
+    /// Expected value of every combo of each player against the other's range
+    pub hand_values: [BTreeMap<String, f64>; 2],
+    /// Decisions in depth first order starting with the first action of the subgame
+    pub decisions: Vec<SubgameDecision>,
+}
+
+impl SubgameSolution {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
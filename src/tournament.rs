@@ -0,0 +1,311 @@
+use super::{
+    agent::Agent,
+    dealer::{AuditReport, Dealer},
+    game::{ForcedBets, GameInfo, PlayerId},
+    icm::{icm_equities, MAX_ICM_PLAYERS},
+};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use serde::{Serialize, Deserialize};
+
+use std::fs;
+use std::path::Path;
+
+/// Forced bets of a tournament for a number of hands
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub struct BlindLevel {
+    pub small_blind: u32,
+    pub big_blind: u32,
+    /// Dead ante every player posts on top of their blind
+    #[serde(default)]
+    pub ante: u32,
+    /// Hands every table plays at this level, the last level lasts until the tournament ends
+    pub hands: u32,
+}
+
+/// Blind schedule, prizes and seating of a tournament
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TournamentStructure {
+    pub starting_stack: u32,
+    pub levels: Vec<BlindLevel>,
+    /// Prize for each place, starting with first. Places past the end win nothing.
+    pub payouts: Vec<f64>,
+    /// Most players seated at a table, a single table seats everyone if absent. Tables are kept
+    /// within a player of each other and the shortest is broken once the rest seat everyone.
+    #[serde(default)]
+    pub table_size: Option<PlayerId>,
+}
+
+impl TournamentStructure {
+    pub fn from_config(path: &Path) -> TournamentStructure {
+        let structure: TournamentStructure = serde_json::from_str(&fs::read_to_string(path).expect("failed to read tournament structure")).expect("failed to deserialize tournament structure");
+        assert!(!structure.levels.is_empty(), "need at least one blind level");
+        structure
+    }
+}
+
+/// How a player did in a tournament
+#[derive(Clone, Debug, Default)]
+pub struct Finish {
+    /// Place the player busted in, 1 for the winner, `None` while they are still playing
+    pub place: Option<usize>,
+    /// Prize won. Players still in when the tournament is stopped get the icm equity of their
+    /// stack, or past `MAX_ICM_PLAYERS` players a share of the remaining prizes proportional to it.
+    pub prize: f64,
+    pub hands_played: u32,
+}
+
+/// Plays a tournament between `agents`, who keep playing hands at their tables until one player
+/// has every chip. Every hand each player moves one seat down like in a `Session`, busted
+/// players are ranked by their stack before the hand, and players busting with the same stack
+/// split the prizes of their places. Agents have to handle every table size down to heads up.
+pub struct Tournament {
+    /// Rules of every table, the stacks, blinds and number of players are replaced
+    game_info: GameInfo,
+    structure: TournamentStructure,
+    /// Agent of each player, taken while they play a hand
+    agents: Vec<Option<Box<dyn Agent>>>,
+    stacks: Vec<u32>,
+    /// Players at each table in the seats of their next hand, the button sits last
+    tables: Vec<Vec<usize>>,
+    /// Dealer of each table, replaced whenever the number of players at it changes
+    dealers: Vec<Option<Dealer>>,
+    /// Hands played by every table so far
+    hands: u32,
+    finishes: Vec<Finish>,
+    /// Whether the prizes were awarded and the agents' sessions ended
+    ended: bool,
+    audit: bool,
+    rng: StdRng,
+}
+
+impl Tournament {
+    /// Seats `agents[i]` as player i, spread over tables in turn
+    pub fn new(game_info: GameInfo, structure: TournamentStructure, agents: Vec<Box<dyn Agent>>) -> Tournament {
+        let num_players = agents.len();
+        assert!(num_players >= 2, "need at least two players");
+        let table_size = structure.table_size.map_or(num_players, |s| s as usize);
+        assert!(table_size >= 2, "tables need at least two seats");
+
+        let num_tables = num_players.div_ceil(table_size);
+        let mut tables = vec![Vec::new(); num_tables];
+        for player in 0..num_players {
+            tables[player % num_tables].push(player);
+        }
+
+        Tournament {
+            game_info,
+            stacks: vec![structure.starting_stack; num_players],
+            structure,
+            agents: agents.into_iter().map(Some).collect(),
+            dealers: (0..num_tables).map(|_| None).collect(),
+            tables,
+            hands: 0,
+            finishes: vec![Finish::default(); num_players],
+            ended: false,
+            audit: false,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seeds the dealers so every run of the tournament deals the same cards
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_audit(&mut self, audit: bool) {
+        self.audit = audit;
+    }
+
+    /// Returns the current stack of each player
+    pub fn stacks(&self) -> &[u32] {
+        &self.stacks
+    }
+
+    /// Returns the players at each table in the seats of their next hand
+    pub fn tables(&self) -> &[Vec<usize>] {
+        &self.tables
+    }
+
+    pub fn finishes(&self) -> &[Finish] {
+        &self.finishes
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.ended || self.num_remaining() <= 1
+    }
+
+    fn num_remaining(&self) -> usize {
+        self.tables.iter().map(|t| t.len()).sum()
+    }
+
+    /// Returns the blind level of the next hand
+    pub fn level(&self) -> BlindLevel {
+        let mut hands = self.hands;
+        for level in &self.structure.levels {
+            if hands < level.hands {
+                return *level;
+            }
+            hands -= level.hands;
+        }
+        *self.structure.levels.last().expect("failed to find a blind level")
+    }
+
+    /// Plays up to `num_hands` hands at every table, stopping early once the tournament is won.
+    /// Calling it again resumes the tournament. Only fails when auditing is enabled and a hand
+    /// did not conserve chips.
+    pub fn play(&mut self, num_hands: u32) -> Result<(), Box<AuditReport>> {
+        for _ in 0..num_hands {
+            if self.is_finished() {
+                break;
+            }
+
+            let starting_stacks = self.stacks.clone();
+            for table in 0..self.tables.len() {
+                self.play_hand(table)?;
+            }
+            self.hands += 1;
+
+            let busted = self.tables.iter().flatten().copied().filter(|p| self.stacks[*p] == 0).collect::<Vec<usize>>();
+            self.rank_busted(busted, &starting_stacks);
+            self.balance_tables();
+        }
+
+        if self.num_remaining() <= 1 {
+            self.stop();
+        }
+        Ok(())
+    }
+
+    /// Ends the tournament, awarding the players still in their prizes and ending every agent's
+    /// session. `play` calls it once the tournament is won, calling it before stops the
+    /// tournament early and nothing is played after it.
+    pub fn stop(&mut self) {
+        if self.ended {
+            return;
+        }
+        self.ended = true;
+
+        self.award_remaining();
+        let game_info = &self.game_info;
+        for agent in self.agents.iter_mut().flatten() {
+            agent.end_session(game_info);
+        }
+    }
+
+    fn play_hand(&mut self, table: usize) -> Result<(), Box<AuditReport>> {
+        let seats = &self.tables[table];
+        if seats.len() < 2 {
+            return Ok(());
+        }
+
+        let level = self.level();
+        let stacks = seats.iter().map(|p| self.stacks[*p]).collect::<Vec<u32>>();
+        let mut hand_info = self.game_info.with_players(stacks.clone(), ForcedBets::Blinds { small_blind: level.small_blind, big_blind: level.big_blind });
+        if level.ante > 0 {
+            hand_info.set_dead_blinds(hand_info.blinds().iter().zip(&stacks).map(|(b, s)| level.ante.min(s - b)).collect());
+        }
+
+        let dealer = match &mut self.dealers[table] {
+            Some(dealer) if dealer.game_info().num_players() == hand_info.num_players() => dealer,
+            slot => {
+                let mut dealer = Dealer::new(hand_info.clone());
+                dealer.set_seed(self.rng.gen());
+                dealer.set_audit(self.audit);
+                slot.insert(dealer)
+            },
+        };
+        dealer.set_game_info(hand_info);
+
+        let mut agents = seats.iter().map(|p| self.agents[*p].take().expect("failed to find agent")).collect::<Vec<Box<dyn Agent>>>();
+        let record = dealer.play_hand(&mut agents);
+        for (player, agent) in seats.iter().zip(agents) {
+            self.agents[*player] = Some(agent);
+        }
+        let record = record?;
+
+        for (seat, player) in seats.iter().enumerate() {
+            self.stacks[*player] = (self.stacks[*player] as i64 + record.payouts[seat] as i64) as u32;
+            self.finishes[*player].hands_played += 1;
+        }
+        // The player in seat i moves to seat i - 1, which moves the button one seat along
+        self.tables[table].rotate_left(1);
+
+        Ok(())
+    }
+
+    /// Gives the players who busted this hand the lowest places left, bigger stacks before the
+    /// hand finishing higher
+    fn rank_busted(&mut self, mut busted: Vec<usize>, starting_stacks: &[u32]) {
+        for table in self.tables.iter_mut() {
+            table.retain(|p| !busted.contains(p));
+        }
+        busted.sort_by_key(|p| std::cmp::Reverse(starting_stacks[*p]));
+
+        let mut place = self.num_remaining() + 1;
+        for tied in busted.chunk_by(|a, b| starting_stacks[*a] == starting_stacks[*b]) {
+            let prizes = (place..place + tied.len()).map(|p| self.structure.payouts.get(p - 1).copied().unwrap_or(0.)).sum::<f64>();
+            for player in tied {
+                self.finishes[*player].place = Some(place);
+                self.finishes[*player].prize = prizes / tied.len() as f64;
+            }
+            info!("Players {:?} finished in place {}", tied, place);
+            place += tied.len();
+        }
+    }
+
+    /// Breaks the shortest table while the others can seat everyone, then moves players from the
+    /// fullest tables to the shortest until they are within a player of each other. Moved
+    /// players leave from and join at the second seat, the big blind of the next hand at tables
+    /// of three or more.
+    fn balance_tables(&mut self) {
+        let num_remaining = self.num_remaining();
+        let table_size = self.structure.table_size.map_or(self.stacks.len(), |s| s as usize);
+        let shortest = |tables: &[Vec<usize>]| (0..tables.len()).min_by_key(|t| tables[*t].len()).expect("failed to find a table");
+
+        while self.tables.len() > 1 && num_remaining <= (self.tables.len() - 1) * table_size {
+            let broken = shortest(&self.tables);
+            let players = self.tables.remove(broken);
+            self.dealers.remove(broken);
+            for player in players {
+                let table = shortest(&self.tables);
+                let seat = self.tables[table].len().min(1);
+                self.tables[table].insert(seat, player);
+            }
+            info!("Broke a table, {} tables left", self.tables.len());
+        }
+
+        loop {
+            let to = shortest(&self.tables);
+            let from = (0..self.tables.len()).max_by_key(|t| self.tables[*t].len()).expect("failed to find a table");
+            if self.tables[from].len() <= self.tables[to].len() + 1 {
+                break;
+            }
+            let player = self.tables[from].remove(1);
+            let seat = self.tables[to].len().min(1);
+            self.tables[to].insert(seat, player);
+        }
+    }
+
+    /// Ends the tournament for the players still in, the winner if it was played to the end
+    fn award_remaining(&mut self) {
+        let remaining = self.tables.iter().flatten().copied().collect::<Vec<usize>>();
+        let stacks = remaining.iter().map(|p| self.stacks[*p]).collect::<Vec<u32>>();
+        let payouts = self.structure.payouts.iter().take(remaining.len()).copied().collect::<Vec<f64>>();
+        let prizes = if remaining.len() <= MAX_ICM_PLAYERS {
+            icm_equities(&stacks, &payouts)
+        } else {
+            let total = stacks.iter().sum::<u32>() as f64;
+            stacks.iter().map(|s| *s as f64 / total * payouts.iter().sum::<f64>()).collect()
+        };
+
+        for (player, prize) in remaining.iter().zip(prizes) {
+            self.finishes[*player].prize = prize;
+            if remaining.len() == 1 {
+                self.finishes[*player].place = Some(1);
+            }
+        }
+    }
+}
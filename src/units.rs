@@ -0,0 +1,55 @@
+use super::game::GameInfo;
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Unit stacks, bets, payouts and values are reported in. Everything is computed in chips, the
+/// conversion happens where results leave the library so the two never mix.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Units {
+    #[default]
+    Chips,
+    /// Chips divided by the big blind of the game, see `GameInfo::big_blind`
+    BigBlinds,
+}
+
+impl Units {
+    /// Converts an amount of chips to these units
+    pub fn from_chips(self, game_info: &GameInfo, chips: f64) -> f64 {
+        match self {
+            Units::Chips => chips,
+            Units::BigBlinds => game_info.to_big_blinds(chips),
+        }
+    }
+
+    /// Converts an amount in these units to chips, e.g. a threshold given by a user
+    pub fn to_chips(self, game_info: &GameInfo, amount: f64) -> f64 {
+        match self {
+            Units::Chips => amount,
+            Units::BigBlinds => amount * game_info.big_blind() as f64,
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Units::Chips => write!(f, "chips"),
+            Units::BigBlinds => write!(f, "bb"),
+        }
+    }
+}
+
+impl FromStr for Units {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Units, &'static str> {
+        match s {
+            "chips" => Ok(Units::Chips),
+            "bb" | "big-blinds" => Ok(Units::BigBlinds),
+            _ => Err("units are chips or bb"),
+        }
+    }
+}
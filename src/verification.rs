@@ -0,0 +1,157 @@
+use super::{
+    abstract_game::AbstractGame,
+    best_response::{best_response_value, exploitability},
+    cancel::CancelToken,
+    game::Action,
+    node::NodeId,
+    strategy::Strategy,
+};
+
+use poker::Card;
+
+use std::error::Error;
+use std::fmt;
+
+/// Value of Kuhn poker for the first player
+pub const KUHN_GAME_VALUE: f64 = -1. / 18.;
+
+/// Value of Leduc hold'em for the first player, with an ante of 1, raises of 2 and 4 and at most
+/// two raises a round
+pub const LEDUC_GAME_VALUE: f64 = -0.085606;
+
+/// Error returned when a strategy is further from a known solution than the tolerance allows
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerificationError {
+    /// What was checked, like a game value or an infoset
+    pub check: String,
+    pub expected: f64,
+    pub actual: f64,
+    pub tolerance: f64,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {:.6} within {}, got {:.6}", self.check, self.expected, self.tolerance, self.actual)
+    }
+}
+
+impl Error for VerificationError {}
+
+fn check(check: String, expected: f64, actual: f64, tolerance: f64) -> Result<(), VerificationError> {
+    if (expected - actual).abs() <= tolerance {
+        Ok(())
+    } else {
+        Err(VerificationError { check, expected, actual, tolerance })
+    }
+}
+
+/// Checks that best responding to `strategy` as either player of a two player game gets within
+/// `tolerance` of the game value, `game_value` being the value for the first player. This holds
+/// when the strategy is close to an equilibrium and the game's payouts are right.
+pub fn verify_game_value(abstract_game: &mut AbstractGame, strategy: &Strategy, game_value: f64, tolerance: f64) -> Result<(), VerificationError> {
+    assert!(abstract_game.game_info.num_players() == 2, "game values are only known for two player games");
+    let cancel_token = CancelToken::new();
+    let first = best_response_value(abstract_game, strategy, 0, &cancel_token).unwrap();
+    let second = best_response_value(abstract_game, strategy, 1, &cancel_token).unwrap();
+
+    check("best response value of the first player".to_string(), game_value, first, tolerance)?;
+    check("best response value of the second player".to_string(), -game_value, second, tolerance)
+}
+
+/// Checks that best responding to `strategy` gains at most `tolerance` chips per hand on
+/// average over the seats. Unlike game values this works for any number of players, in games
+/// with more than two it holds for every equilibrium but doesn't single one out.
+pub fn verify_exploitability(abstract_game: &mut AbstractGame, strategy: &Strategy, tolerance: f64) -> Result<(), VerificationError> {
+    let actual = exploitability(abstract_game, strategy, &CancelToken::new()).unwrap();
+    check("exploitability".to_string(), 0., actual, tolerance)
+}
+
+/// Returns the probability of betting or calling in the Kuhn equilibrium where the first player
+/// bets their lowest card with probability `alpha`, which can be anything in [0, 1/3].
+/// `betting` is the betting so far with checks as `c` and bets as `r`, `card` is 0 for the
+/// lowest card up to 2 for the highest. Checking is never folding.
+pub fn kuhn_equilibrium(alpha: f64, betting: &str, card: usize) -> f64 {
+    assert!((0. ..=1. / 3.).contains(&alpha), "alpha must be between 0 and 1/3");
+    match (betting, card) {
+        // First player betting
+        ("", 0) => alpha,
+        ("", 1) => 0.,
+        ("", 2) => 3. * alpha,
+        // Second player betting after a check
+        ("c", 0) => 1. / 3.,
+        ("c", 1) => 0.,
+        ("c", 2) => 1.,
+        // Second player calling a bet
+        ("r", 0) => 0.,
+        ("r", 1) => 1. / 3.,
+        ("r", 2) => 1.,
+        // First player calling a bet after checking
+        ("cr", 0) => 0.,
+        ("cr", 1) => alpha + 1. / 3.,
+        ("cr", 2) => 1.,
+        _ => panic!("no kuhn infoset for betting {:?} and card {}", betting, card),
+    }
+}
+
+/// Checks that `strategy` is within `tolerance` of the Kuhn game value and of the equilibrium
+/// family at every infoset, with `alpha` taken from how often the first player bets their lowest
+/// card. Infosets where the equilibrium is indifferent aren't checked since any frequency works
+/// there.
+pub fn verify_kuhn(abstract_game: &mut AbstractGame, strategy: &Strategy, tolerance: f64) -> Result<(), VerificationError> {
+    verify_game_value(abstract_game, strategy, KUHN_GAME_VALUE, tolerance)?;
+
+    let game_info = abstract_game.game_info.clone();
+    let mut cards = game_info.generate_deck().collect::<Vec<Card>>();
+    cards.sort_by_key(|c| c.rank());
+    let buckets = cards.iter()
+        .map(|c| abstract_game.get_bucket(0, &[], &[*c]))
+        .collect::<Vec<_>>();
+    let check_action = Action::Call;
+    let bet = Action::Raise(game_info.raise_size(0));
+
+    let aggressive_probability = |abstract_game: &mut AbstractGame, betting: &[Action], card: usize| {
+        let node_id = follow(abstract_game, betting);
+        let state = &abstract_game.nodes.get_node(node_id).unwrap().state;
+        let actions = abstract_game.get_actions(state);
+        let sigma = strategy.action_probabilities(node_id, buckets[card], &actions);
+        let aggressive = if state.num_raises() > 0 { check_action } else { bet };
+        sigma.get(&aggressive).copied().unwrap_or(0.)
+    };
+
+    let alpha = aggressive_probability(abstract_game, &[], 0);
+    check("first player betting the lowest card".to_string(), alpha.min(1. / 3.), alpha, tolerance)?;
+    let alpha = alpha.min(1. / 3.);
+
+    // The second player is indifferent with the middle card facing a check and the first player
+    // with the middle card at the root, these have zero probability in every equilibrium
+    let infosets: [(&str, &[Action], &[usize]); 4] = [
+        ("", &[], &[1, 2]),
+        ("c", &[check_action], &[0, 1, 2]),
+        ("r", &[bet], &[0, 1, 2]),
+        ("cr", &[check_action, bet], &[0, 1, 2]),
+    ];
+    for (name, betting, cards) in infosets {
+        for card in cards {
+            let actual = aggressive_probability(abstract_game, betting, *card);
+            check(format!("infoset {:?} with card {}", name, card), kuhn_equilibrium(alpha, name, *card), actual, tolerance)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `strategy` is within `tolerance` of the Leduc game value
+pub fn verify_leduc(abstract_game: &mut AbstractGame, strategy: &Strategy, tolerance: f64) -> Result<(), VerificationError> {
+    verify_game_value(abstract_game, strategy, LEDUC_GAME_VALUE, tolerance)
+}
+
+/// Returns the node reached by playing `betting` from the root
+fn follow(abstract_game: &mut AbstractGame, betting: &[Action]) -> NodeId {
+    let mut node_id = abstract_game.nodes.get_root_node_id();
+    for action in betting {
+        let mut board_cards_i = 0;
+        node_id = abstract_game.apply_action_to_node(node_id, &mut board_cards_i, *action);
+    }
+    node_id
+}
+
@@ -0,0 +1,121 @@
+use super::{
+    abstract_game::AbstractGame,
+    action_abstraction::ActionAbstraction,
+    agent::{Agent, StrategyAgent},
+    card_abstraction::CardAbstraction,
+    card_set,
+    game::{Action, GameInfo, GameState},
+    history::HandHistory,
+    node::Nodes,
+    session::Session,
+    strategy::{sample_probabilities, Strategy, StrategyProfile},
+};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use wasm_bindgen::prelude::*;
+
+/// A trained strategy loaded from the contents of its config and output files, since the browser
+/// has no file system
+#[wasm_bindgen]
+pub struct Bot {
+    profile: StrategyProfile,
+    game_config: String,
+    action_abstraction_config: String,
+    card_abstraction_config: String,
+    strategy: Vec<u8>,
+    nodes: Vec<u8>,
+    rng: StdRng,
+}
+
+#[wasm_bindgen]
+impl Bot {
+    /// Takes the JSON of the game and abstraction configs and the bytes of the saved strategy
+    /// and nodes
+    #[wasm_bindgen(constructor)]
+    pub fn new(game_config: &str, action_abstraction_config: &str, card_abstraction_config: &str, strategy: &[u8], nodes: &[u8]) -> Result<Bot, JsError> {
+        Ok(Bot {
+            profile: StrategyProfile::new(
+                Bot::abstract_game(game_config, action_abstraction_config, card_abstraction_config, nodes)?,
+                Strategy::from_bytes(strategy),
+            ),
+            game_config: game_config.to_string(),
+            action_abstraction_config: action_abstraction_config.to_string(),
+            card_abstraction_config: card_abstraction_config.to_string(),
+            strategy: strategy.to_vec(),
+            nodes: nodes.to_vec(),
+            rng: StdRng::from_entropy(),
+        })
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Returns a JSON array of `[action, probability]` for the player to act after `betting`, a
+    /// betting string like `r200c/cr400`, holding `hole_cards` like `AhKd`
+    pub fn action_probabilities(&self, betting: &str, hole_cards: &str, board_cards: &str) -> Result<String, JsError> {
+        let probabilities = self.probabilities(betting, hole_cards, board_cards)?
+            .into_iter()
+            .map(|(a, p)| (a.to_string(), p))
+            .collect::<Vec<(String, f64)>>();
+        Ok(serde_json::to_string(&probabilities)?)
+    }
+
+    /// Samples the action the bot takes after `betting`, written like `raise 200`. A
+    /// `temperature` of 1 plays the strategy as is, 0 always plays the most likely action.
+    pub fn sample_action(&mut self, betting: &str, hole_cards: &str, board_cards: &str, temperature: f64) -> Result<String, JsError> {
+        if temperature < 0. {
+            return Err(JsError::new("temperature can't be negative"));
+        }
+        let probabilities = self.probabilities(betting, hole_cards, board_cards)?;
+        Ok(sample_probabilities(&probabilities, temperature, &mut self.rng).to_string())
+    }
+
+    /// Plays `num_hands` hands of the bot against itself and returns them as a JSON array of
+    /// hand histories
+    pub fn simulate(&mut self, num_hands: u32) -> Result<String, JsError> {
+        let game_info = self.profile.abstract_game.game_info.clone();
+        let agents = (0..game_info.num_players())
+            .map(|_| {
+                let abstract_game = Bot::abstract_game(&self.game_config, &self.action_abstraction_config, &self.card_abstraction_config, &self.nodes)?;
+                let mut agent = StrategyAgent::new(abstract_game, Strategy::from_bytes(&self.strategy));
+                agent.set_seed(self.rng.gen());
+                Ok(Box::new(agent) as Box<dyn Agent>)
+            })
+            .collect::<Result<Vec<Box<dyn Agent>>, JsError>>()?;
+
+        let mut session = Session::new(game_info.clone(), agents);
+        session.set_seed(self.rng.gen());
+        let hands = session.play(num_hands).map_err(|report| JsError::new(&report.to_string()))?;
+
+        let players = (0..game_info.num_players()).map(|p| format!("Bot {}", p + 1)).collect::<Vec<String>>();
+        let histories = hands.iter()
+            .map(|hand| {
+                let seat_players = hand.seats.iter().map(|p| players[*p].clone()).collect::<Vec<String>>();
+                HandHistory::from_record(&game_info, &hand.record, &seat_players)
+            })
+            .collect::<Vec<HandHistory>>();
+        Ok(serde_json::to_string(&histories)?)
+    }
+
+    fn abstract_game(game_config: &str, action_abstraction_config: &str, card_abstraction_config: &str, nodes: &[u8]) -> Result<AbstractGame, JsError> {
+        Ok(AbstractGame {
+            game_info: GameInfo::from_json(game_config)?,
+            nodes: Nodes::from_bytes(nodes),
+            action_abstraction: ActionAbstraction::from_json(action_abstraction_config)?,
+            card_abstraction: CardAbstraction::from_json(card_abstraction_config)?,
+        })
+    }
+
+    fn probabilities(&self, betting: &str, hole_cards: &str, board_cards: &str) -> Result<Vec<(Action, f64)>, JsError> {
+        let game_info = &self.profile.abstract_game.game_info;
+        let state = GameState::from_betting_string(game_info, 0, betting).map_err(JsError::new)?;
+        if state.is_finished() {
+            return Err(JsError::new("the hand is over"));
+        }
+        let hole_cards = card_set::parse_cards(hole_cards).map_err(|e| JsError::new(&e.to_string()))?;
+        let board_cards = card_set::parse_cards(board_cards).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(self.profile.action_probabilities(game_info, &state, &hole_cards, &board_cards))
+    }
+}